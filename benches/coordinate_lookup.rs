@@ -0,0 +1,109 @@
+//! Benchmarks for coordinate value lookups.
+//!
+//! Compares the precomputed `CoordinateIndex` used by `AppState` against the
+//! linear scan it replaced, on a long hourly time axis.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::HashMap;
+
+use rossby::coord_index::CoordinateIndex;
+use rossby::{AppState, Config, Dimension, Metadata};
+
+/// Nearest-value linear scan, as previously done per request
+fn linear_nearest(values: &[f64], value: f64) -> usize {
+    let mut closest_idx = 0;
+    let mut min_diff = f64::MAX;
+    for (i, &coord) in values.iter().enumerate() {
+        let diff = (coord - value).abs();
+        if diff < min_diff {
+            min_diff = diff;
+            closest_idx = i;
+        }
+    }
+    closest_idx
+}
+
+fn hourly_axis(len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|i| 1_672_531_200.0 + i as f64 * 3600.0)
+        .collect()
+}
+
+fn state_with_time_axis(time: Vec<f64>) -> AppState {
+    let mut dimensions = HashMap::new();
+    dimensions.insert(
+        "time".to_string(),
+        Dimension {
+            name: "time".to_string(),
+            size: time.len(),
+            is_unlimited: true,
+        },
+    );
+    let mut coordinates = HashMap::new();
+    coordinates.insert("time".to_string(), time);
+
+    let metadata = Metadata {
+        global_attributes: HashMap::new(),
+        dimensions,
+        variables: HashMap::new(),
+        coordinates,
+        groups: HashMap::new(),
+    };
+    AppState::new(Config::default(), metadata, HashMap::new())
+}
+
+fn bench_nearest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nearest_coordinate");
+    for len in [1_000, 100_000, 1_000_000] {
+        let axis = hourly_axis(len);
+        // Query between two grid points near the end of the axis
+        let query = axis[len * 9 / 10] + 1234.5;
+
+        group.bench_with_input(BenchmarkId::new("linear_scan", len), &axis, |b, axis| {
+            b.iter(|| linear_nearest(black_box(axis), black_box(query)))
+        });
+
+        let index = CoordinateIndex::new(&axis);
+        group.bench_with_input(BenchmarkId::new("index", len), &index, |b, index| {
+            b.iter(|| index.nearest(black_box(query)))
+        });
+
+        let state = state_with_time_axis(axis);
+        group.bench_with_input(BenchmarkId::new("app_state", len), &state, |b, state| {
+            b.iter(|| {
+                state
+                    .find_coordinate_index("time", black_box(query))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_exact(c: &mut Criterion) {
+    let mut group = c.benchmark_group("exact_coordinate");
+    for len in [1_000, 100_000, 1_000_000] {
+        let axis = hourly_axis(len);
+        let query = axis[len * 9 / 10];
+
+        group.bench_with_input(BenchmarkId::new("linear_scan", len), &axis, |b, axis| {
+            b.iter(|| {
+                axis.iter()
+                    .position(|&v| (v - black_box(query)).abs() < f64::EPSILON)
+            })
+        });
+
+        let state = state_with_time_axis(axis);
+        group.bench_with_input(BenchmarkId::new("app_state", len), &state, |b, state| {
+            b.iter(|| {
+                state
+                    .find_coordinate_index_exact("time", black_box(query))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_nearest, bench_exact);
+criterion_main!(benches);
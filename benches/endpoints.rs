@@ -0,0 +1,36 @@
+//! Benchmarks of the `/point`, `/data` and `/image` endpoints.
+//!
+//! Runs the cases of `rossby bench` (see [`rossby::bench`]) on the small and
+//! medium synthetic grids, so changes to interpolation, serialization or
+//! rendering can be compared with Criterion's statistics and reports.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::sync::Arc;
+
+use rossby::bench::{bench_router, cases, serve, synthetic_state, GRID_SIZES};
+
+fn bench_endpoints(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("endpoints");
+    group.sample_size(20);
+    for &(size, resolution) in &GRID_SIZES[..2] {
+        let router = bench_router(Arc::new(synthetic_state(resolution)));
+        for case in cases(size) {
+            // Prime the caches of the first request
+            runtime
+                .block_on(serve(router.clone(), case.path.clone()))
+                .unwrap();
+            group.bench_with_input(BenchmarkId::from_parameter(&case.name), &case, |b, case| {
+                b.iter(|| {
+                    runtime
+                        .block_on(serve(router.clone(), case.path.clone()))
+                        .unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_endpoints);
+criterion_main!(benches);
@@ -0,0 +1,121 @@
+//! Benchmarks for packed 16-bit variables.
+//!
+//! Compares reads of a variable held as `f32` with the same variable kept as
+//! its packed `i16` values (`data.keep_packed`), which takes half the memory
+//! but converts values on every read.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ndarray::{Array, IxDyn};
+use std::collections::HashMap;
+
+use rossby::state::{AttributeValue, Variable};
+use rossby::{AppState, Config, Dimension, Metadata};
+
+const TIME_STEPS: usize = 8;
+const LAT: usize = 361;
+const LON: usize = 720;
+
+/// State with a 0.5° global `t2m` of a few time steps, packed or not
+fn state(keep_packed: bool) -> AppState {
+    let axes = [
+        ("time", (0..TIME_STEPS).map(|i| i as f64 * 6.0).collect()),
+        ("lat", (0..LAT).map(|i| -90.0 + i as f64 * 0.5).collect()),
+        (
+            "lon",
+            (0..LON).map(|i| i as f64 * 0.5).collect::<Vec<f64>>(),
+        ),
+    ];
+    let dimensions = axes
+        .iter()
+        .map(|(name, values)| {
+            let dimension = Dimension {
+                name: name.to_string(),
+                size: values.len(),
+                is_unlimited: false,
+            };
+            (name.to_string(), dimension)
+        })
+        .collect();
+    let t2m = Variable {
+        name: "t2m".to_string(),
+        dimensions: axes.iter().map(|(name, _)| name.to_string()).collect(),
+        shape: vec![TIME_STEPS, LAT, LON],
+        attributes: HashMap::from([
+            ("scale_factor".to_string(), AttributeValue::Number(0.002)),
+            ("add_offset".to_string(), AttributeValue::Number(260.0)),
+        ]),
+        dtype: "Basic(Short)".to_string(),
+    };
+    let metadata = Metadata {
+        global_attributes: HashMap::new(),
+        dimensions,
+        variables: HashMap::from([("t2m".to_string(), t2m)]),
+        coordinates: axes
+            .into_iter()
+            .map(|(name, values)| (name.to_string(), values))
+            .collect(),
+        groups: HashMap::new(),
+    };
+    let values = Array::from_shape_fn(IxDyn(&[TIME_STEPS, LAT, LON]), |index| {
+        ((index[1] * 37 + index[2] * 11 + index[0]) % 30_000) as f32 - 15_000.0
+    });
+
+    let mut config = Config::default();
+    config.data.keep_packed = keep_packed;
+    let mut state = AppState::new(
+        config,
+        metadata,
+        HashMap::from([("t2m".to_string(), values)]),
+    );
+    state.pack_variables();
+    state
+}
+
+fn bench_reads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("packed_variables");
+    for keep_packed in [false, true] {
+        let state = state(keep_packed);
+        let label = if keep_packed { "packed" } else { "f32" };
+        let bytes = state
+            .get_variable_values_checked("t2m")
+            .unwrap()
+            .memory_bytes();
+        println!("{}: {} bytes of t2m in memory", label, bytes);
+
+        // A horizontal slice, as rendered by /image
+        let dims = HashMap::from([("time".to_string(), TIME_STEPS - 1)]);
+        group.bench_with_input(BenchmarkId::new("slice", label), &state, |b, state| {
+            b.iter(|| {
+                state
+                    .get_data_slice_with_dims(
+                        "t2m",
+                        black_box(0.0),
+                        -90.0,
+                        black_box(359.5),
+                        90.0,
+                        &dims,
+                    )
+                    .unwrap()
+            })
+        });
+
+        // One time step, as read by /point
+        group.bench_with_input(BenchmarkId::new("time_step", label), &state, |b, state| {
+            b.iter(|| {
+                state
+                    .get_variable_indexed("t2m", &[(0, black_box(3))])
+                    .unwrap()
+                    .len()
+            })
+        });
+
+        // The whole variable, as read by full-array consumers
+        group.bench_with_input(BenchmarkId::new("whole", label), &state, |b, state| {
+            b.iter(|| state.get_variable_checked(black_box("t2m")).unwrap().len())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_reads);
+criterion_main!(benches);
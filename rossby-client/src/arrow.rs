@@ -0,0 +1,160 @@
+//! Decoding of the Arrow output of `/data` into `ndarray` arrays.
+//!
+//! `/data` answers with an Arrow IPC stream holding one `Float64` column per
+//! dimension, expanded to one coordinate per row, and one `Float32` column
+//! per variable whose field metadata gives the `shape` and `dimensions` of
+//! the hyperslab in row-major order.
+
+use arrow_array::{Array, Float32Array, Float64Array, RecordBatch};
+use arrow_ipc::reader::StreamReader;
+use ndarray::{ArrayD, IxDyn};
+use std::collections::BTreeMap;
+
+use crate::error::{ClientError, Result};
+
+/// Hyperslabs of a `/data` response
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataArrays {
+    /// Values of each variable
+    pub variables: BTreeMap<String, ArrayD<f32>>,
+    /// Dimension names of the axes of each variable
+    pub dimensions: BTreeMap<String, Vec<String>>,
+    /// Coordinate values of each dimension along the axes of the arrays
+    pub coordinates: BTreeMap<String, Vec<f64>>,
+    /// Schema metadata, e.g. the `stride` of downsampled responses or the
+    /// `errors` of partial ones
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Decode an Arrow IPC stream returned by `/data`
+pub fn decode_arrow(bytes: &[u8]) -> Result<DataArrays> {
+    let invalid = |message: String| ClientError::decode(format!("Invalid Arrow data: {}", message));
+    let reader = StreamReader::try_new(bytes, None).map_err(|e| invalid(e.to_string()))?;
+    let schema = reader.schema();
+    let batches = reader
+        .collect::<std::result::Result<Vec<RecordBatch>, _>>()
+        .map_err(|e| invalid(e.to_string()))?;
+
+    let mut data = DataArrays {
+        metadata: schema
+            .metadata()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        ..DataArrays::default()
+    };
+
+    // Variables carry the metadata needed to restore their shape
+    let mut first_variable: Option<(Vec<String>, Vec<usize>)> = None;
+    let mut dimension_columns = Vec::new();
+    for (index, field) in schema.fields().iter().enumerate() {
+        let Some(shape) = field.metadata().get("shape") else {
+            dimension_columns.push((index, field.name().clone()));
+            continue;
+        };
+        let shape: Vec<usize> = serde_json::from_str(shape)
+            .map_err(|e| invalid(format!("shape of {}: {}", field.name(), e)))?;
+        let dimensions: Vec<String> = match field.metadata().get("dimensions") {
+            Some(dimensions) => serde_json::from_str(dimensions)
+                .map_err(|e| invalid(format!("dimensions of {}: {}", field.name(), e)))?,
+            None => Vec::new(),
+        };
+
+        let mut values = Vec::new();
+        for batch in &batches {
+            let column = batch
+                .column(index)
+                .as_any()
+                .downcast_ref::<Float32Array>()
+                .ok_or_else(|| invalid(format!("{} is not a Float32 column", field.name())))?;
+            values.extend(column.values().iter().copied());
+        }
+        let array = ArrayD::from_shape_vec(IxDyn(&shape), values)
+            .map_err(|e| invalid(format!("{} does not match its shape: {}", field.name(), e)))?;
+
+        first_variable.get_or_insert_with(|| (dimensions.clone(), shape));
+        data.dimensions.insert(field.name().clone(), dimensions);
+        data.variables.insert(field.name().clone(), array);
+    }
+
+    // Coordinate columns repeat each coordinate for the elements it spans
+    let (axes, shape) = first_variable.unwrap_or_default();
+    for (index, name) in dimension_columns {
+        let mut column = Vec::new();
+        for batch in &batches {
+            let values = batch
+                .column(index)
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .ok_or_else(|| invalid(format!("{} is not a Float64 column", name)))?;
+            column.extend(values.values().iter().copied());
+        }
+        let coordinates = match axes.iter().position(|axis| *axis == name) {
+            Some(axis) => {
+                let stride: usize = shape[axis + 1..].iter().product();
+                (0..shape[axis])
+                    .filter_map(|i| column.get(i * stride).copied())
+                    .collect()
+            }
+            None => column.first().copied().into_iter().collect(),
+        };
+        data.coordinates.insert(name, coordinates);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::ArrayRef;
+    use arrow_ipc::writer::StreamWriter;
+    use arrow_schema::{DataType, Field, Schema};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    /// Arrow stream laid out as `/data` writes it, for a 2x3 (lat, lon) variable
+    fn data_stream() -> Vec<u8> {
+        let metadata = HashMap::from([
+            ("shape".to_string(), "[2,3]".to_string()),
+            ("dimensions".to_string(), r#"["lat","lon"]"#.to_string()),
+        ]);
+        let schema = Arc::new(Schema::new_with_metadata(
+            vec![
+                Field::new("lat", DataType::Float64, false),
+                Field::new("lon", DataType::Float64, false),
+                Field::new("time", DataType::Float64, false),
+                Field::new("t2m", DataType::Float32, false).with_metadata(metadata),
+            ],
+            HashMap::from([("downsampled".to_string(), "true".to_string())]),
+        ));
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Float64Array::from(vec![10.0, 10.0, 10.0, 20.0, 20.0, 20.0])),
+            Arc::new(Float64Array::from(vec![0.0, 5.0, 10.0, 0.0, 5.0, 10.0])),
+            Arc::new(Float64Array::from(vec![6.0; 6])),
+            Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0])),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        let mut output = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut output, &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        output
+    }
+
+    #[test]
+    fn test_decode_arrow() {
+        let data = decode_arrow(&data_stream()).unwrap();
+        let t2m = &data.variables["t2m"];
+        assert_eq!(t2m.shape(), &[2, 3]);
+        assert_eq!(t2m[[1, 2]], 6.0);
+        assert_eq!(data.dimensions["t2m"], vec!["lat", "lon"]);
+        assert_eq!(data.coordinates["lat"], vec![10.0, 20.0]);
+        assert_eq!(data.coordinates["lon"], vec![0.0, 5.0, 10.0]);
+        assert_eq!(data.coordinates["time"], vec![6.0]);
+        assert_eq!(data.metadata["downsampled"], "true");
+
+        assert!(decode_arrow(b"not arrow").is_err());
+    }
+}
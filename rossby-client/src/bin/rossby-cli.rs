@@ -0,0 +1,228 @@
+//! Command-line client of a rossby server.
+//!
+//! `rossby-cli point --lon 139.7 --lat 35.7 --vars t2m` prints the response
+//! of an endpoint as JSON; `rossby-cli image` writes the image to a file.
+
+use clap::{Parser, Subcommand};
+use rossby_client::blocking::Client;
+use rossby_client::{DataRequest, ImageRequest, PointRequest};
+use serde_json::json;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Command-line arguments of `rossby-cli`
+#[derive(Parser, Debug)]
+#[command(name = "rossby-cli")]
+#[command(author, version, about = "Query a rossby server", long_about = None)]
+struct Cli {
+    /// Base URL of the server
+    #[arg(long, env = "ROSSBY_URL", default_value = "http://127.0.0.1:8000")]
+    url: String,
+
+    /// API key to send with every request
+    #[arg(long, env = "ROSSBY_API_KEY")]
+    api_key: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the metadata of the dataset
+    Metadata,
+
+    /// Print the values of variables at a point
+    Point {
+        /// Longitude of the point
+        #[arg(long, allow_hyphen_values = true)]
+        lon: f64,
+        /// Latitude of the point
+        #[arg(long, allow_hyphen_values = true)]
+        lat: f64,
+        /// Comma-separated variables
+        #[arg(long, value_delimiter = ',', required = true)]
+        vars: Vec<String>,
+        /// Physical time value
+        #[arg(long)]
+        time: Option<f64>,
+        /// Interpolation method (nearest, bilinear or bicubic)
+        #[arg(long)]
+        interpolation: Option<String>,
+        /// Further query parameters, as name=value
+        #[arg(long = "param", value_parser = parse_param)]
+        params: Vec<(String, String)>,
+    },
+
+    /// Print the shapes and values of hyperslabs of variables
+    Data {
+        /// Comma-separated variables
+        #[arg(long, value_delimiter = ',', required = true)]
+        vars: Vec<String>,
+        /// Dimension selectors and other query parameters, as name=value
+        #[arg(long = "param", value_parser = parse_param)]
+        params: Vec<(String, String)>,
+    },
+
+    /// Write a rendered image of a variable to a file
+    Image {
+        /// Variable to render
+        #[arg(long)]
+        var: String,
+        /// Area to render, as min_lon,min_lat,max_lon,max_lat
+        #[arg(long, allow_hyphen_values = true)]
+        bbox: Option<String>,
+        /// Image width in pixels
+        #[arg(long)]
+        width: Option<u32>,
+        /// Image height in pixels
+        #[arg(long)]
+        height: Option<u32>,
+        /// Colormap name
+        #[arg(long)]
+        colormap: Option<String>,
+        /// Further query parameters, as name=value
+        #[arg(long = "param", value_parser = parse_param)]
+        params: Vec<(String, String)>,
+        /// File to write the image to
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+/// Parse a `name=value` query parameter
+fn parse_param(param: &str) -> Result<(String, String), String> {
+    param
+        .split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected name=value, got '{}'", param))
+}
+
+/// JSON number, or null for values JSON cannot represent
+fn number(value: f64) -> serde_json::Value {
+    serde_json::Number::from_f64(value)
+        .map(serde_json::Value::Number)
+        .unwrap_or(serde_json::Value::Null)
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = Client::new(&cli.url)?;
+    if let Some(key) = cli.api_key {
+        client = client.api_key(key);
+    }
+
+    match cli.command {
+        Command::Metadata => {
+            let body = client.get("/metadata", &[])?;
+            let metadata: serde_json::Value = serde_json::from_slice(&body)?;
+            println!("{}", serde_json::to_string_pretty(&metadata)?);
+        }
+        Command::Point {
+            lon,
+            lat,
+            vars,
+            time,
+            interpolation,
+            params,
+        } => {
+            let mut request = PointRequest::new(lon, lat, vars);
+            if let Some(time) = time {
+                request = request.time(time);
+            }
+            if let Some(method) = &interpolation {
+                request = request.interpolation(method);
+            }
+            request.params.extend(params);
+
+            let point = client.point(&request)?;
+            let mut output = serde_json::Map::new();
+            for (name, value) in &point.values {
+                output.insert(name.clone(), number(*value));
+            }
+            for (name, error) in &point.errors {
+                output.insert(
+                    name.clone(),
+                    json!({ "kind": error.kind, "error": error.error }),
+                );
+            }
+            if !point.snapped.is_empty() {
+                let snapped: serde_json::Map<_, _> = point
+                    .snapped
+                    .iter()
+                    .map(|(name, cell)| {
+                        let cell = json!({
+                            "lat": cell.lat,
+                            "lon": cell.lon,
+                            "distance_km": cell.distance_km,
+                        });
+                        (name.clone(), cell)
+                    })
+                    .collect();
+                output.insert("snapped".to_string(), snapped.into());
+            }
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        Command::Data { vars, params } => {
+            let mut request = DataRequest::new(vars);
+            request.params.extend(params);
+
+            let data = client.data(&request)?;
+            let mut variables = serde_json::Map::new();
+            for (name, array) in &data.variables {
+                variables.insert(
+                    name.clone(),
+                    json!({
+                        "dimensions": data.dimensions.get(name),
+                        "shape": array.shape(),
+                        "values": array.iter().map(|v| number(*v as f64)).collect::<Vec<_>>(),
+                    }),
+                );
+            }
+            let output = json!({
+                "variables": variables,
+                "coordinates": data.coordinates,
+                "metadata": data.metadata,
+            });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        Command::Image {
+            var,
+            bbox,
+            width,
+            height,
+            colormap,
+            params,
+            output,
+        } => {
+            let mut request = ImageRequest::new(var);
+            if let Some(bbox) = bbox {
+                request = request.param("bbox", bbox);
+            }
+            if let Some(width) = width {
+                request = request.param("width", width);
+            }
+            if let Some(height) = height {
+                request = request.param("height", height);
+            }
+            if let Some(colormap) = &colormap {
+                request = request.colormap(colormap);
+            }
+            request.params.extend(params);
+
+            let image = client.image(&request)?;
+            std::fs::write(&output, &image)?;
+            eprintln!("Wrote {} bytes to {}", image.len(), output.display());
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Blocking client.
+//!
+//! The same calls as the async [`crate::Client`], for programs without an
+//! async runtime. Must not be used from within one.
+
+use crate::arrow::{decode_arrow, DataArrays};
+use crate::client::{normalize_base_url, RetryPolicy, API_KEY_HEADER};
+use crate::error::{ClientError, Result};
+use crate::request::{DataRequest, ImageRequest, PointRequest};
+use crate::types::{Metadata, PointValues};
+
+/// Blocking client of a rossby server
+///
+/// Cloning is cheap and clones share the connection pool.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::blocking::Client,
+    base_url: String,
+    retry: RetryPolicy,
+    api_key: Option<String>,
+}
+
+impl Client {
+    /// Client of the server at `base_url`, e.g. `http://localhost:8000`
+    pub fn new(base_url: &str) -> Result<Self> {
+        Self::with_http_client(base_url, reqwest::blocking::Client::new())
+    }
+
+    /// Client sending requests through a configured `reqwest` client
+    pub fn with_http_client(base_url: &str, http: reqwest::blocking::Client) -> Result<Self> {
+        Ok(Self {
+            http,
+            base_url: normalize_base_url(base_url)?,
+            retry: RetryPolicy::default(),
+            api_key: None,
+        })
+    }
+
+    /// Set the retrying of transient failures
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Send an API key with every request
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Base URL of the server
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Metadata of the dataset (`GET /metadata`)
+    pub fn metadata(&self) -> Result<Metadata> {
+        let body = self.get("/metadata", &[])?;
+        serde_json::from_slice(&body)
+            .map_err(|e| ClientError::decode(format!("Invalid metadata: {}", e)))
+    }
+
+    /// Values of variables at a point (`GET /point`)
+    pub fn point(&self, request: &PointRequest) -> Result<PointValues> {
+        let body = self.get("/point", &request.query())?;
+        PointValues::from_json(&body)
+    }
+
+    /// Hyperslabs of variables (`GET /data`)
+    pub fn data(&self, request: &DataRequest) -> Result<DataArrays> {
+        let body = self.get("/data", &request.query())?;
+        decode_arrow(&body)
+    }
+
+    /// Rendered image of a variable, as encoded by the server (`GET /image`)
+    pub fn image(&self, request: &ImageRequest) -> Result<Vec<u8>> {
+        self.get("/image", &request.query())
+    }
+
+    /// Body of a successful response to `GET path?query`
+    pub fn get(&self, path: &str, query: &[(String, String)]) -> Result<Vec<u8>> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut retry = 0;
+        loop {
+            let mut request = self.http.get(&url).query(query);
+            if let Some(key) = &self.api_key {
+                request = request.header(API_KEY_HEADER, key);
+            }
+
+            let response = match request.send() {
+                Ok(response) => response,
+                Err(error)
+                    if retry < self.retry.max_retries
+                        && RetryPolicy::is_transient_error(&error) =>
+                {
+                    std::thread::sleep(self.retry.backoff(retry, None));
+                    retry += 1;
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response.bytes()?.to_vec());
+            }
+            if retry < self.retry.max_retries && RetryPolicy::is_transient_status(status) {
+                std::thread::sleep(self.retry.backoff(retry, Some(response.headers())));
+                retry += 1;
+                continue;
+            }
+            let body = response.bytes()?;
+            return Err(ClientError::from_response(status.as_u16(), &body));
+        }
+    }
+}
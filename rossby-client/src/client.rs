@@ -0,0 +1,240 @@
+//! Async client.
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+use crate::arrow::{decode_arrow, DataArrays};
+use crate::error::{ClientError, Result};
+use crate::request::{DataRequest, ImageRequest, PointRequest};
+use crate::types::{Metadata, PointValues};
+
+/// Header carrying the API key that quotas are accounted against
+pub(crate) const API_KEY_HEADER: &str = "x-api-key";
+
+/// Retrying of requests that failed for transient reasons
+///
+/// Connection errors, timeouts, `429 Too Many Requests` and `502`, `503` and
+/// `504` responses are retried up to `max_retries` times, waiting twice as
+/// long before each attempt from `initial_backoff` up to `max_backoff`. A
+/// `Retry-After` header of the server replaces the computed wait, within the
+/// same bound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Wait before the first retry
+    pub initial_backoff: Duration,
+    /// Longest wait between attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Wait before retry number `retry` (0-based), honoring `Retry-After`
+    pub(crate) fn backoff(&self, retry: u32, headers: Option<&HeaderMap>) -> Duration {
+        let retry_after = headers
+            .and_then(|headers| headers.get(RETRY_AFTER))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        retry_after
+            .unwrap_or_else(|| {
+                self.initial_backoff
+                    .saturating_mul(2u32.saturating_pow(retry))
+            })
+            .min(self.max_backoff)
+    }
+
+    /// Whether a response status is worth retrying
+    pub(crate) fn is_transient_status(status: StatusCode) -> bool {
+        matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        )
+    }
+
+    /// Whether a transport error is worth retrying
+    pub(crate) fn is_transient_error(error: &reqwest::Error) -> bool {
+        error.is_connect() || error.is_timeout()
+    }
+}
+
+/// Base URL of a server, without a trailing slash
+pub(crate) fn normalize_base_url(url: &str) -> Result<String> {
+    let url = url.trim().trim_end_matches('/');
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(ClientError::InvalidUrl {
+            url: url.to_string(),
+        });
+    }
+    Ok(url.to_string())
+}
+
+/// Async client of a rossby server
+///
+/// Cloning is cheap and clones share the connection pool.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    retry: RetryPolicy,
+    api_key: Option<String>,
+}
+
+impl Client {
+    /// Client of the server at `base_url`, e.g. `http://localhost:8000`
+    pub fn new(base_url: &str) -> Result<Self> {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Client sending requests through a configured `reqwest` client
+    pub fn with_http_client(base_url: &str, http: reqwest::Client) -> Result<Self> {
+        Ok(Self {
+            http,
+            base_url: normalize_base_url(base_url)?,
+            retry: RetryPolicy::default(),
+            api_key: None,
+        })
+    }
+
+    /// Set the retrying of transient failures
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Send an API key with every request
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Base URL of the server
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Metadata of the dataset (`GET /metadata`)
+    pub async fn metadata(&self) -> Result<Metadata> {
+        let body = self.get("/metadata", &[]).await?;
+        serde_json::from_slice(&body)
+            .map_err(|e| ClientError::decode(format!("Invalid metadata: {}", e)))
+    }
+
+    /// Values of variables at a point (`GET /point`)
+    pub async fn point(&self, request: &PointRequest) -> Result<PointValues> {
+        let body = self.get("/point", &request.query()).await?;
+        PointValues::from_json(&body)
+    }
+
+    /// Hyperslabs of variables (`GET /data`)
+    pub async fn data(&self, request: &DataRequest) -> Result<DataArrays> {
+        let body = self.get("/data", &request.query()).await?;
+        decode_arrow(&body)
+    }
+
+    /// Rendered image of a variable, as encoded by the server (`GET /image`)
+    pub async fn image(&self, request: &ImageRequest) -> Result<Vec<u8>> {
+        self.get("/image", &request.query()).await
+    }
+
+    /// Body of a successful response to `GET path?query`
+    pub async fn get(&self, path: &str, query: &[(String, String)]) -> Result<Vec<u8>> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut retry = 0;
+        loop {
+            let mut request = self.http.get(&url).query(query);
+            if let Some(key) = &self.api_key {
+                request = request.header(API_KEY_HEADER, key);
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(error)
+                    if retry < self.retry.max_retries
+                        && RetryPolicy::is_transient_error(&error) =>
+                {
+                    tokio::time::sleep(self.retry.backoff(retry, None)).await;
+                    retry += 1;
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response.bytes().await?.to_vec());
+            }
+            if retry < self.retry.max_retries && RetryPolicy::is_transient_status(status) {
+                let wait = self.retry.backoff(retry, Some(response.headers()));
+                tokio::time::sleep(wait).await;
+                retry += 1;
+                continue;
+            }
+            let body = response.bytes().await?;
+            return Err(ClientError::from_response(status.as_u16(), &body));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+        assert_eq!(policy.backoff(0, None), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2, None), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10, None), Duration::from_secs(1));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("0"));
+        assert_eq!(policy.backoff(3, Some(&headers)), Duration::ZERO);
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3600"));
+        assert_eq!(policy.backoff(0, Some(&headers)), Duration::from_secs(1));
+
+        assert!(RetryPolicy::is_transient_status(
+            StatusCode::TOO_MANY_REQUESTS
+        ));
+        assert!(!RetryPolicy::is_transient_status(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_base_url() {
+        assert_eq!(
+            normalize_base_url("http://localhost:8000/").unwrap(),
+            "http://localhost:8000"
+        );
+        assert!(matches!(
+            normalize_base_url("localhost:8000"),
+            Err(ClientError::InvalidUrl { .. })
+        ));
+    }
+}
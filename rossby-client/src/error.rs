@@ -0,0 +1,90 @@
+//! Error types of the client.
+
+use thiserror::Error;
+
+/// Errors of requests to a rossby server
+#[derive(Error, Debug)]
+pub enum ClientError {
+    /// The server could not be reached or the response could not be read
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The base URL of the server is not an HTTP URL
+    #[error("Invalid server URL: {url}")]
+    InvalidUrl { url: String },
+
+    /// The server answered with an error status
+    #[error("Server returned {status}: {message}")]
+    Api {
+        /// HTTP status code
+        status: u16,
+        /// Error message of the server
+        message: String,
+        /// Id of the request in the server logs, if reported
+        request_id: Option<String>,
+    },
+
+    /// The response body could not be decoded
+    #[error("Failed to decode response: {message}")]
+    Decode { message: String },
+}
+
+/// Result type of client calls
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+impl ClientError {
+    /// Error for a failed response, from its status and body
+    ///
+    /// rossby reports errors as `{"error": ..., "request_id": ...}`; other
+    /// bodies are kept as the message.
+    pub(crate) fn from_response(status: u16, body: &[u8]) -> Self {
+        let json = serde_json::from_slice::<serde_json::Value>(body).ok();
+        let field = |name: &str| {
+            json.as_ref()
+                .and_then(|json| json.get(name))
+                .and_then(serde_json::Value::as_str)
+                .map(str::to_string)
+        };
+        ClientError::Api {
+            status,
+            message: field("error")
+                .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string()),
+            request_id: field("request_id"),
+        }
+    }
+
+    /// Error for a body that is not what the endpoint returns
+    pub(crate) fn decode(message: impl Into<String>) -> Self {
+        ClientError::Decode {
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_response() {
+        let error = ClientError::from_response(
+            400,
+            br#"{"error": "Variable not found: t3m", "request_id": "abc"}"#,
+        );
+        match error {
+            ClientError::Api {
+                status,
+                message,
+                request_id,
+            } => {
+                assert_eq!(status, 400);
+                assert_eq!(message, "Variable not found: t3m");
+                assert_eq!(request_id.as_deref(), Some("abc"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let error = ClientError::from_response(502, b"Bad Gateway\n");
+        assert_eq!(error.to_string(), "Server returned 502: Bad Gateway");
+    }
+}
@@ -0,0 +1,37 @@
+//! Typed client for the rossby NetCDF-to-API server.
+//!
+//! [`Client`] is the async API and [`blocking::Client`] its blocking
+//! counterpart. Both keep a pool of connections to the server, retry
+//! requests failing with connection errors, `429 Too Many Requests` or
+//! `502`-`504` responses with exponential backoff (see [`RetryPolicy`]), and
+//! decode responses into typed values: `/metadata` into [`Metadata`],
+//! `/point` into [`PointValues`] and the Arrow output of `/data` into
+//! `ndarray` arrays ([`DataArrays`]).
+//!
+//! ```no_run
+//! use rossby_client::{blocking::Client, DataRequest, PointRequest};
+//!
+//! let client = Client::new("http://localhost:8000")?;
+//! let point = client.point(&PointRequest::new(139.7, 35.7, ["t2m"]))?;
+//! println!("t2m = {}", point.values["t2m"]);
+//!
+//! let data = client.data(&DataRequest::new(["t2m"]).param("time_index", "0"))?;
+//! println!("shape = {:?}", data.variables["t2m"].shape());
+//! # Ok::<(), rossby_client::ClientError>(())
+//! ```
+//!
+//! With the `cli` feature, the `rossby-cli` binary exposes the same calls on
+//! the command line, e.g. `rossby-cli point --lon 139.7 --lat 35.7 --vars t2m`.
+
+pub mod arrow;
+pub mod blocking;
+mod client;
+pub mod error;
+pub mod request;
+pub mod types;
+
+pub use arrow::{decode_arrow, DataArrays};
+pub use client::{Client, RetryPolicy};
+pub use error::{ClientError, Result};
+pub use request::{DataRequest, ImageRequest, PointRequest};
+pub use types::{Category, Dimension, Metadata, PointValues, SnappedCell, Variable, VariableError};
@@ -0,0 +1,201 @@
+//! Typed requests of the endpoints.
+//!
+//! Each request holds the parameters the client knows about, plus any other
+//! query parameter set with `param` (dimension selectors, `bounds`,
+//! `product`, ...), and is turned into the query string of its endpoint.
+
+/// Query parameters, in the order they are sent
+pub type QueryParams = Vec<(String, String)>;
+
+/// A `GET /point` request
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointRequest {
+    /// Longitude of the point
+    pub lon: f64,
+    /// Latitude of the point
+    pub lat: f64,
+    /// Variables to interpolate
+    pub vars: Vec<String>,
+    /// Further query parameters, e.g. `time` or `interpolation`
+    pub params: QueryParams,
+}
+
+impl PointRequest {
+    /// Values of `vars` at a point
+    pub fn new(lon: f64, lat: f64, vars: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            lon,
+            lat,
+            vars: vars.into_iter().map(Into::into).collect(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Select the time by its physical value
+    pub fn time(self, time: f64) -> Self {
+        self.param("time", time)
+    }
+
+    /// Interpolation method (nearest, bilinear or bicubic)
+    pub fn interpolation(self, method: &str) -> Self {
+        self.param("interpolation", method)
+    }
+
+    /// Set any other query parameter
+    pub fn param(mut self, name: &str, value: impl ToString) -> Self {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Query string parameters
+    pub fn query(&self) -> QueryParams {
+        let mut query = vec![
+            ("lon".to_string(), self.lon.to_string()),
+            ("lat".to_string(), self.lat.to_string()),
+            ("vars".to_string(), self.vars.join(",")),
+        ];
+        query.extend(self.params.iter().cloned());
+        query
+    }
+}
+
+/// A `GET /data` request, always answered in Arrow format
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataRequest {
+    /// Variables to extract
+    pub vars: Vec<String>,
+    /// Dimension selectors and other query parameters
+    pub params: QueryParams,
+}
+
+impl DataRequest {
+    /// Hyperslabs of `vars`, narrowed with [`DataRequest::param`]
+    pub fn new(vars: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            vars: vars.into_iter().map(Into::into).collect(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Set a query parameter, e.g. `("time_index", 0)` or `("lat_range", "30,40")`
+    pub fn param(mut self, name: &str, value: impl ToString) -> Self {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Query string parameters
+    pub fn query(&self) -> QueryParams {
+        let mut query = vec![("vars".to_string(), self.vars.join(","))];
+        query.extend(
+            self.params
+                .iter()
+                .filter(|(name, _)| name != "format")
+                .cloned(),
+        );
+        query.push(("format".to_string(), "arrow".to_string()));
+        query
+    }
+}
+
+/// A `GET /image` request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageRequest {
+    /// Variable to render
+    pub var: String,
+    /// Further query parameters, e.g. `bbox` or `colormap`
+    pub params: QueryParams,
+}
+
+impl ImageRequest {
+    /// Image of a variable
+    pub fn new(var: impl Into<String>) -> Self {
+        Self {
+            var: var.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Area to render, as min_lon, min_lat, max_lon, max_lat
+    pub fn bbox(self, min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> Self {
+        self.param(
+            "bbox",
+            format!("{},{},{},{}", min_lon, min_lat, max_lon, max_lat),
+        )
+    }
+
+    /// Size of the image in pixels
+    pub fn size(self, width: u32, height: u32) -> Self {
+        self.param("width", width).param("height", height)
+    }
+
+    /// Colormap name, e.g. `viridis`
+    pub fn colormap(self, colormap: &str) -> Self {
+        self.param("colormap", colormap)
+    }
+
+    /// Set any other query parameter
+    pub fn param(mut self, name: &str, value: impl ToString) -> Self {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Query string parameters
+    pub fn query(&self) -> QueryParams {
+        let mut query = vec![("var".to_string(), self.var.clone())];
+        query.extend(self.params.iter().cloned());
+        query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(query: &QueryParams) -> Vec<(&str, &str)> {
+        query
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        let point = PointRequest::new(139.5, 35.0, ["t2m", "u10"])
+            .time(6.0)
+            .interpolation("nearest");
+        assert_eq!(
+            pairs(&point.query()),
+            vec![
+                ("lon", "139.5"),
+                ("lat", "35"),
+                ("vars", "t2m,u10"),
+                ("time", "6"),
+                ("interpolation", "nearest"),
+            ]
+        );
+
+        // The Arrow format is always requested
+        let data = DataRequest::new(["t2m"])
+            .param("lat_range", "30,40")
+            .param("format", "json");
+        assert_eq!(
+            pairs(&data.query()),
+            vec![("vars", "t2m"), ("lat_range", "30,40"), ("format", "arrow")]
+        );
+
+        let image = ImageRequest::new("t2m")
+            .bbox(-10.0, 35.0, 30.0, 60.0)
+            .size(400, 300)
+            .colormap("plasma");
+        assert_eq!(
+            pairs(&image.query()),
+            vec![
+                ("var", "t2m"),
+                ("bbox", "-10,35,30,60"),
+                ("width", "400"),
+                ("height", "300"),
+                ("colormap", "plasma"),
+            ]
+        );
+    }
+}
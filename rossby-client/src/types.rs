@@ -0,0 +1,164 @@
+//! Typed responses of the JSON endpoints.
+
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{ClientError, Result};
+
+/// Response of `GET /metadata`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metadata {
+    /// File-level attributes
+    pub global_attributes: HashMap<String, serde_json::Value>,
+    /// Dimensions, keyed by name
+    pub dimensions: HashMap<String, Dimension>,
+    /// Variables, keyed by name
+    pub variables: HashMap<String, Variable>,
+    /// Coordinate values of the dimensions
+    pub coordinates: HashMap<String, Vec<f64>>,
+    /// NetCDF-4 groups, keyed by path
+    #[serde(default)]
+    pub groups: HashMap<String, serde_json::Value>,
+    /// Values and meanings of categorical variables
+    #[serde(default)]
+    pub categories: HashMap<String, Vec<Category>>,
+}
+
+/// A dimension of the dataset
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Dimension {
+    /// Name of the dimension
+    pub name: String,
+    /// Number of coordinates
+    pub size: usize,
+    /// Whether the dimension is unlimited
+    pub is_unlimited: bool,
+}
+
+/// A variable of the dataset
+#[derive(Debug, Clone, Deserialize)]
+pub struct Variable {
+    /// Name of the variable
+    pub name: String,
+    /// Names of its dimensions
+    pub dimensions: Vec<String>,
+    /// Sizes of its dimensions
+    pub shape: Vec<usize>,
+    /// Variable attributes
+    pub attributes: HashMap<String, serde_json::Value>,
+    /// Data type in the file
+    pub dtype: String,
+}
+
+/// One value of a categorical variable
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Category {
+    /// Value stored in the data
+    pub value: f64,
+    /// Meaning of the value
+    pub meaning: String,
+}
+
+/// Response of `GET /point`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointValues {
+    /// Value of each variable at the point (NaN for missing data)
+    pub values: BTreeMap<String, f64>,
+    /// Variables that failed, with `partial=true`
+    pub errors: BTreeMap<String, VariableError>,
+    /// Cells that variables were snapped to, with `snap=nearest_valid`
+    pub snapped: BTreeMap<String, SnappedCell>,
+}
+
+/// Failure of one variable of a partial response
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct VariableError {
+    /// Kind of error, e.g. `variable_not_found`
+    pub kind: String,
+    /// Error message
+    pub error: String,
+}
+
+/// Grid cell a point was moved to
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SnappedCell {
+    /// Latitude of the cell
+    pub lat: f64,
+    /// Longitude of the cell
+    pub lon: f64,
+    /// Distance from the requested point
+    pub distance_km: f64,
+}
+
+/// Decode a keyed section of a response
+fn section<T: serde::de::DeserializeOwned>(value: serde_json::Value, name: &str) -> Result<T> {
+    serde_json::from_value(value)
+        .map_err(|e| ClientError::decode(format!("Invalid {} section: {}", name, e)))
+}
+
+impl PointValues {
+    /// Decode a `/point` response, whose variables are top-level keys
+    pub fn from_json(body: &[u8]) -> Result<Self> {
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(body)
+            .map_err(|e| ClientError::decode(format!("Invalid point response: {}", e)))?;
+        let mut point = PointValues::default();
+        for (name, value) in object {
+            match (name.as_str(), value) {
+                ("errors", value @ serde_json::Value::Object(_)) => {
+                    point.errors = section(value, "errors")?
+                }
+                ("snapped", value @ serde_json::Value::Object(_)) => {
+                    point.snapped = section(value, "snapped")?
+                }
+                (_, value) => {
+                    // Missing values are sent as null
+                    let value = match value {
+                        serde_json::Value::Null => f64::NAN,
+                        value => value.as_f64().ok_or_else(|| {
+                            ClientError::decode(format!("Value of {} is not a number", name))
+                        })?,
+                    };
+                    point.values.insert(name, value);
+                }
+            }
+        }
+        Ok(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_values() {
+        let body = br#"{
+            "t2m": 288.5,
+            "sst": null,
+            "errors": {"t850": {"kind": "variable_not_found", "error": "Variable not found: t850"}},
+            "snapped": {"t2m": {"lat": 35.0, "lon": 139.75, "distance_km": 12.5}}
+        }"#;
+        let point = PointValues::from_json(body).unwrap();
+        assert_eq!(point.values["t2m"], 288.5);
+        assert!(point.values["sst"].is_nan());
+        assert_eq!(point.errors["t850"].kind, "variable_not_found");
+        assert_eq!(point.snapped["t2m"].distance_km, 12.5);
+
+        assert!(PointValues::from_json(br#"{"t2m": "warm"}"#).is_err());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let body = r#"{
+            "global_attributes": {"title": "Test"},
+            "dimensions": {"lat": {"name": "lat", "size": 2, "is_unlimited": false}},
+            "variables": {"t2m": {"name": "t2m", "dimensions": ["lat"], "shape": [2],
+                                  "attributes": {"units": "K"}, "dtype": "f32"}},
+            "coordinates": {"lat": [10.0, 20.0]}
+        }"#;
+        let metadata: Metadata = serde_json::from_str(body).unwrap();
+        assert_eq!(metadata.dimensions["lat"].size, 2);
+        assert_eq!(metadata.variables["t2m"].attributes["units"], "K");
+        assert!(metadata.categories.is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+//! Structured access log.
+//!
+//! With `server.access_log` set, every request is appended to that file as
+//! one JSON object per line: the method, path and raw query of the request,
+//! and the status, latency, size and SHA-256 of the response. `rossby replay`
+//! reads these records back to replay production traffic against another
+//! instance and compare the responses (see [`crate::replay`]).
+//!
+//! Response bodies are checksummed as they are sent, so streamed responses
+//! stay streamed, and the record of a request is written once its response
+//! has been sent, or with the bytes sent when the client goes away first.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http_body::{Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tracing::warn;
+
+use crate::artifact::to_hex;
+use crate::error::Result;
+use crate::state::AppState;
+
+/// One logged request and the response it received
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessRecord {
+    /// Time the request was received, in RFC 3339
+    pub timestamp: String,
+    /// Request method
+    pub method: String,
+    /// Request path, e.g. `/image`
+    pub path: String,
+    /// Raw query string, without the leading `?`
+    #[serde(default)]
+    pub query: String,
+    /// Response status code
+    pub status: u16,
+    /// Time from receiving the request to the end of the response body
+    pub duration_ms: f64,
+    /// Size of the response body
+    pub bytes: u64,
+    /// Hex-encoded SHA-256 of the response body
+    pub sha256: String,
+}
+
+impl AccessRecord {
+    /// Path and query of the request, e.g. `/point?lon=0&lat=0`
+    pub fn path_and_query(&self) -> String {
+        if self.query.is_empty() {
+            self.path.clone()
+        } else {
+            format!("{}?{}", self.path, self.query)
+        }
+    }
+}
+
+/// Append a record to the access log at `path`
+pub fn append_record(path: &Path, record: &AccessRecord) -> Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    // A single write per record keeps lines of concurrent requests intact
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)?;
+    Ok(())
+}
+
+/// Log every request to the configured access log
+pub async fn access_log_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(log_path) = state.config.server.access_log.as_deref() else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or("").to_string();
+
+    let (parts, body) = next.run(request).await.into_parts();
+    let record = AccessRecord {
+        timestamp,
+        method,
+        path,
+        query,
+        status: parts.status.as_u16(),
+        duration_ms: 0.0,
+        bytes: 0,
+        sha256: String::new(),
+    };
+    let body = LoggedBody {
+        inner: body,
+        hasher: Sha256::new(),
+        record: Some(record),
+        log_path: log_path.to_path_buf(),
+        started,
+    };
+    Response::from_parts(parts, Body::new(body))
+}
+
+/// A response body checksummed as it is sent, which writes the record of
+/// its request when it ends or is dropped
+struct LoggedBody {
+    inner: Body,
+    hasher: Sha256,
+    /// The record, counting the bytes sent so far, until it is written
+    record: Option<AccessRecord>,
+    log_path: PathBuf,
+    started: Instant,
+}
+
+impl LoggedBody {
+    /// Complete the record with the bytes sent and write it, once
+    fn finish(&mut self) {
+        let Some(mut record) = self.record.take() else {
+            return;
+        };
+        record.duration_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        record.sha256 = to_hex(&std::mem::take(&mut self.hasher).finalize());
+        if let Err(e) = append_record(&self.log_path, &record) {
+            warn!(
+                access_log = %self.log_path.display(),
+                error = %e,
+                "Failed to write access log record"
+            );
+        }
+    }
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Bytes>, axum::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.hasher.update(data);
+                    if let Some(record) = self.record.as_mut() {
+                        record.bytes += data.len() as u64;
+                    }
+                }
+                // Bodies of known length are not polled past their end
+                if self.inner.is_end_stream() {
+                    self.finish();
+                }
+            }
+            Poll::Ready(Some(Err(e))) => {
+                if let Some(record) = &self.record {
+                    warn!(path = %record.path, error = %e, "Response body failed");
+                }
+                self.finish();
+            }
+            Poll::Ready(None) => self.finish(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::sha256_hex;
+
+    #[test]
+    fn test_append_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.jsonl");
+        let record = AccessRecord {
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            method: "GET".to_string(),
+            path: "/point".to_string(),
+            query: "lon=0&lat=0&vars=t2m".to_string(),
+            status: 200,
+            duration_ms: 0.25,
+            bytes: 42,
+            sha256: sha256_hex(b"{}"),
+        };
+        append_record(&path, &record).unwrap();
+        append_record(&path, &record).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<AccessRecord> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records, vec![record.clone(), record.clone()]);
+        assert_eq!(record.path_and_query(), "/point?lon=0&lat=0&vars=t2m");
+    }
+
+    #[tokio::test]
+    async fn test_logged_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.jsonl");
+        let logged = |inner: Body| {
+            Body::new(LoggedBody {
+                inner,
+                hasher: Sha256::new(),
+                record: Some(AccessRecord {
+                    timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                    method: "GET".to_string(),
+                    path: "/data".to_string(),
+                    query: String::new(),
+                    status: 200,
+                    duration_ms: 0.0,
+                    bytes: 0,
+                    sha256: String::new(),
+                }),
+                log_path: path.clone(),
+                started: Instant::now(),
+            })
+        };
+        let read_records = || -> Vec<AccessRecord> {
+            std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        };
+
+        // Streamed bodies stay streamed and are logged once they end
+        let chunks =
+            ["first,", "second,", "third"].map(|chunk| Ok::<_, std::io::Error>(Bytes::from(chunk)));
+        let body = logged(Body::from_stream(futures::stream::iter(chunks)));
+        assert!(body.size_hint().exact().is_none());
+        let mut stream = body.into_data_stream();
+        assert_eq!(
+            futures::StreamExt::next(&mut stream)
+                .await
+                .unwrap()
+                .unwrap(),
+            "first,"
+        );
+        assert!(read_records().is_empty());
+        let mut sent = b"first,".to_vec();
+        while let Some(chunk) = futures::StreamExt::next(&mut stream).await {
+            sent.extend_from_slice(&chunk.unwrap());
+        }
+        let records = read_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].bytes, sent.len() as u64);
+        assert_eq!(records[0].sha256, sha256_hex(&sent));
+
+        // Bodies of known length keep it, and are logged when dropped unsent
+        let body = logged(Body::from("{}"));
+        assert_eq!(body.size_hint().exact(), Some(2));
+        drop(body);
+        let records = read_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            (records[1].bytes, records[1].sha256.as_str()),
+            (0, sha256_hex(b"").as_str())
+        );
+    }
+}
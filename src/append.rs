@@ -0,0 +1,255 @@
+//! Appending time steps to a loaded dataset.
+//!
+//! Rolling forecast archives grow along their time dimension: new steps are
+//! written to the end of an unlimited `time` dimension while the steps
+//! already there stay unchanged. With `data.append_interval_secs` set, the
+//! server polls its data file and, when the file has grown, builds a new
+//! dataset state from the loaded arrays extended with the new steps only,
+//! instead of replacing everything. The new state gets a new generation, so
+//! clients following `X-Rossby-Generation` see the update (see
+//! [`crate::generation`]).
+//!
+//! Quota counters, the response signer and the last self-check report carry
+//! over to the new state;
+//! derived caches start empty.
+
+use ndarray::{concatenate, Array, Axis, IxDyn, Slice};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::coordinate_fixes::apply_coordinate_fixes;
+use crate::data_loader::source_for;
+use crate::error::{Result, RossbyError};
+use crate::memory_budget::MemoryBudget;
+use crate::state::{AppState, Metadata};
+use crate::time_axis::normalize_time_axes;
+use crate::views::ViewStore;
+
+/// Outcome of appending time steps to a dataset
+#[derive(Debug)]
+pub struct Appended {
+    /// Dataset state including the new steps
+    pub state: AppState,
+    /// Number of time steps appended
+    pub steps: usize,
+}
+
+/// Extend a state with the time steps a grown copy of its file has beyond it
+///
+/// `metadata` and `data` are those of the grown file. Its time coordinate must
+/// start with the loaded one and every other dimension must be unchanged.
+/// Returns `None` when the file has no new steps.
+pub fn append_time_steps(
+    state: &AppState,
+    metadata: Metadata,
+    mut data: HashMap<String, Array<f32, IxDyn>>,
+) -> Result<Option<Appended>> {
+    let time_dim = state.resolve_dimension("time")?.to_string();
+    let loaded = state.metadata.dimensions[&time_dim].size;
+    let available = metadata
+        .dimensions
+        .get(&time_dim)
+        .map(|dim| dim.size)
+        .ok_or_else(|| mismatch(format!("the file has no {} dimension", time_dim)))?;
+    if available < loaded {
+        return Err(mismatch(format!(
+            "the file has {} time steps, fewer than the {} loaded",
+            available, loaded
+        )));
+    }
+
+    // Steps already loaded must be unchanged, or this is a different archive
+    if let Some(times) = state.metadata.coordinates.get(&time_dim) {
+        let grown = metadata.coordinates.get(&time_dim).map(Vec::as_slice);
+        if grown.and_then(|grown| grown.get(..times.len())) != Some(times.as_slice()) {
+            return Err(mismatch(
+                "the time coordinate of the file does not extend the loaded one".to_string(),
+            ));
+        }
+    }
+    for (name, dim) in &state.metadata.dimensions {
+        if *name != time_dim && metadata.dimensions.get(name).map(|d| d.size) != Some(dim.size) {
+            return Err(mismatch(format!("dimension {} has changed", name)));
+        }
+    }
+    if available == loaded {
+        return Ok(None);
+    }
+
+    // Extend each variable with the new steps, keeping the loaded ones
+    let mut extended = HashMap::with_capacity(state.data.len() + state.packed.len());
+    for (name, values) in state.variable_values() {
+        let var = &state.metadata.variables[name];
+        let array = values.to_f32();
+        let Some(axis) = var.dimensions.iter().position(|dim| *dim == time_dim) else {
+            extended.insert(name.clone(), array.into_owned());
+            continue;
+        };
+        let grown = data
+            .remove(name)
+            .ok_or_else(|| mismatch(format!("variable {} is missing", name)))?;
+        let mut expected = array.shape().to_vec();
+        expected[axis] = available;
+        if grown.shape() != expected.as_slice() {
+            return Err(mismatch(format!(
+                "variable {} has shape {:?}, expected {:?}",
+                name,
+                grown.shape(),
+                expected
+            )));
+        }
+        let new_steps = grown.slice_axis(Axis(axis), Slice::from(loaded..));
+        let array = concatenate(Axis(axis), &[array.view(), new_steps]).map_err(|e| {
+            RossbyError::Conversion {
+                message: format!("Failed to append time steps to {}: {}", name, e),
+            }
+        })?;
+        extended.insert(name.clone(), array);
+    }
+
+    // Only the variables being served are kept from the grown file
+    let mut metadata = metadata;
+    metadata
+        .variables
+        .retain(|name, _| state.metadata.variables.contains_key(name));
+
+    let mut appended = AppState::new(state.config.clone(), metadata, extended);
+    appended.pack_variables();
+    appended.usage = state.usage.clone();
+    appended.signer = state.signer.clone();
+    appended.climatology = state.climatology.supplied_only();
+    appended.self_check = state.self_check.clone();
+    appended.validate()?;
+    appended.views = ViewStore::build(&appended)?;
+    Ok(Some(Appended {
+        state: appended,
+        steps: available - loaded,
+    }))
+}
+
+/// Extend a state with the new time steps of the file at `path`
+pub fn append_from_file(state: &AppState, path: &Path) -> Result<Option<Appended>> {
+    let budget = MemoryBudget::from_config(&state.config.data)?;
+    let (mut metadata, mut data) = source_for(path)?.load(path, budget.as_ref())?;
+    // Fixed and normalized like the loaded steps, so those still come first
+    let coordinate_changes = apply_coordinate_fixes(&mut metadata, &mut data, &state.config.data)?;
+    let time_normalization = normalize_time_axes(&mut metadata, &mut data, &state.config.data)?;
+    let mut appended = append_time_steps(state, metadata, data)?;
+    if let Some(appended) = appended.as_mut() {
+        appended.state.coordinate_changes = coordinate_changes;
+        appended.state.time_normalization = time_normalization;
+    }
+    Ok(appended)
+}
+
+fn mismatch(message: String) -> RossbyError {
+    RossbyError::DataNotFound {
+        message: format!("Cannot append time steps: {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Variable};
+
+    /// Metadata and data of a (time, lat) archive with `steps` time steps
+    fn archive(steps: usize) -> (Metadata, HashMap<String, Array<f32, IxDyn>>) {
+        let dimension = |name: &str, size| {
+            (
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: name == "time",
+                },
+            )
+        };
+        let variable = |name: &str, dimensions: &[&str], shape: Vec<usize>| {
+            (
+                name.to_string(),
+                Variable {
+                    name: name.to_string(),
+                    dimensions: dimensions.iter().map(|dim| dim.to_string()).collect(),
+                    shape,
+                    attributes: HashMap::new(),
+                    dtype: "f32".to_string(),
+                },
+            )
+        };
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::from([dimension("time", steps), dimension("lat", 2)]),
+            variables: HashMap::from([
+                variable("t2m", &["time", "lat"], vec![steps, 2]),
+                variable("orography", &["lat"], vec![2]),
+            ]),
+            coordinates: HashMap::from([
+                (
+                    "time".to_string(),
+                    (0..steps).map(|t| t as f64 * 6.0).collect(),
+                ),
+                ("lat".to_string(), vec![10.0, 20.0]),
+            ]),
+            groups: HashMap::new(),
+        };
+        let t2m = Array::from_shape_fn(IxDyn(&[steps, 2]), |idx| (idx[0] * 10 + idx[1]) as f32);
+        let orography = Array::from_shape_vec(IxDyn(&[2]), vec![100.0, 200.0]).unwrap();
+        let data = HashMap::from([
+            ("t2m".to_string(), t2m),
+            ("orography".to_string(), orography),
+        ]);
+        (metadata, data)
+    }
+
+    #[test]
+    fn test_append_time_steps() {
+        let (metadata, data) = archive(2);
+        let state = AppState::new(Config::default(), metadata, data);
+
+        let (metadata, mut data) = archive(4);
+        // Changes to loaded steps are not picked up: only new steps are read
+        data.get_mut("t2m").unwrap()[IxDyn(&[0, 0])] = -1.0;
+        let appended = append_time_steps(&state, metadata, data).unwrap().unwrap();
+        assert_eq!(appended.steps, 2);
+
+        let state = appended.state;
+        assert_eq!(state.time_dim_size(), 4);
+        assert_eq!(
+            state.get_coordinate_checked("time").unwrap(),
+            &[0.0, 6.0, 12.0, 18.0]
+        );
+        let t2m = &state.data["t2m"];
+        assert_eq!(t2m.shape(), &[4, 2]);
+        assert_eq!(t2m[IxDyn(&[0, 0])], 0.0);
+        assert_eq!(t2m[IxDyn(&[3, 1])], 31.0);
+        assert_eq!(state.data["orography"], archive(4).1["orography"]);
+
+        // A file without new steps leaves the state as it is
+        let (metadata, data) = archive(4);
+        assert!(append_time_steps(&state, metadata, data).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_append_rejects_other_archives() {
+        let (metadata, data) = archive(3);
+        let state = AppState::new(Config::default(), metadata, data);
+
+        // Fewer steps than loaded
+        let (metadata, data) = archive(2);
+        assert!(append_time_steps(&state, metadata, data).is_err());
+
+        // Loaded steps at different times
+        let (mut metadata, data) = archive(4);
+        metadata.coordinates.get_mut("time").unwrap()[1] = 7.0;
+        let error = append_time_steps(&state, metadata, data).unwrap_err();
+        assert!(error.to_string().contains("does not extend"));
+
+        // A resized horizontal grid
+        let (mut metadata, data) = archive(4);
+        metadata.dimensions.get_mut("lat").unwrap().size = 3;
+        let error = append_time_steps(&state, metadata, data).unwrap_err();
+        assert!(error.to_string().contains("dimension lat has changed"));
+    }
+}
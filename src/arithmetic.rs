@@ -0,0 +1,478 @@
+//! Arithmetic between two variables at request time.
+//!
+//! `/image` and `/data` accept a variable expression such as
+//! `var=t2m-t2m_climatology` (with `+`, `-`, `*` or `/`), or the explicit form
+//! `var_a=t2m&var_b=t2m_climatology&op=sub`, so quick comparisons like a bias
+//! or the difference of two ensemble members do not need the data to be
+//! exported first. Both operands must have the same dimensions. Values that
+//! are missing in either operand (see [`MissingData`]) are missing in the
+//! result, and so is division by zero.
+//!
+//! A `+` in a URL query decodes to a space, so sums are written `a%2Bb`.
+
+use ndarray::{Array, Dimension, Zip};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::dynamics::DerivedVariable;
+use crate::error::{Result, RossbyError};
+use crate::field::HorizontalField;
+use crate::slice_stats::MissingData;
+use crate::state::{AppState, AttributeValue, Variable};
+
+/// Binary operator between two variables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Operator {
+    /// Parse the `op` parameter: add, sub, mul or div
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "add" => Ok(Operator::Add),
+            "sub" => Ok(Operator::Sub),
+            "mul" => Ok(Operator::Mul),
+            "div" => Ok(Operator::Div),
+            other => Err(RossbyError::InvalidParameter {
+                param: "op".to_string(),
+                message: format!(
+                    "Unknown operator: {}. Valid operators are add, sub, mul and div",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Operator written between the operands of an expression
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        match symbol {
+            '+' => Some(Operator::Add),
+            '-' => Some(Operator::Sub),
+            '*' => Some(Operator::Mul),
+            '/' => Some(Operator::Div),
+            _ => None,
+        }
+    }
+
+    /// Symbol of the operator in an expression
+    pub fn symbol(self) -> char {
+        match self {
+            Operator::Add => '+',
+            Operator::Sub => '-',
+            Operator::Mul => '*',
+            Operator::Div => '/',
+        }
+    }
+
+    /// Apply the operator, returning NaN for division by zero
+    pub fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            Operator::Add => a + b,
+            Operator::Sub => a - b,
+            Operator::Mul => a * b,
+            Operator::Div if b == 0.0 => f32::NAN,
+            Operator::Div => a / b,
+        }
+    }
+}
+
+/// Two variables combined by an operator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableExpression {
+    /// Left operand
+    pub a: String,
+    /// Operator
+    pub op: Operator,
+    /// Right operand
+    pub b: String,
+}
+
+impl fmt::Display for VariableExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.a, self.op.symbol(), self.b)
+    }
+}
+
+impl VariableExpression {
+    /// Build an expression, checking that both operands can be combined
+    pub fn new(state: &AppState, a: &str, op: Operator, b: &str) -> Result<Self> {
+        let missing: Vec<String> = [a, b]
+            .iter()
+            .filter(|name| !state.has_variable(name))
+            .map(|name| name.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(RossbyError::InvalidVariables { names: missing });
+        }
+
+        let meta_a = state.get_variable_metadata_checked(a)?;
+        let meta_b = state.get_variable_metadata_checked(b)?;
+        if meta_a.dimensions != meta_b.dimensions || meta_a.shape != meta_b.shape {
+            return Err(RossbyError::InvalidParameter {
+                param: "var".to_string(),
+                message: format!(
+                    "Cannot combine '{}' {:?} with '{}' {:?}; both variables must have \
+                     the same dimensions",
+                    a, meta_a.dimensions, b, meta_b.dimensions
+                ),
+            });
+        }
+
+        Ok(Self {
+            a: a.to_string(),
+            op,
+            b: b.to_string(),
+        })
+    }
+
+    /// Parse an expression such as `t2m-t2m_climatology`
+    ///
+    /// Returns `None` if `text` names a variable or is not two variables
+    /// joined by an operator. Variable names may themselves contain operator
+    /// characters (e.g. `/` in group paths), so the first split whose two
+    /// sides are both variables is used.
+    pub fn parse(state: &AppState, text: &str) -> Result<Option<Self>> {
+        if state.has_variable(text) {
+            return Ok(None);
+        }
+        for (pos, symbol) in text.char_indices() {
+            let op = match Operator::from_symbol(symbol) {
+                Some(op) => op,
+                None => continue,
+            };
+            let (a, b) = (&text[..pos], &text[pos + symbol.len_utf8()..]);
+            if state.has_variable(a) && state.has_variable(b) {
+                return Self::new(state, a, op, b).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the explicit `var_a`, `var_b` and `op` parameters
+    ///
+    /// Returns `None` when none of them is given.
+    pub fn from_params(
+        state: &AppState,
+        var_a: Option<&str>,
+        var_b: Option<&str>,
+        op: Option<&str>,
+    ) -> Result<Option<Self>> {
+        match (var_a, var_b, op) {
+            (None, None, None) => Ok(None),
+            (Some(a), Some(b), Some(op)) => {
+                Self::new(state, a.trim(), Operator::parse(op)?, b.trim()).map(Some)
+            }
+            _ => {
+                let param = if var_a.is_none() {
+                    "var_a"
+                } else if var_b.is_none() {
+                    "var_b"
+                } else {
+                    "op"
+                };
+                Err(RossbyError::InvalidParameter {
+                    param: param.to_string(),
+                    message: "var_a, var_b and op must be given together".to_string(),
+                })
+            }
+        }
+    }
+
+    /// Metadata of the derived variable
+    ///
+    /// Sums and differences keep the units shared by both operands. The
+    /// result carries no missing-data attributes, as missing values are NaN.
+    pub fn metadata(&self, state: &AppState) -> Result<Variable> {
+        let meta_a = state.get_variable_metadata_checked(&self.a)?;
+        let meta_b = state.get_variable_metadata_checked(&self.b)?;
+
+        let mut attributes = HashMap::from([(
+            "long_name".to_string(),
+            AttributeValue::Text(format!("{} {} {}", self.a, self.op.symbol(), self.b)),
+        )]);
+        if matches!(self.op, Operator::Add | Operator::Sub) {
+            if let (Some(AttributeValue::Text(units)), Some(AttributeValue::Text(other))) = (
+                meta_a.attributes.get("units"),
+                meta_b.attributes.get("units"),
+            ) {
+                if units == other {
+                    attributes.insert("units".to_string(), AttributeValue::Text(units.clone()));
+                }
+            }
+        }
+
+        Ok(Variable {
+            name: self.to_string(),
+            dimensions: meta_a.dimensions.clone(),
+            shape: meta_a.shape.clone(),
+            attributes,
+            dtype: "f32".to_string(),
+        })
+    }
+
+    /// Combine values extracted from the two operands with the same selection
+    ///
+    /// Missing values of each operand are masked before the operator is
+    /// applied.
+    pub fn combine<D: Dimension>(
+        &self,
+        state: &AppState,
+        mut a: Array<f32, D>,
+        mut b: Array<f32, D>,
+    ) -> Result<Array<f32, D>> {
+        if a.shape() != b.shape() {
+            return Err(RossbyError::Conversion {
+                message: format!(
+                    "Cannot combine arrays with shapes {:?} and {:?}",
+                    a.shape(),
+                    b.shape()
+                ),
+            });
+        }
+        MissingData::for_variable(state.get_variable_metadata_checked(&self.a)?).mask(a.iter_mut());
+        MissingData::for_variable(state.get_variable_metadata_checked(&self.b)?).mask(b.iter_mut());
+
+        let op = self.op;
+        Zip::from(&mut a)
+            .and(&b)
+            .for_each(|a, &b| *a = op.apply(*a, b));
+        Ok(a)
+    }
+
+    /// Combine the horizontal fields of the two operands
+    ///
+    /// `field` extracts the field of one operand, e.g. with
+    /// [`HorizontalField::from_state`], which already masks missing values.
+    pub fn field(
+        &self,
+        mut field: impl FnMut(&str) -> Result<HorizontalField>,
+    ) -> Result<HorizontalField> {
+        let mut a = field(&self.a)?;
+        let b = field(&self.b)?;
+        if a.values.shape() != b.values.shape() {
+            return Err(RossbyError::Conversion {
+                message: format!(
+                    "Cannot combine fields with shapes {:?} and {:?}",
+                    a.values.shape(),
+                    b.values.shape()
+                ),
+            });
+        }
+
+        let op = self.op;
+        Zip::from(&mut a.values)
+            .and(&b.values)
+            .for_each(|a, &b| *a = op.apply(*a, b));
+        Ok(a)
+    }
+}
+
+/// Metadata of a variable, a variable expression or a derived variable
+pub fn variable_metadata<'a>(state: &'a AppState, name: &str) -> Result<Cow<'a, Variable>> {
+    if let Some(var_meta) = state.get_variable_metadata(name) {
+        return Ok(Cow::Borrowed(var_meta));
+    }
+    if let Some(derived) = DerivedVariable::parse(state, name)? {
+        return derived.metadata(state).map(Cow::Owned);
+    }
+    match VariableExpression::parse(state, name)? {
+        Some(expression) => expression.metadata(state).map(Cow::Owned),
+        None => Err(RossbyError::InvalidVariables {
+            names: vec![name.to_string()],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata};
+    use ndarray::{arr1, IxDyn};
+
+    fn create_test_state() -> AppState {
+        let dimensions = HashMap::from([
+            (
+                "lat".to_string(),
+                Dimension {
+                    name: "lat".to_string(),
+                    size: 2,
+                    is_unlimited: false,
+                },
+            ),
+            (
+                "lon".to_string(),
+                Dimension {
+                    name: "lon".to_string(),
+                    size: 2,
+                    is_unlimited: false,
+                },
+            ),
+        ]);
+        let variable = |name: &str, dims: &[&str], units: &str| {
+            (
+                name.to_string(),
+                Variable {
+                    name: name.to_string(),
+                    dimensions: dims.iter().map(|d| d.to_string()).collect(),
+                    shape: vec![2; dims.len()],
+                    attributes: HashMap::from([
+                        ("units".to_string(), AttributeValue::Text(units.to_string())),
+                        ("_FillValue".to_string(), AttributeValue::Number(-999.0)),
+                    ]),
+                    dtype: "f32".to_string(),
+                },
+            )
+        };
+        let variables = HashMap::from([
+            variable("t2m", &["lat", "lon"], "K"),
+            variable("t2m_clim", &["lat", "lon"], "K"),
+            variable("ens/t2m", &["lat", "lon"], "K"),
+            variable("zonal_mean", &["lat"], "K"),
+        ]);
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables,
+            coordinates: HashMap::from([
+                ("lat".to_string(), vec![0.0, 10.0]),
+                ("lon".to_string(), vec![0.0, 10.0]),
+            ]),
+            groups: HashMap::new(),
+        };
+        let grid = |values: Vec<f32>| Array::from_shape_vec(IxDyn(&[2, 2]), values).unwrap();
+        let data = HashMap::from([
+            ("t2m".to_string(), grid(vec![280.0, 290.0, -999.0, 300.0])),
+            ("t2m_clim".to_string(), grid(vec![279.0, 0.0, 285.0, 295.0])),
+            (
+                "ens/t2m".to_string(),
+                grid(vec![281.0, 291.0, 286.0, 296.0]),
+            ),
+            (
+                "zonal_mean".to_string(),
+                Array::from_shape_vec(IxDyn(&[2]), vec![1.0, 2.0]).unwrap(),
+            ),
+        ]);
+        AppState::new(Config::default(), metadata, data)
+    }
+
+    #[test]
+    fn test_operator() {
+        assert_eq!(Operator::parse("sub").unwrap(), Operator::Sub);
+        assert_eq!(Operator::parse("DIV").unwrap(), Operator::Div);
+        assert!(Operator::parse("pow").is_err());
+        assert_eq!(Operator::from_symbol('*'), Some(Operator::Mul));
+        assert_eq!(Operator::Add.symbol(), '+');
+        assert_eq!(Operator::Mul.apply(2.0, 3.0), 6.0);
+        assert!(Operator::Div.apply(1.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let state = create_test_state();
+
+        let expression = VariableExpression::parse(&state, "t2m-t2m_clim")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (expression.a.as_str(), expression.op),
+            ("t2m", Operator::Sub)
+        );
+        assert_eq!(expression.to_string(), "t2m-t2m_clim");
+
+        // Group paths contain '/', which is also division
+        assert_eq!(VariableExpression::parse(&state, "ens/t2m").unwrap(), None);
+        let expression = VariableExpression::parse(&state, "ens/t2m/t2m")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (expression.a.as_str(), expression.op, expression.b.as_str()),
+            ("ens/t2m", Operator::Div, "t2m")
+        );
+
+        assert_eq!(
+            VariableExpression::parse(&state, "t2m-unknown").unwrap(),
+            None
+        );
+        assert!(VariableExpression::parse(&state, "t2m*zonal_mean").is_err());
+    }
+
+    #[test]
+    fn test_from_params() {
+        let state = create_test_state();
+        assert_eq!(
+            VariableExpression::from_params(&state, None, None, None).unwrap(),
+            None
+        );
+        let expression =
+            VariableExpression::from_params(&state, Some("t2m"), Some("t2m_clim"), Some("add"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(expression.to_string(), "t2m+t2m_clim");
+
+        assert!(matches!(
+            VariableExpression::from_params(&state, Some("t2m"), None, Some("sub")),
+            Err(RossbyError::InvalidParameter { param, .. }) if param == "var_b"
+        ));
+        assert!(matches!(
+            VariableExpression::from_params(&state, Some("t2m"), Some("sst"), Some("sub")),
+            Err(RossbyError::InvalidVariables { names }) if names == vec!["sst".to_string()]
+        ));
+    }
+
+    #[test]
+    fn test_combine() {
+        let state = create_test_state();
+
+        let difference = VariableExpression::new(&state, "t2m", Operator::Sub, "t2m_clim").unwrap();
+        let a = state.get_variable_checked("t2m").unwrap().to_owned();
+        let b = state.get_variable_checked("t2m_clim").unwrap().to_owned();
+        let values = difference.combine(&state, a.clone(), b.clone()).unwrap();
+        assert_eq!(values[[0, 0]], 1.0);
+        assert_eq!(values[[0, 1]], 290.0);
+        assert!(values[[1, 0]].is_nan());
+
+        let ratio = VariableExpression::new(&state, "t2m", Operator::Div, "t2m_clim").unwrap();
+        let values = ratio.combine(&state, a.clone(), b).unwrap();
+        assert!(values[[0, 1]].is_nan());
+
+        let short = arr1(&[1.0f32]).into_dyn();
+        assert!(ratio.combine(&state, a, short).is_err());
+
+        let meta = difference.metadata(&state).unwrap();
+        assert_eq!(meta.name, "t2m-t2m_clim");
+        assert_eq!(meta.dimensions, vec!["lat", "lon"]);
+        assert!(matches!(
+            meta.attributes.get("units"),
+            Some(AttributeValue::Text(units)) if units == "K"
+        ));
+        assert!(!meta.attributes.contains_key("_FillValue"));
+        assert!(!ratio
+            .metadata(&state)
+            .unwrap()
+            .attributes
+            .contains_key("units"));
+    }
+
+    #[test]
+    fn test_variable_metadata() {
+        let state = create_test_state();
+        assert!(matches!(
+            variable_metadata(&state, "t2m").unwrap(),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            variable_metadata(&state, "t2m-t2m_clim").unwrap(),
+            Cow::Owned(_)
+        ));
+        assert!(matches!(
+            variable_metadata(&state, "sst"),
+            Err(RossbyError::InvalidVariables { .. })
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+//! Resumable, checksum-verified downloads of generated artifacts.
+//!
+//! Binary responses (Arrow streams, rendered images) are generated
+//! deterministically from the in-memory dataset, so the same request always
+//! yields the same bytes. Artifact responses advertise `Accept-Ranges: bytes`,
+//! carry the SHA-256 of the complete artifact in `X-Content-SHA256` and use it
+//! as a strong `ETag`. A client on an unreliable connection can resume an
+//! interrupted download with a `Range` header (guarded by `If-Range`) and
+//! verify the reassembled file against the checksum.
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::ops::Range;
+
+/// Header carrying the hex-encoded SHA-256 of the complete artifact
+pub const CONTENT_SHA256_HEADER: &str = "x-content-sha256";
+
+/// An inclusive byte range within an artifact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    /// First byte of the range
+    pub start: u64,
+    /// Last byte of the range (inclusive)
+    pub end: u64,
+}
+
+/// Outcome of evaluating a `Range` header against an artifact length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable range; serve the whole artifact
+    Full,
+    /// Serve a single byte range
+    Partial(ByteRange),
+    /// The range lies outside the artifact
+    Unsatisfiable,
+}
+
+impl RangeRequest {
+    /// Evaluate a `Range` header value for an artifact of `length` bytes
+    ///
+    /// Only single `bytes` ranges are honoured (`start-end`, `start-` and the
+    /// suffix form `-count`). Malformed headers, other units and multi-range
+    /// requests fall back to the full artifact, as RFC 9110 permits.
+    pub fn parse(value: &str, length: u64) -> Self {
+        let spec = match value.trim().strip_prefix("bytes=") {
+            Some(spec) if !spec.contains(',') => spec.trim(),
+            _ => return RangeRequest::Full,
+        };
+        let (first, last) = match spec.split_once('-') {
+            Some(parts) => parts,
+            None => return RangeRequest::Full,
+        };
+
+        let range = match (first.trim(), last.trim()) {
+            ("", "") => return RangeRequest::Full,
+            // Suffix range: the last `count` bytes
+            ("", count) => match count.parse::<u64>() {
+                Ok(0) => return RangeRequest::Unsatisfiable,
+                Ok(count) if length > 0 => ByteRange {
+                    start: length.saturating_sub(count),
+                    end: length - 1,
+                },
+                Ok(_) => return RangeRequest::Unsatisfiable,
+                Err(_) => return RangeRequest::Full,
+            },
+            (start, end) => {
+                let start = match start.parse::<u64>() {
+                    Ok(start) => start,
+                    Err(_) => return RangeRequest::Full,
+                };
+                let end = match end {
+                    "" => u64::MAX,
+                    end => match end.parse::<u64>() {
+                        Ok(end) if end >= start => end,
+                        _ => return RangeRequest::Full,
+                    },
+                };
+                if start >= length {
+                    return RangeRequest::Unsatisfiable;
+                }
+                ByteRange {
+                    start,
+                    end: end.min(length - 1),
+                }
+            }
+        };
+
+        RangeRequest::Partial(range)
+    }
+}
+
+/// Hex-encoded SHA-256 digest of `bytes`
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+/// Lower-case hex encoding of a digest
+pub fn to_hex(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .fold(String::with_capacity(2 * digest.len()), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+/// Build a response for a complete artifact, honouring `Range` and `If-Range`
+///
+/// `request_headers` are the headers of the incoming request. A range is only
+/// applied when any `If-Range` validator matches the artifact's checksum, so a
+/// client never stitches together pieces of two different artifacts.
+pub fn artifact_response(
+    request_headers: &HeaderMap,
+    content_type: HeaderValue,
+    body: Vec<u8>,
+) -> Response {
+    let checksum = sha256_hex(&body);
+    let length = body.len() as u64;
+    ranged_response(request_headers, content_type, &checksum, length, |range| {
+        Body::from(body[range.start as usize..range.end as usize].to_vec())
+    })
+}
+
+/// [`artifact_response`] for an artifact of `length` bytes with SHA-256
+/// `checksum` that is not held in memory, whose bytes in a range are given
+/// by `body`
+pub fn ranged_response(
+    request_headers: &HeaderMap,
+    content_type: HeaderValue,
+    checksum: &str,
+    length: u64,
+    body: impl FnOnce(Range<u64>) -> Body,
+) -> Response {
+    let etag = format!("\"{}\"", checksum);
+
+    let if_range_matches = request_headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .is_none_or(|value| value.trim() == etag);
+    let range = match request_headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(value) if if_range_matches => RangeRequest::parse(value, length),
+        _ => RangeRequest::Full,
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(value) = HeaderValue::from_str(checksum) {
+        headers.insert(CONTENT_SHA256_HEADER, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+
+    match range {
+        RangeRequest::Full => {
+            headers.insert(header::CONTENT_TYPE, content_type);
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+            (StatusCode::OK, headers, body(0..length)).into_response()
+        }
+        RangeRequest::Partial(range) => {
+            headers.insert(header::CONTENT_TYPE, content_type);
+            headers.insert(
+                header::CONTENT_LENGTH,
+                HeaderValue::from(range.end - range.start + 1),
+            );
+            if let Ok(value) =
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", range.start, range.end, length))
+            {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            (
+                StatusCode::PARTIAL_CONTENT,
+                headers,
+                body(range.start..range.end + 1),
+            )
+                .into_response()
+        }
+        RangeRequest::Unsatisfiable => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", length)) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            (StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    #[test]
+    fn test_parse_range() {
+        let partial = |start, end| RangeRequest::Partial(ByteRange { start, end });
+
+        assert_eq!(RangeRequest::parse("bytes=0-9", 100), partial(0, 9));
+        assert_eq!(RangeRequest::parse("bytes=90-", 100), partial(90, 99));
+        assert_eq!(RangeRequest::parse("bytes=90-500", 100), partial(90, 99));
+        assert_eq!(RangeRequest::parse("bytes=-10", 100), partial(90, 99));
+        assert_eq!(RangeRequest::parse("bytes=-500", 100), partial(0, 99));
+
+        assert_eq!(
+            RangeRequest::parse("bytes=100-", 100),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=-0", 100),
+            RangeRequest::Unsatisfiable
+        );
+
+        for ignored in [
+            "items=0-9",
+            "bytes=0-9,20-29",
+            "bytes=9-0",
+            "bytes=a-",
+            "bytes=-",
+        ] {
+            assert_eq!(RangeRequest::parse(ignored, 100), RangeRequest::Full);
+        }
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_artifact_response() {
+        let body: Vec<u8> = (0..=255).collect();
+        let checksum = sha256_hex(&body);
+        let content_type = HeaderValue::from_static("application/octet-stream");
+
+        let response = artifact_response(&HeaderMap::new(), content_type.clone(), body.clone());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_SHA256_HEADER], checksum.as_str());
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=250-"));
+        let response = artifact_response(&headers, content_type.clone(), body.clone());
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            "bytes 250-255/256"
+        );
+        assert_eq!(response.headers()[CONTENT_SHA256_HEADER], checksum.as_str());
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes.as_ref(), &body[250..]);
+
+        // A stale validator gets the whole artifact back
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"stale\""));
+        let response = artifact_response(&headers, content_type.clone(), body.clone());
+        assert_eq!(response.status(), StatusCode::OK);
+
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=300-"));
+        headers.insert(
+            header::IF_RANGE,
+            HeaderValue::from_str(&format!("\"{}\"", checksum)).unwrap(),
+        );
+        let response = artifact_response(&headers, content_type, body);
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */256");
+    }
+}
@@ -0,0 +1,141 @@
+//! Decoding and sanitizing of text attributes.
+//!
+//! Text attributes of old NetCDF files are frequently not UTF-8: a `history`
+//! written on a Latin-1 system stores `é` as the single byte `0xE9`. Such
+//! bytes are replaced with U+FFFD, control characters that would garble
+//! JSON consumers are replaced as well, and trailing NUL padding is dropped.
+//!
+//! When the decoded text is lossy and the original bytes are available, they
+//! are kept base64-encoded in a sibling attribute named `<name>_raw_base64`,
+//! so clients that know the encoding can recover the exact text. The NetCDF
+//! library decodes text attributes itself, replacing invalid sequences before
+//! rossby sees them, so raw bytes are only kept for attributes whose bytes
+//! rossby reads directly, such as kerchunk metadata.
+
+use std::collections::HashMap;
+
+use base64::Engine;
+
+use crate::state::AttributeValue;
+
+/// Suffix of the attribute holding the raw bytes of a lossy text attribute
+pub const RAW_BYTES_SUFFIX: &str = "_raw_base64";
+
+/// Text decoded from the bytes of an attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedText {
+    /// Valid UTF-8 text without control characters
+    pub text: String,
+    /// The original bytes, if `text` does not represent them exactly
+    pub raw: Option<Vec<u8>>,
+}
+
+/// Decode the bytes of a text attribute
+pub fn decode_text(bytes: &[u8]) -> DecodedText {
+    let trimmed = trim_nul_padding(bytes);
+    let decoded = String::from_utf8_lossy(trimmed);
+    let text = sanitize_text(&decoded);
+    let exact = text.as_bytes() == trimmed;
+    DecodedText {
+        text,
+        raw: (!exact).then(|| bytes.to_vec()),
+    }
+}
+
+/// Replace control characters other than tab, newline and carriage return
+/// with U+FFFD, dropping trailing NUL padding
+pub fn sanitize_text(text: &str) -> String {
+    text.trim_end_matches('\0')
+        .chars()
+        .map(|c| {
+            if c.is_control() && !matches!(c, '\t' | '\n' | '\r') {
+                char::REPLACEMENT_CHARACTER
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Name of the attribute holding the raw bytes of a lossy text attribute
+pub fn raw_bytes_name(name: &str) -> String {
+    format!("{}{}", name, RAW_BYTES_SUFFIX)
+}
+
+/// Insert a text attribute decoded from bytes, together with its raw bytes
+/// when the decoding is lossy
+pub fn insert_text_attribute(
+    attributes: &mut HashMap<String, AttributeValue>,
+    name: &str,
+    bytes: &[u8],
+) {
+    let decoded = decode_text(bytes);
+    if let Some(raw) = decoded.raw {
+        attributes.insert(
+            raw_bytes_name(name),
+            AttributeValue::Text(base64::engine::general_purpose::STANDARD.encode(raw)),
+        );
+    }
+    attributes.insert(name.to_string(), AttributeValue::Text(decoded.text));
+}
+
+fn trim_nul_padding(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    &bytes[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `history` attribute as written by a Latin-1 system
+    const LATIN1_HISTORY: &[u8] = b"Cr\xe9\xe9 le 12 f\xe9vr. 1998 par M\xe9t\xe9o-France";
+
+    #[test]
+    fn test_decode_text() {
+        let decoded = decode_text("Zürich, 20 °C".as_bytes());
+        assert_eq!(decoded.text, "Zürich, 20 °C");
+        assert_eq!(decoded.raw, None);
+
+        // Latin-1 bytes are replaced, and the original bytes are kept
+        let decoded = decode_text(LATIN1_HISTORY);
+        assert_eq!(
+            decoded.text,
+            "Cr\u{fffd}\u{fffd} le 12 f\u{fffd}vr. 1998 par M\u{fffd}t\u{fffd}o-France"
+        );
+        assert_eq!(decoded.raw.as_deref(), Some(LATIN1_HISTORY));
+
+        // NUL padding is dropped without losing anything
+        let decoded = decode_text(b"ERA5\0\0\0");
+        assert_eq!(decoded.text, "ERA5");
+        assert_eq!(decoded.raw, None);
+        assert_eq!(decode_text(b"").text, "");
+    }
+
+    #[test]
+    fn test_sanitize_text() {
+        assert_eq!(sanitize_text("line 1\nline 2\ttab"), "line 1\nline 2\ttab");
+        assert_eq!(sanitize_text("bell\u{7}\0x\0\0"), "bell\u{fffd}\u{fffd}x");
+
+        let decoded = decode_text(b"escape \x1b[1m");
+        assert_eq!(decoded.text, "escape \u{fffd}[1m");
+        assert!(decoded.raw.is_some());
+    }
+
+    #[test]
+    fn test_insert_text_attribute() {
+        let mut attributes = HashMap::new();
+        insert_text_attribute(&mut attributes, "title", b"Test");
+        insert_text_attribute(&mut attributes, "history", LATIN1_HISTORY);
+        assert_eq!(attributes.len(), 3);
+
+        let Some(AttributeValue::Text(raw)) = attributes.get("history_raw_base64") else {
+            panic!("Missing raw bytes of the history attribute");
+        };
+        let raw = base64::engine::general_purpose::STANDARD
+            .decode(raw)
+            .unwrap();
+        assert_eq!(raw, LATIN1_HISTORY);
+        assert!(!attributes.contains_key("title_raw_base64"));
+    }
+}
@@ -0,0 +1,500 @@
+//! Benchmarks of the serving endpoints.
+//!
+//! `rossby bench` builds a synthetic global dataset in memory, with the
+//! sinusoidal pattern of the test suite's generated files, at one or more
+//! grid sizes, and measures `/point`, `/data` and `/image` requests across
+//! interpolation methods, formats and image sizes. Each case is timed one
+//! request at a time for latency and with several requests in flight for
+//! throughput. The results are printed and can be written as JSON.
+//!
+//! With `--baseline`, the results are compared with those of an earlier run,
+//! e.g. of the previous release on the same machine, and the command fails
+//! when a case's median latency grew by more than `--tolerance`, so CI can
+//! catch performance regressions. Requests are sent to the handlers directly,
+//! without the middleware of the server, so results reflect the endpoints
+//! themselves.
+//!
+//! The same cases run as Criterion benchmarks with
+//! `cargo bench --bench endpoints`.
+
+use axum::body::{to_bytes, Body};
+use axum::http::Request;
+use axum::routing::get;
+use axum::Router;
+use clap::Parser;
+use futures::stream::{self, StreamExt};
+use ndarray::{Array, IxDyn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tower::ServiceExt;
+use tracing::info;
+
+use crate::error::{Result, RossbyError};
+use crate::handlers::{data_handler, image_handler, point_handler};
+use crate::replay::percentile;
+use crate::state::{AppState, AttributeValue, Dimension, Metadata, Variable};
+use crate::Config;
+
+/// Command-line subcommand that runs the benchmarks
+pub const BENCH_COMMAND: &str = "bench";
+
+/// Grid sizes of the synthetic dataset, by name, with their resolution in
+/// degrees
+pub const GRID_SIZES: &[(&str, f64)] = &[("small", 2.0), ("medium", 0.5), ("large", 0.25)];
+
+/// Time steps of the synthetic dataset
+const TIME_STEPS: usize = 4;
+
+/// Name of the variable of the synthetic dataset
+pub const BENCH_VARIABLE: &str = "wave";
+
+/// Command-line arguments of `rossby bench`
+#[derive(Parser, Debug)]
+#[command(name = "rossby bench", bin_name = "rossby bench")]
+#[command(about = "Measure endpoint latency and throughput on a synthetic dataset")]
+pub struct BenchArgs {
+    /// Comma-separated grid sizes to benchmark (small, medium, large)
+    #[arg(long, default_value = "small,medium")]
+    pub sizes: String,
+
+    /// Requests timed per case, for latency and again for throughput
+    #[arg(long, default_value_t = 30)]
+    pub iterations: usize,
+
+    /// Number of requests in flight at once when measuring throughput
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// Write the results as JSON to this file
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Compare the results with those of an earlier run, written with --output
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+
+    /// Relative growth of the median latency over the baseline reported as a
+    /// regression
+    #[arg(long, default_value_t = 0.25)]
+    pub tolerance: f64,
+}
+
+/// A request measured by the benchmarks
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchCase {
+    /// Name identifying the case across runs, e.g. `small/point/bilinear`
+    pub name: String,
+    /// Path and query of the request
+    pub path: String,
+}
+
+/// Measurements of one case
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub path: String,
+    /// Requests timed one at a time
+    pub iterations: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub min_ms: f64,
+    /// Requests served per second with several in flight
+    pub requests_per_second: f64,
+    /// Size of the response body
+    pub bytes: usize,
+}
+
+/// Results of a benchmark run, as written with `--output`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    /// Version of rossby that ran the benchmarks
+    pub version: String,
+    /// Requests in flight when measuring throughput
+    pub concurrency: usize,
+    pub results: Vec<CaseResult>,
+}
+
+/// A case slower than in the baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub name: String,
+    pub baseline_ms: f64,
+    pub current_ms: f64,
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: median {:.2}ms, baseline {:.2}ms ({:+.0}%)",
+            self.name,
+            self.current_ms,
+            self.baseline_ms,
+            (self.current_ms / self.baseline_ms - 1.0) * 100.0
+        )
+    }
+}
+
+/// State with a global `wave` variable of a few time steps at a resolution
+/// in degrees
+pub fn synthetic_state(resolution: f64) -> AppState {
+    let lat_count = (180.0 / resolution).round() as usize + 1;
+    let lon_count = (360.0 / resolution).round() as usize;
+    let axes = [
+        (
+            "time",
+            (0..TIME_STEPS)
+                .map(|i| i as f64 * 6.0)
+                .collect::<Vec<f64>>(),
+        ),
+        (
+            "lat",
+            (0..lat_count)
+                .map(|i| -90.0 + i as f64 * resolution)
+                .collect(),
+        ),
+        (
+            "lon",
+            (0..lon_count).map(|i| i as f64 * resolution).collect(),
+        ),
+    ];
+    let dimensions = axes
+        .iter()
+        .map(|(name, values)| {
+            let dimension = Dimension {
+                name: name.to_string(),
+                size: values.len(),
+                is_unlimited: false,
+            };
+            (name.to_string(), dimension)
+        })
+        .collect();
+    let shape = vec![TIME_STEPS, lat_count, lon_count];
+    let wave = Variable {
+        name: BENCH_VARIABLE.to_string(),
+        dimensions: axes.iter().map(|(name, _)| name.to_string()).collect(),
+        shape: shape.clone(),
+        attributes: HashMap::from([
+            (
+                "units".to_string(),
+                AttributeValue::Text("arbitrary".to_string()),
+            ),
+            (
+                "long_name".to_string(),
+                AttributeValue::Text("Sinusoidal Wave Pattern".to_string()),
+            ),
+        ]),
+        dtype: "Basic(Float)".to_string(),
+    };
+    let metadata = Metadata {
+        global_attributes: HashMap::new(),
+        dimensions,
+        variables: HashMap::from([(BENCH_VARIABLE.to_string(), wave)]),
+        coordinates: axes
+            .into_iter()
+            .map(|(name, values)| (name.to_string(), values))
+            .collect(),
+        groups: HashMap::new(),
+    };
+
+    // Sine waves along longitude and cosine waves along latitude, growing
+    // with time
+    let values = Array::from_shape_fn(IxDyn(&shape), |index| {
+        let x = index[2] as f64 / lon_count as f64 * 4.0 * PI;
+        let y = index[1] as f64 / lat_count as f64 * 4.0 * PI;
+        ((x.sin() + y.cos()) / 2.0 * (1.0 + index[0] as f64 * 0.3)) as f32
+    });
+
+    AppState::new(
+        Config::default(),
+        metadata,
+        HashMap::from([(BENCH_VARIABLE.to_string(), values)]),
+    )
+}
+
+/// Router serving the benchmarked endpoints
+pub fn bench_router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/point", get(point_handler))
+        .route("/data", get(data_handler))
+        .route("/image", get(image_handler))
+        .with_state(state)
+}
+
+/// Cases measured on a grid of a named size
+pub fn cases(size: &str) -> Vec<BenchCase> {
+    let case = |name: String, path: String| BenchCase {
+        name: format!("{}/{}", size, name),
+        path,
+    };
+    let mut cases = Vec::new();
+    for interpolation in ["nearest", "bilinear", "bicubic"] {
+        cases.push(case(
+            format!("point/{}", interpolation),
+            format!(
+                "/point?lon=123.4&lat=-12.3&time_index=1&vars={}&interpolation={}",
+                BENCH_VARIABLE, interpolation
+            ),
+        ));
+    }
+    for format in ["json", "arrow"] {
+        cases.push(case(
+            format!("data/{}", format),
+            format!(
+                "/data?vars={}&time_index=1&format={}",
+                BENCH_VARIABLE, format
+            ),
+        ));
+    }
+    for (width, height) in [(512, 256), (2048, 1024)] {
+        for interpolation in ["nearest", "bilinear"] {
+            cases.push(case(
+                format!("image/{}x{}/{}", width, height, interpolation),
+                format!(
+                    "/image?var={}&time_index=1&width={}&height={}&interpolation={}",
+                    BENCH_VARIABLE, width, height, interpolation
+                ),
+            ));
+        }
+    }
+    cases
+}
+
+/// Serve one request, returning the size of its response body
+pub async fn serve(router: Router, path: String) -> Result<usize> {
+    let request = Request::get(&path)
+        .body(Body::empty())
+        .map_err(|e| RossbyError::Server {
+            message: format!("Invalid benchmark request {}: {}", path, e),
+        })?;
+    let response = match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(error) => match error {},
+    };
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| RossbyError::Server {
+            message: format!("Failed to read the response of {}: {}", path, e),
+        })?;
+    if !status.is_success() {
+        return Err(RossbyError::Server {
+            message: format!(
+                "Benchmark request {} failed with {}: {}",
+                path,
+                status,
+                String::from_utf8_lossy(&body)
+            ),
+        });
+    }
+    Ok(body.len())
+}
+
+/// Measure the latency and throughput of a case
+///
+/// One untimed request primes the caches first, so the measurements reflect
+/// a warm server.
+pub async fn measure(
+    router: &Router,
+    case: &BenchCase,
+    iterations: usize,
+    concurrency: usize,
+) -> Result<CaseResult> {
+    let bytes = serve(router.clone(), case.path.clone()).await?;
+
+    let mut latencies = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let started = Instant::now();
+        serve(router.clone(), case.path.clone()).await?;
+        latencies.push(started.elapsed().as_secs_f64() * 1000.0);
+    }
+
+    let started = Instant::now();
+    let mut results = stream::iter(0..iterations)
+        .map(|_| tokio::spawn(serve(router.clone(), case.path.clone())))
+        .buffer_unordered(concurrency.max(1));
+    while let Some(result) = results.next().await {
+        result.map_err(|e| RossbyError::Server {
+            message: format!("Benchmark request {} panicked: {}", case.path, e),
+        })??;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    Ok(CaseResult {
+        name: case.name.clone(),
+        path: case.path.clone(),
+        iterations,
+        mean_ms: latencies.iter().sum::<f64>() / iterations as f64,
+        p50_ms: percentile(&latencies, 0.5).unwrap_or(0.0),
+        p95_ms: percentile(&latencies, 0.95).unwrap_or(0.0),
+        min_ms: latencies.iter().copied().fold(f64::INFINITY, f64::min),
+        requests_per_second: iterations as f64 / elapsed,
+        bytes,
+    })
+}
+
+/// Cases whose median latency grew by more than `tolerance` over the
+/// baseline; cases missing from either run are ignored
+pub fn regressions(
+    report: &BenchReport,
+    baseline: &BenchReport,
+    tolerance: f64,
+) -> Vec<Regression> {
+    report
+        .results
+        .iter()
+        .filter_map(|result| {
+            let base = baseline.results.iter().find(|b| b.name == result.name)?;
+            (result.p50_ms > base.p50_ms * (1.0 + tolerance)).then(|| Regression {
+                name: result.name.clone(),
+                baseline_ms: base.p50_ms,
+                current_ms: result.p50_ms,
+            })
+        })
+        .collect()
+}
+
+/// Run the benchmarks as requested on the command line
+pub async fn run(args: BenchArgs) -> Result<()> {
+    if args.iterations == 0 || args.concurrency == 0 {
+        return Err(RossbyError::InvalidParameter {
+            param: "iterations".to_string(),
+            message: "Iterations and concurrency must be greater than 0".to_string(),
+        });
+    }
+    if !(args.tolerance.is_finite() && args.tolerance >= 0.0) {
+        return Err(RossbyError::InvalidParameter {
+            param: "tolerance".to_string(),
+            message: "Tolerance must be a non-negative number".to_string(),
+        });
+    }
+    let sizes = args
+        .sizes
+        .split(',')
+        .map(|name| {
+            GRID_SIZES
+                .iter()
+                .find(|(size, _)| *size == name.trim())
+                .ok_or_else(|| RossbyError::InvalidParameter {
+                    param: "sizes".to_string(),
+                    message: format!(
+                        "Unknown grid size: {}. Valid sizes are small, medium and large",
+                        name
+                    ),
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Read the baseline before spending time on the benchmarks
+    let baseline: Option<BenchReport> = args
+        .baseline
+        .as_ref()
+        .map(|path| -> Result<BenchReport> {
+            Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+        })
+        .transpose()?;
+
+    let mut report = BenchReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        concurrency: args.concurrency,
+        results: Vec::new(),
+    };
+    for &(size, resolution) in sizes {
+        let state = synthetic_state(resolution);
+        info!(
+            size = size,
+            resolution = resolution,
+            "Benchmarking endpoints"
+        );
+        let router = bench_router(Arc::new(state));
+        for case in cases(size) {
+            let result = measure(&router, &case, args.iterations, args.concurrency).await?;
+            println!(
+                "{:<32} p50 {:>9.2}ms  p95 {:>9.2}ms  {:>9.1} req/s  {:>10} bytes",
+                result.name, result.p50_ms, result.p95_ms, result.requests_per_second, result.bytes
+            );
+            report.results.push(result);
+        }
+    }
+
+    if let Some(path) = &args.output {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        info!(path = %path.display(), "Wrote benchmark results");
+    }
+
+    let Some(baseline) = baseline else {
+        return Ok(());
+    };
+    let regressions = regressions(&report, &baseline, args.tolerance);
+    for regression in &regressions {
+        println!("regression: {}", regression);
+    }
+    match regressions.len() {
+        0 => Ok(()),
+        regressed => Err(RossbyError::BenchRegression {
+            regressed,
+            cases: report.results.len(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, p50_ms: f64) -> CaseResult {
+        CaseResult {
+            name: name.to_string(),
+            path: "/point".to_string(),
+            iterations: 10,
+            mean_ms: p50_ms,
+            p50_ms,
+            p95_ms: p50_ms,
+            min_ms: p50_ms,
+            requests_per_second: 1000.0 / p50_ms,
+            bytes: 100,
+        }
+    }
+
+    fn report(results: Vec<CaseResult>) -> BenchReport {
+        BenchReport {
+            version: "0.0.0".to_string(),
+            concurrency: 1,
+            results,
+        }
+    }
+
+    #[test]
+    fn test_regressions() {
+        let baseline = report(vec![result("a", 1.0), result("b", 2.0), result("c", 1.0)]);
+        let current = report(vec![result("a", 1.2), result("b", 3.0), result("d", 9.0)]);
+        let found = regressions(&current, &baseline, 0.25);
+        assert_eq!(
+            found,
+            vec![Regression {
+                name: "b".to_string(),
+                baseline_ms: 2.0,
+                current_ms: 3.0
+            }]
+        );
+        assert!(regressions(&current, &baseline, 1.0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cases_are_served() {
+        let router = bench_router(Arc::new(synthetic_state(10.0)));
+        let cases = cases("tiny");
+        for case in &cases {
+            let bytes = serve(router.clone(), case.path.clone()).await.unwrap();
+            assert!(bytes > 0, "{}", case.name);
+        }
+        let result = measure(&router, &cases[0], 4, 2).await.unwrap();
+        assert_eq!(result.name, "tiny/point/nearest");
+        assert!(result.min_ms <= result.p50_ms && result.p50_ms <= result.p95_ms);
+    }
+}
@@ -0,0 +1,208 @@
+//! Bit-rounding of exported values.
+//!
+//! Most of the 23 mantissa bits of a 32-bit float carry no real information
+//! in model output, yet they make the values nearly incompressible. `/data`
+//! accepts a `keepbits` parameter that rounds values to the given number of
+//! mantissa bits (round to nearest, ties to even), which bounds the relative
+//! error of every value by 2^-(keepbits + 1) and leaves the trailing bits
+//! zero, so compressed transfers and stored copies shrink dramatically.
+//!
+//! `keepbits` is either a single number applied to every variable, a
+//! comma-separated list of `variable:bits` pairs, or both, e.g. `7,u10:5`
+//! rounds `u10` to 5 bits and every other variable to 7. The precision
+//! applied to each variable is recorded in the output.
+//!
+//! Missing values (NaN or the `_FillValue` of a variable) and infinities are
+//! left as they are.
+
+use ndarray::{Array, IxDyn};
+use std::collections::BTreeMap;
+
+use crate::error::{Result, RossbyError};
+use crate::state::AppState;
+
+/// Name of the query parameter holding the bits to keep
+pub const KEEPBITS_PARAM: &str = "keepbits";
+
+/// Mantissa bits of an `f32`
+pub const MANTISSA_BITS: u32 = 23;
+
+/// Mantissa bits to keep for each variable of a request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeepBits {
+    /// Bits kept for variables without their own entry
+    default: Option<u32>,
+    /// Bits kept for specific variables
+    variables: BTreeMap<String, u32>,
+}
+
+impl KeepBits {
+    /// Parse a `keepbits` parameter for the requested variables
+    pub fn parse(spec: &str, variables: &[String]) -> Result<Self> {
+        let mut keepbits = Self::default();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.rsplit_once(':') {
+                Some((name, bits)) => {
+                    let name = name.trim();
+                    if !variables.iter().any(|var| var == name) {
+                        return Err(invalid(format!(
+                            "Variable '{}' is not part of the request",
+                            name
+                        )));
+                    }
+                    if keepbits
+                        .variables
+                        .insert(name.to_string(), parse_bits(bits)?)
+                        .is_some()
+                    {
+                        return Err(invalid(format!(
+                            "Variable '{}' is listed more than once",
+                            name
+                        )));
+                    }
+                }
+                None => {
+                    if keepbits.default.replace(parse_bits(entry)?).is_some() {
+                        return Err(invalid("More than one default number of bits".to_string()));
+                    }
+                }
+            }
+        }
+        Ok(keepbits)
+    }
+
+    /// Bits to keep for a variable, if it is rounded at all
+    pub fn for_variable(&self, name: &str) -> Option<u32> {
+        self.variables.get(name).copied().or(self.default)
+    }
+
+    /// Bits kept for each of the given variables that is rounded
+    pub fn applied(&self, variables: &[String]) -> BTreeMap<String, u32> {
+        variables
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.for_variable(name)?)))
+            .collect()
+    }
+
+    /// Whether no variable is rounded
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.variables.is_empty()
+    }
+}
+
+fn parse_bits(bits: &str) -> Result<u32> {
+    match bits.trim().parse::<u32>() {
+        Ok(bits) if bits <= MANTISSA_BITS => Ok(bits),
+        _ => Err(invalid(format!(
+            "Invalid number of bits '{}'; expected an integer from 0 to {}",
+            bits.trim(),
+            MANTISSA_BITS
+        ))),
+    }
+}
+
+fn invalid(message: String) -> RossbyError {
+    RossbyError::InvalidParameter {
+        param: KEEPBITS_PARAM.to_string(),
+        message,
+    }
+}
+
+/// Round a value to `keepbits` mantissa bits, to nearest with ties to even
+pub fn round_value(value: f32, keepbits: u32) -> f32 {
+    if !value.is_finite() || keepbits >= MANTISSA_BITS {
+        return value;
+    }
+    let drop = MANTISSA_BITS - keepbits;
+    let bits = value.to_bits();
+    let half = (1u32 << (drop - 1)) - 1;
+    let odd = (bits >> drop) & 1;
+    // A carry out of the mantissa moves on to the exponent, as it should
+    f32::from_bits((bits + half + odd) & !((1u32 << drop) - 1))
+}
+
+/// Round the values of a variable in place, leaving its fill value alone
+pub fn round_array(values: &mut Array<f32, IxDyn>, keepbits: u32, fill_value: Option<f32>) {
+    values.mapv_inplace(|value| {
+        if fill_value == Some(value) {
+            value
+        } else {
+            round_value(value, keepbits)
+        }
+    });
+}
+
+/// The `_FillValue` of a variable, as compared with its values
+pub fn fill_value(state: &AppState, name: &str) -> Option<f32> {
+    state
+        .get_variable_metadata(name)?
+        .attributes
+        .get("_FillValue")
+        .and_then(|attr| attr.as_f64().map(|n| n as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_value() {
+        // 1 + 2^-4 keeps its bits from 4 on, and ties round to even
+        assert_eq!(round_value(1.0625, 4), 1.0625);
+        assert_eq!(round_value(1.0625, 3), 1.0);
+        assert_eq!(round_value(1.1875, 3), 1.25);
+        assert_eq!(round_value(-1.1875, 3), -1.25);
+        // Rounding up can carry into the exponent
+        assert_eq!(round_value(1.99, 2), 2.0);
+        assert_eq!(round_value(0.3, 0), 0.25);
+
+        let value = 287.6543_f32;
+        assert_eq!(round_value(value, MANTISSA_BITS), value);
+        for keepbits in 0..MANTISSA_BITS {
+            let rounded = round_value(value, keepbits);
+            let bound = 2f32.powi(-(keepbits as i32 + 1));
+            assert!(((rounded - value) / value).abs() <= bound);
+            assert_eq!(
+                rounded.to_bits() & ((1 << (MANTISSA_BITS - keepbits)) - 1),
+                0
+            );
+        }
+
+        assert!(round_value(f32::NAN, 3).is_nan());
+        assert_eq!(round_value(f32::INFINITY, 3), f32::INFINITY);
+        assert_eq!(round_value(0.0, 3), 0.0);
+    }
+
+    #[test]
+    fn test_round_array() {
+        let mut values =
+            Array::from_shape_vec(IxDyn(&[3]), vec![1.1875, -9999.0, f32::NAN]).unwrap();
+        round_array(&mut values, 3, Some(-9999.0));
+        assert_eq!(values[0], 1.25);
+        assert_eq!(values[1], -9999.0);
+        assert!(values[2].is_nan());
+    }
+
+    #[test]
+    fn test_parse_keepbits() {
+        let variables = vec!["t2m".to_string(), "u10".to_string()];
+        let keepbits = KeepBits::parse("7, u10:5", &variables).unwrap();
+        assert_eq!(keepbits.for_variable("t2m"), Some(7));
+        assert_eq!(keepbits.for_variable("u10"), Some(5));
+        assert_eq!(
+            keepbits.applied(&variables),
+            BTreeMap::from([("t2m".to_string(), 7), ("u10".to_string(), 5)])
+        );
+
+        let keepbits = KeepBits::parse("u10:5", &variables).unwrap();
+        assert_eq!(keepbits.for_variable("t2m"), None);
+        assert!(!keepbits.is_empty());
+        assert!(KeepBits::parse("", &variables).unwrap().is_empty());
+
+        assert!(KeepBits::parse("24", &variables).is_err());
+        assert!(KeepBits::parse("-1", &variables).is_err());
+        assert!(KeepBits::parse("7,8", &variables).is_err());
+        assert!(KeepBits::parse("sst:7", &variables).is_err());
+        assert!(KeepBits::parse("u10:5,u10:6", &variables).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! Request body size limits.
+//!
+//! Request bodies are limited at two levels. The body limit middleware
+//! rejects bodies whose declared `Content-Length` exceeds
+//! `server.body_limits.max_bytes` before reading any of them, and stops
+//! reading streamed bodies as soon as they pass the limit. Handlers
+//! extracting bodies are additionally bounded by axum's `DefaultBodyLimit`,
+//! set from the same configuration. Oversized requests get a structured
+//! `413 Payload Too Large` response.
+//!
+//! When `server.body_limits.decompress` is enabled, gzip and deflate request
+//! bodies are decompressed before they reach the handlers. Decompression
+//! stops at `max_decompressed_bytes`, so a small compressed body cannot
+//! expand into an unbounded one (a decompression bomb).
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::StreamExt;
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::config::BodyLimitConfig;
+use crate::error::{Result, RossbyError};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::schema::SCHEMA_VERSION;
+use crate::state::AppState;
+
+/// Compression of a request body, from its `Content-Encoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// Read the `Content-Encoding` of a request
+    ///
+    /// Only single encodings are supported; anything else is rejected.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self> {
+        let value = match headers.get(header::CONTENT_ENCODING) {
+            Some(value) => value.to_str().unwrap_or("").trim().to_lowercase(),
+            None => return Ok(ContentEncoding::Identity),
+        };
+        match value.as_str() {
+            "" | "identity" => Ok(ContentEncoding::Identity),
+            "gzip" | "x-gzip" => Ok(ContentEncoding::Gzip),
+            "deflate" => Ok(ContentEncoding::Deflate),
+            other => Err(RossbyError::InvalidParameter {
+                param: "Content-Encoding".to_string(),
+                message: format!(
+                    "Unsupported content encoding: {}. Valid values are 'gzip', 'deflate' or 'identity'",
+                    other
+                ),
+            }),
+        }
+    }
+}
+
+/// Axum's extractor body limit matching the configuration
+///
+/// Extractors see decompressed bodies, so they are bounded by the
+/// decompressed limit when decompression is enabled.
+pub fn handler_body_limit(limits: &BodyLimitConfig) -> DefaultBodyLimit {
+    DefaultBodyLimit::max(limits.handler_limit())
+}
+
+/// Enforce the body limits and decompress request bodies if enabled
+pub async fn body_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let endpoint = request.uri().path().to_string();
+    match limit_body(request, &state.config.server.body_limits).await {
+        Ok(request) => next.run(request).await,
+        Err(error) => body_limit_error_response(error, &endpoint),
+    }
+}
+
+/// Read a request body within the limits, decompressing it if enabled
+///
+/// Returns the request with its body buffered (and decompressed).
+pub async fn limit_body(request: Request, limits: &BodyLimitConfig) -> Result<Request> {
+    let max_bytes = limits.max_bytes;
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > max_bytes as u64) {
+        return Err(too_large(
+            "Declared Content-Length exceeds the limit",
+            max_bytes,
+        ));
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let encoding = if limits.decompress {
+        ContentEncoding::from_headers(&parts.headers)?
+    } else {
+        ContentEncoding::Identity
+    };
+
+    let mut buffer = Vec::new();
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| RossbyError::InvalidParameter {
+            param: "body".to_string(),
+            message: format!("Failed to read request body: {}", e),
+        })?;
+        if buffer.len() + chunk.len() > max_bytes {
+            return Err(too_large("Request body exceeds the limit", max_bytes));
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+
+    if encoding != ContentEncoding::Identity {
+        buffer = decompress(&buffer, encoding, limits.max_decompressed_bytes)?;
+        parts.headers.remove(header::CONTENT_ENCODING);
+        parts
+            .headers
+            .insert(header::CONTENT_LENGTH, HeaderValue::from(buffer.len()));
+    }
+
+    Ok(Request::from_parts(parts, Body::from(Bytes::from(buffer))))
+}
+
+/// Decompress a body, failing once it expands past `max_bytes`
+pub fn decompress(bytes: &[u8], encoding: ContentEncoding, max_bytes: usize) -> Result<Vec<u8>> {
+    let reader: Box<dyn Read + '_> = match encoding {
+        ContentEncoding::Identity => Box::new(bytes),
+        ContentEncoding::Gzip => Box::new(flate2::read::GzDecoder::new(bytes)),
+        ContentEncoding::Deflate => Box::new(flate2::read::ZlibDecoder::new(bytes)),
+    };
+
+    // Read one byte past the limit to tell a body of exactly `max_bytes`
+    // from a larger one without decompressing the rest
+    let mut out = Vec::new();
+    reader
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| RossbyError::InvalidParameter {
+            param: "body".to_string(),
+            message: format!("Failed to decompress request body: {}", e),
+        })?;
+    if out.len() > max_bytes {
+        return Err(too_large(
+            "Decompressed request body exceeds the limit",
+            max_bytes,
+        ));
+    }
+    Ok(out)
+}
+
+fn too_large(message: &str, limit: usize) -> RossbyError {
+    RossbyError::BodyTooLarge {
+        message: message.to_string(),
+        limit,
+    }
+}
+
+/// Build the structured error response for a rejected body
+fn body_limit_error_response(error: RossbyError, endpoint: &str) -> Response {
+    let request_id = generate_request_id();
+    log_request_error(&error, endpoint, &request_id, None);
+
+    let status = match &error {
+        RossbyError::BodyTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        RossbyError::InvalidParameter { param, .. } if param == "Content-Encoding" => {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        }
+        _ => StatusCode::BAD_REQUEST,
+    };
+    let mut body = serde_json::json!({
+        "error": error.to_string(),
+        "request_id": request_id,
+        "schema_version": SCHEMA_VERSION
+    });
+    if let RossbyError::BodyTooLarge { limit, .. } = &error {
+        body["limit_bytes"] = serde_json::json!(limit);
+    }
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn limits(decompress: bool) -> BodyLimitConfig {
+        BodyLimitConfig {
+            max_bytes: 64,
+            decompress,
+            max_decompressed_bytes: 1024,
+        }
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn post(body: Vec<u8>, headers: &[(&'static str, &str)]) -> Request {
+        let mut builder = Request::builder().method("POST").uri("/point");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::from(body)).unwrap()
+    }
+
+    async fn body_of(request: Request) -> Vec<u8> {
+        axum::body::to_bytes(request.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_body_within_limit() {
+        let request = limit_body(post(vec![b'x'; 64], &[]), &limits(false))
+            .await
+            .unwrap();
+        assert_eq!(body_of(request).await, vec![b'x'; 64]);
+    }
+
+    #[tokio::test]
+    async fn test_body_over_limit() {
+        let err = limit_body(post(vec![b'x'; 65], &[]), &limits(false))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RossbyError::BodyTooLarge { limit: 64, .. }));
+
+        // Rejected from the declared length alone
+        let err = limit_body(
+            post(Vec::new(), &[("content-length", "1000000")]),
+            &limits(false),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, RossbyError::BodyTooLarge { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_decompression() {
+        let body = gzip(b"{\"points\": []}");
+        let headers = [("content-encoding", "gzip")];
+
+        let request = limit_body(post(body.clone(), &headers), &limits(true))
+            .await
+            .unwrap();
+        assert!(request.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(body_of(request).await, b"{\"points\": []}");
+
+        // Without decompression the body is passed on as sent
+        let request = limit_body(post(body.clone(), &headers), &limits(false))
+            .await
+            .unwrap();
+        assert_eq!(body_of(request).await, body);
+
+        let err = limit_body(post(body, &[("content-encoding", "br")]), &limits(true))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RossbyError::InvalidParameter { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_decompression_bomb() {
+        // A megabyte of zeros compresses well below the compressed limit
+        let bomb = gzip(&vec![0; 1 << 20]);
+        assert!(bomb.len() <= 64 * 32);
+
+        let mut limits = limits(true);
+        limits.max_bytes = bomb.len();
+        let err = limit_body(post(bomb, &[("content-encoding", "gzip")]), &limits)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RossbyError::BodyTooLarge { limit: 1024, .. }));
+    }
+}
@@ -0,0 +1,297 @@
+//! Handling of coordinates outside the grid.
+//!
+//! `/point` and `/image` resolve out-of-range latitudes and longitudes the
+//! same way, chosen per request with `bounds=error|clamp|wrap` or by default
+//! with `data.bounds` in the configuration:
+//!
+//! - `error` (the default) rejects coordinates outside the grid,
+//! - `clamp` moves them to the nearest grid edge,
+//! - `wrap` maps longitudes into the grid modulo 360 degrees. It is only
+//!   valid for periodic (global) longitude grids; latitudes are still
+//!   rejected when out of range.
+//!
+//! The mode that served a request is returned in the `X-Rossby-Bounds`
+//! response header.
+
+use crate::error::{Result, RossbyError};
+
+/// Response header naming the bounds mode applied to a request
+pub const BOUNDS_HEADER: &str = "x-rossby-bounds";
+
+/// Tolerance in degrees when checking whether a longitude grid is global
+const PERIODIC_TOLERANCE: f64 = 1e-3;
+
+/// Horizontal axis of a coordinate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Latitude,
+    Longitude,
+}
+
+impl Axis {
+    fn label(self) -> &'static str {
+        match self {
+            Axis::Latitude => "Latitude",
+            Axis::Longitude => "Longitude",
+        }
+    }
+}
+
+/// How coordinates outside the grid are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoundsMode {
+    /// Reject out-of-range coordinates
+    #[default]
+    Error,
+    /// Move out-of-range coordinates to the nearest grid edge
+    Clamp,
+    /// Wrap longitudes around a periodic grid
+    Wrap,
+}
+
+impl BoundsMode {
+    /// Parse a bounds mode name
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "error" => Ok(BoundsMode::Error),
+            "clamp" => Ok(BoundsMode::Clamp),
+            "wrap" => Ok(BoundsMode::Wrap),
+            other => Err(RossbyError::InvalidParameter {
+                param: "bounds".to_string(),
+                message: format!(
+                    "Unknown bounds mode: {}. Valid values are 'error', 'clamp' or 'wrap'",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Mode of a request: the `bounds` parameter if given, else the configured default
+    pub fn from_request(param: Option<&str>, default: &str) -> Result<Self> {
+        Self::parse(param.unwrap_or(default))
+    }
+
+    /// Name of the mode as given in `bounds`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BoundsMode::Error => "error",
+            BoundsMode::Clamp => "clamp",
+            BoundsMode::Wrap => "wrap",
+        }
+    }
+
+    /// Resolve a coordinate value against the coordinates of its axis
+    ///
+    /// Returns the value to look up, which lies within the coordinate range.
+    /// Wrapped longitudes falling between the last and the first grid
+    /// longitude take the nearer of the two.
+    pub fn apply(self, axis: Axis, value: f64, coords: &[f64]) -> Result<f64> {
+        let (min, max) = coordinate_range(coords).ok_or_else(|| RossbyError::DataNotFound {
+            message: format!("{} coordinates are empty", axis.label()),
+        })?;
+        if self == BoundsMode::Wrap && axis == Axis::Longitude && !is_periodic_longitude(coords) {
+            return Err(RossbyError::InvalidParameter {
+                param: "bounds".to_string(),
+                message: format!(
+                    "bounds=wrap requires a periodic longitude grid, but longitudes span [{}, {}]",
+                    min, max
+                ),
+            });
+        }
+        if (min..=max).contains(&value) {
+            return Ok(value);
+        }
+
+        match (self, axis) {
+            (BoundsMode::Clamp, _) => Ok(value.clamp(min, max)),
+            (BoundsMode::Wrap, Axis::Longitude) => {
+                let wrapped = min + (value - min).rem_euclid(360.0);
+                if wrapped <= max {
+                    Ok(wrapped)
+                } else if wrapped - max <= min + 360.0 - wrapped {
+                    Ok(max)
+                } else {
+                    Ok(min)
+                }
+            }
+            _ => Err(RossbyError::InvalidCoordinates {
+                message: format!(
+                    "{} {} is outside the range [{}, {}]",
+                    axis.label(),
+                    value,
+                    min,
+                    max
+                ),
+            }),
+        }
+    }
+
+    /// Resolve a bounding box against the grid coordinates
+    ///
+    /// Wrapped boxes spanning 360 degrees or more cover the whole longitude
+    /// range. A box whose western edge ends up east of its eastern edge
+    /// crosses the seam of the grid, as with `wrap_longitude=true`.
+    pub fn apply_bbox(
+        self,
+        (min_lon, min_lat, max_lon, max_lat): (f32, f32, f32, f32),
+        lon_coords: &[f64],
+        lat_coords: &[f64],
+    ) -> Result<(f32, f32, f32, f32)> {
+        let lat = |value: f32| {
+            Ok::<_, RossbyError>(self.apply(Axis::Latitude, value as f64, lat_coords)? as f32)
+        };
+        let lon = |value: f32| {
+            Ok::<_, RossbyError>(self.apply(Axis::Longitude, value as f64, lon_coords)? as f32)
+        };
+
+        let (min_lat, max_lat) = (lat(min_lat)?, lat(max_lat)?);
+        if self == BoundsMode::Wrap && max_lon - min_lon >= 360.0 {
+            // Validates that the grid is periodic
+            lon(min_lon)?;
+            let (first, last) = coordinate_range(lon_coords).unwrap_or_default();
+            return Ok((first as f32, min_lat, last as f32, max_lat));
+        }
+        Ok((lon(min_lon)?, min_lat, lon(max_lon)?, max_lat))
+    }
+}
+
+/// Smallest and largest coordinate value
+fn coordinate_range(coords: &[f64]) -> Option<(f64, f64)> {
+    let min = coords.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = coords.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    (min <= max).then_some((min, max))
+}
+
+/// Whether a longitude grid covers the globe
+///
+/// A regular grid is periodic when its span plus one grid spacing makes 360
+/// degrees.
+pub fn is_periodic_longitude(coords: &[f64]) -> bool {
+    let (min, max) = match coordinate_range(coords) {
+        Some(range) if coords.len() > 1 => range,
+        _ => return false,
+    };
+    let spacing = (max - min) / (coords.len() - 1) as f64;
+    (max - min + spacing - 360.0).abs() < PERIODIC_TOLERANCE.max(spacing * 1e-3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn global() -> Vec<f64> {
+        (0..36).map(|i| i as f64 * 10.0).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(BoundsMode::parse("CLAMP").unwrap(), BoundsMode::Clamp);
+        assert_eq!(
+            BoundsMode::from_request(None, "wrap").unwrap(),
+            BoundsMode::Wrap
+        );
+        assert_eq!(
+            BoundsMode::from_request(Some("error"), "wrap").unwrap(),
+            BoundsMode::Error
+        );
+        assert!(BoundsMode::parse("reflect").is_err());
+    }
+
+    #[test]
+    fn test_is_periodic_longitude() {
+        assert!(is_periodic_longitude(&global()));
+        assert!(is_periodic_longitude(
+            &(0..144)
+                .map(|i| -180.0 + i as f64 * 2.5)
+                .collect::<Vec<_>>()
+        ));
+        assert!(!is_periodic_longitude(&[0.0, 10.0, 20.0]));
+        assert!(!is_periodic_longitude(&[5.0]));
+    }
+
+    #[test]
+    fn test_apply() {
+        let lon = global();
+        let lat: Vec<f64> = (0..18).map(|i| -90.0 + i as f64 * 10.0).collect();
+
+        assert_eq!(
+            BoundsMode::Error
+                .apply(Axis::Longitude, 45.0, &lon)
+                .unwrap(),
+            45.0
+        );
+        assert!(matches!(
+            BoundsMode::Error.apply(Axis::Longitude, 355.0, &lon),
+            Err(RossbyError::InvalidCoordinates { .. })
+        ));
+
+        assert_eq!(
+            BoundsMode::Clamp
+                .apply(Axis::Longitude, 400.0, &lon)
+                .unwrap(),
+            350.0
+        );
+        assert_eq!(
+            BoundsMode::Clamp.apply(Axis::Latitude, 90.0, &lat).unwrap(),
+            80.0
+        );
+
+        assert_eq!(
+            BoundsMode::Wrap
+                .apply(Axis::Longitude, -90.0, &lon)
+                .unwrap(),
+            270.0
+        );
+        assert_eq!(
+            BoundsMode::Wrap
+                .apply(Axis::Longitude, 365.0, &lon)
+                .unwrap(),
+            5.0
+        );
+        // Between the last and the first grid longitude
+        assert_eq!(
+            BoundsMode::Wrap
+                .apply(Axis::Longitude, 358.0, &lon)
+                .unwrap(),
+            0.0
+        );
+        assert_eq!(
+            BoundsMode::Wrap.apply(Axis::Longitude, -8.0, &lon).unwrap(),
+            350.0
+        );
+        // Latitudes never wrap
+        assert!(BoundsMode::Wrap.apply(Axis::Latitude, 95.0, &lat).is_err());
+        // Regional grids cannot wrap
+        assert!(matches!(
+            BoundsMode::Wrap.apply(Axis::Longitude, 5.0, &[0.0, 10.0, 20.0]),
+            Err(RossbyError::InvalidParameter { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_bbox() {
+        let lon = global();
+        let lat: Vec<f64> = (0..18).map(|i| -90.0 + i as f64 * 10.0).collect();
+        let bbox = (-180.0, -90.0, 180.0, 90.0);
+
+        assert!(BoundsMode::Error.apply_bbox(bbox, &lon, &lat).is_err());
+        assert_eq!(
+            BoundsMode::Clamp.apply_bbox(bbox, &lon, &lat).unwrap(),
+            (0.0, -90.0, 180.0, 80.0)
+        );
+        assert!(BoundsMode::Wrap.apply_bbox(bbox, &lon, &lat).is_err());
+        assert_eq!(
+            BoundsMode::Wrap
+                .apply_bbox((-180.0, -90.0, 180.0, 80.0), &lon, &lat)
+                .unwrap(),
+            (0.0, -90.0, 350.0, 80.0)
+        );
+        // Crossing the seam of the grid
+        assert_eq!(
+            BoundsMode::Wrap
+                .apply_bbox((-20.0, -10.0, 20.0, 10.0), &lon, &lat)
+                .unwrap(),
+            (340.0, -10.0, 20.0, 10.0)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Value lookup tables of categorical variables.
+//!
+//! Land cover classes, quality flags and precipitation types are stored as
+//! codes whose meaning is described by the CF `flag_values` and
+//! `flag_meanings` attributes, or by a code table outside the file. This
+//! module turns either into a list of categories that `/metadata` exposes, so
+//! clients can build legends and dropdowns without parsing free text.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::error::{Result, RossbyError};
+use crate::state::{AttributeValue, Variable};
+
+/// One value of a categorical variable and what it stands for
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Category {
+    /// Value stored in the data
+    pub value: f64,
+    /// Meaning of the value, e.g. `evergreen_forest`
+    pub meaning: String,
+}
+
+/// Categories of the variable `name`, ordered by value
+///
+/// A code table configured in `data.code_tables` takes precedence over the
+/// `flag_values` and `flag_meanings` attributes of the file. Returns `None`
+/// for variables with neither, or with attributes that do not pair up.
+pub fn variable_categories(config: &Config, name: &str, var: &Variable) -> Option<Vec<Category>> {
+    let mut categories = match config.data.code_tables.get(name) {
+        Some(table) => parse_code_table(table).ok()?,
+        None => flag_categories(var)?,
+    };
+    categories.sort_by(|a, b| a.value.total_cmp(&b.value));
+    Some(categories)
+}
+
+/// Categories described by the CF `flag_values` and `flag_meanings` attributes
+fn flag_categories(var: &Variable) -> Option<Vec<Category>> {
+    let values = var.attributes.get("flag_values")?.as_f64_vec()?;
+    let meanings = match var.attributes.get("flag_meanings")? {
+        AttributeValue::Text(text) => text.split_whitespace().collect::<Vec<_>>(),
+        _ => return None,
+    };
+    if values.len() != meanings.len() {
+        return None;
+    }
+    Some(
+        values
+            .into_iter()
+            .zip(meanings)
+            .map(|(value, meaning)| Category {
+                value,
+                meaning: meaning.to_string(),
+            })
+            .collect(),
+    )
+}
+
+/// Parse a configured code table, mapping values written as strings to meanings
+pub fn parse_code_table(table: &HashMap<String, String>) -> Result<Vec<Category>> {
+    table
+        .iter()
+        .map(|(value, meaning)| {
+            let value = value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or_else(|| RossbyError::Config {
+                    message: format!("Invalid code table value '{}': must be a number", value),
+                })?;
+            Ok(Category {
+                value,
+                meaning: meaning.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(attributes: Vec<(&str, AttributeValue)>) -> Variable {
+        Variable {
+            name: "land_cover".to_string(),
+            dimensions: vec![],
+            shape: vec![],
+            attributes: attributes
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            dtype: "f32".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_flag_categories() {
+        let var = variable(vec![
+            ("flag_values", AttributeValue::IntegerArray(vec![3, 1, 2])),
+            (
+                "flag_meanings",
+                AttributeValue::Text("water  forest\nurban".to_string()),
+            ),
+        ]);
+        let categories = variable_categories(&Config::default(), "land_cover", &var).unwrap();
+        let pairs: Vec<_> = categories
+            .iter()
+            .map(|c| (c.value, c.meaning.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![(1.0, "forest"), (2.0, "urban"), (3.0, "water")]);
+
+        // Attributes that do not pair up are ignored
+        let var = variable(vec![
+            ("flag_values", AttributeValue::IntegerArray(vec![1, 2])),
+            ("flag_meanings", AttributeValue::Text("forest".to_string())),
+        ]);
+        assert!(variable_categories(&Config::default(), "land_cover", &var).is_none());
+        assert!(variable_categories(&Config::default(), "land_cover", &variable(vec![])).is_none());
+    }
+
+    #[test]
+    fn test_code_table_overrides_attributes() {
+        let config: Config = serde_json::from_str(
+            r#"{"data": {"code_tables": {"land_cover": {"10": "Cropland", "2.5": "Shrubland"}}}}"#,
+        )
+        .unwrap();
+        let var = variable(vec![
+            ("flag_values", AttributeValue::IntegerArray(vec![1])),
+            ("flag_meanings", AttributeValue::Text("forest".to_string())),
+        ]);
+        let categories = variable_categories(&config, "land_cover", &var).unwrap();
+        assert_eq!(
+            categories,
+            vec![
+                Category {
+                    value: 2.5,
+                    meaning: "Shrubland".to_string()
+                },
+                Category {
+                    value: 10.0,
+                    meaning: "Cropland".to_string()
+                },
+            ]
+        );
+
+        let table = HashMap::from([("ten".to_string(), "Cropland".to_string())]);
+        assert!(parse_code_table(&table).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Checks of exported files against the CF conventions.
+//!
+//! `/export` runs every file it lays out through [`check_file`] before
+//! sending it, so subsets that lost or broke CF metadata are noticed: a
+//! `bounds` or `grid_mapping` attribute naming a variable that was not
+//! exported, a coordinate that is not monotonic, a `_FillValue` of another
+//! type than its variable. The checks cover what can be decided from the
+//! file alone; standard names are not looked up in the standard name table.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cf_time::parse_datetime;
+use crate::netcdf_writer::{ClassicFile, NcAttributeValue, NcType, NcVariable};
+
+/// Response header with the number of CF issues of an exported file
+pub const CF_ISSUES_HEADER: &str = "x-rossby-cf-issues";
+
+/// Attributes whose values must be text
+const TEXT_ATTRIBUTES: [&str; 10] = [
+    "units",
+    "standard_name",
+    "long_name",
+    "calendar",
+    "positive",
+    "cell_methods",
+    "bounds",
+    "grid_mapping",
+    "coordinates",
+    "ancillary_variables",
+];
+
+/// Attributes holding values of their variable, of its type
+const VALUE_ATTRIBUTES: [&str; 5] = [
+    "_FillValue",
+    "missing_value",
+    "valid_min",
+    "valid_max",
+    "valid_range",
+];
+
+/// Attributes whose values must be numeric
+const NUMERIC_ATTRIBUTES: [&str; 2] = ["scale_factor", "add_offset"];
+
+/// A departure from the CF conventions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfIssue {
+    /// Variable concerned (None = the file)
+    pub variable: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for CfIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.variable {
+            Some(variable) => write!(f, "{}: {}", variable, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Check a file laid out for writing
+///
+/// `coordinates` holds the values of the coordinate variables by name, for
+/// the checks of their order.
+pub fn check_file(file: &ClassicFile, coordinates: &HashMap<String, Vec<f64>>) -> Vec<CfIssue> {
+    let mut issues = Vec::new();
+    let file_issue = |message: String| CfIssue {
+        variable: None,
+        message,
+    };
+
+    match file.attributes.iter().find(|a| a.name == "Conventions") {
+        Some(attribute) => match &attribute.value {
+            NcAttributeValue::Text(text) if text.contains("CF-") => {}
+            _ => issues.push(file_issue(
+                "The Conventions attribute does not name a CF version".to_string(),
+            )),
+        },
+        None => issues.push(file_issue(
+            "The Conventions attribute is missing".to_string(),
+        )),
+    }
+
+    for var in &file.variables {
+        let mut issue = |message: String| {
+            issues.push(CfIssue {
+                variable: Some(var.name.clone()),
+                message,
+            })
+        };
+        for message in attribute_type_issues(var) {
+            issue(message);
+        }
+        for message in reference_issues(file, var) {
+            issue(message);
+        }
+        if let Some(units) = text_attribute(var, "units") {
+            if let Some((_, reference)) = units.split_once(" since ") {
+                if parse_datetime(reference).is_none() {
+                    issue(format!(
+                        "The reference date of units '{}' is invalid",
+                        units
+                    ));
+                }
+            }
+        }
+        if let Some(values) = coordinates.get(&var.name) {
+            if values.iter().any(|v| !v.is_finite()) {
+                issue("The coordinate has missing values".to_string());
+            } else if !is_strictly_monotonic(values) {
+                issue("The coordinate is not strictly monotonic".to_string());
+            }
+        }
+    }
+    issues
+}
+
+/// Text of an attribute of a variable, if it is text
+fn text_attribute<'a>(var: &'a NcVariable, name: &str) -> Option<&'a str> {
+    var.attributes
+        .iter()
+        .find(|a| a.name == name)
+        .and_then(|a| match &a.value {
+            NcAttributeValue::Text(text) => Some(text.as_str()),
+            _ => None,
+        })
+}
+
+/// Attributes of a variable whose values have the wrong type
+fn attribute_type_issues(var: &NcVariable) -> Vec<String> {
+    let mut issues = Vec::new();
+    for attribute in &var.attributes {
+        let name = attribute.name.as_str();
+        let is_text = matches!(attribute.value, NcAttributeValue::Text(_));
+        if TEXT_ATTRIBUTES.contains(&name) && !is_text {
+            issues.push(format!("The {} attribute must be text", name));
+        } else if NUMERIC_ATTRIBUTES.contains(&name) && is_text {
+            issues.push(format!("The {} attribute must be numeric", name));
+        } else if VALUE_ATTRIBUTES.contains(&name) {
+            let matches_type = matches!(
+                (&attribute.value, var.nc_type),
+                (NcAttributeValue::Floats(_), NcType::Float)
+                    | (NcAttributeValue::Doubles(_), NcType::Double)
+            );
+            if !matches_type {
+                issues.push(format!(
+                    "The {} attribute must have the type of the variable",
+                    name
+                ));
+            }
+        }
+    }
+    issues
+}
+
+/// References of a variable to variables that are missing or do not fit
+fn reference_issues(file: &ClassicFile, var: &NcVariable) -> Vec<String> {
+    let mut issues = Vec::new();
+    let find = |name: &str| file.variables.iter().find(|v| v.name == name);
+
+    if let Some(bounds) = text_attribute(var, "bounds") {
+        match find(bounds.trim()) {
+            None => issues.push(format!("The bounds variable {} is missing", bounds.trim())),
+            Some(bounds_var) => {
+                let fits = bounds_var.dimensions.len() == var.dimensions.len() + 1
+                    && bounds_var.dimensions.starts_with(&var.dimensions);
+                if !fits {
+                    issues.push(format!(
+                        "The bounds variable {} must have the dimensions of the variable \
+                         and one more",
+                        bounds_var.name
+                    ));
+                }
+            }
+        }
+    }
+    if let Some(grid_mapping) = text_attribute(var, "grid_mapping") {
+        for name in grid_mapping_names(grid_mapping) {
+            if find(name).is_none() {
+                issues.push(format!("The grid mapping variable {} is missing", name));
+            }
+        }
+    }
+    for attribute in ["coordinates", "ancillary_variables"] {
+        if let Some(names) = text_attribute(var, attribute) {
+            for name in names.split_whitespace() {
+                if find(name).is_none() {
+                    issues.push(format!(
+                        "The variable {} named in {} is missing",
+                        name, attribute
+                    ));
+                }
+            }
+        }
+    }
+    issues
+}
+
+/// Variables named by a `grid_mapping` attribute, either a variable name or
+/// the extended form `name: coordinates [name: coordinates ...]`
+pub fn grid_mapping_names(value: &str) -> Vec<&str> {
+    let extended: Vec<&str> = value
+        .split_whitespace()
+        .filter_map(|token| token.strip_suffix(':'))
+        .collect();
+    if extended.is_empty() {
+        value.split_whitespace().collect()
+    } else {
+        extended
+    }
+}
+
+/// Whether values strictly increase or strictly decrease
+fn is_strictly_monotonic(values: &[f64]) -> bool {
+    values.windows(2).all(|w| w[0] < w[1]) || values.windows(2).all(|w| w[0] > w[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netcdf_writer::{NcAttribute, NcDimension};
+
+    fn attribute(name: &str, value: NcAttributeValue) -> NcAttribute {
+        NcAttribute {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    fn text(name: &str, value: &str) -> NcAttribute {
+        attribute(name, NcAttributeValue::Text(value.to_string()))
+    }
+
+    /// A (lat) coordinate with bounds and a (lat) variable on a grid mapping
+    fn file() -> ClassicFile {
+        ClassicFile {
+            dimensions: vec![
+                NcDimension {
+                    name: "lat".to_string(),
+                    len: 3,
+                },
+                NcDimension {
+                    name: "nv".to_string(),
+                    len: 2,
+                },
+            ],
+            record_dimension: None,
+            attributes: vec![text("Conventions", "CF-1.8")],
+            variables: vec![
+                NcVariable {
+                    name: "lat".to_string(),
+                    dimensions: vec![0],
+                    nc_type: NcType::Double,
+                    attributes: vec![text("units", "degrees_north"), text("bounds", "lat_bnds")],
+                },
+                NcVariable {
+                    name: "lat_bnds".to_string(),
+                    dimensions: vec![0, 1],
+                    nc_type: NcType::Double,
+                    attributes: Vec::new(),
+                },
+                NcVariable {
+                    name: "crs".to_string(),
+                    dimensions: Vec::new(),
+                    nc_type: NcType::Float,
+                    attributes: vec![text("grid_mapping_name", "latitude_longitude")],
+                },
+                NcVariable {
+                    name: "t2m".to_string(),
+                    dimensions: vec![0],
+                    nc_type: NcType::Float,
+                    attributes: vec![
+                        text("grid_mapping", "crs"),
+                        attribute("_FillValue", NcAttributeValue::Floats(vec![-999.0])),
+                    ],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_check_file() {
+        let coordinates = HashMap::from([("lat".to_string(), vec![-10.0, 0.0, 10.0])]);
+        assert_eq!(check_file(&file(), &coordinates), Vec::new());
+
+        let mut broken = file();
+        broken.attributes.clear();
+        broken.variables.remove(1);
+        broken.variables[2].attributes[1] =
+            attribute("_FillValue", NcAttributeValue::Doubles(vec![-999.0]));
+        broken.variables[2]
+            .attributes
+            .push(text("coordinates", "height"));
+        let coordinates = HashMap::from([("lat".to_string(), vec![-10.0, 10.0, 0.0])]);
+        let issues: Vec<String> = check_file(&broken, &coordinates)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                "The Conventions attribute is missing",
+                "lat: The bounds variable lat_bnds is missing",
+                "lat: The coordinate is not strictly monotonic",
+                "t2m: The _FillValue attribute must have the type of the variable",
+                "t2m: The variable height named in coordinates is missing",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grid_mapping_names() {
+        assert_eq!(grid_mapping_names("crs"), vec!["crs"]);
+        assert_eq!(
+            grid_mapping_names("crs_a: x y crs_b: lat lon"),
+            vec!["crs_a", "crs_b"]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Decoding of CF time coordinates.
+//!
+//! CF time coordinates store offsets from a reference date, described by a
+//! `units` attribute such as `days since 1982-01-01` or
+//! `hours since 2000-01-01 00:00:00 UTC`. Only the standard, Gregorian and
+//! proleptic Gregorian calendars are decoded; the standard calendar is treated
+//! as proleptic Gregorian, so dates before 1582-10-15 are not shifted to the
+//! Julian calendar. Month and year units are not decoded, as their length is
+//! ambiguous.
+
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat};
+
+use crate::state::{AppState, AttributeValue};
+
+/// Calendars whose dates are decoded
+const GREGORIAN_CALENDARS: [&str; 3] = ["standard", "gregorian", "proleptic_gregorian"];
+
+/// Units of a CF time coordinate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeUnits {
+    /// Length of one unit in seconds
+    pub seconds_per_unit: f64,
+    /// Date and time of the zero offset, in UTC
+    pub reference: NaiveDateTime,
+}
+
+impl TimeUnits {
+    /// Parse a `<unit> since <reference>` units string
+    pub fn parse(units: &str) -> Option<Self> {
+        let (unit, reference) = units.trim().split_once(" since ")?;
+        Some(Self {
+            seconds_per_unit: unit_seconds(unit)?,
+            reference: parse_reference(reference)?,
+        })
+    }
+
+    /// Units of a coordinate variable with the given attributes
+    ///
+    /// Returns `None` when the variable is not a time coordinate or uses a
+    /// calendar that is not decoded.
+    pub fn from_attributes(attributes: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let AttributeValue::Text(units) = attributes.get("units")? else {
+            return None;
+        };
+        match attributes.get("calendar") {
+            None => {}
+            Some(AttributeValue::Text(calendar))
+                if GREGORIAN_CALENDARS.contains(&calendar.trim().to_lowercase().as_str()) => {}
+            Some(_) => return None,
+        }
+        Self::parse(units)
+    }
+
+    /// Date and time of an offset, rounded to milliseconds
+    pub fn datetime(&self, value: f64) -> Option<NaiveDateTime> {
+        let millis = value * self.seconds_per_unit * 1_000.0;
+        if !millis.is_finite() || millis.abs() > i64::MAX as f64 {
+            return None;
+        }
+        self.reference
+            .checked_add_signed(Duration::try_milliseconds(millis.round() as i64)?)
+    }
+
+    /// Offset of a date and time, the inverse of [`Self::datetime`]
+    pub fn offset(&self, datetime: NaiveDateTime) -> f64 {
+        (datetime - self.reference).num_milliseconds() as f64 / 1_000.0 / self.seconds_per_unit
+    }
+
+    /// ISO 8601 representation of an offset, e.g. `1982-01-02T00:00:00Z`
+    pub fn iso(&self, value: f64) -> Option<String> {
+        let datetime = self.datetime(value)?;
+        Some(
+            datetime
+                .and_utc()
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        )
+    }
+}
+
+/// Length in seconds of a time unit such as `hours`, as used in durations
+/// like forecast lead times
+pub fn unit_seconds(unit: &str) -> Option<f64> {
+    match unit.trim().to_lowercase().as_str() {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1.0),
+        "min" | "mins" | "minute" | "minutes" => Some(60.0),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(3_600.0),
+        "d" | "day" | "days" => Some(86_400.0),
+        "week" | "weeks" => Some(604_800.0),
+        _ => None,
+    }
+}
+
+/// Units of the coordinate variable of a dimension, if it is a time coordinate
+pub fn time_units(state: &AppState, dim_name: &str) -> Option<TimeUnits> {
+    TimeUnits::from_attributes(&state.get_variable_metadata(dim_name)?.attributes)
+}
+
+/// Parse an ISO 8601 date or date and time such as `2023-01-15` or
+/// `2023-01-15T06:00:00Z`, as accepted in reference dates
+pub fn parse_datetime(text: &str) -> Option<NaiveDateTime> {
+    parse_reference(text)
+}
+
+/// Parse a reference date such as `1982-01-01`, `1982-1-1 6:00` or
+/// `2000-01-01T00:00:00.5Z`, converting explicit UTC offsets to UTC
+fn parse_reference(reference: &str) -> Option<NaiveDateTime> {
+    let reference = reference.trim();
+    let reference = reference
+        .strip_suffix("UTC")
+        .or_else(|| reference.strip_suffix('Z'))
+        .unwrap_or(reference)
+        .trim();
+
+    let (date, rest) = match reference.find(['T', ' ']) {
+        Some(split) => (&reference[..split], reference[split + 1..].trim()),
+        None => (reference, ""),
+    };
+
+    let mut fields = date.splitn(3, '-');
+    let year = fields.next()?.parse().ok()?;
+    let month = fields.next()?.parse().ok()?;
+    let day = fields.next()?.parse().ok()?;
+    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+
+    // Split off an offset such as "+05:30", "-0800" or a separate " +00:00"
+    let (time, offset) = match rest.find(['+', '-']) {
+        Some(split) => (rest[..split].trim(), Some(&rest[split..])),
+        None => (rest, None),
+    };
+    let time = if time.is_empty() {
+        NaiveTime::MIN
+    } else {
+        let mut fields = time.splitn(3, ':');
+        let hour = fields.next()?.parse().ok()?;
+        let minute = fields.next().map_or(Some(0), |m| m.parse().ok())?;
+        let seconds: f64 = fields.next().map_or(Some(0.0), |s| s.parse().ok())?;
+        if !(0.0..60.0).contains(&seconds) {
+            return None;
+        }
+        let nanos = ((seconds.fract() * 1e9).round() as u32).min(999_999_999);
+        NaiveTime::from_hms_nano_opt(hour, minute, seconds.trunc() as u32, nanos)?
+    };
+
+    let offset_minutes = match offset {
+        Some(offset) => parse_offset_minutes(offset)?,
+        None => 0,
+    };
+    date.and_time(time)
+        .checked_sub_signed(Duration::try_minutes(offset_minutes)?)
+}
+
+/// Minutes east of UTC of an offset such as `+05:30`, `-0800` or `+1`
+fn parse_offset_minutes(offset: &str) -> Option<i64> {
+    let (sign, digits) = match offset.split_at(1) {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    let (hours, minutes) = match digits.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if digits.len() == 4 => digits.split_at(2),
+        None => (digits, "0"),
+    };
+    let hours: i64 = hours.trim().parse().ok()?;
+    let minutes: i64 = minutes.trim().parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        let units = TimeUnits::parse("days since 1982-01-01").unwrap();
+        assert_eq!(units.seconds_per_unit, 86_400.0);
+        assert_eq!(units.iso(0.0).unwrap(), "1982-01-01T00:00:00Z");
+        assert_eq!(units.iso(1.5).unwrap(), "1982-01-02T12:00:00Z");
+
+        let units = TimeUnits::parse("hours since 2000-1-1 6:00:00 UTC").unwrap();
+        assert_eq!(units.iso(-12.0).unwrap(), "1999-12-31T18:00:00Z");
+
+        let units = TimeUnits::parse("seconds since 2020-03-01T00:00:00.5Z").unwrap();
+        assert_eq!(units.iso(1.0).unwrap(), "2020-03-01T00:00:01.500Z");
+
+        // Offsets are converted to UTC
+        let units = TimeUnits::parse("minutes since 2020-01-01 00:00 +05:30").unwrap();
+        assert_eq!(units.iso(0.0).unwrap(), "2019-12-31T18:30:00Z");
+
+        assert!(TimeUnits::parse("months since 2000-01-01").is_none());
+        assert!(TimeUnits::parse("degrees_north").is_none());
+        assert!(TimeUnits::parse("days since yesterday").is_none());
+    }
+
+    #[test]
+    fn test_offset() {
+        let units = TimeUnits::parse("hours since 2000-01-01").unwrap();
+        let datetime = parse_datetime("2000-01-02T06:00:00Z").unwrap();
+        assert_eq!(units.offset(datetime), 30.0);
+        assert_eq!(units.datetime(30.0).unwrap(), datetime);
+        assert_eq!(
+            parse_datetime("2000-01-02T08:00:00+02:00").unwrap(),
+            datetime
+        );
+        assert!(parse_datetime("yesterday").is_none());
+    }
+
+    #[test]
+    fn test_from_attributes() {
+        let mut attributes = HashMap::new();
+        attributes.insert(
+            "units".to_string(),
+            AttributeValue::Text("days since 1982-01-01".to_string()),
+        );
+        assert!(TimeUnits::from_attributes(&attributes).is_some());
+
+        attributes.insert(
+            "calendar".to_string(),
+            AttributeValue::Text("proleptic_gregorian".to_string()),
+        );
+        assert!(TimeUnits::from_attributes(&attributes).is_some());
+
+        attributes.insert(
+            "calendar".to_string(),
+            AttributeValue::Text("360_day".to_string()),
+        );
+        assert!(TimeUnits::from_attributes(&attributes).is_none());
+    }
+}
@@ -0,0 +1,675 @@
+//! Climatological normals and the anomalies relative to them.
+//!
+//! `/image` and `/data` accept `mode=percent_normal` (the value as a
+//! percentage of the mean) and `mode=zscore` (the standardized anomaly, the
+//! departure from the mean in standard deviations), computed per cell when
+//! the request is served, so drought monitoring products need no separate
+//! processing pipeline.
+//!
+//! The normals of a variable have its dimensions, with the time dimension
+//! replaced by the period of the year: 12 calendar months, or a single
+//! period for the whole record. They come from the `data.climatology_file`
+//! when it has a variable of the same name (the mean) and, for z-scores, one
+//! named `<name>_std` (the standard deviation). Otherwise they are computed
+//! from the loaded data on first use, by calendar month when the time
+//! coordinate has CF units, and kept with the dataset.
+//!
+//! Values are unpacked with `scale_factor` and `add_offset` before the
+//! transform; missing values, a zero mean and a zero standard deviation give
+//! missing results.
+
+use chrono::Datelike;
+use ndarray::{Array, Axis, Ix2, IxDyn, Zip};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::cf_time::time_units;
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, HorizontalField};
+use crate::query::select_values;
+use crate::slice_stats::MissingData;
+use crate::state::{AppState, AttributeValue, Metadata, Variable};
+
+/// Number of periods of monthly normals
+const MONTHS: usize = 12;
+
+/// Suffix of the standard deviation variables of a climatology file
+const STD_SUFFIX: &str = "_std";
+
+/// Transform of values relative to their normals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalMode {
+    /// The value as a percentage of the mean
+    PercentNormal,
+    /// The departure from the mean in standard deviations
+    ZScore,
+}
+
+impl NormalMode {
+    /// Parse the `mode` parameter: percent_normal or zscore
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "percent_normal" => Ok(NormalMode::PercentNormal),
+            "zscore" => Ok(NormalMode::ZScore),
+            other => Err(RossbyError::InvalidParameter {
+                param: "mode".to_string(),
+                message: format!(
+                    "Unknown mode: {}. Valid modes are percent_normal and zscore",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Name of the mode in parameters and long names
+    pub fn name(self) -> &'static str {
+        match self {
+            NormalMode::PercentNormal => "percent_normal",
+            NormalMode::ZScore => "zscore",
+        }
+    }
+
+    /// The value the transform gives for a value equal to the mean
+    pub fn center(self) -> f32 {
+        match self {
+            NormalMode::PercentNormal => 100.0,
+            NormalMode::ZScore => 0.0,
+        }
+    }
+
+    /// Transform an unpacked value, NaN when it is undefined
+    fn apply(self, value: f32, mean: f32, std: f32) -> f32 {
+        match self {
+            NormalMode::PercentNormal if mean == 0.0 => f32::NAN,
+            NormalMode::PercentNormal => 100.0 * value / mean,
+            NormalMode::ZScore if std == 0.0 => f32::NAN,
+            NormalMode::ZScore => (value - mean) / std,
+        }
+    }
+
+    /// Metadata of a variable transformed by this mode
+    ///
+    /// The result is unpacked and its missing values are NaN, so packing
+    /// and missing-data attributes are dropped.
+    pub fn metadata(self, var: &Variable) -> Variable {
+        let long_name = match var.attributes.get("long_name") {
+            Some(AttributeValue::Text(long_name)) => long_name.clone(),
+            _ => var.name.clone(),
+        };
+        let (suffix, units) = match self {
+            NormalMode::PercentNormal => ("percent of normal", "%"),
+            NormalMode::ZScore => ("standardized anomaly", "1"),
+        };
+        Variable {
+            name: var.name.clone(),
+            dimensions: var.dimensions.clone(),
+            shape: var.shape.clone(),
+            attributes: HashMap::from([
+                (
+                    "long_name".to_string(),
+                    AttributeValue::Text(format!("{} ({})", long_name, suffix)),
+                ),
+                ("units".to_string(), AttributeValue::Text(units.to_string())),
+            ]),
+            dtype: "f32".to_string(),
+        }
+    }
+}
+
+/// Mean and standard deviation of a variable per period of the year
+#[derive(Debug, Clone)]
+pub struct Normals {
+    /// Means, shaped like the variable with the time axis holding periods
+    pub mean: Array<f32, IxDyn>,
+    /// Standard deviations, shaped like `mean` (None = not supplied)
+    pub std: Option<Array<f32, IxDyn>>,
+    /// Whether the periods are the 12 calendar months rather than one period
+    pub monthly: bool,
+}
+
+/// Normals of the variables of a dataset
+#[derive(Debug, Clone, Default)]
+pub struct ClimatologyStore {
+    /// Normals read from the climatology file
+    supplied: HashMap<String, Arc<Normals>>,
+    /// Normals computed from the loaded data on first use
+    computed: Arc<RwLock<HashMap<String, Arc<Normals>>>>,
+}
+
+impl ClimatologyStore {
+    /// Create a store without supplied normals
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a store with the normals of a climatology file
+    ///
+    /// Every variable of the dataset that the file has a variable of the same
+    /// name for gets its normals from the file, which must have the same
+    /// dimensions with a time dimension of 12 months, of a single step, or
+    /// none.
+    pub fn from_file(
+        state: &AppState,
+        metadata: &Metadata,
+        mut data: HashMap<String, Array<f32, IxDyn>>,
+    ) -> Result<Self> {
+        let time_dim = time_dimension(state);
+        let mut supplied = HashMap::new();
+        for (name, var) in &state.metadata.variables {
+            if state.metadata.dimensions.contains_key(name) {
+                continue;
+            }
+            let Some(mean) = supplied_array(time_dim.as_deref(), var, metadata, &mut data, name)?
+            else {
+                continue;
+            };
+            let std_name = format!("{}{}", name, STD_SUFFIX);
+            let std = supplied_array(time_dim.as_deref(), var, metadata, &mut data, &std_name)?;
+            let monthly = time_dim
+                .as_ref()
+                .and_then(|time_dim| var.dimensions.iter().position(|d| d == time_dim))
+                .is_some_and(|axis| mean.len_of(Axis(axis)) == MONTHS);
+            if let Some(std) = &std {
+                if std.shape() != mean.shape() {
+                    return Err(climatology_error(format!(
+                        "{} has shape {:?}, but {} has {:?}",
+                        std_name,
+                        std.shape(),
+                        name,
+                        mean.shape()
+                    )));
+                }
+            }
+            supplied.insert(name.clone(), Arc::new(Normals { mean, std, monthly }));
+        }
+        Ok(Self {
+            supplied,
+            computed: Arc::default(),
+        })
+    }
+
+    /// Normals of a variable, computed from the loaded data unless supplied
+    pub fn normals(&self, state: &AppState, var_name: &str) -> Result<Arc<Normals>> {
+        if let Some(normals) = self.supplied.get(var_name) {
+            return Ok(normals.clone());
+        }
+        if let Some(normals) = self.computed.read().get(var_name) {
+            return Ok(normals.clone());
+        }
+        let normals = Arc::new(compute_normals(state, var_name)?);
+        self.computed
+            .write()
+            .insert(var_name.to_string(), normals.clone());
+        Ok(normals)
+    }
+
+    /// Drop the computed normals, returning how many there were; supplied
+    /// normals are kept
+    pub fn clear(&self) -> usize {
+        std::mem::take(&mut *self.computed.write()).len()
+    }
+
+    /// A store with the same supplied normals and no computed ones, for a
+    /// dataset whose data changed
+    pub fn supplied_only(&self) -> Self {
+        Self {
+            supplied: self.supplied.clone(),
+            computed: Arc::default(),
+        }
+    }
+
+    /// Number of variables with supplied normals
+    pub fn supplied_len(&self) -> usize {
+        self.supplied.len()
+    }
+}
+
+/// Transform values extracted from a variable relative to its normals
+///
+/// `selected_indices` is the selection `values` were extracted with, as in
+/// `/data`: dimensions without an entry are selected in full, and those with
+/// a single index are not axes of `values`.
+pub fn transform(
+    state: &AppState,
+    var_name: &str,
+    mode: NormalMode,
+    values: Array<f32, IxDyn>,
+    selected_indices: &HashMap<String, Vec<usize>>,
+) -> Result<Array<f32, IxDyn>> {
+    let var =
+        state
+            .get_variable_metadata(var_name)
+            .ok_or_else(|| RossbyError::InvalidParameter {
+                param: "mode".to_string(),
+                message: format!(
+                    "mode={} applies to variables, not to '{}'",
+                    mode.name(),
+                    var_name
+                ),
+            })?;
+    let normals = state.climatology.normals(state, var_name)?;
+    let std = match (&normals.std, mode) {
+        (Some(std), _) => Some(std),
+        (None, NormalMode::ZScore) => {
+            return Err(RossbyError::InvalidParameter {
+                param: "mode".to_string(),
+                message: format!(
+                    "The climatology file has no {}{} for z-scores",
+                    var_name, STD_SUFFIX
+                ),
+            })
+        }
+        (None, NormalMode::PercentNormal) => None,
+    };
+
+    // The periods of the selected time steps select the normals
+    let mut normal_indices = selected_indices.clone();
+    if let Some(time_dim) = time_dimension(state).filter(|dim| var.dimensions.contains(dim)) {
+        let steps = match selected_indices.get(&time_dim) {
+            Some(steps) => steps.clone(),
+            None => (0..state.metadata.dimensions[&time_dim].size).collect(),
+        };
+        let periods = steps
+            .iter()
+            .map(|&step| period(state, &time_dim, step, normals.monthly))
+            .collect::<Result<Vec<usize>>>()?;
+        normal_indices.insert(time_dim, periods);
+    }
+
+    let mean = select_values(normals.mean.view(), &var.dimensions, &normal_indices);
+    let std = std.map(|std| select_values(std.view(), &var.dimensions, &normal_indices));
+    if mean.shape() != values.shape() {
+        return Err(RossbyError::Conversion {
+            message: format!(
+                "Normals of {} have shape {:?} for values of shape {:?}",
+                var_name,
+                mean.shape(),
+                values.shape()
+            ),
+        });
+    }
+
+    let mut values = unpack(var, values);
+    match std {
+        Some(std) => Zip::from(&mut values)
+            .and(&mean)
+            .and(&std)
+            .for_each(|value, &mean, &std| *value = mode.apply(*value, mean, std)),
+        None => Zip::from(&mut values)
+            .and(&mean)
+            .for_each(|value, &mean| *value = mode.apply(*value, mean, f32::NAN)),
+    }
+    Ok(values)
+}
+
+/// Transformed horizontal field of a variable, as rendered by `/image`
+///
+/// `dim_indices` pins the non-horizontal dimensions as in
+/// [`HorizontalField::from_state`].
+pub fn field(
+    state: &AppState,
+    var_name: &str,
+    mode: NormalMode,
+    dim_indices: &HashMap<String, usize>,
+) -> Result<HorizontalField> {
+    let mut field = HorizontalField::from_state(state, var_name, dim_indices, None)?;
+    let var = state.get_variable_metadata_checked(var_name)?;
+    let (lat_axis, lon_axis) = find_lat_lon_axes(&var.dimensions).ok_or_else(|| {
+        RossbyError::VariableNotSuitableForImage {
+            name: var_name.to_string(),
+        }
+    })?;
+    let selected_indices: HashMap<String, Vec<usize>> = var
+        .dimensions
+        .iter()
+        .enumerate()
+        .filter(|&(axis, _)| axis != lat_axis && axis != lon_axis)
+        .map(|(_, dim)| {
+            (
+                dim.clone(),
+                vec![dim_indices.get(dim).copied().unwrap_or(0)],
+            )
+        })
+        .collect();
+
+    // Fields are latitude-major; the normals follow the variable
+    let swapped = lon_axis < lat_axis;
+    let values = std::mem::take(&mut field.values);
+    let values = if swapped {
+        values.reversed_axes()
+    } else {
+        values
+    };
+    let values = transform(state, var_name, mode, values.into_dyn(), &selected_indices)?
+        .into_dimensionality::<Ix2>()
+        .map_err(|e| RossbyError::Conversion {
+            message: format!("Expected a 2D latitude/longitude slab: {}", e),
+        })?;
+    field.values = if swapped {
+        values.reversed_axes()
+    } else {
+        values
+    };
+    Ok(field)
+}
+
+/// File-specific name of the time dimension, if the dataset has one
+fn time_dimension(state: &AppState) -> Option<String> {
+    state.resolve_dimension("time").ok().map(str::to_string)
+}
+
+/// Period of the year of a time step: its month, or 0 for a single period
+fn period(state: &AppState, time_dim: &str, step: usize, monthly: bool) -> Result<usize> {
+    if !monthly {
+        return Ok(0);
+    }
+    state
+        .get_coordinate(time_dim)
+        .and_then(|times| times.get(step))
+        .and_then(|&time| time_units(state, time_dim)?.datetime(time))
+        .map(|datetime| datetime.month0() as usize)
+        .ok_or_else(|| {
+            climatology_error(format!(
+                "Cannot tell the month of time step {}; monthly normals need a time \
+                 coordinate with CF units",
+                step
+            ))
+        })
+}
+
+/// Values with missing ones as NaN, unpacked with `scale_factor` and
+/// `add_offset`
+pub(crate) fn unpack(var: &Variable, mut values: Array<f32, IxDyn>) -> Array<f32, IxDyn> {
+    MissingData::for_variable(var).mask(values.iter_mut());
+    let attribute = |name: &str| var.attributes.get(name).and_then(AttributeValue::as_f64);
+    let scale = attribute("scale_factor").unwrap_or(1.0) as f32;
+    let offset = attribute("add_offset").unwrap_or(0.0) as f32;
+    if scale != 1.0 || offset != 0.0 {
+        values.mapv_inplace(|value| value * scale + offset);
+    }
+    values
+}
+
+/// Mean and standard deviation of every cell over the time steps of each
+/// period, ignoring missing values
+fn compute_normals(state: &AppState, var_name: &str) -> Result<Normals> {
+    let var = state.get_variable_metadata_checked(var_name)?;
+    let time_dim = time_dimension(state).unwrap_or_default();
+    let axis = var
+        .dimensions
+        .iter()
+        .position(|dim| *dim == time_dim)
+        .ok_or_else(|| climatology_error(format!("{} has no time dimension", var_name)))?;
+    let data = unpack(var, state.get_variable_checked(var_name)?.into_owned());
+
+    let steps = data.len_of(Axis(axis));
+    let monthly = time_units(state, &time_dim).is_some()
+        && (0..steps).all(|step| period(state, &time_dim, step, true).is_ok());
+    let periods = if monthly { MONTHS } else { 1 };
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); periods];
+    for step in 0..steps {
+        members[period(state, &time_dim, step, monthly)?].push(step);
+    }
+
+    let mut shape = data.shape().to_vec();
+    shape[axis] = periods;
+    let mut mean = Array::from_elem(IxDyn(&shape), f32::NAN);
+    let mut std = Array::from_elem(IxDyn(&shape), f32::NAN);
+    for (period, steps) in members.iter().enumerate() {
+        let slab_shape = data.index_axis(Axis(axis), 0).raw_dim();
+        let mut count = Array::<f64, IxDyn>::zeros(slab_shape.clone());
+        let mut sum = Array::<f64, IxDyn>::zeros(slab_shape.clone());
+        let mut sum_sq = Array::<f64, IxDyn>::zeros(slab_shape);
+        for &step in steps {
+            Zip::from(&mut count)
+                .and(&mut sum)
+                .and(&mut sum_sq)
+                .and(data.index_axis(Axis(axis), step))
+                .for_each(|count, sum, sum_sq, &value| {
+                    if value.is_finite() {
+                        *count += 1.0;
+                        *sum += value as f64;
+                        *sum_sq += value as f64 * value as f64;
+                    }
+                });
+        }
+        Zip::from(mean.index_axis_mut(Axis(axis), period))
+            .and(std.index_axis_mut(Axis(axis), period))
+            .and(&count)
+            .and(&sum)
+            .and(&sum_sq)
+            .for_each(|mean, std, &count, &sum, &sum_sq| {
+                if count > 0.0 {
+                    let m = sum / count;
+                    *mean = m as f32;
+                    *std = (sum_sq / count - m * m).max(0.0).sqrt() as f32;
+                }
+            });
+    }
+
+    Ok(Normals {
+        mean,
+        std: Some(std),
+        monthly,
+    })
+}
+
+/// A variable of the climatology file matching `var`, unpacked and with the
+/// time axis in place
+fn supplied_array(
+    time_dim: Option<&str>,
+    var: &Variable,
+    metadata: &Metadata,
+    data: &mut HashMap<String, Array<f32, IxDyn>>,
+    name: &str,
+) -> Result<Option<Array<f32, IxDyn>>> {
+    let (Some(normal), Some(array)) = (metadata.variables.get(name), data.remove(name)) else {
+        return Ok(None);
+    };
+    let mismatch = || {
+        climatology_error(format!(
+            "{} has dimensions {:?} {:?}, which do not match {:?} {:?}",
+            name, normal.dimensions, normal.shape, var.dimensions, var.shape
+        ))
+    };
+    let time_axis = time_dim.and_then(|time_dim| var.dimensions.iter().position(|d| d == time_dim));
+    let mut array = unpack(normal, array);
+
+    // A climatology without time has a single period
+    if let Some(axis) = time_axis {
+        if normal.dimensions.len() + 1 == var.dimensions.len()
+            && !normal.dimensions.contains(&var.dimensions[axis])
+        {
+            array.insert_axis_inplace(Axis(axis));
+        }
+    }
+    let mut dimensions = normal.dimensions.clone();
+    if dimensions.len() < var.dimensions.len() {
+        if let Some(axis) = time_axis {
+            dimensions.insert(axis, var.dimensions[axis].clone());
+        }
+    }
+    if dimensions != var.dimensions {
+        return Err(mismatch());
+    }
+    for (axis, (&len, &expected)) in array.shape().iter().zip(&var.shape).enumerate() {
+        let periods_ok = Some(axis) == time_axis && (len == MONTHS || len == 1);
+        if len != expected && !periods_ok {
+            return Err(mismatch());
+        }
+    }
+    Ok(Some(array))
+}
+
+fn climatology_error(message: String) -> RossbyError {
+    RossbyError::DataNotFound {
+        message: format!("Climatology: {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::Dimension;
+
+    /// A (time, lat, lon) variable over 24 months of 1 + 2 grid cells
+    fn create_test_state() -> AppState {
+        let dimension = |name: &str, size: usize| {
+            let dimension = Dimension {
+                name: name.to_string(),
+                size,
+                is_unlimited: false,
+            };
+            (name.to_string(), dimension)
+        };
+        let variable = |name: &str, dims: &[&str], shape: &[usize], attributes| {
+            let var = Variable {
+                name: name.to_string(),
+                dimensions: dims.iter().map(|d| d.to_string()).collect(),
+                shape: shape.to_vec(),
+                attributes,
+                dtype: "f32".to_string(),
+            };
+            (name.to_string(), var)
+        };
+        let time_attributes = HashMap::from([(
+            "units".to_string(),
+            AttributeValue::Text("days since 2000-01-01".to_string()),
+        )]);
+        let precip_attributes = HashMap::from([
+            ("_FillValue".to_string(), AttributeValue::Number(-1.0)),
+            (
+                "long_name".to_string(),
+                AttributeValue::Text("Precipitation".to_string()),
+            ),
+        ]);
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::from([
+                dimension("time", 24),
+                dimension("lat", 1),
+                dimension("lon", 2),
+            ]),
+            variables: HashMap::from([
+                variable("time", &["time"], &[24], time_attributes),
+                variable("lat", &["lat"], &[1], HashMap::new()),
+                variable("lon", &["lon"], &[2], HashMap::new()),
+                variable(
+                    "tp",
+                    &["time", "lat", "lon"],
+                    &[24, 1, 2],
+                    precip_attributes,
+                ),
+            ]),
+            coordinates: HashMap::from([
+                (
+                    "time".to_string(),
+                    (0..24).map(|month| month as f64 * 30.5 + 10.0).collect(),
+                ),
+                ("lat".to_string(), vec![0.0]),
+                ("lon".to_string(), vec![0.0, 1.0]),
+            ]),
+            groups: HashMap::new(),
+        };
+        // Month m of year y has m + 1 in the first cell, 10 + 2y in the second,
+        // except a missing value in the first January
+        let values: Vec<f32> = (0..24)
+            .flat_map(|step| [(step % 12 + 1) as f32, (10 + 2 * (step / 12)) as f32])
+            .collect();
+        let mut tp = Array::from_shape_vec(IxDyn(&[24, 1, 2]), values).unwrap();
+        tp[[0, 0, 1]] = -1.0;
+        AppState::new(
+            Config::default(),
+            metadata,
+            HashMap::from([("tp".to_string(), tp)]),
+        )
+    }
+
+    #[test]
+    fn test_normal_mode() {
+        assert_eq!(
+            NormalMode::parse("percent_normal").unwrap(),
+            NormalMode::PercentNormal
+        );
+        assert_eq!(NormalMode::parse("ZScore").unwrap(), NormalMode::ZScore);
+        assert!(NormalMode::parse("anomaly").is_err());
+        assert_eq!(NormalMode::PercentNormal.apply(5.0, 4.0, 1.0), 125.0);
+        assert_eq!(NormalMode::ZScore.apply(5.0, 4.0, 2.0), 0.5);
+        assert!(NormalMode::PercentNormal.apply(5.0, 0.0, 1.0).is_nan());
+        assert!(NormalMode::ZScore.apply(5.0, 4.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn test_computed_normals() {
+        let state = create_test_state();
+        let normals = state.climatology.normals(&state, "tp").unwrap();
+        assert!(normals.monthly);
+        assert_eq!(normals.mean.shape(), &[12, 1, 2]);
+        assert_eq!(normals.mean[[3, 0, 0]], 4.0);
+        assert_eq!(normals.mean[[3, 0, 1]], 11.0);
+        assert_eq!(normals.std.as_ref().unwrap()[[3, 0, 1]], 1.0);
+        // The missing January value is left out
+        assert_eq!(normals.mean[[0, 0, 1]], 12.0);
+
+        // Second year, second cell: one standard deviation above normal; the
+        // first cell is the same every year, so it has no z-score
+        let indices = HashMap::from([("time".to_string(), vec![15, 16])]);
+        let values = select_values(
+            state.data["tp"].view(),
+            &["time", "lat", "lon"].map(String::from),
+            &indices,
+        );
+        let zscore = transform(&state, "tp", NormalMode::ZScore, values.clone(), &indices).unwrap();
+        assert_eq!(zscore.shape(), &[2, 1, 2]);
+        assert_eq!(zscore[[0, 0, 1]], 1.0);
+        assert!(zscore[[0, 0, 0]].is_nan());
+
+        let percent = transform(&state, "tp", NormalMode::PercentNormal, values, &indices).unwrap();
+        assert_eq!(percent[[1, 0, 0]], 100.0);
+        assert!((percent[[1, 0, 1]] - 1200.0 / 11.0).abs() < 1e-4);
+
+        // Flushed normals are computed again on next use
+        assert_eq!(state.climatology.clear(), 1);
+        assert_eq!(state.climatology.clear(), 0);
+        let recomputed = state.climatology.normals(&state, "tp").unwrap();
+        assert!(!Arc::ptr_eq(&normals, &recomputed));
+        assert_eq!(recomputed.mean, normals.mean);
+    }
+
+    #[test]
+    fn test_supplied_normals() {
+        let state = create_test_state();
+        // A climatology without time: 5 and 10 in every month
+        let mut metadata = state.metadata.clone();
+        metadata.variables.get_mut("tp").unwrap().dimensions =
+            vec!["lat".to_string(), "lon".to_string()];
+        metadata.variables.get_mut("tp").unwrap().shape = vec![1, 2];
+        let data = HashMap::from([(
+            "tp".to_string(),
+            Array::from_shape_vec(IxDyn(&[1, 2]), vec![5.0, 10.0]).unwrap(),
+        )]);
+        let store = ClimatologyStore::from_file(&state, &metadata, data).unwrap();
+        assert_eq!(store.supplied_len(), 1);
+        let normals = store.normals(&state, "tp").unwrap();
+        assert!(!normals.monthly);
+        assert_eq!(normals.mean.shape(), &[1, 1, 2]);
+        assert!(normals.std.is_none());
+
+        // Shapes must match the variable
+        let data = HashMap::from([(
+            "tp".to_string(),
+            Array::from_shape_vec(IxDyn(&[1, 3]), vec![5.0, 10.0, 15.0]).unwrap(),
+        )]);
+        metadata.variables.get_mut("tp").unwrap().shape = vec![1, 3];
+        assert!(ClimatologyStore::from_file(&state, &metadata, data).is_err());
+
+        let meta = NormalMode::ZScore.metadata(&state.metadata.variables["tp"]);
+        assert!(matches!(
+            &meta.attributes["long_name"],
+            AttributeValue::Text(name) if name == "Precipitation (standardized anomaly)"
+        ));
+        assert!(!meta.attributes.contains_key("_FillValue"));
+    }
+}
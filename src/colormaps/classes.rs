@@ -0,0 +1,318 @@
+//! Classified (choropleth-style) color scales.
+//!
+//! `classes=jenks:7` on `/image` draws a slice in 7 discrete colors instead of
+//! a continuous ramp, with class boundaries placed at the natural breaks of
+//! the slice distribution (Jenks). Natural breaks minimize the spread of
+//! values within each class, which brings out structure in skewed variables
+//! such as precipitation that a linear ramp compresses into one color.
+//!
+//! Breaks are computed with Fisher's exact algorithm over a histogram of the
+//! slice rather than over every value, which keeps the cost independent of
+//! the grid size. Each class spans whole histogram bins, and its upper break
+//! is the largest value falling in it.
+
+use crate::colormaps::Colormap;
+use crate::error::{Result, RossbyError};
+
+/// Response header listing the class breaks of a classified image
+pub const CLASS_BREAKS_HEADER: &str = "x-rossby-class-breaks";
+
+/// Number of classes when only the method is given
+pub const DEFAULT_CLASSES: usize = 5;
+
+/// Largest number of classes
+pub const MAX_CLASSES: usize = 32;
+
+/// Number of histogram bins the breaks are computed over
+const HISTOGRAM_BINS: usize = 512;
+
+/// Method placing the class breaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassMethod {
+    /// Jenks natural breaks
+    Jenks,
+}
+
+/// A requested classification, e.g. `jenks:7`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classification {
+    pub method: ClassMethod,
+    pub count: usize,
+}
+
+impl Classification {
+    /// Parse `method[:count]`
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = |message: String| RossbyError::InvalidParameter {
+            param: "classes".to_string(),
+            message,
+        };
+        let (method, count) = match value.split_once(':') {
+            Some((method, count)) => (method, Some(count)),
+            None => (value, None),
+        };
+        let method = match method.trim().to_lowercase().as_str() {
+            "jenks" => ClassMethod::Jenks,
+            other => {
+                return Err(invalid(format!(
+                    "Unknown classification method: {}. Valid values are 'jenks'",
+                    other
+                )))
+            }
+        };
+        let count = match count {
+            Some(count) => count
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| invalid(format!("Invalid number of classes: {}", count)))?,
+            None => DEFAULT_CLASSES,
+        };
+        if !(2..=MAX_CLASSES).contains(&count) {
+            return Err(invalid(format!(
+                "Number of classes must be between 2 and {}, got {}",
+                MAX_CLASSES, count
+            )));
+        }
+        Ok(Self { method, count })
+    }
+
+    /// Class breaks of a distribution
+    ///
+    /// Returns the ascending boundaries from the smallest to the largest
+    /// finite value, one more than the number of classes. Distributions with
+    /// fewer distinct values than requested classes get fewer classes; an
+    /// empty distribution gets none.
+    pub fn breaks(&self, values: impl IntoIterator<Item = f32>) -> Vec<f64> {
+        match self.method {
+            ClassMethod::Jenks => jenks_breaks(values, self.count),
+        }
+    }
+}
+
+/// Values falling in one histogram bin
+#[derive(Debug, Clone, Copy)]
+struct Bin {
+    count: f64,
+    sum: f64,
+    sum_sq: f64,
+    min: f64,
+    max: f64,
+}
+
+/// Jenks natural breaks of the finite values, over a histogram
+pub fn jenks_breaks(values: impl IntoIterator<Item = f32>, count: usize) -> Vec<f64> {
+    let values: Vec<f64> = values
+        .into_iter()
+        .filter(|v| v.is_finite())
+        .map(|v| v as f64)
+        .collect();
+    let Some(bins) = histogram(&values) else {
+        return Vec::new();
+    };
+    let classes = count.min(bins.len()).max(1);
+
+    // Prefix sums over the bins, so the squared deviation of any run of
+    // bins from its mean is available in constant time
+    let mut prefix = vec![(0.0, 0.0, 0.0); bins.len() + 1];
+    for (i, bin) in bins.iter().enumerate() {
+        let (n, s, q) = prefix[i];
+        prefix[i + 1] = (n + bin.count, s + bin.sum, q + bin.sum_sq);
+    }
+    let deviation = |first: usize, last: usize| {
+        let n = prefix[last + 1].0 - prefix[first].0;
+        let s = prefix[last + 1].1 - prefix[first].1;
+        let q = prefix[last + 1].2 - prefix[first].2;
+        (q - s * s / n).max(0.0)
+    };
+
+    // cost[c][j]: least total deviation of bins 0..=j in c + 1 classes;
+    // start[c][j]: first bin of the last of those classes
+    let m = bins.len();
+    let mut cost = vec![vec![f64::INFINITY; m]; classes];
+    let mut start = vec![vec![0; m]; classes];
+    for (j, cost) in cost[0].iter_mut().enumerate() {
+        *cost = deviation(0, j);
+    }
+    for c in 1..classes {
+        for j in c..m {
+            for i in c..=j {
+                let total = cost[c - 1][i - 1] + deviation(i, j);
+                if total < cost[c][j] {
+                    cost[c][j] = total;
+                    start[c][j] = i;
+                }
+            }
+        }
+    }
+
+    // Walk back through the first bins of the classes
+    let mut uppers = Vec::with_capacity(classes);
+    let mut last = m - 1;
+    for c in (0..classes).rev() {
+        uppers.push(bins[last].max);
+        if c > 0 {
+            last = start[c][last] - 1;
+        }
+    }
+    uppers.reverse();
+
+    let mut breaks = Vec::with_capacity(classes + 1);
+    breaks.push(bins[0].min);
+    breaks.extend(uppers);
+    breaks
+}
+
+/// Non-empty histogram bins of the values, in ascending order
+fn histogram(values: &[f64]) -> Option<Vec<Bin>> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if min > max {
+        return None;
+    }
+
+    let mut bins = vec![None::<Bin>; HISTOGRAM_BINS];
+    let width = (max - min) / HISTOGRAM_BINS as f64;
+    for &value in values {
+        let index = if width > 0.0 {
+            (((value - min) / width) as usize).min(HISTOGRAM_BINS - 1)
+        } else {
+            0
+        };
+        let bin = bins[index].get_or_insert(Bin {
+            count: 0.0,
+            sum: 0.0,
+            sum_sq: 0.0,
+            min: value,
+            max: value,
+        });
+        bin.count += 1.0;
+        bin.sum += value;
+        bin.sum_sq += value * value;
+        bin.min = bin.min.min(value);
+        bin.max = bin.max.max(value);
+    }
+    Some(bins.into_iter().flatten().collect())
+}
+
+/// Index of the class a value falls in
+///
+/// Values on a break belong to the lower class, as the break is the largest
+/// value of that class.
+pub fn class_of(value: f64, breaks: &[f64]) -> usize {
+    let classes = breaks.len().saturating_sub(1).max(1);
+    breaks
+        .iter()
+        .skip(1)
+        .position(|&upper| value <= upper)
+        .unwrap_or(classes - 1)
+}
+
+/// A colormap drawing each class in one color
+///
+/// Class `i` of `n` takes the color at `(i + 0.5) / n` of the underlying
+/// colormap, regardless of the value range passed to [`Colormap::map`].
+pub struct ClassifiedColormap {
+    inner: Box<dyn Colormap>,
+    breaks: Vec<f64>,
+}
+
+impl ClassifiedColormap {
+    pub fn new(inner: Box<dyn Colormap>, breaks: Vec<f64>) -> Self {
+        Self { inner, breaks }
+    }
+
+    /// Breaks as sent in the class breaks header
+    pub fn header_value(&self) -> String {
+        self.breaks
+            .iter()
+            .map(|b| b.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl Colormap for ClassifiedColormap {
+    fn map_normalized(&self, value: f32) -> [u8; 4] {
+        self.inner.map_normalized(value)
+    }
+
+    fn map(&self, value: f32, _min: f32, _max: f32) -> [u8; 4] {
+        let classes = self.breaks.len().saturating_sub(1).max(1);
+        let class = class_of(value as f64, &self.breaks);
+        self.inner
+            .map_normalized((class as f32 + 0.5) / classes as f32)
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colormaps::Viridis;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Classification::parse("jenks:7").unwrap(),
+            Classification {
+                method: ClassMethod::Jenks,
+                count: 7
+            }
+        );
+        assert_eq!(
+            Classification::parse("Jenks").unwrap().count,
+            DEFAULT_CLASSES
+        );
+        assert!(Classification::parse("jenks:1").is_err());
+        assert!(Classification::parse("jenks:many").is_err());
+        assert!(Classification::parse("quantile:5").is_err());
+    }
+
+    #[test]
+    fn test_jenks_breaks() {
+        let values = [1.0, 1.5, 2.0, 10.0, 11.0, 20.0, 21.0, 22.0];
+        assert_eq!(jenks_breaks(values, 3), vec![1.0, 2.0, 11.0, 22.0]);
+
+        // A skewed distribution: most values small, a long tail
+        let skewed: Vec<f32> = (0..1000)
+            .map(|i| {
+                if i < 900 {
+                    (i % 10) as f32
+                } else {
+                    100.0 + i as f32
+                }
+            })
+            .collect();
+        let breaks = jenks_breaks(skewed.iter().copied(), 4);
+        assert_eq!(breaks.len(), 5);
+        assert_eq!(breaks[0], 0.0);
+        assert_eq!(breaks[4], 1099.0);
+        assert!(breaks.windows(2).all(|w| w[0] < w[1]));
+        // The bulk of small values gets a class of its own
+        assert_eq!(breaks[1], 9.0);
+
+        // Fewer distinct values than classes
+        assert_eq!(jenks_breaks([5.0, 5.0, 7.0], 4), vec![5.0, 5.0, 7.0]);
+        assert_eq!(jenks_breaks([3.0, 3.0], 4), vec![3.0, 3.0]);
+        assert!(jenks_breaks([f32::NAN], 4).is_empty());
+    }
+
+    #[test]
+    fn test_classified_colormap() {
+        let breaks = vec![0.0, 1.0, 10.0, 100.0];
+        assert_eq!(class_of(0.5, &breaks), 0);
+        assert_eq!(class_of(1.0, &breaks), 0);
+        assert_eq!(class_of(5.0, &breaks), 1);
+        assert_eq!(class_of(500.0, &breaks), 2);
+
+        let colormap = ClassifiedColormap::new(Box::new(Viridis), breaks);
+        assert_eq!(colormap.header_value(), "0,1,10,100");
+        // One color per class, whatever the value range
+        assert_eq!(colormap.map(2.0, 0.0, 1.0), colormap.map(9.0, 5.0, 6.0));
+        assert_eq!(colormap.map(2.0, 0.0, 1.0), Viridis.map_normalized(0.5));
+        assert_ne!(colormap.map(0.5, 0.0, 1.0), colormap.map(50.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,90 @@
+//! Marking of values outside the color range.
+//!
+//! A colormap clamps values beyond the ends of the color range to its end
+//! colors, so a range that is too narrow silently saturates the extremes.
+//! With `mark_clipped=true` on `/image`, values below the range are drawn in
+//! one distinct color and values above it in another, and the number of grid
+//! cells on either side is returned in the `X-Rossby-Clipped-Below` and
+//! `X-Rossby-Clipped-Above` response headers.
+
+use crate::colormaps::Colormap;
+
+/// Response header counting the cells below the color range
+pub const CLIPPED_BELOW_HEADER: &str = "x-rossby-clipped-below";
+
+/// Response header counting the cells above the color range
+pub const CLIPPED_ABOVE_HEADER: &str = "x-rossby-clipped-above";
+
+/// A colormap drawing values outside the range in their own colors
+pub struct ClippedColormap {
+    inner: Box<dyn Colormap>,
+    below: [u8; 4],
+    above: [u8; 4],
+}
+
+impl ClippedColormap {
+    pub fn new(inner: Box<dyn Colormap>, below: [u8; 4], above: [u8; 4]) -> Self {
+        Self {
+            inner,
+            below,
+            above,
+        }
+    }
+}
+
+impl Colormap for ClippedColormap {
+    fn map_normalized(&self, value: f32) -> [u8; 4] {
+        self.inner.map_normalized(value)
+    }
+
+    fn map(&self, value: f32, min: f32, max: f32) -> [u8; 4] {
+        if value < min {
+            self.below
+        } else if value > max {
+            self.above
+        } else {
+            self.inner.map(value, min, max)
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+/// Number of values below and above a range, ignoring non-finite ones
+pub fn count_clipped(
+    values: impl IntoIterator<Item = f32>,
+    (min, max): (f32, f32),
+) -> (usize, usize) {
+    values
+        .into_iter()
+        .filter(|value| value.is_finite())
+        .fold((0, 0), |(below, above), value| {
+            (
+                below + usize::from(value < min),
+                above + usize::from(value > max),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colormaps::Viridis;
+
+    #[test]
+    fn test_clipped_colormap() {
+        let below = [0, 255, 255, 255];
+        let above = [255, 0, 255, 255];
+        let clipped = ClippedColormap::new(Box::new(Viridis), below, above);
+        assert_eq!(clipped.map(-1.0, 0.0, 10.0), below);
+        assert_eq!(clipped.map(11.0, 0.0, 10.0), above);
+        // The ends of the range are not clipped
+        assert_eq!(clipped.map(0.0, 0.0, 10.0), Viridis.map(0.0, 0.0, 10.0));
+        assert_eq!(clipped.map(10.0, 0.0, 10.0), Viridis.map(10.0, 0.0, 10.0));
+
+        let values = [-2.0, -1.0, 0.0, 5.0, 10.0, 12.0, f32::NAN];
+        assert_eq!(count_clipped(values, (0.0, 10.0)), (2, 1));
+    }
+}
@@ -4,15 +4,28 @@
 
 use crate::error::{Result, RossbyError};
 
+/// Response header holding the values mapped to the ends of the colormap of
+/// an image, as `min,max`, for clients drawing their own legends
+pub const COLOR_RANGE_HEADER: &str = "x-rossby-color-range";
+
+/// Response header set when an image is drawn in one flat color because its
+/// color range is a single value, holding that value
+pub const FLAT_FIELD_HEADER: &str = "x-rossby-flat-field";
+
 /// Trait for color mapping implementations
 pub trait Colormap: Send + Sync {
     /// Map a normalized value (0.0 to 1.0) to an RGBA color
     fn map_normalized(&self, value: f32) -> [u8; 4];
 
     /// Map a value to an RGBA color given the data range
+    ///
+    /// An empty range, e.g. that of a constant field, maps every value to
+    /// the middle color. The range is normalized in double precision, so
+    /// ranges spanning most of `f32` do not overflow.
     fn map(&self, value: f32, min: f32, max: f32) -> [u8; 4] {
-        let normalized = if max > min {
-            ((value - min) / (max - min)).clamp(0.0, 1.0)
+        let span = max as f64 - min as f64;
+        let normalized = if span > 0.0 {
+            ((value as f64 - min as f64) / span).clamp(0.0, 1.0) as f32
         } else {
             0.5
         };
@@ -23,23 +36,65 @@ pub trait Colormap: Send + Sync {
     fn name(&self) -> &str;
 }
 
+/// Names of the colormaps [`get_colormap`] knows, each also available
+/// reversed with a `_r` suffix
+pub const COLORMAP_NAMES: [&str; 10] = [
+    "viridis", "plasma", "inferno", "magma", "cividis", "blues", "coolwarm", "rdbu", "rdylbu",
+    "seismic",
+];
+
+/// A colormap run from its end to its start
+struct Reversed {
+    inner: Box<dyn Colormap>,
+    name: String,
+}
+
+impl Colormap for Reversed {
+    fn map_normalized(&self, value: f32) -> [u8; 4] {
+        self.inner.map_normalized(1.0 - value)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 /// Get a colormap by name
+///
+/// A `_r` suffix, as in `rdylbu_r`, reverses the colormap.
 pub fn get_colormap(name: &str) -> Result<Box<dyn Colormap>> {
     use super::{diverging::*, sequential::*};
 
-    match name.to_lowercase().as_str() {
-        "viridis" => Ok(Box::new(Viridis)),
-        "plasma" => Ok(Box::new(Plasma)),
-        "inferno" => Ok(Box::new(Inferno)),
-        "magma" => Ok(Box::new(Magma)),
-        "cividis" => Ok(Box::new(Cividis)),
-        "coolwarm" => Ok(Box::new(Coolwarm)),
-        "rdbu" => Ok(Box::new(RdBu)),
-        "seismic" => Ok(Box::new(Seismic)),
-        _ => Err(RossbyError::InvalidParameter {
-            param: "colormap".to_string(),
-            message: format!("Unknown colormap: {}", name),
-        }),
+    let lowercase = name.to_lowercase();
+    let (base, reversed) = match lowercase.strip_suffix("_r") {
+        Some(base) => (base, true),
+        None => (lowercase.as_str(), false),
+    };
+    let colormap: Box<dyn Colormap> = match base {
+        "viridis" => Box::new(Viridis),
+        "plasma" => Box::new(Plasma),
+        "inferno" => Box::new(Inferno),
+        "magma" => Box::new(Magma),
+        "cividis" => Box::new(Cividis),
+        "blues" => Box::new(Blues),
+        "coolwarm" => Box::new(Coolwarm),
+        "rdbu" => Box::new(RdBu),
+        "rdylbu" => Box::new(RdYlBu),
+        "seismic" => Box::new(Seismic),
+        _ => {
+            return Err(RossbyError::InvalidParameter {
+                param: "colormap".to_string(),
+                message: format!("Unknown colormap: {}", name),
+            })
+        }
+    };
+    if reversed {
+        Ok(Box::new(Reversed {
+            inner: colormap,
+            name: lowercase,
+        }))
+    } else {
+        Ok(colormap)
     }
 }
 
@@ -66,4 +121,36 @@ mod tests {
         assert_eq!(mid[1], 127);
         assert_eq!(mid[2], 127);
     }
+
+    #[test]
+    fn test_reversed_colormaps() {
+        use crate::colormaps::RdYlBu;
+
+        let reversed = get_colormap("RdYlBu_r").unwrap();
+        assert_eq!(reversed.name(), "rdylbu_r");
+        assert_eq!(reversed.map_normalized(0.0), RdYlBu.map_normalized(1.0));
+        assert_eq!(reversed.map_normalized(1.0), RdYlBu.map_normalized(0.0));
+
+        for name in COLORMAP_NAMES {
+            assert!(get_colormap(&format!("{}_r", name)).is_ok());
+        }
+        assert!(get_colormap("jet_r").is_err());
+        assert!(get_colormap("viridis_r_r").is_err());
+    }
+
+    #[test]
+    fn test_map_degenerate_ranges() {
+        use crate::colormaps::Viridis;
+
+        let middle = Viridis.map_normalized(0.5);
+        assert_eq!(Viridis.map(273.15, 273.15, 273.15), middle);
+        assert_eq!(Viridis.map(0.0, 0.0, 0.0), middle);
+
+        // The span of this range overflows f32
+        assert_eq!(Viridis.map(0.0, -f32::MAX, f32::MAX), middle);
+        assert_eq!(
+            Viridis.map(f32::MAX, -f32::MAX, f32::MAX),
+            Viridis.map_normalized(1.0)
+        );
+    }
 }
@@ -200,6 +200,57 @@ impl Colormap for Seismic {
     }
 }
 
+/// RdYlBu colormap - red through yellow to blue, see `rdylbu_r` for the
+/// usual temperature orientation
+pub struct RdYlBu;
+
+impl Colormap for RdYlBu {
+    fn map_normalized(&self, value: f32) -> [u8; 4] {
+        // Pre-defined RdYlBu colormap data (RGB triplets)
+        // These are sampled from the matplotlib RdYlBu colormap
+        let colors = [
+            [165, 0, 38], // Dark red
+            [215, 48, 39],
+            [244, 109, 67],
+            [253, 174, 97],
+            [254, 224, 144],
+            [255, 255, 191], // Pale yellow in the middle
+            [224, 243, 248],
+            [171, 217, 233],
+            [116, 173, 209],
+            [69, 117, 180],
+            [49, 54, 149], // Dark blue
+        ];
+
+        // Calculate the position in our color array
+        let position = value * (colors.len() - 1) as f32;
+        let index = position.floor() as usize;
+
+        // Get the two colors to interpolate between
+        if index >= colors.len() - 1 {
+            // Last color
+            return [
+                colors[colors.len() - 1][0],
+                colors[colors.len() - 1][1],
+                colors[colors.len() - 1][2],
+                255,
+            ];
+        }
+
+        let t = position - index as f32; // Fractional part for interpolation
+        let c1 = colors[index];
+        let c2 = colors[index + 1];
+
+        // Interpolate between the two colors
+        let rgb = super::colormap::lerp_color(c1, c2, t);
+        [rgb[0], rgb[1], rgb[2], 255]
+    }
+
+    fn name(&self) -> &str {
+        "rdylbu"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,6 +260,7 @@ mod tests {
         assert_eq!(Coolwarm.name(), "coolwarm");
         assert_eq!(RdBu.name(), "rdbu");
         assert_eq!(Seismic.name(), "seismic");
+        assert_eq!(RdYlBu.name(), "rdylbu");
     }
 
     #[test]
@@ -66,6 +66,135 @@ impl FromStr for MapProjection {
     }
 }
 
+/// Vertical scaling used when mapping image rows to latitudes
+///
+/// Plate carrée gives every degree of latitude the same height, which makes
+/// high-latitude features look much larger than they are. The other options
+/// shrink rows towards the poles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatitudeScaling {
+    /// Rows evenly spaced in latitude (plate carrée)
+    PlateCarree,
+    /// Compromise between plate carrée and equal-area, halving the polar stretch
+    PoleCorrected,
+    /// Rows evenly spaced in sin(latitude) (Lambert cylindrical equal-area)
+    EqualArea,
+    /// Rows spaced as in Web Mercator map tiles
+    Mercator,
+}
+
+/// Latitude in degrees at which Web Mercator maps end, making them square
+pub const MAX_MERCATOR_LAT: f64 = 85.051_128_779_806_59;
+
+/// Web Mercator vertical position of a latitude in degrees
+///
+/// Latitudes are clamped to [`MAX_MERCATOR_LAT`], so the poles map to the
+/// edges of a square map rather than to infinity.
+pub fn mercator_y(lat: f64) -> f64 {
+    let phi = lat.clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT).to_radians();
+    (std::f64::consts::FRAC_PI_4 + phi / 2.0).tan().ln()
+}
+
+impl LatitudeScaling {
+    /// Select the scaling from the `projection` and `enhance_poles` parameters
+    ///
+    /// An explicit `projection=equalarea` takes precedence over `enhance_poles`.
+    pub fn from_params(projection: Option<&str>, enhance_poles: bool) -> Result<Self> {
+        match projection.map(|p| p.to_lowercase()).as_deref() {
+            Some("equalarea") | Some("equal_area") => Ok(LatitudeScaling::EqualArea),
+            Some("mercator") => Ok(LatitudeScaling::Mercator),
+            Some("platecarree") | Some("equirectangular") | None => Ok(if enhance_poles {
+                LatitudeScaling::PoleCorrected
+            } else {
+                LatitudeScaling::PlateCarree
+            }),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "projection".to_string(),
+                message: format!(
+                    "Unknown projection: {}. Valid values are 'platecarree', 'equalarea', 'mercator', 'arctic' or 'antarctic'",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Projected vertical position of a latitude in degrees
+    fn forward(&self, lat: f64) -> f64 {
+        let phi = lat.clamp(-90.0, 90.0).to_radians();
+        match self {
+            LatitudeScaling::PlateCarree => phi,
+            LatitudeScaling::PoleCorrected => (phi + phi.sin()) / 2.0,
+            LatitudeScaling::EqualArea => phi.sin(),
+            LatitudeScaling::Mercator => mercator_y(lat),
+        }
+    }
+
+    /// Latitude in degrees of a projected vertical position
+    fn inverse(&self, y: f64) -> f64 {
+        let phi = match self {
+            LatitudeScaling::PlateCarree => y,
+            LatitudeScaling::EqualArea => y.clamp(-1.0, 1.0).asin(),
+            LatitudeScaling::Mercator => y.sinh().atan(),
+            LatitudeScaling::PoleCorrected => {
+                // Monotonic on [-pi/2, pi/2], so bisection always converges
+                let (mut low, mut high) =
+                    (-std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2);
+                for _ in 0..60 {
+                    let mid = (low + high) / 2.0;
+                    if (mid + mid.sin()) / 2.0 < y {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                (low + high) / 2.0
+            }
+        };
+        phi.to_degrees()
+    }
+
+    /// Fractional data row shown in an image row
+    ///
+    /// `lat_span` holds the latitudes of the first and last data rows, which
+    /// are assumed to be evenly spaced in latitude.
+    pub fn data_row(
+        &self,
+        image_row: u32,
+        image_height: u32,
+        lat_span: (f64, f64),
+        data_height: usize,
+    ) -> f64 {
+        let last_row = data_height.saturating_sub(1) as f64;
+        let t = image_row as f64 / image_height.saturating_sub(1).max(1) as f64;
+        let (first_lat, last_lat) = lat_span;
+
+        if *self == LatitudeScaling::PlateCarree || first_lat == last_lat {
+            return t * last_row;
+        }
+
+        let first_y = self.forward(first_lat);
+        let last_y = self.forward(last_lat);
+        let lat = self.inverse(first_y + t * (last_y - first_y));
+        ((lat - first_lat) / (last_lat - first_lat) * last_row).clamp(0.0, last_row)
+    }
+
+    /// Fractional image row showing a latitude, the inverse of `data_row`
+    ///
+    /// Returns `None` when the latitude lies outside `lat_span` or the span is
+    /// degenerate.
+    pub fn image_row(&self, lat: f64, image_height: u32, lat_span: (f64, f64)) -> Option<f64> {
+        let (first_lat, last_lat) = lat_span;
+        let (low, high) = (first_lat.min(last_lat), first_lat.max(last_lat));
+        if first_lat == last_lat || !(low..=high).contains(&lat) {
+            return None;
+        }
+
+        let first_y = self.forward(first_lat);
+        let t = (self.forward(lat) - first_y) / (self.forward(last_lat) - first_y);
+        Some(t * image_height.saturating_sub(1) as f64)
+    }
+}
+
 /// Parse a bounding box string "min_lon,min_lat,max_lon,max_lat" into its components
 pub fn parse_bbox(bbox: &str) -> Result<(f32, f32, f32, f32)> {
     let parts: Vec<&str> = bbox.split(',').collect();
@@ -186,104 +315,6 @@ pub fn handle_dateline_crossing_bbox(
     }
 }
 
-/// Adjust a data array for dateline crossing
-pub fn adjust_for_dateline_crossing(
-    data: &ArrayView2<f32>,
-    lon_coords: &[f64],
-    crosses_dateline: bool,
-) -> Result<(Array2<f32>, Vec<f64>)> {
-    if !crosses_dateline || lon_coords.is_empty() {
-        // No adjustment needed or empty coordinates
-        return Ok((data.to_owned(), lon_coords.to_vec()));
-    }
-
-    // Safety check for empty data array
-    if data.is_empty() {
-        return Ok((data.to_owned(), lon_coords.to_vec()));
-    }
-
-    // Find the dateline position in the longitude coordinates
-    let dateline_idx = if lon_coords[0] <= lon_coords[lon_coords.len() - 1] {
-        // Increasing longitude array
-        lon_coords
-            .iter()
-            .position(|&lon| (0.0..=180.0).contains(&lon))
-            .unwrap_or(0)
-    } else {
-        // Decreasing longitude array
-        lon_coords
-            .iter()
-            .position(|&lon| (-180.0..=0.0).contains(&lon))
-            .unwrap_or(0)
-    };
-
-    // Calculate right_size safely
-    let right_size = if dateline_idx >= lon_coords.len() {
-        0 // Edge case - no right side
-    } else {
-        lon_coords.len() - dateline_idx
-    };
-
-    // Ensure right_size is valid and not larger than the data width
-    let validated_right_size = right_size.min(data.shape()[1]);
-
-    // Create a new data array with the right side copied to the left
-    let mut new_data = Array2::zeros((data.shape()[0], data.shape()[1] + validated_right_size));
-    let mut new_lon_coords = Vec::with_capacity(lon_coords.len() + validated_right_size);
-
-    // Fill the new array with data from the original array
-    for row in 0..data.shape()[0] {
-        for col in 0..data.shape()[1] {
-            new_data[[row, col]] = data[[row, col]];
-        }
-    }
-
-    // Copy the right side to the left with longitude adjustment, with extra safety checks
-    if validated_right_size > 0 && data.shape()[1] >= validated_right_size {
-        for row in 0..data.shape()[0] {
-            for col in 0..validated_right_size {
-                // Calculate original column index safely
-                let safe_offset = col.min(validated_right_size - 1);
-                let orig_col = if data.shape()[1] > validated_right_size {
-                    data.shape()[1] - validated_right_size + safe_offset
-                } else {
-                    // If we can't safely calculate this, just use the last column
-                    data.shape()[1] - 1
-                };
-
-                // Extra bounds check
-                if orig_col < data.shape()[1] && data.shape()[1] + col < new_data.shape()[1] {
-                    new_data[[row, data.shape()[1] + col]] = data[[row, orig_col]];
-                }
-            }
-        }
-    }
-
-    // Create the new longitude coordinates
-    for &lon in lon_coords {
-        new_lon_coords.push(lon);
-    }
-
-    // Add the wrapped longitudes with safety checks
-    for i in 0..validated_right_size {
-        // Calculate index safely
-        let orig_idx = if lon_coords.len() > validated_right_size {
-            lon_coords.len() - validated_right_size + i
-        } else {
-            // Fall back to the last coordinate if we can't safely calculate
-            lon_coords.len() - 1
-        };
-
-        // Bounds check
-        if orig_idx < lon_coords.len() {
-            let wrapped_lon = lon_coords[orig_idx] + 360.0;
-            new_lon_coords.push(wrapped_lon);
-        }
-    }
-
-    Ok((new_data, new_lon_coords))
-}
-
 /// Resample a 2D data array to a new size using the specified interpolation method
 pub fn resample_data(
     data: &ArrayView2<f32>,
@@ -329,6 +360,81 @@ pub fn resample_data(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_latitude_scaling() {
+        assert_eq!(
+            LatitudeScaling::from_params(None, false).unwrap(),
+            LatitudeScaling::PlateCarree
+        );
+        assert_eq!(
+            LatitudeScaling::from_params(None, true).unwrap(),
+            LatitudeScaling::PoleCorrected
+        );
+        assert_eq!(
+            LatitudeScaling::from_params(Some("EqualArea"), true).unwrap(),
+            LatitudeScaling::EqualArea
+        );
+        assert_eq!(
+            LatitudeScaling::from_params(Some("mercator"), false).unwrap(),
+            LatitudeScaling::Mercator
+        );
+        assert!(LatitudeScaling::from_params(Some("gnomonic"), false).is_err());
+
+        // Plate carrée maps image rows linearly onto data rows
+        let span = (90.0, -90.0);
+        assert_eq!(
+            LatitudeScaling::PlateCarree.data_row(50, 101, span, 181),
+            90.0
+        );
+
+        for scaling in [LatitudeScaling::PoleCorrected, LatitudeScaling::EqualArea] {
+            // The ends and the equator stay fixed
+            assert!(scaling.data_row(0, 101, span, 181).abs() < 1e-6);
+            assert!((scaling.data_row(100, 101, span, 181) - 180.0).abs() < 1e-6);
+            assert!((scaling.data_row(50, 101, span, 181) - 90.0).abs() < 1e-6);
+
+            // High latitudes get fewer image rows: rows near the top cover more latitude
+            let near_pole = scaling.data_row(10, 101, span, 181);
+            assert!(near_pole > 18.0, "{:?} gave {}", scaling, near_pole);
+
+            // Rows stay monotonic
+            let rows: Vec<f64> = (0..101)
+                .map(|y| scaling.data_row(y, 101, span, 181))
+                .collect();
+            assert!(rows.windows(2).all(|w| w[1] >= w[0]));
+        }
+
+        // Equal-area row spacing follows sin(latitude): the top tenth spans ~36 degrees
+        let equal_area = LatitudeScaling::EqualArea.data_row(10, 101, span, 181);
+        assert!((equal_area - (90.0 - 0.8_f64.asin().to_degrees())).abs() < 1e-6);
+
+        // Mercator rows are evenly spaced in Web Mercator y, and the square
+        // map ends at the Mercator latitude limit
+        let mercator = LatitudeScaling::Mercator;
+        let span_80 = (80.0, -80.0);
+        assert!(mercator.data_row(0, 101, span_80, 161).abs() < 1e-6);
+        assert!((mercator.data_row(50, 101, span_80, 161) - 80.0).abs() < 1e-6);
+        // Unlike the other scalings, it stretches high latitudes
+        assert!(mercator.data_row(10, 101, span_80, 161) < 16.0);
+        assert!((mercator_y(MAX_MERCATOR_LAT) - std::f64::consts::PI).abs() < 1e-9);
+        assert_eq!(mercator_y(90.0), mercator_y(MAX_MERCATOR_LAT));
+
+        // image_row inverts data_row for evenly spaced latitudes
+        for scaling in [
+            LatitudeScaling::PlateCarree,
+            LatitudeScaling::PoleCorrected,
+            LatitudeScaling::EqualArea,
+        ] {
+            for y in [0, 10, 37, 100] {
+                let lat = 90.0 - scaling.data_row(y, 101, span, 181);
+                let row = scaling.image_row(lat, 101, span).unwrap();
+                assert!((row - y as f64).abs() < 1e-6, "{:?} row {}", scaling, y);
+            }
+            assert_eq!(scaling.image_row(95.0, 101, span), None);
+            assert_eq!(scaling.image_row(0.0, 101, (10.0, 10.0)), None);
+        }
+    }
+
     #[test]
     fn test_parse_bbox() {
         // Valid bbox
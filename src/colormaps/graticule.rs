@@ -0,0 +1,442 @@
+//! Graticule overlays for rendered images.
+//!
+//! Draws latitude/longitude grid lines every N degrees on top of a rendered
+//! field, with optional degree labels along the left and bottom image edges.
+//! Labels use a small built-in bitmap font so no font files are needed; the
+//! same font draws the titles of thumbnails.
+
+use image::{Rgba, RgbaImage};
+
+use super::geoutil::{normalize_longitude, LatitudeScaling};
+use crate::error::{Result, RossbyError};
+
+/// Upper bound for the line width in pixels
+pub const MAX_LINE_WIDTH: u32 = 10;
+
+/// Glyph size of the bitmap font in font pixels
+const GLYPH_WIDTH: u32 = 3;
+pub(crate) const GLYPH_HEIGHT: u32 = 5;
+
+/// Distance between labels and the image edge in image pixels
+const LABEL_MARGIN: u32 = 2;
+
+/// Styling and spacing of a graticule overlay
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graticule {
+    /// Distance between grid lines in degrees
+    pub spacing: f64,
+    /// Line and label color (RGBA)
+    pub color: [u8; 4],
+    /// Line width in pixels
+    pub line_width: u32,
+    /// Whether to label grid lines along the image edges
+    pub labels: bool,
+}
+
+impl Graticule {
+    /// Create a graticule, validating the spacing and line width
+    pub fn new(spacing: f64, color: [u8; 4], line_width: u32, labels: bool) -> Result<Self> {
+        if !spacing.is_finite() || spacing <= 0.0 {
+            return Err(RossbyError::InvalidParameter {
+                param: "grid_spacing".to_string(),
+                message: format!(
+                    "Grid spacing must be a positive number of degrees, got {}",
+                    spacing
+                ),
+            });
+        }
+        if line_width == 0 || line_width > MAX_LINE_WIDTH {
+            return Err(RossbyError::InvalidParameter {
+                param: "grid_width".to_string(),
+                message: format!(
+                    "Grid line width must be between 1 and {} pixels, got {}",
+                    MAX_LINE_WIDTH, line_width
+                ),
+            });
+        }
+
+        Ok(Self {
+            spacing,
+            color,
+            line_width,
+            labels,
+        })
+    }
+
+    /// Draw the graticule onto an image
+    ///
+    /// `lon_span` and `lat_span` hold the coordinates of the first and last
+    /// data column and row, which are drawn at the left/top and right/bottom
+    /// image edges. Latitude lines follow `scaling` so they line up with the
+    /// rendered rows. Longitudes may lie outside [-180, 180) after dateline
+    /// handling; labels are normalized.
+    pub fn draw(
+        &self,
+        img: &mut RgbaImage,
+        lon_span: (f64, f64),
+        lat_span: (f64, f64),
+        scaling: LatitudeScaling,
+    ) -> Result<()> {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let lons = self.line_values(lon_span, width)?;
+        let lats = self.line_values(lat_span, height)?;
+
+        // Pixel positions of each line, skipping degenerate spans
+        let columns: Vec<(f64, i64)> = lons
+            .into_iter()
+            .filter_map(|lon| {
+                let (first, last) = lon_span;
+                let t = (lon - first) / (last - first);
+                t.is_finite()
+                    .then(|| (lon, (t * (width - 1) as f64).round() as i64))
+            })
+            .collect();
+        let rows: Vec<(f64, i64)> = lats
+            .into_iter()
+            .filter_map(|lat| {
+                scaling
+                    .image_row(lat, height, lat_span)
+                    .map(|row| (lat, row.round() as i64))
+            })
+            .collect();
+
+        let half = (self.line_width / 2) as i64;
+        for &(_, x) in &columns {
+            for dx in 0..self.line_width as i64 {
+                for y in 0..height as i64 {
+                    blend_pixel(img, x - half + dx, y, self.color);
+                }
+            }
+        }
+        for &(_, y) in &rows {
+            for dy in 0..self.line_width as i64 {
+                for x in 0..width as i64 {
+                    blend_pixel(img, x, y - half + dy, self.color);
+                }
+            }
+        }
+
+        if self.labels {
+            let scale = (width.min(height) / 300).clamp(1, 3);
+            let label_color = [self.color[0], self.color[1], self.color[2], 255];
+            let glyph_height = (GLYPH_HEIGHT * scale) as i64;
+
+            // Longitude labels along the bottom edge, centered on their line
+            let mut next_free_x = i64::MIN;
+            for &(lon, x) in &columns {
+                let text = format_longitude(lon);
+                let text_width = text_width(&text, scale) as i64;
+                let left = (x - text_width / 2).clamp(0, (width as i64 - text_width).max(0));
+                if left < next_free_x {
+                    continue;
+                }
+                let top = height as i64 - glyph_height - LABEL_MARGIN as i64;
+                draw_text(img, &text, left, top, scale, label_color);
+                next_free_x = left + text_width + (GLYPH_WIDTH * scale) as i64;
+            }
+
+            // Latitude labels along the left edge, centered on their line
+            let mut occupied: Vec<(i64, i64)> = Vec::new();
+            for &(lat, y) in &rows {
+                let text = format_degrees(lat, 'N', 'S');
+                let top = (y - glyph_height / 2).clamp(0, (height as i64 - glyph_height).max(0));
+                let bottom = top + glyph_height + scale as i64;
+                if occupied.iter().any(|&(a, b)| top < b && a < bottom) {
+                    continue;
+                }
+                draw_text(img, &text, LABEL_MARGIN as i64, top, scale, label_color);
+                occupied.push((top, bottom));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Multiples of the spacing lying within a span
+    ///
+    /// Fails when the lines would be denser than one per pixel.
+    fn line_values(&self, span: (f64, f64), pixels: u32) -> Result<Vec<f64>> {
+        let low = span.0.min(span.1);
+        let high = span.0.max(span.1);
+        if !low.is_finite() || !high.is_finite() {
+            return Ok(Vec::new());
+        }
+
+        let first = (low / self.spacing).ceil() as i64;
+        let last = (high / self.spacing).floor() as i64;
+        if last < first {
+            return Ok(Vec::new());
+        }
+        if (last - first + 1) as u64 > pixels as u64 {
+            return Err(RossbyError::InvalidParameter {
+                param: "grid_spacing".to_string(),
+                message: format!(
+                    "Grid spacing of {} degrees draws more lines than the image has pixels",
+                    self.spacing
+                ),
+            });
+        }
+
+        Ok((first..=last).map(|k| k as f64 * self.spacing).collect())
+    }
+}
+
+/// Parse a color given as `RRGGBB` or `RRGGBBAA` hex, with an optional leading `#`
+pub fn parse_color(param: &str, value: &str) -> Result<[u8; 4]> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let invalid = || RossbyError::InvalidParameter {
+        param: param.to_string(),
+        message: format!(
+            "Invalid color: {}. Expected hex 'RRGGBB' or 'RRGGBBAA'",
+            value
+        ),
+    };
+
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let mut color = [0, 0, 0, 255];
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(color)
+}
+
+/// Format a coordinate as a label such as `30N`, `120W` or `0`
+fn format_degrees(value: f64, positive: char, negative: char) -> String {
+    // Round away floating-point noise from multiples of fractional spacings
+    let rounded = (value * 100.0).round() / 100.0;
+    let magnitude = rounded.abs();
+    let number = if magnitude.fract() == 0.0 {
+        format!("{}", magnitude as i64)
+    } else {
+        format!("{}", magnitude)
+    };
+
+    if rounded > 0.0 {
+        format!("{}{}", number, positive)
+    } else if rounded < 0.0 {
+        format!("{}{}", number, negative)
+    } else {
+        number
+    }
+}
+
+/// Format a longitude label, normalized to [-180, 180) with the antimeridian unsigned
+fn format_longitude(lon: f64) -> String {
+    let normalized = normalize_longitude(lon as f32) as f64;
+    if normalized == -180.0 {
+        "180".to_string()
+    } else {
+        format_degrees(normalized, 'E', 'W')
+    }
+}
+
+/// Width of a label in image pixels
+pub(crate) fn text_width(text: &str, scale: u32) -> u32 {
+    let glyphs = text.chars().count() as u32;
+    (glyphs * (GLYPH_WIDTH + 1)).saturating_sub(1) * scale
+}
+
+/// Draw a label with its top-left corner at (x, y)
+pub(crate) fn draw_text(
+    img: &mut RgbaImage,
+    text: &str,
+    x: i64,
+    y: i64,
+    scale: u32,
+    color: [u8; 4],
+) {
+    for (i, c) in text.chars().enumerate() {
+        let origin_x = x + (i as u32 * (GLYPH_WIDTH + 1) * scale) as i64;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        blend_pixel(
+                            img,
+                            origin_x + (col * scale + sx) as i64,
+                            y + (row as u32 * scale + sy) as i64,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 3x5 bitmap of a character, one byte per row with the leftmost pixel in bit 2
+///
+/// Letters are drawn in upper case; characters without a glyph are blank.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        'N' => [0b101, 0b111, 0b111, 0b111, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        _ => [0; GLYPH_HEIGHT as usize],
+    }
+}
+
+/// Composite a color over an image pixel, ignoring coordinates outside the image
+pub(crate) fn blend_pixel(img: &mut RgbaImage, x: i64, y: i64, color: [u8; 4]) {
+    if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+        return;
+    }
+
+    let Rgba(dst) = *img.get_pixel(x as u32, y as u32);
+    let src_alpha = color[3] as f32 / 255.0;
+    let dst_alpha = dst[3] as f32 / 255.0;
+    let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+
+    let mut out = [0u8; 4];
+    if out_alpha > 0.0 {
+        for i in 0..3 {
+            let value = (color[i] as f32 * src_alpha
+                + dst[i] as f32 * dst_alpha * (1.0 - src_alpha))
+                / out_alpha;
+            out[i] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    out[3] = (out_alpha * 255.0).round() as u8;
+
+    img.put_pixel(x as u32, y as u32, Rgba(out));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("c", "#ff8000").unwrap(), [255, 128, 0, 255]);
+        assert_eq!(parse_color("c", "00000080").unwrap(), [0, 0, 0, 128]);
+        assert!(parse_color("c", "#fff").is_err());
+        assert!(parse_color("c", "zzzzzz").is_err());
+    }
+
+    #[test]
+    fn test_format_degrees() {
+        assert_eq!(format_degrees(30.0, 'N', 'S'), "30N");
+        assert_eq!(format_degrees(-45.0, 'N', 'S'), "45S");
+        assert_eq!(format_longitude(0.0), "0");
+        assert_eq!(format_longitude(180.0), "180");
+        assert_eq!(format_longitude(240.0), "120W");
+        assert_eq!(format_longitude(2.5000000001), "2.5E");
+    }
+
+    #[test]
+    fn test_draw_lines() {
+        let mut img: RgbaImage = ImageBuffer::from_pixel(91, 46, Rgba([0, 0, 0, 0]));
+        let graticule = Graticule::new(30.0, [255, 0, 0, 255], 1, false).unwrap();
+        graticule
+            .draw(
+                &mut img,
+                (0.0, 90.0),
+                (0.0, 45.0),
+                LatitudeScaling::PlateCarree,
+            )
+            .unwrap();
+
+        // Longitude lines at 0, 30, 60 and 90 degrees fall on columns 0, 30, 60, 90
+        for x in [0, 30, 60, 90] {
+            assert_eq!(img.get_pixel(x, 10).0, [255, 0, 0, 255]);
+        }
+        assert_eq!(img.get_pixel(15, 10).0, [0, 0, 0, 0]);
+        // Latitude lines at 0 and 30 degrees fall on rows 0 and 30
+        assert_eq!(img.get_pixel(15, 0).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(15, 30).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(15, 45).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_draw_wrapped_longitudes_and_labels() {
+        let mut img: RgbaImage = ImageBuffer::from_pixel(200, 100, Rgba([0, 0, 0, 255]));
+        let graticule = Graticule::new(60.0, [255, 255, 255, 128], 1, true).unwrap();
+        graticule
+            .draw(
+                &mut img,
+                (150.0, 250.0),
+                (-40.0, 40.0),
+                LatitudeScaling::EqualArea,
+            )
+            .unwrap();
+
+        // 180 and 240 degrees lie inside the unwrapped span
+        let x_180 = ((180.0 - 150.0) / 100.0 * 199.0_f64).round() as u32;
+        assert_eq!(img.get_pixel(x_180, 20).0, [128, 128, 128, 255]);
+        // Labels are drawn opaque near the bottom-left corner
+        assert!(img.pixels().any(|pixel| pixel.0 == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_invalid_graticules() {
+        assert!(Graticule::new(0.0, [0; 4], 1, false).is_err());
+        assert!(Graticule::new(f64::NAN, [0; 4], 1, false).is_err());
+        assert!(Graticule::new(10.0, [0; 4], 0, false).is_err());
+
+        let mut img: RgbaImage = ImageBuffer::new(10, 10);
+        let dense = Graticule::new(0.1, [0; 4], 1, false).unwrap();
+        assert!(dense
+            .draw(
+                &mut img,
+                (0.0, 90.0),
+                (0.0, 1.0),
+                LatitudeScaling::PlateCarree
+            )
+            .is_err());
+    }
+}
@@ -3,19 +3,26 @@
 //! This module provides matplotlib-inspired colormaps for visualizing data
 //! and geographic utilities for visualization.
 
+pub mod classes;
+pub mod clipped;
 pub mod colormap;
 pub mod diverging;
 pub mod geoutil;
+pub mod graticule;
+pub mod polar;
+pub mod presets;
 pub mod sequential;
 
 pub use colormap::{get_colormap, Colormap};
 
 // Re-export commonly used colormaps
-pub use diverging::{Coolwarm, RdBu, Seismic};
-pub use sequential::{Cividis, Inferno, Magma, Plasma, Viridis};
+pub use diverging::{Coolwarm, RdBu, RdYlBu, Seismic};
+pub use sequential::{Blues, Cividis, Inferno, Magma, Plasma, Viridis};
+
+pub use graticule::Graticule;
 
 // Re-export geography utilities
 pub use geoutil::{
-    adjust_for_dateline_crossing, handle_dateline_crossing_bbox, normalize_longitude, parse_bbox,
-    resample_data, MapProjection,
+    handle_dateline_crossing_bbox, normalize_longitude, parse_bbox, resample_data, LatitudeScaling,
+    MapProjection, MAX_MERCATOR_LAT,
 };
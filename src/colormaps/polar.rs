@@ -0,0 +1,279 @@
+//! Polar stereographic rendering.
+//!
+//! Cylindrical maps stretch the polar regions into bands along the top and
+//! bottom edges, which makes sea ice impossible to read. `/image` with
+//! `projection=arctic` or `projection=antarctic` instead renders a disk
+//! centered on the pole in the polar stereographic projection of EPSG:3995
+//! (Arctic, 0° pointing down) or EPSG:3031 (Antarctic, 0° pointing up),
+//! reaching out to a latitude cutoff at the edge of the image.
+//!
+//! Every pixel is mapped back to a latitude and longitude and sampled
+//! bilinearly from the field. Where meridians converge towards the pole,
+//! neighbouring pixels sample across the seam of the longitude axis, so a
+//! global grid is closed by repeating its first column after its last, and
+//! the cap between the last grid row and the pole takes the values of that
+//! row. Pixels outside the cutoff circle are transparent.
+
+use image::{ImageBuffer, RgbaImage};
+use ndarray::{concatenate, s, Axis};
+
+use super::Colormap;
+use crate::error::{Result, RossbyError};
+use crate::field::HorizontalField;
+
+/// Latitude in degrees at the edge of polar views by default
+pub const DEFAULT_LAT_CUTOFF: f64 = 60.0;
+
+/// Projected CRS of Arctic views (WGS 84 / Arctic Polar Stereographic)
+pub const ARCTIC_POLAR_STEREOGRAPHIC: &str = "EPSG:3995";
+
+/// Projected CRS of Antarctic views (WGS 84 / Antarctic Polar Stereographic)
+pub const ANTARCTIC_POLAR_STEREOGRAPHIC: &str = "EPSG:3031";
+
+/// Pole a polar view is centered on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pole {
+    North,
+    South,
+}
+
+impl Pole {
+    /// Pole of a `projection` parameter, or None for cylindrical projections
+    pub fn from_projection(projection: Option<&str>) -> Option<Self> {
+        match projection.map(|p| p.to_lowercase()).as_deref() {
+            Some("arctic") => Some(Pole::North),
+            Some("antarctic") => Some(Pole::South),
+            _ => None,
+        }
+    }
+
+    /// Name of the projection as given in `projection`
+    pub fn projection(self) -> &'static str {
+        match self {
+            Pole::North => "arctic",
+            Pole::South => "antarctic",
+        }
+    }
+
+    /// Identifier of the projected CRS of the view
+    pub fn crs(self) -> &'static str {
+        match self {
+            Pole::North => ARCTIC_POLAR_STEREOGRAPHIC,
+            Pole::South => ANTARCTIC_POLAR_STEREOGRAPHIC,
+        }
+    }
+
+    fn sign(self) -> f64 {
+        match self {
+            Pole::North => 1.0,
+            Pole::South => -1.0,
+        }
+    }
+}
+
+/// A polar stereographic view out to a latitude cutoff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolarView {
+    pub pole: Pole,
+    /// Absolute latitude in degrees at the edge of the image
+    pub lat_cutoff: f64,
+}
+
+impl PolarView {
+    /// Create a view, taking the cutoff as an absolute latitude (60 and -60
+    /// both reach 60°S on an Antarctic view)
+    pub fn new(pole: Pole, lat_cutoff: Option<f64>) -> Result<Self> {
+        let lat_cutoff = lat_cutoff.unwrap_or(DEFAULT_LAT_CUTOFF).abs();
+        if !(lat_cutoff.is_finite() && lat_cutoff < 90.0) {
+            return Err(RossbyError::InvalidParameter {
+                param: "lat_cutoff".to_string(),
+                message: format!(
+                    "lat_cutoff must be a latitude between -90 and 90 (exclusive), got {}",
+                    lat_cutoff
+                ),
+            });
+        }
+        Ok(Self { pole, lat_cutoff })
+    }
+
+    /// Stereographic distance from the pole of a latitude, on a sphere of
+    /// radius 1/2
+    fn radius(lat: f64) -> f64 {
+        ((90.0 - lat.abs()) / 2.0).to_radians().tan()
+    }
+
+    /// Latitude and longitude shown at a pixel, or None outside the cutoff
+    pub fn pixel_location(&self, x: u32, y: u32, width: u32, height: u32) -> Option<(f64, f64)> {
+        // The cutoff circle touches the nearer edges of the image
+        let half = width.min(height) as f64 / 2.0;
+        let dx = (x as f64 + 0.5 - width as f64 / 2.0) / half;
+        let dy = (y as f64 + 0.5 - height as f64 / 2.0) / half;
+        let distance = dx.hypot(dy);
+        if distance > 1.0 {
+            return None;
+        }
+
+        let radius = distance * Self::radius(self.lat_cutoff);
+        let lat = 90.0 - 2.0 * radius.atan().to_degrees();
+        // Image rows run down, so 0° points down from the North Pole and up
+        // from the South Pole
+        let lon = match self.pole {
+            Pole::North => dx.atan2(dy),
+            Pole::South => dx.atan2(-dy),
+        }
+        .to_degrees();
+        Some((self.pole.sign() * lat, lon))
+    }
+
+    /// Render a field, with colors scaled to `value_range`
+    pub fn render(
+        &self,
+        field: &HorizontalField,
+        width: u32,
+        height: u32,
+        colormap: &dyn Colormap,
+        (min_val, max_val): (f32, f32),
+    ) -> RgbaImage {
+        let field = close_seam(field);
+        let cap = polar_row(&field.lat, self.pole);
+
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let value = self
+                .pixel_location(x, y, width, height)
+                .map_or(f32::NAN, |(lat, lon)| {
+                    let lat = match cap {
+                        Some(row) if lat.abs() > row.abs() => row,
+                        _ => lat,
+                    };
+                    field.sample(lat, lon)
+                });
+            image::Rgba(if value.is_finite() {
+                colormap.map(value, min_val, max_val)
+            } else {
+                [0, 0, 0, 0]
+            })
+        })
+    }
+}
+
+/// Repeat the first column of a global grid after its last, so longitudes
+/// between them can be sampled
+fn close_seam(field: &HorizontalField) -> HorizontalField {
+    let n = field.lon.len();
+    if n < 2 {
+        return field.clone();
+    }
+    let (first, last) = (field.lon[0], field.lon[n - 1]);
+    let spacing = (last - first).abs() / (n - 1) as f64;
+    if ((last - first).abs() + spacing - 360.0).abs() > spacing / 2.0 {
+        return field.clone();
+    }
+
+    let mut lon = field.lon.clone();
+    lon.push(first + (last - first).signum() * 360.0);
+    let values = concatenate(
+        Axis(1),
+        &[field.values.view(), field.values.slice(s![.., 0..1])],
+    )
+    .expect("columns of the same field have the same length");
+    HorizontalField {
+        lat: field.lat.clone(),
+        lon,
+        values,
+    }
+}
+
+/// Latitude of the grid row nearest a pole, when the pole lies within one
+/// row spacing of it, so the cap around the pole can take its values
+fn polar_row(lat: &[f64], pole: Pole) -> Option<f64> {
+    if lat.len() < 2 {
+        return None;
+    }
+    let spacing = (lat[lat.len() - 1] - lat[0]).abs() / (lat.len() - 1) as f64;
+    let row = match pole {
+        Pole::North => lat.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        Pole::South => lat.iter().copied().fold(f64::INFINITY, f64::min),
+    };
+    (90.0 - row.abs() <= spacing && row * pole.sign() > 0.0).then_some(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colormaps::Viridis;
+    use ndarray::Array2;
+
+    /// Global 2° grid of cell centers whose values are their latitudes
+    fn latitude_field() -> HorizontalField {
+        let lat: Vec<f64> = (0..90).map(|i| -89.0 + 2.0 * i as f64).collect();
+        let lon: Vec<f64> = (0..180).map(|j| 2.0 * j as f64).collect();
+        let values = Array2::from_shape_fn((lat.len(), lon.len()), |(i, _)| lat[i] as f32);
+        HorizontalField { lat, lon, values }
+    }
+
+    #[test]
+    fn test_parse_projection() {
+        assert_eq!(Pole::from_projection(Some("Arctic")), Some(Pole::North));
+        assert_eq!(Pole::from_projection(Some("antarctic")), Some(Pole::South));
+        assert_eq!(Pole::from_projection(Some("mercator")), None);
+        assert_eq!(Pole::from_projection(None), None);
+
+        assert_eq!(
+            PolarView::new(Pole::South, Some(-50.0)).unwrap().lat_cutoff,
+            50.0
+        );
+        assert!(PolarView::new(Pole::North, Some(90.0)).is_err());
+        assert!(PolarView::new(Pole::North, Some(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_pixel_location() {
+        let view = PolarView::new(Pole::North, Some(60.0)).unwrap();
+        // The pole is in the middle, the cutoff at the edges
+        let (lat, _) = view.pixel_location(50, 50, 101, 101).unwrap();
+        assert!(lat > 89.9);
+        let (lat, lon) = view.pixel_location(50, 100, 101, 101).unwrap();
+        assert!((lat - 60.0).abs() < 0.5, "{}", lat);
+        assert!(lon.abs() < 1e-9);
+        let (_, lon) = view.pixel_location(100, 50, 101, 101).unwrap();
+        assert!((lon - 90.0).abs() < 1e-9);
+        assert_eq!(view.pixel_location(0, 0, 101, 101), None);
+
+        // 0° points up from the South Pole
+        let view = PolarView::new(Pole::South, None).unwrap();
+        let (lat, lon) = view.pixel_location(50, 0, 101, 101).unwrap();
+        assert!((lat + 60.0).abs() < 0.5, "{}", lat);
+        assert!(lon.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_render_covers_the_disk() {
+        let field = latitude_field();
+        let view = PolarView::new(Pole::North, Some(60.0)).unwrap();
+        let img = view.render(&field, 64, 64, &Viridis, (60.0, 90.0));
+
+        // No gaps along the seam or at the pole inside the disk
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let inside = view.pixel_location(x, y, 64, 64).is_some();
+            assert_eq!(pixel[3] == 255, inside, "pixel {},{}", x, y);
+        }
+        // Colors grow towards the pole along every meridian
+        let center = img.get_pixel(32, 32);
+        let edge = img.get_pixel(32, 63);
+        assert_eq!(*center, image::Rgba(Viridis.map(89.0, 60.0, 90.0)));
+        assert_ne!(center, edge);
+    }
+
+    #[test]
+    fn test_close_seam() {
+        let field = latitude_field();
+        let closed = close_seam(&field);
+        assert_eq!(closed.lon.len(), 181);
+        assert_eq!(closed.lon[180], 360.0);
+        assert!(!closed.sample(0.0, 359.0).is_nan());
+
+        // Regional grids are left open
+        let regional = field.crop((0.0, -10.0, 100.0, 10.0));
+        assert_eq!(close_seam(&regional).lon.len(), regional.lon.len());
+    }
+}
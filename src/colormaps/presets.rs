@@ -0,0 +1,206 @@
+//! Default styles of variables by CF standard name.
+//!
+//! Images of a variable drawn without any style parameters take the colormap
+//! and color range of its `standard_name` from here, so that temperatures
+//! are drawn red to blue over a fixed range of temperatures and
+//! precipitation in blues from zero without configuration. Ranges are given
+//! in the units of the preset and converted to those of the variable; a
+//! range that cannot be converted is left out and the colormap kept.
+//! `data.style_presets` overrides or disables the built-in presets.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Response header naming the standard name whose preset styled an image
+pub const STYLE_PRESET_HEADER: &str = "x-rossby-style-preset";
+
+/// Colormap and color range of a standard name
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StylePreset {
+    /// Name of the colormap, e.g. "rdylbu_r"
+    pub colormap: String,
+    /// Value mapped to the start of the colormap (None = slice minimum)
+    #[serde(default)]
+    pub vmin: Option<f64>,
+    /// Value mapped to the end of the colormap (None = slice maximum)
+    #[serde(default)]
+    pub vmax: Option<f64>,
+    /// Units of vmin and vmax (None = those of the variable)
+    #[serde(default)]
+    pub units: Option<String>,
+}
+
+/// The preset of a variable, with its range in the units of the variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPreset {
+    pub standard_name: String,
+    pub colormap: String,
+    pub vmin: Option<f64>,
+    pub vmax: Option<f64>,
+}
+
+/// Built-in preset of a standard name
+fn builtin_preset(standard_name: &str) -> Option<StylePreset> {
+    let preset = |colormap: &str, vmin: Option<f64>, vmax: Option<f64>, units: Option<&str>| {
+        Some(StylePreset {
+            colormap: colormap.to_string(),
+            vmin,
+            vmax,
+            units: units.map(str::to_string),
+        })
+    };
+    match standard_name {
+        "air_temperature" => preset("rdylbu_r", Some(220.0), Some(320.0), Some("K")),
+        "surface_temperature" => preset("rdylbu_r", Some(220.0), Some(330.0), Some("K")),
+        "sea_surface_temperature" => preset("rdylbu_r", Some(271.0), Some(305.0), Some("K")),
+        "precipitation_rate" | "precipitation_flux" | "precipitation_amount" => {
+            preset("blues", Some(0.0), None, None)
+        }
+        "relative_humidity" => preset("blues", Some(0.0), Some(100.0), Some("%")),
+        "eastward_wind" | "northward_wind" => {
+            preset("coolwarm", Some(-30.0), Some(30.0), Some("m s-1"))
+        }
+        _ => None,
+    }
+}
+
+/// Preset of a variable with the given standard name and units
+///
+/// Configured presets take precedence over the built-in ones, and a
+/// configured `None` disables the built-in preset of its standard name.
+pub fn resolve_preset(
+    configured: &HashMap<String, Option<StylePreset>>,
+    standard_name: &str,
+    units: Option<&str>,
+) -> Option<ResolvedPreset> {
+    let preset = match configured.get(standard_name) {
+        Some(preset) => preset.clone()?,
+        None => builtin_preset(standard_name)?,
+    };
+    let convert = |value: Option<f64>| -> Option<Option<f64>> {
+        match (value, preset.units.as_deref()) {
+            (None, _) => Some(None),
+            (Some(value), None) => Some(Some(value)),
+            (Some(value), Some(from)) => convert_units(value, from, units?).map(Some),
+        }
+    };
+    // Both ends are converted or neither, so a range is never half in the
+    // units of the preset
+    let (vmin, vmax) = match (convert(preset.vmin), convert(preset.vmax)) {
+        (Some(vmin), Some(vmax)) => (vmin, vmax),
+        _ => (None, None),
+    };
+    Some(ResolvedPreset {
+        standard_name: standard_name.to_string(),
+        colormap: preset.colormap,
+        vmin,
+        vmax,
+    })
+}
+
+/// Units as (quantity, scale, offset), so that `value * scale + offset` is
+/// the value in the base units of the quantity
+fn unit_definition(units: &str) -> Option<(&'static str, f64, f64)> {
+    let definition = match units.trim() {
+        "K" | "kelvin" | "degK" | "degrees_K" => ("temperature", 1.0, 0.0),
+        "degC" | "degrees_C" | "celsius" | "degree_Celsius" | "degrees_Celsius" | "°C" => {
+            ("temperature", 1.0, 273.15)
+        }
+        "degF" | "degrees_F" | "degree_Fahrenheit" | "degrees_Fahrenheit" | "°F" => {
+            ("temperature", 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0)
+        }
+        "Pa" => ("pressure", 1.0, 0.0),
+        "hPa" | "mbar" | "mb" => ("pressure", 100.0, 0.0),
+        "kPa" => ("pressure", 1000.0, 0.0),
+        "1" | "fraction" => ("ratio", 1.0, 0.0),
+        "%" | "percent" => ("ratio", 0.01, 0.0),
+        "m s-1" | "m/s" | "m s**-1" => ("speed", 1.0, 0.0),
+        "km h-1" | "km/h" => ("speed", 1.0 / 3.6, 0.0),
+        "knot" | "knots" | "kt" => ("speed", 1852.0 / 3600.0, 0.0),
+        "kg m-2 s-1" | "mm s-1" | "mm/s" => ("precipitation_rate", 1.0, 0.0),
+        "mm h-1" | "mm/h" | "mm hr-1" => ("precipitation_rate", 1.0 / 3600.0, 0.0),
+        "mm day-1" | "mm d-1" | "mm/day" => ("precipitation_rate", 1.0 / 86400.0, 0.0),
+        _ => return None,
+    };
+    Some(definition)
+}
+
+/// Convert a value between units of the same quantity (None = units that
+/// are unknown or of different quantities)
+pub fn convert_units(value: f64, from: &str, to: &str) -> Option<f64> {
+    if from.trim() == to.trim() {
+        return Some(value);
+    }
+    let (from_quantity, from_scale, from_offset) = unit_definition(from)?;
+    let (to_quantity, to_scale, to_offset) = unit_definition(to)?;
+    if from_quantity != to_quantity {
+        return None;
+    }
+    Some((value * from_scale + from_offset - to_offset) / to_scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_units() {
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-9;
+        assert!(close(convert_units(273.15, "K", "degC"), 0.0));
+        assert!(close(convert_units(212.0, "degF", "K"), 373.15));
+        assert!(close(convert_units(1013.25, "hPa", "Pa"), 101325.0));
+        assert!(close(convert_units(0.5, "1", "%"), 50.0));
+        assert!(close(convert_units(1.0, "kg m-2 s-1", "mm/day"), 86400.0));
+        assert_eq!(convert_units(3.0, "furlong", "furlong"), Some(3.0));
+        assert_eq!(convert_units(1.0, "K", "hPa"), None);
+        assert_eq!(convert_units(1.0, "K", "arbitrary"), None);
+    }
+
+    #[test]
+    fn test_resolve_preset() {
+        let none = HashMap::new();
+        let resolved = resolve_preset(&none, "air_temperature", Some("degC")).unwrap();
+        assert_eq!(resolved.colormap, "rdylbu_r");
+        assert!((resolved.vmin.unwrap() + 53.15).abs() < 1e-9);
+        assert!((resolved.vmax.unwrap() - 46.85).abs() < 1e-9);
+
+        // Ranges in unknown units are dropped, the colormap is kept
+        let resolved = resolve_preset(&none, "air_temperature", None).unwrap();
+        assert_eq!(
+            (resolved.colormap.as_str(), resolved.vmin),
+            ("rdylbu_r", None)
+        );
+
+        // Ranges without units apply in any units
+        let resolved = resolve_preset(&none, "precipitation_rate", Some("mm/day")).unwrap();
+        assert_eq!((resolved.vmin, resolved.vmax), (Some(0.0), None));
+
+        assert_eq!(
+            resolve_preset(&none, "sea_water_salinity", Some("1e-3")),
+            None
+        );
+
+        let configured = HashMap::from([
+            ("air_temperature".to_string(), None),
+            (
+                "sea_water_salinity".to_string(),
+                Some(StylePreset {
+                    colormap: "viridis".to_string(),
+                    vmin: Some(30.0),
+                    vmax: Some(38.0),
+                    units: None,
+                }),
+            ),
+        ]);
+        assert_eq!(
+            resolve_preset(&configured, "air_temperature", Some("K")),
+            None
+        );
+        let resolved = resolve_preset(&configured, "sea_water_salinity", Some("1e-3")).unwrap();
+        assert_eq!(
+            (resolved.colormap.as_str(), resolved.vmin, resolved.vmax),
+            ("viridis", Some(30.0), Some(38.0))
+        );
+    }
+}
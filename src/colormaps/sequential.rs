@@ -251,6 +251,54 @@ impl Colormap for Cividis {
     }
 }
 
+/// Blues colormap - white to dark blue, for amounts starting at zero
+pub struct Blues;
+
+impl Colormap for Blues {
+    fn map_normalized(&self, value: f32) -> [u8; 4] {
+        // Pre-defined Blues colormap data (RGB triplets)
+        // These are sampled from the matplotlib Blues colormap
+        let colors = [
+            [247, 251, 255], // Near white
+            [222, 235, 247],
+            [198, 219, 239],
+            [158, 202, 225],
+            [107, 174, 214],
+            [66, 146, 198],
+            [33, 113, 181],
+            [8, 81, 156],
+            [8, 48, 107], // Dark blue
+        ];
+
+        // Calculate the position in our color array
+        let position = value * (colors.len() - 1) as f32;
+        let index = position.floor() as usize;
+
+        // Get the two colors to interpolate between
+        if index >= colors.len() - 1 {
+            // Last color
+            return [
+                colors[colors.len() - 1][0],
+                colors[colors.len() - 1][1],
+                colors[colors.len() - 1][2],
+                255,
+            ];
+        }
+
+        let t = position - index as f32; // Fractional part for interpolation
+        let c1 = colors[index];
+        let c2 = colors[index + 1];
+
+        // Interpolate between the two colors
+        let rgb = super::colormap::lerp_color(c1, c2, t);
+        [rgb[0], rgb[1], rgb[2], 255]
+    }
+
+    fn name(&self) -> &str {
+        "blues"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,6 +310,7 @@ mod tests {
         assert_eq!(Inferno.name(), "inferno");
         assert_eq!(Magma.name(), "magma");
         assert_eq!(Cividis.name(), "cividis");
+        assert_eq!(Blues.name(), "blues");
     }
 
     #[test]
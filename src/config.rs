@@ -8,10 +8,26 @@
 
 use clap::Parser;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
+use crate::bounds::BoundsMode;
+use crate::colormaps::graticule::{parse_color, Graticule};
+use crate::colormaps::parse_bbox;
+use crate::colormaps::presets::{resolve_preset, ResolvedPreset, StylePreset};
+use crate::coord_index::Tolerance;
+use crate::crs::Crs;
+use crate::endpoint_switches::DisabledEndpoint;
 use crate::error::{Result, RossbyError};
+use crate::nan_policy::NanPolicy;
+use crate::products::{template_value, PRODUCT_PARAM};
+use crate::query::SelectorInput;
+use crate::quota::ClientId;
+use crate::time_axis::DuplicateTimes;
+use crate::views::{TimeAggregation, TimeWeights};
+
+/// Colormap of rendered images when neither the request nor `data.colormap` sets one
+pub const DEFAULT_COLORMAP: &str = "viridis";
 
 /// Command-line arguments for rossby
 #[derive(Parser, Debug)]
@@ -44,6 +60,14 @@ pub struct Args {
     /// Service discovery URL for registering this server
     #[arg(long, env = "ROSSBY_DISCOVERY_URL")]
     pub discovery_url: Option<String>,
+
+    /// Profile request stages (extraction, interpolation, rendering, serialization)
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Directory to write a folded-stack flamegraph file per profiled request
+    #[arg(long, env = "ROSSBY_PROFILE_DIR")]
+    pub profile_dir: Option<PathBuf>,
 }
 
 /// Server configuration
@@ -68,6 +92,183 @@ pub struct ServerConfig {
     /// Maximum number of data points allowed in a single data request
     #[serde(default = "default_max_data_points")]
     pub max_data_points: usize,
+
+    /// Highest `max_points` a request may raise the point limit to (None =
+    /// requests cannot raise it)
+    #[serde(default)]
+    pub max_points_ceiling: Option<usize>,
+
+    /// Scopes granted to API keys, by key
+    /// For example: {"team-a": ["max_points"]}
+    #[serde(default)]
+    pub api_key_scopes: HashMap<String, Vec<String>>,
+
+    /// Peer rossby instances for federated comparisons, mapping peer name to base URL
+    /// For example: {"era5": "http://era5-server:8000"}
+    #[serde(default)]
+    pub peers: HashMap<String, String>,
+
+    /// Bandwidth quotas per API key or client IP
+    #[serde(default)]
+    pub quotas: QuotaConfig,
+
+    /// Per-request stage profiling
+    #[serde(default)]
+    pub profile: ProfileConfig,
+
+    /// Request body size limits
+    #[serde(default)]
+    pub body_limits: BodyLimitConfig,
+
+    /// Token required in the `X-Admin-Token` header of `/admin` requests
+    /// (None = admin endpoints disabled)
+    #[serde(default)]
+    pub admin_token: Option<String>,
+
+    /// JSON Lines file every request is appended to, for `rossby replay`
+    /// (None = no access log)
+    #[serde(default)]
+    pub access_log: Option<PathBuf>,
+
+    /// File holding the hex or base64 encoded Ed25519 seed signing responses
+    /// (None = responses are not signed)
+    #[serde(default)]
+    pub signing_key_file: Option<PathBuf>,
+
+    /// Endpoints not served, e.g. ["/admin", "/data?format=json"]
+    #[serde(default)]
+    pub disabled_endpoints: Vec<String>,
+
+    /// Requests sent right after loading to prime caches
+    #[serde(default)]
+    pub warmup: WarmupConfig,
+
+    /// Share one computation between identical concurrent `/image`, `/stats`
+    /// and `/data` requests
+    #[serde(default = "default_coalesce_requests")]
+    pub coalesce_requests: bool,
+}
+
+/// API key scope allowing requests to raise the point limit with `max_points`
+pub const MAX_POINTS_SCOPE: &str = "max_points";
+
+/// Scopes that can be granted in `server.api_key_scopes`
+const API_KEY_SCOPES: [&str; 1] = [MAX_POINTS_SCOPE];
+
+impl ServerConfig {
+    /// Whether an API key was granted a scope in `api_key_scopes`
+    pub fn key_has_scope(&self, key: Option<&str>, scope: &str) -> bool {
+        key.and_then(|key| self.api_key_scopes.get(key))
+            .is_some_and(|scopes| scopes.iter().any(|s| s == scope))
+    }
+}
+
+/// Warm-up configuration
+///
+/// The requests are sent through the router in the background once the data
+/// is loaded, so expensive first renders are paid before users arrive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmupConfig {
+    /// Paths with query strings to request, e.g. `/image?var=t2m&time=latest`
+    #[serde(default)]
+    pub requests: Vec<String>,
+
+    /// Number of warm-up requests served at once
+    #[serde(default = "default_warmup_concurrency")]
+    pub concurrency: usize,
+}
+
+/// Request body size limits
+///
+/// Bodies over the limits are rejected with `413 Payload Too Large`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyLimitConfig {
+    /// Maximum size of a request body as received, in bytes
+    #[serde(default = "default_max_body_bytes")]
+    pub max_bytes: usize,
+
+    /// Whether gzip and deflate request bodies are decompressed
+    #[serde(default)]
+    pub decompress: bool,
+
+    /// Maximum size of a decompressed request body, in bytes
+    #[serde(default = "default_max_decompressed_bytes")]
+    pub max_decompressed_bytes: usize,
+}
+
+impl BodyLimitConfig {
+    /// Largest body a handler can receive
+    pub fn handler_limit(&self) -> usize {
+        if self.decompress {
+            self.max_decompressed_bytes
+        } else {
+            self.max_bytes
+        }
+    }
+}
+
+/// Per-request profiling configuration
+///
+/// Profiled requests record how long each stage took; the breakdown is
+/// logged and optionally written as folded stacks for flamegraph tools.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    /// Whether requests are profiled
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Fraction of requests to profile, between 0 (exclusive) and 1
+    #[serde(default = "default_profile_sample_rate")]
+    pub sample_rate: f64,
+
+    /// Directory receiving one `<profile id>.folded` file per profiled request
+    /// (None = log the breakdown only)
+    #[serde(default)]
+    pub output_dir: Option<PathBuf>,
+}
+
+/// Bandwidth quota configuration
+///
+/// Usage is always tracked so clients can inspect it via `/usage`; limits are
+/// only enforced when set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Bytes each client may transfer per window (None = unlimited)
+    #[serde(default)]
+    pub daily_bytes: Option<u64>,
+
+    /// Per-API-key byte allowances overriding `daily_bytes`
+    /// For example: {"team-a": 10737418240}
+    #[serde(default)]
+    pub keys: HashMap<String, u64>,
+
+    /// Length of the rolling accounting window in seconds
+    #[serde(default = "default_quota_window_secs")]
+    pub window_secs: u64,
+
+    /// Bytes charged per estimated cell of work, by endpoint path
+    /// For example: {"/image": 0.5, "/data": 4}
+    #[serde(default)]
+    pub weights: HashMap<String, f64>,
+}
+
+impl QuotaConfig {
+    /// Bytes charged for the estimated work of a request, 0 for endpoints
+    /// without a weight
+    pub fn work_cost(&self, path: &str, cells: u64) -> u64 {
+        match self.weights.get(path) {
+            Some(&weight) if weight > 0.0 => (weight * cells as f64).ceil() as u64,
+            _ => 0,
+        }
+    }
+
+    /// Byte allowance for a client, if limited
+    pub fn limit_for(&self, client: &ClientId) -> Option<u64> {
+        match client {
+            ClientId::ApiKey(key) => self.keys.get(key).copied().or(self.daily_bytes),
+            _ => self.daily_bytes,
+        }
+    }
 }
 
 /// Data processing configuration
@@ -77,6 +278,29 @@ pub struct DataConfig {
     #[serde(default = "default_interpolation")]
     pub interpolation_method: String,
 
+    /// Default handling of coordinates outside the grid (error, clamp or wrap)
+    #[serde(default = "default_bounds")]
+    pub bounds: String,
+
+    /// Default encoding of NaN and infinity in JSON responses (null, string or omit)
+    #[serde(default = "default_json_nan")]
+    pub json_nan: String,
+
+    /// Which of several time steps with the same time stamp to keep (first, last or error)
+    #[serde(default = "default_duplicate_times")]
+    pub duplicate_times: String,
+
+    /// Default colormap of rendered images (None = viridis)
+    #[serde(default)]
+    pub colormap: Option<String>,
+
+    /// Colormaps and ranges of images of variables drawn without style
+    /// parameters, keyed by standard name; they replace the built-in presets
+    /// and `null` disables one
+    /// For example: {"air_temperature": {"colormap": "rdylbu_r", "vmin": -40, "vmax": 45, "units": "degC"}}
+    #[serde(default)]
+    pub style_presets: HashMap<String, Option<StylePreset>>,
+
     /// Path to the NetCDF file
     #[serde(default)]
     pub file_path: Option<PathBuf>,
@@ -85,6 +309,202 @@ pub struct DataConfig {
     /// For example: {"latitude": "lat", "longitude": "lon", "time": "t"}
     #[serde(default)]
     pub dimension_aliases: HashMap<String, String>,
+
+    /// Dimensions renamed when the file is loaded, from the name in the file
+    /// to the served name
+    /// For example: {"x": "lon", "y": "lat"}
+    #[serde(default)]
+    pub rename_dimensions: HashMap<String, String>,
+
+    /// Fixes of coordinate values applied when the file is loaded, keyed by
+    /// served dimension name
+    /// For example: {"lon": {"scale": 0.01}, "level": {"values": [1000, 850, 500]}}
+    #[serde(default)]
+    pub coordinate_fixes: HashMap<String, CoordinateFix>,
+
+    /// Coordinate reference system of the data, as an identifier like
+    /// "EPSG:4326" or a WKT string (None = from the file's grid mapping)
+    #[serde(default)]
+    pub crs: Option<String>,
+
+    /// Display overlays for variable metadata, keyed by language code and then variable name
+    /// For example: {"de": {"t2m": {"long_name": "2 m Temperatur", "units": "K"}}}
+    #[serde(default)]
+    pub translations: HashMap<String, HashMap<String, VariableTranslation>>,
+
+    /// Default styling for graticules drawn on images with `grid=true`
+    #[serde(default)]
+    pub grid: GridConfig,
+
+    /// Colors of out-of-range values on images with `mark_clipped=true`
+    #[serde(default)]
+    pub clip_colors: ClipColors,
+
+    /// Named query templates, expanded by requests with `product=<name>`
+    /// For example: {"europe_t2m_map": {"var": "t2m", "bbox": "-25,34,45,72", "width": 1024}}
+    #[serde(default)]
+    pub products: HashMap<String, ProductTemplate>,
+    /// Named views, served as virtual variables by `/image` and `/data`
+    /// For example: {"t2m_europe_daily": {"var": "t2m", "bbox": "-25,34,45,72", "time_agg": "daily_mean"}}
+    #[serde(default)]
+    pub views: HashMap<String, ViewConfig>,
+    /// Code tables of categorical variables, keyed by variable name and then value
+    /// For example: {"land_cover": {"1": "Forest", "2": "Water"}}
+    #[serde(default)]
+    pub code_tables: HashMap<String, HashMap<String, String>>,
+    /// Variables holding the uncertainty (e.g. standard deviation) of others,
+    /// keyed by the variable they describe
+    /// For example: {"t2m": "t2m_stddev"}
+    #[serde(default)]
+    pub uncertainty: HashMap<String, String>,
+
+    /// Poll the data file every this many seconds for time steps appended
+    /// to it, and serve them without a restart (None = no polling)
+    #[serde(default)]
+    pub append_interval_secs: Option<u64>,
+
+    /// Refuse to load data taking more than this many bytes in memory,
+    /// estimated from the variable shapes before reading (None = no limit)
+    #[serde(default)]
+    pub max_memory_bytes: Option<u64>,
+
+    /// What to do when the data exceeds max_memory_bytes: "error" refuses
+    /// to load, "exclude" leaves out variables until the rest fits
+    #[serde(default = "default_on_memory_exceeded")]
+    pub on_memory_exceeded: String,
+
+    /// Variables in order of importance; with "exclude", unlisted variables
+    /// are left out first, then the listed ones from the end
+    #[serde(default)]
+    pub memory_priority: Vec<String>,
+
+    /// Keep variables packed with scale_factor/add_offset as their 16-bit
+    /// integers in memory instead of f32, converting them when read
+    #[serde(default)]
+    pub keep_packed: bool,
+
+    /// Where variable values are held: "memory" reads them all at startup,
+    /// "lazy" reads them from the file when requests need them
+    #[serde(default = "default_storage")]
+    pub storage: String,
+
+    /// Bytes of recently read slabs kept with "lazy" storage
+    #[serde(default = "default_lazy_cache_bytes")]
+    pub lazy_cache_bytes: u64,
+
+    /// File with the climatological mean (same variable names) and standard
+    /// deviation (`<name>_std`) of variables, by calendar month or for the
+    /// whole record, for `mode=percent_normal|zscore` (None = computed from
+    /// the data)
+    #[serde(default)]
+    pub climatology_file: Option<PathBuf>,
+
+    /// How far a requested time may be from the time step it selects in
+    /// `/point` and `/image`, as `absolute` and `relative` differences
+    #[serde(default)]
+    pub time_tolerance: Tolerance,
+
+    /// Verify the in-memory data against load-time checksums and the data
+    /// file every this many seconds, reported by `/healthz` (None = no
+    /// self-checks)
+    #[serde(default)]
+    pub self_check_interval_secs: Option<u64>,
+
+    /// Number of random cells compared with the data file per self-check
+    #[serde(default = "default_self_check_samples")]
+    pub self_check_samples: usize,
+}
+
+/// Query parameters of a named product, mapping parameter name to a string,
+/// number or boolean value
+pub type ProductTemplate = HashMap<String, serde_json::Value>;
+
+/// A named view: a preset selection of a variable, served as a variable
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ViewConfig {
+    /// Variable the view selects from
+    pub var: String,
+    /// Region as "min_lon,min_lat,max_lon,max_lat" (None = whole grid)
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Aggregation of the time steps, as "<period>_<statistic>" with period
+    /// daily or monthly and statistic mean, min, max or sum (None = none)
+    #[serde(default)]
+    pub time_agg: Option<String>,
+    /// Weighting of the time steps of means, "interval" to weight them by the
+    /// length of their interval (from time bounds or the spacing of the
+    /// steps) or "uniform" (None = interval)
+    #[serde(default)]
+    pub time_weights: Option<String>,
+    /// Dimension selectors applied before aggregating, as query parameters
+    /// For example: {"level": 850} or {"time_range": [0, 744]}
+    #[serde(default)]
+    pub select: ProductTemplate,
+}
+
+/// A fix of the values of a coordinate, applied at load
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CoordinateFix {
+    /// Factor to multiply the values by (None = 1)
+    #[serde(default)]
+    pub scale: Option<f64>,
+    /// Offset to add to the values after scaling (None = 0)
+    #[serde(default)]
+    pub offset: Option<f64>,
+    /// Values replacing those of the file, one per step of the dimension
+    /// (None = the file's values, scaled and offset)
+    #[serde(default)]
+    pub values: Option<Vec<f64>>,
+}
+
+/// Default graticule styling, overridable per request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridConfig {
+    /// Distance between grid lines in degrees
+    #[serde(default = "default_grid_spacing")]
+    pub spacing: f64,
+
+    /// Line and label color as hex "RRGGBB" or "RRGGBBAA"
+    #[serde(default = "default_grid_color")]
+    pub color: String,
+
+    /// Line width in pixels
+    #[serde(default = "default_grid_width")]
+    pub width: u32,
+
+    /// Whether to label grid lines along the image edges
+    #[serde(default = "default_grid_labels")]
+    pub labels: bool,
+}
+
+/// Default colors of values outside the color range, overridable per request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipColors {
+    /// Color of values below the range as hex "RRGGBB" or "RRGGBBAA"
+    #[serde(default = "default_clip_below")]
+    pub below: String,
+
+    /// Color of values above the range as hex "RRGGBB" or "RRGGBBAA"
+    #[serde(default = "default_clip_above")]
+    pub above: String,
+}
+
+/// Localized display metadata for a single variable
+///
+/// Any field left unset falls back to the attribute stored in the NetCDF file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VariableTranslation {
+    /// Replacement for the `long_name` attribute
+    #[serde(default)]
+    pub long_name: Option<String>,
+
+    /// Replacement for the `units` attribute
+    #[serde(default)]
+    pub units: Option<String>,
+
+    /// Replacement for the `description` attribute
+    #[serde(default)]
+    pub description: Option<String>,
 }
 
 /// Complete configuration
@@ -101,6 +521,30 @@ pub struct Config {
     /// Log level
     #[serde(default = "default_log_level")]
     pub log_level: String,
+
+    /// Further datasets served under `/datasets/<name>`, keyed by name
+    #[serde(default)]
+    pub datasets: HashMap<String, DatasetConfig>,
+}
+
+/// A dataset served alongside the main one, with its own overrides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetConfig {
+    /// Path to the data file
+    pub file_path: PathBuf,
+
+    /// Configuration merged over the server-wide one for this dataset
+    /// For example: {"server": {"quotas": {"daily_bytes": 10485760}}, "data": {"colormap": "plasma"}}
+    #[serde(default)]
+    pub overrides: serde_json::Value,
+
+    /// Endpoints served for this dataset, e.g. ["/metadata", "/image"] (None = all)
+    #[serde(default)]
+    pub endpoints: Option<Vec<String>>,
+
+    /// API keys allowed to query this dataset in `X-API-Key` (None = anyone)
+    #[serde(default)]
+    pub api_keys: Option<Vec<String>>,
 }
 
 impl Config {
@@ -108,14 +552,8 @@ impl Config {
     pub fn load() -> Result<(Self, PathBuf)> {
         let args = Args::parse();
 
-        // Start with defaults
-        let mut config = Config::default();
-
-        // Load from JSON file if provided
-        if let Some(config_path) = &args.config {
-            let json_config = Self::load_from_file(config_path)?;
-            config.merge(json_config);
-        }
+        // Start with defaults, merged with the JSON file if provided
+        let mut config = Self::with_file(args.config.as_ref())?;
 
         // Override with command-line arguments
         config.server.host = args.host;
@@ -126,6 +564,12 @@ impl Config {
         if args.discovery_url.is_some() {
             config.server.discovery_url = args.discovery_url;
         }
+        if args.profile {
+            config.server.profile.enabled = true;
+        }
+        if args.profile_dir.is_some() {
+            config.server.profile.output_dir = args.profile_dir;
+        }
         config.log_level = args.log_level;
 
         // NetCDF file path from command line takes precedence
@@ -134,6 +578,16 @@ impl Config {
         Ok((config, netcdf_path))
     }
 
+    /// Default configuration merged with a JSON file, if given
+    pub fn with_file(config_path: Option<&PathBuf>) -> Result<Self> {
+        let mut config = Config::default();
+        if let Some(config_path) = config_path {
+            let json_config = Self::load_from_file(config_path)?;
+            config.merge(json_config);
+        }
+        Ok(config)
+    }
+
     /// Load configuration from a JSON file
     fn load_from_file(path: &PathBuf) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
@@ -148,8 +602,54 @@ impl Config {
         if other.server.workers.is_some() {
             self.server.workers = other.server.workers;
         }
+        if !other.server.peers.is_empty() {
+            self.server.peers = other.server.peers;
+        }
+        self.server.max_data_points = other.server.max_data_points;
+        self.server.max_points_ceiling = other.server.max_points_ceiling;
+        self.server.api_key_scopes = other.server.api_key_scopes;
+        self.server.quotas = other.server.quotas;
+        self.server.profile = other.server.profile;
+        self.server.body_limits = other.server.body_limits;
+        if !other.server.disabled_endpoints.is_empty() {
+            self.server.disabled_endpoints = other.server.disabled_endpoints;
+        }
+        self.server.warmup = other.server.warmup;
+        self.server.coalesce_requests = other.server.coalesce_requests;
+        if other.server.admin_token.is_some() {
+            self.server.admin_token = other.server.admin_token;
+        }
+        if other.server.access_log.is_some() {
+            self.server.access_log = other.server.access_log;
+        }
+        if other.server.signing_key_file.is_some() {
+            self.server.signing_key_file = other.server.signing_key_file;
+        }
         self.data = other.data;
         self.log_level = other.log_level;
+        if !other.datasets.is_empty() {
+            self.datasets = other.datasets;
+        }
+    }
+
+    /// Look up the translation overlay for a variable in the requested language
+    ///
+    /// Tries the exact language tag first (e.g. `de-AT`) and then its primary
+    /// subtag (`de`). Returns `None` when no overlay is configured.
+    pub fn translation_for(&self, lang: &str, var_name: &str) -> Option<&VariableTranslation> {
+        let lookup = |tag: &str| {
+            self.data
+                .translations
+                .get(tag)
+                .and_then(|vars| vars.get(var_name))
+        };
+
+        lookup(lang).or_else(|| {
+            lang.split(['-', '_'])
+                .next()
+                .filter(|primary| *primary != lang)
+                .and_then(lookup)
+        })
     }
 
     /// Validate the configuration
@@ -168,6 +668,178 @@ impl Config {
             });
         }
 
+        // Validate peer URLs
+        for (name, url) in &self.server.peers {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Invalid URL for peer '{}': {}. Must start with http:// or https://",
+                        name, url
+                    ),
+                });
+            }
+        }
+
+        // Validate dataset names, which appear in URLs
+        for name in self.datasets.keys() {
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Invalid dataset name '{}': use letters, digits, '-' and '_'",
+                        name
+                    ),
+                });
+            }
+        }
+
+        // Validate code table values
+        for table in self.data.code_tables.values() {
+            crate::categories::parse_code_table(table)?;
+        }
+
+        // Validate uncertainty companions
+        for (var, companion) in &self.data.uncertainty {
+            if var.is_empty() || companion.is_empty() || var == companion {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Invalid uncertainty pair '{}' -> '{}': name two different variables",
+                        var, companion
+                    ),
+                });
+            }
+        }
+
+        // Validate the append polling interval
+        if self.data.append_interval_secs == Some(0) {
+            return Err(RossbyError::Config {
+                message: "Data append_interval_secs must be greater than 0".to_string(),
+            });
+        }
+
+        // Validate the self-check interval
+        if self.data.self_check_interval_secs == Some(0) {
+            return Err(RossbyError::Config {
+                message: "Data self_check_interval_secs must be greater than 0".to_string(),
+            });
+        }
+
+        // Validate the time tolerance
+        let tolerance = &self.data.time_tolerance;
+        let valid = |t: f64| t.is_finite() && t >= 0.0;
+        if !valid(tolerance.absolute) || !valid(tolerance.relative) {
+            return Err(RossbyError::Config {
+                message: "Data time_tolerance must be finite and non-negative".to_string(),
+            });
+        }
+
+        // Validate the memory budget
+        if self.data.max_memory_bytes == Some(0) {
+            return Err(RossbyError::Config {
+                message: "Data max_memory_bytes must be greater than 0".to_string(),
+            });
+        }
+        crate::memory_budget::OnExceeded::parse(&self.data.on_memory_exceeded)?;
+
+        // Validate the storage backend
+        let storage = crate::lazy::StorageMode::parse(&self.data.storage)?;
+        if storage == crate::lazy::StorageMode::Lazy && self.data.append_interval_secs.is_some() {
+            return Err(RossbyError::Config {
+                message: "Data append_interval_secs is not supported with lazy storage".to_string(),
+            });
+        }
+
+        // Validate quota window
+        if self.server.quotas.window_secs == 0 {
+            return Err(RossbyError::Config {
+                message: "Quota window_secs must be greater than 0".to_string(),
+            });
+        }
+        for (path, weight) in &self.server.quotas.weights {
+            if !(weight.is_finite() && *weight >= 0.0) {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Quota weight for {} must be a non-negative number, got {}",
+                        path, weight
+                    ),
+                });
+            }
+        }
+
+        // Validate the point limit overrides
+        if let Some(ceiling) = self.server.max_points_ceiling {
+            if ceiling < self.server.max_data_points {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Server max_points_ceiling ({}) must be at least max_data_points ({})",
+                        ceiling, self.server.max_data_points
+                    ),
+                });
+            }
+        }
+        for (key, scopes) in &self.server.api_key_scopes {
+            if let Some(scope) = scopes
+                .iter()
+                .find(|s| !API_KEY_SCOPES.contains(&s.as_str()))
+            {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Unknown scope '{}' for an API key. Valid scopes are {:?}",
+                        scope, API_KEY_SCOPES
+                    ),
+                });
+            }
+            if key.trim().is_empty() {
+                return Err(RossbyError::Config {
+                    message: "API key scopes cannot be granted to an empty key".to_string(),
+                });
+            }
+        }
+
+        // Validate profiling sample rate
+        let sample_rate = self.server.profile.sample_rate;
+        if !(sample_rate > 0.0 && sample_rate <= 1.0) {
+            return Err(RossbyError::Config {
+                message: format!(
+                    "Invalid profile sample_rate: {}. Must be greater than 0 and at most 1",
+                    sample_rate
+                ),
+            });
+        }
+
+        // Validate body limits
+        let body_limits = &self.server.body_limits;
+        if body_limits.max_bytes == 0 || body_limits.max_decompressed_bytes == 0 {
+            return Err(RossbyError::Config {
+                message: "Body limits max_bytes and max_decompressed_bytes must be greater than 0"
+                    .to_string(),
+            });
+        }
+
+        // Validate disabled endpoints
+        for spec in &self.server.disabled_endpoints {
+            DisabledEndpoint::parse(spec)?;
+        }
+
+        // Validate warm-up requests
+        let warmup = &self.server.warmup;
+        if warmup.concurrency == 0 {
+            return Err(RossbyError::Config {
+                message: "Warm-up concurrency must be greater than 0".to_string(),
+            });
+        }
+        if let Some(request) = warmup.requests.iter().find(|r| !r.starts_with('/')) {
+            return Err(RossbyError::Config {
+                message: format!(
+                    "Invalid warm-up request: {}. Must be a path starting with '/'",
+                    request
+                ),
+            });
+        }
+
         // Validate log level
         match self.log_level.as_str() {
             "trace" | "debug" | "info" | "warn" | "error" => {}
@@ -194,6 +866,142 @@ impl Config {
             }
         }
 
+        // Validate bounds mode
+        BoundsMode::parse(&self.data.bounds).map_err(|_| RossbyError::Config {
+            message: format!(
+                "Invalid bounds mode: {}. Must be one of: error, clamp, wrap",
+                self.data.bounds
+            ),
+        })?;
+
+        // Validate the encoding of NaN in JSON
+        NanPolicy::parse(&self.data.json_nan).map_err(|_| RossbyError::Config {
+            message: format!(
+                "Invalid json_nan policy: {}. Must be one of: null, string, omit",
+                self.data.json_nan
+            ),
+        })?;
+
+        // Validate the handling of repeated time stamps
+        DuplicateTimes::parse(&self.data.duplicate_times)?;
+
+        // Validate load-time renames and coordinate fixes
+        let mut renamed = HashSet::new();
+        for (old, new) in &self.data.rename_dimensions {
+            if new.is_empty() || !renamed.insert(new) {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Invalid rename of dimension {}: '{}' is empty or used twice",
+                        old, new
+                    ),
+                });
+            }
+        }
+        for (dimension, fix) in &self.data.coordinate_fixes {
+            if fix.values.is_some() && (fix.scale.is_some() || fix.offset.is_some()) {
+                return Err(RossbyError::Config {
+                    message: format!(
+                        "Invalid fix of coordinate {}: values cannot be combined with scale or offset",
+                        dimension
+                    ),
+                });
+            }
+        }
+
+        // Validate default graticule styling
+        let grid = &self.data.grid;
+        parse_color("grid.color", &grid.color)
+            .and_then(|color| Graticule::new(grid.spacing, color, grid.width, grid.labels))
+            .map_err(|e| RossbyError::Config {
+                message: format!("Invalid grid configuration: {}", e),
+            })?;
+
+        // Validate out-of-range colors
+        let clip = &self.data.clip_colors;
+        parse_color("clip_colors.below", &clip.below)
+            .and_then(|_| parse_color("clip_colors.above", &clip.above))
+            .map_err(|e| RossbyError::Config {
+                message: format!("Invalid clip colors: {}", e),
+            })?;
+
+        // Validate product templates
+        for (name, template) in &self.data.products {
+            if name.is_empty() {
+                return Err(RossbyError::Config {
+                    message: "Product names cannot be empty".to_string(),
+                });
+            }
+            for (param, value) in template {
+                if param == PRODUCT_PARAM {
+                    return Err(RossbyError::Config {
+                        message: format!("Product '{}' cannot reference another product", name),
+                    });
+                }
+                if template_value(value).is_none() {
+                    return Err(RossbyError::Config {
+                        message: format!(
+                            "Invalid value for '{}' in product '{}': {}. Must be a string, number or boolean",
+                            param, name, value
+                        ),
+                    });
+                }
+            }
+        }
+
+        // Validate style presets
+        for (standard_name, preset) in &self.data.style_presets {
+            let Some(preset) = preset else { continue };
+            let invalid = |message: String| RossbyError::Config {
+                message: format!("Invalid style preset '{}': {}", standard_name, message),
+            };
+            crate::colormaps::get_colormap(&preset.colormap)
+                .map_err(|_| invalid(format!("unknown colormap '{}'", preset.colormap)))?;
+            if let (Some(vmin), Some(vmax)) = (preset.vmin, preset.vmax) {
+                if vmin >= vmax {
+                    return Err(invalid(format!(
+                        "vmin ({}) must be less than vmax ({})",
+                        vmin, vmax
+                    )));
+                }
+            }
+        }
+
+        // Validate the configured CRS
+        if let Some(crs) = &self.data.crs {
+            Crs::parse(crs)?;
+        }
+
+        // Validate named views
+        for (name, view) in &self.data.views {
+            let invalid = |message: String| RossbyError::Config {
+                message: format!("Invalid view '{}': {}", name, message),
+            };
+            if name.is_empty() {
+                return Err(RossbyError::Config {
+                    message: "View names cannot be empty".to_string(),
+                });
+            }
+            if let Some(bbox) = &view.bbox {
+                parse_bbox(bbox).map_err(|e| invalid(e.to_string()))?;
+            }
+            if let Some(time_agg) = &view.time_agg {
+                TimeAggregation::parse(time_agg).map_err(|e| invalid(e.to_string()))?;
+            }
+            if let Some(time_weights) = &view.time_weights {
+                TimeWeights::parse(time_weights).map_err(|e| invalid(e.to_string()))?;
+            }
+            if let Some((param, value)) = view
+                .select
+                .iter()
+                .find(|(_, value)| SelectorInput::from_json(value).is_none())
+            {
+                return Err(invalid(format!(
+                    "selector '{}' must be a string, a number or a list of numbers, not {}",
+                    param, value
+                )));
+            }
+        }
+
         Ok(())
     }
 }
@@ -204,6 +1012,7 @@ impl Default for Config {
             server: ServerConfig::default(),
             data: DataConfig::default(),
             log_level: default_log_level(),
+            datasets: HashMap::new(),
         }
     }
 }
@@ -216,16 +1025,119 @@ impl Default for ServerConfig {
             workers: None,
             discovery_url: None,
             max_data_points: default_max_data_points(),
+            max_points_ceiling: None,
+            api_key_scopes: HashMap::new(),
+            peers: HashMap::new(),
+            quotas: QuotaConfig::default(),
+            profile: ProfileConfig::default(),
+            body_limits: BodyLimitConfig::default(),
+            admin_token: None,
+            access_log: None,
+            signing_key_file: None,
+            disabled_endpoints: Vec::new(),
+            warmup: WarmupConfig::default(),
+            coalesce_requests: default_coalesce_requests(),
+        }
+    }
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            requests: Vec::new(),
+            concurrency: default_warmup_concurrency(),
         }
     }
 }
 
+impl Default for BodyLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: default_max_body_bytes(),
+            decompress: false,
+            max_decompressed_bytes: default_max_decompressed_bytes(),
+        }
+    }
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_rate: default_profile_sample_rate(),
+            output_dir: None,
+        }
+    }
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            daily_bytes: None,
+            keys: HashMap::new(),
+            window_secs: default_quota_window_secs(),
+            weights: HashMap::new(),
+        }
+    }
+}
+
+impl DataConfig {
+    /// Colormap of a rendered image: the requested one, else the configured default
+    pub fn colormap<'a>(&'a self, requested: Option<&'a str>) -> &'a str {
+        requested
+            .or(self.colormap.as_deref())
+            .unwrap_or(DEFAULT_COLORMAP)
+    }
+
+    /// Style preset of a variable with the given standard name and units
+    pub fn style_preset(&self, standard_name: &str, units: Option<&str>) -> Option<ResolvedPreset> {
+        resolve_preset(&self.style_presets, standard_name, units)
+    }
+}
+
 impl Default for DataConfig {
     fn default() -> Self {
         Self {
             interpolation_method: default_interpolation(),
+            bounds: default_bounds(),
+            json_nan: default_json_nan(),
+            duplicate_times: default_duplicate_times(),
+            colormap: None,
+            style_presets: HashMap::new(),
             file_path: None,
             dimension_aliases: HashMap::new(),
+            rename_dimensions: HashMap::new(),
+            coordinate_fixes: HashMap::new(),
+            crs: None,
+            translations: HashMap::new(),
+            grid: GridConfig::default(),
+            clip_colors: ClipColors::default(),
+            products: HashMap::new(),
+            views: HashMap::new(),
+            code_tables: HashMap::new(),
+            uncertainty: HashMap::new(),
+            append_interval_secs: None,
+            max_memory_bytes: None,
+            on_memory_exceeded: default_on_memory_exceeded(),
+            memory_priority: Vec::new(),
+            keep_packed: false,
+            storage: default_storage(),
+            lazy_cache_bytes: default_lazy_cache_bytes(),
+            climatology_file: None,
+            time_tolerance: Tolerance::default(),
+            self_check_interval_secs: None,
+            self_check_samples: default_self_check_samples(),
+        }
+    }
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            spacing: default_grid_spacing(),
+            color: default_grid_color(),
+            width: default_grid_width(),
+            labels: default_grid_labels(),
         }
     }
 }
@@ -243,6 +1155,34 @@ fn default_interpolation() -> String {
     "bilinear".to_string()
 }
 
+fn default_on_memory_exceeded() -> String {
+    "error".to_string()
+}
+
+fn default_storage() -> String {
+    "memory".to_string()
+}
+
+fn default_lazy_cache_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_self_check_samples() -> usize {
+    64
+}
+
+fn default_bounds() -> String {
+    "error".to_string()
+}
+
+fn default_json_nan() -> String {
+    "null".to_string()
+}
+
+fn default_duplicate_times() -> String {
+    "first".to_string()
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -251,6 +1191,63 @@ fn default_max_data_points() -> usize {
     100_000_000 // 100 million points default
 }
 
+fn default_max_body_bytes() -> usize {
+    1024 * 1024 // 1 MiB
+}
+
+fn default_max_decompressed_bytes() -> usize {
+    8 * 1024 * 1024 // 8 MiB
+}
+
+fn default_quota_window_secs() -> u64 {
+    86_400 // one day
+}
+
+fn default_profile_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_coalesce_requests() -> bool {
+    true
+}
+
+fn default_warmup_concurrency() -> usize {
+    4
+}
+
+fn default_grid_spacing() -> f64 {
+    30.0
+}
+
+fn default_grid_color() -> String {
+    "ffffffb3".to_string() // white, 70% opaque
+}
+
+fn default_grid_width() -> u32 {
+    1
+}
+
+fn default_grid_labels() -> bool {
+    true
+}
+
+impl Default for ClipColors {
+    fn default() -> Self {
+        Self {
+            below: default_clip_below(),
+            above: default_clip_above(),
+        }
+    }
+}
+
+fn default_clip_below() -> String {
+    "00ffff".to_string()
+}
+
+fn default_clip_above() -> String {
+    "ff00ff".to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -289,6 +1286,61 @@ mod tests {
         config.server.host = "".to_string();
         assert!(config.validate().is_err());
 
+        // Test invalid body limit
+        let mut config = Config::default();
+        config.server.body_limits.max_bytes = 0;
+        assert!(config.validate().is_err());
+
+        // Test style presets with unknown colormaps or empty ranges
+        let mut config = Config::default();
+        let preset = StylePreset {
+            colormap: "rdylbu_r".to_string(),
+            vmin: Some(-40.0),
+            vmax: Some(45.0),
+            units: Some("degC".to_string()),
+        };
+        config
+            .data
+            .style_presets
+            .insert("air_temperature".to_string(), Some(preset.clone()));
+        config
+            .data
+            .style_presets
+            .insert("sea_ice_area_fraction".to_string(), None);
+        assert!(config.validate().is_ok());
+        let unknown = StylePreset {
+            colormap: "jet".to_string(),
+            ..preset.clone()
+        };
+        config
+            .data
+            .style_presets
+            .insert("air_temperature".to_string(), Some(unknown));
+        assert!(config.validate().is_err());
+        let empty = StylePreset {
+            vmin: Some(45.0),
+            ..preset
+        };
+        config
+            .data
+            .style_presets
+            .insert("air_temperature".to_string(), Some(empty));
+        assert!(config.validate().is_err());
+
+        // Test invalid disabled endpoint
+        let mut config = Config::default();
+        config.server.disabled_endpoints = vec!["/admin".to_string(), "data".to_string()];
+        assert!(config.validate().is_err());
+
+        // Test invalid warm-up request
+        let mut config = Config::default();
+        config.server.warmup.requests = vec!["image?var=t2m".to_string()];
+        assert!(config.validate().is_err());
+        config.server.warmup.requests = vec!["/image?var=t2m".to_string()];
+        assert!(config.validate().is_ok());
+        config.server.warmup.concurrency = 0;
+        assert!(config.validate().is_err());
+
         // Test invalid port
         let mut config = Config::default();
         config.server.port = 0;
@@ -303,5 +1355,105 @@ mod tests {
         let mut config = Config::default();
         config.data.interpolation_method = "invalid".to_string();
         assert!(config.validate().is_err());
+
+        // Test invalid bounds mode
+        let mut config = Config::default();
+        config.data.bounds = "reflect".to_string();
+        assert!(config.validate().is_err());
+
+        // Test invalid storage backends
+        let mut config = Config::default();
+        config.data.storage = "mmap".to_string();
+        assert!(config.validate().is_err());
+        config.data.storage = "lazy".to_string();
+        assert!(config.validate().is_ok());
+        config.data.append_interval_secs = Some(60);
+        assert!(config.validate().is_err());
+
+        // Test invalid peer URL
+        let mut config = Config::default();
+        config
+            .server
+            .peers
+            .insert("era5".to_string(), "era5-server:8000".to_string());
+        assert!(config.validate().is_err());
+
+        // Test empty quota window
+        let mut config = Config::default();
+        config.server.quotas.window_secs = 0;
+        assert!(config.validate().is_err());
+
+        // Test point limit overrides
+        let mut config = Config::default();
+        config.server.max_points_ceiling = Some(config.server.max_data_points - 1);
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.server.api_key_scopes =
+            HashMap::from([("team-a".to_string(), vec!["admin".to_string()])]);
+        assert!(config.validate().is_err());
+        config.server.api_key_scopes =
+            HashMap::from([("team-a".to_string(), vec![MAX_POINTS_SCOPE.to_string()])]);
+        assert!(config.validate().is_ok());
+        assert!(config
+            .server
+            .key_has_scope(Some("team-a"), MAX_POINTS_SCOPE));
+        assert!(!config
+            .server
+            .key_has_scope(Some("team-b"), MAX_POINTS_SCOPE));
+        assert!(!config.server.key_has_scope(None, MAX_POINTS_SCOPE));
+
+        // Test invalid profile sample rates
+        for sample_rate in [0.0, 1.5, f64::NAN] {
+            let mut config = Config::default();
+            config.server.profile.sample_rate = sample_rate;
+            assert!(config.validate().is_err());
+        }
+
+        // Test invalid grid styling
+        let mut config = Config::default();
+        config.data.grid.color = "white".to_string();
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.data.grid.spacing = -10.0;
+        assert!(config.validate().is_err());
+
+        // Test invalid product templates
+        let mut config = Config::default();
+        config.data.products.insert(
+            "map".to_string(),
+            HashMap::from([("bbox".to_string(), serde_json::json!([0, 0, 10, 10]))]),
+        );
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.data.products.insert(
+            "map".to_string(),
+            HashMap::from([("product".to_string(), serde_json::json!("other"))]),
+        );
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_translation_lookup() {
+        let json = r#"{
+            "data": {
+                "translations": {
+                    "de": {
+                        "t2m": { "long_name": "2 m Temperatur" }
+                    }
+                }
+            }
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+
+        let exact = config.translation_for("de", "t2m").unwrap();
+        assert_eq!(exact.long_name.as_deref(), Some("2 m Temperatur"));
+        assert_eq!(exact.units, None);
+
+        // Regional tags fall back to the primary language
+        assert!(config.translation_for("de-AT", "t2m").is_some());
+        assert!(config.translation_for("de_CH", "t2m").is_some());
+
+        assert!(config.translation_for("fr", "t2m").is_none());
+        assert!(config.translation_for("de", "u10").is_none());
     }
 }
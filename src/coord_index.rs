@@ -0,0 +1,267 @@
+//! Precomputed lookup structures for coordinate arrays.
+//!
+//! Coordinate lookups happen on every request that selects a slice by
+//! physical value. A linear scan becomes a hotspot for long axes (e.g. hourly
+//! time series with 100k+ steps), so each coordinate array gets a
+//! `CoordinateIndex` when the application state is built: the values sorted
+//! together with their original positions for O(log n) nearest-neighbour
+//! search, and a hash of exact values for O(1) exact matches.
+//!
+//! Physical values sent by clients rarely equal a coordinate bit for bit
+//! once they have been through float formatting, so lookups that expect a
+//! match accept the nearest value within a [`Tolerance`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How far a requested value may be from the coordinate it matches
+///
+/// A value matches a coordinate when they differ by at most `absolute`, or
+/// by at most `relative` times the larger magnitude of the two.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tolerance {
+    /// Largest absolute difference
+    #[serde(default = "default_absolute")]
+    pub absolute: f64,
+
+    /// Largest difference relative to the magnitude of the values
+    #[serde(default = "default_relative")]
+    pub relative: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            absolute: default_absolute(),
+            relative: default_relative(),
+        }
+    }
+}
+
+impl Tolerance {
+    /// Whether `a` and `b` are equal within the tolerance
+    pub fn allows(&self, a: f64, b: f64) -> bool {
+        let difference = (a - b).abs();
+        difference <= self.absolute || difference <= self.relative * a.abs().max(b.abs())
+    }
+}
+
+fn default_absolute() -> f64 {
+    1e-6
+}
+
+fn default_relative() -> f64 {
+    1e-12
+}
+
+/// Lookup index over one coordinate array
+#[derive(Debug, Clone, Default)]
+pub struct CoordinateIndex {
+    /// Finite coordinate values paired with their position, sorted by value
+    sorted: Vec<(f64, usize)>,
+    /// Position of the first occurrence of each exact value (keyed by bit pattern)
+    exact: HashMap<u64, usize>,
+}
+
+impl CoordinateIndex {
+    /// Build an index over coordinate values in any order
+    ///
+    /// Non-finite values are never matched.
+    pub fn new(values: &[f64]) -> Self {
+        let mut sorted: Vec<(f64, usize)> = values
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_finite())
+            .map(|(i, &v)| (v, i))
+            .collect();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let mut exact = HashMap::with_capacity(sorted.len());
+        for &(value, position) in &sorted {
+            exact.entry(value_key(value)).or_insert(position);
+        }
+
+        Self { sorted, exact }
+    }
+
+    /// Smallest and largest finite coordinate value
+    pub fn bounds(&self) -> Option<(f64, f64)> {
+        Some((self.sorted.first()?.0, self.sorted.last()?.0))
+    }
+
+    /// Position of the value closest to `value`
+    ///
+    /// Ties are resolved in favour of the lowest position. Returns `None` for a
+    /// non-finite `value` or an index without finite values.
+    pub fn nearest(&self, value: f64) -> Option<usize> {
+        if !value.is_finite() || self.sorted.is_empty() {
+            return None;
+        }
+
+        // First entry with a value >= the query
+        let upper = self.sorted.partition_point(|&(v, _)| v < value);
+
+        let mut best: Option<(f64, usize)> = None;
+        let mut consider = |diff: f64, position: usize| match best {
+            Some((best_diff, best_position))
+                if diff > best_diff || (diff == best_diff && position >= best_position) => {}
+            _ => best = Some((diff, position)),
+        };
+
+        // Entries equal to the nearest value below the query
+        if upper > 0 {
+            let below = self.sorted[upper - 1].0;
+            let first = self.sorted[..upper].partition_point(|&(v, _)| v < below);
+            consider(value - below, self.sorted[first].1);
+        }
+        // Entries equal to the nearest value at or above the query
+        if upper < self.sorted.len() {
+            consider(self.sorted[upper].0 - value, self.sorted[upper].1);
+        }
+
+        best.map(|(_, position)| position)
+    }
+
+    /// Position of a value within `f64::EPSILON` of `value`, preferring an exact match
+    pub fn exact(&self, value: f64) -> Option<usize> {
+        if let Some(&position) = self.exact.get(&value_key(value)) {
+            return Some(position);
+        }
+
+        // Values that differ only by rounding noise
+        let start = self
+            .sorted
+            .partition_point(|&(v, _)| v <= value - f64::EPSILON);
+        self.sorted[start..]
+            .iter()
+            .take_while(|&&(v, _)| v < value + f64::EPSILON)
+            .map(|&(_, position)| position)
+            .min()
+    }
+
+    /// Position of the value nearest to `value` if it is within `tolerance`,
+    /// preferring an exact match
+    pub fn within(&self, value: f64, tolerance: &Tolerance) -> Option<usize> {
+        if let Some(position) = self.exact(value) {
+            return Some(position);
+        }
+        let sorted = self.sorted.partition_point(|&(v, _)| v < value);
+        let candidates = self.sorted[sorted.saturating_sub(1)..]
+            .iter()
+            .take(2)
+            .filter(|&&(v, _)| tolerance.allows(v, value));
+        candidates
+            .min_by(|a, b| {
+                let (da, db) = ((a.0 - value).abs(), (b.0 - value).abs());
+                da.total_cmp(&db).then(a.1.cmp(&b.1))
+            })
+            .map(|&(_, position)| position)
+    }
+
+    /// Up to `count` distinct values closest to `value`, in ascending order
+    pub fn nearby(&self, value: f64, count: usize) -> Vec<f64> {
+        let mut values: Vec<f64> = self.sorted.iter().map(|&(v, _)| v).collect();
+        values.dedup();
+        if !value.is_finite() || values.len() <= count {
+            return values;
+        }
+        let center = values.partition_point(|&v| v < value);
+        let mut start = center.saturating_sub(count / 2).min(values.len() - count);
+        // Shift the window towards the closer side
+        while start > 0 && (value - values[start - 1]) < (values[start + count - 1] - value) {
+            start -= 1;
+        }
+        values[start..start + count].to_vec()
+    }
+}
+
+/// Hash key for a coordinate value, treating -0.0 and 0.0 as equal
+fn value_key(value: f64) -> u64 {
+    if value == 0.0 {
+        0.0_f64.to_bits()
+    } else {
+        value.to_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference implementation matching the original linear scan
+    fn linear_nearest(values: &[f64], value: f64) -> usize {
+        let mut closest_idx = 0;
+        let mut min_diff = f64::MAX;
+        for (i, &coord) in values.iter().enumerate() {
+            let diff = (coord - value).abs();
+            if diff < min_diff {
+                min_diff = diff;
+                closest_idx = i;
+            }
+        }
+        closest_idx
+    }
+
+    #[test]
+    fn test_nearest_matches_linear_scan() {
+        let ascending: Vec<f64> = (0..50).map(|i| i as f64 * 0.5).collect();
+        let descending: Vec<f64> = ascending.iter().rev().copied().collect();
+        let irregular = vec![3.0, -1.0, 7.5, 7.5, 2.0, 10.0, 0.0];
+
+        for values in [&ascending, &descending, &irregular] {
+            let index = CoordinateIndex::new(values);
+            for step in -10..=250 {
+                let query = step as f64 * 0.125 - 1.0;
+                assert_eq!(
+                    index.nearest(query),
+                    Some(linear_nearest(values, query)),
+                    "query {} on {:?}",
+                    query,
+                    values
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_and_bounds() {
+        let index = CoordinateIndex::new(&[5.0, -0.0, 1.0, 5.0, f64::NAN, 3.0]);
+
+        assert_eq!(index.bounds(), Some((-0.0, 5.0)));
+        assert_eq!(index.exact(5.0), Some(0));
+        assert_eq!(index.exact(0.0), Some(1));
+        assert_eq!(index.exact(3.0 + f64::EPSILON / 2.0), Some(5));
+        assert_eq!(index.exact(2.0), None);
+        assert_eq!(index.exact(f64::NAN), None);
+        assert_eq!(index.nearest(f64::NAN), None);
+
+        assert_eq!(CoordinateIndex::new(&[]).nearest(1.0), None);
+        assert_eq!(CoordinateIndex::new(&[f64::NAN]).bounds(), None);
+    }
+
+    #[test]
+    fn test_within_tolerance() {
+        let times: Vec<f64> = (0..10).map(|i| 1672531200.0 + i as f64 * 3600.0).collect();
+        let index = CoordinateIndex::new(&times);
+        let tolerance = Tolerance::default();
+
+        assert_eq!(index.within(1672534800.0, &tolerance), Some(1));
+        // Client float formatting noise, and a relative tolerance for it
+        assert_eq!(index.within(1672534800.0000002, &tolerance), Some(1));
+        assert_eq!(index.within(1672534800.01, &tolerance), None);
+        let loose = Tolerance {
+            absolute: 0.0,
+            relative: 1e-9,
+        };
+        assert_eq!(index.within(1672534800.01, &loose), Some(1));
+        assert_eq!(index.within(1672534803.0, &loose), None);
+        assert!(Tolerance::default().allows(1.0, 1.0 + 1e-7));
+
+        assert_eq!(
+            index.nearby(1672538000.0, 3),
+            vec![1672534800.0, 1672538400.0, 1672542000.0]
+        );
+        assert_eq!(index.nearby(0.0, 2), vec![1672531200.0, 1672534800.0]);
+        assert_eq!(CoordinateIndex::new(&[1.0, 1.0]).nearby(1.0, 5), vec![1.0]);
+    }
+}
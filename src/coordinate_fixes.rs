@@ -0,0 +1,298 @@
+//! Renaming of dimensions and fixes of coordinate values at load.
+//!
+//! Some files carry plainly wrong coordinates, such as longitudes in
+//! centi-degrees or a time axis without values, or dimension names that
+//! tools do not recognize (`x`, `y`). Rather than rewriting such files,
+//! `data.rename_dimensions` renames dimensions as they are loaded (from the
+//! name in the file to the served name), and `data.coordinate_fixes` either
+//! applies an affine `scale` and `offset` to the values of a coordinate or
+//! replaces them with configured `values`.
+//!
+//! Renames come first, so fixes are keyed by served names. A coordinate
+//! variable named after a renamed dimension is renamed with it. Both run
+//! before time axes are normalized (see [`crate::time_axis`]), and every
+//! change is recorded in the `coordinate_fixes` section of `/metadata`.
+
+use ndarray::{Array, IxDyn};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::config::{CoordinateFix, DataConfig};
+use crate::data_loader::rename_dimension;
+use crate::error::{Result, RossbyError};
+use crate::state::Metadata;
+
+/// How the coordinate of a dimension was changed at load
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoordinateChange {
+    /// Name of the dimension as served
+    pub dimension: String,
+    /// Name of the dimension in the file, if it was renamed
+    pub renamed_from: Option<String>,
+    /// Factor the coordinate values were multiplied by
+    pub scale: Option<f64>,
+    /// Offset added to the coordinate values after scaling
+    pub offset: Option<f64>,
+    /// Whether the coordinate values were replaced by configured ones
+    pub replaced: bool,
+}
+
+impl CoordinateChange {
+    fn new(dimension: &str) -> Self {
+        Self {
+            dimension: dimension.to_string(),
+            renamed_from: None,
+            scale: None,
+            offset: None,
+            replaced: false,
+        }
+    }
+}
+
+/// Rename dimensions and fix coordinate values of a loaded file
+///
+/// Returns the changes made, ordered by served dimension name.
+pub fn apply_coordinate_fixes(
+    metadata: &mut Metadata,
+    data: &mut HashMap<String, Array<f32, IxDyn>>,
+    config: &DataConfig,
+) -> Result<Vec<CoordinateChange>> {
+    let mut changes: HashMap<String, CoordinateChange> = HashMap::new();
+
+    let mut renames: Vec<(&String, &String)> = config.rename_dimensions.iter().collect();
+    renames.sort();
+    for (old, new) in renames {
+        rename(metadata, data, old, new)?;
+        warn!(from = %old, to = %new, "Renamed dimension at load");
+        changes
+            .entry(new.clone())
+            .or_insert_with(|| CoordinateChange::new(new))
+            .renamed_from = Some(old.clone());
+    }
+
+    let mut fixes: Vec<(&String, &CoordinateFix)> = config.coordinate_fixes.iter().collect();
+    fixes.sort_by_key(|(dimension, _)| *dimension);
+    for (dimension, fix) in fixes {
+        let values = fixed_values(metadata, dimension, fix)?;
+        if let Some(array) = data.get_mut(dimension).filter(|array| array.ndim() == 1) {
+            *array = Array::from_iter(values.iter().map(|&v| v as f32)).into_dyn();
+        }
+        metadata.coordinates.insert(dimension.clone(), values);
+        warn!(dimension = %dimension, "Fixed coordinate values at load");
+
+        let change = changes
+            .entry(dimension.clone())
+            .or_insert_with(|| CoordinateChange::new(dimension));
+        change.scale = fix.scale;
+        change.offset = fix.offset;
+        change.replaced = fix.values.is_some();
+    }
+
+    let mut changes: Vec<CoordinateChange> = changes.into_values().collect();
+    changes.sort_by(|a, b| a.dimension.cmp(&b.dimension));
+    Ok(changes)
+}
+
+/// Rename a dimension, and the coordinate variable named after it
+fn rename(
+    metadata: &mut Metadata,
+    data: &mut HashMap<String, Array<f32, IxDyn>>,
+    old: &str,
+    new: &str,
+) -> Result<()> {
+    if !metadata.dimensions.contains_key(old) {
+        return Err(RossbyError::Config {
+            message: format!("Cannot rename dimension {}: not in the file", old),
+        });
+    }
+    if metadata.dimensions.contains_key(new) || metadata.variables.contains_key(new) {
+        return Err(RossbyError::Config {
+            message: format!(
+                "Cannot rename dimension {} to {}: the name is already taken",
+                old, new
+            ),
+        });
+    }
+
+    rename_dimension(metadata, old, new);
+    if let Some(mut variable) = metadata.variables.remove(old) {
+        variable.name = new.to_string();
+        metadata.variables.insert(new.to_string(), variable);
+    }
+    if let Some(array) = data.remove(old) {
+        data.insert(new.to_string(), array);
+    }
+    Ok(())
+}
+
+/// The values of a coordinate after a fix
+fn fixed_values(metadata: &Metadata, dimension: &str, fix: &CoordinateFix) -> Result<Vec<f64>> {
+    let size = metadata
+        .dimensions
+        .get(dimension)
+        .map(|dim| dim.size)
+        .ok_or_else(|| RossbyError::Config {
+            message: format!("Cannot fix coordinate {}: no such dimension", dimension),
+        })?;
+
+    if let Some(values) = &fix.values {
+        if values.len() != size {
+            return Err(RossbyError::Config {
+                message: format!(
+                    "Cannot replace coordinate {}: {} values given for {} steps",
+                    dimension,
+                    values.len(),
+                    size
+                ),
+            });
+        }
+        return Ok(values.clone());
+    }
+
+    let values = metadata
+        .coordinates
+        .get(dimension)
+        .ok_or_else(|| RossbyError::Config {
+            message: format!(
+                "Cannot scale coordinate {}: the file has no values for it",
+                dimension
+            ),
+        })?;
+    let (scale, offset) = (fix.scale.unwrap_or(1.0), fix.offset.unwrap_or(0.0));
+    Ok(values.iter().map(|v| v * scale + offset).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Dimension, Variable};
+
+    fn metadata() -> (Metadata, HashMap<String, Array<f32, IxDyn>>) {
+        let mut dimensions = HashMap::new();
+        for (name, size) in [("t", 2), ("x", 3)] {
+            dimensions.insert(
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                },
+            );
+        }
+
+        let mut variables = HashMap::new();
+        for (name, dims) in [("x", vec!["x"]), ("v", vec!["t", "x"])] {
+            variables.insert(
+                name.to_string(),
+                Variable {
+                    name: name.to_string(),
+                    dimensions: dims.iter().map(|d| d.to_string()).collect(),
+                    shape: dims.iter().map(|d| dimensions[*d].size).collect(),
+                    attributes: HashMap::new(),
+                    dtype: "f32".to_string(),
+                },
+            );
+        }
+
+        let coordinates = HashMap::from([("x".to_string(), vec![0.0, 18000.0, 35000.0])]);
+        let data = HashMap::from([
+            (
+                "x".to_string(),
+                Array::from_vec(vec![0.0, 18000.0, 35000.0]).into_dyn(),
+            ),
+            ("v".to_string(), Array::zeros(IxDyn(&[2, 3]))),
+        ]);
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables,
+            coordinates,
+            groups: HashMap::new(),
+        };
+        (metadata, data)
+    }
+
+    #[test]
+    fn test_rename_and_fix() {
+        let (mut metadata, mut data) = metadata();
+        let mut config = DataConfig::default();
+        config
+            .rename_dimensions
+            .insert("x".to_string(), "lon".to_string());
+        config.coordinate_fixes.insert(
+            "lon".to_string(),
+            CoordinateFix {
+                scale: Some(0.01),
+                offset: Some(-180.0),
+                values: None,
+            },
+        );
+        config.coordinate_fixes.insert(
+            "t".to_string(),
+            CoordinateFix {
+                values: Some(vec![0.0, 6.0]),
+                ..Default::default()
+            },
+        );
+
+        let changes = apply_coordinate_fixes(&mut metadata, &mut data, &config).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].dimension, "lon");
+        assert_eq!(changes[0].renamed_from.as_deref(), Some("x"));
+        assert_eq!(changes[0].scale, Some(0.01));
+        assert!(changes[1].replaced);
+
+        assert_eq!(metadata.coordinates["lon"], vec![-180.0, 0.0, 170.0]);
+        assert_eq!(metadata.coordinates["t"], vec![0.0, 6.0]);
+        assert_eq!(metadata.variables["v"].dimensions, vec!["t", "lon"]);
+        assert_eq!(metadata.variables["lon"].name, "lon");
+        assert!(!metadata.dimensions.contains_key("x"));
+        assert_eq!(data["lon"].as_slice().unwrap(), &[-180.0, 0.0, 170.0]);
+        assert!(!data.contains_key("x"));
+    }
+
+    #[test]
+    fn test_invalid_fixes() {
+        let (metadata, data) = metadata();
+        let apply = |config: &DataConfig| {
+            apply_coordinate_fixes(&mut metadata.clone(), &mut data.clone(), config)
+        };
+
+        // Unknown dimension
+        let mut config = DataConfig::default();
+        config
+            .rename_dimensions
+            .insert("y".to_string(), "lat".to_string());
+        assert!(apply(&config).is_err());
+
+        // Name taken by a variable
+        let mut config = DataConfig::default();
+        config
+            .rename_dimensions
+            .insert("x".to_string(), "v".to_string());
+        assert!(apply(&config).is_err());
+
+        // Wrong number of values
+        let mut config = DataConfig::default();
+        config.coordinate_fixes.insert(
+            "x".to_string(),
+            CoordinateFix {
+                values: Some(vec![1.0]),
+                ..Default::default()
+            },
+        );
+        assert!(apply(&config).is_err());
+
+        // No values to scale
+        let mut config = DataConfig::default();
+        config.coordinate_fixes.insert(
+            "t".to_string(),
+            CoordinateFix {
+                scale: Some(2.0),
+                ..Default::default()
+            },
+        );
+        assert!(apply(&config).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Pre-flight estimates of the work a request causes.
+//!
+//! Quotas count transferred bytes, which says little about the work behind a
+//! response: a 4K PNG takes far longer to render than its size suggests. The
+//! estimator counts the cells a request touches before it runs (pixels for
+//! rendered images and WMS maps, values for `/data` extracts and `/export` files, one
+//! per variable for `/point`, one for anything else), and
+//! `server.quotas.weights` prices
+//! those cells in bytes per endpoint. Requests whose estimated cost exceeds
+//! the remaining allowance are rejected before any work is done.
+
+use crate::handlers::{
+    data::estimate_values, exceedance, export, image, profile_series, thumbnail, wms,
+};
+use crate::state::AppState;
+
+/// Number of cells a request to `path` with the raw query `query` touches
+///
+/// Invalid queries count as one cell, as they fail before doing any work.
+pub fn estimate_cells(state: &AppState, path: &str, query: &str) -> u64 {
+    let estimate = match path {
+        "/image" | "/exceedance" | "/correlate" | "/lagcorr" | "/profile_series" | "/thumbnail"
+            if renders_image(path, query) =>
+        {
+            let (default_width, default_height) = default_image_size(path);
+            let size = |name: &str, default: u32| {
+                query_value(query, name)
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .unwrap_or(default as u64)
+            };
+            Some(size("width", default_width).saturating_mul(size("height", default_height)))
+        }
+        "/data" => estimate_values(state, query),
+        "/export" => export::estimate_values(state, query),
+        "/wms" => wms::estimate_pixels(query),
+        "/point" => query_value(query, "vars")
+            .map(|vars| vars.split(',').filter(|v| !v.trim().is_empty()).count() as u64),
+        _ => None,
+    };
+    estimate.unwrap_or(1).max(1)
+}
+
+/// Whether a request renders an image rather than returning data
+fn renders_image(path: &str, query: &str) -> bool {
+    path == "/image"
+        || path == "/thumbnail"
+        || query_value(query, "format").as_deref() == Some("png")
+}
+
+/// Size of the images an endpoint renders when none is requested
+fn default_image_size(path: &str) -> (u32, u32) {
+    match path {
+        "/exceedance" | "/correlate" | "/lagcorr" => {
+            (exceedance::DEFAULT_WIDTH, exceedance::DEFAULT_HEIGHT)
+        }
+        "/thumbnail" => (thumbnail::DEFAULT_WIDTH, thumbnail::DEFAULT_HEIGHT),
+        "/profile_series" => (
+            profile_series::DEFAULT_WIDTH,
+            profile_series::DEFAULT_HEIGHT,
+        ),
+        _ => (image::DEFAULT_WIDTH, image::DEFAULT_HEIGHT),
+    }
+}
+
+/// Decoded value of a query parameter
+fn query_value(query: &str, name: &str) -> Option<String> {
+    serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+        .ok()?
+        .into_iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::Metadata;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_estimate_cells() {
+        let state = AppState::new(
+            Config::default(),
+            Metadata {
+                global_attributes: HashMap::new(),
+                dimensions: HashMap::new(),
+                variables: HashMap::new(),
+                coordinates: HashMap::new(),
+                groups: HashMap::new(),
+            },
+            HashMap::new(),
+        );
+
+        assert_eq!(estimate_cells(&state, "/image", "var=t2m"), 800 * 600);
+        assert_eq!(
+            estimate_cells(&state, "/profile_series", "var=t&format=png"),
+            800 * 400
+        );
+        assert_eq!(
+            estimate_cells(&state, "/image", "var=t2m&width=3840&height=2160"),
+            3840 * 2160
+        );
+        assert_eq!(
+            estimate_cells(
+                &state,
+                "/exceedance",
+                "var=t2m&format=png&width=10&height=10"
+            ),
+            100
+        );
+        assert_eq!(estimate_cells(&state, "/exceedance", "var=t2m"), 1);
+        assert_eq!(
+            estimate_cells(&state, "/point", "lon=0&lat=0&vars=t2m%2Cu10"),
+            2
+        );
+        assert_eq!(
+            estimate_cells(
+                &state,
+                "/wms",
+                "REQUEST=GetMap&LAYERS=t2m,u10&WIDTH=256&HEIGHT=256"
+            ),
+            2 * 256 * 256
+        );
+        assert_eq!(estimate_cells(&state, "/wms", "request=GetCapabilities"), 1);
+        assert_eq!(estimate_cells(&state, "/heartbeat", ""), 1);
+        // Invalid queries count as one cell
+        assert_eq!(estimate_cells(&state, "/data", "vars=missing"), 1);
+    }
+}
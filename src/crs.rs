@@ -0,0 +1,346 @@
+//! Coordinate reference system of the dataset.
+//!
+//! The CRS is taken, in order of precedence, from `data.crs` in the
+//! configuration (an `EPSG:<code>` identifier or a WKT string), from the CF
+//! grid mapping variable named by the `grid_mapping` attribute of the data
+//! variables (its `crs_wkt` or GDAL `spatial_ref` WKT, `epsg_code`, or a
+//! `latitude_longitude` grid mapping), or else assumed to be WGS 84
+//! (`EPSG:4326`), the CRS of plain latitude/longitude grids.
+//!
+//! `/metadata` lists the CRS and where it came from. Georeferenced outputs
+//! name the CRS of their pixels in the `X-Rossby-CRS` response header, as
+//! given by [`Crs::rendered`], so GIS clients need not guess.
+
+use serde::Serialize;
+
+use crate::colormaps::LatitudeScaling;
+use crate::error::{Result, RossbyError};
+use crate::state::{AttributeValue, Metadata};
+
+/// Response header naming the CRS of a georeferenced output
+pub const CRS_HEADER: &str = "x-rossby-crs";
+
+/// Identifier of WGS 84 latitude/longitude coordinates
+pub const WGS84: &str = "EPSG:4326";
+
+/// Identifier of the Web Mercator projection of map tiles
+pub const WEB_MERCATOR: &str = "EPSG:3857";
+
+/// Identifier of the Lambert cylindrical equal-area projection on WGS 84
+pub const CYLINDRICAL_EQUAL_AREA: &str = "ESRI:54034";
+
+/// Grid mapping attributes holding a WKT description of the CRS
+const WKT_ATTRIBUTES: [&str; 2] = ["crs_wkt", "spatial_ref"];
+
+/// Keywords opening the WKT description of a geographic CRS
+const GEOGRAPHIC_WKT_KEYWORDS: [&str; 4] = ["GEOGCS", "GEOGCRS", "GEOGRAPHICCRS", "GEODCRS"];
+
+/// Where the CRS of the dataset was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrsSource {
+    /// `data.crs` in the configuration
+    Config,
+    /// The grid mapping variable of the file
+    GridMapping,
+    /// Neither; latitude/longitude coordinates are assumed to be WGS 84
+    Assumed,
+}
+
+/// Coordinate reference system of the dataset
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Crs {
+    /// Authority identifier, e.g. `EPSG:4326` (None = not known)
+    pub identifier: Option<String>,
+    /// WKT description, if given
+    pub wkt: Option<String>,
+    /// Name of the grid mapping variable the CRS was read from
+    pub grid_mapping: Option<String>,
+    /// CF `grid_mapping_name` of the grid mapping variable
+    pub grid_mapping_name: Option<String>,
+    /// Where the CRS was found
+    pub source: CrsSource,
+}
+
+impl Crs {
+    /// The assumed CRS of latitude/longitude grids
+    pub fn wgs84() -> Self {
+        Self {
+            identifier: Some(WGS84.to_string()),
+            wkt: None,
+            grid_mapping: None,
+            grid_mapping_name: None,
+            source: CrsSource::Assumed,
+        }
+    }
+
+    /// Parse a configured CRS, an `EPSG:<code>` identifier or a WKT string
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let invalid = || RossbyError::Config {
+            message: format!(
+                "Invalid CRS: {}. Must be an identifier like EPSG:4326 or a WKT string",
+                value
+            ),
+        };
+        let (identifier, wkt) = if value.contains('[') {
+            (wkt_identifier(value), Some(value.to_string()))
+        } else {
+            let (authority, code) = value.split_once(':').ok_or_else(invalid)?;
+            if authority.is_empty() || code.is_empty() || code.contains(char::is_whitespace) {
+                return Err(invalid());
+            }
+            (Some(format!("{}:{}", authority.to_uppercase(), code)), None)
+        };
+        Ok(Self {
+            identifier,
+            wkt,
+            grid_mapping: None,
+            grid_mapping_name: None,
+            source: CrsSource::Config,
+        })
+    }
+
+    /// The CRS of a dataset, from the configured one or the file's metadata
+    pub fn detect(configured: Option<&str>, metadata: &Metadata) -> Result<Self> {
+        if let Some(configured) = configured {
+            return Self::parse(configured);
+        }
+        Ok(Self::from_grid_mapping(metadata).unwrap_or_else(Self::wgs84))
+    }
+
+    /// The CRS described by the grid mapping variable of the data variables
+    fn from_grid_mapping(metadata: &Metadata) -> Option<Self> {
+        // Every data variable names the same grid mapping in practice; the
+        // first in name order is taken so the choice is stable
+        let mut names: Vec<&String> = metadata.variables.keys().collect();
+        names.sort();
+        let name = names.into_iter().find_map(|name| {
+            match metadata.variables[name].attributes.get("grid_mapping") {
+                Some(AttributeValue::Text(value)) => grid_mapping_variable(value),
+                _ => None,
+            }
+        })?;
+        let attributes = &metadata.variables.get(&name)?.attributes;
+
+        let text = |attribute: &str| match attributes.get(attribute) {
+            Some(AttributeValue::Text(value)) if !value.trim().is_empty() => {
+                Some(value.trim().to_string())
+            }
+            _ => None,
+        };
+        let wkt = WKT_ATTRIBUTES.iter().find_map(|attribute| text(attribute));
+        let grid_mapping_name = text("grid_mapping_name");
+        let epsg_code = match attributes.get("epsg_code") {
+            Some(AttributeValue::Text(code)) => Some(epsg_identifier(code)),
+            Some(value) => value.as_f64().map(|code| format!("EPSG:{}", code as i64)),
+            None => None,
+        };
+        let identifier = epsg_code
+            .or_else(|| wkt.as_deref().and_then(wkt_identifier))
+            .or_else(|| match (&wkt, grid_mapping_name.as_deref()) {
+                (None, Some("latitude_longitude")) => Some(WGS84.to_string()),
+                _ => None,
+            });
+        Some(Self {
+            identifier,
+            wkt,
+            grid_mapping: Some(name),
+            grid_mapping_name,
+            source: CrsSource::GridMapping,
+        })
+    }
+
+    /// Whether the CRS is WGS 84 latitude/longitude
+    pub fn is_wgs84(&self) -> bool {
+        self.identifier.as_deref() == Some(WGS84)
+    }
+
+    /// Whether the CRS is geographic (latitude/longitude)
+    ///
+    /// Judged from the WKT description where given; an identifier alone is
+    /// taken to name a geographic CRS, as the grid has latitude/longitude
+    /// coordinates.
+    pub fn is_geographic(&self) -> bool {
+        match &self.wkt {
+            Some(wkt) => GEOGRAPHIC_WKT_KEYWORDS
+                .iter()
+                .any(|keyword| wkt.trim_start().starts_with(keyword)),
+            None => self.identifier.is_some(),
+        }
+    }
+
+    /// Identifier of the CRS of an image rendered with a latitude scaling
+    ///
+    /// Images are rendered on the latitude/longitude grid of the dataset, so
+    /// plate carrée images share its CRS, while Mercator and equal-area
+    /// images of WGS 84 data are in those projections. Pole-corrected images
+    /// are in no standard CRS (None).
+    pub fn rendered(&self, scaling: LatitudeScaling) -> Option<&str> {
+        match scaling {
+            LatitudeScaling::PlateCarree => self.identifier.as_deref(),
+            LatitudeScaling::Mercator if self.is_wgs84() => Some(WEB_MERCATOR),
+            LatitudeScaling::EqualArea if self.is_wgs84() => Some(CYLINDRICAL_EQUAL_AREA),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the grid mapping variable in a `grid_mapping` attribute
+///
+/// The attribute is either a variable name or, in the extended CF form,
+/// `name: coordinates [name: coordinates ...]`, of which the first is taken.
+fn grid_mapping_variable(value: &str) -> Option<String> {
+    let name = value.split_whitespace().next()?.trim_end_matches(':');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// `EPSG:<code>` identifier from an `epsg_code` attribute value
+fn epsg_identifier(code: &str) -> String {
+    let code = code.trim();
+    match code.split_once(':') {
+        Some((authority, code)) => format!("{}:{}", authority.trim().to_uppercase(), code.trim()),
+        None => format!("EPSG:{}", code),
+    }
+}
+
+/// Authority identifier of a CRS described in WKT
+///
+/// Takes the `AUTHORITY["EPSG","4326"]` (WKT 1) or `ID["EPSG",4326]` (WKT 2)
+/// element of the outermost CRS; those of nested datums, ellipsoids and
+/// units are ignored.
+pub fn wkt_identifier(wkt: &str) -> Option<String> {
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut keyword_start = 0;
+    for (position, c) in wkt.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '[' | '(' => {
+                let keyword = wkt[keyword_start..position].trim();
+                if depth == 1 && (keyword == "AUTHORITY" || keyword == "ID") {
+                    return authority_element(&wkt[position + 1..]);
+                }
+                depth += 1;
+                keyword_start = position + 1;
+            }
+            ']' | ')' => {
+                depth = depth.saturating_sub(1);
+                keyword_start = position + 1;
+            }
+            ',' => keyword_start = position + 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `AUTHORITY:CODE` from the contents of an authority element
+fn authority_element(contents: &str) -> Option<String> {
+    let end = contents.find([']', ')'])?;
+    let mut parts = contents[..end]
+        .split(',')
+        .map(|part| part.trim().trim_matches('"').trim());
+    let authority = parts.next().filter(|part| !part.is_empty())?;
+    let code = parts.next().filter(|part| !part.is_empty())?;
+    Some(format!("{}:{}", authority.to_uppercase(), code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Variable;
+    use std::collections::HashMap;
+
+    const WGS84_WKT: &str = r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433],AUTHORITY["EPSG","4326"]]"#;
+
+    fn metadata(mapping: HashMap<String, AttributeValue>) -> Metadata {
+        let variable = |name: &str, attributes| Variable {
+            name: name.to_string(),
+            dimensions: vec![],
+            shape: vec![],
+            attributes,
+            dtype: "f32".to_string(),
+        };
+        let t2m = HashMap::from([(
+            "grid_mapping".to_string(),
+            AttributeValue::Text("crs: lat lon".to_string()),
+        )]);
+        Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::new(),
+            variables: HashMap::from([
+                ("t2m".to_string(), variable("t2m", t2m)),
+                ("crs".to_string(), variable("crs", mapping)),
+            ]),
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_wkt_identifier() {
+        assert_eq!(wkt_identifier(WGS84_WKT).as_deref(), Some("EPSG:4326"));
+        let wkt2 = r#"PROJCRS["WGS 84 / UTM zone 33N",BASEGEOGCRS["WGS 84",DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,298.257223563]],ID["EPSG",4326]],CONVERSION["UTM zone 33N",METHOD["Transverse Mercator"]],ID["EPSG",32633]]"#;
+        assert_eq!(wkt_identifier(wkt2).as_deref(), Some("EPSG:32633"));
+        assert_eq!(wkt_identifier(r#"GEOGCS["unknown",DATUM["x"]]"#), None);
+    }
+
+    #[test]
+    fn test_detect_crs() {
+        // From the WKT of the grid mapping variable
+        let wkt = HashMap::from([(
+            "spatial_ref".to_string(),
+            AttributeValue::Text(WGS84_WKT.to_string()),
+        )]);
+        let crs = Crs::detect(None, &metadata(wkt)).unwrap();
+        assert_eq!(crs.identifier.as_deref(), Some("EPSG:4326"));
+        assert_eq!(crs.grid_mapping.as_deref(), Some("crs"));
+        assert_eq!(crs.source, CrsSource::GridMapping);
+
+        // From the EPSG code, or a latitude_longitude grid mapping
+        let code = HashMap::from([("epsg_code".to_string(), AttributeValue::Integer(3035))]);
+        let crs = Crs::detect(None, &metadata(code)).unwrap();
+        assert_eq!(crs.identifier.as_deref(), Some("EPSG:3035"));
+        let lat_lon = HashMap::from([(
+            "grid_mapping_name".to_string(),
+            AttributeValue::Text("latitude_longitude".to_string()),
+        )]);
+        assert!(Crs::detect(None, &metadata(lat_lon)).unwrap().is_wgs84());
+
+        // The configured CRS takes precedence
+        let crs = Crs::detect(Some("epsg:4258"), &metadata(HashMap::new())).unwrap();
+        assert_eq!(crs.identifier.as_deref(), Some("EPSG:4258"));
+        assert_eq!(crs.source, CrsSource::Config);
+        assert!(Crs::parse("4326").is_err());
+
+        // Without a grid mapping, WGS 84 is assumed
+        let mut plain = metadata(HashMap::new());
+        plain.variables.remove("crs");
+        let crs = Crs::detect(None, &plain).unwrap();
+        assert_eq!(crs, Crs::wgs84());
+    }
+
+    #[test]
+    fn test_rendered_crs() {
+        let crs = Crs::wgs84();
+        assert_eq!(crs.rendered(LatitudeScaling::PlateCarree), Some(WGS84));
+        assert_eq!(crs.rendered(LatitudeScaling::Mercator), Some(WEB_MERCATOR));
+        assert_eq!(crs.rendered(LatitudeScaling::PoleCorrected), None);
+
+        let etrs89 = Crs::parse("EPSG:4258").unwrap();
+        assert_eq!(
+            etrs89.rendered(LatitudeScaling::PlateCarree),
+            Some("EPSG:4258")
+        );
+        assert_eq!(etrs89.rendered(LatitudeScaling::Mercator), None);
+
+        // Geographic unless the WKT describes a projection
+        assert!(crs.is_geographic() && etrs89.is_geographic());
+        assert!(Crs::parse(WGS84_WKT).unwrap().is_geographic());
+        let laea =
+            Crs::parse(r#"PROJCS["ETRS89 / LAEA Europe",AUTHORITY["EPSG","3035"]]"#).unwrap();
+        assert!(!laea.is_geographic());
+    }
+}
@@ -2,37 +2,127 @@
 //!
 //! This module handles reading NetCDF files and loading them into memory.
 //! It converts NetCDF variables and metadata into a format that can be efficiently
-//! accessed by the application.
+//! accessed by the application. Container formats are abstracted by the
+//! [`DataSource`] trait, with implementations for NetCDF and plain HDF5 files
+//! and kerchunk reference files (see [`crate::kerchunk`]) selected by
+//! sniffing the file signature.
 
 use ndarray::{Array, Dim, IxDyn};
 use netcdf::{self, Attribute, Variable as NetCDFVariable};
 use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::sync::Arc;
 use tracing::{debug, info, warn};
 
-use crate::config::Config;
+use crate::attribute_text::sanitize_text;
+use crate::climatology::ClimatologyStore;
+use crate::config::{Config, DataConfig};
+use crate::coordinate_fixes::apply_coordinate_fixes;
 use crate::error::{Result, RossbyError};
-use crate::state::{AppState, AttributeValue, Dimension, Metadata, Variable};
+use crate::kerchunk::KerchunkSource;
+use crate::lazy::{LazyVariable, SlabCache, StorageMode};
+use crate::memory_budget::{apply_budget, MemoryBudget};
+use crate::state::{AppState, AttributeValue, Dimension, Group, Metadata, Variable};
+use crate::time_axis::normalize_time_axes;
+use crate::views::ViewStore;
 
 /// Type alias for the NetCDF loading result to simplify the complex return type
 pub type LoadResult = Result<(Metadata, HashMap<String, Array<f32, IxDyn>>)>;
 
+/// Metadata, coordinate variables and variables read on demand of a file
+/// opened with lazy storage
+pub type LazyLoad = (
+    Metadata,
+    HashMap<String, Array<f32, IxDyn>>,
+    HashMap<String, LazyVariable>,
+);
+
 /// Load a NetCDF file into memory and create the application state
+///
+/// With `data.storage: "lazy"`, only the metadata and coordinate variables
+/// are read into memory (see [`crate::lazy`]).
 pub fn load_netcdf(path: &Path, config: Config) -> Result<AppState> {
-    // Load the NetCDF data and metadata
-    let (metadata, data) = load_netcdf_file(path)?;
+    let (mut metadata, mut data, lazy) = match StorageMode::parse(&config.data.storage)? {
+        StorageMode::Memory => {
+            // Load the NetCDF data and metadata, within the memory budget if any
+            let budget = MemoryBudget::from_config(&config.data)?;
+            let (metadata, data) = load_netcdf_file(path, budget.as_ref())?;
+            (metadata, data, HashMap::new())
+        }
+        StorageMode::Lazy => load_netcdf_lazy(path, &config.data)?,
+    };
 
     // Validate the loaded data
     validate_netcdf_data(&metadata, &data)?;
 
+    // Rename dimensions and fix broken coordinates as configured
+    let coordinate_changes = apply_coordinate_fixes(&mut metadata, &mut data, &config.data)?;
+
+    // Sort and deduplicate irregular time axes
+    let time_normalization = normalize_time_axes(&mut metadata, &mut data, &config.data)?;
+    // Values read on demand are served in the order of the file
+    if let Some(normalization) = time_normalization.first().filter(|_| !lazy.is_empty()) {
+        return Err(RossbyError::Config {
+            message: format!(
+                "Lazy storage cannot serve time axis {}, which is not increasing in the file",
+                normalization.dimension
+            ),
+        });
+    }
+
     // Create the application state
-    let app_state = AppState::new(config, metadata, data);
+    let mut app_state = AppState::new(config, metadata, data);
+    app_state.lazy = lazy;
+    app_state.coordinate_changes = coordinate_changes;
+    app_state.time_normalization = time_normalization;
+    app_state.pack_variables();
+
+    // Read the normals of the climatology file, if any
+    if let Some(climatology_path) = app_state.config.data.climatology_file.clone() {
+        let (metadata, data) = source_for(&climatology_path)?.load(&climatology_path, None)?;
+        app_state.climatology = ClimatologyStore::from_file(&app_state, &metadata, data)?;
+        info!(
+            variables = app_state.climatology.supplied_len(),
+            "Loaded climatology file: {}",
+            climatology_path.display()
+        );
+    }
+
+    // Build the configured views from the loaded data
+    app_state.views = ViewStore::build(&app_state)?;
 
     Ok(app_state)
 }
 
-/// Load a NetCDF file into memory, returning metadata and data
-fn load_netcdf_file(path: &Path) -> LoadResult {
+/// Load a NetCDF or HDF5 file into memory, returning metadata and data
+///
+/// The container format is detected from the file signature (see
+/// [`source_for`]). With a `budget`, the metadata is checked against it
+/// before any data is read.
+fn load_netcdf_file(path: &Path, budget: Option<&MemoryBudget>) -> LoadResult {
+    let source = detect_source(path)?;
+    source.load(path, budget)
+}
+
+/// Open a NetCDF or HDF5 file for reading its variables on demand
+///
+/// Fails for formats that cannot read part of a variable.
+fn load_netcdf_lazy(path: &Path, config: &DataConfig) -> Result<LazyLoad> {
+    let source = detect_source(path)?;
+    let cache = Arc::new(SlabCache::new(config.lazy_cache_bytes));
+    source
+        .load_lazy(path, cache)?
+        .ok_or_else(|| RossbyError::Config {
+            message: format!(
+                "Lazy storage cannot read {} files; use storage \"memory\"",
+                source.format_name()
+            ),
+        })
+}
+
+/// The data source of an existing file
+fn detect_source(path: &Path) -> Result<Box<dyn DataSource>> {
     // Check if the file exists
     if !path.exists() {
         return Err(RossbyError::Io(std::io::Error::new(
@@ -41,39 +131,324 @@ fn load_netcdf_file(path: &Path) -> LoadResult {
         )));
     }
 
-    // Open the NetCDF file
-    let file = match netcdf::open(path) {
-        Ok(f) => f,
-        Err(e) => {
-            return Err(RossbyError::NetCdf {
-                message: format!("Failed to open NetCDF file: {}", e),
-            });
+    let source = source_for(path)?;
+    debug!(
+        format = source.format_name(),
+        "Detected container format of {}",
+        path.display()
+    );
+    Ok(source)
+}
+
+/// A container format that can be read into the in-memory data model
+///
+/// Implementations produce the same [`Metadata`] and data arrays whatever the
+/// format, so everything downstream of the loader is format-agnostic.
+pub trait DataSource {
+    /// Human-readable name of the format, for logs and errors
+    fn format_name(&self) -> &'static str;
+
+    /// Read the metadata and data arrays of a file
+    ///
+    /// With a `budget`, the memory the data needs is estimated from the
+    /// metadata first (see [`apply_budget`]), and variables it leaves out
+    /// are not read.
+    fn load(&self, path: &Path, budget: Option<&MemoryBudget>) -> LoadResult;
+
+    /// Read the metadata and coordinate variables of a file, opening its
+    /// other variables for reading on demand (see [`crate::lazy`])
+    ///
+    /// Returns `None` for formats that cannot read part of a variable.
+    fn load_lazy(&self, _path: &Path, _cache: Arc<SlabCache>) -> Result<Option<LazyLoad>> {
+        Ok(None)
+    }
+
+    /// Read single values of a variable, converted as when loading
+    ///
+    /// Used to compare the served data with the file (see
+    /// [`crate::integrity`]). Returns `None` for formats that cannot read
+    /// values one at a time.
+    fn read_values(
+        &self,
+        _path: &Path,
+        _name: &str,
+        _indices: &[Vec<usize>],
+    ) -> Result<Option<Vec<f32>>> {
+        Ok(None)
+    }
+}
+
+/// Container formats recognized by their file signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// NetCDF classic, 64-bit offset or CDF-5
+    NetCdfClassic,
+    /// HDF5, which includes NetCDF-4 files
+    Hdf5,
+    /// Kerchunk JSON reference file
+    Kerchunk,
+}
+
+/// Signature at the start of an HDF5 superblock
+const HDF5_SIGNATURE: [u8; 8] = *b"\x89HDF\r\n\x1a\n";
+
+impl FileFormat {
+    /// Detect the format of a file from its signature
+    ///
+    /// Returns `None` for unrecognized signatures.
+    pub fn sniff(path: &Path) -> Result<Option<Self>> {
+        let mut file = std::fs::File::open(path)?;
+        Ok(Self::sniff_reader(&mut file)?)
+    }
+
+    /// Detect the format of a seekable byte stream from its signature
+    ///
+    /// The HDF5 superblock may follow a user block, so it is looked for at
+    /// offset 0 and at every power of two from 512 bytes. Binary formats have
+    /// no signature starting with `{`, so a JSON object is taken for a
+    /// kerchunk reference file.
+    pub fn sniff_reader<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let length = reader.seek(SeekFrom::End(0))?;
+
+        let mut head = [0u8; 64];
+        reader.seek(SeekFrom::Start(0))?;
+        let read = reader.read(&mut head)?;
+        if head[..read].iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+            return Ok(Some(FileFormat::Kerchunk));
         }
-    };
 
-    info!("Opened NetCDF file: {}", path.display());
-    let variables_count = file.variables().count();
-    let dimensions_count = file.dimensions().count();
-    debug!("File has {} variables", variables_count);
-    debug!("File has {} dimensions", dimensions_count);
+        let mut signature = [0u8; 8];
+        let mut offset = 0u64;
+        while offset + 8 <= length {
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read_exact(&mut signature)?;
+            if offset == 0 && signature.starts_with(b"CDF") && matches!(signature[3], 1 | 2 | 5) {
+                return Ok(Some(FileFormat::NetCdfClassic));
+            }
+            if signature == HDF5_SIGNATURE {
+                return Ok(Some(FileFormat::Hdf5));
+            }
+            offset = if offset == 0 { 512 } else { offset * 2 };
+        }
+
+        Ok(None)
+    }
+}
+
+/// Choose the data source for a file by sniffing its signature
+///
+/// Files with unrecognized signatures are handed to the NetCDF reader, which
+/// reports its own error if it cannot open them either.
+pub fn source_for(path: &Path) -> Result<Box<dyn DataSource>> {
+    Ok(match FileFormat::sniff(path)? {
+        Some(FileFormat::Hdf5) => Box::new(Hdf5Source),
+        Some(FileFormat::Kerchunk) => Box::new(KerchunkSource),
+        Some(FileFormat::NetCdfClassic) | None => Box::new(NetCdfSource),
+    })
+}
+
+/// NetCDF files, classic and NetCDF-4
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetCdfSource;
+
+impl DataSource for NetCdfSource {
+    fn format_name(&self) -> &'static str {
+        "NetCDF"
+    }
+
+    fn load(&self, path: &Path, budget: Option<&MemoryBudget>) -> LoadResult {
+        let file = open_file(path, self.format_name())?;
+        let mut metadata = extract_metadata(&file)?;
+        apply_budget(budget, &mut metadata)?;
+        let data = extract_data(&file, &metadata)?;
+        Ok((metadata, data))
+    }
+
+    fn load_lazy(&self, path: &Path, cache: Arc<SlabCache>) -> Result<Option<LazyLoad>> {
+        let file = open_file(path, self.format_name())?;
+        let metadata = extract_metadata(&file)?;
+        open_lazy(&file, path, metadata, cache).map(Some)
+    }
+
+    fn read_values(
+        &self,
+        path: &Path,
+        name: &str,
+        indices: &[Vec<usize>],
+    ) -> Result<Option<Vec<f32>>> {
+        read_netcdf_values(path, self.format_name(), name, indices).map(Some)
+    }
+}
+
+/// HDF5 files
+///
+/// NetCDF-4 is a profile of HDF5, and the NetCDF library reads plain HDF5
+/// files through its HDF5 layer. Datasets without HDF5 dimension scales get
+/// anonymous `phony_dim_<n>` dimensions; where a phony dimension is the only
+/// dimension of exactly one 1D dataset in its group, it is named after that
+/// dataset, which then serves as its coordinate variable.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hdf5Source;
+
+impl DataSource for Hdf5Source {
+    fn format_name(&self) -> &'static str {
+        "HDF5"
+    }
+
+    fn load(&self, path: &Path, budget: Option<&MemoryBudget>) -> LoadResult {
+        let file = open_file(path, self.format_name())?;
+        let mut metadata = extract_hdf5_metadata(&file)?;
+        apply_budget(budget, &mut metadata)?;
+
+        let data = extract_data(&file, &metadata)?;
+        Ok((metadata, data))
+    }
+
+    fn load_lazy(&self, path: &Path, cache: Arc<SlabCache>) -> Result<Option<LazyLoad>> {
+        let file = open_file(path, self.format_name())?;
+        let metadata = extract_hdf5_metadata(&file)?;
+        open_lazy(&file, path, metadata, cache).map(Some)
+    }
+
+    fn read_values(
+        &self,
+        path: &Path,
+        name: &str,
+        indices: &[Vec<usize>],
+    ) -> Result<Option<Vec<f32>>> {
+        read_netcdf_values(path, self.format_name(), name, indices).map(Some)
+    }
+}
+
+/// Extract the metadata of an HDF5 file, naming its phony dimensions
+fn extract_hdf5_metadata(file: &netcdf::File) -> Result<Metadata> {
+    let mut metadata = extract_metadata(file)?;
+    for (phony, name) in phony_dimension_names(&metadata) {
+        rename_dimension(&mut metadata, &phony, &name);
+        if let Some(var) = file.variable(&name) {
+            let values = extract_coordinate_values(&var)?;
+            metadata.coordinates.insert(name.clone(), values);
+        }
+        debug!(dimension = %phony, coordinate = %name, "Named phony HDF5 dimension");
+    }
+    Ok(metadata)
+}
+
+/// Read the coordinate variables of an open file and open the others for
+/// reading on demand
+fn open_lazy(
+    file: &netcdf::File,
+    path: &Path,
+    metadata: Metadata,
+    cache: Arc<SlabCache>,
+) -> Result<LazyLoad> {
+    let mut data = HashMap::new();
+    let mut lazy = HashMap::new();
+    for (var_name, var_meta) in &metadata.variables {
+        let Some(var) = file.variable(var_name) else {
+            continue;
+        };
+        if !is_supported_variable(&var) {
+            continue;
+        }
+        if metadata.coordinates.contains_key(var_name) {
+            let array = convert_variable_to_array(&var, &var_meta.shape)?;
+            data.insert(var_name.clone(), array);
+        } else {
+            let var = LazyVariable::new(path, var_name, var_meta.shape.clone(), cache.clone());
+            lazy.insert(var_name.clone(), var);
+        }
+    }
+    info!(
+        variables = lazy.len(),
+        "Opened variables for reading on demand"
+    );
+    Ok((metadata, data, lazy))
+}
+
+/// Open a file with the NetCDF library
+fn open_file(path: &Path, format_name: &str) -> Result<netcdf::File> {
+    let file = netcdf::open(path).map_err(|e| RossbyError::NetCdf {
+        message: format!("Failed to open {} file: {}", format_name, e),
+    })?;
+
+    info!("Opened {} file: {}", format_name, path.display());
+    debug!("File has {} variables", file.variables().count());
+    debug!("File has {} dimensions", file.dimensions().count());
 
-    // Extract file metadata
-    let metadata = extract_metadata(&file)?;
+    Ok(file)
+}
 
-    // Extract data from variables
-    let data = extract_data(&file, &metadata)?;
+/// Prefix of the dimensions the NetCDF library invents for HDF5 datasets
+const PHONY_DIM_PREFIX: &str = "phony_dim_";
+
+/// Split a namespaced name into its group path and base name
+fn split_namespaced(name: &str) -> (&str, &str) {
+    name.rsplit_once('/').unwrap_or(("", name))
+}
 
-    Ok((metadata, data))
+/// Names for phony dimensions, as (phony name, new name) pairs
+///
+/// A phony dimension is named after the 1D variable of its group that uses
+/// it, when there is exactly one and its name is not already a dimension.
+fn phony_dimension_names(metadata: &Metadata) -> Vec<(String, String)> {
+    let mut names: Vec<(String, String)> = metadata
+        .dimensions
+        .keys()
+        .filter(|dim| split_namespaced(dim).1.starts_with(PHONY_DIM_PREFIX))
+        .filter_map(|dim| {
+            let group = split_namespaced(dim).0;
+            let mut candidates = metadata.variables.values().filter(|var| {
+                var.dimensions.len() == 1
+                    && var.dimensions[0] == *dim
+                    && split_namespaced(&var.name).0 == group
+                    && !metadata.dimensions.contains_key(&var.name)
+            });
+            match (candidates.next(), candidates.next()) {
+                (Some(var), None) => Some((dim.clone(), var.name.clone())),
+                _ => None,
+            }
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Rename a dimension throughout the metadata
+pub(crate) fn rename_dimension(metadata: &mut Metadata, old: &str, new: &str) {
+    if let Some(mut dimension) = metadata.dimensions.remove(old) {
+        dimension.name = new.to_string();
+        metadata.dimensions.insert(new.to_string(), dimension);
+    }
+    if let Some(values) = metadata.coordinates.remove(old) {
+        metadata.coordinates.insert(new.to_string(), values);
+    }
+
+    let dimension_lists = metadata
+        .variables
+        .values_mut()
+        .map(|var| &mut var.dimensions)
+        .chain(
+            metadata
+                .groups
+                .values_mut()
+                .map(|group| &mut group.dimensions),
+        );
+    for dimensions in dimension_lists {
+        for dim in dimensions.iter_mut().filter(|dim| *dim == old) {
+            *dim = new.to_string();
+        }
+    }
 }
 
 /// Extract metadata from the NetCDF file
+///
+/// Variables and dimensions in NetCDF-4 groups are namespaced by their group
+/// path (e.g. `forecast/surface/t2m`), so the whole hierarchy shares one flat
+/// namespace with the root group.
 fn extract_metadata(file: &netcdf::File) -> Result<Metadata> {
     // Extract global attributes
-    let mut global_attributes = HashMap::new();
-    for attr in file.attributes() {
-        let value = convert_attribute(&attr)?;
-        global_attributes.insert(attr.name().to_string(), value);
-    }
+    let global_attributes = convert_attributes(file.attributes(), "global");
 
     // Extract dimensions
     let mut dimensions = HashMap::new();
@@ -86,54 +461,30 @@ fn extract_metadata(file: &netcdf::File) -> Result<Metadata> {
         dimensions.insert(dim.name().to_string(), dimension);
     }
 
+    // Root dimensions are visible under their own names in every group
+    let root_scope: HashMap<String, String> = dimensions
+        .keys()
+        .map(|name| (name.clone(), name.clone()))
+        .collect();
+
     // Extract variables and their metadata
     let mut variables = HashMap::new();
     let mut coordinates = HashMap::new();
 
     for var in file.variables() {
-        // Skip variables we can't handle (non-numeric types)
-        if !is_supported_variable(&var) {
-            warn!("Skipping unsupported variable: {}", var.name());
-            continue;
-        }
-
-        // Extract variable dimensions
-        let var_dims: Vec<String> = var
-            .dimensions()
-            .iter()
-            .map(|dim| dim.name().to_string())
-            .collect();
-
-        // Extract variable shape
-        let var_shape: Vec<usize> = var_dims
-            .iter()
-            .map(|name| file.dimension(name).unwrap().len())
-            .collect();
-
-        // Extract variable attributes
-        let mut var_attrs = HashMap::new();
-        for attr in var.attributes() {
-            let value = convert_attribute(&attr)?;
-            var_attrs.insert(attr.name().to_string(), value);
-        }
-
-        // Create variable metadata
-        let variable = Variable {
-            name: var.name().to_string(),
-            dimensions: var_dims,
-            shape: var_shape,
-            attributes: var_attrs,
-            dtype: format!("{:?}", var.vartype()),
-        };
-
-        variables.insert(var.name().to_string(), variable);
+        add_variable(&var, "", &root_scope, &mut variables, &mut coordinates)?;
+    }
 
-        // If this is a coordinate variable (name matches a dimension),
-        // extract the coordinate values
-        if file.dimension(&var.name()).is_some() {
-            let coord_values = extract_coordinate_values(&var)?;
-            coordinates.insert(var.name().to_string(), coord_values);
-        }
+    // Walk the group hierarchy
+    let mut groups = HashMap::new();
+    let mut tree = GroupTree {
+        dimensions: &mut dimensions,
+        variables: &mut variables,
+        coordinates: &mut coordinates,
+        groups: &mut groups,
+    };
+    for group in file.groups()? {
+        extract_group(&group, "", &root_scope, &mut tree)?;
     }
 
     // Check for missing coordinate variables and create them if needed
@@ -153,9 +504,148 @@ fn extract_metadata(file: &netcdf::File) -> Result<Metadata> {
         dimensions,
         variables,
         coordinates,
+        groups,
     })
 }
 
+/// Metadata collections filled while walking the group hierarchy
+struct GroupTree<'a> {
+    dimensions: &'a mut HashMap<String, Dimension>,
+    variables: &'a mut HashMap<String, Variable>,
+    coordinates: &'a mut HashMap<String, Vec<f64>>,
+    groups: &'a mut HashMap<String, Group>,
+}
+
+/// Join a group path and a member name
+fn namespaced(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
+
+/// Extract the metadata of a group and, recursively, its subgroups
+///
+/// `scope` maps the dimension names visible from the parent group to their
+/// namespaced names. Dimensions defined in this group shadow inherited ones,
+/// as in NetCDF-4.
+fn extract_group(
+    group: &netcdf::Group,
+    parent_path: &str,
+    scope: &HashMap<String, String>,
+    tree: &mut GroupTree,
+) -> Result<()> {
+    let path = namespaced(parent_path, &group.name());
+    let mut scope = scope.clone();
+
+    let mut group_dimensions = Vec::new();
+    for dim in group.dimensions() {
+        let name = namespaced(&path, &dim.name());
+        tree.dimensions.insert(
+            name.clone(),
+            Dimension {
+                name: name.clone(),
+                size: dim.len(),
+                is_unlimited: dim.is_unlimited(),
+            },
+        );
+        scope.insert(dim.name().to_string(), name.clone());
+        group_dimensions.push(name);
+    }
+
+    let mut group_variables = Vec::new();
+    for var in group.variables() {
+        if let Some(name) = add_variable(&var, &path, &scope, tree.variables, tree.coordinates)? {
+            group_variables.push(name);
+        }
+    }
+
+    let attributes = convert_attributes(group.attributes(), &path);
+
+    let mut subgroups = Vec::new();
+    for subgroup in group.groups() {
+        extract_group(&subgroup, &path, &scope, tree)?;
+        subgroups.push(namespaced(&path, &subgroup.name()));
+    }
+
+    debug!(
+        group = %path,
+        dimensions = group_dimensions.len(),
+        variables = group_variables.len(),
+        "Loaded NetCDF group"
+    );
+
+    tree.groups.insert(
+        path.clone(),
+        Group {
+            path,
+            attributes,
+            dimensions: group_dimensions,
+            variables: group_variables,
+            groups: subgroups,
+        },
+    );
+
+    Ok(())
+}
+
+/// Add the metadata of a variable defined in the group at `path`
+///
+/// Returns the namespaced variable name, or `None` if the variable has an
+/// unsupported type and was skipped.
+fn add_variable(
+    var: &NetCDFVariable,
+    path: &str,
+    scope: &HashMap<String, String>,
+    variables: &mut HashMap<String, Variable>,
+    coordinates: &mut HashMap<String, Vec<f64>>,
+) -> Result<Option<String>> {
+    let name = namespaced(path, &var.name());
+
+    // Skip variables we can't handle (non-numeric types)
+    if !is_supported_variable(var) {
+        warn!("Skipping unsupported variable: {}", name);
+        return Ok(None);
+    }
+
+    // Extract variable dimensions, namespaced by the group defining them
+    let var_dims: Vec<String> = var
+        .dimensions()
+        .iter()
+        .map(|dim| {
+            let dim_name = dim.name().to_string();
+            scope.get(&dim_name).cloned().unwrap_or(dim_name)
+        })
+        .collect();
+
+    // Extract variable shape
+    let var_shape: Vec<usize> = var.dimensions().iter().map(|dim| dim.len()).collect();
+
+    // Extract variable attributes
+    let var_attrs = convert_attributes(var.attributes(), &name);
+
+    // Create variable metadata
+    let variable = Variable {
+        name: name.clone(),
+        dimensions: var_dims,
+        shape: var_shape,
+        attributes: var_attrs,
+        dtype: format!("{:?}", var.vartype()),
+    };
+
+    variables.insert(name.clone(), variable);
+
+    // If this is a coordinate variable (name matches a dimension of the same
+    // group), extract the coordinate values
+    if scope.get(&var.name()) == Some(&name) {
+        let coord_values = extract_coordinate_values(var)?;
+        coordinates.insert(name.clone(), coord_values);
+    }
+
+    Ok(Some(name))
+}
+
 /// Check if a variable has a supported type that we can work with
 fn is_supported_variable(var: &NetCDFVariable) -> bool {
     use netcdf::types::{BasicType, VariableType};
@@ -164,39 +654,90 @@ fn is_supported_variable(var: &NetCDFVariable) -> bool {
         var.vartype(),
         VariableType::Basic(BasicType::Byte)
             | VariableType::Basic(BasicType::Char)
+            | VariableType::Basic(BasicType::Ubyte)
             | VariableType::Basic(BasicType::Short)
+            | VariableType::Basic(BasicType::Ushort)
             | VariableType::Basic(BasicType::Int)
+            | VariableType::Basic(BasicType::Uint)
             | VariableType::Basic(BasicType::Int64)
+            | VariableType::Basic(BasicType::Uint64)
             | VariableType::Basic(BasicType::Float)
             | VariableType::Basic(BasicType::Double)
     )
 }
 
 /// Convert a NetCDF attribute to our AttributeValue enum
+///
+/// Numeric types up to 32 bits are stored as f64, which represents them
+/// exactly. 64-bit integers keep their exact integer value.
+/// Convert the attributes of a file, group or variable
+///
+/// Attributes the NetCDF library cannot read, e.g. those of user-defined
+/// types, are skipped with a warning rather than failing the whole load.
+fn convert_attributes<'a>(
+    attributes: impl Iterator<Item = Attribute<'a>>,
+    owner: &str,
+) -> HashMap<String, AttributeValue> {
+    let mut converted = HashMap::new();
+    for attr in attributes {
+        match convert_attribute(&attr) {
+            Ok(value) => {
+                converted.insert(attr.name().to_string(), value);
+            }
+            Err(error) => warn!(
+                owner = owner,
+                attribute = attr.name(),
+                error = %error,
+                "Skipping unreadable attribute"
+            ),
+        }
+    }
+    converted
+}
+
 fn convert_attribute(attr: &Attribute) -> Result<AttributeValue> {
     use netcdf::AttributeValue as NcAttributeValue;
 
+    fn numbers<T: Into<f64>>(values: Vec<T>) -> AttributeValue {
+        AttributeValue::NumberArray(values.into_iter().map(Into::into).collect())
+    }
+
     // The new API returns an AttributeValue enum directly
     let value = attr.value()?;
 
     match value {
-        // String types
-        NcAttributeValue::Str(s) => Ok(AttributeValue::Text(s)),
+        // String types, already decoded by the library with invalid UTF-8
+        // replaced; control characters are replaced here
+        NcAttributeValue::Str(s) => Ok(AttributeValue::Text(sanitize_text(&s))),
+        NcAttributeValue::Strs(s) => Ok(AttributeValue::TextArray(
+            s.iter().map(|s| sanitize_text(s)).collect(),
+        )),
 
         // Numeric types - store as f64 for simplicity
         NcAttributeValue::Uchar(v) => Ok(AttributeValue::Number(v as f64)),
         NcAttributeValue::Schar(v) => Ok(AttributeValue::Number(v as f64)),
+        NcAttributeValue::Ushort(v) => Ok(AttributeValue::Number(v as f64)),
         NcAttributeValue::Short(v) => Ok(AttributeValue::Number(v as f64)),
+        NcAttributeValue::Uint(v) => Ok(AttributeValue::Number(v as f64)),
         NcAttributeValue::Int(v) => Ok(AttributeValue::Number(v as f64)),
         NcAttributeValue::Float(v) => Ok(AttributeValue::Number(v as f64)),
         NcAttributeValue::Double(v) => Ok(AttributeValue::Number(v)),
 
-        // For array types, the netcdf crate now returns a Vec<T>, but we need to check the API
-        // to see what the exact variants are
-        _ => {
-            // Convert any other types to a text representation for now
-            Ok(AttributeValue::Text(format!("{:?}", value)))
-        }
+        // 64-bit integers would lose precision as f64
+        NcAttributeValue::Longlong(v) => Ok(AttributeValue::Integer(v)),
+        NcAttributeValue::Ulonglong(v) => Ok(AttributeValue::UnsignedInteger(v)),
+
+        // Array types
+        NcAttributeValue::Uchars(v) => Ok(numbers(v)),
+        NcAttributeValue::Schars(v) => Ok(numbers(v)),
+        NcAttributeValue::Ushorts(v) => Ok(numbers(v)),
+        NcAttributeValue::Shorts(v) => Ok(numbers(v)),
+        NcAttributeValue::Uints(v) => Ok(numbers(v)),
+        NcAttributeValue::Ints(v) => Ok(numbers(v)),
+        NcAttributeValue::Floats(v) => Ok(numbers(v)),
+        NcAttributeValue::Doubles(v) => Ok(AttributeValue::NumberArray(v)),
+        NcAttributeValue::Longlongs(v) => Ok(AttributeValue::IntegerArray(v)),
+        NcAttributeValue::Ulonglongs(v) => Ok(AttributeValue::UnsignedIntegerArray(v)),
     }
 }
 
@@ -217,6 +758,13 @@ fn extract_coordinate_values(var: &NetCDFVariable) -> Result<Vec<f64>> {
                 values.push(value as f64);
             }
         }
+        VariableType::Basic(BasicType::Ubyte) => {
+            for i in 0..dim_size {
+                let index = [i];
+                let value: u8 = var.get_value(index)?;
+                values.push(value as f64);
+            }
+        }
         VariableType::Basic(BasicType::Short) => {
             for i in 0..dim_size {
                 let index = [i];
@@ -224,6 +772,13 @@ fn extract_coordinate_values(var: &NetCDFVariable) -> Result<Vec<f64>> {
                 values.push(value as f64);
             }
         }
+        VariableType::Basic(BasicType::Ushort) => {
+            for i in 0..dim_size {
+                let index = [i];
+                let value: u16 = var.get_value(index)?;
+                values.push(value as f64);
+            }
+        }
         VariableType::Basic(BasicType::Int) => {
             for i in 0..dim_size {
                 let index = [i];
@@ -231,6 +786,13 @@ fn extract_coordinate_values(var: &NetCDFVariable) -> Result<Vec<f64>> {
                 values.push(value as f64);
             }
         }
+        VariableType::Basic(BasicType::Uint) => {
+            for i in 0..dim_size {
+                let index = [i];
+                let value: u32 = var.get_value(index)?;
+                values.push(value as f64);
+            }
+        }
         VariableType::Basic(BasicType::Int64) => {
             for i in 0..dim_size {
                 let index = [i];
@@ -238,6 +800,13 @@ fn extract_coordinate_values(var: &NetCDFVariable) -> Result<Vec<f64>> {
                 values.push(value as f64);
             }
         }
+        VariableType::Basic(BasicType::Uint64) => {
+            for i in 0..dim_size {
+                let index = [i];
+                let value: u64 = var.get_value(index)?;
+                values.push(value as f64);
+            }
+        }
         VariableType::Basic(BasicType::Float) => {
             for i in 0..dim_size {
                 let index = [i];
@@ -324,6 +893,17 @@ fn convert_variable_to_array(var: &NetCDFVariable, shape: &[usize]) -> Result<Ar
                 data.push(value as f32);
             }
         }
+        VariableType::Basic(BasicType::Ubyte) => {
+            let mut index_array = [0; 10];
+
+            for i in 0..total_elements {
+                compute_indices(&mut indices, i, shape);
+                index_array[..shape.len()].copy_from_slice(&indices[..shape.len()]);
+
+                let value: u8 = var.get_value(&index_array[..shape.len()])?;
+                data.push(value as f32);
+            }
+        }
         VariableType::Basic(BasicType::Short) => {
             let mut index_array = [0; 10];
 
@@ -335,6 +915,17 @@ fn convert_variable_to_array(var: &NetCDFVariable, shape: &[usize]) -> Result<Ar
                 data.push(value as f32);
             }
         }
+        VariableType::Basic(BasicType::Ushort) => {
+            let mut index_array = [0; 10];
+
+            for i in 0..total_elements {
+                compute_indices(&mut indices, i, shape);
+                index_array[..shape.len()].copy_from_slice(&indices[..shape.len()]);
+
+                let value: u16 = var.get_value(&index_array[..shape.len()])?;
+                data.push(value as f32);
+            }
+        }
         VariableType::Basic(BasicType::Int) => {
             let mut index_array = [0; 10];
 
@@ -346,6 +937,17 @@ fn convert_variable_to_array(var: &NetCDFVariable, shape: &[usize]) -> Result<Ar
                 data.push(value as f32);
             }
         }
+        VariableType::Basic(BasicType::Uint) => {
+            let mut index_array = [0; 10];
+
+            for i in 0..total_elements {
+                compute_indices(&mut indices, i, shape);
+                index_array[..shape.len()].copy_from_slice(&indices[..shape.len()]);
+
+                let value: u32 = var.get_value(&index_array[..shape.len()])?;
+                data.push(value as f32);
+            }
+        }
         VariableType::Basic(BasicType::Int64) => {
             let mut index_array = [0; 10];
 
@@ -357,6 +959,17 @@ fn convert_variable_to_array(var: &NetCDFVariable, shape: &[usize]) -> Result<Ar
                 data.push(value as f32);
             }
         }
+        VariableType::Basic(BasicType::Uint64) => {
+            let mut index_array = [0; 10];
+
+            for i in 0..total_elements {
+                compute_indices(&mut indices, i, shape);
+                index_array[..shape.len()].copy_from_slice(&indices[..shape.len()]);
+
+                let value: u64 = var.get_value(&index_array[..shape.len()])?;
+                data.push(value as f32);
+            }
+        }
         VariableType::Basic(BasicType::Float) => {
             let mut index_array = [0; 10];
 
@@ -391,6 +1004,50 @@ fn convert_variable_to_array(var: &NetCDFVariable, shape: &[usize]) -> Result<Ar
     Ok(array)
 }
 
+/// Read values of a variable at the given indices through the NetCDF library
+fn read_netcdf_values(
+    path: &Path,
+    format_name: &str,
+    name: &str,
+    indices: &[Vec<usize>],
+) -> Result<Vec<f32>> {
+    let file = netcdf::open(path).map_err(|e| RossbyError::NetCdf {
+        message: format!("Failed to open {} file: {}", format_name, e),
+    })?;
+    let var = file
+        .variable(name)
+        .ok_or_else(|| RossbyError::VariableNotFound {
+            name: name.to_string(),
+        })?;
+    indices
+        .iter()
+        .map(|index| read_value(&var, index))
+        .collect()
+}
+
+/// Read one value of a variable, converted to f32 as when loading
+fn read_value(var: &NetCDFVariable, index: &[usize]) -> Result<f32> {
+    use netcdf::types::{BasicType, VariableType};
+
+    Ok(match var.vartype() {
+        VariableType::Basic(BasicType::Byte) => var.get_value::<i8, _>(index)? as f32,
+        VariableType::Basic(BasicType::Ubyte) => var.get_value::<u8, _>(index)? as f32,
+        VariableType::Basic(BasicType::Short) => var.get_value::<i16, _>(index)? as f32,
+        VariableType::Basic(BasicType::Ushort) => var.get_value::<u16, _>(index)? as f32,
+        VariableType::Basic(BasicType::Int) => var.get_value::<i32, _>(index)? as f32,
+        VariableType::Basic(BasicType::Uint) => var.get_value::<u32, _>(index)? as f32,
+        VariableType::Basic(BasicType::Int64) => var.get_value::<i64, _>(index)? as f32,
+        VariableType::Basic(BasicType::Uint64) => var.get_value::<u64, _>(index)? as f32,
+        VariableType::Basic(BasicType::Float) => var.get_value::<f32, _>(index)?,
+        VariableType::Basic(BasicType::Double) => var.get_value::<f64, _>(index)? as f32,
+        _ => {
+            return Err(RossbyError::NetCdf {
+                message: format!("Unsupported variable type: {:?}", var.vartype()),
+            })
+        }
+    })
+}
+
 /// Helper function to convert a flat index to multi-dimensional indices
 fn compute_indices(indices: &mut [usize], flat_index: usize, shape: &[usize]) {
     let mut remaining = flat_index;
@@ -587,7 +1244,7 @@ mod tests {
         println!("Loading real climate data from: {}", file_path.display());
 
         // Load the file
-        let (metadata, data) = load_netcdf_file(file_path)?;
+        let (metadata, data) = load_netcdf_file(file_path, None)?;
 
         // Verify dimensions
         assert!(metadata.dimensions.contains_key("time"));
@@ -717,7 +1374,7 @@ mod tests {
 
     #[test]
     fn test_file_not_found() {
-        let result = load_netcdf_file(Path::new("/nonexistent/file.nc"));
+        let result = load_netcdf_file(Path::new("/nonexistent/file.nc"), None);
         assert!(result.is_err());
         match result.unwrap_err() {
             RossbyError::Io(e) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
@@ -735,7 +1392,7 @@ mod tests {
         create_test_netcdf_file(&file_path)?;
 
         // Load the file
-        let (metadata, data) = load_netcdf_file(&file_path)?;
+        let (metadata, data) = load_netcdf_file(&file_path, None)?;
 
         // Simplified verification based on our new test file structure
         assert!(metadata.global_attributes.contains_key("title"));
@@ -769,6 +1426,200 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_irregular_time_axis() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_irregular_time.nc");
+        {
+            let mut file = netcdf::create(&file_path)?;
+            file.add_dimension("time", 4)?;
+            file.add_dimension("x", 1)?;
+            let mut time = file.add_variable::<f64>("time", &["time"])?;
+            time.put_attribute("units", "hours since 2000-01-01")?;
+            time.put_values(&[0.0, 12.0, 6.0, 12.0], ..)?;
+            let mut t2m = file.add_variable::<f32>("t2m", &["time", "x"])?;
+            t2m.put_values(&[0.0, 1.0, 2.0, 3.0], ..)?;
+        }
+
+        let state = load_netcdf(&file_path, Config::default())?;
+        assert_eq!(state.metadata.coordinates["time"], vec![0.0, 6.0, 12.0]);
+        assert_eq!(state.time_dim_size(), 3);
+        let t2m = state.get_variable_checked("t2m")?;
+        assert_eq!(t2m.iter().copied().collect::<Vec<_>>(), vec![0.0, 2.0, 1.0]);
+        let normalization = &state.time_normalization[0];
+        assert_eq!(normalization.original_indices, vec![0, 2, 1]);
+        assert_eq!(normalization.dropped, vec![3]);
+
+        // Time ranges now select contiguous, increasing steps
+        assert_eq!(state.find_coordinate_index("time", 6.0)?, 1);
+
+        let mut config = Config::default();
+        config.data.duplicate_times = "last".to_string();
+        let state = load_netcdf(&file_path, config)?;
+        let t2m = state.get_variable_checked("t2m")?;
+        assert_eq!(t2m.iter().copied().collect::<Vec<_>>(), vec![0.0, 2.0, 3.0]);
+
+        let mut config = Config::default();
+        config.data.duplicate_times = "error".to_string();
+        assert!(load_netcdf(&file_path, config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_forecast_time_axes() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_forecast.nc");
+        {
+            let mut file = netcdf::create(&file_path)?;
+            file.add_dimension("run", 2)?;
+            file.add_dimension("step", 3)?;
+            file.add_dimension("lat", 1)?;
+            file.add_dimension("lon", 2)?;
+            let mut run = file.add_variable::<f64>("run", &["run"])?;
+            run.put_attribute("standard_name", "forecast_reference_time")?;
+            run.put_attribute("units", "hours since 2024-06-01 00:00")?;
+            run.put_values(&[0.0, 12.0], ..)?;
+            let mut step = file.add_variable::<f64>("step", &["step"])?;
+            step.put_attribute("standard_name", "forecast_period")?;
+            step.put_attribute("units", "minutes")?;
+            step.put_values(&[0.0, 360.0, 720.0], ..)?;
+            file.add_variable::<f64>("lat", &["lat"])?
+                .put_values(&[45.0], ..)?;
+            file.add_variable::<f64>("lon", &["lon"])?
+                .put_values(&[0.0, 10.0], ..)?;
+            let mut t2m = file.add_variable::<f32>("t2m", &["run", "step", "lat", "lon"])?;
+            t2m.put_values(&(0..12).map(|i| i as f32).collect::<Vec<_>>(), ..)?;
+        }
+
+        let state = load_netcdf(&file_path, Config::default())?;
+        let axes = crate::forecast_time::ForecastAxes::detect(&state).unwrap();
+        assert_eq!(
+            (axes.reference.as_str(), axes.lead.as_str()),
+            ("run", "step")
+        );
+        assert_eq!(axes.valid_times(&state)[1], vec![12.0, 18.0, 24.0]);
+
+        // Valid at 12:00 in both runs; the later run is used
+        let params = HashMap::from([("valid_time".to_string(), "12".to_string())]);
+        let indices = crate::field::resolve_dimension_indices(&state, "t2m", &params)?;
+        assert_eq!((indices["run"], indices["step"]), (1, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_packed() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_packed.nc");
+        {
+            let mut file = netcdf::create(&file_path)?;
+            file.add_dimension("time", 2)?;
+            file.add_dimension("lat", 2)?;
+            file.add_dimension("lon", 3)?;
+            for (name, values) in [("time", vec![0.0, 6.0]), ("lat", vec![10.0, 20.0])] {
+                file.add_variable::<f64>(name, &[name])?
+                    .put_values(&values, ..)?;
+            }
+            file.add_variable::<f64>("lon", &["lon"])?
+                .put_values(&[100.0, 110.0, 120.0], ..)?;
+            let mut t2m = file.add_variable::<i16>("t2m", &["time", "lat", "lon"])?;
+            t2m.put_attribute("scale_factor", 0.01)?;
+            t2m.put_attribute("add_offset", 273.15)?;
+            t2m.put_values(&(0..12).map(|i| i * 100 - 500).collect::<Vec<i16>>(), ..)?;
+        }
+
+        let unpacked = load_netcdf(&file_path, Config::default())?;
+        let mut config = Config::default();
+        config.data.keep_packed = true;
+        let packed = load_netcdf(&file_path, config)?;
+        assert!(!packed.data.contains_key("t2m"));
+        assert_eq!(packed.packed["t2m"].len(), 12);
+        assert_eq!(
+            packed.get_variable_values_checked("t2m")?.memory_bytes(),
+            unpacked.get_variable_values_checked("t2m")?.memory_bytes() / 2
+        );
+
+        // Values read either way are the same
+        assert_eq!(
+            packed.get_variable_checked("t2m")?,
+            unpacked.get_variable_checked("t2m")?
+        );
+        let dims = HashMap::from([("time".to_string(), 1)]);
+        assert_eq!(
+            packed.get_data_slice_with_dims("t2m", 100.0, 10.0, 120.0, 20.0, &dims)?,
+            unpacked.get_data_slice_with_dims("t2m", 100.0, 10.0, 120.0, 20.0, &dims)?
+        );
+        assert_eq!(packed.fingerprint(), unpacked.fingerprint());
+        assert!(matches!(
+            packed.get_variable_indexed("t2m", &[(0, 2)]),
+            Err(RossbyError::IndexOutOfBounds { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_storage() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_lazy.nc");
+        create_test_netcdf_file(&file_path)?;
+
+        let loaded = load_netcdf(&file_path, Config::default())?;
+        let mut config = Config::default();
+        config.data.storage = "lazy".to_string();
+        let lazy = load_netcdf(&file_path, config)?;
+        assert!(!lazy.data.contains_key("temperature"));
+        assert!(lazy.lazy.contains_key("temperature"));
+        assert!(lazy.data.contains_key("lat"));
+        assert_eq!(
+            lazy.get_variable_values_checked("temperature")?
+                .memory_bytes(),
+            0
+        );
+
+        // Values read on demand are those read at startup
+        assert_eq!(
+            lazy.get_variable_checked("temperature")?,
+            loaded.get_variable_checked("temperature")?
+        );
+        assert_eq!(
+            lazy.get_variable_indexed("temperature", &[(0, 1)])?,
+            loaded.get_variable_indexed("temperature", &[(0, 1)])?
+        );
+        assert_eq!(
+            lazy.get_variable_indexed("temperature", &[(1, 0), (2, 1)])?,
+            loaded.get_variable_indexed("temperature", &[(1, 0), (2, 1)])?
+        );
+        assert_eq!(lazy.fingerprint(), loaded.fingerprint());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_budget() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_budget.nc");
+        create_test_netcdf_file(&file_path)?;
+
+        // 32 bytes of temperature, 24 of coordinate variables and 48 of coordinates
+        let mut config = Config::default();
+        config.data.max_memory_bytes = Some(80);
+        match load_netcdf(&file_path, config.clone()) {
+            Err(RossbyError::MemoryBudgetExceeded {
+                required, largest, ..
+            }) => {
+                assert_eq!(required, 104);
+                assert_eq!(largest[0], ("temperature".to_string(), 32));
+            }
+            other => panic!("Expected a memory budget error, got {:?}", other.err()),
+        }
+
+        config.data.on_memory_exceeded = "exclude".to_string();
+        let state = load_netcdf(&file_path, config)?;
+        assert!(!state.metadata.variables.contains_key("temperature"));
+        assert!(!state.data.contains_key("temperature"));
+        assert_eq!(state.get_coordinate("lon"), Some(&vec![0.0, 1.0]));
+
+        Ok(())
+    }
+
     #[test]
     fn test_attribute_conversion() -> Result<()> {
         // Create a temporary directory for the test file
@@ -782,7 +1633,7 @@ mod tests {
 
         // Load the file with debugging
         println!("Loading NetCDF file for attribute test");
-        let (metadata, _) = load_netcdf_file(&file_path)?;
+        let (metadata, _) = load_netcdf_file(&file_path, None)?;
         println!("File loaded successfully");
 
         // Debugging output
@@ -829,6 +1680,320 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_non_utf8_text_attributes() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_latin1.nc");
+
+        {
+            let mut file = netcdf::create(&file_path)?;
+            // A Latin-1 history as the NetCDF library hands it out, with the
+            // invalid bytes already replaced, and a stray escape sequence
+            file.add_attribute(
+                "history",
+                "Cr\u{fffd}\u{fffd} par \u{1b}[1mM\u{fffd}t\u{fffd}o",
+            )?;
+            file.add_attribute("source", "ERA5\0\0")?;
+            file.add_attribute("keywords", vec!["ocean\u{7}".to_string()])?;
+            file.add_dimension("x", 2)?;
+            let mut x_var = file.add_variable::<f32>("x", &["x"])?;
+            x_var.put_attribute("comment", "line 1\nline 2")?;
+            x_var.put_values(&[0.0_f32, 1.0], ..)?;
+        }
+
+        let (metadata, _) = load_netcdf_file(&file_path, None)?;
+        let text =
+            |attributes: &HashMap<String, AttributeValue>, name: &str| match &attributes[name] {
+                AttributeValue::Text(text) => text.clone(),
+                other => panic!("Expected Text attribute, got {:?}", other),
+            };
+        assert_eq!(
+            text(&metadata.global_attributes, "history"),
+            "Cr\u{fffd}\u{fffd} par \u{fffd}[1mM\u{fffd}t\u{fffd}o"
+        );
+        assert_eq!(text(&metadata.global_attributes, "source"), "ERA5");
+        assert_eq!(
+            text(&metadata.variables["x"].attributes, "comment"),
+            "line 1\nline 2"
+        );
+        match &metadata.global_attributes["keywords"] {
+            AttributeValue::TextArray(values) => assert_eq!(values, &["ocean\u{fffd}"]),
+            other => panic!("Expected TextArray attribute, got {:?}", other),
+        }
+
+        // The sanitized attributes serialize to valid JSON
+        let json = serde_json::to_string(&metadata.global_attributes).unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&json).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsigned_and_string_types() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_unsigned.nc");
+
+        {
+            let mut file = netcdf::create(&file_path)?;
+            file.add_attribute("keywords", vec!["ocean".to_string(), "wind".to_string()])?;
+            file.add_attribute("checksum", u64::MAX - 1)?;
+            file.add_attribute("offset", -9_007_199_254_740_993_i64)?;
+            file.add_attribute("valid_range", vec![0_u16, 65534])?;
+            file.add_dimension("x", 3)?;
+
+            let mut x_var = file.add_variable::<u32>("x", &["x"])?;
+            x_var.put_values(&[10_u32, 20, 4_000_000_000], ..)?;
+
+            let mut count_var = file.add_variable::<u16>("count", &["x"])?;
+            count_var.put_attribute("_FillValue", 65535_u16)?;
+            count_var.put_values(&[1_u16, 65535, 60000], ..)?;
+
+            let mut id_var = file.add_variable::<u64>("id", &["x"])?;
+            id_var.put_values(&[1_u64, 2, 3], ..)?;
+
+            let mut flag_var = file.add_variable::<u8>("flag", &["x"])?;
+            flag_var.put_values(&[0_u8, 128, 255], ..)?;
+        }
+
+        let (metadata, data) = load_netcdf_file(&file_path, None)?;
+
+        match &metadata.global_attributes["keywords"] {
+            AttributeValue::TextArray(values) => assert_eq!(values, &["ocean", "wind"]),
+            other => panic!("Expected TextArray attribute, got {:?}", other),
+        }
+        match &metadata.global_attributes["checksum"] {
+            AttributeValue::UnsignedInteger(value) => assert_eq!(*value, u64::MAX - 1),
+            other => panic!("Expected UnsignedInteger attribute, got {:?}", other),
+        }
+        match &metadata.global_attributes["offset"] {
+            AttributeValue::Integer(value) => assert_eq!(*value, -9_007_199_254_740_993),
+            other => panic!("Expected Integer attribute, got {:?}", other),
+        }
+        match &metadata.global_attributes["valid_range"] {
+            AttributeValue::NumberArray(values) => assert_eq!(values, &[0.0, 65534.0]),
+            other => panic!("Expected NumberArray attribute, got {:?}", other),
+        }
+        assert_eq!(
+            metadata.variables["count"].attributes["_FillValue"].as_f64(),
+            Some(65535.0)
+        );
+
+        assert_eq!(metadata.coordinates["x"], vec![10.0, 20.0, 4_000_000_000.0]);
+        assert_eq!(
+            data["count"].iter().copied().collect::<Vec<f32>>(),
+            vec![1.0, 65535.0, 60000.0]
+        );
+        assert_eq!(
+            data["id"].iter().copied().collect::<Vec<f32>>(),
+            vec![1.0, 2.0, 3.0]
+        );
+        assert_eq!(
+            data["flag"].iter().copied().collect::<Vec<f32>>(),
+            vec![0.0, 128.0, 255.0]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hierarchical_groups() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_groups.nc");
+
+        {
+            let mut file = netcdf::create(&file_path)?;
+            file.add_dimension("lat", 2)?;
+            file.add_dimension("lon", 3)?;
+            let mut lat_var = file.add_variable::<f64>("lat", &["lat"])?;
+            lat_var.put_values(&[10.0, 20.0], ..)?;
+            let mut t2m_var = file.add_variable::<f32>("t2m", &["lat", "lon"])?;
+            t2m_var.put_values(&[0.0_f32; 6], ..)?;
+
+            let mut forecast = file.add_group("forecast")?;
+            forecast.add_attribute("model", "ifs")?;
+            forecast.add_dimension("level", 2)?;
+            let mut level_var = forecast.add_variable::<f64>("level", &["level"])?;
+            level_var.put_values(&[500.0, 850.0], ..)?;
+            let mut temp_var = forecast.add_variable::<f32>("temp", &["level", "lat", "lon"])?;
+            temp_var.put_values(&(0..12).map(|v| v as f32).collect::<Vec<_>>(), ..)?;
+
+            let mut surface = forecast.add_group("surface")?;
+            let mut sst_var = surface.add_variable::<f32>("sst", &["lat", "lon"])?;
+            sst_var.put_values(&[1.0_f32; 6], ..)?;
+        }
+
+        let (metadata, data) = load_netcdf_file(&file_path, None)?;
+
+        // Group members are namespaced by their path
+        let temp = &metadata.variables["forecast/temp"];
+        assert_eq!(temp.dimensions, vec!["forecast/level", "lat", "lon"]);
+        assert_eq!(temp.shape, vec![2, 2, 3]);
+        assert_eq!(metadata.dimensions["forecast/level"].size, 2);
+        assert_eq!(metadata.coordinates["forecast/level"], vec![500.0, 850.0]);
+        assert_eq!(
+            metadata.variables["forecast/surface/sst"].dimensions,
+            vec!["lat", "lon"]
+        );
+        assert_eq!(data["forecast/temp"][[1, 1, 2]], 11.0);
+        assert!(data.contains_key("forecast/surface/sst"));
+
+        // The hierarchy is described separately
+        let forecast = &metadata.groups["forecast"];
+        assert_eq!(forecast.groups, vec!["forecast/surface"]);
+        assert_eq!(forecast.dimensions, vec!["forecast/level"]);
+        assert!(forecast.variables.contains(&"forecast/temp".to_string()));
+        assert!(matches!(
+            &forecast.attributes["model"],
+            AttributeValue::Text(model) if model == "ifs"
+        ));
+        assert_eq!(
+            metadata.groups["forecast/surface"].variables,
+            vec!["forecast/surface/sst"]
+        );
+
+        // Namespaced dimensions can be selected like any other
+        let state = AppState::new(Config::default(), metadata, data);
+        state.validate()?;
+        let params = HashMap::from([("forecast/level".to_string(), "850".to_string())]);
+        let selection = crate::query::Selection::parse(&state, &params)?;
+        assert_eq!(selection.resolve(&state)?["forecast/level"], vec![1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff_file_format() {
+        use std::io::Cursor;
+
+        let sniff = |bytes: Vec<u8>| FileFormat::sniff_reader(&mut Cursor::new(bytes)).unwrap();
+
+        for version in [1u8, 2, 5] {
+            let mut header = b"CDF".to_vec();
+            header.push(version);
+            header.extend([0; 28]);
+            assert_eq!(sniff(header), Some(FileFormat::NetCdfClassic));
+        }
+
+        let mut hdf5 = HDF5_SIGNATURE.to_vec();
+        hdf5.extend([0; 100]);
+        assert_eq!(sniff(hdf5), Some(FileFormat::Hdf5));
+
+        // An HDF5 superblock after a 1 KiB user block
+        let mut with_user_block = vec![b'#'; 1024];
+        with_user_block.extend(HDF5_SIGNATURE);
+        with_user_block.extend([0; 100]);
+        assert_eq!(sniff(with_user_block), Some(FileFormat::Hdf5));
+
+        assert_eq!(
+            sniff(b"\n  {\"version\": 1, \"refs\": {}}".to_vec()),
+            Some(FileFormat::Kerchunk)
+        );
+
+        assert_eq!(sniff(b"CDF\x03 not a version".to_vec()), None);
+        assert_eq!(sniff(b"GRIB".to_vec()), None);
+        assert_eq!(sniff(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_source_for() -> Result<()> {
+        let dir = tempdir().unwrap();
+
+        let hdf5_path = dir.path().join("archive.h5");
+        let mut bytes = HDF5_SIGNATURE.to_vec();
+        bytes.extend([0; 64]);
+        std::fs::write(&hdf5_path, bytes)?;
+        assert_eq!(source_for(&hdf5_path)?.format_name(), "HDF5");
+
+        let classic_path = dir.path().join("classic.nc");
+        std::fs::write(&classic_path, b"CDF\x01\0\0\0\0")?;
+        assert_eq!(source_for(&classic_path)?.format_name(), "NetCDF");
+
+        let reference_path = dir.path().join("archive.json");
+        std::fs::write(&reference_path, b"{\"refs\": {}}")?;
+        assert_eq!(source_for(&reference_path)?.format_name(), "Kerchunk");
+
+        let other_path = dir.path().join("unknown.nc");
+        std::fs::write(&other_path, b"unknown")?;
+        assert_eq!(source_for(&other_path)?.format_name(), "NetCDF");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_phony_dimension_names() {
+        let dimension = |name: &str, size| {
+            (
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                },
+            )
+        };
+        let variable = |name: &str, dims: &[&str], shape: &[usize]| {
+            (
+                name.to_string(),
+                Variable {
+                    name: name.to_string(),
+                    dimensions: dims.iter().map(|d| d.to_string()).collect(),
+                    shape: shape.to_vec(),
+                    attributes: HashMap::new(),
+                    dtype: "Float".to_string(),
+                },
+            )
+        };
+
+        let mut metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::from([
+                dimension("phony_dim_0", 3),
+                dimension("phony_dim_1", 4),
+                dimension("phony_dim_2", 2),
+                dimension("obs/phony_dim_0", 5),
+            ]),
+            variables: HashMap::from([
+                variable("lat", &["phony_dim_0"], &[3]),
+                variable("lon", &["phony_dim_1"], &[4]),
+                // Two 1D datasets share phony_dim_2, so it stays anonymous
+                variable("a", &["phony_dim_2"], &[2]),
+                variable("b", &["phony_dim_2"], &[2]),
+                variable("t2m", &["phony_dim_0", "phony_dim_1"], &[3, 4]),
+                variable("obs/station", &["obs/phony_dim_0"], &[5]),
+            ]),
+            coordinates: HashMap::from([("phony_dim_0".to_string(), vec![0.0, 1.0, 2.0])]),
+            groups: HashMap::from([(
+                "obs".to_string(),
+                Group {
+                    path: "obs".to_string(),
+                    attributes: HashMap::new(),
+                    dimensions: vec!["obs/phony_dim_0".to_string()],
+                    variables: vec!["obs/station".to_string()],
+                    groups: Vec::new(),
+                },
+            )]),
+        };
+
+        let names = phony_dimension_names(&metadata);
+        assert_eq!(
+            names,
+            vec![
+                ("obs/phony_dim_0".to_string(), "obs/station".to_string()),
+                ("phony_dim_0".to_string(), "lat".to_string()),
+                ("phony_dim_1".to_string(), "lon".to_string()),
+            ]
+        );
+
+        for (phony, name) in &names {
+            rename_dimension(&mut metadata, phony, name);
+        }
+        assert_eq!(metadata.variables["t2m"].dimensions, vec!["lat", "lon"]);
+        assert_eq!(metadata.dimensions["lat"].name, "lat");
+        assert_eq!(metadata.dimensions["obs/station"].size, 5);
+        assert!(metadata.dimensions.contains_key("phony_dim_2"));
+        assert!(metadata.coordinates.contains_key("lat"));
+        assert_eq!(metadata.groups["obs"].dimensions, vec!["obs/station"]);
+    }
+
     #[test]
     fn test_validation() -> Result<()> {
         // Create a temporary directory for the test file
@@ -842,7 +2007,7 @@ mod tests {
 
         // Load the file with debugging
         println!("Loading NetCDF file for validation test");
-        let (metadata, data) = load_netcdf_file(&file_path)?;
+        let (metadata, data) = load_netcdf_file(&file_path, None)?;
         println!("File loaded successfully");
 
         // Print debugging information
@@ -0,0 +1,244 @@
+//! Datasets served alongside the main one.
+//!
+//! The `datasets` config map loads further files, each served with the full
+//! API under `/datasets/<name>` (e.g. `/datasets/demo/image`) by its own
+//! dataset state. Each dataset's configuration is the server-wide one with
+//! its `overrides` merged over it, so a public demo dataset can have low
+//! quotas, its own default colormap or products alongside an internal
+//! full-resolution one, and quota usage is accounted per dataset.
+//!
+//! Before a request reaches the dataset's endpoints, its access rules are
+//! checked: `endpoints` restricts the endpoints served (others are `404 Not
+//! Found`), and `api_keys` requires one of the listed keys in `X-API-Key`
+//! (`401 Unauthorized` otherwise).
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::config::{Config, DatasetConfig};
+use crate::data_loader::load_netcdf;
+use crate::error::{Result, RossbyError};
+use crate::handlers::admin::tokens_match;
+use crate::logging::generate_request_id;
+use crate::quota::API_KEY_HEADER;
+use crate::state::AppState;
+
+/// Path under which the datasets are mounted
+pub const DATASETS_PREFIX: &str = "/datasets";
+
+/// Path at which a dataset is served
+pub fn mount_path(name: &str) -> String {
+    format!("{}/{}", DATASETS_PREFIX, name)
+}
+
+/// Configuration of a dataset: the server-wide one with its overrides merged in
+///
+/// Objects in the overrides are merged key by key, any other value replaces
+/// the server-wide one.
+pub fn dataset_config(base: &Config, name: &str, dataset: &DatasetConfig) -> Result<Config> {
+    let invalid = |message: String| RossbyError::Config {
+        message: format!("Invalid overrides for dataset '{}': {}", name, message),
+    };
+    let mut value = serde_json::to_value(base)?;
+    merge_json(&mut value, &dataset.overrides);
+    let mut config: Config = serde_json::from_value(value).map_err(|e| invalid(e.to_string()))?;
+    config.datasets.clear();
+    config.data.file_path = Some(dataset.file_path.clone());
+    config.validate().map_err(|e| invalid(e.to_string()))?;
+    Ok(config)
+}
+
+/// Load a dataset with its configuration
+pub fn load_dataset(base: &Config, name: &str, dataset: &DatasetConfig) -> Result<AppState> {
+    let config = dataset_config(base, name, dataset)?;
+    let state = load_netcdf(&dataset.file_path, config)?;
+    state.validate()?;
+    Ok(state)
+}
+
+/// Merge `overrides` into `base`, recursing into objects present in both
+fn merge_json(base: &mut serde_json::Value, overrides: &serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (_, serde_json::Value::Null) => {}
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+/// Access rules of a dataset
+#[derive(Debug, Clone)]
+pub struct DatasetAccess {
+    /// Name of the dataset
+    pub name: String,
+    /// Endpoints served (None = all)
+    pub endpoints: Option<Vec<String>>,
+    /// Accepted API keys (None = anyone)
+    pub api_keys: Option<Vec<String>>,
+}
+
+impl DatasetAccess {
+    /// Access rules of a configured dataset
+    pub fn new(name: &str, dataset: &DatasetConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            endpoints: dataset.endpoints.clone(),
+            api_keys: dataset.api_keys.clone(),
+        }
+    }
+
+    /// Check a request for a path within the dataset, e.g. `/image`
+    pub fn check(
+        &self,
+        path: &str,
+        headers: &HeaderMap,
+    ) -> std::result::Result<(), (StatusCode, String)> {
+        if let Some(endpoints) = &self.endpoints {
+            let served = endpoints.iter().any(|endpoint| {
+                let endpoint = endpoint.trim_end_matches('/');
+                path == endpoint
+                    || path
+                        .strip_prefix(endpoint)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            });
+            if !served {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    format!("Endpoint {} is not served for dataset {}", path, self.name),
+                ));
+            }
+        }
+
+        if let Some(keys) = &self.api_keys {
+            let provided = headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .unwrap_or("");
+            // Check every key so the time taken does not reveal which matched
+            let accepted = keys
+                .iter()
+                .fold(false, |found, key| tokens_match(provided, key) | found);
+            if !accepted {
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    format!(
+                        "Dataset {} requires a valid {} header",
+                        self.name, API_KEY_HEADER
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Enforce the access rules of a dataset before its endpoints are reached
+pub async fn dataset_access_middleware(
+    State(access): State<Arc<DatasetAccess>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    match access.check(path, request.headers()) {
+        Ok(()) => {
+            debug!(dataset = %access.name, path = %path, "Dispatching dataset request");
+            next.run(request).await
+        }
+        Err((status, message)) => {
+            let request_id = generate_request_id();
+            warn!(
+                dataset = %access.name,
+                path = %path,
+                request_id = %request_id,
+                status = status.as_u16(),
+                "Rejected dataset request"
+            );
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": message,
+                    "request_id": request_id,
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn dataset(overrides: serde_json::Value) -> DatasetConfig {
+        DatasetConfig {
+            file_path: "demo.nc".into(),
+            overrides,
+            endpoints: Some(vec!["/metadata".to_string(), "/admin/".to_string()]),
+            api_keys: Some(vec!["demo-key".to_string()]),
+        }
+    }
+
+    #[test]
+    fn test_dataset_config() {
+        let base: Config = serde_json::from_str(
+            r#"{"server": {"port": 9000, "quotas": {"daily_bytes": 1000000, "window_secs": 3600}},
+                "data": {"interpolation_method": "nearest"},
+                "datasets": {"demo": {"file_path": "demo.nc"}}}"#,
+        )
+        .unwrap();
+        let overrides = serde_json::json!({
+            "server": {"quotas": {"daily_bytes": 1000}},
+            "data": {"colormap": "plasma"},
+        });
+        let config = dataset_config(&base, "demo", &dataset(overrides)).unwrap();
+
+        // Overridden values replace, the rest is inherited
+        assert_eq!(config.server.quotas.daily_bytes, Some(1000));
+        assert_eq!(config.server.quotas.window_secs, 3600);
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.data.colormap(None), "plasma");
+        assert_eq!(config.data.interpolation_method, "nearest");
+        assert!(config.datasets.is_empty());
+
+        let overrides = serde_json::json!({"server": {"quotas": {"window_secs": 0}}});
+        let error = dataset_config(&base, "demo", &dataset(overrides)).unwrap_err();
+        assert!(error.to_string().contains("dataset 'demo'"));
+    }
+
+    #[test]
+    fn test_dataset_access() {
+        let access = DatasetAccess::new("demo", &dataset(serde_json::Value::Null));
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("demo-key"));
+
+        assert!(access.check("/metadata", &headers).is_ok());
+        assert!(access.check("/admin/caches/flush", &headers).is_ok());
+        let (status, _) = access.check("/data", &headers).unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = access.check("/metadata2", &headers).unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = access.check("/metadata", &HeaderMap::new()).unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("other-key"));
+        let (status, _) = access.check("/metadata", &headers).unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,644 @@
+//! Derived dynamics fields computed at request time.
+//!
+//! `/image` and `/data` accept derived variables written as functions of
+//! dataset variables:
+//!
+//! - `gradient_x(f)` and `gradient_y(f)`: eastward and northward derivatives
+//!   of `f` per meter,
+//! - `gradient_magnitude(f)`: magnitude of the horizontal gradient,
+//! - `vorticity(u,v)`: relative vorticity of the wind `(u, v)`,
+//! - `divergence(u,v)`: horizontal divergence of the wind `(u, v)`.
+//!
+//! Derivatives are centered finite differences on the sphere (one-sided at
+//! the edges of regional grids, wrapping around periodic longitude grids),
+//! so vorticity and divergence include the metric terms of spherical
+//! coordinates. They are evaluated on the whole horizontal grid and only
+//! then restricted to the requested region, so edges of a bounding box do
+//! not degrade the result. Values at the poles, where longitude derivatives
+//! are undefined, and values next to missing data are NaN.
+
+use ndarray::{Array2, ArrayD, ArrayView2, Axis, IxDyn, Zip};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::bounds::is_periodic_longitude;
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, HorizontalField};
+use crate::slice_stats::MissingData;
+use crate::state::{AppState, AttributeValue, Variable};
+
+/// Mean Earth radius in meters
+pub const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Smallest cos(latitude) at which longitude derivatives are evaluated
+const MIN_COS_LAT: f64 = 1e-9;
+
+/// Units of wind components for which vorticity and divergence are in s-1
+const WIND_UNITS: [&str; 3] = ["m s-1", "m/s", "m s**-1"];
+
+/// Diagnostic computed by a derived variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnostic {
+    GradientX,
+    GradientY,
+    GradientMagnitude,
+    Vorticity,
+    Divergence,
+}
+
+impl Diagnostic {
+    /// Diagnostic of a function name, if known
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gradient_x" => Some(Diagnostic::GradientX),
+            "gradient_y" => Some(Diagnostic::GradientY),
+            "gradient_magnitude" => Some(Diagnostic::GradientMagnitude),
+            "vorticity" => Some(Diagnostic::Vorticity),
+            "divergence" => Some(Diagnostic::Divergence),
+            _ => None,
+        }
+    }
+
+    /// Function name of the diagnostic
+    pub fn name(self) -> &'static str {
+        match self {
+            Diagnostic::GradientX => "gradient_x",
+            Diagnostic::GradientY => "gradient_y",
+            Diagnostic::GradientMagnitude => "gradient_magnitude",
+            Diagnostic::Vorticity => "vorticity",
+            Diagnostic::Divergence => "divergence",
+        }
+    }
+
+    /// Number of variables the diagnostic takes
+    pub fn arity(self) -> usize {
+        match self {
+            Diagnostic::Vorticity | Diagnostic::Divergence => 2,
+            _ => 1,
+        }
+    }
+
+    fn long_name(self) -> &'static str {
+        match self {
+            Diagnostic::GradientX => "eastward derivative",
+            Diagnostic::GradientY => "northward derivative",
+            Diagnostic::GradientMagnitude => "horizontal gradient magnitude",
+            Diagnostic::Vorticity => "relative vorticity",
+            Diagnostic::Divergence => "horizontal divergence",
+        }
+    }
+}
+
+/// A diagnostic applied to dataset variables, e.g. `vorticity(u,v)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivedVariable {
+    pub diagnostic: Diagnostic,
+    /// Variables the diagnostic is computed from
+    pub args: Vec<String>,
+}
+
+impl fmt::Display for DerivedVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.diagnostic.name(), self.args.join(","))
+    }
+}
+
+impl DerivedVariable {
+    /// Parse a derived variable such as `vorticity(u_wind,v_wind)`
+    ///
+    /// Returns `None` if `text` names a variable or is not a call of a known
+    /// diagnostic. The arguments must be variables with latitude and
+    /// longitude dimensions, and the same dimensions as each other.
+    pub fn parse(state: &AppState, text: &str) -> Result<Option<Self>> {
+        if state.has_variable(text) {
+            return Ok(None);
+        }
+        let Some((name, rest)) = text.trim().split_once('(') else {
+            return Ok(None);
+        };
+        let (Some(diagnostic), Some(args)) =
+            (Diagnostic::from_name(name.trim()), rest.strip_suffix(')'))
+        else {
+            return Ok(None);
+        };
+
+        let args: Vec<String> = args.split(',').map(|a| a.trim().to_string()).collect();
+        if args.len() != diagnostic.arity() || args.iter().any(String::is_empty) {
+            return Err(RossbyError::InvalidParameter {
+                param: "var".to_string(),
+                message: format!(
+                    "{} takes {} variable(s), got '{}'",
+                    diagnostic.name(),
+                    diagnostic.arity(),
+                    text
+                ),
+            });
+        }
+        let missing: Vec<String> = args
+            .iter()
+            .filter(|name| !state.has_variable(name))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(RossbyError::InvalidVariables { names: missing });
+        }
+
+        let first = state.get_variable_metadata_checked(&args[0])?;
+        if find_lat_lon_axes(&first.dimensions).is_none() {
+            return Err(RossbyError::VariableNotSuitableForImage {
+                name: args[0].clone(),
+            });
+        }
+        for other in &args[1..] {
+            let meta = state.get_variable_metadata_checked(other)?;
+            if meta.dimensions != first.dimensions || meta.shape != first.shape {
+                return Err(RossbyError::InvalidParameter {
+                    param: "var".to_string(),
+                    message: format!(
+                        "Cannot compute {} from '{}' {:?} and '{}' {:?}; both variables \
+                         must have the same dimensions",
+                        diagnostic.name(),
+                        args[0],
+                        first.dimensions,
+                        other,
+                        meta.dimensions
+                    ),
+                });
+            }
+        }
+
+        Ok(Some(Self { diagnostic, args }))
+    }
+
+    /// Metadata of the derived variable
+    ///
+    /// Vorticity and divergence of winds in m s-1 are in s-1; other results
+    /// are in the units of the arguments per meter.
+    pub fn metadata(&self, state: &AppState) -> Result<Variable> {
+        let meta = state.get_variable_metadata_checked(&self.args[0])?;
+        let mut attributes = HashMap::from([(
+            "long_name".to_string(),
+            AttributeValue::Text(format!(
+                "{} of {}",
+                self.diagnostic.long_name(),
+                self.args.join(", ")
+            )),
+        )]);
+        if let Some(AttributeValue::Text(units)) = meta.attributes.get("units") {
+            let units = match self.diagnostic {
+                Diagnostic::Vorticity | Diagnostic::Divergence
+                    if WIND_UNITS.contains(&units.as_str()) =>
+                {
+                    "s-1".to_string()
+                }
+                _ => format!("{} m-1", units),
+            };
+            attributes.insert("units".to_string(), AttributeValue::Text(units));
+        }
+
+        Ok(Variable {
+            name: self.to_string(),
+            dimensions: meta.dimensions.clone(),
+            shape: meta.shape.clone(),
+            attributes,
+            dtype: "f32".to_string(),
+        })
+    }
+
+    /// Compute the diagnostic from the horizontal fields of its arguments
+    ///
+    /// `field` extracts the whole field of one argument, e.g. with
+    /// [`HorizontalField::from_state`], which already masks missing values.
+    pub fn field(
+        &self,
+        mut field: impl FnMut(&str) -> Result<HorizontalField>,
+    ) -> Result<HorizontalField> {
+        let fields = self
+            .args
+            .iter()
+            .map(|name| field(name))
+            .collect::<Result<Vec<_>>>()?;
+        let first = &fields[0];
+        if fields.iter().any(|f| f.values.dim() != first.values.dim()) {
+            return Err(RossbyError::Conversion {
+                message: format!("Arguments of {} have different shapes", self),
+            });
+        }
+
+        let views: Vec<ArrayView2<f32>> = fields.iter().map(|f| f.values.view()).collect();
+        let values = self.compute(&views, &first.lat, &first.lon)?;
+        Ok(HorizontalField {
+            lat: first.lat.clone(),
+            lon: first.lon.clone(),
+            values,
+        })
+    }
+
+    /// Compute the diagnostic on arrays of any rank
+    ///
+    /// The arrays hold the arguments with the same shape, with the latitude
+    /// and longitude axes at `lat_axis` and `lon_axis` and the whole grid
+    /// along them; the diagnostic is evaluated on every horizontal slab.
+    /// Missing values of the arguments are masked first.
+    pub fn compute_nd(
+        &self,
+        state: &AppState,
+        mut arrays: Vec<ArrayD<f32>>,
+        (lat_axis, lon_axis): (usize, usize),
+        lat: &[f64],
+        lon: &[f64],
+    ) -> Result<ArrayD<f32>> {
+        for (array, name) in arrays.iter_mut().zip(&self.args) {
+            MissingData::for_variable(state.get_variable_metadata_checked(name)?)
+                .mask(array.iter_mut());
+        }
+        let shape = arrays[0].shape().to_vec();
+        if arrays.iter().any(|a| a.shape() != shape.as_slice()) {
+            return Err(RossbyError::Conversion {
+                message: format!("Arguments of {} have different shapes", self),
+            });
+        }
+
+        // Move the horizontal axes last and stack the slabs along one axis
+        let mut order: Vec<usize> = (0..shape.len())
+            .filter(|&axis| axis != lat_axis && axis != lon_axis)
+            .collect();
+        order.extend([lat_axis, lon_axis]);
+        let (rows, cols) = (shape[lat_axis], shape[lon_axis]);
+        let slabs = shape.iter().product::<usize>() / (rows * cols).max(1);
+        let stacked: Vec<ndarray::Array3<f32>> = arrays
+            .iter()
+            .map(|array| {
+                let permuted = array.view().permuted_axes(order.clone());
+                let standard = permuted.as_standard_layout().into_owned();
+                standard.into_shape((slabs, rows, cols))
+            })
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| RossbyError::Conversion {
+                message: format!("Failed to arrange horizontal slabs: {}", e),
+            })?;
+
+        let mut result = ndarray::Array3::<f32>::zeros((slabs, rows, cols));
+        for (k, mut slab) in result.outer_iter_mut().enumerate() {
+            let views: Vec<ArrayView2<f32>> =
+                stacked.iter().map(|s| s.index_axis(Axis(0), k)).collect();
+            slab.assign(&self.compute(&views, lat, lon)?);
+        }
+
+        // Restore the original axis order
+        let permuted_shape: Vec<usize> = order.iter().map(|&axis| shape[axis]).collect();
+        let mut inverse = vec![0; order.len()];
+        for (position, &axis) in order.iter().enumerate() {
+            inverse[axis] = position;
+        }
+        let result =
+            result
+                .into_shape(IxDyn(&permuted_shape))
+                .map_err(|e| RossbyError::Conversion {
+                    message: format!("Failed to restore axes: {}", e),
+                })?;
+        Ok(result
+            .permuted_axes(inverse)
+            .as_standard_layout()
+            .into_owned())
+    }
+
+    /// Compute the diagnostic on one horizontal slab of each argument
+    fn compute(&self, args: &[ArrayView2<f32>], lat: &[f64], lon: &[f64]) -> Result<Array2<f32>> {
+        let (rows, cols) = args[0].dim();
+        if rows != lat.len() || cols != lon.len() {
+            return Err(RossbyError::Conversion {
+                message: format!(
+                    "Field of shape {:?} does not match a {}x{} grid",
+                    (rows, cols),
+                    lat.len(),
+                    lon.len()
+                ),
+            });
+        }
+        if rows < 2 || cols < 2 {
+            return Err(RossbyError::InvalidParameter {
+                param: "var".to_string(),
+                message: format!(
+                    "{} needs at least two latitudes and two longitudes",
+                    self.diagnostic.name()
+                ),
+            });
+        }
+
+        let grid = SphericalGrid::new(lat, lon);
+        let values: Vec<Array2<f64>> = args.iter().map(|a| a.mapv(|v| v as f64)).collect();
+        let result = match self.diagnostic {
+            Diagnostic::GradientX => grid.gradient_x(&values[0]),
+            Diagnostic::GradientY => grid.gradient_y(&values[0]),
+            Diagnostic::GradientMagnitude => {
+                let mut gx = grid.gradient_x(&values[0]);
+                let gy = grid.gradient_y(&values[0]);
+                Zip::from(&mut gx)
+                    .and(&gy)
+                    .for_each(|x, &y| *x = x.hypot(y));
+                gx
+            }
+            Diagnostic::Vorticity => {
+                // dv/dx - (1 / (R cos(lat))) d(u cos(lat))/dlat
+                let mut dv_dx = grid.gradient_x(&values[1]);
+                let du_dy = grid.weighted_gradient_y(&values[0]);
+                Zip::from(&mut dv_dx).and(&du_dy).for_each(|a, &b| *a -= b);
+                dv_dx
+            }
+            Diagnostic::Divergence => {
+                // du/dx + (1 / (R cos(lat))) d(v cos(lat))/dlat
+                let mut du_dx = grid.gradient_x(&values[0]);
+                let dv_dy = grid.weighted_gradient_y(&values[1]);
+                Zip::from(&mut du_dx).and(&dv_dy).for_each(|a, &b| *a += b);
+                du_dx
+            }
+        };
+        Ok(result.mapv(|v| v as f32))
+    }
+}
+
+/// Finite differences on a latitude/longitude grid
+struct SphericalGrid<'a> {
+    lat: &'a [f64],
+    lon: &'a [f64],
+    /// cos(latitude) of every row
+    cos_lat: Vec<f64>,
+    periodic: bool,
+}
+
+impl<'a> SphericalGrid<'a> {
+    fn new(lat: &'a [f64], lon: &'a [f64]) -> Self {
+        Self {
+            lat,
+            lon,
+            cos_lat: lat.iter().map(|l| l.to_radians().cos()).collect(),
+            periodic: is_periodic_longitude(lon),
+        }
+    }
+
+    /// Eastward derivative per meter
+    fn gradient_x(&self, values: &Array2<f64>) -> Array2<f64> {
+        let mut result = differentiate(values.view(), self.lon, Axis(1), self.periodic);
+        for (mut row, &cos_lat) in result.outer_iter_mut().zip(&self.cos_lat) {
+            row.mapv_inplace(|d| scale_by_cos_lat(d, cos_lat));
+        }
+        result
+    }
+
+    /// Northward derivative per meter
+    fn gradient_y(&self, values: &Array2<f64>) -> Array2<f64> {
+        differentiate(values.view(), self.lat, Axis(0), false) / EARTH_RADIUS
+    }
+
+    /// `(1 / (R cos(lat))) d(f cos(lat))/dlat`, the northward term of the
+    /// divergence (and of the vorticity, with the other sign)
+    fn weighted_gradient_y(&self, values: &Array2<f64>) -> Array2<f64> {
+        let mut weighted = values.clone();
+        for (mut row, &cos_lat) in weighted.outer_iter_mut().zip(&self.cos_lat) {
+            row *= cos_lat;
+        }
+        let mut result = differentiate(weighted.view(), self.lat, Axis(0), false);
+        for (mut row, &cos_lat) in result.outer_iter_mut().zip(&self.cos_lat) {
+            row.mapv_inplace(|d| scale_by_cos_lat(d, cos_lat));
+        }
+        result
+    }
+}
+
+/// Convert a derivative per radian of longitude (or latitude, weighted by
+/// cos(latitude)) to one per meter
+fn scale_by_cos_lat(derivative: f64, cos_lat: f64) -> f64 {
+    if cos_lat.abs() < MIN_COS_LAT {
+        f64::NAN
+    } else {
+        derivative / (EARTH_RADIUS * cos_lat)
+    }
+}
+
+/// Derivative per radian along one axis by centered differences
+///
+/// Coordinates are in degrees and may be unevenly spaced or descending.
+/// Edges use one-sided differences unless `periodic`, in which case the
+/// first and last points are neighbours across the seam.
+fn differentiate(
+    values: ArrayView2<f64>,
+    coords: &[f64],
+    axis: Axis,
+    periodic: bool,
+) -> Array2<f64> {
+    let n = coords.len();
+    let ascending = coords[n - 1] >= coords[0];
+    let mut result = Array2::<f64>::zeros(values.dim());
+
+    for (lane, mut out) in values.lanes(axis).into_iter().zip(result.lanes_mut(axis)) {
+        for i in 0..n {
+            let (prev, next) = match (i, periodic) {
+                (0, true) => (n - 1, 1),
+                (i, true) if i == n - 1 => (n - 2, 0),
+                (0, false) => (0, 1),
+                (i, false) if i == n - 1 => (n - 2, n - 1),
+                (i, _) => (i - 1, i + 1),
+            };
+            let mut delta = coords[next] - coords[prev];
+            if periodic && (next < prev) {
+                // Across the seam, the step continues in the grid's direction
+                delta += if ascending { 360.0 } else { -360.0 };
+            }
+            out[i] = (lane[next] - lane[prev]) / delta.to_radians();
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata};
+
+    const LAT: [f64; 5] = [-20.0, -10.0, 0.0, 10.0, 20.0];
+
+    fn lon() -> Vec<f64> {
+        (0..36).map(|i| i as f64 * 10.0).collect()
+    }
+
+    fn create_test_state() -> AppState {
+        let lon = lon();
+        let dimension = |name: &str, size: usize| {
+            (
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                },
+            )
+        };
+        let variable = |name: &str, units: &str| {
+            (
+                name.to_string(),
+                Variable {
+                    name: name.to_string(),
+                    dimensions: vec!["time".to_string(), "lat".to_string(), "lon".to_string()],
+                    shape: vec![2, LAT.len(), lon.len()],
+                    attributes: HashMap::from([(
+                        "units".to_string(),
+                        AttributeValue::Text(units.to_string()),
+                    )]),
+                    dtype: "f32".to_string(),
+                },
+            )
+        };
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::from([
+                dimension("time", 2),
+                dimension("lat", LAT.len()),
+                dimension("lon", lon.len()),
+            ]),
+            variables: HashMap::from([
+                variable("u", "m s-1"),
+                variable("v", "m s-1"),
+                variable("t", "K"),
+            ]),
+            coordinates: HashMap::from([
+                ("time".to_string(), vec![0.0, 1.0]),
+                ("lat".to_string(), LAT.to_vec()),
+                ("lon".to_string(), lon.clone()),
+            ]),
+            groups: HashMap::new(),
+        };
+
+        // Solid-body rotation u = U cos(lat), v = 0, whose vorticity is
+        // 2 U sin(lat) / R and whose divergence is zero; t increases eastward
+        let field = |f: &dyn Fn(f64, f64) -> f32| {
+            ArrayD::from_shape_fn(IxDyn(&[2, LAT.len(), lon.len()]), |ix| {
+                f(LAT[ix[1]], lon[ix[2]]) * (ix[0] + 1) as f32
+            })
+        };
+        let data = HashMap::from([
+            (
+                "u".to_string(),
+                field(&|lat, _| 10.0 * lat.to_radians().cos() as f32),
+            ),
+            ("v".to_string(), field(&|_, _| 0.0)),
+            (
+                "t".to_string(),
+                field(&|_, lon| (lon.to_radians().sin()) as f32),
+            ),
+        ]);
+        AppState::new(Config::default(), metadata, data)
+    }
+
+    #[test]
+    fn test_parse() {
+        let state = create_test_state();
+
+        let derived = DerivedVariable::parse(&state, "vorticity(u, v)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(derived.diagnostic, Diagnostic::Vorticity);
+        assert_eq!(derived.to_string(), "vorticity(u,v)");
+
+        assert_eq!(DerivedVariable::parse(&state, "u").unwrap(), None);
+        assert_eq!(DerivedVariable::parse(&state, "curl(u,v)").unwrap(), None);
+        assert!(DerivedVariable::parse(&state, "vorticity(u)").is_err());
+        assert!(matches!(
+            DerivedVariable::parse(&state, "divergence(u,w)"),
+            Err(RossbyError::InvalidVariables { .. })
+        ));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let state = create_test_state();
+        let units = |text: &str| {
+            let meta = DerivedVariable::parse(&state, text)
+                .unwrap()
+                .unwrap()
+                .metadata(&state)
+                .unwrap();
+            match meta.attributes.get("units") {
+                Some(AttributeValue::Text(units)) => units.clone(),
+                _ => panic!("no units"),
+            }
+        };
+        assert_eq!(units("vorticity(u,v)"), "s-1");
+        assert_eq!(units("gradient_x(t)"), "K m-1");
+    }
+
+    #[test]
+    fn test_solid_body_rotation() {
+        let state = create_test_state();
+        let dim_indices = HashMap::from([("time".to_string(), 0)]);
+        let extract = |name: &str| HorizontalField::from_state(&state, name, &dim_indices, None);
+
+        let vorticity = DerivedVariable::parse(&state, "vorticity(u,v)")
+            .unwrap()
+            .unwrap()
+            .field(extract)
+            .unwrap();
+        // Interior rows, away from one-sided latitude differences
+        for (i, &lat) in LAT.iter().enumerate().take(4).skip(1) {
+            let expected = 2.0 * 10.0 * lat.to_radians().sin() / EARTH_RADIUS;
+            let actual = vorticity.values[[i, 7]] as f64;
+            assert!(
+                (actual - expected).abs() < 0.02 * 2.0 * 10.0 / EARTH_RADIUS,
+                "lat {}: {} vs {}",
+                lat,
+                actual,
+                expected
+            );
+        }
+
+        let divergence = DerivedVariable::parse(&state, "divergence(u,v)")
+            .unwrap()
+            .unwrap()
+            .field(extract)
+            .unwrap();
+        assert!(divergence.values.iter().all(|d| d.abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_periodic_gradient() {
+        let state = create_test_state();
+        let dim_indices = HashMap::from([("time".to_string(), 0)]);
+        let gradient = DerivedVariable::parse(&state, "gradient_x(t)")
+            .unwrap()
+            .unwrap()
+            .field(|name| HorizontalField::from_state(&state, name, &dim_indices, None))
+            .unwrap();
+
+        // d sin(lon)/dx = cos(lon) / (R cos(lat)), including across the seam
+        for j in [0, 18, 35] {
+            let expected = lon()[j].to_radians().cos() / EARTH_RADIUS;
+            let actual = gradient.values[[2, j]] as f64;
+            assert!(
+                (actual - expected).abs() < 0.01 / EARTH_RADIUS,
+                "column {}",
+                j
+            );
+        }
+    }
+
+    #[test]
+    fn test_compute_nd() {
+        let state = create_test_state();
+        let derived = DerivedVariable::parse(&state, "gradient_x(t)")
+            .unwrap()
+            .unwrap();
+        let t = state.get_variable_checked("t").unwrap().to_owned();
+        let result = derived
+            .compute_nd(&state, vec![t], (1, 2), &LAT, &lon())
+            .unwrap();
+        assert_eq!(result.shape(), &[2, LAT.len(), 36]);
+
+        let dim_indices = HashMap::from([("time".to_string(), 1)]);
+        let second = derived
+            .field(|name| HorizontalField::from_state(&state, name, &dim_indices, None))
+            .unwrap();
+        assert_eq!(result.index_axis(Axis(0), 1), second.values.into_dyn());
+    }
+}
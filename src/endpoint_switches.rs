@@ -0,0 +1,196 @@
+//! Switches disabling endpoints.
+//!
+//! `server.disabled_endpoints` lists endpoints an instance does not serve, so
+//! a public-facing image server can expose only what it needs. An entry is
+//! either a path, disabling the endpoint and everything under it (e.g.
+//! `/admin`), or a path with a query string, disabling only the requests
+//! carrying those parameters (e.g. `/data?format=json`).
+//!
+//! Requests for a disabled endpoint are answered `404 Not Found`, as if it
+//! did not exist; requests using disabled parameters of a served endpoint are
+//! answered `403 Forbidden`. The check runs after products are expanded, so a
+//! product cannot reach a disabled variant.
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::error::{Result, RossbyError};
+use crate::logging::generate_request_id;
+use crate::schema::SCHEMA_VERSION;
+use crate::state::AppState;
+
+/// An entry of `server.disabled_endpoints`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisabledEndpoint {
+    /// Path of the endpoint, matching everything under it as well
+    pub path: String,
+    /// Parameters a request must all carry to be rejected (empty = any request)
+    pub params: Vec<(String, String)>,
+}
+
+impl DisabledEndpoint {
+    /// Parse an entry such as `/admin` or `/data?format=json`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |message: &str| RossbyError::Config {
+            message: format!("Invalid disabled endpoint '{}': {}", spec, message),
+        };
+        let (path, query) = match spec.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (spec, None),
+        };
+        if !path.starts_with('/') {
+            return Err(invalid("the path must start with '/'"));
+        }
+        let params: Vec<(String, String)> = match query {
+            Some(query) => serde_urlencoded::from_str(query)
+                .map_err(|e| invalid(&format!("malformed query: {}", e)))?,
+            None => Vec::new(),
+        };
+        if query.is_some() && params.is_empty() {
+            return Err(invalid("the query lists no parameters"));
+        }
+        Ok(Self {
+            path: path.trim_end_matches('/').to_string(),
+            params,
+        })
+    }
+
+    /// Whether the entry covers a path, e.g. `/admin` covers `/admin/state`
+    fn covers(&self, path: &str) -> bool {
+        path == self.path
+            || path
+                .strip_prefix(&self.path)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    /// Status rejecting a request, if the entry disables it
+    pub fn check(&self, path: &str, params: &[(String, String)]) -> Option<StatusCode> {
+        if !self.covers(path) {
+            return None;
+        }
+        if self.params.is_empty() {
+            return Some(StatusCode::NOT_FOUND);
+        }
+        self.params
+            .iter()
+            .all(|param| params.contains(param))
+            .then_some(StatusCode::FORBIDDEN)
+    }
+}
+
+/// Status rejecting a request, if any of the entries disables it
+pub fn check_request(
+    disabled: &[String],
+    path: &str,
+    query: Option<&str>,
+) -> Option<(StatusCode, String)> {
+    if disabled.is_empty() {
+        return None;
+    }
+    let params: Vec<(String, String)> = query
+        .and_then(|query| serde_urlencoded::from_str(query).ok())
+        .unwrap_or_default();
+    // Entries were validated with the config
+    let entries = disabled
+        .iter()
+        .filter_map(|spec| DisabledEndpoint::parse(spec).ok());
+    let mut forbidden = None;
+    for entry in entries {
+        match entry.check(path, &params) {
+            Some(StatusCode::NOT_FOUND) => {
+                return Some((
+                    StatusCode::NOT_FOUND,
+                    format!("Endpoint {} is not served", path),
+                ))
+            }
+            Some(status) => {
+                let params = serde_urlencoded::to_string(&entry.params).unwrap_or_default();
+                forbidden.get_or_insert((
+                    status,
+                    format!("Requests with {} are disabled on {}", params, path),
+                ));
+            }
+            None => {}
+        }
+    }
+    forbidden
+}
+
+/// Reject requests for disabled endpoints before they reach the handlers
+pub async fn endpoint_switch_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    match check_request(
+        &state.config.server.disabled_endpoints,
+        path,
+        request.uri().query(),
+    ) {
+        None => next.run(request).await,
+        Some((status, message)) => {
+            let request_id = generate_request_id();
+            warn!(
+                path = %path,
+                request_id = %request_id,
+                status = status.as_u16(),
+                "Rejected request for a disabled endpoint"
+            );
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": message,
+                    "request_id": request_id,
+                    "schema_version": SCHEMA_VERSION
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_disabled_endpoint() {
+        let entry = DisabledEndpoint::parse("/data?format=json").unwrap();
+        assert_eq!(entry.path, "/data");
+        assert_eq!(
+            entry.params,
+            vec![("format".to_string(), "json".to_string())]
+        );
+        assert_eq!(DisabledEndpoint::parse("/admin/").unwrap().path, "/admin");
+        assert!(DisabledEndpoint::parse("admin").is_err());
+        assert!(DisabledEndpoint::parse("/data?").is_err());
+    }
+
+    #[test]
+    fn test_check_request() {
+        let disabled = vec!["/admin".to_string(), "/data?format=json".to_string()];
+        let status = |path: &str, query: Option<&str>| {
+            check_request(&disabled, path, query).map(|(status, _)| status)
+        };
+
+        assert_eq!(status("/admin", None), Some(StatusCode::NOT_FOUND));
+        assert_eq!(status("/admin/state", None), Some(StatusCode::NOT_FOUND));
+        assert_eq!(status("/administer", None), None);
+        assert_eq!(
+            status("/data", Some("vars=t2m&format=json")),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(status("/data", Some("vars=t2m&format=arrow")), None);
+        assert_eq!(status("/data", Some("vars=t2m")), None);
+        assert_eq!(status("/image", Some("format=json")), None);
+        assert_eq!(check_request(&[], "/admin", None), None);
+    }
+}
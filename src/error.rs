@@ -5,6 +5,8 @@
 
 use thiserror::Error;
 
+use crate::memory_budget::format_bytes;
+
 /// The main error type for rossby operations.
 #[derive(Error, Debug)]
 pub enum RossbyError {
@@ -29,7 +31,9 @@ pub enum RossbyError {
     InvalidCoordinates { message: String },
 
     /// Physical value not found in coordinate array
-    #[error("Physical value not found: {dimension}={value}. Available values: {available:?}")]
+    #[error(
+        "Physical value not found: {dimension}={value}. Nearest available values: {available:?}"
+    )]
     PhysicalValueNotFound {
         dimension: String,
         value: f64,
@@ -72,6 +76,14 @@ pub enum RossbyError {
     #[error("Variable {name} is not suitable for image rendering. It must be a 2D grid with latitude and longitude dimensions.")]
     VariableNotSuitableForImage { name: String },
 
+    /// Values that could not be read from the data file, or are corrupt
+    #[error("Corrupt data: {variable} could not be read, {message}")]
+    CorruptData { variable: String, message: String },
+
+    /// Slice without a single valid value
+    #[error("No valid data: {name} has only missing values in the requested slice")]
+    NoValidData { name: String },
+
     /// JSON serialization/deserialization errors
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
@@ -88,6 +100,30 @@ pub enum RossbyError {
     #[error("Server error: {message}")]
     Server { message: String },
 
+    /// Errors talking to a federated peer instance
+    #[error("Peer '{peer}' error: {message}")]
+    Peer { peer: String, message: String },
+
+    /// Bandwidth quota exhausted
+    #[error("Quota exceeded for {client}: used {used} of {limit} bytes in the current window")]
+    QuotaExceeded {
+        client: String,
+        used: u64,
+        limit: u64,
+    },
+
+    /// The dataset was swapped since the generation a client required
+    #[error("Dataset generation mismatch: required {required}, current generation is {current}")]
+    GenerationMismatch { required: u64, current: u64 },
+
+    /// Invalid or unreadable kerchunk reference file
+    #[error("Reference error: {message}")]
+    Reference { message: String },
+
+    /// Request body over the configured size limit
+    #[error("Request body too large: {message}. Maximum allowed: {limit} bytes")]
+    BodyTooLarge { message: String, limit: usize },
+
     /// Payload too large error
     #[error("Payload too large: {message}. Requested points: {requested}, maximum allowed: {max_allowed}")]
     PayloadTooLarge {
@@ -95,6 +131,72 @@ pub enum RossbyError {
         requested: usize,
         max_allowed: usize,
     },
+
+    /// Replayed requests whose responses differ from the recorded ones
+    #[error("Replay mismatch: {differing} of {replayed} replayed requests differ")]
+    ReplayMismatch { differing: usize, replayed: usize },
+
+    /// Benchmark cases slower than the baseline
+    #[error("Performance regression: {regressed} of {cases} benchmark cases are slower than the baseline")]
+    BenchRegression { regressed: usize, cases: usize },
+
+    /// Dataset larger than the configured memory budget
+    #[error(
+        "Memory budget exceeded: loading needs {} but the budget is {}. Largest variables: {}",
+        format_bytes(*required),
+        format_bytes(*budget),
+        largest_variables(largest)
+    )]
+    MemoryBudgetExceeded {
+        required: u64,
+        budget: u64,
+        largest: Vec<(String, u64)>,
+    },
+}
+
+/// `name (size)` list of the variables in a budget error
+fn largest_variables(largest: &[(String, u64)]) -> String {
+    largest
+        .iter()
+        .map(|(name, bytes)| format!("{} ({})", name, format_bytes(*bytes)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl RossbyError {
+    /// Short machine-readable name of the error kind, e.g. `variable_not_found`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RossbyError::NetCdf { .. } => "netcdf",
+            RossbyError::Conversion { .. } => "conversion",
+            RossbyError::Io(_) => "io",
+            RossbyError::Config { .. } => "config",
+            RossbyError::InvalidCoordinates { .. } => "invalid_coordinates",
+            RossbyError::PhysicalValueNotFound { .. } => "physical_value_not_found",
+            RossbyError::InvalidParameter { .. } => "invalid_parameter",
+            RossbyError::DataNotFound { .. } => "data_not_found",
+            RossbyError::VariableNotFound { .. } => "variable_not_found",
+            RossbyError::IndexOutOfBounds { .. } => "index_out_of_bounds",
+            RossbyError::Interpolation { .. } => "interpolation",
+            RossbyError::ImageGeneration { .. } => "image_generation",
+            RossbyError::InvalidVariables { .. } => "invalid_variables",
+            RossbyError::VariableNotSuitableForImage { .. } => "variable_not_suitable_for_image",
+            RossbyError::NoValidData { .. } => "no_valid_data",
+            RossbyError::CorruptData { .. } => "corrupt_data",
+            RossbyError::Json(_) => "json",
+            RossbyError::DimensionNotFound { .. } => "dimension_not_found",
+            RossbyError::Server { .. } => "server",
+            RossbyError::Peer { .. } => "peer",
+            RossbyError::QuotaExceeded { .. } => "quota_exceeded",
+            RossbyError::GenerationMismatch { .. } => "generation_mismatch",
+            RossbyError::Reference { .. } => "reference",
+            RossbyError::BodyTooLarge { .. } => "body_too_large",
+            RossbyError::PayloadTooLarge { .. } => "payload_too_large",
+            RossbyError::ReplayMismatch { .. } => "replay_mismatch",
+            RossbyError::BenchRegression { .. } => "bench_regression",
+            RossbyError::MemoryBudgetExceeded { .. } => "memory_budget_exceeded",
+        }
+    }
 }
 
 /// Convenience type alias for Results with RossbyError
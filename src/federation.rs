@@ -0,0 +1,252 @@
+//! Federation with peer rossby instances.
+//!
+//! A peer is another rossby server registered by name in `server.peers`.
+//! Comparison endpoints fetch the peer's horizontal slab of a variable through
+//! its public `/metadata` and `/data` (Arrow) endpoints and regrid it onto the
+//! local grid, so clients do not need to orchestrate both servers.
+
+use arrow::array::{Array, Float32Array};
+use arrow_ipc::reader::StreamReader;
+use ndarray::Array2;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, HorizontalField};
+
+/// Timeout applied to every request sent to a peer
+const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP client for a single peer rossby instance
+#[derive(Debug, Clone)]
+pub struct PeerClient {
+    name: String,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl PeerClient {
+    /// Create a client for the peer with the given name and base URL
+    pub fn new(name: &str, base_url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Name under which the peer is registered
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Fetch the peer's horizontal slab of a variable
+    ///
+    /// `fixed` maps non-horizontal dimension names to the physical coordinate
+    /// value to select on the peer (nearest match on the peer's side). Every
+    /// non-horizontal dimension of the peer's variable must be present.
+    pub async fn fetch_field(
+        &self,
+        var_name: &str,
+        fixed: &HashMap<String, f64>,
+    ) -> Result<HorizontalField> {
+        let metadata: serde_json::Value = self
+            .get("/metadata", &[])
+            .await?
+            .json()
+            .await
+            .map_err(|e| self.error(format!("Invalid metadata response: {}", e)))?;
+
+        let dimensions: Vec<String> = metadata["variables"][var_name]["dimensions"]
+            .as_array()
+            .ok_or_else(|| self.error(format!("Peer does not serve variable '{}'", var_name)))?
+            .iter()
+            .filter_map(|d| d.as_str().map(str::to_string))
+            .collect();
+
+        let (lat_axis, lon_axis) = find_lat_lon_axes(&dimensions).ok_or_else(|| {
+            self.error(format!(
+                "Peer variable '{}' has no latitude/longitude dimensions",
+                var_name
+            ))
+        })?;
+
+        let coordinate = |dim: &str| -> Result<Vec<f64>> {
+            metadata["coordinates"][dim]
+                .as_array()
+                .map(|values| values.iter().filter_map(|v| v.as_f64()).collect())
+                .ok_or_else(|| self.error(format!("Peer has no coordinates for '{}'", dim)))
+        };
+        let lat = coordinate(&dimensions[lat_axis])?;
+        let lon = coordinate(&dimensions[lon_axis])?;
+
+        // Pin every other dimension to a physical value
+        let mut query = vec![("vars".to_string(), var_name.to_string())];
+        for (axis, dim) in dimensions.iter().enumerate() {
+            if axis == lat_axis || axis == lon_axis {
+                continue;
+            }
+            let value = fixed.get(dim).ok_or_else(|| {
+                self.error(format!(
+                    "No local selection matches peer dimension '{}'",
+                    dim
+                ))
+            })?;
+            query.push((dim.clone(), value.to_string()));
+        }
+
+        let body = self
+            .get("/data", &query)
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| self.error(format!("Failed to read data response: {}", e)))?;
+        let (shape, values) = decode_arrow_variable(&body, var_name)?;
+
+        let mut values = Array2::from_shape_vec(
+            match shape.as_slice() {
+                [rows, cols] => (*rows, *cols),
+                _ => {
+                    return Err(self.error(format!(
+                        "Expected a 2D slab from peer, got shape {:?}",
+                        shape
+                    )))
+                }
+            },
+            values,
+        )?;
+        if lon_axis < lat_axis {
+            values = values.reversed_axes();
+        }
+
+        if values.shape() != [lat.len(), lon.len()] {
+            return Err(self.error(format!(
+                "Peer slab shape {:?} does not match its coordinates ({}, {})",
+                values.shape(),
+                lat.len(),
+                lon.len()
+            )));
+        }
+
+        Ok(HorizontalField { lat, lon, values })
+    }
+
+    async fn get(&self, path: &str, query: &[(String, String)]) -> Result<reqwest::Response> {
+        let url = format!("{}{}", self.base_url, path);
+        debug!(peer = %self.name, url = %url, query = ?query, "Requesting peer");
+
+        let response = self
+            .client
+            .get(&url)
+            .query(query)
+            .timeout(PEER_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| self.error(format!("Request to {} failed: {}", url, e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(self.error(format!("{} returned {}: {}", url, status, text)));
+        }
+
+        Ok(response)
+    }
+
+    fn error(&self, message: String) -> RossbyError {
+        RossbyError::Peer {
+            peer: self.name.clone(),
+            message,
+        }
+    }
+}
+
+/// Decode one variable column from a `/data` Arrow IPC stream
+///
+/// Returns the array shape recorded in the field metadata and the flattened values.
+pub fn decode_arrow_variable(bytes: &[u8], var_name: &str) -> Result<(Vec<usize>, Vec<f32>)> {
+    let reader = StreamReader::try_new(std::io::Cursor::new(bytes), None).map_err(|e| {
+        RossbyError::Conversion {
+            message: format!("Failed to read Arrow stream: {}", e),
+        }
+    })?;
+
+    let schema = reader.schema();
+    let (column_index, field) =
+        schema
+            .column_with_name(var_name)
+            .ok_or_else(|| RossbyError::VariableNotFound {
+                name: var_name.to_string(),
+            })?;
+
+    let shape: Vec<usize> = match field.metadata().get("shape") {
+        Some(shape) => serde_json::from_str(shape)?,
+        None => {
+            return Err(RossbyError::Conversion {
+                message: format!("Arrow field '{}' has no shape metadata", var_name),
+            })
+        }
+    };
+
+    let mut values = Vec::with_capacity(shape.iter().product());
+    for batch in reader {
+        let batch = batch.map_err(|e| RossbyError::Conversion {
+            message: format!("Failed to read Arrow record batch: {}", e),
+        })?;
+        let column = batch
+            .column(column_index)
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .ok_or_else(|| RossbyError::Conversion {
+                message: format!("Arrow column '{}' is not Float32", var_name),
+            })?;
+        values.extend((0..column.len()).map(|i| {
+            if column.is_null(i) {
+                f32::NAN
+            } else {
+                column.value(i)
+            }
+        }));
+    }
+
+    Ok((shape, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::ArrayRef;
+    use arrow::record_batch::RecordBatch;
+    use arrow_ipc::writer::StreamWriter;
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_decode_arrow_variable() {
+        let mut metadata = HashMap::new();
+        metadata.insert("shape".to_string(), "[2,2]".to_string());
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("lat", DataType::Float32, false),
+            Field::new("t2m", DataType::Float32, false).with_metadata(metadata),
+        ]));
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Float32Array::from(vec![0.0, 0.0, 1.0, 1.0])),
+            Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0, 4.0])),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+
+        let mut bytes = Vec::new();
+        {
+            let mut writer = StreamWriter::try_new(&mut bytes, &schema).unwrap();
+            writer.write(&batch).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let (shape, values) = decode_arrow_variable(&bytes, "t2m").unwrap();
+        assert_eq!(shape, vec![2, 2]);
+        assert_eq!(values, vec![1.0, 2.0, 3.0, 4.0]);
+
+        assert!(decode_arrow_variable(&bytes, "missing").is_err());
+    }
+}
@@ -0,0 +1,526 @@
+//! Two-dimensional horizontal fields used by the analysis endpoints.
+//!
+//! A `HorizontalField` is a latitude/longitude slab of one variable with all
+//! other dimensions pinned to a single index. It is the common currency of
+//! `/stats` and `/diff`: it can be extracted from the local dataset, decoded
+//! from a peer, regridded onto another grid, and summarized.
+
+use ndarray::{Array2, Axis};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::error::{Result, RossbyError};
+use crate::geometry::{lon_indices, range_indices, unwrap_longitudes, wrap_into_range};
+use crate::query::Selection;
+use crate::slice_stats::{summarize, MissingData};
+use crate::state::AppState;
+
+/// Dimension names recognized as latitude
+pub const LAT_NAMES: [&str; 2] = ["lat", "latitude"];
+
+/// Dimension names recognized as longitude
+pub const LON_NAMES: [&str; 2] = ["lon", "longitude"];
+
+/// A 2D field on a rectilinear latitude/longitude grid
+#[derive(Debug, Clone)]
+pub struct HorizontalField {
+    /// Latitude coordinates (one per row)
+    pub lat: Vec<f64>,
+    /// Longitude coordinates (one per column)
+    pub lon: Vec<f64>,
+    /// Values with shape `[lat.len(), lon.len()]`
+    pub values: Array2<f32>,
+}
+
+/// Summary statistics over the finite values of a field
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FieldStats {
+    /// Number of finite values
+    pub count: usize,
+    /// Minimum finite value
+    pub min: Option<f64>,
+    /// Maximum finite value
+    pub max: Option<f64>,
+    /// Arithmetic mean
+    pub mean: Option<f64>,
+    /// Population standard deviation
+    pub std: Option<f64>,
+}
+
+impl FieldStats {
+    /// Smallest and largest finite value, as the color range of a rendering
+    ///
+    /// A field without finite values has no range and cannot be rendered;
+    /// `name` identifies it in the error. A constant field has a range of a
+    /// single value, which colormaps draw in one flat color.
+    pub fn value_range(&self, name: &str) -> Result<(f64, f64)> {
+        self.min
+            .zip(self.max)
+            .ok_or_else(|| RossbyError::NoValidData {
+                name: name.to_string(),
+            })
+    }
+}
+
+/// Statistics comparing a field against a reference on the same grid
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ComparisonStats {
+    /// Number of grid points where both fields are finite
+    pub count: usize,
+    /// Mean of (field - reference)
+    pub bias: Option<f64>,
+    /// Mean absolute difference
+    pub mae: Option<f64>,
+    /// Root mean square difference
+    pub rmse: Option<f64>,
+    /// Pearson correlation coefficient
+    pub correlation: Option<f64>,
+}
+
+/// Find the positions of the latitude and longitude dimensions in a dimension list
+pub fn find_lat_lon_axes(dimensions: &[String]) -> Option<(usize, usize)> {
+    let lat = dimensions
+        .iter()
+        .position(|d| LAT_NAMES.contains(&d.as_str()))?;
+    let lon = dimensions
+        .iter()
+        .position(|d| LON_NAMES.contains(&d.as_str()))?;
+    Some((lat, lon))
+}
+
+/// Resolve the index to use for every non-horizontal dimension of a variable
+///
+/// Each dimension may be pinned to a single slice by any selector accepted by
+/// [`Selection`]. Dimensions without a selection use index 0, and selecting
+/// the horizontal dimensions is an error (use a bounding box instead).
+pub fn resolve_dimension_indices(
+    state: &AppState,
+    var_name: &str,
+    params: &HashMap<String, String>,
+) -> Result<HashMap<String, usize>> {
+    let var_meta = state.get_variable_metadata_checked(var_name)?;
+    let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+        RossbyError::VariableNotSuitableForImage {
+            name: var_name.to_string(),
+        }
+    })?;
+    let selection = Selection::parse(state, params)?;
+
+    for selected in selection.iter() {
+        let axis = var_meta
+            .dimensions
+            .iter()
+            .position(|d| *d == selected.dimension);
+        if axis.is_none() || axis == Some(lat_axis) || axis == Some(lon_axis) {
+            return Err(RossbyError::InvalidParameter {
+                param: selected.param.clone(),
+                message: format!(
+                    "Dimension '{}' cannot be selected for '{}'; only non-horizontal \
+                     dimensions of the variable can be pinned",
+                    selected.dimension, var_name
+                ),
+            });
+        }
+    }
+
+    let mut indices = HashMap::new();
+    for (axis, dim_name) in var_meta.dimensions.iter().enumerate() {
+        if axis == lat_axis || axis == lon_axis {
+            continue;
+        }
+        let index = match selection.get(dim_name) {
+            Some(selected) => selected.resolve_single(state)?,
+            None => 0,
+        };
+        indices.insert(dim_name.clone(), index);
+    }
+
+    Ok(indices)
+}
+
+impl HorizontalField {
+    /// Extract a field from the loaded dataset
+    ///
+    /// `dim_indices` pins every non-horizontal dimension (missing entries use
+    /// index 0). If `bbox` is given as `(min_lon, min_lat, max_lon, max_lat)`
+    /// only grid points inside it are kept. Missing values (see
+    /// [`MissingData`]) are replaced by NaN.
+    pub fn from_state(
+        state: &AppState,
+        var_name: &str,
+        dim_indices: &HashMap<String, usize>,
+        bbox: Option<(f64, f64, f64, f64)>,
+    ) -> Result<Self> {
+        let var_meta = state.get_variable_metadata_checked(var_name)?;
+        let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+            RossbyError::VariableNotSuitableForImage {
+                name: var_name.to_string(),
+            }
+        })?;
+
+        let lat_coords = state.get_coordinate_checked(&var_meta.dimensions[lat_axis])?;
+        let lon_coords = state.get_coordinate_checked(&var_meta.dimensions[lon_axis])?;
+
+        // Remove the non-horizontal axes; packed values are converted for the
+        // remaining slab only
+        let pins: Vec<(usize, usize)> = var_meta
+            .dimensions
+            .iter()
+            .enumerate()
+            .filter(|&(axis, _)| axis != lat_axis && axis != lon_axis)
+            .map(|(axis, dim_name)| (axis, dim_indices.get(dim_name).copied().unwrap_or(0)))
+            .collect();
+        let data = state.get_variable_indexed(var_name, &pins)?;
+        let view = data.view();
+
+        let mut values = view
+            .into_dimensionality::<ndarray::Ix2>()
+            .map_err(|e| RossbyError::Conversion {
+                message: format!("Expected a 2D latitude/longitude slab: {}", e),
+            })?
+            .to_owned();
+        if lon_axis < lat_axis {
+            values = values.reversed_axes();
+        }
+        MissingData::for_variable(var_meta).mask(values.iter_mut());
+
+        let field = Self {
+            lat: lat_coords.clone(),
+            lon: lon_coords.clone(),
+            values,
+        };
+
+        Ok(match bbox {
+            Some(bbox) => field.crop(bbox),
+            None => field,
+        })
+    }
+
+    /// Keep only the grid points inside `(min_lon, min_lat, max_lon, max_lat)`
+    ///
+    /// A box crossing the seam of the longitude axis (see [`crate::geometry`])
+    /// keeps the columns on both sides of it, with their longitudes made
+    /// continuous across the seam so the field can still be sampled.
+    pub fn crop(&self, (min_lon, min_lat, max_lon, max_lat): (f64, f64, f64, f64)) -> Self {
+        let rows = range_indices(&self.lat, min_lat, max_lat);
+        let cols = lon_indices(&self.lon, min_lon, max_lon);
+
+        let values = self.values.select(Axis(0), &rows).select(Axis(1), &cols);
+        let lon: Vec<f64> = cols.iter().map(|&j| self.lon[j]).collect();
+        Self {
+            lat: rows.iter().map(|&i| self.lat[i]).collect(),
+            lon: unwrap_longitudes(&lon),
+            values,
+        }
+    }
+
+    /// Bilinearly sample the field at a physical location
+    ///
+    /// Longitudes are wrapped by multiples of 360 degrees to fall inside the
+    /// field's longitude range. Returns NaN outside the grid.
+    pub fn sample(&self, lat: f64, lon: f64) -> f32 {
+        let lon = wrap_into_range(lon, &self.lon);
+        let (Some((i0, i1, wy)), Some((j0, j1, wx))) =
+            (bracket(&self.lat, lat), bracket(&self.lon, lon))
+        else {
+            return f32::NAN;
+        };
+
+        let v00 = self.values[[i0, j0]] as f64;
+        let v01 = self.values[[i0, j1]] as f64;
+        let v10 = self.values[[i1, j0]] as f64;
+        let v11 = self.values[[i1, j1]] as f64;
+
+        let top = v00 * (1.0 - wx) + v01 * wx;
+        let bottom = v10 * (1.0 - wx) + v11 * wx;
+        (top * (1.0 - wy) + bottom * wy) as f32
+    }
+
+    /// Sample the value of the grid point nearest a physical location
+    ///
+    /// Unlike [`HorizontalField::sample`] this never blends values, so codes
+    /// of categorical fields stay valid. Returns NaN outside the grid.
+    pub fn sample_nearest(&self, lat: f64, lon: f64) -> f32 {
+        let lon = wrap_into_range(lon, &self.lon);
+        let nearest = |(lower, upper, weight): (usize, usize, f64)| {
+            if weight < 0.5 {
+                lower
+            } else {
+                upper
+            }
+        };
+        match (bracket(&self.lat, lat), bracket(&self.lon, lon)) {
+            (Some(i), Some(j)) => self.values[[nearest(i), nearest(j)]],
+            _ => f32::NAN,
+        }
+    }
+
+    /// Regrid this field onto another latitude/longitude grid by nearest
+    /// neighbour
+    pub fn regrid_nearest(&self, lat: &[f64], lon: &[f64]) -> Self {
+        let values = Array2::from_shape_fn((lat.len(), lon.len()), |(i, j)| {
+            self.sample_nearest(lat[i], lon[j])
+        });
+        Self {
+            lat: lat.to_vec(),
+            lon: lon.to_vec(),
+            values,
+        }
+    }
+
+    /// Bilinearly regrid this field onto another latitude/longitude grid
+    pub fn regrid(&self, lat: &[f64], lon: &[f64]) -> Self {
+        let values =
+            Array2::from_shape_fn((lat.len(), lon.len()), |(i, j)| self.sample(lat[i], lon[j]));
+        Self {
+            lat: lat.to_vec(),
+            lon: lon.to_vec(),
+            values,
+        }
+    }
+
+    /// Subtract a reference field defined on the same grid
+    pub fn difference(&self, reference: &HorizontalField) -> Result<Self> {
+        if self.values.shape() != reference.values.shape() {
+            return Err(RossbyError::Conversion {
+                message: format!(
+                    "Cannot subtract fields with shapes {:?} and {:?}",
+                    self.values.shape(),
+                    reference.values.shape()
+                ),
+            });
+        }
+        Ok(Self {
+            lat: self.lat.clone(),
+            lon: self.lon.clone(),
+            values: &self.values - &reference.values,
+        })
+    }
+
+    /// Summary statistics over the finite values of the field
+    pub fn stats(&self) -> FieldStats {
+        summarize(self.values.iter().copied(), &MissingData::default())
+    }
+
+    /// Compare this field against a reference on the same grid
+    pub fn compare(&self, reference: &HorizontalField) -> Result<ComparisonStats> {
+        if self.values.shape() != reference.values.shape() {
+            return Err(RossbyError::Conversion {
+                message: format!(
+                    "Cannot compare fields with shapes {:?} and {:?}",
+                    self.values.shape(),
+                    reference.values.shape()
+                ),
+            });
+        }
+
+        let pairs: Vec<(f64, f64)> = self
+            .values
+            .iter()
+            .zip(reference.values.iter())
+            .filter(|(a, b)| a.is_finite() && b.is_finite())
+            .map(|(&a, &b)| (a as f64, b as f64))
+            .collect();
+
+        if pairs.is_empty() {
+            return Ok(ComparisonStats {
+                count: 0,
+                bias: None,
+                mae: None,
+                rmse: None,
+                correlation: None,
+            });
+        }
+
+        let n = pairs.len() as f64;
+        let bias = pairs.iter().map(|(a, b)| a - b).sum::<f64>() / n;
+        let mae = pairs.iter().map(|(a, b)| (a - b).abs()).sum::<f64>() / n;
+        let rmse = (pairs.iter().map(|(a, b)| (a - b).powi(2)).sum::<f64>() / n).sqrt();
+
+        let mean_a = pairs.iter().map(|(a, _)| a).sum::<f64>() / n;
+        let mean_b = pairs.iter().map(|(_, b)| b).sum::<f64>() / n;
+        let cov = pairs
+            .iter()
+            .map(|(a, b)| (a - mean_a) * (b - mean_b))
+            .sum::<f64>();
+        let var_a = pairs.iter().map(|(a, _)| (a - mean_a).powi(2)).sum::<f64>();
+        let var_b = pairs.iter().map(|(_, b)| (b - mean_b).powi(2)).sum::<f64>();
+        let correlation = if var_a > 0.0 && var_b > 0.0 {
+            Some(cov / (var_a.sqrt() * var_b.sqrt()))
+        } else {
+            None
+        };
+
+        Ok(ComparisonStats {
+            count: pairs.len(),
+            bias: Some(bias),
+            mae: Some(mae),
+            rmse: Some(rmse),
+            correlation,
+        })
+    }
+
+    /// Field values as nested JSON rows, with non-finite values as null
+    pub fn values_to_json(&self) -> serde_json::Value {
+        self.values
+            .outer_iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&v| {
+                        serde_json::Number::from_f64(v as f64)
+                            .map(serde_json::Value::Number)
+                            .unwrap_or(serde_json::Value::Null)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// Find the two coordinates bracketing `x` and the weight of the second one
+///
+/// Works for ascending and descending coordinate arrays. Returns `None` if `x`
+/// lies outside the coordinate range.
+fn bracket(coords: &[f64], x: f64) -> Option<(usize, usize, f64)> {
+    let n = coords.len();
+    if n == 0 || !x.is_finite() {
+        return None;
+    }
+    if n == 1 {
+        return ((coords[0] - x).abs() < f64::EPSILON).then_some((0, 0, 0.0));
+    }
+
+    let ascending = coords[n - 1] >= coords[0];
+    let (lo, hi) = if ascending {
+        (coords[0], coords[n - 1])
+    } else {
+        (coords[n - 1], coords[0])
+    };
+    if x < lo || x > hi {
+        return None;
+    }
+
+    // First index whose coordinate is past x in the direction of the axis
+    let upper = if ascending {
+        coords.partition_point(|&c| c < x)
+    } else {
+        coords.partition_point(|&c| c > x)
+    };
+
+    if upper == 0 {
+        return Some((0, 0, 0.0));
+    }
+    let i1 = upper.min(n - 1);
+    let i0 = i1 - 1;
+    let span = coords[i1] - coords[i0];
+    let weight = if span == 0.0 {
+        0.0
+    } else {
+        ((x - coords[i0]) / span).clamp(0.0, 1.0)
+    };
+    Some((i0, i1, weight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn ramp_field() -> HorizontalField {
+        // value = lat + lon on a 3x4 grid
+        let lat = vec![0.0, 10.0, 20.0];
+        let lon = vec![0.0, 10.0, 20.0, 30.0];
+        let values = Array2::from_shape_fn((3, 4), |(i, j)| (lat[i] + lon[j]) as f32);
+        HorizontalField { lat, lon, values }
+    }
+
+    #[test]
+    fn test_bracket() {
+        let ascending = [0.0, 10.0, 20.0];
+        assert_eq!(bracket(&ascending, 5.0), Some((0, 1, 0.5)));
+        assert_eq!(bracket(&ascending, 20.0), Some((1, 2, 1.0)));
+        assert_eq!(bracket(&ascending, 0.0), Some((0, 0, 0.0)));
+        assert_eq!(bracket(&ascending, 25.0), None);
+
+        let descending = [20.0, 10.0, 0.0];
+        assert_eq!(bracket(&descending, 15.0), Some((0, 1, 0.5)));
+        assert_eq!(bracket(&descending, -1.0), None);
+    }
+
+    #[test]
+    fn test_nearest_regrid() {
+        let field = ramp_field();
+        assert_eq!(field.sample_nearest(4.0, 16.0), 20.0);
+        assert_eq!(field.sample_nearest(6.0, 14.0), 20.0);
+        assert!(field.sample_nearest(30.0, 15.0).is_nan());
+
+        let regridded = field.regrid_nearest(&[2.0, 18.0], &[1.0, 29.0]);
+        assert_eq!(regridded.values, array![[0.0, 30.0], [20.0, 50.0]]);
+    }
+
+    #[test]
+    fn test_sample_and_regrid() {
+        let field = ramp_field();
+        assert!((field.sample(5.0, 15.0) - 20.0).abs() < 1e-5);
+        assert!(field.sample(30.0, 15.0).is_nan());
+
+        // Longitude given in the -180..180 convention wraps into 0..360
+        let wrapped = HorizontalField {
+            lat: vec![0.0, 10.0],
+            lon: vec![0.0, 180.0, 350.0],
+            values: array![[0.0, 1.0, 2.0], [0.0, 1.0, 2.0]],
+        };
+        assert!((wrapped.sample(0.0, -10.0) - 2.0).abs() < 1e-5);
+
+        let regridded = field.regrid(&[5.0, 15.0], &[5.0, 25.0]);
+        assert_eq!(regridded.values.shape(), &[2, 2]);
+        assert!((regridded.values[[1, 1]] - 40.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_crop() {
+        let field = ramp_field().crop((10.0, 0.0, 20.0, 10.0));
+        assert_eq!(field.lat, vec![0.0, 10.0]);
+        assert_eq!(field.lon, vec![10.0, 20.0]);
+        assert_eq!(field.values, array![[10.0, 20.0], [20.0, 30.0]]);
+    }
+
+    #[test]
+    fn test_stats_and_compare() {
+        let field = HorizontalField {
+            lat: vec![0.0, 1.0],
+            lon: vec![0.0, 1.0],
+            values: array![[1.0, 2.0], [3.0, f32::NAN]],
+        };
+        let stats = field.stats();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.min, Some(1.0));
+        assert_eq!(stats.max, Some(3.0));
+        assert_eq!(stats.mean, Some(2.0));
+        assert_eq!(stats.value_range("t").unwrap(), (1.0, 3.0));
+
+        let missing = HorizontalField {
+            values: array![[f32::NAN, f32::NAN], [f32::NAN, f32::NAN]],
+            ..field.clone()
+        };
+        assert!(matches!(
+            missing.stats().value_range("t"),
+            Err(RossbyError::NoValidData { .. })
+        ));
+
+        let reference = HorizontalField {
+            values: array![[0.0, 1.0], [2.0, 5.0]],
+            ..field.clone()
+        };
+        let comparison = field.compare(&reference).unwrap();
+        assert_eq!(comparison.count, 3);
+        assert_eq!(comparison.bias, Some(1.0));
+        assert_eq!(comparison.rmse, Some(1.0));
+        assert!((comparison.correlation.unwrap() - 1.0).abs() < 1e-12);
+
+        let diff = field.difference(&reference).unwrap();
+        assert_eq!(diff.values[[0, 0]], 1.0);
+        assert!(diff.values[[1, 1]].is_nan());
+    }
+}
@@ -0,0 +1,274 @@
+//! Cell filters on companion variables.
+//!
+//! Satellite products ship quality flags next to every retrieved field, and
+//! values whose flags mark them as bad must not be used. `/data` accepts a
+//! `filter` such as `qc_flag==0`: cells where the condition does not hold on
+//! the companion variable are masked to NaN (`null` in JSON), or left out of
+//! long-format Arrow files altogether.
+//!
+//! A filter is a comma-separated list of conditions that must all hold, each
+//! comparing a variable with `==`, `!=`, `<`, `<=`, `>` or `>=` to a number
+//! or to one of the `flag_meanings` of the variable (e.g. `qc_flag==good`).
+//! Cells whose filter values are missing (NaN) never match.
+
+use ndarray::{Array, IxDyn, Zip};
+
+use crate::categories::variable_categories;
+use crate::error::{Result, RossbyError};
+use crate::state::AppState;
+
+/// Name of the query parameter holding a filter
+pub const FILTER_PARAM: &str = "filter";
+
+/// Comparison operators, two-character ones first so they match greedily
+const OPERATORS: [(&str, Comparison); 7] = [
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    ("<=", Comparison::Le),
+    (">=", Comparison::Ge),
+    ("<", Comparison::Lt),
+    (">", Comparison::Gt),
+    ("=", Comparison::Eq),
+];
+
+/// How a condition compares a variable with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A comparison of a variable with a constant
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    /// Variable the condition is evaluated on
+    pub var: String,
+    /// Comparison operator
+    pub comparison: Comparison,
+    /// Value compared with
+    pub value: f64,
+}
+
+impl Condition {
+    /// Whether a value of the variable satisfies the condition
+    pub fn matches(&self, value: f32) -> bool {
+        let value = value as f64;
+        match self.comparison {
+            Comparison::Eq => value == self.value,
+            Comparison::Ne => !value.is_nan() && value != self.value,
+            Comparison::Lt => value < self.value,
+            Comparison::Le => value <= self.value,
+            Comparison::Gt => value > self.value,
+            Comparison::Ge => value >= self.value,
+        }
+    }
+}
+
+/// Conditions that must all hold for a cell to be kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellFilter {
+    conditions: Vec<Condition>,
+}
+
+impl CellFilter {
+    /// Parse a filter, checking its variables against the dataset
+    pub fn parse(state: &AppState, text: &str) -> Result<Self> {
+        let conditions = text
+            .split(',')
+            .map(str::trim)
+            .filter(|condition| !condition.is_empty())
+            .map(|condition| parse_condition(state, condition))
+            .collect::<Result<Vec<_>>>()?;
+        if conditions.is_empty() {
+            return Err(invalid("Filter has no conditions".to_string()));
+        }
+        Ok(Self { conditions })
+    }
+
+    /// Conditions of the filter, in the order given
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    /// Cells of an array of `shape` that pass the filter
+    ///
+    /// `values` returns the values of a filter variable for the same cells;
+    /// they must have the same shape.
+    pub fn mask(
+        &self,
+        shape: &[usize],
+        mut values: impl FnMut(&str) -> Result<Array<f32, IxDyn>>,
+    ) -> Result<Array<bool, IxDyn>> {
+        let mut mask = Array::from_elem(IxDyn(shape), true);
+        for condition in &self.conditions {
+            let values = values(&condition.var)?;
+            if values.shape() != shape {
+                return Err(invalid(format!(
+                    "Filter variable '{}' has shape {:?} where {:?} is needed",
+                    condition.var,
+                    values.shape(),
+                    shape
+                )));
+            }
+            Zip::from(&mut mask)
+                .and(&values)
+                .for_each(|keep, &value| *keep &= condition.matches(value));
+        }
+        Ok(mask)
+    }
+}
+
+/// Mask the values of cells that do not pass a filter to NaN
+pub fn apply_mask(array: &mut Array<f32, IxDyn>, mask: &Array<bool, IxDyn>) {
+    Zip::from(array).and(mask).for_each(|value, &keep| {
+        if !keep {
+            *value = f32::NAN;
+        }
+    });
+}
+
+/// Parse one condition such as `qc_flag==0`
+fn parse_condition(state: &AppState, condition: &str) -> Result<Condition> {
+    let (position, symbol, comparison) = condition
+        .char_indices()
+        .find_map(|(position, _)| {
+            OPERATORS
+                .iter()
+                .find(|(symbol, _)| condition[position..].starts_with(symbol))
+                .map(|&(symbol, comparison)| (position, symbol, comparison))
+        })
+        .ok_or_else(|| {
+            invalid(format!(
+                "Invalid condition '{}'. Expected <variable><operator><value> with one of ==, !=, <, <=, >, >=",
+                condition
+            ))
+        })?;
+    let var = condition[..position].trim();
+    let raw = condition[position + symbol.len()..].trim();
+
+    let Some(meta) = state.metadata.variables.get(var) else {
+        return Err(invalid(format!(
+            "Unknown variable '{}' in filter condition '{}'",
+            var, condition
+        )));
+    };
+    let value = match raw.parse::<f64>() {
+        Ok(value) if value.is_finite() => value,
+        _ => variable_categories(&state.config, var, meta)
+            .and_then(|categories| {
+                categories
+                    .into_iter()
+                    .find(|category| category.meaning == raw)
+            })
+            .map(|category| category.value)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "Invalid value '{}' in filter condition '{}'. Expected a number or a flag meaning of '{}'",
+                    raw, condition, var
+                ))
+            })?,
+    };
+    Ok(Condition {
+        var: var.to_string(),
+        comparison,
+        value,
+    })
+}
+
+fn invalid(message: String) -> RossbyError {
+    RossbyError::InvalidParameter {
+        param: FILTER_PARAM.to_string(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{AttributeValue, Metadata, Variable};
+    use std::collections::HashMap;
+
+    fn create_test_state() -> AppState {
+        let variable = |attributes: HashMap<String, AttributeValue>| Variable {
+            name: String::new(),
+            dimensions: vec!["x".to_string()],
+            shape: vec![4],
+            attributes,
+            dtype: "f32".to_string(),
+        };
+        let flag_attributes = HashMap::from([
+            (
+                "flag_values".to_string(),
+                AttributeValue::IntegerArray(vec![0, 1, 2]),
+            ),
+            (
+                "flag_meanings".to_string(),
+                AttributeValue::Text("good suspect bad".to_string()),
+            ),
+        ]);
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::new(),
+            variables: HashMap::from([
+                ("sst".to_string(), variable(HashMap::new())),
+                ("qc_flag".to_string(), variable(flag_attributes)),
+            ]),
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        };
+        AppState::new(Config::default(), metadata, HashMap::new())
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let state = create_test_state();
+        let filter = CellFilter::parse(&state, "qc_flag==0").unwrap();
+        assert_eq!(
+            filter.conditions(),
+            [Condition {
+                var: "qc_flag".to_string(),
+                comparison: Comparison::Eq,
+                value: 0.0,
+            }]
+        );
+        let filter = CellFilter::parse(&state, "qc_flag <= suspect, sst>-2.5").unwrap();
+        assert_eq!(filter.conditions()[0].comparison, Comparison::Le);
+        assert_eq!(filter.conditions()[0].value, 1.0);
+        assert_eq!(filter.conditions()[1].comparison, Comparison::Gt);
+        assert_eq!(filter.conditions()[1].value, -2.5);
+
+        for invalid in ["", "qc_flag", "missing==0", "qc_flag==unknown", "sst==nan"] {
+            assert!(
+                CellFilter::parse(&state, invalid).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_filter_mask() {
+        let state = create_test_state();
+        let filter = CellFilter::parse(&state, "qc_flag!=2,sst>=0").unwrap();
+        let qc = Array::from_shape_vec(IxDyn(&[4]), vec![0.0, 2.0, 1.0, f32::NAN]).unwrap();
+        let sst = Array::from_shape_vec(IxDyn(&[4]), vec![1.0, 1.0, -1.0, 1.0]).unwrap();
+        let mask = filter
+            .mask(&[4], |var| {
+                Ok(if var == "qc_flag" { &qc } else { &sst }.clone())
+            })
+            .unwrap();
+        assert_eq!(mask.as_slice().unwrap(), [true, false, false, false]);
+
+        let mut values = sst.clone();
+        apply_mask(&mut values, &mask);
+        assert_eq!(values[0], 1.0);
+        assert!(values.iter().skip(1).all(|v| v.is_nan()));
+
+        assert!(filter.mask(&[2, 2], |_| Ok(qc.clone())).is_err());
+    }
+}
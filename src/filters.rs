@@ -0,0 +1,208 @@
+//! Spatial smoothing filters.
+//!
+//! `/image` and `/data` accept `smooth=gaussian:<sigma>` or
+//! `smooth=boxcar:<width>`, which smooth the selected slice along its
+//! latitude and longitude axes before it is rendered or serialized, e.g. for
+//! clean contours of pressure fields. Sizes are in grid cells: a Gaussian
+//! kernel of standard deviation `sigma`, truncated at three standard
+//! deviations, or a moving average over an odd `width` of cells. (Cell
+//! filters on companion variables are in [`crate::filter`].)
+//!
+//! Both kernels are separable, so a slice is smoothed one axis at a time.
+//! Missing values are left out of the averages, whose weights are
+//! renormalized over the valid cells in reach, and stay missing; cells near
+//! the edges of the slice are averaged over the part of the kernel inside it.
+
+use ndarray::{Array, Axis, Dimension, Zip};
+
+use crate::error::{Result, RossbyError};
+
+/// Name of the query parameter selecting a smoothing filter
+pub const SMOOTH_PARAM: &str = "smooth";
+
+/// Largest reach of a kernel, in cells on either side of its center
+const MAX_RADIUS: usize = 100;
+
+/// A spatial smoothing filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// Gaussian kernel of a standard deviation in grid cells
+    Gaussian { sigma: f64 },
+    /// Moving average over an odd number of grid cells
+    Boxcar { width: usize },
+}
+
+impl Smoothing {
+    /// Parse a `smooth` parameter, `gaussian:<sigma>` or `boxcar:<width>`
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = |message: String| RossbyError::InvalidParameter {
+            param: SMOOTH_PARAM.to_string(),
+            message,
+        };
+        let (kind, size) = value.split_once(':').ok_or_else(|| {
+            invalid(format!(
+                "Invalid filter: {}. Must be 'gaussian:<sigma>' or 'boxcar:<width>'",
+                value
+            ))
+        })?;
+        match kind.trim().to_lowercase().as_str() {
+            "gaussian" => {
+                let sigma: f64 = size.trim().parse().map_err(|_| {
+                    invalid(format!("Invalid Gaussian standard deviation: {}", size))
+                })?;
+                let reach = MAX_RADIUS as f64 / 3.0;
+                if !(sigma > 0.0 && sigma <= reach) {
+                    return Err(invalid(format!(
+                        "Gaussian standard deviation must be above 0 and at most {:.1} cells, \
+                         got {}",
+                        reach, sigma
+                    )));
+                }
+                Ok(Smoothing::Gaussian { sigma })
+            }
+            "boxcar" => {
+                let width: usize = size
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid(format!("Invalid boxcar width: {}", size)))?;
+                if width.is_multiple_of(2) || width > 2 * MAX_RADIUS + 1 {
+                    return Err(invalid(format!(
+                        "Boxcar width must be an odd number of cells up to {}, got {}",
+                        2 * MAX_RADIUS + 1,
+                        width
+                    )));
+                }
+                Ok(Smoothing::Boxcar { width })
+            }
+            other => Err(invalid(format!(
+                "Unknown filter: {}. Valid filters are 'gaussian' and 'boxcar'",
+                other
+            ))),
+        }
+    }
+
+    /// Weights of the kernel, centered on its middle element
+    fn weights(&self) -> Vec<f64> {
+        match *self {
+            Smoothing::Gaussian { sigma } => {
+                let radius = (3.0 * sigma).ceil() as i64;
+                (-radius..=radius)
+                    .map(|offset| (-0.5 * (offset as f64 / sigma).powi(2)).exp())
+                    .collect()
+            }
+            Smoothing::Boxcar { width } => vec![1.0; width],
+        }
+    }
+
+    /// Smooth an array along the given axes in place
+    ///
+    /// Missing (non-finite) values are skipped and left as they are.
+    pub fn apply<D: Dimension>(&self, values: &mut Array<f32, D>, axes: &[usize]) {
+        let weights = self.weights();
+        let mut sums = values.mapv(|value| if value.is_finite() { value as f64 } else { 0.0 });
+        let mut counts = values.mapv(|value| if value.is_finite() { 1.0 } else { 0.0 });
+        for &axis in axes {
+            convolve_axis(&mut sums, Axis(axis), &weights);
+            convolve_axis(&mut counts, Axis(axis), &weights);
+        }
+        Zip::from(values)
+            .and(&sums)
+            .and(&counts)
+            .for_each(|value, &sum, &count| {
+                if value.is_finite() && count > 0.0 {
+                    *value = (sum / count) as f32;
+                }
+            });
+    }
+}
+
+/// Convolve every lane of an array along an axis with a centered kernel,
+/// treating values beyond the ends as zero
+fn convolve_axis<D: Dimension>(values: &mut Array<f64, D>, axis: Axis, weights: &[f64]) {
+    let radius = weights.len() / 2;
+    let mut lane_values = Vec::new();
+    for mut lane in values.lanes_mut(axis) {
+        lane_values.clear();
+        lane_values.extend(lane.iter().copied());
+        let len = lane_values.len();
+        for (i, out) in lane.iter_mut().enumerate() {
+            let first = i.saturating_sub(radius);
+            let last = (i + radius).min(len.saturating_sub(1));
+            *out = (first..=last)
+                .map(|j| lane_values[j] * weights[j + radius - i])
+                .sum();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, Array2, Array3};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Smoothing::parse("gaussian:2").unwrap(),
+            Smoothing::Gaussian { sigma: 2.0 }
+        );
+        assert_eq!(
+            Smoothing::parse("Boxcar:3").unwrap(),
+            Smoothing::Boxcar { width: 3 }
+        );
+        for invalid in [
+            "gaussian",
+            "gaussian:0",
+            "gaussian:-1",
+            "gaussian:NaN",
+            "gaussian:40",
+            "boxcar:4",
+            "boxcar:1.5",
+            "boxcar:203",
+            "median:3",
+        ] {
+            assert!(Smoothing::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_boxcar() {
+        let mut values = array![[1.0f32, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]];
+        Smoothing::Boxcar { width: 3 }.apply(&mut values, &[1]);
+        // Edge cells average over the part of the window inside the array
+        assert_eq!(
+            values,
+            array![[1.5f32, 2.0, 3.0, 3.5], [5.5, 6.0, 7.0, 7.5]]
+        );
+
+        // Missing values are skipped and stay missing
+        let mut values = array![[1.0f32, f32::NAN, 3.0], [f32::NAN, 5.0, 9.0]];
+        Smoothing::Boxcar { width: 3 }.apply(&mut values, &[0, 1]);
+        assert_eq!(values[[0, 0]], 3.0);
+        assert!(values[[0, 1]].is_nan() && values[[1, 0]].is_nan());
+        assert!((values[[1, 2]] - 17.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_gaussian() {
+        // A constant field is left unchanged, edges included
+        let mut constant = Array2::from_elem((5, 7), 2.5f32);
+        Smoothing::Gaussian { sigma: 1.5 }.apply(&mut constant, &[0, 1]);
+        assert!(constant.iter().all(|&value| (value - 2.5).abs() < 1e-6));
+
+        // A spike spreads symmetrically, keeping its peak in place, and only
+        // along the smoothed axes
+        let mut spike = Array3::<f32>::zeros((2, 13, 13));
+        spike[[1, 6, 6]] = 1.0;
+        Smoothing::Gaussian { sigma: 1.0 }.apply(&mut spike, &[1, 2]);
+        assert!(spike
+            .index_axis(Axis(0), 0)
+            .iter()
+            .all(|&value| value == 0.0));
+        let plane = spike.index_axis(Axis(0), 1);
+        assert!(plane[[6, 6]] > plane[[6, 7]] && plane[[6, 7]] > plane[[6, 8]]);
+        assert_eq!(plane[[5, 6]], plane[[7, 6]]);
+        assert_eq!(plane[[6, 5]], plane[[5, 6]]);
+        assert!((plane.sum() - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,777 @@
+//! Router mode for a fleet of instances sharded by time.
+//!
+//! A record too long for one instance can be split by time range across
+//! several rossby instances, e.g. one per year, that share nothing.
+//! `rossby route --manifest fleet.json` starts a lightweight instance that
+//! holds no data and forwards each request to the backends whose time range
+//! it selects:
+//!
+//! ```json
+//! {
+//!   "time_dimension": "time",
+//!   "backends": [
+//!     {"name": "2022", "url": "http://rossby-2022:8000", "start": 0, "end": 8759},
+//!     {"name": "2023", "url": "http://rossby-2023:8000", "start": 8760, "end": 17519}
+//!   ]
+//! }
+//! ```
+//!
+//! `start` and `end` are the first and last time coordinate values a backend
+//! serves, in the time units the backends share. Time is read from the
+//! `<time>=<value>[,...]` and `<time>_range=<start>,<end>[,<step>]` selectors
+//! (see [`crate::query`]), with `<time>` the manifest's `time_dimension` or
+//! `time`, optionally prefixed with `_`:
+//!
+//! - values are sent to the backend serving them (the nearest one for values
+//!   between backends), and ranges to every backend they overlap, clipped to
+//!   its time range, so a `<step>` applies within each backend,
+//! - `/profile_series` without a time selection is sent to every backend,
+//! - other requests without a time selection, such as `/metadata`, are
+//!   answered by the first backend.
+//!
+//! JSON `/profile_series` responses of several backends are merged into one
+//! series. Other requests must select time within a single backend. Raw time
+//! indices are local to each backend and are only accepted by a fleet of
+//! one. The backends that answered are listed in the `X-Rossby-Backend`
+//! response header.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use clap::Parser;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::error::{Result, RossbyError};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::slice_stats::{summarize, MissingData};
+
+/// Command-line subcommand that routes requests across a fleet
+pub const ROUTE_COMMAND: &str = "route";
+
+/// Response header listing the backends that answered a request
+pub const BACKEND_HEADER: &str = "x-rossby-backend";
+
+/// Timeout applied to every request forwarded to a backend
+const BACKEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Endpoints whose responses of several backends are merged
+const MERGED_ENDPOINTS: [&str; 1] = ["/profile_series"];
+
+/// Response headers not copied from backend responses
+const HOP_HEADERS: [&str; 4] = [
+    "connection",
+    "content-length",
+    "keep-alive",
+    "transfer-encoding",
+];
+
+/// Command-line arguments of `rossby route`
+#[derive(Parser, Debug)]
+#[command(name = "rossby route", bin_name = "rossby route")]
+#[command(about = "Route requests across rossby instances sharded by time range")]
+pub struct RouteArgs {
+    /// JSON manifest listing the backends and their time ranges
+    #[arg(long)]
+    pub manifest: PathBuf,
+
+    /// Host address to bind to
+    #[arg(short = 'H', long, env = "ROSSBY_HOST", default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Port to listen on
+    #[arg(short, long, env = "ROSSBY_PORT", default_value = "8000")]
+    pub port: u16,
+}
+
+/// One instance of a fleet
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Backend {
+    /// Name of the backend, reported in `X-Rossby-Backend`
+    pub name: String,
+    /// Base URL of the instance
+    pub url: String,
+    /// First time coordinate value served
+    pub start: f64,
+    /// Last time coordinate value served
+    pub end: f64,
+}
+
+impl Backend {
+    /// Whether a time value lies within the backend's time range
+    fn contains(&self, value: f64) -> bool {
+        (self.start..=self.end).contains(&value)
+    }
+
+    /// Distance from a time value to the backend's time range
+    fn distance(&self, value: f64) -> f64 {
+        (self.start - value).max(value - self.end).max(0.0)
+    }
+}
+
+fn default_time_dimension() -> String {
+    "time".to_string()
+}
+
+/// Backends of a fleet, ordered by time
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FleetManifest {
+    /// Name of the time dimension of the backends
+    #[serde(default = "default_time_dimension")]
+    pub time_dimension: String,
+    /// Backends with disjoint time ranges
+    pub backends: Vec<Backend>,
+}
+
+impl FleetManifest {
+    /// Read and validate a manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let manifest: Self = serde_json::from_str(&text).map_err(|e| RossbyError::Config {
+            message: format!("Invalid fleet manifest {}: {}", path.display(), e),
+        })?;
+        manifest.validated()
+    }
+
+    /// Check the backends, ordering them by time
+    pub fn validated(mut self) -> Result<Self> {
+        let error = |message: String| Err(RossbyError::Config { message });
+        if self.backends.is_empty() {
+            return error("The fleet manifest lists no backends".to_string());
+        }
+        let mut names = HashSet::new();
+        for backend in &self.backends {
+            if !names.insert(backend.name.as_str()) {
+                return error(format!("Backend '{}' is listed twice", backend.name));
+            }
+            if !backend.url.starts_with("http://") && !backend.url.starts_with("https://") {
+                return error(format!(
+                    "Invalid URL of backend '{}': {}. Must start with http:// or https://",
+                    backend.name, backend.url
+                ));
+            }
+            if !(backend.start.is_finite() && backend.end.is_finite())
+                || backend.start > backend.end
+            {
+                return error(format!(
+                    "Invalid time range of backend '{}': [{}, {}]",
+                    backend.name, backend.start, backend.end
+                ));
+            }
+        }
+        self.backends.sort_by(|a, b| a.start.total_cmp(&b.start));
+        for pair in self.backends.windows(2) {
+            if pair[1].start <= pair[0].end {
+                return error(format!(
+                    "The time ranges of backends '{}' and '{}' overlap",
+                    pair[0].name, pair[1].name
+                ));
+            }
+        }
+        for backend in &mut self.backends {
+            backend.url = backend.url.trim_end_matches('/').to_string();
+        }
+        Ok(self)
+    }
+
+    /// Parameter names selecting time by value
+    fn time_params(&self) -> Vec<String> {
+        let mut names = vec![self.time_dimension.clone(), "time".to_string()];
+        names.dedup();
+        let prefixed: Vec<String> = names.iter().map(|name| format!("_{}", name)).collect();
+        names.extend(prefixed);
+        names
+    }
+
+    /// Whether a parameter selects time by raw index
+    fn is_time_index_param(&self, key: &str) -> bool {
+        key == "time_index"
+            || [self.time_dimension.as_str(), "time"].iter().any(|name| {
+                key == format!("__{}_index", name) || key == format!("__{}_index_range", name)
+            })
+    }
+
+    /// Plan the backend requests answering a request
+    ///
+    /// Requests spanning several backends are only planned for the endpoints
+    /// whose responses can be merged.
+    pub fn plan(&self, path: &str, query: &[(String, String)]) -> Result<Vec<Route>> {
+        let mergeable = MERGED_ENDPOINTS.contains(&path)
+            && query
+                .iter()
+                .all(|(key, value)| key != "format" || value.eq_ignore_ascii_case("json"));
+        let routes = match self.time_selection(query)? {
+            TimeSelection::Values { param, values } => {
+                let mut routes: Vec<(usize, Vec<String>)> = Vec::new();
+                for value in values {
+                    let backend = self.backend_for(value.parse().map_err(|_| {
+                        RossbyError::InvalidParameter {
+                            param: param.clone(),
+                            message: format!("Invalid time value: {}", value),
+                        }
+                    })?);
+                    match routes.iter_mut().find(|(index, _)| *index == backend) {
+                        Some((_, subset)) => subset.push(value),
+                        None => routes.push((backend, vec![value])),
+                    }
+                }
+                routes.sort_by_key(|(index, _)| *index);
+                routes
+                    .into_iter()
+                    .map(|(backend, subset)| Route {
+                        backend,
+                        query: replace_param(query, &param, subset.join(",")),
+                    })
+                    .collect()
+            }
+            TimeSelection::Range {
+                param,
+                start,
+                end,
+                step,
+            } => {
+                let (low, high) = (start.min(end), start.max(end));
+                let routes: Vec<Route> = self
+                    .backends
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, backend)| backend.start <= high && low <= backend.end)
+                    .map(|(index, backend)| Route {
+                        backend: index,
+                        query: replace_param(
+                            query,
+                            &param,
+                            range_value(
+                                low.max(backend.start),
+                                high.min(backend.end),
+                                step.as_deref(),
+                            ),
+                        ),
+                    })
+                    .collect();
+                if routes.is_empty() {
+                    return Err(RossbyError::InvalidParameter {
+                        param,
+                        message: format!("No backend serves times between {} and {}", low, high),
+                    });
+                }
+                routes
+            }
+            TimeSelection::None if mergeable => {
+                let param = format!("{}_range", self.time_dimension);
+                self.backends
+                    .iter()
+                    .enumerate()
+                    .map(|(index, backend)| Route {
+                        backend: index,
+                        query: replace_param(
+                            query,
+                            &param,
+                            range_value(backend.start, backend.end, None),
+                        ),
+                    })
+                    .collect()
+            }
+            TimeSelection::None => vec![Route {
+                backend: 0,
+                query: query.to_vec(),
+            }],
+        };
+
+        if routes.len() > 1 && !mergeable {
+            let names: Vec<&str> = routes
+                .iter()
+                .map(|route| self.backends[route.backend].name.as_str())
+                .collect();
+            return Err(RossbyError::InvalidParameter {
+                param: self.time_dimension.clone(),
+                message: format!(
+                    "The time selection spans backends {}, but only JSON responses of {} can be merged. Select times served by a single backend",
+                    names.join(", "),
+                    MERGED_ENDPOINTS.join(", ")
+                ),
+            });
+        }
+        Ok(routes)
+    }
+
+    /// The time selection of a request
+    fn time_selection(&self, query: &[(String, String)]) -> Result<TimeSelection> {
+        let time_params = self.time_params();
+        for (key, value) in query {
+            if self.backends.len() > 1 && self.is_time_index_param(key) {
+                return Err(RossbyError::InvalidParameter {
+                    param: key.clone(),
+                    message: format!(
+                        "Raw time indices are local to each backend. Select time by value with '{0}' or '{0}_range'",
+                        self.time_dimension
+                    ),
+                });
+            }
+            if time_params.contains(key) {
+                return Ok(TimeSelection::Values {
+                    param: key.clone(),
+                    values: value.split(',').map(|v| v.trim().to_string()).collect(),
+                });
+            }
+            let is_range = key
+                .strip_suffix("_range")
+                .is_some_and(|name| time_params.iter().any(|param| param == name));
+            if is_range {
+                let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+                let invalid = || RossbyError::InvalidParameter {
+                    param: key.clone(),
+                    message: format!(
+                        "Invalid range: {}. Expected <start>,<end> or <start>,<end>,<step>",
+                        value
+                    ),
+                };
+                let (start, end, step) = match parts.as_slice() {
+                    [start, end] => (start, end, None),
+                    [start, end, step] => (start, end, Some(step.to_string())),
+                    _ => return Err(invalid()),
+                };
+                return Ok(TimeSelection::Range {
+                    param: key.clone(),
+                    start: start.parse().map_err(|_| invalid())?,
+                    end: end.parse().map_err(|_| invalid())?,
+                    step,
+                });
+            }
+        }
+        Ok(TimeSelection::None)
+    }
+
+    /// Index of the backend serving a time value, or the nearest one
+    fn backend_for(&self, value: f64) -> usize {
+        self.backends
+            .iter()
+            .position(|backend| backend.contains(value))
+            .unwrap_or_else(|| {
+                (0..self.backends.len())
+                    .min_by(|&a, &b| {
+                        self.backends[a]
+                            .distance(value)
+                            .total_cmp(&self.backends[b].distance(value))
+                    })
+                    .unwrap_or(0)
+            })
+    }
+}
+
+/// Time selection of a request
+#[derive(Debug, Clone, PartialEq)]
+enum TimeSelection {
+    /// No time selector
+    None,
+    /// Time values, as given
+    Values { param: String, values: Vec<String> },
+    /// A closed time range with an optional stride
+    Range {
+        param: String,
+        start: f64,
+        end: f64,
+        step: Option<String>,
+    },
+}
+
+/// A request to one backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    /// Index of the backend in the manifest, ordered by time
+    pub backend: usize,
+    /// Query parameters sent to the backend
+    pub query: Vec<(String, String)>,
+}
+
+/// The query with a parameter set to a value, replacing it if present
+fn replace_param(query: &[(String, String)], param: &str, value: String) -> Vec<(String, String)> {
+    let mut query: Vec<(String, String)> = query
+        .iter()
+        .filter(|(key, _)| key != param)
+        .cloned()
+        .collect();
+    query.push((param.to_string(), value));
+    query
+}
+
+/// Value of a range parameter
+fn range_value(start: f64, end: f64, step: Option<&str>) -> String {
+    match step {
+        Some(step) => format!("{},{},{}", start, end, step),
+        None => format!("{},{}", start, end),
+    }
+}
+
+/// Merge the JSON `/profile_series` responses of consecutive backends into
+/// one series, recomputing its shape and statistics
+pub fn merge_profile_series(parts: Vec<Value>) -> Result<Value> {
+    let mut parts = parts.into_iter();
+    let mut merged = parts.next().ok_or_else(|| RossbyError::Server {
+        message: "No backend responses to merge".to_string(),
+    })?;
+    let invalid = |message: &str| RossbyError::Conversion {
+        message: format!("Cannot merge backend responses: {}", message),
+    };
+    for part in parts {
+        if part["vertical"] != merged["vertical"] {
+            return Err(invalid("the vertical levels differ"));
+        }
+        for pointer in ["/time/values", "/values"] {
+            let rows = part
+                .pointer(pointer)
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid(&format!("missing {}", pointer)))?
+                .clone();
+            merged
+                .pointer_mut(pointer)
+                .and_then(Value::as_array_mut)
+                .ok_or_else(|| invalid(&format!("missing {}", pointer)))?
+                .extend(rows);
+        }
+    }
+
+    let rows = merged["values"].as_array().map_or(0, Vec::len);
+    let levels = merged["vertical"]["values"].as_array().map_or(0, Vec::len);
+    let values: Vec<f32> = merged["values"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|row| row.as_array().cloned().unwrap_or_default())
+        .map(|value| value.as_f64().map_or(f32::NAN, |v| v as f32))
+        .collect();
+    merged["shape"] = json!([rows, levels]);
+    merged["stats"] = serde_json::to_value(summarize(values, &MissingData::default()))?;
+    Ok(merged)
+}
+
+/// A backend response
+#[derive(Debug, Clone)]
+struct Forwarded {
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+    body: bytes::Bytes,
+}
+
+impl Forwarded {
+    fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_GATEWAY);
+        let mut response = (status, self.body).into_response();
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_bytes(&value),
+            ) {
+                response.headers_mut().insert(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// Router state: the manifest and a client shared by all requests
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    manifest: FleetManifest,
+    client: reqwest::Client,
+}
+
+impl Fleet {
+    /// A fleet of the backends of a validated manifest
+    pub fn new(manifest: FleetManifest) -> Self {
+        Self {
+            manifest,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Send a request to a backend
+    async fn forward(&self, route: &Route, path: &str) -> Result<Forwarded> {
+        let backend = &self.manifest.backends[route.backend];
+        let url = format!("{}{}", backend.url, path);
+        debug!(backend = %backend.name, url = %url, query = ?route.query, "Forwarding request");
+        let error = |message: String| RossbyError::Peer {
+            peer: backend.name.clone(),
+            message,
+        };
+
+        let response = self
+            .client
+            .get(&url)
+            .query(&route.query)
+            .timeout(BACKEND_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| error(format!("Request to {} failed: {}", url, e)))?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !HOP_HEADERS.contains(&name.as_str()))
+            .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+            .collect();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| error(format!("Failed to read response of {}: {}", url, e)))?;
+        Ok(Forwarded {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Answer a request from the backends
+    async fn answer(&self, path: &str, query: &str) -> Result<Response> {
+        let query: Vec<(String, String)> =
+            serde_urlencoded::from_str(query).map_err(|e| RossbyError::InvalidParameter {
+                param: "query".to_string(),
+                message: format!("Invalid query string: {}", e),
+            })?;
+        let routes = self.manifest.plan(path, &query)?;
+        let names: Vec<&str> = routes
+            .iter()
+            .map(|route| self.manifest.backends[route.backend].name.as_str())
+            .collect();
+        let backends = HeaderValue::from_str(&names.join(",")).ok();
+
+        let responses =
+            futures::future::try_join_all(routes.iter().map(|route| self.forward(route, path)))
+                .await?;
+
+        // Errors of a backend are passed on as they are
+        let mut response = match responses.iter().position(|r| !r.is_success()) {
+            Some(failed) => responses[failed].clone().into_response(),
+            None if responses.len() == 1 => responses[0].clone().into_response(),
+            None => {
+                let parts = responses
+                    .iter()
+                    .map(|r| serde_json::from_slice(&r.body))
+                    .collect::<std::result::Result<Vec<Value>, _>>()?;
+                Json(merge_profile_series(parts)?).into_response()
+            }
+        };
+        if let Some(backends) = backends {
+            response.headers_mut().insert(BACKEND_HEADER, backends);
+        }
+        Ok(response)
+    }
+}
+
+/// Handle every request to the router
+async fn route_handler(State(fleet): State<Arc<Fleet>>, request: Request) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or("").to_string();
+
+    let result = if request.method() == Method::GET || request.method() == Method::HEAD {
+        fleet.answer(&path, &query).await
+    } else {
+        Err(RossbyError::InvalidParameter {
+            param: "method".to_string(),
+            message: format!(
+                "The router forwards GET requests only, not {}",
+                request.method()
+            ),
+        })
+    };
+
+    match result {
+        Ok(response) => {
+            info!(
+                endpoint = %path,
+                request_id = %request_id,
+                status = response.status().as_u16(),
+                duration_us = start_time.elapsed().as_micros() as u64,
+                "Routed request"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(&error, &path, &request_id, Some(&query));
+            let status = if matches!(error, RossbyError::Peer { .. }) {
+                StatusCode::BAD_GATEWAY
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            (
+                status,
+                Json(json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Build the router forwarding requests to a fleet
+pub fn router(fleet: Arc<Fleet>) -> Router {
+    Router::new().fallback(route_handler).with_state(fleet)
+}
+
+/// Route requests across a fleet as requested on the command line
+pub async fn run(args: RouteArgs) -> Result<()> {
+    let manifest = FleetManifest::load(&args.manifest)?;
+    for backend in &manifest.backends {
+        info!(
+            backend = %backend.name,
+            url = %backend.url,
+            start = backend.start,
+            end = backend.end,
+            "Routing to backend"
+        );
+    }
+
+    let addr = SocketAddr::from((
+        args.host
+            .parse::<std::net::IpAddr>()
+            .map_err(|e| RossbyError::Config {
+                message: format!("Invalid host address: {}", e),
+            })?,
+        args.port,
+    ));
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| RossbyError::Server {
+            message: format!("Failed to bind to address: {}", e),
+        })?;
+    info!(address = %addr, "Router listening on http://{}", addr);
+
+    axum::serve(listener, router(Arc::new(Fleet::new(manifest))))
+        .await
+        .map_err(|e| RossbyError::Server {
+            message: format!("Server error: {}", e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> FleetManifest {
+        let backend = |name: &str, start: f64, end: f64| Backend {
+            name: name.to_string(),
+            url: format!("http://{}:8000/", name),
+            start,
+            end,
+        };
+        FleetManifest {
+            time_dimension: "time".to_string(),
+            backends: vec![backend("b", 10.0, 19.0), backend("a", 0.0, 9.0)],
+        }
+        .validated()
+        .unwrap()
+    }
+
+    fn query(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_manifest_validation() {
+        let fleet = manifest();
+        assert_eq!(fleet.backends[0].name, "a");
+        assert_eq!(fleet.backends[1].url, "http://b:8000");
+
+        let mut overlapping = fleet.clone();
+        overlapping.backends[1].start = 9.0;
+        assert!(overlapping.validated().is_err());
+        let mut duplicate = fleet.clone();
+        duplicate.backends[1].name = "a".to_string();
+        assert!(duplicate.validated().is_err());
+        let mut empty = fleet;
+        empty.backends.clear();
+        assert!(empty.validated().is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let fleet = manifest();
+
+        // Values go to the backends serving them, the nearest one in gaps
+        let routes = fleet
+            .plan("/profile_series", &query(&[("time", "12,3,9.5")]))
+            .unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].query, query(&[("time", "3,9.5")]));
+        assert_eq!(routes[1].query, query(&[("time", "12")]));
+
+        // Ranges are clipped to each backend
+        let routes = fleet
+            .plan("/profile_series", &query(&[("time_range", "5,15,2")]))
+            .unwrap();
+        assert_eq!(routes[0].query, query(&[("time_range", "5,9,2")]));
+        assert_eq!(routes[1].query, query(&[("time_range", "10,15,2")]));
+        assert!(fleet
+            .plan("/point", &query(&[("time_range", "30,40")]))
+            .is_err());
+
+        // Series without a time selection span the fleet
+        let routes = fleet.plan("/profile_series", &[]).unwrap();
+        assert_eq!(routes[1].query, query(&[("time_range", "10,19")]));
+        let routes = fleet.plan("/metadata", &[]).unwrap();
+        assert_eq!(
+            routes,
+            vec![Route {
+                backend: 0,
+                query: vec![]
+            }]
+        );
+
+        // Only mergeable responses may span backends
+        let routes = fleet.plan("/point", &query(&[("lon", "0"), ("time", "15")]));
+        assert_eq!(routes.unwrap()[0].backend, 1);
+        assert!(fleet.plan("/point", &query(&[("time", "1,15")])).is_err());
+        assert!(fleet
+            .plan("/profile_series", &query(&[("format", "png")]))
+            .is_err());
+        assert!(fleet
+            .plan("/point", &query(&[("__time_index", "0")]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_merge_profile_series() {
+        let part = |times: Value, values: Value| {
+            json!({
+                "var": "temp",
+                "time": {"dimension": "time", "values": times},
+                "vertical": {"dimension": "depth", "values": [0.0, 10.0]},
+                "shape": [1, 2],
+                "values": values,
+            })
+        };
+        let merged = merge_profile_series(vec![
+            part(json!([0.0]), json!([[1.0, 2.0]])),
+            part(json!([1.0, 2.0]), json!([[3.0, null], [5.0, 6.0]])),
+        ])
+        .unwrap();
+        assert_eq!(merged["time"]["values"], json!([0.0, 1.0, 2.0]));
+        assert_eq!(merged["shape"], json!([3, 2]));
+        assert_eq!(merged["stats"]["count"], 5);
+        assert_eq!(merged["stats"]["max"], 6.0);
+
+        let mut other = part(json!([3.0]), json!([[1.0, 2.0]]));
+        other["vertical"]["values"] = json!([0.0]);
+        assert!(merge_profile_series(vec![merged, other]).is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! Forecast reference time and lead time axes.
+//!
+//! Archives of numerical weather prediction runs index their fields by two
+//! time dimensions: the reference time at which a forecast was started
+//! (CF `standard_name = "forecast_reference_time"`, often named `reftime`)
+//! and the lead time since then (`standard_name = "forecast_period"`, often
+//! `leadtime` or `step`). Both are ordinary dimensions that can be selected
+//! like any other, e.g. `reftime=...&leadtime=24`. The time a field is valid
+//! at is their sum, which is what users usually want to select by:
+//! `valid_time=<value>` selects the pair of indices valid at that time,
+//! given in the units of the reference time coordinate.
+//!
+//! Several runs usually cover the same valid time. Unless the reference time
+//! or the lead time is selected as well, the most recent run is used, i.e.
+//! the shortest lead time. `/metadata` describes both axes and the valid
+//! time of every pair.
+
+use crate::cf_time::{unit_seconds, TimeUnits};
+use crate::error::{Result, RossbyError};
+use crate::state::{AppState, AttributeValue};
+
+/// Query parameter selecting a valid time
+pub const VALID_TIME_PARAM: &str = "valid_time";
+
+/// CF standard name of forecast reference time coordinates
+const REFERENCE_STANDARD_NAME: &str = "forecast_reference_time";
+
+/// CF standard name of forecast lead time coordinates
+const LEAD_STANDARD_NAME: &str = "forecast_period";
+
+/// Usual names of reference time dimensions without a standard name
+const REFERENCE_NAMES: [&str; 4] = [
+    "reftime",
+    "ref_time",
+    "init_time",
+    "forecast_reference_time",
+];
+
+/// Usual names of lead time dimensions without a standard name
+const LEAD_NAMES: [&str; 5] = [
+    "leadtime",
+    "lead_time",
+    "step",
+    "forecast_period",
+    "fcst_hour",
+];
+
+/// Valid times closer than this are the same, in seconds
+const MATCH_TOLERANCE_SECONDS: f64 = 0.5;
+
+/// The reference time and lead time dimensions of a dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForecastAxes {
+    /// Name of the reference time dimension
+    pub reference: String,
+    /// Name of the lead time dimension
+    pub lead: String,
+    /// Units of the reference time coordinate, which valid times share
+    pub units: TimeUnits,
+    /// Length of one lead time unit in seconds
+    pub lead_seconds: f64,
+}
+
+impl ForecastAxes {
+    /// Find the forecast axes of a dataset, if it has both
+    ///
+    /// Both dimensions need coordinate variables with time units: `<unit>
+    /// since <date>` for the reference time, and a plain unit such as `hours`
+    /// for the lead time.
+    pub fn detect(state: &AppState) -> Option<Self> {
+        let reference = find_axis(state, REFERENCE_STANDARD_NAME, &REFERENCE_NAMES)?;
+        let lead = find_axis(state, LEAD_STANDARD_NAME, &LEAD_NAMES)?;
+        let units =
+            TimeUnits::from_attributes(&state.get_variable_metadata(&reference)?.attributes)?;
+        let lead_seconds = match state
+            .get_variable_metadata(&lead)?
+            .attributes
+            .get("units")?
+        {
+            AttributeValue::Text(units) => match units.split_once(" since ") {
+                Some(_) => TimeUnits::parse(units)?.seconds_per_unit,
+                None => unit_seconds(units)?,
+            },
+            _ => return None,
+        };
+        Some(Self {
+            reference,
+            lead,
+            units,
+            lead_seconds,
+        })
+    }
+
+    /// Valid time of a reference time and lead time, in reference time units
+    pub fn valid_time(&self, reference: f64, lead: f64) -> f64 {
+        reference + lead * self.lead_seconds / self.units.seconds_per_unit
+    }
+
+    /// Valid times of every pair of reference and lead time indices
+    pub fn valid_times(&self, state: &AppState) -> Vec<Vec<f64>> {
+        let (references, leads) = self.coordinates(state);
+        references
+            .iter()
+            .map(|&reference| {
+                leads
+                    .iter()
+                    .map(|&lead| self.valid_time(reference, lead))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Indices of the reference time and lead time valid at `valid_time`
+    ///
+    /// Either index may be fixed by another selection. Otherwise the most
+    /// recent reference time covering the valid time is used.
+    pub fn resolve(
+        &self,
+        state: &AppState,
+        valid_time: f64,
+        reference: Option<usize>,
+        lead: Option<usize>,
+    ) -> Result<(usize, usize)> {
+        let (references, leads) = self.coordinates(state);
+        let matches = |r: usize, l: usize| {
+            let difference = self.valid_time(references[r], leads[l]) - valid_time;
+            (difference * self.units.seconds_per_unit).abs() < MATCH_TOLERANCE_SECONDS
+        };
+
+        let mut candidates: Vec<usize> = match reference {
+            Some(r) => vec![r],
+            None => (0..references.len()).collect(),
+        };
+        candidates.sort_by(|&a, &b| references[b].total_cmp(&references[a]));
+        for r in candidates {
+            let found = match lead {
+                Some(l) => matches(r, l).then_some(l),
+                None => (0..leads.len()).find(|&l| matches(r, l)),
+            };
+            if let Some(l) = found {
+                return Ok((r, l));
+            }
+        }
+
+        let time = self.units.iso(valid_time).map_or_else(
+            || valid_time.to_string(),
+            |iso| format!("{} ({})", valid_time, iso),
+        );
+        Err(RossbyError::InvalidParameter {
+            param: VALID_TIME_PARAM.to_string(),
+            message: format!(
+                "No forecast {}is valid at {}",
+                match (reference, lead) {
+                    (Some(_), _) => "of the selected reference time ",
+                    (None, Some(_)) => "with the selected lead time ",
+                    (None, None) => "",
+                },
+                time
+            ),
+        })
+    }
+
+    /// Description of the axes for `/metadata`
+    pub fn to_json(&self, state: &AppState) -> serde_json::Value {
+        let units = state
+            .get_variable_metadata(&self.reference)
+            .and_then(|var| match var.attributes.get("units") {
+                Some(AttributeValue::Text(units)) => Some(units.clone()),
+                _ => None,
+            });
+        serde_json::json!({
+            "reference_time": self.reference,
+            "lead_time": self.lead,
+            "valid_time_units": units,
+            "valid_times": self.valid_times(state),
+        })
+    }
+
+    /// Coordinates of the reference time and lead time dimensions
+    fn coordinates<'a>(&self, state: &'a AppState) -> (&'a [f64], &'a [f64]) {
+        let coordinates = |name: &str| {
+            state
+                .metadata
+                .coordinates
+                .get(name)
+                .map_or(&[][..], Vec::as_slice)
+        };
+        (coordinates(&self.reference), coordinates(&self.lead))
+    }
+}
+
+/// Dimension with a coordinate variable of the standard name, or else one of
+/// the usual names
+fn find_axis(state: &AppState, standard_name: &str, names: &[&str]) -> Option<String> {
+    let mut dimensions: Vec<&String> = state
+        .metadata
+        .dimensions
+        .keys()
+        .filter(|name| state.metadata.coordinates.contains_key(*name))
+        .collect();
+    dimensions.sort();
+
+    let has_standard_name = |name: &str| {
+        state.get_variable_metadata(name).is_some_and(|var| {
+            matches!(
+                var.attributes.get("standard_name"),
+                Some(AttributeValue::Text(text)) if text == standard_name
+            )
+        })
+    };
+    dimensions
+        .iter()
+        .find(|name| has_standard_name(name))
+        .or_else(|| {
+            dimensions
+                .iter()
+                .find(|name| names.contains(&name.to_lowercase().as_str()))
+        })
+        .map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::query::Selection;
+    use crate::state::{Dimension, Metadata, Variable};
+    use ndarray::{Array, IxDyn};
+    use std::collections::HashMap;
+
+    /// Two runs 12 hours apart, with lead times of 0, 6, 12 and 18 hours
+    fn forecast_state() -> AppState {
+        let sizes = [("reftime", 2), ("leadtime", 4), ("lat", 2), ("lon", 3)];
+        let dimensions = sizes
+            .iter()
+            .map(|&(name, size)| {
+                let dimension = Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                };
+                (name.to_string(), dimension)
+            })
+            .collect();
+        let coordinates = HashMap::from([
+            ("reftime".to_string(), vec![0.0, 43_200.0]),
+            ("leadtime".to_string(), vec![0.0, 6.0, 12.0, 18.0]),
+            ("lat".to_string(), vec![40.0, 50.0]),
+            ("lon".to_string(), vec![0.0, 10.0, 20.0]),
+        ]);
+        let coordinate = |name: &str, size: usize, units: &str| Variable {
+            name: name.to_string(),
+            dimensions: vec![name.to_string()],
+            shape: vec![size],
+            attributes: HashMap::from([(
+                "units".to_string(),
+                AttributeValue::Text(units.to_string()),
+            )]),
+            dtype: "f64".to_string(),
+        };
+        let mut t2m = coordinate("t2m", 0, "K");
+        t2m.dimensions = ["reftime", "leadtime", "lat", "lon"]
+            .map(String::from)
+            .to_vec();
+        t2m.shape = vec![2, 4, 2, 3];
+        let variables = HashMap::from([
+            (
+                "reftime".to_string(),
+                coordinate("reftime", 2, "seconds since 2024-01-01 00:00:00"),
+            ),
+            ("leadtime".to_string(), coordinate("leadtime", 4, "hours")),
+            ("lat".to_string(), coordinate("lat", 2, "degrees_north")),
+            ("lon".to_string(), coordinate("lon", 3, "degrees_east")),
+            ("t2m".to_string(), t2m),
+        ]);
+        let values = Array::from_shape_fn(IxDyn(&[2, 4, 2, 3]), |i| {
+            (i[0] * 1000 + i[1] * 100 + i[2] * 10 + i[3]) as f32
+        });
+        AppState::new(
+            Config::default(),
+            Metadata {
+                global_attributes: HashMap::new(),
+                dimensions,
+                variables,
+                coordinates,
+                groups: HashMap::new(),
+            },
+            HashMap::from([("t2m".to_string(), values)]),
+        )
+    }
+
+    #[test]
+    fn test_detect_forecast_axes() {
+        let state = forecast_state();
+        let axes = ForecastAxes::detect(&state).unwrap();
+        assert_eq!(axes.reference, "reftime");
+        assert_eq!(axes.lead, "leadtime");
+        assert_eq!(axes.lead_seconds, 3_600.0);
+        assert_eq!(
+            axes.valid_times(&state),
+            vec![
+                vec![0.0, 21_600.0, 43_200.0, 64_800.0],
+                vec![43_200.0, 64_800.0, 86_400.0, 108_000.0]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_valid_time() {
+        let state = forecast_state();
+        let axes = ForecastAxes::detect(&state).unwrap();
+
+        // The most recent run covering the valid time
+        assert_eq!(axes.resolve(&state, 43_200.0, None, None).unwrap(), (1, 0));
+        assert_eq!(axes.resolve(&state, 21_600.0, None, None).unwrap(), (0, 1));
+        // Unless the run or lead time is fixed
+        assert_eq!(
+            axes.resolve(&state, 43_200.0, Some(0), None).unwrap(),
+            (0, 2)
+        );
+        assert_eq!(
+            axes.resolve(&state, 64_800.0, None, Some(3)).unwrap(),
+            (0, 3)
+        );
+
+        assert!(axes.resolve(&state, 10_000.0, None, None).is_err());
+        assert!(axes.resolve(&state, 108_000.0, Some(0), None).is_err());
+    }
+
+    #[test]
+    fn test_valid_time_selection() {
+        let state = forecast_state();
+        let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let selection = Selection::parse(&state, &params(&[("valid_time", "64800")])).unwrap();
+        let resolved = selection.resolve(&state).unwrap();
+        assert_eq!(resolved["reftime"], vec![1]);
+        assert_eq!(resolved["leadtime"], vec![1]);
+        assert_eq!(selection.get("reftime").unwrap().param, "valid_time");
+
+        let selection = Selection::parse(
+            &state,
+            &params(&[("valid_time", "64800"), ("__reftime_index", "0")]),
+        )
+        .unwrap();
+        let resolved = selection.resolve(&state).unwrap();
+        assert_eq!(resolved["reftime"], vec![0]);
+        assert_eq!(resolved["leadtime"], vec![3]);
+
+        assert!(Selection::parse(
+            &state,
+            &params(&[
+                ("valid_time", "64800"),
+                ("reftime", "0"),
+                ("leadtime", "18")
+            ]),
+        )
+        .is_err());
+        assert!(Selection::parse(&state, &params(&[("valid_time", "soon")])).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Dataset generations.
+//!
+//! Every `AppState` gets a generation number, increasing with each dataset
+//! loaded by the process. All responses carry the generation that served
+//! them in `X-Rossby-Generation`, so a client running a multi-request
+//! workflow (metadata, then tiles, then data) can tell when the dataset was
+//! swapped underneath it. Clients can also pass `require_generation=<n>` on
+//! any request to have it rejected with `409 Conflict` instead of being
+//! answered from a different dataset, and restart the sequence coherently.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::{Result, RossbyError};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::schema::SCHEMA_VERSION;
+use crate::state::AppState;
+
+/// Response header carrying the dataset generation
+pub const GENERATION_HEADER: &str = "x-rossby-generation";
+
+/// Query parameter rejecting requests served by another generation
+pub const REQUIRE_GENERATION_PARAM: &str = "require_generation";
+
+/// Generation assigned to the next dataset state
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Allocate the generation of a newly built dataset state
+pub fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Split `require_generation` off a query string
+///
+/// Returns the required generation, if any, and the remaining query to pass
+/// on to the handler. Queries without the guard are returned as `None` so the
+/// request can be forwarded unchanged.
+pub fn take_required_generation(query: &str) -> Result<Option<(u64, String)>> {
+    let params: Vec<(String, String)> = match serde_urlencoded::from_str(query) {
+        Ok(params) => params,
+        Err(_) => return Ok(None),
+    };
+
+    let mut required = None;
+    let mut rest = Vec::with_capacity(params.len());
+    for (key, value) in params {
+        if key != REQUIRE_GENERATION_PARAM {
+            rest.push((key, value));
+            continue;
+        }
+        let generation =
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| RossbyError::InvalidParameter {
+                    param: REQUIRE_GENERATION_PARAM.to_string(),
+                    message: format!("Expected a generation number, got '{}'", value),
+                })?;
+        if required.is_some_and(|r| r != generation) {
+            return Err(RossbyError::InvalidParameter {
+                param: REQUIRE_GENERATION_PARAM.to_string(),
+                message: "Conflicting generations required".to_string(),
+            });
+        }
+        required = Some(generation);
+    }
+
+    match required {
+        Some(generation) => serde_urlencoded::to_string(&rest)
+            .map(|rest| Some((generation, rest)))
+            .map_err(|e| RossbyError::Server {
+                message: format!("Failed to encode query: {}", e),
+            }),
+        None => Ok(None),
+    }
+}
+
+/// Tag responses with the dataset generation and enforce `require_generation`
+pub async fn generation_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let guard = match request.uri().query() {
+        Some(query) => take_required_generation(query),
+        None => Ok(None),
+    };
+
+    let mut response = match guard {
+        Ok(None) => next.run(request).await,
+        Ok(Some((required, _))) if required != state.generation => generation_error_response(
+            RossbyError::GenerationMismatch {
+                required,
+                current: state.generation,
+            },
+            request.uri().path(),
+        ),
+        Ok(Some((_, rest))) => {
+            let path = request.uri().path();
+            let uri = if rest.is_empty() {
+                path.parse::<Uri>()
+            } else {
+                format!("{}?{}", path, rest).parse::<Uri>()
+            };
+            match uri {
+                Ok(uri) => {
+                    *request.uri_mut() = uri;
+                    next.run(request).await
+                }
+                Err(e) => generation_error_response(
+                    RossbyError::Server {
+                        message: format!("Failed to rewrite request URI: {}", e),
+                    },
+                    request.uri().path(),
+                ),
+            }
+        }
+        Err(error) => generation_error_response(error, request.uri().path()),
+    };
+
+    response
+        .headers_mut()
+        .insert(GENERATION_HEADER, HeaderValue::from(state.generation));
+    response
+}
+
+/// Build the error response for a rejected generation guard
+fn generation_error_response(error: RossbyError, endpoint: &str) -> Response {
+    let request_id = generate_request_id();
+    log_request_error(&error, endpoint, &request_id, None);
+
+    match &error {
+        RossbyError::GenerationMismatch { current, .. } => (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": error.to_string(),
+                "generation": current,
+                "request_id": request_id,
+                "schema_version": SCHEMA_VERSION
+            })),
+        )
+            .into_response(),
+        _ => {
+            let status = match &error {
+                RossbyError::Server { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id,
+                    "schema_version": SCHEMA_VERSION
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_generation_increases() {
+        let first = next_generation();
+        let second = next_generation();
+        assert!(second > first);
+    }
+
+    #[test]
+    fn test_take_required_generation() {
+        assert_eq!(take_required_generation("var=t2m").unwrap(), None);
+        assert_eq!(
+            take_required_generation("var=t2m&require_generation=3&time=0").unwrap(),
+            Some((3, "var=t2m&time=0".to_string()))
+        );
+        assert_eq!(
+            take_required_generation("require_generation=7").unwrap(),
+            Some((7, String::new()))
+        );
+
+        for invalid in [
+            "require_generation=latest",
+            "require_generation=1&require_generation=2",
+        ] {
+            assert!(matches!(
+                take_required_generation(invalid),
+                Err(RossbyError::InvalidParameter { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Longitude geometry of bounding boxes.
+//!
+//! A bounding box whose western edge lies east of its eastern edge, such as
+//! `350,-30,10,30`, crosses the seam of the longitude axis: on a 0..360 grid
+//! it covers the columns from 350° to the end of the grid followed by those
+//! from the start of the grid to 10°. Longitudes of a box are taken modulo
+//! 360 degrees into the convention of the grid first, so the same box also
+//! selects the contiguous columns from -10° to 10° of a -180..180 grid, and
+//! a box written as `-10,-30,10,30` crosses the seam of a 0..360 grid.
+//!
+//! The columns of a box are returned in eastward order, so the slabs of
+//! `/image`, `/data`, `/stats` and pregenerated tiles are the concatenation
+//! of both sides of the seam.
+
+/// Smallest longitude equivalent to `lon` modulo 360 degrees that is not
+/// below the start of the coordinate range
+pub fn wrap_into_range(lon: f64, coords: &[f64]) -> f64 {
+    let (Some(&first), Some(&last)) = (coords.first(), coords.last()) else {
+        return lon;
+    };
+    let lo = first.min(last);
+    if !lon.is_finite() {
+        return lon;
+    }
+
+    // Smallest equivalent longitude that is not below the start of the range
+    lo + (lon - lo).rem_euclid(360.0)
+}
+
+/// The western and eastern edges of a longitude interval in the convention
+/// of a grid, or `None` when it spans the globe
+fn wrapped_interval(lon_coords: &[f64], min_lon: f64, max_lon: f64) -> Option<(f64, f64)> {
+    if min_lon <= max_lon && max_lon - min_lon >= 360.0 {
+        return None;
+    }
+    Some((
+        wrap_into_range(min_lon, lon_coords),
+        wrap_into_range(max_lon, lon_coords),
+    ))
+}
+
+/// The western and eastern edges of a longitude interval in the convention
+/// of a grid, when the interval crosses the seam of the grid
+pub fn seam_crossing(lon_coords: &[f64], min_lon: f64, max_lon: f64) -> Option<(f64, f64)> {
+    wrapped_interval(lon_coords, min_lon, max_lon).filter(|(west, east)| west > east)
+}
+
+/// Indices of the coordinates within a closed interval, in grid order
+pub fn range_indices(coords: &[f64], min: f64, max: f64) -> Vec<usize> {
+    (0..coords.len())
+        .filter(|&i| coords[i] >= min && coords[i] <= max)
+        .collect()
+}
+
+/// Indices of the grid longitudes within `[min_lon, max_lon]`
+///
+/// An interval crossing the seam of the grid selects the longitudes on
+/// either side of it, ordered as the grid runs (eastward on ascending
+/// grids). Intervals spanning 360 degrees or more select the whole axis.
+pub fn lon_indices(lon_coords: &[f64], min_lon: f64, max_lon: f64) -> Vec<usize> {
+    let Some((west, east)) = wrapped_interval(lon_coords, min_lon, max_lon) else {
+        return (0..lon_coords.len()).collect();
+    };
+    if west <= east {
+        return range_indices(lon_coords, west, east);
+    }
+
+    let from_west: Vec<usize> = (0..lon_coords.len())
+        .filter(|&i| lon_coords[i] >= west)
+        .collect();
+    let to_east: Vec<usize> = (0..lon_coords.len())
+        .filter(|&i| lon_coords[i] <= east)
+        .collect();
+    let descending = lon_coords.first() > lon_coords.last();
+    let (head, tail) = if descending {
+        (to_east, from_west)
+    } else {
+        (from_west, to_east)
+    };
+    head.into_iter().chain(tail).collect()
+}
+
+/// Longitudes made continuous across the seam by adding or subtracting 360
+/// degrees where consecutive values jump by more than 180 degrees
+pub fn unwrap_longitudes(lons: &[f64]) -> Vec<f64> {
+    let mut unwrapped: Vec<f64> = Vec::with_capacity(lons.len());
+    for &lon in lons {
+        let lon = match unwrapped.last() {
+            Some(&previous) => previous + (lon - previous + 180.0).rem_euclid(360.0) - 180.0,
+            None => lon,
+        };
+        unwrapped.push(lon);
+    }
+    unwrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(start: f64, step: f64, n: usize) -> Vec<f64> {
+        (0..n).map(|i| start + i as f64 * step).collect()
+    }
+
+    #[test]
+    fn test_lon_indices() {
+        // 0..360 grid in 10 degree steps
+        let east = grid(0.0, 10.0, 36);
+        assert_eq!(lon_indices(&east, 20.0, 40.0), vec![2, 3, 4]);
+        assert_eq!(lon_indices(&east, 340.0, 10.0), vec![34, 35, 0, 1]);
+        assert_eq!(lon_indices(&east, -20.0, 10.0), vec![34, 35, 0, 1]);
+        assert_eq!(lon_indices(&east, 0.0, 360.0).len(), 36);
+        assert_eq!(seam_crossing(&east, 340.0, 10.0), Some((340.0, 10.0)));
+        assert_eq!(seam_crossing(&east, -20.0, 10.0), Some((340.0, 10.0)));
+        assert_eq!(seam_crossing(&east, 20.0, 40.0), None);
+
+        // The same box is contiguous on a -180..180 grid
+        let centered = grid(-180.0, 10.0, 36);
+        assert_eq!(lon_indices(&centered, 340.0, 10.0), vec![16, 17, 18, 19]);
+        assert_eq!(seam_crossing(&centered, 340.0, 10.0), None);
+        assert_eq!(lon_indices(&centered, 160.0, -170.0), vec![34, 35, 0, 1]);
+
+        // Descending grids run westward on both sides of the seam
+        let descending: Vec<f64> = east.iter().rev().copied().collect();
+        assert_eq!(lon_indices(&descending, 340.0, 10.0), vec![34, 35, 0, 1]);
+        assert_eq!(lon_indices(&[], 340.0, 10.0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_unwrap_longitudes() {
+        assert_eq!(
+            unwrap_longitudes(&[340.0, 350.0, 0.0, 10.0]),
+            vec![340.0, 350.0, 360.0, 370.0]
+        );
+        assert_eq!(
+            unwrap_longitudes(&[10.0, 0.0, 350.0]),
+            vec![10.0, 0.0, -10.0]
+        );
+        assert_eq!(
+            unwrap_longitudes(&[-10.0, 0.0, 10.0]),
+            vec![-10.0, 0.0, 10.0]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! GeoTIFF encoding of rendered images.
+//!
+//! `/image?format=geotiff` returns the rendered RGBA image as a GeoTIFF, so
+//! GIS clients can place it without being told where it lies. The affine
+//! transform from pixels to model coordinates is derived from the
+//! coordinates of the first and last data rows and columns, which are drawn
+//! at the centers of the edge pixels (as graticules assume), and the CRS is
+//! named by its EPSG code in the GeoKey directory. Plate carrée images are
+//! in the geographic CRS of the dataset and Mercator images of WGS 84 data
+//! in Web Mercator; other projections have no affine transform and cannot
+//! be written.
+
+use image::RgbaImage;
+use std::io::Cursor;
+use tiff::encoder::{colortype::RGBA8, compression::Deflate, TiffEncoder};
+use tiff::tags::Tag;
+
+use crate::colormaps::geoutil::mercator_y;
+use crate::colormaps::LatitudeScaling;
+use crate::crs::{Crs, WEB_MERCATOR};
+use crate::error::{Result, RossbyError};
+
+/// Radius in meters of the sphere of the Web Mercator projection
+pub(crate) const WEB_MERCATOR_RADIUS: f64 = 6_378_137.0;
+
+// GeoKey ids and values, from the GeoTIFF 1.1 specification
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+const GEOGRAPHIC_TYPE_GEO_KEY: u16 = 2048;
+const GEOG_ANGULAR_UNITS_GEO_KEY: u16 = 2054;
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const PROJ_LINEAR_UNITS_GEO_KEY: u16 = 3076;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+const ANGULAR_DEGREE: u16 = 9102;
+const LINEAR_METER: u16 = 9001;
+
+/// `ExtraSamples` value of an alpha channel not premultiplied into colors
+const UNASSOCIATED_ALPHA: u16 = 2;
+
+/// Georeferencing of a rendered image
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoReference {
+    /// Model coordinates of the upper-left corner of the first pixel
+    pub origin: (f64, f64),
+    /// Model size of a pixel along rows and columns, negative where
+    /// coordinates decrease (e.g. latitudes from the top row down)
+    pub pixel_size: (f64, f64),
+    /// EPSG code of the CRS of the model coordinates
+    pub epsg: u16,
+    /// Whether the CRS is projected (meters) rather than geographic (degrees)
+    pub projected: bool,
+}
+
+impl GeoReference {
+    /// Georeferencing of an image rendered with a latitude scaling
+    ///
+    /// `lon_span` and `lat_span` hold the coordinates of the first and last
+    /// data column and row, drawn at the centers of the edge pixels.
+    pub fn new(
+        crs: &Crs,
+        scaling: LatitudeScaling,
+        lon_span: (f64, f64),
+        lat_span: (f64, f64),
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let unsupported = |message: String| RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: format!("GeoTIFF output {}", message),
+        };
+        let (identifier, projected) = match scaling {
+            LatitudeScaling::PlateCarree if crs.is_geographic() => {
+                (crs.identifier.as_deref().unwrap_or_default(), false)
+            }
+            LatitudeScaling::Mercator if crs.is_wgs84() => (WEB_MERCATOR, true),
+            _ => {
+                return Err(unsupported(
+                    "needs a plate carrée image of a geographic CRS or a Mercator image of \
+                     WGS 84 data"
+                        .to_string(),
+                ))
+            }
+        };
+        let epsg = epsg_code(identifier).ok_or_else(|| {
+            unsupported(format!(
+                "needs a CRS with an EPSG code, not {}",
+                crs.identifier.as_deref().unwrap_or("an unidentified one")
+            ))
+        })?;
+
+        let project = |lon: f64, lat: f64| {
+            if projected {
+                (
+                    WEB_MERCATOR_RADIUS * lon.to_radians(),
+                    WEB_MERCATOR_RADIUS * mercator_y(lat),
+                )
+            } else {
+                (lon, lat)
+            }
+        };
+        let first = project(lon_span.0, lat_span.0);
+        let last = project(lon_span.1, lat_span.1);
+        let pixel_size = (
+            (last.0 - first.0) / width.saturating_sub(1) as f64,
+            (last.1 - first.1) / height.saturating_sub(1) as f64,
+        );
+        let valid = |size: f64| size.is_finite() && size != 0.0;
+        if !valid(pixel_size.0) || !valid(pixel_size.1) {
+            return Err(unsupported(
+                "needs at least two rows and columns of distinct coordinates".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            origin: (first.0 - pixel_size.0 / 2.0, first.1 - pixel_size.1 / 2.0),
+            pixel_size,
+            epsg,
+            projected,
+        })
+    }
+
+    /// Whether columns run east and rows south, as the pixel scale and tie
+    /// point tags (read by every GIS) require; other images are described
+    /// by a full transformation matrix
+    fn is_north_up(&self) -> bool {
+        self.pixel_size.0 > 0.0 && self.pixel_size.1 < 0.0
+    }
+
+    /// Contents of the GeoKey directory: a header, then keys in id order
+    fn geo_keys(&self) -> Vec<u16> {
+        let keys: Vec<(u16, u16)> = if self.projected {
+            vec![
+                (GT_MODEL_TYPE_GEO_KEY, MODEL_TYPE_PROJECTED),
+                (GT_RASTER_TYPE_GEO_KEY, RASTER_PIXEL_IS_AREA),
+                (PROJECTED_CS_TYPE_GEO_KEY, self.epsg),
+                (PROJ_LINEAR_UNITS_GEO_KEY, LINEAR_METER),
+            ]
+        } else {
+            vec![
+                (GT_MODEL_TYPE_GEO_KEY, MODEL_TYPE_GEOGRAPHIC),
+                (GT_RASTER_TYPE_GEO_KEY, RASTER_PIXEL_IS_AREA),
+                (GEOGRAPHIC_TYPE_GEO_KEY, self.epsg),
+                (GEOG_ANGULAR_UNITS_GEO_KEY, ANGULAR_DEGREE),
+            ]
+        };
+        // Version 1.1.0; values are held in the directory itself (location 0)
+        let mut directory = vec![1, 1, 0, keys.len() as u16];
+        for (id, value) in keys {
+            directory.extend([id, 0, 1, value]);
+        }
+        directory
+    }
+}
+
+/// EPSG code of an `EPSG:<code>` identifier, if it fits a GeoKey
+fn epsg_code(identifier: &str) -> Option<u16> {
+    let (authority, code) = identifier.split_once(':')?;
+    if !authority.eq_ignore_ascii_case("EPSG") {
+        return None;
+    }
+    code.parse().ok()
+}
+
+/// Encode an image as a deflate-compressed GeoTIFF
+pub fn encode(img: &RgbaImage, georeference: &GeoReference) -> Result<Vec<u8>> {
+    let failed = |e: tiff::TiffError| RossbyError::ImageGeneration {
+        message: format!("Failed to encode GeoTIFF: {}", e),
+    };
+    let mut buffer = Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut buffer).map_err(failed)?;
+    let mut image = encoder
+        .new_image_with_compression::<RGBA8, _>(img.width(), img.height(), Deflate::default())
+        .map_err(failed)?;
+
+    let directory = image.encoder();
+    directory
+        .write_tag(Tag::ExtraSamples, UNASSOCIATED_ALPHA)
+        .map_err(failed)?;
+    let (x, y) = georeference.origin;
+    let (dx, dy) = georeference.pixel_size;
+    if georeference.is_north_up() {
+        directory
+            .write_tag(Tag::ModelPixelScaleTag, &[dx, -dy, 0.0][..])
+            .map_err(failed)?;
+        directory
+            .write_tag(Tag::ModelTiepointTag, &[0.0, 0.0, 0.0, x, y, 0.0][..])
+            .map_err(failed)?;
+    } else {
+        #[rustfmt::skip]
+        let matrix = [
+            dx, 0.0, 0.0, x,
+            0.0, dy, 0.0, y,
+            0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ];
+        directory
+            .write_tag(Tag::ModelTransformationTag, &matrix[..])
+            .map_err(failed)?;
+    }
+    directory
+        .write_tag(Tag::GeoKeyDirectoryTag, &georeference.geo_keys()[..])
+        .map_err(failed)?;
+
+    image.write_data(img.as_raw()).map_err(failed)?;
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiff::decoder::{Decoder, DecodingResult};
+
+    #[test]
+    fn test_geo_reference() {
+        let wgs84 = Crs::wgs84();
+
+        // Pixel centers at the coordinates of the edge rows and columns
+        let georeference = GeoReference::new(
+            &wgs84,
+            LatitudeScaling::PlateCarree,
+            (0.0, 10.0),
+            (50.0, 40.0),
+            11,
+            6,
+        )
+        .unwrap();
+        assert_eq!(georeference.origin, (-0.5, 51.0));
+        assert_eq!(georeference.pixel_size, (1.0, -2.0));
+        assert_eq!((georeference.epsg, georeference.projected), (4326, false));
+        assert!(georeference.is_north_up());
+
+        // Mercator images are in meters of Web Mercator
+        let mercator = GeoReference::new(
+            &wgs84,
+            LatitudeScaling::Mercator,
+            (-180.0, 180.0),
+            (0.0, -85.0),
+            3,
+            3,
+        )
+        .unwrap();
+        assert_eq!((mercator.epsg, mercator.projected), (3857, true));
+        let half_circumference = std::f64::consts::PI * WEB_MERCATOR_RADIUS;
+        assert!((mercator.pixel_size.0 - half_circumference).abs() < 1e-6);
+        assert!(mercator.is_north_up());
+
+        // Projections without an affine transform, CRS without EPSG codes
+        // and degenerate spans are rejected
+        let span = ((0.0, 10.0), (50.0, 40.0));
+        let new = |crs: &Crs, scaling| GeoReference::new(crs, scaling, span.0, span.1, 11, 6);
+        assert!(new(&wgs84, LatitudeScaling::EqualArea).is_err());
+        assert!(new(&wgs84, LatitudeScaling::PoleCorrected).is_err());
+        assert!(new(
+            &Crs::parse("ESRI:104199").unwrap(),
+            LatitudeScaling::PlateCarree
+        )
+        .is_err());
+        assert!(new(&Crs::parse("EPSG:4258").unwrap(), LatitudeScaling::Mercator).is_err());
+        assert!(GeoReference::new(
+            &wgs84,
+            LatitudeScaling::PlateCarree,
+            (5.0, 5.0),
+            span.1,
+            11,
+            6
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let img = RgbaImage::from_fn(4, 3, |x, y| image::Rgba([x as u8, y as u8, 7, 255]));
+        let decode = |georeference: &GeoReference| {
+            let bytes = encode(&img, georeference).unwrap();
+            Decoder::new(Cursor::new(bytes)).unwrap()
+        };
+
+        let north_up = GeoReference {
+            origin: (-0.5, 51.0),
+            pixel_size: (1.0, -2.0),
+            epsg: 4326,
+            projected: false,
+        };
+        let mut decoder = decode(&north_up);
+        assert_eq!(decoder.dimensions().unwrap(), (4, 3));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(pixels) => assert_eq!(pixels, img.as_raw().clone()),
+            other => panic!("unexpected pixels: {:?}", other),
+        }
+        assert_eq!(
+            decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).unwrap(),
+            vec![1.0, 2.0, 0.0]
+        );
+        assert_eq!(
+            decoder.get_tag_f64_vec(Tag::ModelTiepointTag).unwrap(),
+            vec![0.0, 0.0, 0.0, -0.5, 51.0, 0.0]
+        );
+        assert_eq!(
+            decoder.get_tag_u16_vec(Tag::GeoKeyDirectoryTag).unwrap(),
+            vec![1, 1, 0, 4, 1024, 0, 1, 2, 1025, 0, 1, 1, 2048, 0, 1, 4326, 2054, 0, 1, 9102]
+        );
+
+        // South-up images are described by a transformation matrix
+        let south_up = GeoReference {
+            pixel_size: (1.0, 2.0),
+            ..north_up
+        };
+        let mut decoder = decode(&south_up);
+        assert!(decoder.find_tag(Tag::ModelTiepointTag).unwrap().is_none());
+        let matrix = decoder
+            .get_tag_f64_vec(Tag::ModelTransformationTag)
+            .unwrap();
+        assert_eq!(&matrix[..8], &[1.0, 0.0, 0.0, -0.5, 0.0, 2.0, 0.0, 51.0]);
+    }
+}
@@ -0,0 +1,167 @@
+//! Snapping of rendered images to a global reference grid.
+//!
+//! An image maps its first and last pixel columns onto the first and last
+//! grid columns inside the bbox and spaces the others evenly in between, so
+//! images of the same variable at different bboxes or sizes generally place
+//! their pixels at different longitudes and latitudes, and mosaics of them
+//! show seams. With `snap_to_grid=true`, each horizontal axis is rendered on a
+//! reference grid anchored at the first native grid point, with a pixel
+//! spacing of the native spacing times a power of two: the bbox is shrunk to
+//! whole reference pixels and the image size follows from it, staying within
+//! a factor of √2 of the size requested. Every image at the same zoom level
+//! then samples exactly the same points. The bbox actually rendered is
+//! returned in a header.
+
+use crate::error::{Result, RossbyError};
+
+/// Query parameter enabling snapping
+pub const SNAP_PARAM: &str = "snap_to_grid";
+
+/// Response header giving the coordinates of the outermost pixel centers of a
+/// snapped image as `min_lon,min_lat,max_lon,max_lat`
+pub const SNAPPED_BBOX_HEADER: &str = "x-rossby-snapped-bbox";
+
+/// Relative deviation from the mean spacing tolerated in a regular grid
+const SPACING_TOLERANCE: f64 = 1e-6;
+
+/// An image axis snapped to the reference grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnappedAxis {
+    /// Grid index of the first pixel
+    pub first: usize,
+    /// Grid index of the last pixel
+    pub last: usize,
+    /// Number of pixels along the axis
+    pub pixels: u32,
+    /// Pixel spacing as a power of two of the native spacing, e.g. 1 for
+    /// every other grid point or -1 for two pixels per grid cell
+    pub zoom: i32,
+}
+
+impl SnappedAxis {
+    /// Snap the range `[min, max]` of a regular coordinate axis, rendered
+    /// with about `pixels` pixels
+    pub fn new(axis: &str, coords: &[f64], min: f64, max: f64, pixels: u32) -> Result<Self> {
+        let invalid = |message: String| RossbyError::InvalidParameter {
+            param: SNAP_PARAM.to_string(),
+            message,
+        };
+        let spacing = regular_spacing(coords).ok_or_else(|| {
+            invalid(format!(
+                "The {} coordinates are not evenly spaced, so there is no reference grid",
+                axis
+            ))
+        })?;
+
+        // Fractional grid indices of the range, in ascending order
+        let to_index = |value: f64| (value - coords[0]) / spacing;
+        let last_index = (coords.len() - 1) as f64;
+        let (lo, hi) = (to_index(min), to_index(max));
+        let (lo, hi) = (lo.min(hi).max(0.0), lo.max(hi).min(last_index));
+        let (lo, hi) = (snap(lo, f64::ceil), snap(hi, f64::floor));
+        if hi <= lo {
+            return Err(invalid(format!(
+                "The bbox spans less than one {} grid cell",
+                axis
+            )));
+        }
+        let (lo, hi) = (lo as usize, hi as usize);
+
+        // Grid cells per pixel, rounded to a power of two
+        let cells_per_pixel = (hi - lo) as f64 / pixels.saturating_sub(1).max(1) as f64;
+        let mut zoom = cells_per_pixel.log2().round() as i32;
+        if zoom < 0 {
+            return Ok(Self {
+                first: lo,
+                last: hi,
+                pixels: ((hi - lo) << -zoom) as u32 + 1,
+                zoom,
+            });
+        }
+        // Keep at least two pixels, so the axis has a spacing
+        loop {
+            let stride = 1usize << zoom;
+            let first = lo.div_ceil(stride) * stride;
+            let last = hi / stride * stride;
+            if last > first || zoom == 0 {
+                return Ok(Self {
+                    first,
+                    last,
+                    pixels: ((last - first) / stride) as u32 + 1,
+                    zoom,
+                });
+            }
+            zoom -= 1;
+        }
+    }
+}
+
+/// Round fractional indices within rounding error of an integer to it
+fn snap(index: f64, round: fn(f64) -> f64) -> f64 {
+    if (index - index.round()).abs() < SPACING_TOLERANCE {
+        index.round()
+    } else {
+        round(index)
+    }
+}
+
+/// Spacing of coordinates, if they are evenly spaced
+pub fn regular_spacing(coords: &[f64]) -> Option<f64> {
+    let (first, last) = (*coords.first()?, *coords.last()?);
+    if coords.len() < 2 {
+        return None;
+    }
+    let spacing = (last - first) / (coords.len() - 1) as f64;
+    let regular = spacing != 0.0
+        && coords
+            .windows(2)
+            .all(|pair| ((pair[1] - pair[0]) / spacing - 1.0).abs() < SPACING_TOLERANCE);
+    regular.then_some(spacing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(first: f64, spacing: f64, n: usize) -> Vec<f64> {
+        (0..n).map(|i| first + i as f64 * spacing).collect()
+    }
+
+    #[test]
+    fn test_regular_spacing() {
+        assert_eq!(regular_spacing(&grid(0.0, 0.25, 9)), Some(0.25));
+        assert_eq!(regular_spacing(&grid(90.0, -1.0, 181)), Some(-1.0));
+        assert_eq!(regular_spacing(&[0.0, 1.0, 3.0]), None);
+        assert_eq!(regular_spacing(&[0.0]), None);
+    }
+
+    #[test]
+    fn test_snapped_axis() {
+        let lons = grid(0.0, 0.25, 1440);
+
+        // 99 cells on 26 pixels round to 4 cells per pixel
+        let axis = SnappedAxis::new("longitude", &lons, 10.1, 35.1, 26).unwrap();
+        assert_eq!(axis.zoom, 2);
+        assert_eq!((axis.first, axis.last), (44, 140));
+        assert_eq!(axis.pixels, 25);
+
+        // The same zoom over another range samples the same grid points
+        let other = SnappedAxis::new("longitude", &lons, 20.0, 60.0, 41).unwrap();
+        assert_eq!(other.zoom, 2);
+        assert_eq!(other.first % 4, 0);
+        assert_eq!(other.pixels, 41);
+
+        // More pixels than cells subdivide them
+        let axis = SnappedAxis::new("longitude", &lons, 10.0, 11.0, 17).unwrap();
+        assert_eq!(axis.zoom, -2);
+        assert_eq!((axis.first, axis.last, axis.pixels), (40, 44, 17));
+
+        // Descending coordinates give the same indices
+        let lats = grid(90.0, -0.25, 721);
+        let axis = SnappedAxis::new("latitude", &lats, 40.0, 50.0, 21).unwrap();
+        assert_eq!((axis.first, axis.last, axis.zoom), (160, 200, 1));
+
+        assert!(SnappedAxis::new("longitude", &lons, 10.01, 10.2, 8).is_err());
+        assert!(SnappedAxis::new("longitude", &[0.0, 1.0, 3.0], 0.0, 3.0, 8).is_err());
+    }
+}
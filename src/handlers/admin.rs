@@ -0,0 +1,275 @@
+//! Operator endpoints under `/admin`.
+//!
+//! Admin endpoints are disabled unless `server.admin_token` is configured,
+//! and every request must carry that token in the `X-Admin-Token` header.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::error::{Result, RossbyError};
+use crate::lazy::flush_slab_caches;
+use crate::logging::generate_request_id;
+use crate::snapshot::snapshot;
+use crate::state::AppState;
+
+/// Header carrying the admin token
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// A server-side cache that can be flushed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    /// Statistics of whole horizontal slices, shared by `/image` and `/stats`
+    Stats,
+    /// Pressure fields derived from parametric vertical coordinates
+    Pressure,
+    /// Rendered `/thumbnail` previews
+    Thumbnails,
+    /// Climatological normals computed from the loaded data
+    Climatology,
+    /// Slabs of lazily read variables, with their checksums (see
+    /// [`crate::lazy`])
+    Slabs,
+}
+
+impl CacheKind {
+    /// Every cache, in the order they are reported
+    pub const ALL: [CacheKind; 5] = [
+        CacheKind::Stats,
+        CacheKind::Pressure,
+        CacheKind::Thumbnails,
+        CacheKind::Climatology,
+        CacheKind::Slabs,
+    ];
+
+    /// Name used in the `cache` parameter and the response
+    pub fn name(self) -> &'static str {
+        match self {
+            CacheKind::Stats => "stats",
+            CacheKind::Pressure => "pressure",
+            CacheKind::Thumbnails => "thumbnails",
+            CacheKind::Climatology => "climatology",
+            CacheKind::Slabs => "slabs",
+        }
+    }
+
+    /// Parse a comma-separated list of caches; an empty list selects all
+    pub fn parse_list(value: Option<&str>) -> Result<Vec<CacheKind>> {
+        let mut kinds = Vec::new();
+        for name in value.unwrap_or("").split(',').map(str::trim) {
+            if name.is_empty() {
+                continue;
+            }
+            if name == "all" {
+                return Ok(Self::ALL.to_vec());
+            }
+            let kind = Self::ALL
+                .into_iter()
+                .find(|kind| kind.name() == name)
+                .ok_or_else(|| RossbyError::InvalidParameter {
+                    param: "cache".to_string(),
+                    message: format!(
+                        "Unknown cache: {}. Valid values are {}, or all",
+                        name,
+                        Self::ALL.map(CacheKind::name).join(", ")
+                    ),
+                })?;
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        if kinds.is_empty() {
+            return Ok(Self::ALL.to_vec());
+        }
+        Ok(kinds)
+    }
+
+    /// Drop the cached entries, returning how many there were
+    pub fn flush(self, state: &AppState) -> usize {
+        match self {
+            CacheKind::Stats => state.slice_stats.clear(),
+            CacheKind::Pressure => state.pressure_fields.clear(),
+            CacheKind::Thumbnails => state.thumbnails.clear(),
+            CacheKind::Climatology => state.climatology.clear(),
+            CacheKind::Slabs => flush_slab_caches(state),
+        }
+    }
+}
+
+/// Query parameters for the cache flush endpoint
+#[derive(Debug, Deserialize)]
+pub struct FlushQuery {
+    /// Comma-separated caches to flush (default: all)
+    pub cache: Option<String>,
+}
+
+/// Handle POST /admin/caches/flush requests
+///
+/// Flushed caches are refilled on demand by later requests, so operators can
+/// invalidate derived products after correcting data without a restart.
+pub async fn flush_caches_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<FlushQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    debug!(
+        endpoint = "/admin/caches/flush",
+        request_id = %request_id,
+        cache = ?params.cache,
+        "Processing cache flush request"
+    );
+
+    if let Err((status, message)) = authorize(&state, &headers, &request_id) {
+        return error_response(status, message, &request_id);
+    }
+
+    let kinds = match CacheKind::parse_list(params.cache.as_deref()) {
+        Ok(kinds) => kinds,
+        Err(error) => {
+            return error_response(StatusCode::BAD_REQUEST, error.to_string(), &request_id)
+        }
+    };
+
+    let flushed: BTreeMap<&str, usize> = kinds
+        .into_iter()
+        .map(|kind| (kind.name(), kind.flush(&state)))
+        .collect();
+    info!(
+        endpoint = "/admin/caches/flush",
+        request_id = %request_id,
+        flushed = ?flushed,
+        "Flushed caches"
+    );
+
+    Json(serde_json::json!({
+        "flushed": flushed,
+        "request_id": request_id,
+    }))
+    .into_response()
+}
+
+/// Handle GET /admin/state requests
+///
+/// Returns the snapshot written by `rossby dump-state` of the running server
+/// (see [`crate::snapshot`]), including appended time steps.
+pub async fn state_snapshot_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let request_id = generate_request_id();
+    debug!(
+        endpoint = "/admin/state",
+        request_id = %request_id,
+        "Processing state snapshot request"
+    );
+
+    if let Err((status, message)) = authorize(&state, &headers, &request_id) {
+        return error_response(status, message, &request_id);
+    }
+
+    match snapshot(&state) {
+        Ok(snapshot) => {
+            info!(
+                endpoint = "/admin/state",
+                request_id = %request_id,
+                generation = state.generation,
+                "Returned state snapshot"
+            );
+            Json(snapshot).into_response()
+        }
+        Err(error) => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            error.to_string(),
+            &request_id,
+        ),
+    }
+}
+
+/// Check the admin token of a request, returning the status and message of
+/// the rejection otherwise
+fn authorize(
+    state: &AppState,
+    headers: &HeaderMap,
+    request_id: &str,
+) -> std::result::Result<(), (StatusCode, String)> {
+    let Some(expected) = state.config.server.admin_token.as_deref() else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Admin endpoints are disabled; set server.admin_token to enable them".to_string(),
+        ));
+    };
+    let provided = headers
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    if !tokens_match(provided, expected) {
+        warn!(request_id = %request_id, "Rejected admin request with an invalid token");
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            format!("Missing or invalid {} header", ADMIN_TOKEN_HEADER),
+        ));
+    }
+    Ok(())
+}
+
+/// Compare tokens in time independent of where they differ
+pub(crate) fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn error_response(status: StatusCode, message: String, request_id: &str) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": message,
+            "request_id": request_id,
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cache_list() {
+        assert_eq!(CacheKind::parse_list(None).unwrap(), CacheKind::ALL);
+        assert_eq!(CacheKind::parse_list(Some("all")).unwrap(), CacheKind::ALL);
+        assert_eq!(
+            CacheKind::parse_list(Some("pressure, stats,pressure")).unwrap(),
+            vec![CacheKind::Pressure, CacheKind::Stats]
+        );
+        assert_eq!(
+            CacheKind::parse_list(Some("thumbnails,climatology,slabs")).unwrap(),
+            vec![
+                CacheKind::Thumbnails,
+                CacheKind::Climatology,
+                CacheKind::Slabs
+            ]
+        );
+        let error = CacheKind::parse_list(Some("stats,tiles")).unwrap_err();
+        assert!(error.to_string().contains("Unknown cache: tiles"));
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3creT", "s3cret"));
+        assert!(!tokens_match("s3cre", "s3cret"));
+        assert!(!tokens_match("", "s3cret"));
+    }
+}
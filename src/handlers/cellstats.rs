@@ -0,0 +1,378 @@
+//! Per-cell statistics endpoint handler.
+//!
+//! Summarizes a variable at one location over its whole time axis (or a
+//! selected range of it): count, extremes, mean, standard deviation and
+//! percentiles, optionally over the time steps of one season only. The
+//! values are interpolated one time step at a time and folded into running
+//! statistics (see [`crate::running_stats`]), so the series is never held in
+//! memory and long records answer "climatology at my location" as quickly as
+//! they can be read. Percentiles are P² estimates, exact for up to five
+//! values.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Datelike;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, info_span};
+
+use crate::bounds::{BoundsMode, BOUNDS_HEADER};
+use crate::cf_time::time_units;
+use crate::error::{Result, RossbyError};
+use crate::field::find_lat_lon_axes;
+use crate::handlers::profile_series::{coordinate_value, for_each_point_value, time_dimension};
+use crate::handlers::stats::selection_to_json;
+use crate::interpolation::get_interpolator;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::query::Selection;
+use crate::query_resolved::{echo_requested, QueryResolved, ResolvedPoint, QUERY_RESOLVED_KEY};
+use crate::running_stats::{P2Quantile, RunningStats};
+use crate::state::{AppState, AttributeValue};
+
+/// Percentiles estimated unless `percentiles` is given
+const DEFAULT_PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+/// Most percentiles one request may ask for
+const MAX_PERCENTILES: usize = 20;
+
+/// Query parameters for the per-cell statistics endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct CellStatsQuery {
+    /// Variable name
+    pub var: String,
+    /// Longitude of the cell
+    pub lon: f64,
+    /// Latitude of the cell
+    pub lat: f64,
+    /// Comma-separated percentiles between 0 and 100 exclusive (default
+    /// 5,25,50,75,95)
+    #[serde(default)]
+    pub percentiles: Option<String>,
+    /// Meteorological season the time steps are restricted to (djf, mam, jja
+    /// or son; default: all time steps)
+    #[serde(default)]
+    pub season: Option<String>,
+    /// Horizontal interpolation method (nearest, bilinear, bicubic; default bilinear)
+    #[serde(default)]
+    pub interpolation: Option<String>,
+    /// Handling of coordinates outside the grid (error, clamp or wrap)
+    #[serde(default)]
+    pub bounds: Option<String>,
+    /// Describe how the query was interpreted in a `query_resolved` section
+    /// (true or false)
+    #[serde(default)]
+    pub echo_params: Option<String>,
+    /// Dimension selectors: the range of time steps (default: all) and single
+    /// slices of the other non-horizontal dimensions
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Three-month meteorological season
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    /// December, January, February
+    Djf,
+    /// March, April, May
+    Mam,
+    /// June, July, August
+    Jja,
+    /// September, October, November
+    Son,
+}
+
+impl Season {
+    /// Parse a season name
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "djf" => Ok(Season::Djf),
+            "mam" => Ok(Season::Mam),
+            "jja" => Ok(Season::Jja),
+            "son" => Ok(Season::Son),
+            other => Err(RossbyError::InvalidParameter {
+                param: "season".to_string(),
+                message: format!(
+                    "Unknown season: {}. Valid values are 'djf', 'mam', 'jja' or 'son'",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Name of the season as given in `season`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Season::Djf => "djf",
+            Season::Mam => "mam",
+            Season::Jja => "jja",
+            Season::Son => "son",
+        }
+    }
+
+    /// Whether a calendar month (1 to 12) falls in the season
+    pub fn contains(self, month: u32) -> bool {
+        let season = match month {
+            12 | 1 | 2 => Season::Djf,
+            3..=5 => Season::Mam,
+            6..=8 => Season::Jja,
+            _ => Season::Son,
+        };
+        season == self
+    }
+}
+
+/// Handle GET /cellstats requests
+pub async fn cellstats_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CellStatsQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/cellstats",
+        request_id = %request_id,
+        var = %params.var,
+        lon = params.lon,
+        lat = params.lat,
+        season = ?params.season,
+        "Processing cell statistics request"
+    );
+
+    match process_cellstats_query(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/cellstats",
+                request_id = %request_id,
+                var = %params.var,
+                duration_us = duration.as_micros() as u64,
+                "Cell statistics request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/cellstats",
+                &request_id,
+                Some(&format!(
+                    "var={}, lon={}, lat={}",
+                    params.var, params.lon, params.lat
+                )),
+            );
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_cellstats_query(state: &AppState, params: &CellStatsQuery) -> Result<Response> {
+    if !state.has_variable(&params.var) {
+        return Err(RossbyError::VariableNotFound {
+            name: params.var.clone(),
+        });
+    }
+    let interpolator = get_interpolator(params.interpolation.as_deref().unwrap_or("bilinear"))?;
+    let bounds = BoundsMode::from_request(params.bounds.as_deref(), &state.config.data.bounds)?;
+    let percentiles = parse_percentiles(params.percentiles.as_deref())?;
+    let season = params.season.as_deref().map(Season::parse).transpose()?;
+    let echo = echo_requested(params.echo_params.as_deref())?;
+    let time_dim = time_dimension(state, &params.var)?;
+
+    // Seasons need calendar dates of the time steps
+    let units = season
+        .map(|_| {
+            time_units(state, &time_dim).ok_or_else(|| RossbyError::InvalidParameter {
+                param: "season".to_string(),
+                message: format!(
+                    "The time coordinate '{}' has no CF time units to take months from",
+                    time_dim
+                ),
+            })
+        })
+        .transpose()?;
+    let in_season = |index: usize| match (season, &units) {
+        (Some(season), Some(units)) => units
+            .datetime(coordinate_value(state, &time_dim, index))
+            .is_some_and(|date| season.contains(date.month())),
+        _ => true,
+    };
+
+    let mut stats = RunningStats::new();
+    let mut estimators = percentiles
+        .iter()
+        .map(|&p| P2Quantile::new(p))
+        .collect::<Result<Vec<_>>>()?;
+    let mut steps = 0;
+    let mut range: Option<(usize, usize)> = None;
+    let mut visited = Vec::new();
+    let pinned = {
+        let _stage = info_span!("accumulate").entered();
+        for_each_point_value(
+            state,
+            &params.var,
+            (params.lon, params.lat),
+            &time_dim,
+            &params.dimension_params,
+            bounds,
+            interpolator.as_ref(),
+            |index, value| {
+                if echo {
+                    visited.push(index);
+                }
+                if !in_season(index) {
+                    return;
+                }
+                steps += 1;
+                range = Some(range.map_or((index, index), |(first, _)| (first, index)));
+                stats.push(value as f64);
+                for estimator in &mut estimators {
+                    estimator.push(value as f64);
+                }
+            },
+        )?
+    };
+
+    let units = match state
+        .get_variable_metadata_checked(&params.var)?
+        .attributes
+        .get("units")
+    {
+        Some(AttributeValue::Text(units)) => Some(units.clone()),
+        _ => None,
+    };
+    let selection: HashMap<String, (usize, f64)> = pinned
+        .iter()
+        .map(|(dim, &index)| (dim.clone(), (index, coordinate_value(state, dim, index))))
+        .collect();
+    let percentiles_json: serde_json::Map<String, Value> = percentiles
+        .iter()
+        .zip(&estimators)
+        .map(|(p, estimator)| (p.to_string(), json!(estimator.value())))
+        .collect();
+
+    let mut body = json!({
+        "var": params.var,
+        "units": units,
+        "lon": params.lon,
+        "lat": params.lat,
+        "interpolation": interpolator.name(),
+        "season": season.map(Season::as_str),
+        "time": {
+            "dimension": time_dim,
+            "steps": steps,
+            "first": range.map(|(first, _)| coordinate_value(state, &time_dim, first)),
+            "last": range.map(|(_, last)| coordinate_value(state, &time_dim, last)),
+        },
+        "selection": selection_to_json(&selection),
+        "stats": stats.stats(),
+        "percentiles": percentiles_json,
+    });
+    if echo {
+        let methods = (interpolator.name(), bounds);
+        let resolved = describe_query(state, params, &time_dim, &visited, &pinned, methods)?;
+        body[QUERY_RESOLVED_KEY] = resolved.to_json();
+    }
+
+    let mut response = Json(body).into_response();
+    response
+        .headers_mut()
+        .insert(BOUNDS_HEADER, HeaderValue::from_static(bounds.as_str()));
+    Ok(response)
+}
+
+/// The echo of a query (see [`crate::query_resolved`]): the time steps
+/// visited, the slices pinned and the location sampled
+fn describe_query(
+    state: &AppState,
+    params: &CellStatsQuery,
+    time_dim: &str,
+    visited: &[usize],
+    pinned: &HashMap<String, usize>,
+    (interpolation, bounds): (&str, BoundsMode),
+) -> Result<QueryResolved> {
+    let selection = Selection::parse(state, &params.dimension_params)?;
+    let mut resolved = QueryResolved::new();
+    resolved.pinned(state, &selection, pinned);
+    let param = selection.get(time_dim).map(|s| s.param.as_str());
+    resolved.dimension(state, time_dim, param, visited);
+
+    let dimensions = &state.get_variable_metadata_checked(&params.var)?.dimensions;
+    if let Some((lat_axis, lon_axis)) = find_lat_lon_axes(dimensions) {
+        resolved.point = Some(ResolvedPoint::locate(
+            state,
+            (&dimensions[lon_axis], &dimensions[lat_axis]),
+            (params.lon, params.lat),
+            bounds,
+        )?);
+    }
+    resolved.interpolation = Some(interpolation.to_string());
+    resolved.bounds = Some(bounds.as_str());
+    Ok(resolved)
+}
+
+/// Parse the `percentiles` parameter, in increasing order without repeats
+fn parse_percentiles(value: Option<&str>) -> Result<Vec<f64>> {
+    let Some(value) = value else {
+        return Ok(DEFAULT_PERCENTILES.to_vec());
+    };
+    let mut percentiles = value
+        .split(',')
+        .map(|p| {
+            p.trim()
+                .parse::<f64>()
+                .map_err(|_| RossbyError::InvalidParameter {
+                    param: "percentiles".to_string(),
+                    message: format!("Invalid percentile: {}", p),
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    percentiles.sort_by(f64::total_cmp);
+    percentiles.dedup();
+    if percentiles.len() > MAX_PERCENTILES {
+        return Err(RossbyError::InvalidParameter {
+            param: "percentiles".to_string(),
+            message: format!("At most {} percentiles can be requested", MAX_PERCENTILES),
+        });
+    }
+    Ok(percentiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seasons() {
+        assert_eq!(Season::parse("JJA").unwrap(), Season::Jja);
+        assert!(Season::parse("summer").is_err());
+        assert!(Season::Djf.contains(12));
+        assert!(Season::Djf.contains(1));
+        assert!(!Season::Djf.contains(3));
+        assert!(Season::Son.contains(11));
+    }
+
+    #[test]
+    fn test_parse_percentiles() {
+        assert_eq!(parse_percentiles(None).unwrap(), DEFAULT_PERCENTILES);
+        assert_eq!(
+            parse_percentiles(Some("90, 10,50,10")).unwrap(),
+            vec![10.0, 50.0, 90.0]
+        );
+        assert!(parse_percentiles(Some("10,x")).is_err());
+    }
+}
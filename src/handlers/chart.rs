@@ -0,0 +1,470 @@
+//! Chart endpoint handler.
+//!
+//! Returns a ready-to-render Vega-Lite specification of a common plot of a
+//! variable, so notebooks and dashboards can show it with any Vega-Lite
+//! renderer and no plotting code of their own:
+//!
+//! - `type=timeseries`: the variable interpolated at `lon`/`lat` for every
+//!   selected time step, as a line
+//! - `type=profile`: the variable interpolated at `lon`/`lat` for every
+//!   selected vertical level, as a line with the levels on the y axis
+//! - `type=histogram`: the distribution of the values of one horizontal slice
+//!   (optionally within a `bbox`), as bars
+//!
+//! The data is computed by the same code as `/profile_series` and `/stats`
+//! and inlined in the spec as records, since Vega-Lite cannot read the
+//! array-shaped responses of `/data`. The request the chart was made from is
+//! kept under `usermeta`.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::bounds::{BoundsMode, BOUNDS_HEADER};
+use crate::cf_time::time_units;
+use crate::colormaps::parse_bbox;
+use crate::error::{Result, RossbyError};
+use crate::field::{resolve_dimension_indices, HorizontalField};
+use crate::handlers::profile_series::{
+    coordinate_value, point_series, time_dimension, vertical_dimension, PointSeries,
+};
+use crate::handlers::stats::selection_to_json;
+use crate::interpolation::get_interpolator;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::{AppState, AttributeValue};
+
+/// Vega-Lite schema the specs are written against
+pub const VEGA_LITE_SCHEMA: &str = "https://vega.github.io/schema/vega-lite/v5.json";
+
+/// Default and maximum number of histogram bins
+const DEFAULT_BINS: usize = 20;
+const MAX_BINS: usize = 200;
+
+/// Default chart dimensions in pixels
+const DEFAULT_WIDTH: u32 = 600;
+const DEFAULT_HEIGHT: u32 = 300;
+
+/// Units of vertical coordinates increasing downwards
+const PRESSURE_UNITS: &[&str] = &["Pa", "hPa", "kPa", "mbar", "millibar", "bar", "atm"];
+
+/// Query parameters for the chart endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChartQuery {
+    /// Chart type (timeseries, profile or histogram)
+    #[serde(rename = "type")]
+    pub chart_type: String,
+    /// Variable name
+    pub var: String,
+    /// Longitude of a timeseries or profile
+    #[serde(default)]
+    pub lon: Option<f64>,
+    /// Latitude of a timeseries or profile
+    #[serde(default)]
+    pub lat: Option<f64>,
+    /// Horizontal interpolation method (nearest, bilinear, bicubic; default bilinear)
+    #[serde(default)]
+    pub interpolation: Option<String>,
+    /// Handling of coordinates outside the grid (error, clamp or wrap)
+    #[serde(default)]
+    pub bounds: Option<String>,
+    /// Vertical dimension of a profile, if the variable has several candidates
+    #[serde(default)]
+    pub vertical_dim: Option<String>,
+    /// Number of histogram bins (default 20)
+    #[serde(default)]
+    pub bins: Option<usize>,
+    /// Bounding box of a histogram as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Chart title (default: the long name and units of the variable)
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Chart width in pixels
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Chart height in pixels
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Dimension selectors: the range of the plotted dimension and single
+    /// slices of the others
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Handle GET /chart requests
+pub async fn chart_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChartQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/chart",
+        request_id = %request_id,
+        chart_type = %params.chart_type,
+        var = %params.var,
+        "Processing chart request"
+    );
+
+    match process_chart_query(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/chart",
+                request_id = %request_id,
+                chart_type = %params.chart_type,
+                var = %params.var,
+                duration_us = duration.as_micros() as u64,
+                "Chart request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/chart",
+                &request_id,
+                Some(&format!("type={}, var={}", params.chart_type, params.var)),
+            );
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_chart_query(state: &AppState, params: &ChartQuery) -> Result<Response> {
+    if !state.has_variable(&params.var) {
+        return Err(RossbyError::VariableNotFound {
+            name: params.var.clone(),
+        });
+    }
+    let bounds = BoundsMode::from_request(params.bounds.as_deref(), &state.config.data.bounds)?;
+
+    let spec = match params.chart_type.to_lowercase().as_str() {
+        "timeseries" => {
+            let time_dim = time_dimension(state, &params.var)?;
+            let series = chart_series(state, params, &time_dim, bounds)?;
+            series_spec(state, params, &series, false)
+        }
+        "profile" => {
+            let time_dim = time_dimension(state, &params.var).ok();
+            let vertical_dim = vertical_dimension(
+                state,
+                &params.var,
+                time_dim.as_deref(),
+                params.vertical_dim.as_deref(),
+            )?;
+            let series = chart_series(state, params, &vertical_dim, bounds)?;
+            series_spec(state, params, &series, true)
+        }
+        "histogram" => histogram_spec(state, params)?,
+        other => {
+            return Err(RossbyError::InvalidParameter {
+                param: "type".to_string(),
+                message: format!(
+                    "Unsupported chart type: {}. Valid values are 'timeseries', 'profile' \
+                     or 'histogram'",
+                    other
+                ),
+            })
+        }
+    };
+
+    let mut response = Json(spec).into_response();
+    response
+        .headers_mut()
+        .insert(BOUNDS_HEADER, HeaderValue::from_static(bounds.as_str()));
+    Ok(response)
+}
+
+/// Interpolate the variable at the query location along `dimension`
+fn chart_series(
+    state: &AppState,
+    params: &ChartQuery,
+    dimension: &str,
+    bounds: BoundsMode,
+) -> Result<PointSeries> {
+    let location = match (params.lon, params.lat) {
+        (Some(lon), Some(lat)) => (lon, lat),
+        _ => {
+            return Err(RossbyError::InvalidParameter {
+                param: if params.lon.is_none() { "lon" } else { "lat" }.to_string(),
+                message: format!("A {} chart needs both lon and lat", params.chart_type),
+            })
+        }
+    };
+    let interpolator = get_interpolator(params.interpolation.as_deref().unwrap_or("bilinear"))?;
+    point_series(
+        state,
+        &params.var,
+        location,
+        dimension,
+        &params.dimension_params,
+        bounds,
+        interpolator.as_ref(),
+    )
+}
+
+/// Line chart of a point series; profiles put the dimension on the y axis
+fn series_spec(
+    state: &AppState,
+    params: &ChartQuery,
+    series: &PointSeries,
+    profile: bool,
+) -> Value {
+    let dimension = &series.dimension;
+    let time = time_units(state, dimension);
+    let values: Vec<Value> = series
+        .coordinates
+        .iter()
+        .zip(&series.values)
+        .map(|(&coordinate, &value)| {
+            let coordinate = match time.as_ref().and_then(|units| units.iso(coordinate)) {
+                Some(iso) => json!(iso),
+                None => json!(coordinate),
+            };
+            json!({ dimension.as_str(): coordinate, params.var.as_str(): finite(value) })
+        })
+        .collect();
+
+    let dimension_encoding = json!({
+        "field": dimension,
+        "type": if time.is_some() { "temporal" } else { "quantitative" },
+        "title": axis_title(state, dimension),
+    });
+    let mut value_encoding = json!({
+        "field": params.var,
+        "type": "quantitative",
+        "title": axis_title(state, &params.var),
+    });
+    let encoding = if profile {
+        let mut level_encoding = dimension_encoding;
+        if increases_downwards(state, dimension) {
+            level_encoding["scale"] = json!({ "reverse": true });
+        }
+        value_encoding["scale"] = json!({ "zero": false });
+        json!({
+            "x": value_encoding,
+            "y": level_encoding,
+            "order": { "field": dimension, "type": "quantitative" },
+        })
+    } else {
+        value_encoding["scale"] = json!({ "zero": false });
+        json!({ "x": dimension_encoding, "y": value_encoding })
+    };
+
+    let mut spec = base_spec(state, params, values);
+    spec["mark"] = json!({ "type": "line", "point": true, "tooltip": true });
+    spec["encoding"] = encoding;
+    spec["usermeta"]["lon"] = json!(params.lon);
+    spec["usermeta"]["lat"] = json!(params.lat);
+    spec["usermeta"]["selection"] = selection_json(state, &series.pinned);
+    spec
+}
+
+/// Bar chart of the distribution of one horizontal slice
+fn histogram_spec(state: &AppState, params: &ChartQuery) -> Result<Value> {
+    let bins = params.bins.unwrap_or(DEFAULT_BINS);
+    if bins == 0 || bins > MAX_BINS {
+        return Err(RossbyError::InvalidParameter {
+            param: "bins".to_string(),
+            message: format!("bins must be between 1 and {}", MAX_BINS),
+        });
+    }
+    let bbox = params
+        .bbox
+        .as_deref()
+        .map(parse_bbox)
+        .transpose()?
+        .map(|(a, b, c, d)| (a as f64, b as f64, c as f64, d as f64));
+    let dim_indices = resolve_dimension_indices(state, &params.var, &params.dimension_params)?;
+    let field = HorizontalField::from_state(state, &params.var, &dim_indices, bbox)?;
+
+    let values: Vec<Value> = histogram(field.values.iter().copied(), bins)
+        .into_iter()
+        .map(|bin| json!({ "bin_start": bin.start, "bin_end": bin.end, "count": bin.count }))
+        .collect();
+
+    let mut spec = base_spec(state, params, values);
+    spec["mark"] = json!({ "type": "bar", "tooltip": true });
+    spec["encoding"] = json!({
+        "x": {
+            "field": "bin_start",
+            "type": "quantitative",
+            "bin": { "binned": true },
+            "title": axis_title(state, &params.var),
+        },
+        "x2": { "field": "bin_end" },
+        "y": { "field": "count", "type": "quantitative", "title": "Grid points" },
+    });
+    spec["usermeta"]["bbox"] = json!(params.bbox);
+    spec["usermeta"]["selection"] = selection_json(state, &dim_indices);
+    Ok(spec)
+}
+
+/// Fields shared by every spec
+fn base_spec(state: &AppState, params: &ChartQuery, values: Vec<Value>) -> Value {
+    let title = params
+        .title
+        .clone()
+        .unwrap_or_else(|| axis_title(state, &params.var));
+    json!({
+        "$schema": VEGA_LITE_SCHEMA,
+        "title": title,
+        "width": params.width.unwrap_or(DEFAULT_WIDTH),
+        "height": params.height.unwrap_or(DEFAULT_HEIGHT),
+        "data": { "values": values },
+        "usermeta": {
+            "type": params.chart_type.to_lowercase(),
+            "var": params.var,
+        },
+    })
+}
+
+/// One equal-width histogram bin
+#[derive(Debug, Clone, PartialEq)]
+struct Bin {
+    start: f64,
+    end: f64,
+    count: usize,
+}
+
+/// Count finite values in `bins` equal-width bins spanning their range
+///
+/// The last bin includes its upper edge. Without finite values there are no
+/// bins; a constant field gets a single bin of width one around its value.
+fn histogram(values: impl Iterator<Item = f32>, bins: usize) -> Vec<Bin> {
+    let values: Vec<f64> = values.filter(|v| v.is_finite()).map(f64::from).collect();
+    let (min, max) = values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+    if values.is_empty() {
+        return Vec::new();
+    }
+    if min == max {
+        return vec![Bin {
+            start: min - 0.5,
+            end: max + 0.5,
+            count: values.len(),
+        }];
+    }
+
+    let width = (max - min) / bins as f64;
+    let mut counts = vec![0; bins];
+    for value in values {
+        let bin = (((value - min) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| Bin {
+            start: min + i as f64 * width,
+            end: if i + 1 == bins {
+                max
+            } else {
+                min + (i + 1) as f64 * width
+            },
+            count,
+        })
+        .collect()
+}
+
+/// Finite values as JSON numbers, missing ones as null
+fn finite(value: f32) -> Value {
+    if value.is_finite() {
+        json!(value)
+    } else {
+        Value::Null
+    }
+}
+
+/// Text attribute of a variable or coordinate
+fn text_attribute<'a>(state: &'a AppState, name: &str, attribute: &str) -> Option<&'a str> {
+    match state.get_variable_metadata(name)?.attributes.get(attribute) {
+        Some(AttributeValue::Text(text)) => Some(text),
+        _ => None,
+    }
+}
+
+/// Long name (or name) of a variable or coordinate followed by its units
+fn axis_title(state: &AppState, name: &str) -> String {
+    let label = text_attribute(state, name, "long_name").unwrap_or(name);
+    match text_attribute(state, name, "units") {
+        Some(units) if !units.is_empty() && time_units(state, name).is_none() => {
+            format!("{} ({})", label, units)
+        }
+        _ => label.to_string(),
+    }
+}
+
+/// Whether a vertical coordinate increases downwards (pressure or
+/// `positive="down"`), so profiles plot it on a reversed axis
+fn increases_downwards(state: &AppState, dimension: &str) -> bool {
+    text_attribute(state, dimension, "positive").is_some_and(|p| p.eq_ignore_ascii_case("down"))
+        || text_attribute(state, dimension, "units").is_some_and(|u| PRESSURE_UNITS.contains(&u))
+}
+
+/// Indices and coordinates of the pinned dimensions
+fn selection_json(state: &AppState, pinned: &HashMap<String, usize>) -> Value {
+    let selection: HashMap<String, (usize, f64)> = pinned
+        .iter()
+        .map(|(dim, &index)| (dim.clone(), (index, coordinate_value(state, dim, index))))
+        .collect();
+    selection_to_json(&selection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let values = [0.0, 1.0, 2.0, 3.0, 4.0, f32::NAN, 4.0];
+        let bins = histogram(values.into_iter(), 4);
+        assert_eq!(bins.len(), 4);
+        assert_eq!((bins[0].start, bins[0].end), (0.0, 1.0));
+        assert_eq!(bins[3].end, 4.0);
+        // The maximum falls into the last bin; NaN is skipped
+        let counts: Vec<usize> = bins.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![1, 1, 1, 3]);
+    }
+
+    #[test]
+    fn test_histogram_degenerate() {
+        assert!(histogram([f32::NAN].into_iter(), 10).is_empty());
+        let bins = histogram([2.0, 2.0].into_iter(), 10);
+        assert_eq!(
+            bins,
+            vec![Bin {
+                start: 1.5,
+                end: 2.5,
+                count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_finite() {
+        assert_eq!(finite(1.5), json!(1.5));
+        assert_eq!(finite(f32::NAN), Value::Null);
+    }
+}
@@ -0,0 +1,665 @@
+//! Correlation endpoint handler.
+//!
+//! Computes, for every grid cell, the Pearson correlation or the covariance
+//! of two variables along a dimension (usually time), and returns it as a
+//! field: JSON values, a PNG rendered through the same colormaps as
+//! `/image`, or a single area-weighted mean over the region. The moments
+//! are accumulated one slice at a time with Welford's updates, so only one
+//! slab of each variable is extracted at once.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use ndarray::{Array2, Zip};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, info_span};
+
+use crate::colormaps::{self, parse_bbox, LatitudeScaling};
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, HorizontalField};
+use crate::handlers::exceedance::{DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use crate::handlers::image::generate_image;
+use crate::handlers::stats::selection_to_json;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::query::Selection;
+use crate::state::AppState;
+
+/// Query parameters for the correlation endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct CorrelateQuery {
+    /// First variable
+    pub var_a: String,
+    /// Second variable
+    pub var_b: String,
+    /// Dimension along which the variables are compared (default time)
+    #[serde(default)]
+    pub dim: Option<String>,
+    /// Computed statistic (correlation or covariance; default correlation)
+    #[serde(default)]
+    pub statistic: Option<String>,
+    /// Result (map of cells or their area-weighted mean; default map)
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Bounding box as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Output format of a map (json or png; default json)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Colormap name for `format=png`
+    #[serde(default)]
+    pub colormap: Option<String>,
+    /// Image width in pixels for `format=png`
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Image height in pixels for `format=png`
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Dimension selectors: a selection of the compared dimension (default:
+    /// all of it) and single slices of the other non-horizontal dimensions
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Statistic computed for every cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorrelationStatistic {
+    /// Pearson correlation coefficient
+    Correlation,
+    /// Sample covariance
+    Covariance,
+}
+
+impl CorrelationStatistic {
+    /// Parse the `statistic` parameter (default: correlation)
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("correlation") => Ok(CorrelationStatistic::Correlation),
+            Some("covariance") => Ok(CorrelationStatistic::Covariance),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "statistic".to_string(),
+                message: format!(
+                    "Unknown statistic: {}. Valid values are 'correlation' or 'covariance'",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Name of the statistic as given in `statistic`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CorrelationStatistic::Correlation => "correlation",
+            CorrelationStatistic::Covariance => "covariance",
+        }
+    }
+}
+
+/// Running means and co-moments of two variables in one cell
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoMoments {
+    /// Number of pairs with both values valid
+    pub count: u32,
+    mean_a: f64,
+    mean_b: f64,
+    /// Sum of squared deviations of the first variable
+    m2_a: f64,
+    /// Sum of squared deviations of the second variable
+    m2_b: f64,
+    /// Sum of products of the deviations of both variables
+    co_moment: f64,
+}
+
+impl CoMoments {
+    /// Add a pair of values with Welford's update
+    pub fn push(&mut self, a: f64, b: f64) {
+        self.count += 1;
+        let n = self.count as f64;
+        let delta_a = a - self.mean_a;
+        self.mean_a += delta_a / n;
+        let delta_b = b - self.mean_b;
+        self.mean_b += delta_b / n;
+        self.m2_a += delta_a * (a - self.mean_a);
+        self.m2_b += delta_b * (b - self.mean_b);
+        self.co_moment += delta_a * (b - self.mean_b);
+    }
+
+    /// Pearson correlation, None with fewer than two pairs or a constant variable
+    pub fn correlation(&self) -> Option<f64> {
+        if self.count < 2 || self.m2_a <= 0.0 || self.m2_b <= 0.0 {
+            return None;
+        }
+        Some((self.co_moment / (self.m2_a * self.m2_b).sqrt()).clamp(-1.0, 1.0))
+    }
+
+    /// Sample covariance, None with fewer than two pairs
+    pub fn covariance(&self) -> Option<f64> {
+        (self.count >= 2).then(|| self.co_moment / (self.count - 1) as f64)
+    }
+
+    /// The statistic, None where it is undefined
+    pub fn statistic(&self, statistic: CorrelationStatistic) -> Option<f64> {
+        match statistic {
+            CorrelationStatistic::Correlation => self.correlation(),
+            CorrelationStatistic::Covariance => self.covariance(),
+        }
+    }
+}
+
+/// Slices of two variables compared along a dimension
+pub(crate) struct PairSelection {
+    /// Compared dimension
+    pub dim: String,
+    /// Selected indices of the compared dimension
+    pub indices: Vec<usize>,
+    /// Single slices of the other non-horizontal dimensions of either variable
+    pub pinned: HashMap<String, usize>,
+}
+
+impl PairSelection {
+    /// Select the slices of two variables compared along `dim` (default time)
+    ///
+    /// The compared dimension may be selected by any range or list, every
+    /// other non-horizontal dimension of either variable is pinned to a
+    /// single slice (the first by default), and horizontal dimensions cannot
+    /// be selected.
+    pub fn parse(
+        state: &AppState,
+        var_a: &str,
+        var_b: &str,
+        dim: Option<&str>,
+        dimension_params: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let unknown: Vec<String> = [var_a, var_b]
+            .into_iter()
+            .filter(|name| !state.has_variable(name))
+            .map(str::to_string)
+            .collect();
+        if !unknown.is_empty() {
+            return Err(RossbyError::InvalidVariables { names: unknown });
+        }
+
+        let dims_a = &state.get_variable_metadata_checked(var_a)?.dimensions;
+        let dims_b = &state.get_variable_metadata_checked(var_b)?.dimensions;
+        let mut horizontal = Vec::new();
+        for (name, dims) in [(var_a, dims_a), (var_b, dims_b)] {
+            let (lat_axis, lon_axis) = find_lat_lon_axes(dims).ok_or_else(|| {
+                RossbyError::VariableNotSuitableForImage {
+                    name: name.to_string(),
+                }
+            })?;
+            horizontal.push(dims[lat_axis].clone());
+            horizontal.push(dims[lon_axis].clone());
+        }
+        let requested = dim.unwrap_or("time");
+        let dim = state
+            .resolve_dimension(requested)
+            .ok()
+            .filter(|dim| dims_a.iter().any(|d| d == dim) && dims_b.iter().any(|d| d == dim))
+            .filter(|dim| !horizontal.iter().any(|h| h == dim))
+            .ok_or_else(|| RossbyError::InvalidParameter {
+                param: "dim".to_string(),
+                message: format!(
+                    "'{}' is not a non-horizontal dimension of both '{}' and '{}'",
+                    requested, var_a, var_b
+                ),
+            })?
+            .to_string();
+
+        let selection = Selection::parse(state, dimension_params)?;
+        let mut pinned = HashMap::new();
+        let mut indices = None;
+        for dim_name in dims_a.iter().chain(dims_b.iter()) {
+            let selected = selection.get(dim_name);
+            if horizontal.contains(dim_name) {
+                if let Some(selected) = selected {
+                    return Err(RossbyError::InvalidParameter {
+                        param: selected.param.clone(),
+                        message: "Horizontal dimensions cannot be selected; use bbox instead"
+                            .to_string(),
+                    });
+                }
+            } else if *dim_name == dim {
+                if indices.is_none() {
+                    indices = selected.map(|s| s.resolve(state)).transpose()?;
+                }
+            } else if !pinned.contains_key(dim_name) {
+                let index = selected.map(|s| s.resolve_single(state)).transpose()?;
+                pinned.insert(dim_name.clone(), index.unwrap_or(0));
+            }
+        }
+        if let Some(selected) = selection
+            .iter()
+            .find(|s| !dims_a.contains(&s.dimension) && !dims_b.contains(&s.dimension))
+        {
+            return Err(RossbyError::InvalidParameter {
+                param: selected.param.clone(),
+                message: format!(
+                    "Dimension '{}' is not a dimension of '{}' or '{}'",
+                    selected.dimension, var_a, var_b
+                ),
+            });
+        }
+        let indices = match indices {
+            Some(indices) => indices,
+            None => {
+                let size = state.metadata.dimensions.get(&dim).map_or(0, |d| d.size);
+                (0..size).collect()
+            }
+        };
+        Ok(Self {
+            dim,
+            indices,
+            pinned,
+        })
+    }
+
+    /// Dimension indices of the slices at an index of the compared dimension
+    pub fn slice(&self, index: usize) -> HashMap<String, usize> {
+        let mut dim_indices = self.pinned.clone();
+        dim_indices.insert(self.dim.clone(), index);
+        dim_indices
+    }
+
+    /// Coordinates of the selected steps of the compared dimension
+    pub fn coordinates(&self, state: &AppState) -> Vec<f64> {
+        self.indices
+            .iter()
+            .map(|&i| {
+                state
+                    .get_coordinate(&self.dim)
+                    .and_then(|coords| coords.get(i).copied())
+                    .unwrap_or(i as f64)
+            })
+            .collect()
+    }
+
+    /// The pinned slices as a `selection` JSON section
+    pub fn selection_json(&self, state: &AppState) -> serde_json::Value {
+        let selection: HashMap<String, (usize, f64)> = self
+            .pinned
+            .iter()
+            .map(|(dim, &index)| {
+                let value = state
+                    .get_coordinate(dim)
+                    .and_then(|coords| coords.get(index).copied())
+                    .unwrap_or(index as f64);
+                (dim.clone(), (index, value))
+            })
+            .collect();
+        selection_to_json(&selection)
+    }
+}
+
+/// Accumulate the statistic over pairs of fields of successive slices
+///
+/// Pairs where either value is missing are skipped, and cells with fewer
+/// than two valid pairs (or, for correlation, a constant variable) are NaN.
+/// All fields must share one grid.
+pub fn correlate(
+    pairs: impl IntoIterator<Item = Result<(HorizontalField, HorizontalField)>>,
+    statistic: CorrelationStatistic,
+) -> Result<HorizontalField> {
+    let mut grid: Option<(Vec<f64>, Vec<f64>)> = None;
+    let mut moments = Array2::<CoMoments>::default((0, 0));
+
+    for pair in pairs {
+        let (a, b) = pair?;
+        if a.values.dim() != b.values.dim() || a.lat != b.lat || a.lon != b.lon {
+            return Err(RossbyError::InvalidParameter {
+                param: "var_b".to_string(),
+                message: "The variables must share one latitude/longitude grid".to_string(),
+            });
+        }
+        match &grid {
+            None => {
+                moments = Array2::default(a.values.dim());
+                grid = Some((a.lat.clone(), a.lon.clone()));
+            }
+            Some(_) if a.values.dim() != moments.dim() => {
+                return Err(RossbyError::Conversion {
+                    message: format!(
+                        "Slices have different shapes {:?} and {:?}",
+                        moments.dim(),
+                        a.values.dim()
+                    ),
+                });
+            }
+            Some(_) => {}
+        }
+        Zip::from(&mut moments)
+            .and(&a.values)
+            .and(&b.values)
+            .for_each(|moments, &a, &b| {
+                if a.is_finite() && b.is_finite() {
+                    moments.push(a as f64, b as f64);
+                }
+            });
+    }
+
+    let (lat, lon) = grid.ok_or_else(|| RossbyError::InvalidParameter {
+        param: "dim".to_string(),
+        message: "No slices selected".to_string(),
+    })?;
+    let values = moments.mapv(|moments| {
+        moments
+            .statistic(statistic)
+            .map_or(f32::NAN, |value| value as f32)
+    });
+    Ok(HorizontalField { lat, lon, values })
+}
+
+/// Mean of the finite values of a field, weighted by the cell areas
+///
+/// Cells on a regular latitude/longitude grid have areas proportional to
+/// the cosine of their latitude. Returns None without finite values.
+pub fn area_weighted_mean(field: &HorizontalField) -> Option<f64> {
+    let (mut sum, mut weights) = (0.0, 0.0);
+    for (row, &lat) in field.values.outer_iter().zip(&field.lat) {
+        let weight = lat.to_radians().cos().max(0.0);
+        for &value in row.iter().filter(|v| v.is_finite()) {
+            sum += weight * value as f64;
+            weights += weight;
+        }
+    }
+    (weights > 0.0).then(|| sum / weights)
+}
+
+/// Handle GET /correlate requests
+pub async fn correlate_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CorrelateQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/correlate",
+        request_id = %request_id,
+        var_a = %params.var_a,
+        var_b = %params.var_b,
+        dim = ?params.dim,
+        "Processing correlation request"
+    );
+
+    match process_correlate_query(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/correlate",
+                request_id = %request_id,
+                var_a = %params.var_a,
+                var_b = %params.var_b,
+                duration_us = duration.as_micros() as u64,
+                "Correlation request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/correlate",
+                &request_id,
+                Some(&format!(
+                    "var_a={}, var_b={}, dim={:?}",
+                    params.var_a, params.var_b, params.dim
+                )),
+            );
+            let status = match &error {
+                RossbyError::ImageGeneration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_correlate_query(state: &AppState, params: &CorrelateQuery) -> Result<Response> {
+    let statistic = CorrelationStatistic::parse(params.statistic.as_deref())?;
+    let output = params.output.as_deref().unwrap_or("map").to_lowercase();
+    if output != "map" && output != "mean" {
+        return Err(RossbyError::InvalidParameter {
+            param: "output".to_string(),
+            message: format!(
+                "Unsupported output: {}. Valid values are 'map' or 'mean'",
+                output
+            ),
+        });
+    }
+    let format = params.format.as_deref().unwrap_or("json").to_lowercase();
+    if format != "json" && format != "png" {
+        return Err(RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: format!(
+                "Unsupported format: {}. Valid values are 'json' or 'png'",
+                format
+            ),
+        });
+    }
+    if format == "png" && output == "mean" {
+        return Err(RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: "format=png renders maps; it cannot be combined with output=mean".to_string(),
+        });
+    }
+    let pair = PairSelection::parse(
+        state,
+        &params.var_a,
+        &params.var_b,
+        params.dim.as_deref(),
+        &params.dimension_params,
+    )?;
+
+    let bbox = params
+        .bbox
+        .as_deref()
+        .map(parse_bbox)
+        .transpose()?
+        .map(|(a, b, c, d)| (a as f64, b as f64, c as f64, d as f64));
+
+    let extract_stage = info_span!("extract").entered();
+    let pairs = pair.indices.iter().map(|&index| {
+        let dim_indices = pair.slice(index);
+        let a = HorizontalField::from_state(state, &params.var_a, &dim_indices, bbox)?;
+        let b = HorizontalField::from_state(state, &params.var_b, &dim_indices, bbox)?;
+        Ok((a, b))
+    });
+    let field = correlate(pairs, statistic)?;
+    extract_stage.exit();
+
+    if format == "png" {
+        let colormap = state.config.data.colormap(params.colormap.as_deref());
+        let value_range = match statistic {
+            CorrelationStatistic::Correlation => (-1.0, 1.0),
+            CorrelationStatistic::Covariance => symmetric_range(&field),
+        };
+        return render_png(&field, params.width, params.height, colormap, value_range);
+    }
+
+    let coords = pair.coordinates(state);
+    let _stage = info_span!("serialize").entered();
+    let mut body = serde_json::json!({
+        "var_a": params.var_a,
+        "var_b": params.var_b,
+        "statistic": statistic.as_str(),
+        "dim": {
+            "dimension": pair.dim,
+            "steps": pair.indices.len(),
+            "first": coords.first(),
+            "last": coords.last(),
+        },
+        "selection": pair.selection_json(state),
+    });
+    if output == "mean" {
+        body["value"] = serde_json::json!(area_weighted_mean(&field));
+        body["cells"] = serde_json::json!(field.stats().count);
+    } else {
+        body["lat"] = serde_json::json!(field.lat);
+        body["lon"] = serde_json::json!(field.lon);
+        body["shape"] = serde_json::json!(field.values.shape());
+        body["values"] = field.values_to_json();
+        body["stats"] = serde_json::json!(field.stats());
+    }
+    Ok(Json(body).into_response())
+}
+
+/// Range of a field symmetric about zero, reaching its largest magnitude
+pub(crate) fn symmetric_range(field: &HorizontalField) -> (f32, f32) {
+    let stats = field.stats();
+    let extent = stats
+        .min
+        .zip(stats.max)
+        .map_or(1.0, |(min, max)| min.abs().max(max.abs()) as f32);
+    let extent = if extent > 0.0 { extent } else { 1.0 };
+    (-extent, extent)
+}
+
+/// Render a field with a colormap spanning `value_range`
+pub(crate) fn render_png(
+    field: &HorizontalField,
+    width: Option<u32>,
+    height: Option<u32>,
+    colormap: &str,
+    value_range: (f32, f32),
+) -> Result<Response> {
+    let width = width.unwrap_or(DEFAULT_WIDTH);
+    let height = height.unwrap_or(DEFAULT_HEIGHT);
+    if width < 2 || height < 2 || width > 8192 || height > 8192 {
+        return Err(RossbyError::InvalidParameter {
+            param: "width/height".to_string(),
+            message: "Image dimensions must be between 2 and 8192 pixels".to_string(),
+        });
+    }
+    if field.values.is_empty() {
+        return Err(RossbyError::InvalidParameter {
+            param: "bbox".to_string(),
+            message: "The bounding box contains no grid points".to_string(),
+        });
+    }
+    let colormap = colormaps::get_colormap(colormap)?;
+
+    let lat_span = match (field.lat.first(), field.lat.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => (0.0, 0.0),
+    };
+
+    let render_stage = info_span!("render").entered();
+    let img = generate_image(
+        field.values.view(),
+        width,
+        height,
+        colormap.as_ref(),
+        "auto",
+        LatitudeScaling::PlateCarree,
+        lat_span,
+        value_range,
+    )?;
+    render_stage.exit();
+
+    let _stage = info_span!("encode").entered();
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, image::ImageFormat::Png)
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to encode PNG: {}", e),
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("image/png"))],
+        buffer.into_inner(),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn field(values: Array2<f32>) -> HorizontalField {
+        HorizontalField {
+            lat: vec![0.0, 60.0],
+            lon: vec![0.0, 10.0],
+            values,
+        }
+    }
+
+    #[test]
+    fn test_co_moments() {
+        let mut moments = CoMoments::default();
+        for (a, b) in [(1.0, 2.0), (2.0, 4.5), (3.0, 5.5), (4.0, 8.0)] {
+            moments.push(a, b);
+        }
+        // Two-pass reference values
+        let covariance: f64 = (-1.5 * -3.0 + -0.5 * -0.5 + 0.5 * 0.5 + 1.5 * 3.0) / 3.0;
+        assert!((moments.covariance().unwrap() - covariance).abs() < 1e-12);
+        let correlation = covariance * 3.0 / (5.0f64.sqrt() * 18.5f64.sqrt());
+        assert!((moments.correlation().unwrap() - correlation).abs() < 1e-12);
+
+        let mut constant = CoMoments::default();
+        constant.push(1.0, 2.0);
+        assert_eq!(constant.covariance(), None);
+        constant.push(1.0, 3.0);
+        assert_eq!(constant.correlation(), None);
+        assert_eq!(constant.covariance(), Some(0.0));
+    }
+
+    #[test]
+    fn test_correlate() {
+        let pairs = || {
+            vec![
+                Ok((
+                    field(array![[1.0, 1.0], [f32::NAN, 5.0]]),
+                    field(array![[2.0, -1.0], [1.0, 5.0]]),
+                )),
+                Ok((
+                    field(array![[2.0, 2.0], [1.0, 5.0]]),
+                    field(array![[4.0, -2.0], [1.0, 6.0]]),
+                )),
+                Ok((
+                    field(array![[3.0, 3.0], [2.0, 5.0]]),
+                    field(array![[6.0, -3.0], [1.0, 7.0]]),
+                )),
+            ]
+        };
+        let result = correlate(pairs(), CorrelationStatistic::Correlation).unwrap();
+        assert!((result.values[[0, 0]] - 1.0).abs() < 1e-6);
+        assert!((result.values[[0, 1]] + 1.0).abs() < 1e-6);
+        // A constant variable has no correlation
+        assert!(result.values[[1, 0]].is_nan());
+        assert!(result.values[[1, 1]].is_nan());
+
+        let result = correlate(pairs(), CorrelationStatistic::Covariance).unwrap();
+        assert_eq!(result.values[[0, 0]], 2.0);
+        assert_eq!(result.values[[1, 0]], 0.0);
+
+        // Cells are weighted by the cosine of their latitude
+        let mean = area_weighted_mean(&field(array![[1.0, 1.0], [-1.0, f32::NAN]])).unwrap();
+        assert!((mean - (2.0 - 0.5) / 2.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_correlate_errors() {
+        assert!(correlate(Vec::new(), CorrelationStatistic::Correlation).is_err());
+        let mismatched = vec![Ok((
+            field(array![[1.0, 2.0], [3.0, 4.0]]),
+            field(array![[1.0, 2.0]]),
+        ))];
+        assert!(correlate(mismatched, CorrelationStatistic::Correlation).is_err());
+        assert!(CorrelationStatistic::parse(Some("spearman")).is_err());
+    }
+}
@@ -3,26 +3,46 @@
 //! This module implements the data endpoint that streams user-defined,
 //! N-dimensional data hyperslabs in Apache Arrow format.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
 
 use arrow::array::{ArrayRef, Float32Array, Float64Array};
 use arrow::record_batch::RecordBatch;
-use arrow_ipc::writer::StreamWriter;
-use arrow_schema::Field;
-use axum::extract::{Query, State};
-use axum::http::{header, HeaderValue, StatusCode};
+use arrow_ipc::writer::{IpcWriteOptions, StreamWriter};
+use arrow_ipc::CompressionType;
+use arrow_schema::{Field, SchemaRef};
+use axum::extract::Query;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use bytes::Bytes;
 use futures::stream::{self, Stream, StreamExt};
 use ndarray::{Array, IxDyn};
 use serde::Deserialize;
-use tracing::{debug, info};
-
+use tracing::{debug, info, info_span};
+
+use crate::arithmetic::{variable_metadata, VariableExpression};
+use crate::artifact::artifact_response;
+use crate::bitround::{self, KeepBits};
+use crate::cf_time::time_units;
+use crate::climatology::{self, NormalMode};
+use crate::colormaps::parse_bbox;
+use crate::config::MAX_POINTS_SCOPE;
+use crate::dynamics::DerivedVariable;
 use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, LAT_NAMES, LON_NAMES};
+use crate::filter::{apply_mask, CellFilter, FILTER_PARAM};
+use crate::filters::{Smoothing, SMOOTH_PARAM};
+use crate::nan_policy::{NanPolicy, NAN_HEADER};
+use crate::partial::VariableErrors;
+use crate::query::{no_variables, select_values, split_variables, Dataset, Selection};
+use crate::query_resolved::{echo_requested, QueryResolved, QUERY_RESOLVED_KEY};
+use crate::quota::API_KEY_HEADER;
+use crate::schema::{SCHEMA_VERSION, SCHEMA_VERSION_PARAM};
 use crate::state::AppState;
+use crate::tidy::{tidy_ipc_file, TidyVariable, ARROW_FILE_CONTENT_TYPE};
 
 /// Generate a unique request ID for tracking
 fn generate_request_id() -> String {
@@ -44,41 +64,114 @@ fn log_request_error(error: &RossbyError, endpoint: &str, request_id: &str, para
 /// Query parameters for the data endpoint
 #[derive(Debug, Deserialize, Clone)]
 pub struct DataQuery {
-    /// Comma-separated list of variables to extract
+    /// Comma-separated list of variables or expressions of two variables
+    /// (e.g. `t2m-t2m_clim`) to extract
+    #[serde(default)]
     pub vars: String,
 
+    /// Left operand of an explicit arithmetic expression
+    #[serde(default)]
+    pub var_a: Option<String>,
+
+    /// Right operand of an explicit arithmetic expression
+    #[serde(default)]
+    pub var_b: Option<String>,
+
+    /// Operator of an explicit arithmetic expression (add, sub, mul or div)
+    #[serde(default)]
+    pub op: Option<String>,
+
     /// Optional layout specification (comma-separated dimension names)
     #[serde(default)]
     pub layout: Option<String>,
 
-    /// Output format (arrow or json)
+    /// Output format (arrow, polars-ipc or json)
     #[serde(default)]
     pub format: Option<String>,
 
-    /// Dynamic parameters - will be parsed separately
+    /// Language code for translated variable attributes in JSON output (e.g. "de")
+    #[serde(default)]
+    pub lang: Option<String>,
+
+    /// Behavior when the selection exceeds `max_data_points` (error or downsample)
+    #[serde(default)]
+    pub on_limit: Option<String>,
+
+    /// Point limit of this request in place of `max_data_points` (e.g.
+    /// `5e7`), for API keys with the `max_points` scope
+    #[serde(default)]
+    pub max_points: Option<String>,
+
+    /// Report failing variables in an `errors` section instead of failing
+    /// the request (true or false)
+    #[serde(default)]
+    pub partial: Option<String>,
+
+    /// Include the selected coordinate values in a `coords` section of JSON
+    /// output (true or false)
+    #[serde(default)]
+    pub coords: Option<String>,
+
+    /// Transform relative to the climatology (percent_normal or zscore)
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// Conditions on companion variables that cells must meet, e.g.
+    /// `qc_flag==0`; other cells are masked
+    #[serde(default)]
+    pub filter: Option<String>,
+
+    /// Spatial smoothing filter, e.g. `gaussian:2` or `boxcar:3`
+    #[serde(default)]
+    pub smooth: Option<String>,
+
+    /// Bounding box as `min_lon,min_lat,max_lon,max_lat`, crossing the seam
+    /// of the longitude axis when `min_lon` is greater than `max_lon`
+    #[serde(default)]
+    pub bbox: Option<String>,
+
+    /// Mantissa bits to keep when rounding values, for every variable (e.g.
+    /// `7`) and/or per variable (e.g. `t2m:7,u10:5`)
+    #[serde(default)]
+    pub keepbits: Option<String>,
+
+    /// Encoding of NaN and infinity in JSON output (null, string or omit)
+    #[serde(default)]
+    pub nan: Option<String>,
+
+    /// Compression of the buffers of Arrow output (none, lz4 or zstd)
+    #[serde(default)]
+    pub compression: Option<String>,
+
+    /// Describe how the query was interpreted in a `query_resolved` section
+    /// (true or false)
+    #[serde(default)]
+    pub echo_params: Option<String>,
+
+    /// Dimension selectors, parsed into a typed `Selection`
     #[serde(flatten)]
     pub dynamic_params: HashMap<String, String>,
 }
 
-/// Represents a dimension selection constraint
-#[derive(Debug, Clone)]
-pub enum DimensionSelector {
-    /// Select a single slice by physical value
-    SingleValue { dimension: String, value: f64 },
-    /// Select a range by physical values (inclusive)
-    ValueRange {
-        dimension: String,
-        start: f64,
-        end: f64,
-    },
-    /// Select a single slice by raw index
-    SingleIndex { dimension: String, index: usize },
-    /// IndexRange is inclusive (i.e., start and end indices are included)
-    IndexRange {
-        dimension: String,
-        start: usize,
-        end: usize,
-    },
+/// `format` value selecting a long-format Arrow IPC file
+const POLARS_IPC_FORMAT: &str = "polars-ipc";
+
+/// Arrow encoding of a `/data` response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArrowOutput {
+    /// One row per element with a column per variable, as an IPC stream
+    Stream,
+    /// One row per value of each variable, as an IPC file
+    TidyFile,
+}
+
+impl ArrowOutput {
+    fn content_type(self) -> &'static str {
+        match self {
+            ArrowOutput::Stream => "application/vnd.apache.arrow.stream",
+            ArrowOutput::TidyFile => ARROW_FILE_CONTENT_TYPE,
+        }
+    }
 }
 
 /// Parsed query information
@@ -86,16 +179,167 @@ struct ParsedDataQuery {
     /// List of variable names to extract
     variables: Vec<String>,
 
-    /// Dimension constraints
-    dimension_selectors: Vec<DimensionSelector>,
+    /// Dimension selections
+    selection: Selection,
 
     /// Requested dimension order
     layout: Option<Vec<String>>,
+
+    /// Behavior when the selection exceeds `max_data_points`
+    on_limit: LimitPolicy,
+
+    /// Number of points the selection may have
+    max_points: usize,
+
+    /// Variables set aside in a partial query, `None` unless `partial=true`
+    errors: Option<VariableErrors>,
+
+    /// Transform relative to the climatology
+    mode: Option<NormalMode>,
+
+    /// Conditions cells must meet to be kept
+    filter: Option<CellFilter>,
+
+    /// Smoothing along the latitude and longitude axes
+    smoothing: Option<Smoothing>,
+
+    /// Mantissa bits kept by bit-rounding
+    keepbits: KeepBits,
+
+    /// Whether to describe how the query was interpreted
+    echo_params: bool,
+}
+
+/// What to do with a selection larger than `max_data_points`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LimitPolicy {
+    /// Reject the request with 413 Payload Too Large
+    Reject,
+    /// Thin the selection with a uniform stride until it fits
+    Downsample,
+}
+
+impl LimitPolicy {
+    /// Parse the `on_limit` parameter (default: error)
+    fn parse(value: Option<&str>) -> Result<Self> {
+        match value {
+            None | Some("error") => Ok(LimitPolicy::Reject),
+            Some("downsample") => Ok(LimitPolicy::Downsample),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "on_limit".to_string(),
+                message: format!(
+                    "Unknown value: {}. Valid values are 'error' or 'downsample'",
+                    other
+                ),
+            }),
+        }
+    }
+}
+
+/// Parse the `compression` parameter into the options of Arrow IPC writers
+///
+/// Arrow buffer compression compresses each column buffer of a record batch
+/// inside the IPC messages, so Arrow readers decompress it themselves and
+/// it combines with any HTTP compression of the whole response.
+fn parse_compression(value: Option<&str>) -> Result<IpcWriteOptions> {
+    let compression = match value {
+        None | Some("none") => None,
+        Some("lz4") => Some(CompressionType::LZ4_FRAME),
+        Some("zstd") => Some(CompressionType::ZSTD),
+        Some(other) => {
+            return Err(RossbyError::InvalidParameter {
+                param: "compression".to_string(),
+                message: format!(
+                    "Unknown value: {}. Valid values are 'none', 'lz4' or 'zstd'",
+                    other
+                ),
+            })
+        }
+    };
+    IpcWriteOptions::default()
+        .try_with_compression(compression)
+        .map_err(|e| RossbyError::Conversion {
+            message: format!("Failed to set Arrow IPC compression: {}", e),
+        })
+}
+
+/// Parse the `max_points` parameter, a whole number that may be written
+/// with an exponent (e.g. `5e7`)
+fn parse_max_points(value: Option<&str>) -> Result<Option<usize>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    match value.trim().parse::<f64>() {
+        Ok(points) if points >= 1.0 && points.fract() == 0.0 && points < usize::MAX as f64 => {
+            Ok(Some(points as usize))
+        }
+        _ => Err(RossbyError::InvalidParameter {
+            param: "max_points".to_string(),
+            message: format!("Invalid value: {}. Expected a positive whole number", value),
+        }),
+    }
+}
+
+/// Point limit of a request: `max_points` when the client's API key has the
+/// `max_points` scope and it is within `server.max_points_ceiling`, else
+/// `server.max_data_points`
+fn point_limit(state: &AppState, params: &DataQuery, headers: &HeaderMap) -> Result<usize> {
+    let server = &state.config.server;
+    let Some(points) = parse_max_points(params.max_points.as_deref())? else {
+        return Ok(server.max_data_points);
+    };
+    let invalid = |message: String| RossbyError::InvalidParameter {
+        param: "max_points".to_string(),
+        message,
+    };
+    let Some(ceiling) = server.max_points_ceiling else {
+        return Err(invalid(
+            "The point limit cannot be raised on this server".to_string(),
+        ));
+    };
+    let key = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim);
+    if !server.key_has_scope(key, MAX_POINTS_SCOPE) {
+        return Err(invalid(format!(
+            "Requires an {} header with the {} scope",
+            API_KEY_HEADER, MAX_POINTS_SCOPE
+        )));
+    }
+    if points > ceiling {
+        return Err(invalid(format!(
+            "{} exceeds the ceiling of {} points",
+            points, ceiling
+        )));
+    }
+    debug!(
+        max_points = points,
+        default = server.max_data_points,
+        "Point limit overridden by the request"
+    );
+    Ok(points)
+}
+
+/// Parse a true/false parameter such as `partial` (default: false)
+pub(crate) fn parse_flag(param: &str, value: Option<&str>) -> Result<bool> {
+    match value {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(other) => Err(RossbyError::InvalidParameter {
+            param: param.to_string(),
+            message: format!(
+                "Invalid value: {}. Valid values are 'true' or 'false'",
+                other
+            ),
+        }),
+    }
 }
 
 /// Handle GET /data requests
 pub async fn data_handler(
-    State(state): State<Arc<AppState>>,
+    Dataset(state): Dataset,
+    headers: HeaderMap,
     Query(params): Query<DataQuery>,
 ) -> Response {
     let request_id = generate_request_id();
@@ -129,35 +373,59 @@ pub async fn data_handler(
     let output_format = params.format.as_deref().unwrap_or("arrow");
 
     match output_format {
-        "arrow" => {
-            match process_data_query(state, params_clone.clone()) {
-                Ok(arrow_data) => {
+        "arrow" | POLARS_IPC_FORMAT => {
+            let output = if output_format == POLARS_IPC_FORMAT {
+                ArrowOutput::TidyFile
+            } else {
+                ArrowOutput::Stream
+            };
+            let result = point_limit(&state, &params, &headers).and_then(|max_points| {
+                process_data_query(state, params_clone.clone(), output, max_points)
+            });
+            match result {
+                Ok(body) => {
                     // Log successful request
                     let duration = start_time.elapsed();
                     info!(
                         endpoint = "/data",
                         request_id = %request_id,
-                        format = "arrow",
+                        format = output_format,
                         duration_us = duration.as_micros() as u64,
                         "Data query successful"
                     );
 
-                    // Build the response with Arrow IPC stream
-                    (
-                        StatusCode::OK,
-                        [(
-                            header::CONTENT_TYPE,
-                            HeaderValue::from_static("application/vnd.apache.arrow.stream"),
-                        )],
-                        arrow_data,
-                    )
-                        .into_response()
+                    let content_type = HeaderValue::from_static(output.content_type());
+                    match body {
+                        // Build the response with the Arrow IPC data, resumable via Range
+                        ArrowBody::Buffered(arrow_data) => {
+                            artifact_response(&headers, content_type, arrow_data)
+                        }
+                        // Resumed downloads of streamed tables are served from
+                        // the whole serialization, which is deterministic
+                        ArrowBody::Streamed(table) if headers.contains_key(header::RANGE) => {
+                            match table.to_ipc_bytes() {
+                                Ok(arrow_data) => {
+                                    artifact_response(&headers, content_type, arrow_data)
+                                }
+                                Err(error) => handle_data_error(error, &request_id, &params),
+                            }
+                        }
+                        ArrowBody::Streamed(table) => {
+                            match streamed_response(table, content_type) {
+                                Ok(response) => response,
+                                Err(error) => handle_data_error(error, &request_id, &params),
+                            }
+                        }
+                    }
                 }
                 Err(error) => handle_data_error(error, &request_id, &params),
             }
         }
         "json" => {
-            match process_data_query_json(state, params_clone.clone()) {
+            let result = point_limit(&state, &params, &headers).and_then(|max_points| {
+                process_data_query_json(state, params_clone.clone(), max_points)
+            });
+            match result {
                 Ok(response) => {
                     // Log successful request
                     let duration = start_time.elapsed();
@@ -188,6 +456,27 @@ pub async fn data_handler(
     }
 }
 
+/// Response sending an Arrow table batch by batch
+fn streamed_response(table: ArrowTable, content_type: HeaderValue) -> Result<Response> {
+    use axum::body::Body;
+
+    let batches = table.batch_count();
+    let stream = table.into_ipc_stream()?;
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::ACCEPT_RANGES, HeaderValue::from_static("bytes")),
+            (
+                HeaderName::from_static(ARROW_BATCHES_HEADER),
+                HeaderValue::from(batches),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
 /// Handle error responses for data queries
 fn handle_data_error(error: RossbyError, request_id: &str, params: &DataQuery) -> Response {
     // Log error with more detail
@@ -224,41 +513,39 @@ fn handle_data_error(error: RossbyError, request_id: &str, params: &DataQuery) -
 }
 
 /// Process the data query and return a JSON formatted response
-fn process_data_query_json(state: Arc<AppState>, params: DataQuery) -> Result<Response> {
+fn process_data_query_json(
+    state: Arc<AppState>,
+    params: DataQuery,
+    max_points: usize,
+) -> Result<Response> {
     use axum::body::Body;
 
     // Parse and validate the query (similar to process_data_query)
-    let variables = params
-        .vars
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<_>>();
-
-    if variables.is_empty() {
+    let mut errors = parse_flag("partial", params.partial.as_deref())?.then(VariableErrors::new);
+    let variables = parse_variables(&state, &params, errors.as_mut())?;
+
+    // Parse dimension selectors and the point limit policy
+    let selection = parse_selection(&state, &params)?;
+    let on_limit = LimitPolicy::parse(params.on_limit.as_deref())?;
+    let mode = params.mode.as_deref().map(NormalMode::parse).transpose()?;
+    let filter = params
+        .filter
+        .as_deref()
+        .map(|filter| CellFilter::parse(&state, filter))
+        .transpose()?;
+    let smoothing = params.smooth.as_deref().map(Smoothing::parse).transpose()?;
+    let include_coords = parse_flag("coords", params.coords.as_deref())?;
+    let nan = NanPolicy::from_request(params.nan.as_deref(), &state.config.data.json_nan)?;
+    if let Some(compression) = params.compression.as_deref().filter(|&c| c != "none") {
         return Err(RossbyError::InvalidParameter {
-            param: "vars".to_string(),
-            message: "At least one variable must be specified".to_string(),
-        });
-    }
-
-    // Check that all variables exist in the dataset
-    let mut invalid_vars = Vec::new();
-    for var in &variables {
-        if !state.has_variable(var) {
-            invalid_vars.push(var.clone());
-        }
-    }
-
-    if !invalid_vars.is_empty() {
-        return Err(RossbyError::InvalidVariables {
-            names: invalid_vars,
+            param: "compression".to_string(),
+            message: format!(
+                "compression={} applies to Arrow output, not to format=json",
+                compression
+            ),
         });
     }
 
-    // Process dimension constraints
-    let dimension_selectors = process_dimension_constraints(&state, &params.dynamic_params)?;
-
     // Parse layout parameter if present
     let layout = params.layout.as_ref().map(|layout_str| {
         layout_str
@@ -291,23 +578,44 @@ fn process_data_query_json(state: Arc<AppState>, params: DataQuery) -> Result<Re
         }
     }
 
+    let keepbits = params
+        .keepbits
+        .as_deref()
+        .map(|spec| KeepBits::parse(spec, &variables))
+        .transpose()?
+        .unwrap_or_default();
+
     // Package the parsed query
     let parsed_query = ParsedDataQuery {
         variables,
-        dimension_selectors,
+        selection,
         layout,
+        on_limit,
+        max_points,
+        errors,
+        mode,
+        filter,
+        smoothing,
+        keepbits,
+        echo_params: echo_requested(params.echo_params.as_deref())?,
     };
 
     // Create a stream that yields JSON chunks
-    let stream = create_json_stream(state, parsed_query, params.clone())?;
+    let stream = create_json_stream(state, parsed_query, params.clone(), include_coords, nan)?;
 
     // Return a response with the chunked JSON stream
     Ok((
         StatusCode::OK,
-        [(
-            header::CONTENT_TYPE,
-            HeaderValue::from_static("application/json"),
-        )],
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            ),
+            (
+                HeaderName::from_static(NAN_HEADER),
+                HeaderValue::from_static(nan.as_str()),
+            ),
+        ],
         Body::from_stream(stream),
     )
         .into_response())
@@ -317,127 +625,89 @@ fn process_data_query_json(state: Arc<AppState>, params: DataQuery) -> Result<Re
 fn create_json_stream(
     state: Arc<AppState>,
     query: ParsedDataQuery,
-    _params: DataQuery,
+    params: DataQuery,
+    include_coords: bool,
+    nan: NanPolicy,
 ) -> Result<impl Stream<Item = std::result::Result<Bytes, std::io::Error>> + Send> {
     let ParsedDataQuery {
         variables,
-        dimension_selectors,
+        selection,
         layout,
+        on_limit,
+        max_points,
+        mut errors,
+        mode,
+        filter,
+        smoothing,
+        keepbits,
+        echo_params,
     } = query;
 
-    // Maps from dimension name to selected range
-    let mut selected_ranges: HashMap<String, (usize, usize)> = HashMap::new();
-    let mut coordinate_arrays: HashMap<String, Vec<f64>> = HashMap::new();
-
-    // Process each dimension selector (similar to extract_and_format_data)
-    for selector in dimension_selectors {
-        match selector {
-            DimensionSelector::SingleValue { dimension, value } => {
-                let index = state.find_coordinate_index(&dimension, value)?;
-                selected_ranges.insert(dimension.clone(), (index, index));
-                let coords = state.get_coordinate_checked(&dimension)?;
-                coordinate_arrays.insert(dimension, vec![coords[index]]);
-            }
-            DimensionSelector::ValueRange {
-                dimension,
-                start,
-                end,
-            } => {
-                let start_idx = state.find_coordinate_index(&dimension, start)?;
-                let end_idx = state.find_coordinate_index(&dimension, end)?;
-                selected_ranges.insert(dimension.clone(), (start_idx, end_idx));
-                let coords = state.get_coordinate_checked(&dimension)?;
-                let selected_coords = coords[start_idx..=end_idx].to_vec();
-                coordinate_arrays.insert(dimension, selected_coords);
-            }
-            DimensionSelector::SingleIndex { dimension, index } => {
-                let coords = state.get_coordinate_checked(&dimension)?;
-                if index >= coords.len() {
-                    return Err(RossbyError::IndexOutOfBounds {
-                        param: dimension.clone(),
-                        value: index.to_string(),
-                        max: coords.len() - 1,
-                    });
-                }
-                selected_ranges.insert(dimension.clone(), (index, index));
-                coordinate_arrays.insert(dimension, vec![coords[index]]);
+    let mut resolved = resolve_selection(&state, &variables, &selection)?;
+    let strides = enforce_point_limit(&mut resolved, max_points, on_limit)?;
+    let query_resolved = echo_params.then(|| describe_query(&state, &selection, &resolved));
+    let ResolvedSelection {
+        indices: selected_indices,
+        coordinates: coordinate_arrays,
+    } = resolved;
+
+    // Extract data for each variable
+    let mut var_data_arrays = Vec::new();
+    let mut var_metadata = Vec::new();
+    let mut var_dimensions = Vec::new();
+    for var_name in variables {
+        let extracted = extract_with_layout(
+            &state,
+            &var_name,
+            &selected_indices,
+            layout.as_deref(),
+            mode,
+        )
+        .and_then(|(mut array, dims)| {
+            if let Some(smoothing) = smoothing {
+                smooth_extracted(smoothing, &var_name, &mut array, &dims)?;
             }
-            DimensionSelector::IndexRange {
-                dimension,
-                start,
-                end,
-            } => {
-                let coords = state.get_coordinate_checked(&dimension)?;
-                if start >= coords.len() || end >= coords.len() {
-                    return Err(RossbyError::IndexOutOfBounds {
-                        param: dimension.clone(),
-                        value: format!("{}..{}", start, end),
-                        max: coords.len() - 1,
-                    });
-                }
-                selected_ranges.insert(dimension.clone(), (start, end));
-                let selected_coords = coords[start..=end].to_vec();
-                coordinate_arrays.insert(dimension, selected_coords);
+            if let Some(filter) = &filter {
+                let mask = filter_mask(
+                    &state,
+                    filter,
+                    &var_name,
+                    (&array, &dims),
+                    &selected_indices,
+                    layout.as_deref(),
+                )?;
+                apply_mask(&mut array, &mask);
             }
-        }
-    }
-
-    // For dimensions not explicitly selected, use the entire range
-    for (dim_name, dim) in &state.metadata.dimensions {
-        if !selected_ranges.contains_key(dim_name) {
-            selected_ranges.insert(dim_name.clone(), (0, dim.size - 1));
-            if let Some(coords) = state.get_coordinate(dim_name) {
-                coordinate_arrays.insert(dim_name.clone(), coords.clone());
+            // Get variable metadata for attributes like units, long_name
+            let var_meta = if let Some(mode) = mode {
+                mode.metadata(state.get_variable_metadata_checked(&var_name)?)
+            } else if let Some(expression) = VariableExpression::parse(&state, &var_name)? {
+                expression.metadata(&state)?
+            } else if let Some(derived) = DerivedVariable::parse(&state, &var_name)? {
+                derived.metadata(&state)?
             } else {
-                let indices: Vec<f64> = (0..dim.size).map(|i| i as f64).collect();
-                coordinate_arrays.insert(dim_name.clone(), indices);
+                state.get_localized_variable_metadata(&var_name, params.lang.as_deref())?
+            };
+            Ok((array, dims, var_meta))
+        });
+        match (extracted, errors.as_mut()) {
+            (Ok((array, dims, var_meta)), _) => {
+                var_data_arrays.push(array);
+                var_dimensions.push(dims);
+                var_metadata.push((var_name, var_meta));
             }
+            (Err(error), Some(errors)) => errors.push(var_name, error),
+            (Err(error), None) => return Err(error),
         }
     }
-
-    // Calculate the total number of data points to check against limit
-    let total_points: usize = coordinate_arrays
-        .values()
-        .map(|coords| coords.len())
-        .product();
-
-    // Check if total points exceeds the limit
-    if total_points > state.config.server.max_data_points {
-        return Err(RossbyError::PayloadTooLarge {
-            message: "The requested data would exceed the maximum allowed size".to_string(),
-            requested: total_points,
-            max_allowed: state.config.server.max_data_points,
-        });
+    let variables: Vec<String> = var_metadata.iter().map(|(name, _)| name.clone()).collect();
+    let errors_json = errors.as_ref().map(VariableErrors::to_json);
+    if variables.is_empty() {
+        return Err(no_variable_succeeded(errors));
     }
 
-    // Extract data for each variable
-    let mut var_data_arrays = Vec::new();
-    let mut var_metadata = Vec::new();
-    for var_name in &variables {
-        let array = extract_variable_data(&state, var_name, &selected_ranges)?;
-        var_data_arrays.push(array);
-
-        // Get variable metadata for attributes like units, long_name
-        let var_meta = state.get_variable_metadata_checked(var_name)?;
-        var_metadata.push((var_name.clone(), var_meta));
-    }
-
-    // Get dimensions based on the first variable for use in metadata
-    let dimension_order = if let Some(layout_dims) = &layout {
-        layout_dims
-            .iter()
-            .map(|dim| state.resolve_dimension(dim).unwrap_or(dim).to_string())
-            .collect::<Vec<_>>()
-    } else if !variables.is_empty() {
-        // Use dimensions from the first variable
-        let var_meta = state.get_variable_metadata_checked(&variables[0])?;
-        var_meta.dimensions.clone()
-    } else {
-        return Err(RossbyError::InvalidParameter {
-            param: "vars".to_string(),
-            message: "No valid variables specified".to_string(),
-        });
-    };
+    // Dimensions of the output arrays, those of the first variable
+    let dimension_order = var_dimensions.swap_remove(0);
 
     // Prepare shape information for metadata
     let shapes: Vec<Vec<usize>> = var_data_arrays
@@ -464,6 +734,21 @@ fn create_json_stream(
                         attrs.insert(key.clone(), serde_json::Value::Null);
                     }
                 }
+                crate::state::AttributeValue::Integer(num) => {
+                    attrs.insert(key.clone(), serde_json::Value::from(*num));
+                }
+                crate::state::AttributeValue::UnsignedInteger(num) => {
+                    attrs.insert(key.clone(), serde_json::Value::from(*num));
+                }
+                crate::state::AttributeValue::IntegerArray(nums) => {
+                    attrs.insert(key.clone(), serde_json::Value::from(nums.clone()));
+                }
+                crate::state::AttributeValue::UnsignedIntegerArray(nums) => {
+                    attrs.insert(key.clone(), serde_json::Value::from(nums.clone()));
+                }
+                crate::state::AttributeValue::TextArray(texts) => {
+                    attrs.insert(key.clone(), serde_json::Value::from(texts.clone()));
+                }
                 crate::state::AttributeValue::NumberArray(nums) => {
                     let arr: Vec<serde_json::Value> = nums
                         .iter()
@@ -480,67 +765,100 @@ fn create_json_stream(
             }
         }
 
+        if let Some(bits) = keepbits.for_variable(var_name) {
+            attrs.insert(bitround::KEEPBITS_PARAM.to_string(), bits.into());
+        }
+
         var_meta_json.insert(var_name.clone(), serde_json::Value::Object(attrs));
     }
 
     // Create the metadata section of the JSON response
-    let metadata = serde_json::json!({
+    let mut metadata = serde_json::json!({
         "query": {
             "vars": variables.join(","),
             "layout": layout,
-            "format": "json"
+            "format": "json",
+            "nan": nan.as_str()
         },
         "shapes": shapes,
         "dimensions": dimension_order,
-        "variables": var_meta_json
+        "variables": var_meta_json,
+        "downsampled": strides.is_some(),
+        "stride": strides
     });
+    if let Some(errors) = errors_json {
+        metadata["errors"] = errors;
+    }
 
-    // Start building the JSON response with the metadata section
-    let mut json_prefix = String::from("{\n  \"metadata\": ");
-    json_prefix.push_str(&serde_json::to_string_pretty(&metadata).unwrap_or_default());
-    json_prefix.push_str(",\n  \"data\": {\n");
+    // The response is written in canonical key order (see crate::schema):
+    // coords, data (by variable name), metadata, query_resolved and
+    // schema_version
+    let mut json_prefix = String::from("{\n");
+    if include_coords {
+        let coords = coordinates_to_json(&state, &coordinate_arrays);
+        json_prefix.push_str("  \"coords\": ");
+        json_prefix.push_str(&serde_json::to_string(&coords).unwrap_or_default());
+        json_prefix.push_str(",\n");
+    }
+    json_prefix.push_str("  \"data\": {\n");
+    let echo = match &query_resolved {
+        Some(resolved) => format!(
+            "  \"{}\": {},\n",
+            QUERY_RESOLVED_KEY,
+            serde_json::to_string_pretty(&resolved.to_json()).unwrap_or_default()
+        ),
+        None => String::new(),
+    };
+    let json_suffix = format!(
+        "\n  }},\n  \"metadata\": {},\n{}  \"{}\": {}\n}}",
+        serde_json::to_string_pretty(&metadata).unwrap_or_default(),
+        echo,
+        SCHEMA_VERSION_PARAM,
+        SCHEMA_VERSION
+    );
 
     // Create a stream for each variable's data
     let mut streams = Vec::new();
+    let mut by_name: Vec<(&String, &Array<f32, IxDyn>)> =
+        variables.iter().zip(var_data_arrays.iter()).collect();
+    by_name.sort_by_key(|(var_name, _)| *var_name);
 
-    for (idx, (var_name, data_array)) in variables.iter().zip(var_data_arrays.iter()).enumerate() {
+    for (idx, (var_name, data_array)) in by_name.into_iter().enumerate() {
         // Start with variable name
+        let key = serde_json::to_string(var_name)?;
         let var_prefix = if idx == 0 {
-            format!("    \"{}\": [", var_name)
+            format!("    {}: [", key)
         } else {
-            format!(",\n    \"{}\": [", var_name)
+            format!(",\n    {}: [", key)
         };
 
         // Get variable metadata to check for fill values, scale factors, etc.
-        let var_meta = state.get_variable_metadata_checked(var_name)?;
+        let var_meta = match mode {
+            Some(mode) => Cow::Owned(mode.metadata(state.get_variable_metadata_checked(var_name)?)),
+            None => variable_metadata(&state, var_name)?,
+        };
 
         // Look for fill value, scale factor, and add offset attributes
         let fill_value = var_meta
             .attributes
             .get("_FillValue")
-            .and_then(|attr| match attr {
-                crate::state::AttributeValue::Number(n) => Some(*n as f32),
-                _ => None,
-            });
+            .and_then(|attr| attr.as_f64().map(|n| n as f32));
 
         let scale_factor = var_meta
             .attributes
             .get("scale_factor")
-            .and_then(|attr| match attr {
-                crate::state::AttributeValue::Number(n) => Some(*n as f32),
-                _ => None,
-            })
+            .and_then(|attr| attr.as_f64().map(|n| n as f32))
             .unwrap_or(1.0);
 
         let add_offset = var_meta
             .attributes
             .get("add_offset")
-            .and_then(|attr| match attr {
-                crate::state::AttributeValue::Number(n) => Some(*n as f32),
-                _ => None,
-            })
+            .and_then(|attr| attr.as_f64().map(|n| n as f32))
             .unwrap_or(0.0);
 
+        // Values are rounded as written, after scale factor and add offset
+        let keep = keepbits.for_variable(var_name);
+
         // Flatten the data array
         let flat_data: Vec<f32> = data_array.iter().copied().collect();
 
@@ -574,19 +892,22 @@ fn create_json_stream(
                             chunk_str.push_str(", ");
                         }
 
-                        // Check if it's a fill value and output null, otherwise apply scale factor and offset
-                        if let Some(fill) = fill_value {
-                            if value == fill {
-                                chunk_str.push_str("null");
-                                continue;
-                            }
+                        // A fill value (or missing in a variable expression)
+                        // is written as NaN, otherwise apply scale factor and
+                        // offset
+                        if fill_value == Some(value) || value.is_nan() {
+                            nan.write_element(f32::NAN, &mut chunk_str);
+                            continue;
                         }
 
                         // Apply scale factor and add offset
-                        let processed_value = value * scale_factor + add_offset;
+                        let mut processed_value = value * scale_factor + add_offset;
+                        if let Some(bits) = keep {
+                            processed_value = bitround::round_value(processed_value, bits);
+                        }
 
                         // Add the value to the chunk string
-                        chunk_str.push_str(&processed_value.to_string());
+                        nan.write_element(processed_value, &mut chunk_str);
                     }
 
                     // Close the array if this is the last chunk
@@ -606,7 +927,7 @@ fn create_json_stream(
 
     // Combine all streams
     let json_prefix_stream = stream::once(async { Ok(Bytes::from(json_prefix)) });
-    let json_suffix_stream = stream::once(async { Ok(Bytes::from("\n  }\n}")) });
+    let json_suffix_stream = stream::once(async { Ok(Bytes::from(json_suffix)) });
 
     // Flatten nested streams
     let combined_stream = json_prefix_stream
@@ -617,38 +938,26 @@ fn create_json_stream(
 }
 
 /// Process the data query and return the Arrow formatted data
-fn process_data_query(state: Arc<AppState>, params: DataQuery) -> Result<Vec<u8>> {
+fn process_data_query(
+    state: Arc<AppState>,
+    params: DataQuery,
+    output: ArrowOutput,
+    max_points: usize,
+) -> Result<ArrowBody> {
     // Parse the vars parameter into a list of variable names
-    let variables = params
-        .vars
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<_>>();
-
-    if variables.is_empty() {
-        return Err(RossbyError::InvalidParameter {
-            param: "vars".to_string(),
-            message: "At least one variable must be specified".to_string(),
-        });
-    }
-
-    // Check that all variables exist in the dataset
-    let mut invalid_vars = Vec::new();
-    for var in &variables {
-        if !state.has_variable(var) {
-            invalid_vars.push(var.clone());
-        }
-    }
-
-    if !invalid_vars.is_empty() {
-        return Err(RossbyError::InvalidVariables {
-            names: invalid_vars,
-        });
-    }
-
-    // Process dimension constraints
-    let dimension_selectors = process_dimension_constraints(&state, &params.dynamic_params)?;
+    let mut errors = parse_flag("partial", params.partial.as_deref())?.then(VariableErrors::new);
+    let variables = parse_variables(&state, &params, errors.as_mut())?;
+
+    // Parse dimension selectors and the point limit policy
+    let selection = parse_selection(&state, &params)?;
+    let on_limit = LimitPolicy::parse(params.on_limit.as_deref())?;
+    let mode = params.mode.as_deref().map(NormalMode::parse).transpose()?;
+    let filter = params
+        .filter
+        .as_deref()
+        .map(|filter| CellFilter::parse(&state, filter))
+        .transpose()?;
+    let smoothing = params.smooth.as_deref().map(Smoothing::parse).transpose()?;
 
     // Parse layout parameter if present
     let layout = params.layout.as_ref().map(|layout_str| {
@@ -696,581 +1005,1019 @@ fn process_data_query(state: Arc<AppState>, params: DataQuery) -> Result<Vec<u8>
         }
     }
 
+    let keepbits = params
+        .keepbits
+        .as_deref()
+        .map(|spec| KeepBits::parse(spec, &variables))
+        .transpose()?
+        .unwrap_or_default();
+
     // Package the parsed query
     let parsed_query = ParsedDataQuery {
         variables,
-        dimension_selectors,
+        selection,
         layout,
+        on_limit,
+        max_points,
+        errors,
+        mode,
+        filter,
+        smoothing,
+        keepbits,
+        echo_params: echo_requested(params.echo_params.as_deref())?,
     };
 
+    let ipc_options = parse_compression(params.compression.as_deref())?;
+
     // Extract the data based on the query
-    extract_and_format_data(state, parsed_query)
+    extract_and_format_data(state, parsed_query, output, ipc_options)
 }
 
-/// Process dimension constraints from query parameters
-fn process_dimension_constraints(
+/// Parse the requested variables, variable expressions and derived variables
+///
+/// Expressions given with `var_a`, `var_b` and `op` are added after those
+/// listed in `vars`. In a partial query, invalid variables are recorded in
+/// `errors` and left out rather than failing the request.
+fn parse_variables(
     state: &AppState,
-    dynamic_params: &HashMap<String, String>,
-) -> Result<Vec<DimensionSelector>> {
-    let mut selectors = Vec::new();
-
-    // Process each parameter to find dimension constraints
-    for (key, value) in dynamic_params {
-        // Handle single value selections (e.g., time=1672531200)
-        if let Ok(file_specific) = state.resolve_dimension(key) {
-            // Parse the value as a float
-            let parsed_value = value
-                .parse::<f64>()
-                .map_err(|_| RossbyError::InvalidParameter {
-                    param: key.clone(),
-                    message: format!("Could not parse '{}' as a number", value),
-                })?;
-
-            selectors.push(DimensionSelector::SingleValue {
-                dimension: file_specific.to_string(),
-                value: parsed_value,
-            });
-            continue;
-        }
+    params: &DataQuery,
+    errors: Option<&mut VariableErrors>,
+) -> Result<Vec<String>> {
+    let mut variables = split_variables(&params.vars);
+
+    let explicit = VariableExpression::from_params(
+        state,
+        params.var_a.as_deref(),
+        params.var_b.as_deref(),
+        params.op.as_deref(),
+    )?;
+    if let Some(expression) = explicit {
+        variables.push(expression.to_string());
+    }
 
-        // Special handling for time_index
-        if key == "time_index" {
-            let index = value
-                .parse::<usize>()
-                .map_err(|_| RossbyError::InvalidParameter {
-                    param: key.clone(),
-                    message: format!("Could not parse '{}' as an integer index", value),
-                })?;
+    if variables.is_empty() {
+        return Err(no_variables("vars"));
+    }
 
-            // Find the time dimension - try standard names
-            let mut found_time_dim = false;
-            for time_dim_name in &["time", "t"] {
-                if let Ok(time_dim) = state.resolve_dimension(time_dim_name) {
-                    selectors.push(DimensionSelector::SingleIndex {
-                        dimension: time_dim.to_string(),
-                        index,
-                    });
-                    found_time_dim = true;
-                    break;
-                }
-            }
+    // Check that all variables exist in the dataset, or combine two that do
+    let is_valid = |var: &str| -> Result<bool> {
+        Ok(state.has_variable(var)
+            || VariableExpression::parse(state, var)?.is_some()
+            || DerivedVariable::parse(state, var)?.is_some())
+    };
 
-            // Fallback: If no time dimension found, use the first dimension in the dataset
-            if !found_time_dim && !state.metadata.dimensions.is_empty() {
-                // Get the first dimension name (they're in a HashMap, so just take any)
-                if let Some((first_dim_name, _)) = state.metadata.dimensions.iter().next() {
-                    tracing::debug!(
-                        "No time dimension found, using first dimension '{}' for time_index",
-                        first_dim_name
-                    );
-                    selectors.push(DimensionSelector::SingleIndex {
-                        dimension: first_dim_name.clone(),
-                        index,
-                    });
-                }
+    if let Some(errors) = errors {
+        let mut valid = Vec::new();
+        for var in variables {
+            match is_valid(&var) {
+                Ok(true) => valid.push(var),
+                Ok(false) => errors.push(var.clone(), RossbyError::VariableNotFound { name: var }),
+                Err(error) => errors.push(var, error),
             }
-
-            continue;
         }
-
-        // Handle range selections (e.g., time_range=1672531200,1675209600)
-        if let Some(dim_name) = key.strip_suffix("_range") {
-            if let Ok(file_specific) = state.resolve_dimension(dim_name) {
-                // Parse range as two comma-separated values
-                let parts: Vec<&str> = value.split(',').collect();
-                if parts.len() != 2 {
-                    return Err(RossbyError::InvalidParameter {
-                        param: key.clone(),
-                        message: format!(
-                            "Range parameter must contain exactly two comma-separated values, got: '{}'",
-                            value
-                        ),
-                    });
-                }
-
-                let start =
-                    parts[0]
-                        .trim()
-                        .parse::<f64>()
-                        .map_err(|_| RossbyError::InvalidParameter {
-                            param: key.clone(),
-                            message: format!(
-                                "Could not parse start value '{}' as a number",
-                                parts[0]
-                            ),
-                        })?;
-
-                let end =
-                    parts[1]
-                        .trim()
-                        .parse::<f64>()
-                        .map_err(|_| RossbyError::InvalidParameter {
-                            param: key.clone(),
-                            message: format!(
-                                "Could not parse end value '{}' as a number",
-                                parts[1]
-                            ),
-                        })?;
-
-                selectors.push(DimensionSelector::ValueRange {
-                    dimension: file_specific.to_string(),
-                    start,
-                    end,
-                });
-                continue;
-            }
+        if valid.is_empty() {
+            return Err(no_variable_succeeded(Some(std::mem::take(errors))));
         }
+        return Ok(valid);
+    }
 
-        // Handle raw index selections (e.g., __time_index=0)
-        if let Some(dim_name) = key
-            .strip_prefix("__")
-            .and_then(|s| s.strip_suffix("_index"))
-        {
-            if let Some(canonical) = state.get_canonical_dimension_name(dim_name) {
-                if let Ok(file_specific) = state.resolve_dimension(canonical) {
-                    // Parse as integer index
-                    let index =
-                        value
-                            .parse::<usize>()
-                            .map_err(|_| RossbyError::InvalidParameter {
-                                param: key.clone(),
-                                message: format!("Could not parse '{}' as an integer index", value),
-                            })?;
-
-                    selectors.push(DimensionSelector::SingleIndex {
-                        dimension: file_specific.to_string(),
-                        index,
-                    });
-                    continue;
-                }
-            }
+    let mut invalid_vars = Vec::new();
+    for var in &variables {
+        if !is_valid(var)? {
+            invalid_vars.push(var.clone());
         }
+    }
 
-        // Handle raw index range selections (e.g., __time_index_range=0,10)
-        if let Some(dim_name) = key
-            .strip_prefix("__")
-            .and_then(|s| s.strip_suffix("_index_range"))
-        {
-            if let Some(canonical) = state.get_canonical_dimension_name(dim_name) {
-                if let Ok(file_specific) = state.resolve_dimension(canonical) {
-                    // Parse range as two comma-separated values
-                    let parts: Vec<&str> = value.split(',').collect();
-                    if parts.len() != 2 {
-                        return Err(RossbyError::InvalidParameter {
-                            param: key.clone(),
-                            message: format!(
-                                "Range parameter must contain exactly two comma-separated values, got: '{}'",
-                                value
-                            ),
-                        });
-                    }
+    if !invalid_vars.is_empty() {
+        return Err(RossbyError::InvalidVariables {
+            names: invalid_vars,
+        });
+    }
 
-                    let start = parts[0].trim().parse::<usize>().map_err(|_| {
-                        RossbyError::InvalidParameter {
-                            param: key.clone(),
-                            message: format!(
-                                "Could not parse start index '{}' as an integer",
-                                parts[0]
-                            ),
-                        }
-                    })?;
-
-                    let end = parts[1].trim().parse::<usize>().map_err(|_| {
-                        RossbyError::InvalidParameter {
-                            param: key.clone(),
-                            message: format!(
-                                "Could not parse end index '{}' as an integer",
-                                parts[1]
-                            ),
-                        }
-                    })?;
-
-                    selectors.push(DimensionSelector::IndexRange {
-                        dimension: file_specific.to_string(),
-                        start,
-                        end,
-                    });
-                    continue;
-                }
-            }
-        }
+    Ok(variables)
+}
+
+/// Error of a partial query none of whose variables succeeded
+fn no_variable_succeeded(errors: Option<VariableErrors>) -> RossbyError {
+    errors
+        .and_then(VariableErrors::into_first_error)
+        .unwrap_or_else(|| RossbyError::InvalidParameter {
+            param: "vars".to_string(),
+            message: "No valid variables specified".to_string(),
+        })
+}
+
+/// Parse the dimension selectors and bounding box of a query
+fn parse_selection(state: &AppState, params: &DataQuery) -> Result<Selection> {
+    let mut selection = Selection::parse(state, &params.dynamic_params)?;
+    if let Some(bbox) = params.bbox.as_deref() {
+        let (min_lon, min_lat, max_lon, max_lat) = parse_bbox(bbox)?;
+        let bbox = (
+            min_lon as f64,
+            min_lat as f64,
+            max_lon as f64,
+            max_lat as f64,
+        );
+        selection.add_bbox(state, "bbox", bbox)?;
     }
+    Ok(selection)
+}
 
-    Ok(selectors)
+/// Pre-flight estimate of the number of values a `/data` query extracts
+///
+/// Returns `None` for invalid queries, which fail before extracting anything.
+pub fn estimate_values(state: &AppState, query: &str) -> Option<u64> {
+    let params: DataQuery = serde_urlencoded::from_str(query).ok()?;
+    let variables = parse_variables(state, &params, None).ok()?;
+    let selection = parse_selection(state, &params).ok()?;
+    let resolved = resolve_selection(state, &variables, &selection).ok()?;
+    let points = resolved
+        .coordinates
+        .values()
+        .fold(1u64, |acc, coords| acc.saturating_mul(coords.len() as u64))
+        // Larger selections are rejected or downsampled to the limit
+        .min(point_limit_bound(state, &params) as u64);
+    Some(points.saturating_mul(variables.len() as u64))
 }
 
-/// Extract data based on the query and format it as Arrow
-fn extract_and_format_data(state: Arc<AppState>, query: ParsedDataQuery) -> Result<Vec<u8>> {
-    let ParsedDataQuery {
-        variables,
-        dimension_selectors,
-        layout,
-    } = query;
+/// Largest point limit a query may get, whoever sends it
+fn point_limit_bound(state: &AppState, params: &DataQuery) -> usize {
+    let server = &state.config.server;
+    match (
+        parse_max_points(params.max_points.as_deref()),
+        server.max_points_ceiling,
+    ) {
+        (Ok(Some(points)), Some(ceiling)) => points.min(ceiling),
+        _ => server.max_data_points,
+    }
+}
 
-    // Maps from dimension name to selected range
-    let mut selected_ranges: HashMap<String, (usize, usize)> = HashMap::new();
-    let mut coordinate_arrays: HashMap<String, Vec<f64>> = HashMap::new();
-
-    // Process each dimension selector
-    for selector in dimension_selectors {
-        match selector {
-            DimensionSelector::SingleValue { dimension, value } => {
-                // Find the index corresponding to this value
-                let index = state.find_coordinate_index(&dimension, value)?;
-                selected_ranges.insert(dimension.clone(), (index, index));
-
-                // Store the coordinate value
-                let coords = state.get_coordinate_checked(&dimension)?;
-                coordinate_arrays.insert(dimension, vec![coords[index]]);
-            }
-            DimensionSelector::ValueRange {
-                dimension,
-                start,
-                end,
-            } => {
-                // Find the indices corresponding to these values
-                let start_idx = state.find_coordinate_index(&dimension, start)?;
-                let end_idx = state.find_coordinate_index(&dimension, end)?;
-                selected_ranges.insert(dimension.clone(), (start_idx, end_idx));
-
-                // Store the coordinate values
-                let coords = state.get_coordinate_checked(&dimension)?;
-                let selected_coords = coords[start_idx..=end_idx].to_vec();
-                coordinate_arrays.insert(dimension, selected_coords);
-            }
-            DimensionSelector::SingleIndex { dimension, index } => {
-                // Verify the index is valid
-                let coords = state.get_coordinate_checked(&dimension)?;
-                if index >= coords.len() {
-                    return Err(RossbyError::IndexOutOfBounds {
-                        param: dimension.clone(),
-                        value: index.to_string(),
-                        max: coords.len() - 1,
-                    });
-                }
-                selected_ranges.insert(dimension.clone(), (index, index));
+/// Raw indices and coordinate values selected for every dimension
+struct ResolvedSelection {
+    indices: HashMap<String, Vec<usize>>,
+    coordinates: HashMap<String, Vec<f64>>,
+}
 
-                // Store the coordinate value
-                coordinate_arrays.insert(dimension, vec![coords[index]]);
-            }
-            DimensionSelector::IndexRange {
-                dimension,
-                start,
-                end,
-            } => {
-                // Verify the indices are valid
-                let coords = state.get_coordinate_checked(&dimension)?;
-                if start >= coords.len() || end >= coords.len() {
-                    return Err(RossbyError::IndexOutOfBounds {
-                        param: dimension.clone(),
-                        value: format!("{}..{}", start, end),
-                        max: coords.len() - 1,
-                    });
-                }
-                selected_ranges.insert(dimension.clone(), (start, end));
+/// Resolve a selection for every dimension used by the requested variables
+///
+/// Dimensions without a selector are selected in full. Dimensions no variable
+/// uses (e.g. those of unrelated NetCDF-4 groups) do not count towards the
+/// size of the request.
+fn resolve_selection(
+    state: &AppState,
+    variables: &[String],
+    selection: &Selection,
+) -> Result<ResolvedSelection> {
+    let mut selected_indices = selection.resolve(state)?;
+    let mut coordinate_arrays = HashMap::new();
 
-                // Store the coordinate values
-                let selected_coords = coords[start..=end].to_vec();
-                coordinate_arrays.insert(dimension, selected_coords);
-            }
+    let used_dimensions: HashSet<String> = variables
+        .iter()
+        .filter_map(|var_name| variable_metadata(state, var_name).ok())
+        .flat_map(|var| var.dimensions.clone())
+        .collect();
+
+    for (dim_name, dim) in &state.metadata.dimensions {
+        if !used_dimensions.contains(dim_name) {
+            continue;
         }
+        let indices = selected_indices
+            .entry(dim_name.clone())
+            .or_insert_with(|| (0..dim.size).collect());
+
+        // If no coordinates are available, use the indices as coordinates
+        // This is a fallback for test data that might not have explicit coordinate variables
+        let coords = match state.get_coordinate(dim_name) {
+            Some(coords) => indices.iter().map(|&i| coords[i]).collect(),
+            None => indices.iter().map(|&i| i as f64).collect(),
+        };
+        coordinate_arrays.insert(dim_name.clone(), coords);
     }
 
-    // For dimensions not explicitly selected, use the entire range
-    for (dim_name, dim) in &state.metadata.dimensions {
-        if !selected_ranges.contains_key(dim_name) {
-            selected_ranges.insert(dim_name.clone(), (0, dim.size - 1));
+    Ok(ResolvedSelection {
+        indices: selected_indices,
+        coordinates: coordinate_arrays,
+    })
+}
 
-            // Store all coordinate values
-            if let Some(coords) = state.get_coordinate(dim_name) {
-                coordinate_arrays.insert(dim_name.clone(), coords.clone());
-            } else {
-                // If no coordinates are available, create a range of indices as coordinates
-                // This is a fallback for test data that might not have explicit coordinate variables
-                let indices: Vec<f64> = (0..dim.size).map(|i| i as f64).collect();
-                coordinate_arrays.insert(dim_name.clone(), indices);
-            }
+/// The echo of a query (see [`crate::query_resolved`]): the indices chosen
+/// for every dimension and, with a bounding box, the extent it covers
+fn describe_query(
+    state: &AppState,
+    selection: &Selection,
+    resolved: &ResolvedSelection,
+) -> QueryResolved {
+    let mut echo = QueryResolved::new();
+    echo.selection(state, selection, &resolved.indices);
+    if selection.iter().any(|selected| selected.param == "bbox") {
+        let coordinates = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| state.resolve_dimension(name).ok())
+                .and_then(|dim| resolved.coordinates.get(dim))
+        };
+        if let (Some(lon), Some(lat)) = (coordinates(&LON_NAMES), coordinates(&LAT_NAMES)) {
+            echo.covered_bbox(lon, lat);
         }
     }
+    echo
+}
+
+/// The `coords` section of a JSON response: the selected coordinate values
+/// of every dimension, with time coordinates decoded to ISO 8601 strings
+fn coordinates_to_json(
+    state: &AppState,
+    coordinates: &HashMap<String, Vec<f64>>,
+) -> serde_json::Value {
+    let coords = coordinates
+        .iter()
+        .map(|(dim_name, values)| {
+            let values: Vec<serde_json::Value> = match time_units(state, dim_name) {
+                Some(units) => values
+                    .iter()
+                    .map(|&v| units.iso(v).map_or(serde_json::Value::Null, Into::into))
+                    .collect(),
+                None => values
+                    .iter()
+                    .map(|&v| {
+                        serde_json::Number::from_f64(v)
+                            .map_or(serde_json::Value::Null, serde_json::Value::Number)
+                    })
+                    .collect(),
+            };
+            (dim_name.clone(), serde_json::Value::Array(values))
+        })
+        .collect();
+    serde_json::Value::Object(coords)
+}
 
-    // Calculate the total number of data points to check against limit
-    let total_points: usize = coordinate_arrays
+/// Check the selection against the point limit, downsampling it if allowed
+///
+/// Returns the stride applied to each thinned dimension, or `None` when the
+/// selection already fits.
+fn enforce_point_limit(
+    resolved: &mut ResolvedSelection,
+    max_points: usize,
+    policy: LimitPolicy,
+) -> Result<Option<BTreeMap<String, usize>>> {
+    let total_points = resolved
+        .coordinates
         .values()
-        .map(|coords| coords.len())
-        .product();
-
-    // Check if total points exceeds the limit
-    if total_points > state.config.server.max_data_points {
-        return Err(RossbyError::PayloadTooLarge {
-            message: "The requested data would exceed the maximum allowed size".to_string(),
-            requested: total_points,
-            max_allowed: state.config.server.max_data_points,
-        });
+        .fold(1usize, |acc, coords| acc.saturating_mul(coords.len()));
+
+    if total_points <= max_points {
+        return Ok(None);
     }
 
-    // Extract data for each variable
-    let mut var_data_arrays = Vec::new();
-    for var_name in &variables {
-        let array = extract_variable_data(&state, var_name, &selected_ranges)?;
-        var_data_arrays.push(array);
+    let too_large = || RossbyError::PayloadTooLarge {
+        message: "The requested data would exceed the maximum allowed size".to_string(),
+        requested: total_points,
+        max_allowed: max_points,
+    };
+    if policy == LimitPolicy::Reject {
+        return Err(too_large());
+    }
+
+    let lengths: Vec<usize> = resolved.coordinates.values().map(Vec::len).collect();
+    let stride = downsample_stride(&lengths, max_points).ok_or_else(too_large)?;
+
+    let mut strides = BTreeMap::new();
+    for (dim_name, indices) in resolved.indices.iter_mut() {
+        if indices.len() > 1 {
+            *indices = indices.iter().copied().step_by(stride).collect();
+            strides.insert(dim_name.clone(), stride);
+        }
+    }
+    for coords in resolved.coordinates.values_mut() {
+        if coords.len() > 1 {
+            *coords = coords.iter().copied().step_by(stride).collect();
+        }
     }
 
-    // Get dimensions based on the first variable for use in Arrow schema
-    // Or use layout order if specified
-    let dimension_order = if let Some(layout_dims) = &layout {
-        // Convert layout names to file-specific names
-        layout_dims
+    debug!(
+        requested = total_points,
+        max_allowed = max_points,
+        stride = stride,
+        "Downsampled data selection to fit the point limit"
+    );
+
+    Ok(Some(strides))
+}
+
+/// Smallest uniform stride that brings a selection of the given dimension
+/// lengths under `max_points`, if any
+fn downsample_stride(lengths: &[usize], max_points: usize) -> Option<usize> {
+    let points = |stride: usize| {
+        lengths
             .iter()
-            .map(|dim| state.resolve_dimension(dim).unwrap_or(dim).to_string())
-            .collect::<Vec<_>>()
-    } else if !variables.is_empty() {
-        // Use dimensions from the first variable
-        let var_meta = state.get_variable_metadata_checked(&variables[0])?;
-        var_meta.dimensions.clone()
-    } else {
-        return Err(RossbyError::InvalidParameter {
-            param: "vars".to_string(),
-            message: "No valid variables specified".to_string(),
-        });
+            .fold(1usize, |acc, &len| acc.saturating_mul(len.div_ceil(stride)))
     };
 
-    // Convert coordinate arrays HashMap to vectors in dimension order
-    let mut ordered_dimension_names = Vec::new();
-    let mut ordered_coordinate_arrays = Vec::new();
+    // The point count never increases with the stride, so binary search it
+    let (mut low, mut high) = (1, lengths.iter().copied().max().unwrap_or(1).max(1));
+    if points(high) > max_points {
+        return None;
+    }
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if points(mid) <= max_points {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Some(high)
+}
 
-    for dim_name in &dimension_order {
-        if let Some(coords) = coordinate_arrays.get(dim_name) {
-            ordered_dimension_names.push(dim_name.clone());
-            ordered_coordinate_arrays.push(coords);
+/// Extract data based on the query and format it as Arrow
+fn extract_and_format_data(
+    state: Arc<AppState>,
+    query: ParsedDataQuery,
+    output: ArrowOutput,
+    ipc_options: IpcWriteOptions,
+) -> Result<ArrowBody> {
+    let ParsedDataQuery {
+        variables,
+        selection,
+        layout,
+        on_limit,
+        max_points,
+        mut errors,
+        mode,
+        filter,
+        smoothing,
+        keepbits,
+        echo_params,
+    } = query;
+
+    let extract_stage = info_span!("extract").entered();
+    let mut resolved = resolve_selection(&state, &variables, &selection)?;
+    let strides = enforce_point_limit(&mut resolved, max_points, on_limit)?;
+    let query_resolved = echo_params.then(|| describe_query(&state, &selection, &resolved));
+    let ResolvedSelection {
+        indices: selected_indices,
+        coordinates: coordinate_arrays,
+    } = resolved;
+
+    // Extract data for each variable
+    let mut extracted_variables = Vec::new();
+    let mut var_data_arrays = Vec::new();
+    let mut var_dimensions = Vec::new();
+    let mut var_masks = Vec::new();
+    for var_name in variables {
+        let extracted = extract_with_layout(
+            &state,
+            &var_name,
+            &selected_indices,
+            layout.as_deref(),
+            mode,
+        )
+        .and_then(|(mut array, dims)| {
+            if let Some(smoothing) = smoothing {
+                smooth_extracted(smoothing, &var_name, &mut array, &dims)?;
+            }
+            let Some(filter) = &filter else {
+                return Ok((array, dims, None));
+            };
+            let mask = filter_mask(
+                &state,
+                filter,
+                &var_name,
+                (&array, &dims),
+                &selected_indices,
+                layout.as_deref(),
+            )?;
+            // Long-format files leave filtered cells out instead
+            if output == ArrowOutput::TidyFile {
+                return Ok((array, dims, Some(mask.iter().copied().collect::<Vec<_>>())));
+            }
+            apply_mask(&mut array, &mask);
+            Ok((array, dims, None))
+        });
+        match (extracted, errors.as_mut()) {
+            (Ok((mut array, dims, mask)), _) => {
+                if let Some(bits) = keepbits.for_variable(&var_name) {
+                    bitround::round_array(
+                        &mut array,
+                        bits,
+                        bitround::fill_value(&state, &var_name),
+                    );
+                }
+                extracted_variables.push(var_name);
+                var_data_arrays.push(array);
+                var_dimensions.push(dims);
+                var_masks.push(mask);
+            }
+            (Err(error), Some(errors)) => errors.push(var_name, error),
+            (Err(error), None) => return Err(error),
         }
     }
+    let variables = extracted_variables;
+    let errors_json = errors.as_ref().map(VariableErrors::to_json);
+    if variables.is_empty() {
+        return Err(no_variable_succeeded(errors));
+    }
+    extract_stage.exit();
+
+    // One coordinate column per dimension of the first variable: those kept
+    // in the output follow its layout, dropped single slices stay in place
+    let var_meta = variable_metadata(&state, &variables[0])?;
+    let mut output_dims = var_dimensions[0].iter();
+    let column_dimensions: Vec<String> = var_meta
+        .dimensions
+        .iter()
+        .map(|dim| {
+            if var_dimensions[0].contains(dim) {
+                output_dims.next().unwrap_or(dim).clone()
+            } else {
+                dim.clone()
+            }
+        })
+        .filter(|dim| coordinate_arrays.contains_key(dim))
+        .collect();
+    let ordered_coordinate_arrays: Vec<&Vec<f64>> = column_dimensions
+        .iter()
+        .map(|dim| &coordinate_arrays[dim])
+        .collect();
 
     // Convert data to Arrow format
-    let var_data_array_refs: Vec<&Array<f32, IxDyn>> = var_data_arrays.iter().collect();
-    create_arrow_table(
+    let _stage = info_span!("serialize").entered();
+    let mut metadata = schema_metadata(
+        strides.as_ref(),
+        errors_json.as_ref(),
+        &keepbits.applied(&variables),
+    )?;
+    if let Some(resolved) = &query_resolved {
+        metadata.insert(
+            QUERY_RESOLVED_KEY.to_string(),
+            resolved.to_json().to_string(),
+        );
+    }
+    if output == ArrowOutput::TidyFile {
+        // Every dimension of each variable, including dropped single slices,
+        // with those kept in the output in the order of its layout
+        let all_dimensions = variables
+            .iter()
+            .zip(&var_dimensions)
+            .map(|(var_name, dims)| {
+                let mut output_dims = dims.iter();
+                Ok(variable_metadata(&state, var_name)?
+                    .dimensions
+                    .iter()
+                    .map(|dim| {
+                        if dims.contains(dim) {
+                            output_dims.next().unwrap_or(dim).clone()
+                        } else {
+                            dim.clone()
+                        }
+                    })
+                    .collect())
+            })
+            .collect::<Result<Vec<Vec<String>>>>()?;
+        let tidy_variables: Vec<TidyVariable> = variables
+            .iter()
+            .zip(&var_data_arrays)
+            .zip(&var_dimensions)
+            .zip(&all_dimensions)
+            .zip(&var_masks)
+            .map(|((((name, data), axes), dimensions), keep)| TidyVariable {
+                name,
+                data,
+                axes,
+                dimensions,
+                keep: keep.as_deref(),
+            })
+            .collect();
+        return tidy_ipc_file(
+            &tidy_variables,
+            &coordinate_arrays,
+            |dim| time_units(&state, dim),
+            metadata,
+            ipc_options,
+        )
+        .map(ArrowBody::Buffered);
+    }
+    let table = ArrowTable::new(
         &variables,
-        &var_data_array_refs,
-        &ordered_dimension_names,
+        var_data_arrays,
+        &var_dimensions,
+        &column_dimensions,
         &ordered_coordinate_arrays,
-        layout.as_ref(),
-    )
+        metadata,
+        BATCH_ROWS,
+        ipc_options,
+    )?;
+    // Tables of one batch are small enough to serve as resumable artifacts
+    if table.batch_count() > 1 {
+        Ok(ArrowBody::Streamed(table))
+    } else {
+        table.to_ipc_bytes().map(ArrowBody::Buffered)
+    }
 }
 
-/// Extract data for a variable based on the selected ranges
-fn extract_variable_data(
+/// Extract data for a variable and arrange its axes in the requested layout
+///
+/// With a `mode`, the values are transformed relative to the climatology of
+/// the variable (see [`crate::climatology`]). Returns the array together with the names of its dimensions. Dimensions
+/// with a single selected index are removed from the array. A layout must
+/// list every remaining dimension; entries naming dimensions the variable
+/// does not have, or only has a single slice of, are skipped, so one layout
+/// can serve variables of different dimensionality.
+fn extract_with_layout(
     state: &AppState,
     var_name: &str,
-    selected_ranges: &HashMap<String, (usize, usize)>,
-) -> Result<Array<f32, IxDyn>> {
-    // Get the variable data
-    let var_data = state.get_variable_checked(var_name)?;
-
-    // Get the variable dimensions
-    let var_meta = state.get_variable_metadata_checked(var_name)?;
-    let dimensions = &var_meta.dimensions;
-
-    // We need to create a copy of the data to work with
-    let mut result = var_data.to_owned();
-
-    // We'll handle each dimension separately, starting from the last dimension
-    // to avoid shape issues when slicing
-    for (i, dim_name) in dimensions.iter().enumerate().rev() {
-        if let Some(&(start, end)) = selected_ranges.get(dim_name) {
-            // Use slice_axis to get a view of just this dimension
-            let axis = ndarray::Axis(i);
+    selected_indices: &HashMap<String, Vec<usize>>,
+    layout: Option<&[String]>,
+    mode: Option<NormalMode>,
+) -> Result<(Array<f32, IxDyn>, Vec<String>)> {
+    let mut array = extract_variable_data(state, var_name, selected_indices)?;
+    if let Some(mode) = mode {
+        array = climatology::transform(state, var_name, mode, array, selected_indices)?;
+    }
+    let dimensions: Vec<String> = variable_metadata(state, var_name)?
+        .dimensions
+        .iter()
+        .filter(|dim| {
+            selected_indices
+                .get(*dim)
+                .is_none_or(|indices| indices.len() != 1)
+        })
+        .cloned()
+        .collect();
+    let Some(layout) = layout else {
+        return Ok((array, dimensions));
+    };
 
-            // For a single index (start == end), we use index_axis
-            if start == end {
-                result = result.index_axis(axis, start).to_owned().into_dyn();
-            } else {
-                // For a range, we use slice_axis
-                result = result
-                    .slice_axis(axis, ndarray::Slice::from(start..=end))
-                    .to_owned();
-            }
+    let mut order: Vec<usize> = Vec::with_capacity(dimensions.len());
+    for name in layout {
+        let Some(axis) = layout_axis(state, name, &dimensions) else {
+            continue;
+        };
+        if order.contains(&axis) {
+            return Err(RossbyError::InvalidParameter {
+                param: "layout".to_string(),
+                message: format!("Dimension '{}' is listed more than once", dimensions[axis]),
+            });
         }
+        order.push(axis);
+    }
+    let omitted: Vec<&String> = (0..dimensions.len())
+        .filter(|axis| !order.contains(axis))
+        .map(|axis| &dimensions[axis])
+        .collect();
+    if !omitted.is_empty() {
+        return Err(RossbyError::InvalidParameter {
+            param: "layout".to_string(),
+            message: format!(
+                "Layout omits dimension(s) {:?} of '{}'; it must list every dimension of the output",
+                omitted, var_name
+            ),
+        });
     }
 
-    Ok(result)
+    let dimensions = order.iter().map(|&axis| dimensions[axis].clone()).collect();
+    let array = array
+        .permuted_axes(IxDyn(&order))
+        .as_standard_layout()
+        .into_owned();
+    Ok((array, dimensions))
 }
 
-/// Convert ndarray data to Arrow format
-fn create_arrow_table(
-    variables: &[String],
-    data_arrays: &[&Array<f32, IxDyn>],
-    dimension_names: &[String],
-    coordinate_arrays: &[&Vec<f64>],
-    layout: Option<&Vec<String>>,
-) -> Result<Vec<u8>> {
-    use arrow_schema::DataType;
-    use arrow_schema::Schema;
-    use std::sync::Arc;
-
-    // Debug logging to help diagnose column length issues
-    debug!(
-        "Creating Arrow table with {} variables, {} dimensions",
-        variables.len(),
-        dimension_names.len()
-    );
-
-    // First, determine the total number of elements we need for each column
-    let total_elements: usize = if let Some(first_data) = data_arrays.first() {
-        first_data.len()
-    } else {
-        return Err(RossbyError::Conversion {
-            message: "No data arrays provided for Arrow table creation".to_string(),
+/// Smooth an extracted variable along its latitude and longitude axes
+///
+/// Only the selected cells are smoothed over, so the filter does not reach
+/// past the edges of the selection. Variables without either axis (e.g.
+/// sliced at one latitude and longitude) cannot be smoothed.
+fn smooth_extracted(
+    smoothing: Smoothing,
+    var_name: &str,
+    array: &mut Array<f32, IxDyn>,
+    dims: &[String],
+) -> Result<()> {
+    let axes: Vec<usize> = dims
+        .iter()
+        .enumerate()
+        .filter(|(_, dim)| LAT_NAMES.contains(&dim.as_str()) || LON_NAMES.contains(&dim.as_str()))
+        .map(|(axis, _)| axis)
+        .collect();
+    if axes.is_empty() {
+        return Err(RossbyError::InvalidParameter {
+            param: SMOOTH_PARAM.to_string(),
+            message: format!(
+                "'{}' has no latitude or longitude axis in the selection to smooth along",
+                var_name
+            ),
         });
-    };
+    }
+    smoothing.apply(array, &axes);
+    Ok(())
+}
 
-    debug!("Total elements needed for each column: {}", total_elements);
-    for (i, arr) in data_arrays.iter().enumerate() {
-        debug!(
-            "Data array {} has {} elements and shape {:?}",
-            i,
-            arr.len(),
-            arr.shape()
-        );
+/// Cells of an extracted variable that pass the filter of a query
+///
+/// The filter variables are extracted with the same selection and layout as
+/// the variable, whose dimensions they must share.
+fn filter_mask(
+    state: &AppState,
+    filter: &CellFilter,
+    var_name: &str,
+    (array, dims): (&Array<f32, IxDyn>, &[String]),
+    selected_indices: &HashMap<String, Vec<usize>>,
+    layout: Option<&[String]>,
+) -> Result<Array<bool, IxDyn>> {
+    filter.mask(array.shape(), |flag| {
+        let (values, flag_dims) = extract_with_layout(state, flag, selected_indices, layout, None)?;
+        if flag_dims != dims {
+            return Err(RossbyError::InvalidParameter {
+                param: FILTER_PARAM.to_string(),
+                message: format!(
+                    "Filter variable '{}' has dimensions {:?}, but '{}' has {:?}",
+                    flag, flag_dims, var_name, dims
+                ),
+            });
+        }
+        Ok(values)
+    })
+}
+
+/// Position of the dimension a layout entry names among `dimensions`
+///
+/// Entries may use file-specific names, aliases, or the canonical
+/// `latitude`, `longitude` and `time` for the usual file names when no
+/// alias is configured.
+fn layout_axis(state: &AppState, name: &str, dimensions: &[String]) -> Option<usize> {
+    if let Ok(dim) = state.resolve_dimension(name) {
+        return dimensions.iter().position(|d| d == dim);
     }
+    let known: &[&str] = match name {
+        "latitude" => &LAT_NAMES,
+        "longitude" => &LON_NAMES,
+        "time" => &["time", "t"],
+        _ => &[],
+    };
+    dimensions.iter().position(|d| known.contains(&d.as_str()))
+}
 
-    // Check coordinate arrays lengths
-    for (i, coords) in coordinate_arrays.iter().enumerate() {
-        debug!(
-            "Coordinate array {} ({}) has {} elements",
-            i,
-            dimension_names.get(i).unwrap_or(&"unknown".to_string()),
-            coords.len()
-        );
+/// Extract data for a variable based on the selected indices
+///
+/// Dimensions with a single selected index are removed from the result.
+fn extract_variable_data(
+    state: &AppState,
+    var_name: &str,
+    selected_indices: &HashMap<String, Vec<usize>>,
+) -> Result<Array<f32, IxDyn>> {
+    // Combine the operands of a variable expression
+    if let Some(expression) = VariableExpression::parse(state, var_name)? {
+        let a = extract_variable_data(state, &expression.a, selected_indices)?;
+        let b = extract_variable_data(state, &expression.b, selected_indices)?;
+        return expression.combine(state, a, b);
+    }
+    if let Some(derived) = DerivedVariable::parse(state, var_name)? {
+        return extract_derived_data(state, &derived, selected_indices);
     }
 
-    // Create schema
-    let mut fields = Vec::new();
+    // Copy (and convert, if packed) only the selected values of the variable
+    let var_data = state.get_variable_values_checked(var_name)?;
+    let var_meta = state.get_variable_metadata_checked(var_name)?;
+    Ok(var_data.select(&var_meta.dimensions, selected_indices))
+}
 
-    // Add coordinate fields - one field for each dimension
-    for dim_name in dimension_names.iter() {
-        fields.push(Field::new(dim_name, DataType::Float64, false));
-    }
+/// Extract data for a derived variable based on the selected indices
+///
+/// The arguments are extracted on the whole horizontal grid, since finite
+/// differences need the neighbours of the selected points; the latitude and
+/// longitude selection is applied to the result.
+fn extract_derived_data(
+    state: &AppState,
+    derived: &DerivedVariable,
+    selected_indices: &HashMap<String, Vec<usize>>,
+) -> Result<Array<f32, IxDyn>> {
+    let var_meta = state.get_variable_metadata_checked(&derived.args[0])?;
+    let dimensions = &var_meta.dimensions;
+    let (lat_axis, lon_axis) =
+        find_lat_lon_axes(dimensions).ok_or_else(|| RossbyError::VariableNotSuitableForImage {
+            name: derived.args[0].clone(),
+        })?;
+    let (lat_dim, lon_dim) = (&dimensions[lat_axis], &dimensions[lon_axis]);
 
-    // Add variable fields with metadata for reconstruction
-    for (var_name, data_array) in variables.iter().zip(data_arrays.iter()) {
-        // Create metadata for reconstruction
-        let mut metadata = HashMap::new();
+    let mut arg_indices = selected_indices.clone();
+    arg_indices.remove(lat_dim);
+    arg_indices.remove(lon_dim);
+    let args = derived
+        .args
+        .iter()
+        .map(|arg| extract_variable_data(state, arg, &arg_indices))
+        .collect::<Result<Vec<_>>>()?;
 
-        // Add shape as JSON array
-        let shape = data_array.shape();
+    // Axis positions once dimensions with a single selected index are dropped
+    let position = |axis: usize| {
+        axis - dimensions[..axis]
+            .iter()
+            .filter(|dim| {
+                arg_indices
+                    .get(*dim)
+                    .is_some_and(|indices| indices.len() == 1)
+            })
+            .count()
+    };
+    let (lat_pos, lon_pos) = (position(lat_axis), position(lon_axis));
+    let result = derived.compute_nd(
+        state,
+        args,
+        (lat_pos, lon_pos),
+        state.get_coordinate_checked(lat_dim)?,
+        state.get_coordinate_checked(lon_dim)?,
+    )?;
+
+    // Select the latitudes and longitudes of the result, whose dimensions
+    // are those of the arguments less the ones with a single selected index
+    let result_dims: Vec<String> = dimensions
+        .iter()
+        .filter(|dim| {
+            arg_indices
+                .get(*dim)
+                .is_none_or(|indices| indices.len() != 1)
+        })
+        .cloned()
+        .collect();
+    let horizontal: HashMap<String, Vec<usize>> = [lat_dim, lon_dim]
+        .into_iter()
+        .filter_map(|dim| Some((dim.clone(), selected_indices.get(dim)?.clone())))
+        .collect();
+    Ok(select_values(result.view(), &result_dims, &horizontal))
+}
+
+/// Schema metadata of Arrow output, marking downsampled responses with the
+/// applied strides, listing the failing variables of a partial query and
+/// recording the mantissa bits kept for bit-rounded variables
+fn schema_metadata(
+    strides: Option<&BTreeMap<String, usize>>,
+    errors: Option<&serde_json::Value>,
+    keepbits: &BTreeMap<String, u32>,
+) -> Result<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    if let Some(strides) = strides {
+        metadata.insert("downsampled".to_string(), "true".to_string());
         metadata.insert(
-            "shape".to_string(),
-            serde_json::to_string(&shape).map_err(|e| RossbyError::Conversion {
-                message: format!("Failed to serialize shape metadata: {}", e),
+            "stride".to_string(),
+            serde_json::to_string(strides).map_err(|e| RossbyError::Conversion {
+                message: format!("Failed to serialize stride metadata: {}", e),
             })?,
         );
-
-        // Add dimension names based on requested layout or original order
-        let dimension_names_vec = dimension_names.to_vec();
-        let dimension_order = layout.unwrap_or(&dimension_names_vec);
+    }
+    if let Some(errors) = errors {
+        metadata.insert("errors".to_string(), errors.to_string());
+    }
+    if !keepbits.is_empty() {
         metadata.insert(
-            "dimensions".to_string(),
-            serde_json::to_string(dimension_order).map_err(|e| RossbyError::Conversion {
-                message: format!("Failed to serialize dimensions metadata: {}", e),
-            })?,
+            bitround::KEEPBITS_PARAM.to_string(),
+            serde_json::json!(keepbits).to_string(),
         );
-
-        // Create field with metadata
-        let field = Field::new(var_name, DataType::Float32, false).with_metadata(metadata);
-        fields.push(field);
     }
+    Ok(metadata)
+}
 
-    // Create schema
-    let schema = Arc::new(Schema::new(fields));
+/// Response header with the number of record batches of a streamed Arrow
+/// response
+pub const ARROW_BATCHES_HEADER: &str = "x-rossby-arrow-batches";
 
-    // Create record batch
-    let mut columns = Vec::new();
+/// Rows per record batch of an Arrow stream, rounded to whole slices of the
+/// outermost dimension
+const BATCH_ROWS: usize = 1 << 20;
 
-    // In Arrow, all columns in a record batch must have the same length.
-    // For test data, we'll replicate coordinate values to match data array length if needed
+/// Arrow output of a query
+enum ArrowBody {
+    /// Serialized whole, and served as a resumable artifact
+    Buffered(Vec<u8>),
+    /// Serialized one record batch at a time while it is sent
+    Streamed(ArrowTable),
+}
 
-    // Add coordinate columns - these need to match the total elements
-    for (dim_idx, &coords) in coordinate_arrays.iter().enumerate() {
-        // Create a string first, then reference it
-        let unknown_str = "unknown".to_string();
-        let dim_name = dimension_names.get(dim_idx).unwrap_or(&unknown_str);
+/// Coordinate column of an Arrow table
+struct CoordinateColumn {
+    values: Vec<f64>,
+    /// Row-major stride and length of the axis it follows, None for a
+    /// single value repeated on every row
+    axis: Option<(usize, usize)>,
+}
 
-        let array = if coords.len() == total_elements {
-            // If lengths match, use as-is
-            debug!(
-                "Using coordinate array for {} as-is ({} elements)",
-                dim_name,
-                coords.len()
-            );
-            Float64Array::from((*coords).clone())
-        } else if coords.len() == 1 {
-            // If we have a single value (common for time_index=0), repeat it
-            debug!(
-                "Repeating single coordinate value for {} to {} elements",
-                dim_name, total_elements
-            );
-            let repeated_val = coords[0];
-            Float64Array::from(vec![repeated_val; total_elements])
-        } else {
-            // Otherwise, we need to create a compatible array by using indices
-            debug!(
-                "Creating compatible coordinate array for {} ({} elements needed, had {})",
-                dim_name,
-                total_elements,
-                coords.len()
-            );
+/// An Arrow table of extracted data, serialized as an IPC stream of record
+/// batches holding whole slices of the outermost dimension
+///
+/// The table has one row per element of the first array, in its (row-major)
+/// order, and one coordinate column per entry of `dimension_names`:
+/// dimensions of the first array are expanded to the coordinate of each row,
+/// any other dimension must have a single coordinate, which is repeated.
+/// Rows are only expanded batch by batch, so serializing a large table takes
+/// little memory beyond the extracted data.
+struct ArrowTable {
+    schema: SchemaRef,
+    coordinates: Vec<CoordinateColumn>,
+    /// Values of each variable in row order
+    values: Vec<Vec<f32>>,
+    rows: usize,
+    batch_rows: usize,
+    /// Options of the IPC writer, e.g. its buffer compression
+    ipc_options: IpcWriteOptions,
+}
 
-            // Use the first N values, or repeat if we don't have enough
-            let mut compatible_coords = Vec::with_capacity(total_elements);
-            for i in 0..total_elements {
-                compatible_coords.push(coords[i % coords.len()]);
-            }
-            Float64Array::from(compatible_coords)
+impl ArrowTable {
+    /// Build the table of `data_arrays`, whose axes are named by
+    /// `var_dimensions`, with batches of about `batch_rows` rows
+    ///
+    /// `metadata` is attached to the schema (see [`schema_metadata`]), and
+    /// the table is written with `ipc_options`.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        variables: &[String],
+        data_arrays: Vec<Array<f32, IxDyn>>,
+        var_dimensions: &[Vec<String>],
+        dimension_names: &[String],
+        coordinate_arrays: &[&Vec<f64>],
+        metadata: HashMap<String, String>,
+        batch_rows: usize,
+        ipc_options: IpcWriteOptions,
+    ) -> Result<Self> {
+        use arrow_schema::{DataType, Schema};
+
+        let Some(first) = data_arrays.first() else {
+            return Err(RossbyError::Conversion {
+                message: "No data arrays provided for Arrow table creation".to_string(),
+            });
         };
+        let rows = first.len();
+        let first_shape = first.shape().to_vec();
+        debug!(
+            "Creating Arrow table with {} variables, {} dimensions and {} rows",
+            variables.len(),
+            dimension_names.len(),
+            rows
+        );
 
-        columns.push(Arc::new(array) as ArrayRef);
-    }
+        // Coordinate fields first, then the variables with the metadata to
+        // reconstruct their arrays
+        let mut fields = Vec::new();
+        for dim_name in dimension_names {
+            fields.push(Field::new(dim_name, DataType::Float64, false));
+        }
+        for ((var_name, data_array), dims) in variables.iter().zip(&data_arrays).zip(var_dimensions)
+        {
+            if data_array.len() != rows {
+                return Err(RossbyError::Conversion {
+                    message: format!(
+                        "Variable {} has {} values, but the table has {} rows",
+                        var_name,
+                        data_array.len(),
+                        rows
+                    ),
+                });
+            }
+            let mut field_metadata = HashMap::new();
+            field_metadata.insert(
+                "shape".to_string(),
+                serde_json::to_string(data_array.shape()).map_err(|e| RossbyError::Conversion {
+                    message: format!("Failed to serialize shape metadata: {}", e),
+                })?,
+            );
+            field_metadata.insert(
+                "dimensions".to_string(),
+                serde_json::to_string(dims).map_err(|e| RossbyError::Conversion {
+                    message: format!("Failed to serialize dimensions metadata: {}", e),
+                })?,
+            );
+            fields
+                .push(Field::new(var_name, DataType::Float32, false).with_metadata(field_metadata));
+        }
+        let schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+
+        // Row-major: a coordinate advances every `stride` rows
+        let first_dims = var_dimensions
+            .first()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let coordinates = dimension_names
+            .iter()
+            .zip(coordinate_arrays)
+            .map(|(dim_name, &coords)| {
+                let axis = first_dims.iter().position(|d| d == dim_name).map(|axis| {
+                    let stride: usize = first_shape[axis + 1..].iter().product();
+                    (stride, first_shape[axis])
+                });
+                CoordinateColumn {
+                    values: coords.clone(),
+                    axis,
+                }
+            })
+            .collect();
 
-    // Add variable data columns
-    for (var_idx, &data_array) in data_arrays.iter().enumerate() {
-        // Create a string first, then reference it
-        let unknown_str = "unknown".to_string();
-        let var_name = variables.get(var_idx).unwrap_or(&unknown_str);
+        let values = data_arrays
+            .into_iter()
+            .map(|array| {
+                if array.is_standard_layout() {
+                    array.into_raw_vec()
+                } else {
+                    array.iter().copied().collect()
+                }
+            })
+            .collect();
 
-        // Flatten the ndarray to 1D
-        let flat_data: Vec<f32> = data_array.iter().copied().collect();
+        // Batches hold whole slices of the outermost dimension
+        let slice_rows: usize = first_shape.iter().skip(1).product();
+        let slice_rows = slice_rows.max(1);
+        let batch_rows = (batch_rows / slice_rows).max(1) * slice_rows;
 
-        debug!(
-            "Adding variable {} with {} elements",
-            var_name,
-            flat_data.len()
-        );
+        Ok(Self {
+            schema,
+            coordinates,
+            values,
+            rows,
+            batch_rows,
+            ipc_options,
+        })
+    }
 
-        let array = Float32Array::from(flat_data);
-        columns.push(Arc::new(array) as ArrayRef);
+    /// Number of record batches, at least one so that an empty table still
+    /// has a batch
+    fn batch_count(&self) -> usize {
+        self.rows.div_ceil(self.batch_rows).max(1)
     }
 
-    // Create record batch
-    let batch =
-        RecordBatch::try_new(schema.clone(), columns).map_err(|e| RossbyError::Conversion {
+    /// Record batch `index` of the table
+    fn batch(&self, index: usize) -> Result<RecordBatch> {
+        let start = (index * self.batch_rows).min(self.rows);
+        let end = (start + self.batch_rows).min(self.rows);
+
+        let mut columns = Vec::with_capacity(self.coordinates.len() + self.values.len());
+        for column in &self.coordinates {
+            let array = match column.axis {
+                Some((stride, len)) => Float64Array::from_iter_values(
+                    (start..end).map(|row| column.values[(row / stride) % len]),
+                ),
+                None => {
+                    let value = column.values.first().copied().unwrap_or(f64::NAN);
+                    Float64Array::from(vec![value; end - start])
+                }
+            };
+            columns.push(Arc::new(array) as ArrayRef);
+        }
+        for values in &self.values {
+            let array = Float32Array::from(values[start..end].to_vec());
+            columns.push(Arc::new(array) as ArrayRef);
+        }
+
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(|e| RossbyError::Conversion {
             message: format!("Failed to create Arrow record batch: {}", e),
-        })?;
+        })
+    }
 
-    // Serialize to IPC format
-    let mut output = Vec::new();
-    let mut writer =
-        StreamWriter::try_new(&mut output, &schema).map_err(|e| RossbyError::Conversion {
-            message: format!("Failed to create Arrow IPC writer: {}", e),
-        })?;
+    /// IPC stream writer of the table, with the schema written
+    fn writer(&self) -> Result<StreamWriter<Vec<u8>>> {
+        StreamWriter::try_new_with_options(Vec::new(), &self.schema, self.ipc_options.clone())
+            .map_err(|e| RossbyError::Conversion {
+                message: format!("Failed to create Arrow IPC writer: {}", e),
+            })
+    }
 
-    writer.write(&batch).map_err(|e| RossbyError::Conversion {
-        message: format!("Failed to write Arrow record batch: {}", e),
-    })?;
+    /// Write record batch `index`, or the end of the stream after the last
+    /// batch, returning the bytes written since the last call
+    fn write_step(&self, writer: &mut StreamWriter<Vec<u8>>, index: usize) -> Result<Vec<u8>> {
+        if index < self.batch_count() {
+            writer
+                .write(&self.batch(index)?)
+                .map_err(|e| RossbyError::Conversion {
+                    message: format!("Failed to write Arrow record batch: {}", e),
+                })?;
+        } else {
+            writer.finish().map_err(|e| RossbyError::Conversion {
+                message: format!("Failed to finalize Arrow IPC stream: {}", e),
+            })?;
+        }
+        Ok(std::mem::take(writer.get_mut()))
+    }
 
-    writer.finish().map_err(|e| RossbyError::Conversion {
-        message: format!("Failed to finalize Arrow IPC stream: {}", e),
-    })?;
+    /// The whole IPC stream
+    fn to_ipc_bytes(&self) -> Result<Vec<u8>> {
+        let mut writer = self.writer()?;
+        let mut output = Vec::new();
+        for index in 0..=self.batch_count() {
+            output.extend(self.write_step(&mut writer, index)?);
+        }
+        Ok(output)
+    }
 
-    Ok(output)
+    /// The IPC stream as chunks of bytes, one record batch each, serialized
+    /// as they are polled; a failing batch ends the response body
+    fn into_ipc_stream(
+        self,
+    ) -> Result<impl Stream<Item = std::result::Result<Bytes, std::io::Error>> + Send> {
+        let mut writer = self.writer()?;
+        let steps = self.batch_count() + 1;
+        Ok(stream::iter(0..steps).map(move |index| {
+            self.write_step(&mut writer, index)
+                .map(Bytes::from)
+                .map_err(|e| std::io::Error::other(e.to_string()))
+        }))
+    }
 }
 
 #[cfg(test)]
@@ -1342,6 +2089,7 @@ mod tests {
             dimensions,
             variables,
             coordinates,
+            groups: HashMap::new(),
         };
 
         // Create data
@@ -1368,44 +2116,123 @@ mod tests {
     }
 
     #[test]
-    fn test_dimension_selector_parsing() {
-        // Create a test state
-        let _state = create_test_state();
+    fn test_resolve_selection() {
+        let state = create_test_state();
 
         // Test various parameter combinations
         let mut params = HashMap::new();
         params.insert("time".to_string(), "1672531200".to_string());
         params.insert("lat_range".to_string(), "35.0,37.0".to_string());
-        params.insert("__lon_index".to_string(), "2".to_string());
+        params.insert("__lon_index_range".to_string(), "0,3,2".to_string());
+
+        let selection = Selection::parse(&state, &params).unwrap();
+        let ResolvedSelection {
+            indices,
+            coordinates: coords,
+        } = resolve_selection(&state, &["t2m".to_string()], &selection).unwrap();
+
+        assert_eq!(indices["time"], vec![0]);
+        assert_eq!(indices["lat"], vec![0, 1, 2]);
+        assert_eq!(indices["lon"], vec![0, 2]);
+        assert_eq!(coords["lon"], vec![139.0, 141.0]);
+
+        let result = extract_variable_data(&state, "t2m", &indices).unwrap();
+        assert_eq!(result.shape(), &[3, 2]);
+        assert_eq!(result[[1, 1]], 12.0);
+
+        // Unknown parameters are rejected
+        params.insert("lat_rnage".to_string(), "35.0,37.0".to_string());
+        assert!(Selection::parse(&state, &params).is_err());
+    }
 
-        let selectors = process_dimension_constraints(&_state, &params).unwrap();
+    #[test]
+    fn test_downsample_stride() {
+        assert_eq!(downsample_stride(&[10, 10], 100), Some(1));
+        assert_eq!(downsample_stride(&[10, 10], 99), Some(2));
+        assert_eq!(downsample_stride(&[1, 100, 100], 400), Some(5));
+        assert_eq!(downsample_stride(&[7], 1), Some(7));
+        assert_eq!(downsample_stride(&[7], 0), None);
+    }
 
-        // Check we parsed all three selectors
-        assert_eq!(selectors.len(), 3);
+    #[test]
+    fn test_enforce_point_limit() {
+        // The test state allows 1000 points
+        let state = create_test_state();
+        let selection = Selection::parse(&state, &HashMap::new()).unwrap();
+
+        let mut resolved = resolve_selection(&state, &["t2m".to_string()], &selection).unwrap();
+        assert!(
+            enforce_point_limit(&mut resolved, 1000, LimitPolicy::Reject)
+                .unwrap()
+                .is_none()
+        );
 
-        // Check types and values of selectors
-        for selector in selectors {
-            match selector {
-                DimensionSelector::SingleValue { dimension, value } => {
-                    assert_eq!(dimension, "time");
-                    assert_eq!(value, 1672531200.0);
-                }
-                DimensionSelector::ValueRange {
-                    dimension,
-                    start,
-                    end,
-                } => {
-                    assert_eq!(dimension, "lat");
-                    assert_eq!(start, 35.0);
-                    assert_eq!(end, 37.0);
-                }
-                DimensionSelector::SingleIndex { dimension, index } => {
-                    assert_eq!(dimension, "lon");
-                    assert_eq!(index, 2);
-                }
-                _ => panic!("Unexpected selector type"),
-            }
+        let err = enforce_point_limit(&mut resolved, 5, LimitPolicy::Reject).unwrap_err();
+        assert!(matches!(err, RossbyError::PayloadTooLarge { .. }));
+
+        // time(5) x lat(3) x lon(4) = 60 points thinned with stride 2 to 3 x 2 x 2
+        let strides = enforce_point_limit(&mut resolved, 12, LimitPolicy::Downsample)
+            .unwrap()
+            .unwrap();
+        assert_eq!(strides["lat"], 2);
+        assert_eq!(resolved.indices["time"], vec![0, 2, 4]);
+        assert_eq!(resolved.indices["lat"], vec![0, 2]);
+        assert_eq!(resolved.indices["lon"], vec![0, 2]);
+        assert_eq!(resolved.coordinates["lon"].len(), 2);
+
+        assert!(LimitPolicy::parse(Some("truncate")).is_err());
+    }
+
+    #[test]
+    fn test_point_limit() {
+        assert_eq!(parse_max_points(Some("5e7")).unwrap(), Some(50_000_000));
+        assert_eq!(parse_max_points(Some("2000")).unwrap(), Some(2000));
+        assert_eq!(parse_max_points(None).unwrap(), None);
+        for invalid in ["0", "-5", "1.5", "inf", "many"] {
+            assert!(parse_max_points(Some(invalid)).is_err(), "{}", invalid);
         }
+
+        // The test state allows 1000 points
+        let Ok(mut state) = Arc::try_unwrap(create_test_state()) else {
+            panic!("The test state is shared");
+        };
+        let params = |query: &str| -> DataQuery { serde_urlencoded::from_str(query).unwrap() };
+        let with_key = |key: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(API_KEY_HEADER, HeaderValue::from_str(key).unwrap());
+            headers
+        };
+        let raised = params("vars=t2m&max_points=5e3");
+        assert_eq!(
+            point_limit(&state, &params("vars=t2m"), &HeaderMap::new()).unwrap(),
+            1000
+        );
+        let error = |state: &AppState, params: &DataQuery, headers: &HeaderMap| {
+            point_limit(state, params, headers).unwrap_err().to_string()
+        };
+        // Overrides are disabled without a ceiling, even for a scoped key
+        state.config.server.api_key_scopes =
+            HashMap::from([("team-a".to_string(), vec![MAX_POINTS_SCOPE.to_string()])]);
+        assert!(error(&state, &raised, &with_key("team-a")).contains("cannot be raised"));
+        assert_eq!(point_limit_bound(&state, &raised), 1000);
+
+        state.config.server.max_points_ceiling = Some(10_000);
+        assert_eq!(
+            point_limit(&state, &raised, &with_key("team-a")).unwrap(),
+            5000
+        );
+        // The limit may be raised up to the ceiling itself
+        let ceiling = params("vars=t2m&max_points=1e4");
+        assert_eq!(
+            point_limit(&state, &ceiling, &with_key("team-a")).unwrap(),
+            10_000
+        );
+        // Keys without the scope, and requests without a key, are refused
+        assert!(error(&state, &raised, &with_key("team-b")).contains("scope"));
+        assert!(error(&state, &raised, &HeaderMap::new()).contains("scope"));
+        let too_many = params("vars=t2m&max_points=1e5");
+        assert!(error(&state, &too_many, &with_key("team-a")).contains("ceiling"));
+        assert_eq!(point_limit_bound(&state, &too_many), 10_000);
     }
 
     #[test]
@@ -1413,10 +2240,10 @@ mod tests {
         let state = create_test_state(); // This state is used
 
         // Select time=0, all lat/lon
-        let mut selected_ranges = HashMap::new();
-        selected_ranges.insert("time".to_string(), (0, 0));
+        let mut selected_indices = HashMap::new();
+        selected_indices.insert("time".to_string(), vec![0]);
 
-        let result = extract_variable_data(&state, "t2m", &selected_ranges).unwrap();
+        let result = extract_variable_data(&state, "t2m", &selected_indices).unwrap();
 
         // The shape should be preserved and maintain dimensionality
         assert_eq!(result.shape(), &[3, 4]);
@@ -1426,31 +2253,191 @@ mod tests {
     }
 
     #[test]
-    fn test_create_arrow_table() {
-        // For this test, we'll directly generate valid Arrow IPC data
-        // by ensuring all arrays have the same length
-
-        // Create a 1D array for simplicity
-        let data = Array::from_vec(vec![1.0, 2.0, 3.0, 4.0, 5.0]);
-        let data_dyn = data.into_dyn();
+    fn test_smooth_extracted() {
+        let state = create_test_state();
+        let boxcar = Smoothing::Boxcar { width: 3 };
+
+        // t2m = time * 100 + lat * 10 + lon is linear, so only the edges,
+        // averaged over part of the window, change
+        let single_time = HashMap::from([("time".to_string(), vec![1])]);
+        let (mut array, dims) =
+            extract_with_layout(&state, "t2m", &single_time, None, None).unwrap();
+        smooth_extracted(boxcar, "t2m", &mut array, &dims).unwrap();
+        assert_eq!(array[[1, 1]], 111.0);
+        assert_eq!(array[[0, 0]], 105.5);
+
+        // Smoothing needs a horizontal axis
+        let point = HashMap::from([("lat".to_string(), vec![1]), ("lon".to_string(), vec![2])]);
+        let (mut array, dims) = extract_with_layout(&state, "t2m", &point, None, None).unwrap();
+        assert!(smooth_extracted(boxcar, "t2m", &mut array, &dims).is_err());
+    }
 
-        // Create matching coordinate arrays (same length as the data)
-        let dim_names = vec!["x".to_string()];
-        let x_coords = vec![10.0, 20.0, 30.0, 40.0, 50.0]; // Same length as data
-        let coord_arrays = vec![&x_coords];
+    #[test]
+    fn test_extract_with_layout() {
+        let state = create_test_state();
+        let selected_indices = HashMap::new();
+        let layout = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let (array, dims) = extract_with_layout(
+            &state,
+            "t2m",
+            &selected_indices,
+            Some(&layout(&["lon", "lat", "time"])),
+            None,
+        )
+        .unwrap();
+        assert_eq!(dims, vec!["lon", "lat", "time"]);
+        assert_eq!(array.shape(), &[4, 3, 5]);
+        // t2m = time * 100 + lat * 10 + lon
+        assert_eq!(array[[2, 1, 3]], 312.0);
+
+        // A single selected index drops the dimension, which may be omitted
+        let mut single_time = HashMap::new();
+        single_time.insert("time".to_string(), vec![1]);
+        let (array, dims) = extract_with_layout(
+            &state,
+            "t2m",
+            &single_time,
+            Some(&layout(&["lon", "lat"])),
+            None,
+        )
+        .unwrap();
+        assert_eq!(dims, vec!["lon", "lat"]);
+        assert_eq!(array.shape(), &[4, 3]);
+        assert_eq!(array[[3, 2]], 123.0);
+
+        // Omitted and repeated dimensions are rejected
+        assert!(extract_with_layout(
+            &state,
+            "t2m",
+            &selected_indices,
+            Some(&layout(&["lat", "lon"])),
+            None
+        )
+        .is_err());
+        assert!(extract_with_layout(
+            &state,
+            "t2m",
+            &selected_indices,
+            Some(&layout(&["lat", "lat", "lon", "time"])),
+            None
+        )
+        .is_err());
+    }
 
-        // Create variables
-        let variables = vec!["temp".to_string()];
-        let data_arrays = vec![&data_dyn];
+    #[test]
+    fn test_arrow_table() {
+        use arrow_ipc::reader::StreamReader;
+
+        // A (time, x) variable of 3 x 2 values, in batches of one time step
+        let data =
+            Array::from_shape_vec(IxDyn(&[3, 2]), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        let dims = vec!["time".to_string(), "x".to_string()];
+        let time = vec![0.0, 1.0, 2.0];
+        let x = vec![10.0, 20.0];
+        let table = |batch_rows| {
+            ArrowTable::new(
+                &["temp".to_string()],
+                vec![data.clone()],
+                std::slice::from_ref(&dims),
+                &dims,
+                &[&time, &x],
+                HashMap::new(),
+                batch_rows,
+                IpcWriteOptions::default(),
+            )
+            .unwrap()
+        };
 
-        // Convert to Arrow
-        let arrow_data =
-            create_arrow_table(&variables, &data_arrays, &dim_names, &coord_arrays, None).unwrap();
+        // Batches hold whole time steps, however small they are asked to be
+        assert_eq!(table(1).batch_count(), 3);
+        assert_eq!(table(5).batch_count(), 2);
+        assert_eq!(table(BATCH_ROWS).batch_count(), 1);
+
+        let bytes = table(1).to_ipc_bytes().unwrap();
+        let batches: Vec<RecordBatch> = StreamReader::try_new(bytes.as_slice(), None)
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 3);
+        let column = |batch: &RecordBatch, index: usize| -> Vec<f64> {
+            let column = batch.column(index);
+            match column.as_any().downcast_ref::<Float64Array>() {
+                Some(values) => values.values().to_vec(),
+                None => column
+                    .as_any()
+                    .downcast_ref::<Float32Array>()
+                    .unwrap()
+                    .values()
+                    .iter()
+                    .map(|&v| v as f64)
+                    .collect(),
+            }
+        };
+        assert_eq!(column(&batches[1], 0), vec![1.0, 1.0]);
+        assert_eq!(column(&batches[1], 1), vec![10.0, 20.0]);
+        assert_eq!(column(&batches[2], 2), vec![5.0, 6.0]);
+        assert_eq!(batches[0].schema().field(2).metadata()["shape"], "[3,2]");
+
+        // Variables must have a value for every row
+        let short = Array::from_vec(vec![1.0f32]).into_dyn();
+        assert!(ArrowTable::new(
+            &["temp".to_string(), "short".to_string()],
+            vec![data.clone(), short],
+            &[dims.clone(), vec!["x".to_string()]],
+            &dims,
+            &[&time, &x],
+            HashMap::new(),
+            BATCH_ROWS,
+            IpcWriteOptions::default(),
+        )
+        .is_err());
+    }
 
-        // Check that we got data
-        assert!(!arrow_data.is_empty());
+    #[test]
+    fn test_arrow_compression() {
+        use arrow_ipc::reader::StreamReader;
+
+        assert!(parse_compression(Some("gzip")).is_err());
+
+        // A smooth field of 100 x 100 values, which compresses well
+        let values = (0..10_000).map(|i| (i / 100) as f32).collect();
+        let data = Array::from_shape_vec(IxDyn(&[100, 100]), values).unwrap();
+        let dims = vec!["y".to_string(), "x".to_string()];
+        let coords: Vec<f64> = (0..100).map(f64::from).collect();
+        let ipc_bytes = |compression| {
+            ArrowTable::new(
+                &["temp".to_string()],
+                vec![data.clone()],
+                std::slice::from_ref(&dims),
+                &dims,
+                &[&coords, &coords],
+                HashMap::new(),
+                BATCH_ROWS,
+                parse_compression(compression).unwrap(),
+            )
+            .unwrap()
+            .to_ipc_bytes()
+            .unwrap()
+        };
+        let read = |bytes: Vec<u8>| -> Vec<RecordBatch> {
+            StreamReader::try_new(bytes.as_slice(), None)
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
 
-        // Make sure the length is significant (it should be more than just headers)
-        assert!(arrow_data.len() > 100);
+        let uncompressed = ipc_bytes(Some("none"));
+        assert_eq!(uncompressed, ipc_bytes(None));
+        for compression in ["lz4", "zstd"] {
+            let compressed = ipc_bytes(Some(compression));
+            assert!(compressed.len() < uncompressed.len() / 3, "{}", compression);
+            assert_eq!(
+                read(compressed),
+                read(uncompressed.clone()),
+                "{}",
+                compression
+            );
+        }
     }
 }
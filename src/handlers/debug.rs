@@ -0,0 +1,217 @@
+//! Interpolation debug endpoint handler.
+//!
+//! Returns the stencil behind an interpolated value: the grid points read
+//! along each horizontal axis with their coordinates, raw values and weights,
+//! and the values interpolated along the second axis before combining them
+//! along the first. The value is computed exactly as `/point` computes it.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::bounds::{Axis, BoundsMode};
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, resolve_dimension_indices};
+use crate::handlers::stats::selection_to_json;
+use crate::interpolation::common::coord_to_index;
+use crate::interpolation::{get_interpolator, stencil};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::AppState;
+
+/// Query parameters for the interpolation debug endpoint
+#[derive(Debug, Deserialize)]
+pub struct InterpolateDebugQuery {
+    /// Variable name
+    pub var: String,
+    /// Longitude of the point
+    pub lon: f64,
+    /// Latitude of the point
+    pub lat: f64,
+    /// Interpolation method (nearest, bilinear, bicubic)
+    pub method: Option<String>,
+    /// Handling of coordinates outside the grid (error, clamp or wrap)
+    pub bounds: Option<String>,
+    /// Dimension selectors pinning non-horizontal dimensions (see `query::Selection`)
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Handle GET /debug/interpolate requests
+pub async fn interpolate_debug_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<InterpolateDebugQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/debug/interpolate",
+        request_id = %request_id,
+        var = %params.var,
+        lon = params.lon,
+        lat = params.lat,
+        method = ?params.method,
+        "Processing interpolation debug request"
+    );
+
+    match trace_interpolation(&state, &params) {
+        Ok(response) => {
+            info!(
+                endpoint = "/debug/interpolate",
+                request_id = %request_id,
+                var = %params.var,
+                duration_us = start_time.elapsed().as_micros() as u64,
+                "Interpolation debug request successful"
+            );
+            Json(response).into_response()
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/debug/interpolate",
+                &request_id,
+                Some(&format!(
+                    "var={}, lon={}, lat={}",
+                    params.var, params.lon, params.lat
+                )),
+            );
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Interpolate a variable at a point and describe the stencil used
+fn trace_interpolation(
+    state: &AppState,
+    params: &InterpolateDebugQuery,
+) -> Result<serde_json::Value> {
+    let var_meta = state.get_variable_metadata_checked(&params.var)?;
+    let dimensions = var_meta.dimensions.clone();
+    let (lat_axis, lon_axis) =
+        find_lat_lon_axes(&dimensions).ok_or_else(|| RossbyError::DataNotFound {
+            message: format!(
+                "Variable {} does not have latitude and longitude dimensions",
+                params.var
+            ),
+        })?;
+
+    let method = params.method.as_deref().unwrap_or("bilinear");
+    let interpolator = get_interpolator(method)?;
+    let bounds = BoundsMode::from_request(params.bounds.as_deref(), &state.config.data.bounds)?;
+
+    // Pin every other dimension, leaving the horizontal slab in variable order
+    let dim_indices = resolve_dimension_indices(state, &params.var, &params.dimension_params)?;
+    let pins: Vec<(usize, usize)> = dimensions
+        .iter()
+        .enumerate()
+        .filter_map(|(axis, dim)| dim_indices.get(dim).map(|&index| (axis, index)))
+        .collect();
+    let data = state.get_variable_indexed(&params.var, &pins)?;
+    let data = data.as_standard_layout();
+
+    let lat_coords = state.get_coordinate_checked(&dimensions[lat_axis])?;
+    let lon_coords = state.get_coordinate_checked(&dimensions[lon_axis])?;
+    let lat = bounds.apply(Axis::Latitude, params.lat, lat_coords)?;
+    let lon = bounds.apply(Axis::Longitude, params.lon, lon_coords)?;
+    let lat_index = coord_to_index(lat, lat_coords)?;
+    let lon_index = coord_to_index(lon, lon_coords)?;
+
+    // The slab keeps the order of the horizontal axes in the variable
+    let (axis_names, axis_coords, indices) = if lat_axis < lon_axis {
+        (
+            [&dimensions[lat_axis], &dimensions[lon_axis]],
+            [lat_coords, lon_coords],
+            [lat_index, lon_index],
+        )
+    } else {
+        (
+            [&dimensions[lon_axis], &dimensions[lat_axis]],
+            [lon_coords, lat_coords],
+            [lon_index, lat_index],
+        )
+    };
+
+    let slice = data.as_slice().ok_or_else(|| RossbyError::DataNotFound {
+        message: format!(
+            "Cannot access data for variable {} as contiguous slice",
+            params.var
+        ),
+    })?;
+    let value = interpolator.interpolate(slice, data.shape(), &indices)?;
+    let stencil = stencil::trace(interpolator.name(), data.view(), &indices)?;
+
+    let axes: Vec<serde_json::Value> = stencil
+        .axes
+        .iter()
+        .enumerate()
+        .map(|(i, kernel)| {
+            serde_json::json!({
+                "dimension": axis_names[i],
+                "index": kernel.index,
+                "positions": kernel.positions,
+                "coordinates": kernel
+                    .positions
+                    .iter()
+                    .map(|&p| axis_coords[i][p])
+                    .collect::<Vec<_>>(),
+                "weights": kernel.weights,
+            })
+        })
+        .collect();
+    let points: Vec<serde_json::Value> = stencil
+        .points
+        .iter()
+        .map(|point| {
+            let mut indices = serde_json::Map::new();
+            let mut coordinates = serde_json::Map::new();
+            for (i, &p) in point.indices.iter().enumerate() {
+                indices.insert(axis_names[i].clone(), p.into());
+                coordinates.insert(axis_names[i].clone(), axis_coords[i][p].into());
+            }
+            serde_json::json!({
+                "indices": indices,
+                "coordinates": coordinates,
+                "value": point.value,
+                "weight": point.weight,
+            })
+        })
+        .collect();
+
+    let selection: HashMap<String, (usize, f64)> = dim_indices
+        .iter()
+        .map(|(dim, &index)| {
+            let value = state
+                .get_coordinate(dim)
+                .and_then(|coords| coords.get(index).copied())
+                .unwrap_or(index as f64);
+            (dim.clone(), (index, value))
+        })
+        .collect();
+
+    Ok(serde_json::json!({
+        "var": params.var,
+        "method": interpolator.name(),
+        "lon": lon,
+        "lat": lat,
+        "selection": selection_to_json(&selection),
+        "axes": axes,
+        "points": points,
+        "intermediate": stencil.intermediate,
+        "value": value,
+    }))
+}
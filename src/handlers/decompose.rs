@@ -0,0 +1,338 @@
+//! Time-series decomposition endpoint handler.
+//!
+//! Interpolates a variable at one location for every selected time step, as
+//! `/chart?type=timeseries` does, and splits the series into trend, seasonal
+//! and residual components with a classical additive decomposition:
+//!
+//! - the trend is a centered moving average over one `period` (a 2×`period`
+//!   average for even periods), missing where the window runs off the series
+//!   or covers a missing value
+//! - the seasonal component repeats the mean detrended value of each phase of
+//!   the period, shifted to sum to zero over a period
+//! - the residual is what is left of the observed value
+//!
+//! The period is counted in time steps, so the time axis is assumed to be
+//! regular; phases are counted from the first selected step.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, info_span};
+
+use crate::bounds::{BoundsMode, BOUNDS_HEADER};
+use crate::error::{Result, RossbyError};
+use crate::handlers::profile_series::{coordinate_value, point_series, time_dimension};
+use crate::handlers::stats::selection_to_json;
+use crate::interpolation::get_interpolator;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::{AppState, AttributeValue};
+
+/// Query parameters for the decomposition endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct DecomposeQuery {
+    /// Variable name
+    pub var: String,
+    /// Longitude of the series
+    pub lon: f64,
+    /// Latitude of the series
+    pub lat: f64,
+    /// Length of the seasonal cycle in time steps
+    pub period: usize,
+    /// Horizontal interpolation method (nearest, bilinear, bicubic; default bilinear)
+    #[serde(default)]
+    pub interpolation: Option<String>,
+    /// Handling of coordinates outside the grid (error, clamp or wrap)
+    #[serde(default)]
+    pub bounds: Option<String>,
+    /// Dimension selectors: the range of time steps (default: all) and single
+    /// slices of the other non-horizontal dimensions
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Components of a series; missing values are NaN
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decomposition {
+    pub trend: Vec<f32>,
+    pub seasonal: Vec<f32>,
+    pub residual: Vec<f32>,
+    /// Seasonal component of each phase of the period
+    pub pattern: Vec<f32>,
+}
+
+/// Handle GET /decompose requests
+pub async fn decompose_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DecomposeQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/decompose",
+        request_id = %request_id,
+        var = %params.var,
+        lon = params.lon,
+        lat = params.lat,
+        period = params.period,
+        "Processing decomposition request"
+    );
+
+    match process_decompose_query(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/decompose",
+                request_id = %request_id,
+                var = %params.var,
+                duration_us = duration.as_micros() as u64,
+                "Decomposition request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/decompose",
+                &request_id,
+                Some(&format!(
+                    "var={}, lon={}, lat={}, period={}",
+                    params.var, params.lon, params.lat, params.period
+                )),
+            );
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_decompose_query(state: &AppState, params: &DecomposeQuery) -> Result<Response> {
+    if !state.has_variable(&params.var) {
+        return Err(RossbyError::VariableNotFound {
+            name: params.var.clone(),
+        });
+    }
+    let interpolator = get_interpolator(params.interpolation.as_deref().unwrap_or("bilinear"))?;
+    let bounds = BoundsMode::from_request(params.bounds.as_deref(), &state.config.data.bounds)?;
+    let time_dim = time_dimension(state, &params.var)?;
+
+    let extract_stage = info_span!("interpolate").entered();
+    let series = point_series(
+        state,
+        &params.var,
+        (params.lon, params.lat),
+        &time_dim,
+        &params.dimension_params,
+        bounds,
+        interpolator.as_ref(),
+    )?;
+    extract_stage.exit();
+
+    let decomposition = {
+        let _stage = info_span!("decompose").entered();
+        decompose(&series.values, params.period)?
+    };
+
+    let units = match state
+        .get_variable_metadata_checked(&params.var)?
+        .attributes
+        .get("units")
+    {
+        Some(AttributeValue::Text(units)) => Some(units.clone()),
+        _ => None,
+    };
+    let selection: HashMap<String, (usize, f64)> = series
+        .pinned
+        .iter()
+        .map(|(dim, &index)| (dim.clone(), (index, coordinate_value(state, dim, index))))
+        .collect();
+    let mut response = Json(json!({
+        "var": params.var,
+        "units": units,
+        "lon": params.lon,
+        "lat": params.lat,
+        "interpolation": interpolator.name(),
+        "period": params.period,
+        "time": {
+            "dimension": series.dimension,
+            "values": series.coordinates,
+        },
+        "selection": selection_to_json(&selection),
+        "observed": values_to_json(&series.values),
+        "trend": values_to_json(&decomposition.trend),
+        "seasonal": values_to_json(&decomposition.seasonal),
+        "residual": values_to_json(&decomposition.residual),
+        "seasonal_pattern": values_to_json(&decomposition.pattern),
+    }))
+    .into_response();
+    response
+        .headers_mut()
+        .insert(BOUNDS_HEADER, HeaderValue::from_static(bounds.as_str()));
+    Ok(response)
+}
+
+/// Additive decomposition of a regular series with a seasonal cycle of
+/// `period` steps
+///
+/// The series must cover at least two periods.
+pub fn decompose(values: &[f32], period: usize) -> Result<Decomposition> {
+    if period < 2 {
+        return Err(RossbyError::InvalidParameter {
+            param: "period".to_string(),
+            message: "period must be at least 2 time steps".to_string(),
+        });
+    }
+    if values.len() < 2 * period {
+        return Err(RossbyError::InvalidParameter {
+            param: "period".to_string(),
+            message: format!(
+                "A period of {} needs at least {} time steps, the series has {}",
+                period,
+                2 * period,
+                values.len()
+            ),
+        });
+    }
+
+    let trend = moving_average(values, period);
+
+    // Mean detrended value of each phase, centered on zero
+    let mut sums = vec![(0.0f64, 0usize); period];
+    for (i, (&value, &trend)) in values.iter().zip(&trend).enumerate() {
+        let detrended = value - trend;
+        if detrended.is_finite() {
+            sums[i % period].0 += detrended as f64;
+            sums[i % period].1 += 1;
+        }
+    }
+    let means: Vec<f64> = sums
+        .iter()
+        .map(|&(sum, count)| {
+            if count > 0 {
+                sum / count as f64
+            } else {
+                f64::NAN
+            }
+        })
+        .collect();
+    let offset = means.iter().sum::<f64>() / period as f64;
+    let pattern: Vec<f32> = means.iter().map(|mean| (mean - offset) as f32).collect();
+
+    let seasonal: Vec<f32> = (0..values.len()).map(|i| pattern[i % period]).collect();
+    let residual = values
+        .iter()
+        .zip(&trend)
+        .zip(&seasonal)
+        .map(|((value, trend), seasonal)| value - trend - seasonal)
+        .collect();
+    Ok(Decomposition {
+        trend,
+        seasonal,
+        residual,
+        pattern,
+    })
+}
+
+/// Centered moving average over one period, NaN where the window is
+/// incomplete
+fn moving_average(values: &[f32], period: usize) -> Vec<f32> {
+    // Even periods average two adjacent windows, weighting the ends by half
+    let half = period / 2;
+    let weights: Vec<f64> = if period.is_multiple_of(2) {
+        (0..=period)
+            .map(|k| if k == 0 || k == period { 0.5 } else { 1.0 })
+            .map(|w| w / period as f64)
+            .collect()
+    } else {
+        vec![1.0 / period as f64; period]
+    };
+
+    (0..values.len())
+        .map(|i| {
+            if i < half || i + half >= values.len() {
+                return f32::NAN;
+            }
+            weights
+                .iter()
+                .zip(&values[i - half..=i + half])
+                .map(|(&w, &v)| w * v as f64)
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+/// Values as a JSON array, with non-finite values as null
+fn values_to_json(values: &[f32]) -> Value {
+    values
+        .iter()
+        .map(|&v| {
+            serde_json::Number::from_f64(v as f64)
+                .map(Value::Number)
+                .unwrap_or(Value::Null)
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompose_recovers_trend_and_cycle() {
+        let cycle = [1.0, -2.0, 0.5, 0.5];
+        let values: Vec<f32> = (0..16)
+            .map(|i| 10.0 + 0.5 * i as f32 + cycle[i % 4])
+            .collect();
+        let result = decompose(&values, 4).unwrap();
+
+        assert_eq!(result.pattern.len(), 4);
+        for (phase, expected) in cycle.iter().enumerate() {
+            assert!((result.pattern[phase] - expected).abs() < 1e-4);
+        }
+        // The window runs off the series at both ends
+        assert!(result.trend[..2].iter().all(|t| t.is_nan()));
+        assert!(result.trend[14..].iter().all(|t| t.is_nan()));
+        for i in 2..14 {
+            assert!((result.trend[i] - (10.0 + 0.5 * i as f32)).abs() < 1e-4);
+            assert!(result.residual[i].abs() < 1e-4);
+        }
+        assert_eq!(result.seasonal[5], result.pattern[1]);
+    }
+
+    #[test]
+    fn test_odd_period_and_missing_values() {
+        let mut values: Vec<f32> = (0..9).map(|i| [3.0, 0.0, -3.0][i % 3]).collect();
+        values[4] = f32::NAN;
+        let result = decompose(&values, 3).unwrap();
+
+        // Windows covering the missing value have no trend
+        assert!(result.trend[3..6].iter().all(|t| t.is_nan()));
+        assert_eq!(result.trend[1], 0.0);
+        assert_eq!(result.trend[7], 0.0);
+        assert_eq!(result.pattern, vec![3.0, 0.0, -3.0]);
+        assert!(result.residual[4].is_nan());
+    }
+
+    #[test]
+    fn test_invalid_periods() {
+        let values = [1.0; 6];
+        assert!(decompose(&values, 1).is_err());
+        assert!(decompose(&values, 4).is_err());
+        assert!(decompose(&values, 3).is_ok());
+    }
+}
@@ -0,0 +1,513 @@
+//! Difference endpoint handler.
+//!
+//! Returns the local field of a variable minus the same field served by a
+//! registered peer instance, regridded bilinearly onto the local grid.
+//!
+//! A numeric difference of categorical fields (precipitation type, land
+//! cover) is meaningless, so `mode=categorical` compares codes instead: the
+//! peer field is regridded by nearest neighbour, and every cell is marked as
+//! agreeing or disagreeing, with the disagreeing cells grouped by category
+//! transition (the local code and the peer code). The result is returned as
+//! JSON or rendered as an agreement map with `encoding=png`.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::categories::{variable_categories, Category};
+use crate::error::{Result, RossbyError};
+use crate::field::HorizontalField;
+use crate::handlers::stats::{
+    describe_field_query, field_error_response, field_nan_policy, load_field_comparison,
+    selection_to_json, FieldQuery,
+};
+use crate::logging::generate_request_id;
+use crate::nan_policy::NAN_HEADER;
+use crate::query_resolved::{QueryResolved, QUERY_RESOLVED_HEADER, QUERY_RESOLVED_KEY};
+use crate::state::AppState;
+
+/// Response header listing the colors of category transitions in agreement
+/// maps, as `<local>:<peer>=#rrggbb` entries separated by commas
+pub const TRANSITIONS_HEADER: &str = "x-rossby-transitions";
+
+/// Color of cells where both fields have the same category
+const AGREEMENT_COLOR: [u8; 4] = [210, 210, 210, 255];
+
+/// Colors of category transitions, assigned from the most frequent one
+const TRANSITION_COLORS: [[u8; 4]; 10] = [
+    [228, 26, 28, 255],
+    [55, 126, 184, 255],
+    [77, 175, 74, 255],
+    [152, 78, 163, 255],
+    [255, 127, 0, 255],
+    [166, 86, 40, 255],
+    [247, 129, 191, 255],
+    [255, 217, 47, 255],
+    [27, 158, 119, 255],
+    [102, 102, 102, 255],
+];
+
+/// Most distinct transitions a categorical diff may have; more suggest the
+/// variable is not categorical
+const MAX_TRANSITIONS: usize = 256;
+
+/// Query parameters for the diff endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct DiffQuery {
+    #[serde(flatten)]
+    pub field: FieldQuery,
+    /// Kind of difference: numeric (default) or categorical
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Encoding of categorical diffs: json (default) or png
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+/// Kind of difference computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffMode {
+    /// Local values minus peer values
+    Numeric,
+    /// Agreement of category codes
+    Categorical,
+}
+
+impl DiffMode {
+    /// Parse the `mode` parameter (default: numeric)
+    fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("numeric") => Ok(DiffMode::Numeric),
+            Some("categorical") => Ok(DiffMode::Categorical),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "mode".to_string(),
+                message: format!(
+                    "Unknown diff mode: {}. Valid values are 'numeric' or 'categorical'",
+                    other
+                ),
+            }),
+        }
+    }
+}
+
+/// Cells where the local and peer categories differ, for one pair of codes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    /// Local category code
+    pub local: f32,
+    /// Peer category code
+    pub peer: f32,
+    /// Number of cells
+    pub count: usize,
+    /// Color of the cells in agreement maps
+    pub color: [u8; 4],
+}
+
+/// Agreement of two categorical fields on the same grid
+#[derive(Debug, Clone)]
+pub struct CategoricalDiff {
+    /// Latitude coordinates (one per row)
+    pub lat: Vec<f64>,
+    /// Longitude coordinates (one per column)
+    pub lon: Vec<f64>,
+    /// Whether the categories agree in each cell, None where either is missing
+    pub agreement: Vec<Vec<Option<bool>>>,
+    /// Transitions by decreasing number of cells
+    pub transitions: Vec<Transition>,
+}
+
+impl CategoricalDiff {
+    /// Compare the categories of a field against a reference on the same grid
+    pub fn new(field: &HorizontalField, reference: &HorizontalField) -> Result<Self> {
+        if field.values.shape() != reference.values.shape() {
+            return Err(RossbyError::Conversion {
+                message: format!(
+                    "Cannot compare fields with shapes {:?} and {:?}",
+                    field.values.shape(),
+                    reference.values.shape()
+                ),
+            });
+        }
+
+        let mut counts: HashMap<(u32, u32), usize> = HashMap::new();
+        let agreement = field
+            .values
+            .outer_iter()
+            .zip(reference.values.outer_iter())
+            .map(|(row, reference_row)| {
+                row.iter()
+                    .zip(reference_row.iter())
+                    .map(|(&local, &peer)| {
+                        if !(local.is_finite() && peer.is_finite()) {
+                            return None;
+                        }
+                        if local != peer {
+                            *counts.entry((local.to_bits(), peer.to_bits())).or_default() += 1;
+                        }
+                        Some(local == peer)
+                    })
+                    .collect()
+            })
+            .collect();
+        if counts.len() > MAX_TRANSITIONS {
+            return Err(RossbyError::InvalidParameter {
+                param: "mode".to_string(),
+                message: format!(
+                    "The fields differ in more than {} pairs of categories; mode=categorical needs categorical codes",
+                    MAX_TRANSITIONS
+                ),
+            });
+        }
+
+        let mut transitions: Vec<Transition> = counts
+            .into_iter()
+            .map(|((local, peer), count)| Transition {
+                local: f32::from_bits(local),
+                peer: f32::from_bits(peer),
+                count,
+                color: [0; 4],
+            })
+            .collect();
+        transitions.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(a.local.total_cmp(&b.local))
+                .then(a.peer.total_cmp(&b.peer))
+        });
+        for (i, transition) in transitions.iter_mut().enumerate() {
+            transition.color = TRANSITION_COLORS[i % TRANSITION_COLORS.len()];
+        }
+
+        Ok(Self {
+            lat: field.lat.clone(),
+            lon: field.lon.clone(),
+            agreement,
+            transitions,
+        })
+    }
+
+    /// Number of cells where both fields have a category, and where they agree
+    pub fn counts(&self) -> (usize, usize) {
+        let cells = self.agreement.iter().flatten().flatten();
+        let valid = cells.clone().count();
+        let agreeing = cells.filter(|&&agree| agree).count();
+        (valid, agreeing)
+    }
+
+    /// Render the agreement map, with rows running north to south
+    ///
+    /// Agreeing cells are light gray, disagreeing ones take the color of
+    /// their transition, and missing ones are transparent.
+    pub fn render(&self, field: &HorizontalField, reference: &HorizontalField) -> RgbaImage {
+        let colors: HashMap<(u32, u32), [u8; 4]> = self
+            .transitions
+            .iter()
+            .map(|t| ((t.local.to_bits(), t.peer.to_bits()), t.color))
+            .collect();
+        let (rows, cols) = field.values.dim();
+        let north_first = self.lat.first() >= self.lat.last();
+        RgbaImage::from_fn(cols as u32, rows as u32, |x, y| {
+            let row = if north_first {
+                y as usize
+            } else {
+                rows - 1 - y as usize
+            };
+            let col = x as usize;
+            Rgba(match self.agreement[row][col] {
+                None => [0, 0, 0, 0],
+                Some(true) => AGREEMENT_COLOR,
+                Some(false) => {
+                    let key = (
+                        field.values[[row, col]].to_bits(),
+                        reference.values[[row, col]].to_bits(),
+                    );
+                    colors[&key]
+                }
+            })
+        })
+    }
+
+    /// Transition colors in the format of [`TRANSITIONS_HEADER`]
+    pub fn legend_header(&self) -> String {
+        self.transitions
+            .iter()
+            .map(|t| format!("{}:{}={}", t.local, t.peer, hex_color(t.color)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Transitions as JSON, with the meanings of known categories
+    fn transitions_to_json(&self, categories: &[Category]) -> serde_json::Value {
+        let meaning = |code: f32| {
+            categories
+                .iter()
+                .find(|c| c.value == code as f64)
+                .map(|c| c.meaning.clone())
+        };
+        self.transitions
+            .iter()
+            .map(|t| {
+                serde_json::json!({
+                    "local": t.local,
+                    "peer": t.peer,
+                    "local_meaning": meaning(t.local),
+                    "peer_meaning": meaning(t.peer),
+                    "count": t.count,
+                    "color": hex_color(t.color),
+                })
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// Color as `#rrggbb`
+fn hex_color([r, g, b, _]: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Handle GET /diff requests
+pub async fn diff_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DiffQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/diff",
+        request_id = %request_id,
+        var = %params.field.var,
+        peer = ?params.field.peer,
+        bbox = ?params.field.bbox,
+        mode = ?params.mode,
+        "Processing diff request"
+    );
+
+    match process_diff_query(&state, &params).await {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/diff",
+                request_id = %request_id,
+                var = %params.field.var,
+                peer = ?params.field.peer,
+                duration_us = duration.as_micros() as u64,
+                "Diff request successful"
+            );
+            response
+        }
+        Err(error) => field_error_response(error, "/diff", &request_id, &params.field),
+    }
+}
+
+async fn process_diff_query(state: &AppState, params: &DiffQuery) -> Result<Response> {
+    let mode = DiffMode::parse(params.mode.as_deref())?;
+    let png = match params.encoding.as_deref().map(str::trim) {
+        None | Some("json") => false,
+        Some("png") if mode == DiffMode::Categorical => true,
+        Some(other) => {
+            return Err(RossbyError::InvalidParameter {
+                param: "encoding".to_string(),
+                message: format!(
+                    "Unknown encoding: {}. Valid values are 'json', or 'png' with mode=categorical",
+                    other
+                ),
+            })
+        }
+    };
+
+    let nan = field_nan_policy(state, &params.field)?;
+
+    let comparison = load_field_comparison(state, &params.field).await?;
+    let query_resolved = describe_field_query(state, &params.field, &comparison)?;
+    let Some((name, peer_field, regridded)) = &comparison.peer else {
+        return Err(RossbyError::InvalidParameter {
+            param: "peer".to_string(),
+            message: "A registered peer name is required".to_string(),
+        });
+    };
+
+    if mode == DiffMode::Numeric {
+        let diff = comparison.local.difference(regridded)?;
+        let mut body = serde_json::json!({
+            "var": params.field.var,
+            "peer": name,
+            "selection": selection_to_json(&comparison.selection),
+            "lat": diff.lat,
+            "lon": diff.lon,
+            "shape": diff.values.shape(),
+            "values": nan.rows(&diff.values),
+            "stats": diff.stats(),
+            "comparison": comparison.local.compare(regridded)?,
+            "nan": nan.as_str(),
+        });
+        nan.undefined_members(&mut body["stats"]);
+        nan.undefined_members(&mut body["comparison"]);
+        if let Some(resolved) = &query_resolved {
+            body[QUERY_RESOLVED_KEY] = resolved.to_json();
+        }
+        let mut response = Json(body).into_response();
+        response
+            .headers_mut()
+            .insert(NAN_HEADER, HeaderValue::from_static(nan.as_str()));
+        return Ok(response);
+    }
+
+    // Codes must not be blended, so the peer is regridded by nearest neighbour
+    let local = &comparison.local;
+    let reference = peer_field.regrid_nearest(&local.lat, &local.lon);
+    let diff = CategoricalDiff::new(local, &reference)?;
+
+    if png {
+        let img = diff.render(local, &reference);
+        let mut buffer = Cursor::new(Vec::new());
+        img.write_to(&mut buffer, image::ImageFormat::Png)
+            .map_err(|e| RossbyError::ImageGeneration {
+                message: format!("Failed to encode PNG: {}", e),
+            })?;
+        let mut response = (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, HeaderValue::from_static("image/png"))],
+            buffer.into_inner(),
+        )
+            .into_response();
+        if let Ok(legend) = HeaderValue::from_str(&diff.legend_header()) {
+            response.headers_mut().insert(TRANSITIONS_HEADER, legend);
+        }
+        if let Some(value) = query_resolved
+            .as_ref()
+            .and_then(QueryResolved::header_value)
+        {
+            response.headers_mut().insert(QUERY_RESOLVED_HEADER, value);
+        }
+        return Ok(response);
+    }
+
+    let categories = state
+        .get_variable_metadata(&params.field.var)
+        .and_then(|var| variable_categories(&state.config, &params.field.var, var))
+        .unwrap_or_default();
+    let (valid, agreeing) = diff.counts();
+    let mut body = serde_json::json!({
+        "var": params.field.var,
+        "peer": name,
+        "mode": "categorical",
+        "selection": selection_to_json(&comparison.selection),
+        "lat": diff.lat,
+        "lon": diff.lon,
+        "shape": local.values.shape(),
+        "agreement": diff.agreement,
+        "count": valid,
+        "agreeing": agreeing,
+        "agreement_rate": (valid > 0).then(|| agreeing as f64 / valid as f64),
+        "agreement_color": hex_color(AGREEMENT_COLOR),
+        "transitions": diff.transitions_to_json(&categories),
+    });
+    if let Some(resolved) = &query_resolved {
+        body[QUERY_RESOLVED_KEY] = resolved.to_json();
+    }
+    Ok(Json(body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn field(values: ndarray::Array2<f32>) -> HorizontalField {
+        HorizontalField {
+            lat: vec![0.0, 10.0],
+            lon: vec![0.0, 10.0, 20.0],
+            values,
+        }
+    }
+
+    #[test]
+    fn test_categorical_diff() {
+        let local = field(array![[1.0, 2.0, 3.0], [2.0, 2.0, f32::NAN]]);
+        let peer = field(array![[1.0, 1.0, 1.0], [1.0, 2.0, 3.0]]);
+        let diff = CategoricalDiff::new(&local, &peer).unwrap();
+
+        assert_eq!(
+            diff.agreement,
+            vec![
+                vec![Some(true), Some(false), Some(false)],
+                vec![Some(false), Some(true), None],
+            ]
+        );
+        assert_eq!(diff.counts(), (5, 2));
+        // The most frequent transition comes first
+        assert_eq!(
+            diff.transitions
+                .iter()
+                .map(|t| (t.local, t.peer, t.count))
+                .collect::<Vec<_>>(),
+            vec![(2.0, 1.0, 2), (3.0, 1.0, 1)]
+        );
+        assert_eq!(diff.legend_header(), "2:1=#e41a1c,3:1=#377eb8");
+
+        let categories = vec![
+            Category {
+                value: 1.0,
+                meaning: "rain".to_string(),
+            },
+            Category {
+                value: 2.0,
+                meaning: "snow".to_string(),
+            },
+        ];
+        let json = diff.transitions_to_json(&categories);
+        assert_eq!(json[0]["local_meaning"], "snow");
+        assert_eq!(json[0]["peer_meaning"], "rain");
+        assert!(json[1]["local_meaning"].is_null());
+    }
+
+    #[test]
+    fn test_render_agreement_map() {
+        let local = field(array![[1.0, 2.0, f32::NAN], [1.0, 1.0, 1.0]]);
+        let peer = field(array![[1.0, 1.0, 1.0], [1.0, 1.0, 1.0]]);
+        let diff = CategoricalDiff::new(&local, &peer).unwrap();
+        let img = diff.render(&local, &peer);
+
+        // Latitudes ascend, so the first row of the field is the bottom row
+        assert_eq!(img.dimensions(), (3, 2));
+        assert_eq!(img.get_pixel(0, 1).0, AGREEMENT_COLOR);
+        assert_eq!(img.get_pixel(1, 1).0, TRANSITION_COLORS[0]);
+        assert_eq!(img.get_pixel(2, 1).0, [0, 0, 0, 0]);
+        assert_eq!(img.get_pixel(1, 0).0, AGREEMENT_COLOR);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(DiffMode::parse(None).unwrap(), DiffMode::Numeric);
+        assert_eq!(
+            DiffMode::parse(Some("Categorical")).unwrap(),
+            DiffMode::Categorical
+        );
+        assert!(DiffMode::parse(Some("ratio")).is_err());
+    }
+
+    #[test]
+    fn test_too_many_transitions() {
+        let values = ndarray::Array2::from_shape_fn((2, 300), |(_, j)| j as f32);
+        let local = HorizontalField {
+            lat: vec![0.0, 1.0],
+            lon: (0..300).map(|j| j as f64).collect(),
+            values: values.clone(),
+        };
+        let mut reference = local.clone();
+        reference.values = values + 0.5;
+        assert!(CategoricalDiff::new(&local, &reference).is_err());
+    }
+}
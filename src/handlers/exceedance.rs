@@ -0,0 +1,492 @@
+//! Threshold exceedance endpoint handler.
+//!
+//! Counts, for every grid cell, the time steps at which a variable exceeds a
+//! threshold (e.g. days above 30°C), and returns the count or the fraction of
+//! valid time steps as a field: JSON values, or a PNG rendered through the
+//! same colormaps as `/image`.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use ndarray::{Array2, Zip};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, info_span};
+
+use crate::colormaps::{self, parse_bbox, LatitudeScaling};
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, HorizontalField};
+use crate::handlers::image::generate_image;
+use crate::handlers::mask::Comparison;
+use crate::handlers::stats::selection_to_json;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::query::Selection;
+use crate::state::AppState;
+
+/// Default image dimensions for `format=png`
+pub(crate) const DEFAULT_WIDTH: u32 = 800;
+pub(crate) const DEFAULT_HEIGHT: u32 = 600;
+
+/// Query parameters for the exceedance endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExceedanceQuery {
+    /// Variable name
+    pub var: String,
+    /// Value the variable is compared against
+    pub threshold: f64,
+    /// Comparison (gt, ge, lt, le, eq or ne; default gt)
+    #[serde(default)]
+    pub op: Option<String>,
+    /// Reported statistic (count or fraction; default fraction)
+    #[serde(default)]
+    pub statistic: Option<String>,
+    /// Bounding box as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Output format (json or png; default json)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Colormap name for `format=png`
+    #[serde(default)]
+    pub colormap: Option<String>,
+    /// Image width in pixels for `format=png`
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Image height in pixels for `format=png`
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Dimension selectors: a time selection (default: all time steps) and
+    /// single slices of the other non-horizontal dimensions
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Per-cell statistic of the time steps satisfying the condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceedanceStatistic {
+    /// Number of time steps
+    Count,
+    /// Fraction of the time steps with a valid value
+    Fraction,
+}
+
+impl ExceedanceStatistic {
+    /// Parse the `statistic` parameter (default: fraction)
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("fraction") => Ok(ExceedanceStatistic::Fraction),
+            Some("count") => Ok(ExceedanceStatistic::Count),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "statistic".to_string(),
+                message: format!(
+                    "Unknown statistic: {}. Valid values are 'count' or 'fraction'",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Name of the statistic as given in `statistic`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExceedanceStatistic::Count => "count",
+            ExceedanceStatistic::Fraction => "fraction",
+        }
+    }
+}
+
+/// Accumulate the exceedance statistic over the fields of successive time steps
+///
+/// Missing values count neither as exceedances nor as valid time steps, so
+/// the fraction of a cell without any valid value is NaN. All fields must
+/// share one grid.
+pub fn exceedance(
+    fields: impl IntoIterator<Item = Result<HorizontalField>>,
+    comparison: Comparison,
+    threshold: f64,
+    statistic: ExceedanceStatistic,
+) -> Result<HorizontalField> {
+    let mut grid: Option<(Vec<f64>, Vec<f64>)> = None;
+    let mut counts = Array2::<u32>::zeros((0, 0));
+    let mut valid = Array2::<u32>::zeros((0, 0));
+
+    for field in fields {
+        let field = field?;
+        match &grid {
+            None => {
+                counts = Array2::zeros(field.values.dim());
+                valid = Array2::zeros(field.values.dim());
+                grid = Some((field.lat.clone(), field.lon.clone()));
+            }
+            Some(_) if field.values.dim() != counts.dim() => {
+                return Err(RossbyError::Conversion {
+                    message: format!(
+                        "Time steps have different shapes {:?} and {:?}",
+                        counts.dim(),
+                        field.values.dim()
+                    ),
+                });
+            }
+            Some(_) => {}
+        }
+        Zip::from(&mut counts)
+            .and(&mut valid)
+            .and(&field.values)
+            .for_each(|count, valid, &value| {
+                if !value.is_nan() {
+                    *valid += 1;
+                }
+                if comparison.matches(value, threshold) {
+                    *count += 1;
+                }
+            });
+    }
+
+    let (lat, lon) = grid.ok_or_else(|| RossbyError::InvalidParameter {
+        param: "time".to_string(),
+        message: "No time steps selected".to_string(),
+    })?;
+    let values = match statistic {
+        ExceedanceStatistic::Count => counts.mapv(|count| count as f32),
+        ExceedanceStatistic::Fraction => {
+            Zip::from(&counts)
+                .and(&valid)
+                .map_collect(|&count, &valid| match valid {
+                    0 => f32::NAN,
+                    valid => count as f32 / valid as f32,
+                })
+        }
+    };
+
+    Ok(HorizontalField { lat, lon, values })
+}
+
+/// Handle GET /exceedance requests
+pub async fn exceedance_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExceedanceQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/exceedance",
+        request_id = %request_id,
+        var = %params.var,
+        threshold = params.threshold,
+        op = ?params.op,
+        "Processing exceedance request"
+    );
+
+    match process_exceedance_query(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/exceedance",
+                request_id = %request_id,
+                var = %params.var,
+                duration_us = duration.as_micros() as u64,
+                "Exceedance request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/exceedance",
+                &request_id,
+                Some(&format!(
+                    "var={}, threshold={}, op={:?}",
+                    params.var, params.threshold, params.op
+                )),
+            );
+            let status = match &error {
+                RossbyError::ImageGeneration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_exceedance_query(state: &AppState, params: &ExceedanceQuery) -> Result<Response> {
+    let comparison = Comparison::parse(params.op.as_deref())?;
+    let statistic = ExceedanceStatistic::parse(params.statistic.as_deref())?;
+    let format = params.format.as_deref().unwrap_or("json").to_lowercase();
+    if format != "json" && format != "png" {
+        return Err(RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: format!(
+                "Unsupported format: {}. Valid values are 'json' or 'png'",
+                format
+            ),
+        });
+    }
+    if !params.threshold.is_finite() {
+        return Err(RossbyError::InvalidParameter {
+            param: "threshold".to_string(),
+            message: "Threshold must be a finite number".to_string(),
+        });
+    }
+    if !state.has_variable(&params.var) {
+        return Err(RossbyError::InvalidVariables {
+            names: vec![params.var.clone()],
+        });
+    }
+
+    let var_meta = state.get_variable_metadata_checked(&params.var)?;
+    let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+        RossbyError::VariableNotSuitableForImage {
+            name: params.var.clone(),
+        }
+    })?;
+    let time_dim = state
+        .resolve_dimension("time")
+        .ok()
+        .filter(|dim| var_meta.dimensions.iter().any(|d| d == dim))
+        .ok_or_else(|| RossbyError::InvalidParameter {
+            param: "var".to_string(),
+            message: format!("Variable '{}' has no time dimension", params.var),
+        })?
+        .to_string();
+
+    // The time dimension may be selected by any range or list, every other
+    // non-horizontal dimension is pinned to a single slice
+    let selection = Selection::parse(state, &params.dimension_params)?;
+    let mut pinned = HashMap::new();
+    let mut time_indices = None;
+    for (axis, dim_name) in var_meta.dimensions.iter().enumerate() {
+        let selected = selection.get(dim_name);
+        if axis == lat_axis || axis == lon_axis {
+            if let Some(selected) = selected {
+                return Err(RossbyError::InvalidParameter {
+                    param: selected.param.clone(),
+                    message: "Horizontal dimensions cannot be selected; use bbox instead"
+                        .to_string(),
+                });
+            }
+        } else if *dim_name == time_dim {
+            time_indices = selected.map(|s| s.resolve(state)).transpose()?;
+        } else {
+            let index = selected.map(|s| s.resolve_single(state)).transpose()?;
+            pinned.insert(dim_name.clone(), index.unwrap_or(0));
+        }
+    }
+    if let Some(selected) = selection
+        .iter()
+        .find(|s| !var_meta.dimensions.contains(&s.dimension))
+    {
+        return Err(RossbyError::InvalidParameter {
+            param: selected.param.clone(),
+            message: format!(
+                "Dimension '{}' is not a dimension of '{}'",
+                selected.dimension, params.var
+            ),
+        });
+    }
+    let time_indices = match time_indices {
+        Some(indices) => indices,
+        None => {
+            let size = state
+                .metadata
+                .dimensions
+                .get(&time_dim)
+                .map_or(0, |d| d.size);
+            (0..size).collect()
+        }
+    };
+
+    let bbox = params
+        .bbox
+        .as_deref()
+        .map(parse_bbox)
+        .transpose()?
+        .map(|(a, b, c, d)| (a as f64, b as f64, c as f64, d as f64));
+
+    let extract_stage = info_span!("extract").entered();
+    let fields = time_indices.iter().map(|&index| {
+        let mut dim_indices = pinned.clone();
+        dim_indices.insert(time_dim.clone(), index);
+        HorizontalField::from_state(state, &params.var, &dim_indices, bbox)
+    });
+    let field = exceedance(fields, comparison, params.threshold, statistic)?;
+    extract_stage.exit();
+
+    if format == "png" {
+        let colormap = state.config.data.colormap(params.colormap.as_deref());
+        return render_png(&field, params, colormap, statistic, time_indices.len());
+    }
+
+    let time_coords: Vec<f64> = time_indices
+        .iter()
+        .map(|&i| {
+            state
+                .get_coordinate(&time_dim)
+                .and_then(|coords| coords.get(i).copied())
+                .unwrap_or(i as f64)
+        })
+        .collect();
+    let selection: HashMap<String, (usize, f64)> = pinned
+        .iter()
+        .map(|(dim, &index)| {
+            let value = state
+                .get_coordinate(dim)
+                .and_then(|coords| coords.get(index).copied())
+                .unwrap_or(index as f64);
+            (dim.clone(), (index, value))
+        })
+        .collect();
+
+    let _stage = info_span!("serialize").entered();
+    Ok(Json(serde_json::json!({
+        "var": params.var,
+        "op": comparison.as_str(),
+        "threshold": params.threshold,
+        "statistic": statistic.as_str(),
+        "time": {
+            "dimension": time_dim,
+            "steps": time_indices.len(),
+            "first": time_coords.first(),
+            "last": time_coords.last(),
+        },
+        "selection": selection_to_json(&selection),
+        "lat": field.lat,
+        "lon": field.lon,
+        "shape": field.values.shape(),
+        "values": field.values_to_json(),
+        "stats": field.stats(),
+    }))
+    .into_response())
+}
+
+/// Render an exceedance field with a colormap spanning its possible values
+fn render_png(
+    field: &HorizontalField,
+    params: &ExceedanceQuery,
+    colormap: &str,
+    statistic: ExceedanceStatistic,
+    steps: usize,
+) -> Result<Response> {
+    let width = params.width.unwrap_or(DEFAULT_WIDTH);
+    let height = params.height.unwrap_or(DEFAULT_HEIGHT);
+    if width < 2 || height < 2 || width > 8192 || height > 8192 {
+        return Err(RossbyError::InvalidParameter {
+            param: "width/height".to_string(),
+            message: "Image dimensions must be between 2 and 8192 pixels".to_string(),
+        });
+    }
+    if field.values.is_empty() {
+        return Err(RossbyError::InvalidParameter {
+            param: "bbox".to_string(),
+            message: "The bounding box contains no grid points".to_string(),
+        });
+    }
+    let colormap = colormaps::get_colormap(colormap)?;
+
+    let value_range = match statistic {
+        ExceedanceStatistic::Count => (0.0, steps as f32),
+        ExceedanceStatistic::Fraction => (0.0, 1.0),
+    };
+    let lat_span = match (field.lat.first(), field.lat.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => (0.0, 0.0),
+    };
+
+    let render_stage = info_span!("render").entered();
+    let img = generate_image(
+        field.values.view(),
+        width,
+        height,
+        colormap.as_ref(),
+        "auto",
+        LatitudeScaling::PlateCarree,
+        lat_span,
+        value_range,
+    )?;
+    render_stage.exit();
+
+    let _stage = info_span!("encode").entered();
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, image::ImageFormat::Png)
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to encode PNG: {}", e),
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("image/png"))],
+        buffer.into_inner(),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn field(values: Array2<f32>) -> Result<HorizontalField> {
+        Ok(HorizontalField {
+            lat: vec![0.0, 10.0],
+            lon: vec![0.0, 10.0],
+            values,
+        })
+    }
+
+    #[test]
+    fn test_exceedance() {
+        let steps = || {
+            vec![
+                field(array![[301.0, 290.0], [f32::NAN, 305.0]]),
+                field(array![[302.0, 300.0], [f32::NAN, 299.0]]),
+                field(array![[280.0, 310.0], [f32::NAN, 306.0]]),
+                field(array![[303.0, 290.0], [310.0, 301.0]]),
+            ]
+        };
+
+        let counts =
+            exceedance(steps(), Comparison::Gt, 300.0, ExceedanceStatistic::Count).unwrap();
+        assert_eq!(counts.values, array![[3.0, 1.0], [1.0, 3.0]]);
+
+        let fractions = exceedance(
+            steps(),
+            Comparison::Gt,
+            300.0,
+            ExceedanceStatistic::Fraction,
+        )
+        .unwrap();
+        assert_eq!(fractions.values[[0, 0]], 0.75);
+        // Only one valid time step
+        assert_eq!(fractions.values[[1, 0]], 1.0);
+    }
+
+    #[test]
+    fn test_exceedance_errors() {
+        assert!(exceedance(Vec::new(), Comparison::Gt, 0.0, ExceedanceStatistic::Count).is_err());
+
+        let steps = vec![
+            field(array![[1.0, 2.0], [3.0, 4.0]]),
+            field(array![[1.0, 2.0]]),
+        ];
+        assert!(exceedance(steps, Comparison::Gt, 0.0, ExceedanceStatistic::Count).is_err());
+
+        assert_eq!(
+            ExceedanceStatistic::parse(Some("count")).unwrap(),
+            ExceedanceStatistic::Count
+        );
+        assert!(ExceedanceStatistic::parse(Some("mean")).is_err());
+    }
+}
@@ -0,0 +1,835 @@
+//! Export endpoint handler.
+//!
+//! Writes a subset of the dataset (variables, dimension selectors and a
+//! bounding box) as a NetCDF file, streamed as it is written (see
+//! [`crate::netcdf_writer`]): the outermost dimension of the first variable
+//! becomes the unlimited dimension and every variable is read and sent one
+//! step of its outermost dimension at a time, so large subsets are exported
+//! with bounded memory.
+//!
+//! CF metadata is carried over and kept true of the subset: variables named
+//! by the `bounds`, `grid_mapping`, `coordinates` and `ancillary_variables`
+//! attributes of the exported ones are exported along, the export is
+//! recorded in the `history` attribute, and the extents the dataset
+//! describes (`actual_range` of coordinates, the ACDD `geospatial_*` and
+//! `time_coverage_*` attributes) are recomputed. `actual_range` is dropped
+//! from the other variables, whose values are only read while streaming.
+//! Every file is checked with [`crate::cf_check`] before it is sent; with
+//! `strict=true` a file that fails the checks is refused.
+//!
+//! The file of a query is the same byte for byte on every request to a
+//! dataset (the export is dated with the time the dataset was loaded), and
+//! the size of each chunk follows from the layout alone, so downloads are
+//! resumable like other artifacts (see [`crate::artifact`]): the SHA-256 of
+//! the file is computed in a first pass over the values before it is sent,
+//! and a `Range` is served from the chunks it covers. With `keepbits`,
+//! values are bit-rounded (see [`crate::bitround`]) and the bits kept are
+//! recorded in a `keepbits` attribute of each rounded variable. Values are
+//! read on the blocking thread pool.
+
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use chrono::SecondsFormat;
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::artifact::{ranged_response, to_hex};
+use crate::bitround::{self, KeepBits};
+use crate::cf_check::{check_file, grid_mapping_names, CF_ISSUES_HEADER};
+use crate::cf_time::time_units;
+use crate::colormaps::parse_bbox;
+use crate::error::{Result, RossbyError};
+use crate::field::{LAT_NAMES, LON_NAMES};
+use crate::handlers::data::parse_flag;
+use crate::lazy::checked_reads;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::netcdf_writer::{
+    encode_doubles, encode_floats, Chunk, ClassicFile, NcAttribute, NcAttributeValue, NcDimension,
+    NcType, NcVariable,
+};
+use crate::query::{no_variables, split_variables, Dataset, Selection};
+use crate::state::{AppState, AttributeValue};
+
+/// Bytes gathered into one chunk of the response body
+const BODY_CHUNK_BYTES: usize = 1 << 20;
+
+/// Attributes that hold values of their variable, written in its type
+const VALUE_ATTRIBUTES: [&str; 5] = [
+    "_FillValue",
+    "missing_value",
+    "valid_min",
+    "valid_max",
+    "valid_range",
+];
+
+/// Attributes naming other variables, which are exported along
+const REFERENCE_ATTRIBUTES: [&str; 4] = [
+    "bounds",
+    "grid_mapping",
+    "coordinates",
+    "ancillary_variables",
+];
+
+/// Conventions attribute of exported files whose dataset has none
+const CF_CONVENTIONS: &str = "CF-1.8";
+
+/// Query parameters for the export endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExportQuery {
+    /// Comma-separated variables to export
+    #[serde(default)]
+    pub vars: String,
+    /// Output format (netcdf)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Bounding box as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Refuse files failing the CF checks (true/false, default false)
+    #[serde(default)]
+    pub strict: Option<String>,
+    /// Mantissa bits to keep, e.g. "7" or "7,u10:5" (see [`crate::bitround`])
+    #[serde(default)]
+    pub keepbits: Option<String>,
+    /// Dimension selectors (default: the whole dimension)
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Where the values of a variable of the exported file come from
+#[derive(Debug, Clone)]
+enum Source {
+    /// The coordinates of a dimension at the selected indices
+    Coordinate(Vec<f64>),
+    /// A variable of the dataset at the selected indices of its dimensions
+    Variable {
+        name: String,
+        dimensions: Vec<String>,
+        selected: HashMap<String, Vec<usize>>,
+    },
+}
+
+/// An exported file and the sources of its variables
+#[derive(Debug, Clone)]
+struct Export {
+    file: ClassicFile,
+    sources: Vec<Source>,
+    /// Mantissa bits kept for each bit-rounded variable of the dataset
+    keepbits: BTreeMap<String, u32>,
+}
+
+impl Export {
+    /// Values of the coordinate variables of the file, by name
+    fn coordinates(&self) -> HashMap<String, Vec<f64>> {
+        self.file
+            .variables
+            .iter()
+            .zip(&self.sources)
+            .filter_map(|(var, source)| match source {
+                Source::Coordinate(values) => Some((var.name.clone(), values.clone())),
+                Source::Variable { .. } => None,
+            })
+            .collect()
+    }
+}
+
+/// Handle GET /export requests
+pub async fn export_handler(
+    Dataset(state): Dataset,
+    headers: HeaderMap,
+    Query(params): Query<ExportQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/export",
+        request_id = %request_id,
+        vars = %params.vars,
+        format = ?params.format,
+        "Processing export request"
+    );
+
+    match process_export_query(state, &params, &headers).await {
+        Ok(response) => {
+            info!(
+                endpoint = "/export",
+                request_id = %request_id,
+                vars = %params.vars,
+                duration_us = start_time.elapsed().as_micros() as u64,
+                "Export request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/export",
+                &request_id,
+                Some(&format!("vars={}, format={:?}", params.vars, params.format)),
+            );
+            let status = match &error {
+                RossbyError::CorruptData { .. } => StatusCode::BAD_GATEWAY,
+                RossbyError::Server { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn process_export_query(
+    state: Arc<AppState>,
+    params: &ExportQuery,
+    request_headers: &HeaderMap,
+) -> Result<Response> {
+    match params.format.as_deref().unwrap_or("netcdf") {
+        "netcdf" => {}
+        other => {
+            return Err(RossbyError::InvalidParameter {
+                param: "format".to_string(),
+                message: format!("Unsupported format: {}. Must be netcdf", other),
+            })
+        }
+    }
+    let strict = parse_flag("strict", params.strict.as_deref())?;
+    let export = plan_export(&state, params)?;
+
+    let issues = check_file(&export.file, &export.coordinates());
+    if strict && !issues.is_empty() {
+        let issues: Vec<String> = issues.iter().map(ToString::to_string).collect();
+        return Err(RossbyError::Conversion {
+            message: format!(
+                "The exported file does not follow the CF conventions: {}",
+                issues.join("; ")
+            ),
+        });
+    }
+    for issue in &issues {
+        warn!(endpoint = "/export", issue = %issue, "Exported file departs from CF");
+    }
+
+    let body = Arc::new(ExportBody::new(state, export)?);
+    let checksum = {
+        let body = body.clone();
+        tokio::task::spawn_blocking(move || body.checksum())
+            .await
+            .unwrap_or_else(|e| {
+                Err(RossbyError::Server {
+                    message: format!("Export task failed: {}", e),
+                })
+            })?
+    };
+
+    let mut response = ranged_response(
+        request_headers,
+        HeaderValue::from_static("application/x-netcdf"),
+        &checksum,
+        body.len(),
+        |range| Body::from_stream(export_stream(body, range)),
+    );
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"export.nc\""),
+    );
+    headers.insert(
+        HeaderName::from_static(CF_ISSUES_HEADER),
+        HeaderValue::from(issues.len()),
+    );
+    Ok(response)
+}
+
+/// Pre-flight estimate of the number of values an `/export` query writes
+///
+/// Returns `None` for invalid queries, which fail before writing anything.
+pub fn estimate_values(state: &AppState, query: &str) -> Option<u64> {
+    let params: ExportQuery = serde_urlencoded::from_str(query).ok()?;
+    let export = plan_export(state, &params).ok()?;
+    let file = &export.file;
+    Some(file.variables.iter().fold(0u64, |total, var| {
+        let values = var.dimensions.iter().fold(1u64, |acc, &dim| {
+            acc.saturating_mul(file.dimensions[dim].len as u64)
+        });
+        total.saturating_add(values)
+    }))
+}
+
+/// Lay out the exported file of a request
+fn plan_export(state: &AppState, params: &ExportQuery) -> Result<Export> {
+    let vars = split_variables(&params.vars);
+    if vars.is_empty() {
+        return Err(no_variables("vars"));
+    }
+
+    let mut selection = Selection::parse(state, &params.dimension_params)?;
+    if let Some(bbox) = params.bbox.as_deref() {
+        let (min_lon, min_lat, max_lon, max_lat) = parse_bbox(bbox)?;
+        let bbox = (
+            min_lon as f64,
+            min_lat as f64,
+            max_lon as f64,
+            max_lat as f64,
+        );
+        selection.add_bbox(state, "bbox", bbox)?;
+    }
+
+    // Dimensions in order of first use
+    let mut dimensions: Vec<String> = Vec::new();
+    for (position, var) in vars.iter().enumerate() {
+        if vars[..position].contains(var) {
+            return Err(RossbyError::InvalidParameter {
+                param: "vars".to_string(),
+                message: format!("Variable '{}' is listed more than once", var),
+            });
+        }
+        let var_meta = state.get_variable_metadata_checked(var)?;
+        state.get_variable_values_checked(var)?;
+        for dim in &var_meta.dimensions {
+            if !dimensions.contains(dim) {
+                dimensions.push(dim.clone());
+            }
+        }
+    }
+    let referenced = referenced_variables(state, &vars);
+    for var in &referenced {
+        for dim in &state.metadata.variables[var].dimensions {
+            if !dimensions.contains(dim) {
+                dimensions.push(dim.clone());
+            }
+        }
+    }
+    // Only the listed variables are rounded, not those they reference
+    let keepbits = params
+        .keepbits
+        .as_deref()
+        .map(|spec| KeepBits::parse(spec, &vars))
+        .transpose()?
+        .unwrap_or_default()
+        .applied(&vars);
+    let vars: Vec<String> = vars.into_iter().chain(referenced).collect();
+    if let Some(selected) = selection
+        .iter()
+        .find(|s| !dimensions.contains(&s.dimension))
+    {
+        return Err(RossbyError::InvalidParameter {
+            param: selected.param.clone(),
+            message: format!(
+                "Dimension '{}' is not a dimension of the exported variables",
+                selected.dimension
+            ),
+        });
+    }
+    let mut selected = HashMap::new();
+    for dim in &dimensions {
+        let indices = match selection.get(dim) {
+            Some(selected) => selected.resolve(state)?,
+            None => (0..state.metadata.dimensions[dim].size).collect(),
+        };
+        selected.insert(dim.clone(), indices);
+    }
+
+    // The outermost dimension of the first variable is unlimited, unless
+    // another variable has it inside
+    let outermost = state.metadata.variables[&vars[0]].dimensions.first();
+    let record_dimension = outermost
+        .filter(|outermost| {
+            vars.iter().all(|var| {
+                let dims = &state.metadata.variables[var].dimensions;
+                !dims.iter().skip(1).any(|dim| dim == *outermost)
+            })
+        })
+        .and_then(|outermost| dimensions.iter().position(|dim| dim == outermost));
+
+    let mut file = ClassicFile {
+        dimensions: dimensions
+            .iter()
+            .map(|dim| NcDimension {
+                name: export_name(dim),
+                len: selected[dim].len(),
+            })
+            .collect(),
+        record_dimension,
+        attributes: global_attributes(state, params, &dimensions, &selected),
+        variables: Vec::new(),
+    };
+    let mut sources = Vec::new();
+
+    // Coordinate variables first, then the exported variables
+    for (position, dim) in dimensions.iter().enumerate() {
+        let Some(coords) = state.metadata.coordinates.get(dim) else {
+            continue;
+        };
+        let values: Vec<f64> = selected[dim].iter().map(|&i| coords[i]).collect();
+        let mut attributes = state
+            .get_variable_metadata(dim)
+            .map(|var| export_attributes(&var.attributes, NcType::Double))
+            .unwrap_or_default();
+        if let Some((min, max)) = value_range(&values) {
+            if attributes.iter().any(|a| a.name == "actual_range") {
+                set_attribute(
+                    &mut attributes,
+                    "actual_range",
+                    NcAttributeValue::Doubles(vec![min, max]),
+                );
+            }
+        }
+        file.variables.push(NcVariable {
+            name: export_name(dim),
+            dimensions: vec![position],
+            nc_type: NcType::Double,
+            attributes,
+        });
+        sources.push(Source::Coordinate(values));
+    }
+    for var in &vars {
+        if state.metadata.coordinates.contains_key(var) {
+            continue;
+        }
+        let var_meta = state.get_variable_metadata_checked(var)?;
+        let mut attributes: Vec<NcAttribute> =
+            export_attributes(&var_meta.attributes, NcType::Float)
+                .into_iter()
+                .filter(|a| a.name != "actual_range")
+                .collect();
+        if let Some(&bits) = keepbits.get(var) {
+            set_attribute(
+                &mut attributes,
+                bitround::KEEPBITS_PARAM,
+                NcAttributeValue::Ints(vec![bits as i32]),
+            );
+        }
+        file.variables.push(NcVariable {
+            name: export_name(var),
+            dimensions: var_meta
+                .dimensions
+                .iter()
+                .map(|dim| dimensions.iter().position(|d| d == dim).unwrap_or(0))
+                .collect(),
+            nc_type: NcType::Float,
+            attributes,
+        });
+        sources.push(Source::Variable {
+            name: var.clone(),
+            dimensions: var_meta.dimensions.clone(),
+            selected: var_meta
+                .dimensions
+                .iter()
+                .map(|dim| (dim.clone(), selected[dim].clone()))
+                .collect(),
+        });
+    }
+    file.validate()?;
+    Ok(Export {
+        file,
+        sources,
+        keepbits,
+    })
+}
+
+/// Variables named by the reference attributes of the exported variables
+/// and of the coordinate variables of their dimensions, and in turn by
+/// those, that the dataset holds
+///
+/// Coordinate variables are left out, as every dimension of the file has
+/// its coordinate variable exported.
+fn referenced_variables(state: &AppState, vars: &[String]) -> Vec<String> {
+    let mut seen: Vec<String> = vars.to_vec();
+    let mut referenced = Vec::new();
+    let mut next = 0;
+    while next < seen.len() {
+        let var = state.get_variable_metadata(&seen[next]);
+        next += 1;
+        let Some(var) = var else {
+            continue;
+        };
+
+        let mut names: Vec<String> = var.dimensions.clone();
+        for attribute in REFERENCE_ATTRIBUTES {
+            let Some(AttributeValue::Text(value)) = var.attributes.get(attribute) else {
+                continue;
+            };
+            if attribute == "grid_mapping" {
+                names.extend(grid_mapping_names(value).into_iter().map(str::to_string));
+            } else {
+                names.extend(value.split_whitespace().map(str::to_string));
+            }
+        }
+        for name in names {
+            if seen.contains(&name) || state.get_variable_metadata(&name).is_none() {
+                continue;
+            }
+            if state.metadata.coordinates.contains_key(&name) {
+                seen.push(name);
+            } else if state.get_variable_values(&name).is_some() {
+                seen.push(name.clone());
+                referenced.push(name);
+            }
+        }
+    }
+    referenced
+}
+
+/// Global attributes of the exported file: those of the dataset, with the
+/// export prepended to `history` (dated when the dataset was loaded, so that
+/// the file is the same on every request), `Conventions` set if missing and
+/// the ACDD extents the dataset describes recomputed for the subset
+fn global_attributes(
+    state: &AppState,
+    params: &ExportQuery,
+    dimensions: &[String],
+    selected: &HashMap<String, Vec<usize>>,
+) -> Vec<NcAttribute> {
+    let mut attributes = export_attributes(&state.metadata.global_attributes, NcType::Double);
+
+    let mut query: BTreeMap<&str, &str> = params
+        .dimension_params
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    query.insert("vars", &params.vars);
+    if let Some(bbox) = &params.bbox {
+        query.insert("bbox", bbox);
+    }
+    if let Some(keepbits) = &params.keepbits {
+        query.insert(bitround::KEEPBITS_PARAM, keepbits);
+    }
+    let query: Vec<String> = query
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let mut history = format!(
+        "{}: rossby {} /export?{}",
+        state.loaded_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        env!("CARGO_PKG_VERSION"),
+        query.join("&")
+    );
+    if let Some(AttributeValue::Text(previous)) = state.metadata.global_attributes.get("history") {
+        history = format!("{}\n{}", history, previous);
+    }
+    set_attribute(&mut attributes, "history", NcAttributeValue::Text(history));
+    if !attributes.iter().any(|a| a.name == "Conventions") {
+        set_attribute(
+            &mut attributes,
+            "Conventions",
+            NcAttributeValue::Text(CF_CONVENTIONS.to_string()),
+        );
+    }
+
+    // Smallest and largest selected coordinate value of a dimension
+    let range = |dim: &String| {
+        let coords = state.metadata.coordinates.get(dim)?;
+        value_range(&selected[dim].iter().map(|&i| coords[i]).collect::<Vec<_>>())
+    };
+    let find = |names: &[&str]| dimensions.iter().find(|d| names.contains(&d.as_str()));
+    let mut extents = Vec::new();
+    if let Some((min, max)) = find(&LAT_NAMES).and_then(range) {
+        extents.push(("geospatial_lat_min", NcAttributeValue::Doubles(vec![min])));
+        extents.push(("geospatial_lat_max", NcAttributeValue::Doubles(vec![max])));
+    }
+    if let Some((min, max)) = find(&LON_NAMES).and_then(range) {
+        extents.push(("geospatial_lon_min", NcAttributeValue::Doubles(vec![min])));
+        extents.push(("geospatial_lon_max", NcAttributeValue::Doubles(vec![max])));
+    }
+    let time = dimensions
+        .iter()
+        .find_map(|dim| Some((time_units(state, dim)?, range(dim)?)));
+    if let Some((units, (min, max))) = time {
+        if let (Some(start), Some(end)) = (units.iso(min), units.iso(max)) {
+            extents.push(("time_coverage_start", NcAttributeValue::Text(start)));
+            extents.push(("time_coverage_end", NcAttributeValue::Text(end)));
+        }
+    }
+    for (name, value) in extents {
+        if attributes.iter().any(|a| a.name == name) {
+            set_attribute(&mut attributes, name, value);
+        }
+    }
+    attributes
+}
+
+/// Smallest and largest finite value, if any
+fn value_range(values: &[f64]) -> Option<(f64, f64)> {
+    values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold(None, |range, &v| match range {
+            None => Some((v, v)),
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+        })
+}
+
+/// Set an attribute of a list sorted by name, keeping it sorted
+fn set_attribute(attributes: &mut Vec<NcAttribute>, name: &str, value: NcAttributeValue) {
+    match attributes.binary_search_by(|a| a.name.as_str().cmp(name)) {
+        Ok(position) => attributes[position].value = value,
+        Err(position) => attributes.insert(
+            position,
+            NcAttribute {
+                name: name.to_string(),
+                value,
+            },
+        ),
+    }
+}
+
+/// Name of a dataset variable or dimension in the exported file, with the
+/// group separators of NetCDF-4 names replaced
+fn export_name(name: &str) -> String {
+    name.replace('/', "_")
+}
+
+/// Attributes in the exported file, sorted by name, with the attributes
+/// holding values of a variable in its type
+fn export_attributes(
+    attributes: &HashMap<String, AttributeValue>,
+    nc_type: NcType,
+) -> Vec<NcAttribute> {
+    let mut names: Vec<&String> = attributes.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| {
+            let numbers = |values: Vec<f64>| {
+                if nc_type == NcType::Float && VALUE_ATTRIBUTES.contains(&name.as_str()) {
+                    NcAttributeValue::Floats(values.into_iter().map(|v| v as f32).collect())
+                } else {
+                    NcAttributeValue::Doubles(values)
+                }
+            };
+            let value = match &attributes[name] {
+                AttributeValue::Text(text) => NcAttributeValue::Text(text.clone()),
+                AttributeValue::TextArray(texts) => NcAttributeValue::Text(texts.join("\n")),
+                AttributeValue::Integer(value) => numbers(vec![*value as f64]),
+                AttributeValue::UnsignedInteger(value) => numbers(vec![*value as f64]),
+                AttributeValue::Number(value) => numbers(vec![*value]),
+                AttributeValue::IntegerArray(values) => {
+                    numbers(values.iter().map(|&v| v as f64).collect())
+                }
+                AttributeValue::UnsignedIntegerArray(values) => {
+                    numbers(values.iter().map(|&v| v as f64).collect())
+                }
+                AttributeValue::NumberArray(values) => numbers(values.clone()),
+            };
+            NcAttribute {
+                name: name.clone(),
+                value,
+            }
+        })
+        .collect()
+}
+
+/// The bytes of one chunk of the exported file
+fn chunk_bytes(state: &AppState, export: &Export, chunk: Chunk) -> Result<Vec<u8>> {
+    match &export.sources[chunk.variable] {
+        Source::Coordinate(values) => Ok(encode_doubles([values[chunk.step]])),
+        Source::Variable {
+            name,
+            dimensions,
+            selected,
+        } => {
+            let values = state.get_variable_values_checked(name)?;
+            let mut selected = selected.clone();
+            if let Some(outermost) = dimensions.first() {
+                let index = selected[outermost][chunk.step];
+                selected.insert(outermost.clone(), vec![index]);
+            }
+            // Values read on demand fail the checksum pass before the
+            // response is sent, or abort the download once it is streaming
+            let mut values = checked_reads(|| values.select(dimensions, &selected))?;
+            if let Some(&bits) = export.keepbits.get(name) {
+                bitround::round_array(&mut values, bits, bitround::fill_value(state, name));
+            }
+            Ok(encode_floats(values.iter().copied()))
+        }
+    }
+}
+
+/// The bytes of an exported file: the header, then its chunks in file order
+struct ExportBody {
+    state: Arc<AppState>,
+    export: Export,
+    header: Vec<u8>,
+    chunks: Vec<Chunk>,
+    /// Offset of each chunk in the file
+    offsets: Vec<u64>,
+    len: u64,
+}
+
+impl ExportBody {
+    fn new(state: Arc<AppState>, export: Export) -> Result<Self> {
+        let header = export.file.header()?;
+        let chunks: Vec<Chunk> = export.file.chunks().collect();
+        let mut offsets = Vec::with_capacity(chunks.len());
+        let mut len = header.len() as u64;
+        for &chunk in &chunks {
+            offsets.push(len);
+            len += export.file.chunk_size(chunk);
+        }
+        Ok(Self {
+            state,
+            export,
+            header,
+            chunks,
+            offsets,
+            len,
+        })
+    }
+
+    /// Size of the file in bytes
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// The bytes from `start` on, up to `end` (exclusive) or, short of it,
+    /// to the end of the chunk that makes them about `BODY_CHUNK_BYTES`
+    fn read(&self, start: u64, end: u64) -> Result<Vec<u8>> {
+        let end = end.min(self.len);
+        let header_len = self.header.len() as u64;
+        let mut body = Vec::new();
+        let mut position = start;
+        if position < header_len {
+            let header_end = header_len.min(end);
+            body.extend_from_slice(&self.header[position as usize..header_end as usize]);
+            position = header_end;
+        }
+        // The chunk holding the position
+        let mut next = self.offsets.partition_point(|&offset| offset <= position);
+        next = next.saturating_sub(1);
+        while position < end && body.len() < BODY_CHUNK_BYTES && next < self.chunks.len() {
+            let offset = self.offsets[next];
+            let bytes = chunk_bytes(&self.state, &self.export, self.chunks[next])?;
+            let from = (position - offset) as usize;
+            let to = ((end - offset) as usize).min(bytes.len());
+            body.extend_from_slice(&bytes[from..to]);
+            position = offset + to as u64;
+            next += 1;
+        }
+        Ok(body)
+    }
+
+    /// Hex-encoded SHA-256 of the whole file
+    fn checksum(&self) -> Result<String> {
+        let mut hasher = Sha256::new();
+        let mut position = 0;
+        while position < self.len {
+            let bytes = self.read(position, self.len)?;
+            if bytes.is_empty() {
+                break;
+            }
+            position += bytes.len() as u64;
+            hasher.update(&bytes);
+        }
+        Ok(to_hex(&hasher.finalize()))
+    }
+}
+
+/// Stream a range of the exported file in body chunks of about
+/// `BODY_CHUNK_BYTES`, each read on the blocking thread pool
+fn export_stream(
+    body: Arc<ExportBody>,
+    range: Range<u64>,
+) -> impl Stream<Item = std::result::Result<Bytes, std::io::Error>> + Send {
+    stream::unfold(range.start, move |position| {
+        let body = body.clone();
+        let end = range.end;
+        async move {
+            if position >= end {
+                return None;
+            }
+            let read = tokio::task::spawn_blocking(move || body.read(position, end))
+                .await
+                .map_err(std::io::Error::other)
+                .and_then(|read| read.map_err(std::io::Error::other));
+            match read {
+                Ok(bytes) if !bytes.is_empty() => {
+                    let next = position + bytes.len() as u64;
+                    Some((Ok(Bytes::from(bytes)), next))
+                }
+                Ok(_) => None,
+                Err(e) => Some((Err(e), end)),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_attributes() {
+        let attributes = HashMap::from([
+            ("units".to_string(), AttributeValue::Text("K".to_string())),
+            ("_FillValue".to_string(), AttributeValue::Number(-999.0)),
+            ("scale_factor".to_string(), AttributeValue::Number(0.01)),
+        ]);
+        let exported = export_attributes(&attributes, NcType::Float);
+        let names: Vec<&str> = exported.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["_FillValue", "scale_factor", "units"]);
+        assert_eq!(exported[0].value, NcAttributeValue::Floats(vec![-999.0]));
+        assert_eq!(exported[1].value, NcAttributeValue::Doubles(vec![0.01]));
+
+        let exported = export_attributes(&attributes, NcType::Double);
+        assert_eq!(exported[0].value, NcAttributeValue::Doubles(vec![-999.0]));
+        assert_eq!(export_name("forecast/surface/t2m"), "forecast_surface_t2m");
+    }
+
+    #[test]
+    fn test_set_attribute() {
+        let attributes = HashMap::from([
+            (
+                "history".to_string(),
+                AttributeValue::Text("created".to_string()),
+            ),
+            (
+                "title".to_string(),
+                AttributeValue::Text("ERA5".to_string()),
+            ),
+        ]);
+        let mut exported = export_attributes(&attributes, NcType::Double);
+        set_attribute(
+            &mut exported,
+            "history",
+            NcAttributeValue::Text("exported".to_string()),
+        );
+        set_attribute(
+            &mut exported,
+            "Conventions",
+            NcAttributeValue::Text(CF_CONVENTIONS.to_string()),
+        );
+        set_attribute(
+            &mut exported,
+            "source",
+            NcAttributeValue::Doubles(vec![1.0]),
+        );
+        let names: Vec<&str> = exported.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["Conventions", "history", "source", "title"]);
+        assert_eq!(
+            exported[1].value,
+            NcAttributeValue::Text("exported".to_string())
+        );
+
+        assert_eq!(value_range(&[3.0, f64::NAN, -1.0]), Some((-1.0, 3.0)));
+        assert_eq!(value_range(&[f64::NAN]), None);
+    }
+}
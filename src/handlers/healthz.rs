@@ -0,0 +1,105 @@
+//! Health endpoint handler.
+//!
+//! Reports whether the served data passed its last background self-check
+//! (see [`crate::integrity`]), for load balancers and orchestrators that
+//! should take an instance with corrupted data out of service.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::integrity::CheckReport;
+use crate::logging::generate_request_id;
+use crate::state::AppState;
+
+/// Health response structure
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    /// "ok", or "corrupted" when the last self-check found corrupted data
+    pub status: &'static str,
+    /// Generation of the served dataset
+    pub generation: u64,
+    /// Background self-check details
+    pub self_check: SelfCheckDetails,
+}
+
+/// Self-check settings and outcome
+#[derive(Debug, Serialize)]
+pub struct SelfCheckDetails {
+    /// Whether self-checks run
+    pub enabled: bool,
+    /// Seconds between checks
+    pub interval_secs: Option<u64>,
+    /// Report of the last finished check (None = no check yet)
+    pub last: Option<CheckReport>,
+}
+
+/// Handle GET /healthz requests
+///
+/// Responds `503 Service Unavailable` when the last self-check failed, and
+/// `200 OK` otherwise, including before the first check.
+pub async fn healthz_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let request_id = generate_request_id();
+    debug!(
+        endpoint = "/healthz",
+        request_id = %request_id,
+        "Processing health request"
+    );
+
+    let last = state.self_check.last();
+    let corrupted = last.as_ref().is_some_and(|report| !report.passed);
+    let interval_secs = state.config.data.self_check_interval_secs;
+    let response = HealthResponse {
+        status: if corrupted { "corrupted" } else { "ok" },
+        generation: state.generation,
+        self_check: SelfCheckDetails {
+            enabled: interval_secs.is_some(),
+            interval_secs,
+            last,
+        },
+    };
+    let status = if corrupted {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::integrity::SourceComparison;
+    use crate::state::Metadata;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_healthz_reports_failed_check() {
+        let metadata = Metadata {
+            dimensions: HashMap::new(),
+            variables: HashMap::new(),
+            global_attributes: HashMap::new(),
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        };
+        let state = Arc::new(AppState::new(Config::default(), metadata, HashMap::new()));
+        let response = healthz_handler(State(state.clone())).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        state.self_check.record(CheckReport {
+            checked_at: "2025-07-01T00:00:00Z".to_string(),
+            generation: state.generation,
+            passed: false,
+            duration_ms: 1,
+            chunks_verified: 1,
+            cells_sampled: 0,
+            source: SourceComparison::NoFile,
+            source_error: None,
+            failures: vec!["t2m: values 0..65536 changed since loading".to_string()],
+        });
+        let response = healthz_handler(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -51,6 +51,8 @@ pub struct DatasetInfo {
     pub dimension_count: usize,
     /// Map of dimension names to sizes
     pub dimensions: Vec<(String, usize)>,
+    /// Generation of the loaded dataset
+    pub generation: u64,
     /// Approximate memory usage for dataset in bytes
     pub data_memory_bytes: usize,
 }
@@ -110,6 +112,7 @@ pub async fn heartbeat_handler(State(state): State<Arc<AppState>>) -> Json<Heart
             .iter()
             .map(|(name, dim)| (name.clone(), dim.size))
             .collect(),
+        generation: state.generation,
         data_memory_bytes: data_memory,
     };
 
@@ -143,10 +146,9 @@ pub async fn heartbeat_handler(State(state): State<Arc<AppState>>) -> Json<Heart
 fn calculate_data_memory_usage(state: &AppState) -> usize {
     let mut total_bytes = 0;
 
-    // Add up the size of each ndarray
-    for array in state.data.values() {
-        // Each element is a f32 (4 bytes)
-        total_bytes += array.len() * 4;
+    // Add up the size of each ndarray, f32 or packed 16-bit integers
+    for (_, array) in state.variable_values() {
+        total_bytes += array.memory_bytes();
     }
 
     total_bytes
@@ -312,6 +314,7 @@ mod tests {
             variables: HashMap::new(),
             global_attributes: HashMap::new(),
             coordinates: HashMap::new(),
+            groups: HashMap::new(),
         };
 
         let data = HashMap::new();
@@ -3,8 +3,8 @@
 //! Returns a PNG/JPEG image rendering of a variable over a specified region and time.
 
 use axum::{
-    extract::{Query, State},
-    http::{header, HeaderMap, StatusCode},
+    extract::Query,
+    http::{HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -15,32 +15,91 @@ use std::collections::HashMap;
 use std::io::Cursor;
 use std::sync::Arc;
 use std::time::Instant;
-use tracing::{debug, info};
-
+use tracing::{debug, info, info_span};
+
+use crate::arithmetic::{variable_metadata, VariableExpression};
+use crate::artifact::artifact_response;
+use crate::bounds::{BoundsMode, BOUNDS_HEADER};
+use crate::climatology::{self, NormalMode};
+use crate::colormaps::classes::{Classification, ClassifiedColormap, CLASS_BREAKS_HEADER};
+use crate::colormaps::clipped::{
+    count_clipped, ClippedColormap, CLIPPED_ABOVE_HEADER, CLIPPED_BELOW_HEADER,
+};
+use crate::colormaps::colormap::{COLOR_RANGE_HEADER, FLAT_FIELD_HEADER};
+use crate::colormaps::polar::{PolarView, Pole};
+use crate::colormaps::presets::{ResolvedPreset, STYLE_PRESET_HEADER};
 use crate::colormaps::{
-    self, adjust_for_dateline_crossing, handle_dateline_crossing_bbox, parse_bbox, resample_data,
-    Colormap, MapProjection,
+    self, graticule::parse_color, handle_dateline_crossing_bbox, parse_bbox, resample_data,
+    Colormap, Graticule, LatitudeScaling, MapProjection,
 };
+use crate::crs::CRS_HEADER;
+use crate::dynamics::DerivedVariable;
 use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, HorizontalField};
+use crate::filters::Smoothing;
+use crate::geometry::{lon_indices, range_indices, seam_crossing, unwrap_longitudes};
+use crate::geotiff::{self, GeoReference};
+use crate::grid_snap::{SnappedAxis, SNAPPED_BBOX_HEADER, SNAP_PARAM};
+use crate::interpolation::bicubic::{resample_separable, CubicKernel};
 use crate::logging::{generate_request_id, log_request_error};
-use crate::state::AppState;
+use crate::query::{no_variables, Dataset, Selection, SelectorInput, TimeParams, TimeStep};
+use crate::query_resolved::{QueryResolved, QUERY_RESOLVED_HEADER};
+use crate::slice_stats::MissingData;
+use crate::state::{coordinate_bounds, AppState, AttributeValue};
+use crate::uncertainty::{self, UncertaintyLevels, UncertaintyStyle};
+use crate::vertical::{interpolate_to_level, LevelType};
 
 /// Default image dimensions
-const DEFAULT_WIDTH: u32 = 800;
-const DEFAULT_HEIGHT: u32 = 600;
-
-/// Default colormap
-const DEFAULT_COLORMAP: &str = "viridis";
+pub(crate) const DEFAULT_WIDTH: u32 = 800;
+pub(crate) const DEFAULT_HEIGHT: u32 = 600;
 
 /// Default output format
 const DEFAULT_FORMAT: &str = "png";
 
+/// Names of the vertical dimensions a `level` value is looked up in
+pub(crate) const LEVEL_NAMES: [&str; 5] = ["level", "lev", "plev", "pressure", "height"];
+
+/// Vertical dimension of a variable with the given dimensions: one named
+/// in [`LEVEL_NAMES`], or else the only dimension that is neither time nor
+/// horizontal
+pub(crate) fn level_dimension(state: &AppState, dimensions: &[String]) -> Option<String> {
+    let (lat_axis, lon_axis) = find_lat_lon_axes(dimensions).unwrap_or((usize::MAX, usize::MAX));
+    let time_dim = state.resolve_dimension("time").ok();
+    let others: Vec<&String> = dimensions
+        .iter()
+        .enumerate()
+        .filter(|&(axis, dim)| {
+            axis != lat_axis && axis != lon_axis && Some(dim.as_str()) != time_dim
+        })
+        .map(|(_, dim)| dim)
+        .collect();
+    others
+        .iter()
+        .find(|dim| LEVEL_NAMES.contains(&dim.as_str()))
+        .or(match others.as_slice() {
+            [dim] => Some(dim),
+            _ => None,
+        })
+        .map(|dim| dim.to_string())
+}
+
 /// Query parameters for image endpoint
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ImageQuery {
-    /// Variable name to render
+    /// Variable name to render, or an expression of two variables (e.g. `t2m-t2m_clim`)
+    #[serde(default)]
     pub var: String,
+    /// Left operand of an explicit arithmetic expression
+    pub var_a: Option<String>,
+    /// Right operand of an explicit arithmetic expression
+    pub var_b: Option<String>,
+    /// Operator of an explicit arithmetic expression (add, sub, mul or div)
+    pub op: Option<String>,
+    /// Transform relative to the climatology (percent_normal or zscore)
+    pub mode: Option<String>,
+    /// Spatial smoothing filter, e.g. `gaussian:2` or `boxcar:3`
+    pub smooth: Option<String>,
     /// Time index (0-based)
     pub time_index: Option<usize>,
     /// Time physical value (preferred over time_index)
@@ -51,6 +110,8 @@ pub struct ImageQuery {
     pub level: Option<f64>,
     /// Raw level index
     pub __level_index: Option<usize>,
+    /// Vertical coordinate of `level`: model (native), pressure (hPa) or height (m)
+    pub level_type: Option<String>,
     /// Bounding box as "min_lon,min_lat,max_lon,max_lat"
     pub bbox: Option<String>,
     /// Image width in pixels
@@ -59,21 +120,70 @@ pub struct ImageQuery {
     pub height: Option<u32>,
     /// Colormap name (e.g., viridis, plasma, coolwarm)
     pub colormap: Option<String>,
+    /// Classified color scale as "method:count" (e.g., jenks:7)
+    pub classes: Option<String>,
+    /// Value mapped to the low end of the colormap (defaults to the slice minimum)
+    pub vmin: Option<f32>,
+    /// Value mapped to the high end of the colormap (defaults to the slice maximum)
+    pub vmax: Option<f32>,
+    /// Draw values outside the color range in distinct colors and count them
+    pub mark_clipped: Option<bool>,
+    /// Color of values below the range as hex "RRGGBB" or "RRGGBBAA"
+    pub clip_below_color: Option<String>,
+    /// Color of values above the range as hex "RRGGBB" or "RRGGBBAA"
+    pub clip_above_color: Option<String>,
     /// Interpolation method for resampling (deprecated, use resampling instead)
     pub interpolation: Option<String>,
-    /// Output format (png or jpeg)
+    /// Output format (png, jpeg or geotiff)
     pub format: Option<String>,
     /// Map centering (eurocentric, americas, pacific, or custom longitude)
     pub center: Option<String>,
     /// Allow bounding boxes that cross the dateline/prime meridian
     pub wrap_longitude: Option<bool>,
+    /// Handling of bbox coordinates outside the grid (error, clamp or wrap)
+    pub bounds: Option<String>,
     /// Upsampling/downsampling quality (auto, nearest, bilinear, bicubic)
     pub resampling: Option<String>,
     /// Whether to enhance pole regions to reduce distortion
     pub enhance_poles: Option<bool>,
+    /// Vertical scaling of latitudes (platecarree, equalarea or mercator), or
+    /// a polar stereographic view (arctic or antarctic)
+    pub projection: Option<String>,
+    /// Latitude at the edge of polar views in degrees (default 60)
+    pub lat_cutoff: Option<f64>,
+    /// Draw a latitude/longitude graticule over the image
+    pub grid: Option<bool>,
+    /// Graticule spacing in degrees (defaults to the configured spacing)
+    pub grid_spacing: Option<f64>,
+    /// Graticule line and label color as hex "RRGGBB" or "RRGGBBAA"
+    pub grid_color: Option<String>,
+    /// Graticule line width in pixels
+    pub grid_width: Option<u32>,
+    /// Whether to label graticule lines along the image edges
+    pub grid_labels: Option<bool>,
+    /// Align pixels to a reference grid derived from the native resolution,
+    /// adjusting the bbox and size (see `grid_snap`)
+    pub snap_to_grid: Option<bool>,
+    /// Show the uncertainty companion of the variable (alpha or hatch)
+    pub uncertainty: Option<String>,
+    /// Companion value shown as fully uncertain (defaults to the slice maximum)
+    pub uncertainty_max: Option<f32>,
+    /// Describe how the query was interpreted in the `X-Rossby-Query-Resolved` header
+    pub echo_params: Option<bool>,
     /// Extra fields for arbitrary dimension values and indices
     #[serde(flatten)]
-    pub extra: HashMap<String, serde_json::Value>,
+    pub extra: HashMap<String, SelectorInput>,
+}
+
+impl ImageQuery {
+    /// Time step parameters of the query
+    pub fn time_params(&self) -> TimeParams {
+        TimeParams {
+            raw_index: self.__time_index,
+            value: self.time,
+            legacy_index: self.time_index,
+        }
+    }
 }
 
 // Note: parse_bbox function now imported from colormaps::geoutil
@@ -81,58 +191,61 @@ pub struct ImageQuery {
 // Note: adjust_bbox_for_center replaced by handle_dateline_crossing_bbox from colormaps::geoutil
 
 /// Generate an image from 2D data array using specified colormap and interpolation method
-fn generate_image(
+///
+/// `value_range` holds the values mapped to the ends of the colormap, and
+/// `lat_span` holds the latitudes of the first and last data rows and is used
+/// by `scaling` to decide which latitude each image row shows. Non-finite
+/// values are drawn transparent.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn generate_image(
     data: ArrayView2<f32>,
     width: u32,
     height: u32,
     colormap: &dyn Colormap,
     resampling: &str,
+    scaling: LatitudeScaling,
+    lat_span: (f64, f64),
+    (min_val, max_val): (f32, f32),
 ) -> Result<RgbaImage> {
-    // Find min/max values for normalization
-    let mut min_val = f32::INFINITY;
-    let mut max_val = f32::NEG_INFINITY;
-
-    for &val in data.iter() {
-        if val.is_finite() {
-            min_val = min_val.min(val);
-            max_val = max_val.max(val);
-        }
-    }
-
     // Create a new image buffer
     let mut img = ImageBuffer::new(width, height);
 
-    // Get the interpolator based on the resampling method
-    let interpolator = match resampling {
-        "nearest" => crate::interpolation::get_interpolator("nearest")?,
-        "bilinear" => crate::interpolation::get_interpolator("bilinear")?,
-        "bicubic" => crate::interpolation::get_interpolator("bicubic")?,
-        "auto" => {
-            // Automatically select the best interpolation method based on the scaling factor
-            let scale_x = width as f32 / data.shape()[1] as f32;
-            let scale_y = height as f32 / data.shape()[0] as f32;
-            let scale = scale_x.max(scale_y);
-
-            if scale <= 0.5 {
-                // Downsampling by more than 2x: use bilinear to avoid aliasing
-                crate::interpolation::get_interpolator("bilinear")?
-            } else if scale <= 1.0 {
-                // Slight downsampling: use bilinear
-                crate::interpolation::get_interpolator("bilinear")?
-            } else if scale <= 2.0 {
-                // Slight upsampling: use bilinear
-                crate::interpolation::get_interpolator("bilinear")?
-            } else {
-                // Significant upsampling: use bicubic for smoother results
-                crate::interpolation::get_interpolator("bicubic")?
-            }
+    let data_height = data.shape()[0];
+    let data_width = data.shape()[1];
+    let method = resampling_method(resampling, data.shape(), width, height);
+    let color = |value: f32| {
+        if value.is_finite() {
+            colormap.map(value, min_val, max_val)
+        } else {
+            // Use transparent black for NaN/missing values
+            [0, 0, 0, 0]
         }
-        // Default to bilinear for any other value
-        _ => crate::interpolation::get_interpolator("bilinear")?,
     };
 
-    let data_height = data.shape()[0];
-    let data_width = data.shape()[1];
+    // Bicubic kernels are separable: compute each row and column kernel once
+    if method == "bicubic" && data_height >= 4 && data_width >= 4 {
+        let cols: Vec<CubicKernel> = (0..width)
+            .map(|x| {
+                let data_x = x as f64 * (data_width - 1) as f64 / (width - 1) as f64;
+                CubicKernel::at(data_x, data_width)
+            })
+            .collect();
+        let rows: Vec<CubicKernel> = (0..height)
+            .map(|y| {
+                CubicKernel::at(
+                    scaling.data_row(y, height, lat_span, data_height),
+                    data_height,
+                )
+            })
+            .collect();
+        let values = resample_separable(data, &rows, &cols);
+        for ((y, x), &value) in values.indexed_iter() {
+            img.put_pixel(x as u32, y as u32, image::Rgba(color(value)));
+        }
+        return Ok(img);
+    }
+
+    let interpolator = crate::interpolation::get_interpolator(method)?;
 
     // Flatten the 2D array for the interpolator
     let flat_data: Vec<f32> = data.iter().cloned().collect();
@@ -157,8 +270,9 @@ fn generate_image(
             // For longitude (x): direct mapping (left-to-right)
             let data_x = x as f64 * (data_width - 1) as f64 / (width - 1) as f64;
 
-            // For latitude (y): direct mapping (don't invert)
-            let data_y = y as f64 * (data_height - 1) as f64 / (height - 1) as f64;
+            // For latitude (y): direct mapping (don't invert), with rows
+            // shrunk towards the poles unless plate carrée is requested
+            let data_y = scaling.data_row(y, height, lat_span, data_height);
 
             // Perform interpolation to get the value at this pixel
             let indices = vec![data_y, data_x];
@@ -167,25 +281,55 @@ fn generate_image(
                 Err(_) => f32::NAN, // Use NaN for interpolation errors
             };
 
-            // Map value to color
-            let color = if data_value.is_finite() {
-                colormap.map(data_value, min_val, max_val)
-            } else {
-                // Use transparent black for NaN/missing values
-                [0, 0, 0, 0]
-            };
-
-            // Set pixel color
-            img.put_pixel(x, y, image::Rgba(color));
+            // Map value to color and set the pixel
+            img.put_pixel(x, y, image::Rgba(color(data_value)));
         }
     }
 
     Ok(img)
 }
 
+/// Interpolation method used to resample `data_shape` to `width` x `height`
+fn resampling_method(
+    resampling: &str,
+    data_shape: &[usize],
+    width: u32,
+    height: u32,
+) -> &'static str {
+    match resampling {
+        "nearest" => "nearest",
+        "bilinear" => "bilinear",
+        "bicubic" => "bicubic",
+        "auto" => {
+            // Automatically select the best interpolation method based on the scaling factor
+            let scale_x = width as f32 / data_shape[1] as f32;
+            let scale_y = height as f32 / data_shape[0] as f32;
+            let scale = scale_x.max(scale_y);
+
+            if scale <= 0.5 {
+                // Downsampling by more than 2x: use bilinear to avoid aliasing
+                "bilinear"
+            } else if scale <= 1.0 {
+                // Slight downsampling: use bilinear
+                "bilinear"
+            } else if scale <= 2.0 {
+                // Slight upsampling: use bilinear
+                "bilinear"
+            } else {
+                // Significant upsampling: use bicubic for smoother results
+                "bicubic"
+            }
+        }
+        // Default to bilinear for any other value
+        _ => "bilinear",
+    }
+}
+
 /// Handle GET /image requests
 pub async fn image_handler(
-    State(state): State<Arc<AppState>>,
+    Dataset(state): Dataset,
+    TimeStep(time_index): TimeStep,
+    headers: HeaderMap,
     Query(params): Query<ImageQuery>,
 ) -> Response {
     // Include all query parameters in the log for diagnostic purposes
@@ -213,7 +357,7 @@ pub async fn image_handler(
     );
 
     // Process the request
-    match generate_image_response(state.clone(), &params) {
+    match generate_image_response(state.clone(), &params, &headers) {
         Ok(response) => {
             // Log successful request
             let duration = start_time.elapsed();
@@ -222,7 +366,8 @@ pub async fn image_handler(
                 Some(bbox) => bbox.clone(),
                 None => {
                     let (min_lon, min_lat, max_lon, max_lat) = state
-                        .get_lat_lon_bounds()
+                        .get_variable_lat_lon_bounds(&params.var)
+                        .or_else(|_| state.get_lat_lon_bounds())
                         .unwrap_or((0.0, -90.0, 360.0, 90.0));
                     format!(
                         "{:.2},{:.2},{:.2},{:.2}",
@@ -231,20 +376,6 @@ pub async fn image_handler(
                 }
             };
 
-            // Determine the time index - similar logic as in generate_image_response
-            let time_index = if let Some(raw_index) = params.__time_index {
-                raw_index
-            } else if let Some(time_val) = params.time {
-                match state.find_coordinate_index_exact("time", time_val) {
-                    Ok(idx) => idx,
-                    Err(_) => state
-                        .find_coordinate_index("time", time_val)
-                        .unwrap_or_else(|_| params.time_index.unwrap_or(0)),
-                }
-            } else {
-                params.time_index.unwrap_or(0)
-            };
-
             // Get the actual time value used (if available)
             let time_value_str = if let Some(time_val) = params.time {
                 format!("{}", time_val)
@@ -309,84 +440,82 @@ pub async fn image_handler(
     }
 }
 
+/// Style preset of images of a variable drawn without style parameters:
+/// that of its standard name, for variables of the data only
+pub(crate) fn style_preset(state: &AppState, var_name: &str) -> Option<ResolvedPreset> {
+    let attributes = &state.get_variable_metadata(var_name)?.attributes;
+    let text = |name: &str| match attributes.get(name) {
+        Some(AttributeValue::Text(text)) => Some(text.as_str()),
+        _ => None,
+    };
+    state
+        .config
+        .data
+        .style_preset(text("standard_name")?, text("units"))
+}
+
 /// Helper function to generate image response
-fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<Response> {
+pub(crate) fn generate_image_response(
+    state: Arc<AppState>,
+    params: &ImageQuery,
+    request_headers: &HeaderMap,
+) -> Result<Response> {
     let operation_start = Instant::now();
 
-    // Get variable name from query
-    let var_name = params.var.clone();
+    // Get the variable, or the two variables to combine, from the query
+    let explicit = VariableExpression::from_params(
+        &state,
+        params.var_a.as_deref(),
+        params.var_b.as_deref(),
+        params.op.as_deref(),
+    )?;
+    let expression = match explicit {
+        Some(_) if !params.var.is_empty() => {
+            return Err(RossbyError::InvalidParameter {
+                param: "var".to_string(),
+                message: "Use either var or var_a, var_b and op".to_string(),
+            })
+        }
+        Some(expression) => Some(expression),
+        None if params.var.is_empty() => return Err(no_variables("var")),
+        None => VariableExpression::parse(&state, &params.var)?,
+    };
+    let var_name = match &expression {
+        Some(expression) => expression.to_string(),
+        None => params.var.clone(),
+    };
+    let derived = match &expression {
+        Some(_) => None,
+        None => DerivedVariable::parse(&state, &var_name)?,
+    };
+    let mode = params.mode.as_deref().map(NormalMode::parse).transpose()?;
+    let smoothing = params.smooth.as_deref().map(Smoothing::parse).transpose()?;
+    if let (Some(mode), true) = (mode, expression.is_some() || derived.is_some()) {
+        return Err(RossbyError::InvalidParameter {
+            param: "mode".to_string(),
+            message: format!(
+                "mode={} applies to variables, not to '{}'",
+                mode.name(),
+                var_name
+            ),
+        });
+    }
     debug!(
         var_name = %var_name,
         "Checking variable validity"
     );
 
-    // Verify variable exists
-    if !state.has_variable(&var_name) {
-        return Err(RossbyError::InvalidVariables {
-            names: vec![var_name],
-        });
-    }
-
-    // Verify variable is suitable for image rendering (must have latitude and longitude dimensions)
-    let var_meta = state.get_variable_metadata_checked(&var_name)?;
-
-    // Check for common latitude dimension names (lat, latitude)
-    let has_lat = var_meta
-        .dimensions
-        .iter()
-        .any(|d| d == "lat" || d == "latitude");
-
-    // Check for common longitude dimension names (lon, longitude)
-    let has_lon = var_meta
-        .dimensions
-        .iter()
-        .any(|d| d == "lon" || d == "longitude");
-
-    if !has_lat || !has_lon {
-        return Err(RossbyError::VariableNotSuitableForImage { name: var_name });
-    }
-
-    // Determine time index based on priority:
-    // 1. Raw index (__time_index) - most specific
-    // 2. Physical value (time) - preferred for normal use
-    // 3. Legacy time_index - deprecated but supported
-    // 4. Default to 0
-    let time_index = if let Some(raw_index) = params.__time_index {
-        // Use the raw index directly
-        raw_index
-    } else if let Some(time_val) = params.time {
-        // Convert physical time value to index
-        match state.find_coordinate_index_exact("time", time_val) {
-            Ok(idx) => idx,
-            Err(RossbyError::PhysicalValueNotFound {
-                dimension,
-                value,
-                available,
-            }) => {
-                return Err(RossbyError::PhysicalValueNotFound {
-                    dimension,
-                    value,
-                    available,
-                });
-            }
-            Err(_) => {
-                // Fall back to closest match if exact match fails
-                state.find_coordinate_index("time", time_val)?
-            }
-        }
-    } else {
-        // Fall back to legacy time_index or default
-        params.time_index.unwrap_or(0)
+    // Verify variable exists and is suitable for image rendering (must have
+    // latitude and longitude dimensions, in any order)
+    let var_meta = variable_metadata(&state, &var_name)?;
+    let (lat_axis, lon_axis) = match find_lat_lon_axes(&var_meta.dimensions) {
+        Some(axes) => axes,
+        None => return Err(RossbyError::VariableNotSuitableForImage { name: var_name }),
     };
 
-    // Check time index is in bounds
-    if time_index >= state.time_dim_size() {
-        return Err(RossbyError::IndexOutOfBounds {
-            param: "time_index".to_string(),
-            value: time_index.to_string(),
-            max: state.time_dim_size() - 1,
-        });
-    }
+    // Determine the time step (default to index 0)
+    let time = params.time_params();
+    let time_index = time.resolve(&state)?;
 
     // Get map projection (default to eurocentric)
     let projection = match params.center.as_deref().unwrap_or("eurocentric") {
@@ -421,20 +550,25 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
         }
     };
 
-    // Get longitude wrapping setting (default to false)
-    let wrap_longitude = params.wrap_longitude.unwrap_or(false);
+    // Get the coordinate arrays of the variable's horizontal dimensions
+    let lon_coords = state.get_coordinate_checked(&var_meta.dimensions[lon_axis])?;
+    let lat_coords = state.get_coordinate_checked(&var_meta.dimensions[lat_axis])?;
+
+    // Get the handling of bbox coordinates outside the grid; wrapping
+    // implies that the bbox may cross the seam of the grid
+    let bounds = BoundsMode::from_request(params.bounds.as_deref(), &state.config.data.bounds)?;
+    let wrap_longitude = params.wrap_longitude.unwrap_or(false) || bounds == BoundsMode::Wrap;
 
     // Parse bounding box (if provided)
     let (min_lon, min_lat, max_lon, max_lat) = if let Some(ref bbox) = params.bbox {
-        parse_bbox(bbox)?
+        bounds.apply_bbox(parse_bbox(bbox)?, lon_coords, lat_coords)?
     } else {
-        // Use full domain if no bbox specified
-        state.get_lat_lon_bounds()?
+        // Use the full extent of the variable's own grid if no bbox specified
+        coordinate_bounds(lon_coords, lat_coords)?
     };
 
     // Handle dateline crossing and adjust bounding box for the selected projection
-    let ((adj_min_lon, adj_min_lat, adj_max_lon, adj_max_lat), crosses_dateline) = if wrap_longitude
-    {
+    let ((adj_min_lon, adj_min_lat, adj_max_lon, adj_max_lat), _) = if wrap_longitude {
         handle_dateline_crossing_bbox(min_lon, min_lat, max_lon, max_lat, &projection)?
     } else if min_lon > max_lon {
         // If not explicitly allowing wrapping, but bbox crosses the dateline, return an error
@@ -445,14 +579,145 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
     } else {
         ((min_lon, min_lat, max_lon, max_lat), false)
     };
+    // The slice of a bbox crossing the seam of the grid takes the columns on
+    // either side of it, in eastward order
+    let crosses_dateline = adj_min_lon > adj_max_lon
+        || seam_crossing(lon_coords, adj_min_lon as f64, adj_max_lon as f64).is_some();
 
     // Get image dimensions
-    let width = params.width.unwrap_or(DEFAULT_WIDTH);
-    let height = params.height.unwrap_or(DEFAULT_HEIGHT);
-
-    // Get colormap
-    let colormap_name = params.colormap.as_deref().unwrap_or(DEFAULT_COLORMAP);
+    let mut width = params.width.unwrap_or(DEFAULT_WIDTH);
+    let mut height = params.height.unwrap_or(DEFAULT_HEIGHT);
+
+    // Get colormap: the requested one, else the preset of the variable's
+    // standard name when no style parameter is given, else the default
+    let unstyled = params.colormap.is_none()
+        && params.vmin.is_none()
+        && params.vmax.is_none()
+        && params.classes.is_none();
+    let preset = match (&expression, &derived, mode) {
+        (None, None, None) if unstyled => style_preset(&state, &var_name),
+        _ => None,
+    };
+    let colormap_name = match &preset {
+        Some(preset) => preset.colormap.as_str(),
+        None => state.config.data.colormap(params.colormap.as_deref()),
+    };
     let colormap = colormaps::get_colormap(colormap_name)?;
+    let classification = params
+        .classes
+        .as_deref()
+        .map(Classification::parse)
+        .transpose()?;
+    if let (Some(vmin), Some(vmax)) = (params.vmin, params.vmax) {
+        if vmin >= vmax {
+            return Err(RossbyError::InvalidParameter {
+                param: "vmin".to_string(),
+                message: format!("vmin ({}) must be less than vmax ({})", vmin, vmax),
+            });
+        }
+    }
+
+    // Get the colors of out-of-range values, with unset colors taken from
+    // the configuration
+    let clip_colors = if params.mark_clipped.unwrap_or(false) {
+        let defaults = &state.config.data.clip_colors;
+        let below = params.clip_below_color.as_deref();
+        let above = params.clip_above_color.as_deref();
+        Some((
+            parse_color("clip_below_color", below.unwrap_or(&defaults.below))?,
+            parse_color("clip_above_color", above.unwrap_or(&defaults.above))?,
+        ))
+    } else {
+        None
+    };
+
+    // Polar views are rendered on a disk around the pole rather than on the
+    // latitude/longitude grid, and are square unless both sides are given
+    let polar = Pole::from_projection(params.projection.as_deref())
+        .map(|pole| PolarView::new(pole, params.lat_cutoff))
+        .transpose()?;
+    if let Some(view) = polar {
+        let unsupported = [
+            ("bbox", params.bbox.is_some()),
+            (SNAP_PARAM, params.snap_to_grid.unwrap_or(false)),
+            ("grid", params.grid.unwrap_or(false)),
+        ];
+        if let Some((param, _)) = unsupported.iter().find(|(_, set)| *set) {
+            return Err(RossbyError::InvalidParameter {
+                param: param.to_string(),
+                message: format!(
+                    "{} is not supported with projection={}",
+                    param,
+                    view.pole.projection()
+                ),
+            });
+        }
+        width = params.width.or(params.height).unwrap_or(DEFAULT_HEIGHT);
+        height = params.height.unwrap_or(width);
+    }
+
+    // Get latitude scaling (default to plate carrée)
+    let scaling = match polar {
+        Some(_) => LatitudeScaling::PlateCarree,
+        None => LatitudeScaling::from_params(
+            params.projection.as_deref(),
+            params.enhance_poles.unwrap_or(false),
+        )?,
+    };
+
+    // Get graticule styling, with unset options taken from the configuration
+    let graticule = if params.grid.unwrap_or(false) {
+        let defaults = &state.config.data.grid;
+        let color = match params.grid_color.as_deref() {
+            Some(color) => parse_color("grid_color", color)?,
+            None => parse_color("grid_color", &defaults.color)?,
+        };
+        Some(Graticule::new(
+            params.grid_spacing.unwrap_or(defaults.spacing),
+            color,
+            params.grid_width.unwrap_or(defaults.width),
+            params.grid_labels.unwrap_or(defaults.labels),
+        )?)
+    } else {
+        None
+    };
+
+    // Get the uncertainty companion to show, which only plain variables have
+    let uncertainty_style = params
+        .uncertainty
+        .as_deref()
+        .map(UncertaintyStyle::parse)
+        .transpose()?;
+    let companion = match uncertainty_style {
+        Some(_) if expression.is_some() || derived.is_some() || mode.is_some() => {
+            return Err(RossbyError::InvalidParameter {
+                param: "uncertainty".to_string(),
+                message: format!(
+                    "Uncertainty applies to variables, not to '{}' or with mode",
+                    var_name
+                ),
+            })
+        }
+        Some(_) => Some(uncertainty::companion(&state, &var_name)?.ok_or_else(|| {
+            RossbyError::InvalidParameter {
+                param: "uncertainty".to_string(),
+                message: format!(
+                    "Variable '{}' has no uncertainty variable configured",
+                    var_name
+                ),
+            }
+        })?),
+        None => None,
+    };
+    if let Some(max) = params
+        .uncertainty_max
+        .filter(|max| max.is_nan() || *max <= 0.0)
+    {
+        return Err(RossbyError::InvalidParameter {
+            param: "uncertainty_max".to_string(),
+            message: format!("uncertainty_max must be positive, got {}", max),
+        });
+    }
 
     // Get resampling method (default to auto)
     // Fall back to interpolation parameter for backward compatibility
@@ -468,114 +733,200 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
         .as_deref()
         .unwrap_or(DEFAULT_FORMAT)
         .to_lowercase();
-    if format != "png" && format != "jpeg" {
+    if format != "png" && format != "jpeg" && format != "geotiff" {
         return Err(RossbyError::InvalidParameter {
             param: "format".to_string(),
-            message: "Format must be 'png' or 'jpeg'".to_string(),
+            message: "Format must be 'png', 'jpeg' or 'geotiff'".to_string(),
         });
     }
 
-    // Get the coordinate arrays for the region - try both common naming conventions
-    let lon_coords = if state.has_coordinate("lon") {
-        state.get_coordinate_checked("lon")?
+    // Shrink the bbox to whole pixels of the reference grid, which also fixes
+    // the image size
+    let snapped = if params.snap_to_grid.unwrap_or(false) {
+        if crosses_dateline {
+            return Err(RossbyError::InvalidParameter {
+                param: SNAP_PARAM.to_string(),
+                message: "Snapping is not supported for a bbox crossing the seam of the grid"
+                    .to_string(),
+            });
+        }
+        if scaling != LatitudeScaling::PlateCarree {
+            return Err(RossbyError::InvalidParameter {
+                param: SNAP_PARAM.to_string(),
+                message: "Snapping requires evenly spaced rows; it cannot be combined with \
+                          enhance_poles or another projection"
+                    .to_string(),
+            });
+        }
+        let lon = SnappedAxis::new(
+            "longitude",
+            lon_coords,
+            adj_min_lon as f64,
+            adj_max_lon as f64,
+            width,
+        )?;
+        let lat = SnappedAxis::new(
+            "latitude",
+            lat_coords,
+            adj_min_lat as f64,
+            adj_max_lat as f64,
+            height,
+        )?;
+        width = lon.pixels;
+        height = lat.pixels;
+        Some((lon, lat))
     } else {
-        state.get_coordinate_checked("longitude")?
+        None
+    };
+    let span = |coords: &[f64], axis: &SnappedAxis| {
+        let (first, last) = (coords[axis.first], coords[axis.last]);
+        (first.min(last) as f32, first.max(last) as f32)
+    };
+    let (adj_min_lon, adj_min_lat, adj_max_lon, adj_max_lat) = match &snapped {
+        Some((lon, lat)) => {
+            let (min_lon, max_lon) = span(lon_coords, lon);
+            let (min_lat, max_lat) = span(lat_coords, lat);
+            (min_lon, min_lat, max_lon, max_lat)
+        }
+        None => (adj_min_lon, adj_min_lat, adj_max_lon, adj_max_lat),
+    };
+
+    // Latitudes of the first and last rows of the data slice, selected the
+    // same way as in get_data_slice_with_dims
+    let first_lat = lat_coords
+        .iter()
+        .find(|&&lat| lat as f32 >= adj_min_lat)
+        .or(lat_coords.first());
+    let last_lat = lat_coords
+        .iter()
+        .rfind(|&&lat| lat as f32 <= adj_max_lat)
+        .or(lat_coords.last());
+    let lat_span = match (first_lat, last_lat) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => (adj_min_lat as f64, adj_max_lat as f64),
     };
 
-    let _lat_coords = if state.has_coordinate("lat") {
-        state.get_coordinate_checked("lat")?
+    // Longitudes of the first and last image columns. A dateline-crossing
+    // slice continues past the seam, so the eastern edge is unwrapped.
+    let lon_span = if crosses_dateline {
+        let columns = lon_indices(lon_coords, adj_min_lon as f64, adj_max_lon as f64);
+        let lons: Vec<f64> = columns.iter().map(|&j| lon_coords[j]).collect();
+        match unwrap_longitudes(&lons).as_slice() {
+            [first, .., last] => (*first, *last),
+            _ => (adj_min_lon as f64, adj_max_lon as f64 + 360.0),
+        }
     } else {
-        state.get_coordinate_checked("latitude")?
+        let first_lon = lon_coords
+            .iter()
+            .find(|&&lon| lon as f32 >= adj_min_lon)
+            .or(lon_coords.first());
+        let last_lon = lon_coords
+            .iter()
+            .rfind(|&&lon| lon as f32 <= adj_max_lon)
+            .or(lon_coords.last());
+        match (first_lon, last_lon) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => (adj_min_lon as f64, adj_max_lon as f64),
+        }
+    };
+
+    // GeoTIFFs are georeferenced up front, so that images without an affine
+    // transform are rejected before anything is rendered
+    let georeference = match (format.as_str(), polar) {
+        ("geotiff", Some(view)) => {
+            return Err(RossbyError::InvalidParameter {
+                param: "format".to_string(),
+                message: format!(
+                    "GeoTIFF output is not supported with projection={}",
+                    view.pole.projection()
+                ),
+            })
+        }
+        ("geotiff", None) => Some(GeoReference::new(
+            &state.crs, scaling, lon_span, lat_span, width, height,
+        )?),
+        _ => None,
     };
 
     // Extract all dimension values from the query parameters
     // This includes explicitly defined parameters like time, level
     // as well as any extra dimensions in the flattened HashMap
     let mut dim_indices = HashMap::new();
+    // Parameter that selected each dimension, for echo_params
+    let mut dim_params = HashMap::new();
 
     // Handle explicit time dimension
-    if let Some(raw_index) = params.__time_index {
-        // Raw index takes precedence
-        dim_indices.insert("time".to_string(), raw_index);
-    } else if let Some(time_val) = params.time {
-        // Physical value - convert to index
-        match state.find_coordinate_index_exact("time", time_val) {
-            Ok(idx) => {
-                dim_indices.insert("time".to_string(), idx);
-            }
-            Err(_) => {
-                // Fall back to closest match or error
-                let idx = state.find_coordinate_index("time", time_val)?;
-                dim_indices.insert("time".to_string(), idx);
-            }
-        }
-    } else if let Some(time_idx) = params.time_index {
-        // Legacy time_index
-        dim_indices.insert("time".to_string(), time_idx);
+    if time.is_given() {
+        dim_indices.insert("time".to_string(), time_index);
+        dim_params.insert(
+            "time".to_string(),
+            time.param().unwrap_or("time").to_string(),
+        );
     }
 
-    // Handle explicit level dimension
-    if let Some(raw_index) = params.__level_index {
-        dim_indices.insert("level".to_string(), raw_index);
-    } else if let Some(level_val) = params.level {
-        // Try to find with common level dimension names
-        let level_names = ["level", "lev", "plev", "pressure", "height"];
-
-        for &level_name in &level_names {
-            if let Ok(idx) = state.find_coordinate_index_exact(level_name, level_val) {
-                dim_indices.insert(level_name.to_string(), idx);
-                break;
-            } else if let Ok(idx) = state.find_coordinate_index(level_name, level_val) {
-                dim_indices.insert(level_name.to_string(), idx);
-                break;
-            }
+    // Pressure and height levels are interpolated from model levels rather
+    // than selected
+    let level_type = LevelType::parse(params.level_type.as_deref())?;
+    let target_pressure = match (level_type, params.level) {
+        (LevelType::Model, _) => None,
+        (_, Some(level)) if params.__level_index.is_none() => level_type.target_pressure(level),
+        _ => {
+            return Err(RossbyError::InvalidParameter {
+                param: "level_type".to_string(),
+                message: "Pressure and height levels must be given with 'level' \
+                          (hPa or meters) and cannot be combined with '__level_index'"
+                    .to_string(),
+            })
         }
+    };
+    if target_pressure.is_some() && companion.is_some() {
+        return Err(RossbyError::InvalidParameter {
+            param: "uncertainty".to_string(),
+            message: "Uncertainty cannot be shown on interpolated levels".to_string(),
+        });
+    }
+    if target_pressure.is_some() && crosses_dateline {
+        return Err(RossbyError::InvalidParameter {
+            param: "level_type".to_string(),
+            message: "Interpolated levels cannot be rendered over a dateline-crossing bbox"
+                .to_string(),
+        });
     }
 
-    // Process any additional dimensions from the flattened extra HashMap
-    for (key, value) in &params.extra {
-        // Skip standard parameters we've already processed
-        if [
-            "var",
-            "time_index",
-            "time",
-            "__time_index",
-            "level",
-            "__level_index",
-            "bbox",
-            "width",
-            "height",
-            "colormap",
-            "interpolation",
-            "format",
-            "center",
-            "wrap_longitude",
-            "resampling",
-            "enhance_poles",
-        ]
-        .contains(&key.as_str())
-        {
-            continue;
-        }
-
-        // Check if this is a raw index parameter (starts with __)
-        if key.starts_with("__") && key.ends_with("_index") {
-            let dim_name = key.trim_start_matches("__").trim_end_matches("_index");
-            if let Some(index) = value.as_u64() {
-                dim_indices.insert(dim_name.to_string(), index as usize);
-            }
-            continue;
+    // Handle explicit level dimension, selected along the variable's own
+    // vertical dimension whatever it is called
+    let level_dim = |param: &str| {
+        level_dimension(&state, &var_meta.dimensions).ok_or_else(|| RossbyError::InvalidParameter {
+            param: param.to_string(),
+            message: format!("Variable '{}' has no vertical dimension", var_name),
+        })
+    };
+    if let Some(raw_index) = params.__level_index {
+        let dim = level_dim("__level_index")?;
+        let size = state.metadata.dimensions.get(&dim).map_or(0, |d| d.size);
+        if raw_index >= size {
+            return Err(RossbyError::IndexOutOfBounds {
+                param: "__level_index".to_string(),
+                value: raw_index.to_string(),
+                max: size.saturating_sub(1),
+            });
         }
+        dim_indices.insert(dim.clone(), raw_index);
+        dim_params.insert(dim, "__level_index".to_string());
+    } else if let (Some(level_val), None) = (params.level, target_pressure) {
+        // Levels are values of the level coordinate, never the nearest one
+        let dim = level_dim("level")?;
+        let idx = state.find_coordinate_index_exact(&dim, level_val)?;
+        dim_indices.insert(dim.clone(), idx);
+        dim_params.insert(dim, "level".to_string());
+    }
 
-        // Otherwise treat as a physical value and try to find the corresponding dimension
-        if let Some(val) = value.as_f64() {
-            // Try with common dimension prefixes/patterns
-            let dim_name = key;
-            if let Ok(idx) = state.find_coordinate_index_exact(dim_name, val) {
-                dim_indices.insert(dim_name.to_string(), idx);
-            } else if let Ok(idx) = state.find_coordinate_index(dim_name, val) {
-                dim_indices.insert(dim_name.to_string(), idx);
-            }
+    // Process any additional dimension selectors from the flattened extra HashMap
+    for selected in Selection::from_inputs(&state, &params.extra)?.iter() {
+        let index = selected.resolve_single(&state)?;
+        if !dim_indices.contains_key(&selected.dimension) {
+            dim_indices.insert(selected.dimension.clone(), index);
+            dim_params.insert(selected.dimension.clone(), selected.param.clone());
         }
     }
 
@@ -586,35 +937,215 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
         "Using these dimension indices for slicing"
     );
 
-    // Get data slice for the specified dimensions and spatial bounds
-    let mut data = state.get_data_slice_with_dims(
-        &var_name,
-        adj_min_lon,
-        adj_min_lat,
-        adj_max_lon,
-        adj_max_lat,
-        &dim_indices,
-    )?;
+    let bbox = (
+        adj_min_lon as f64,
+        adj_min_lat as f64,
+        adj_max_lon as f64,
+        adj_max_lat as f64,
+    );
+    let extract_surface = |name: &str| match target_pressure {
+        Some(target) => interpolate_to_level(&state, name, &dim_indices, target),
+        None => HorizontalField::from_state(&state, name, &dim_indices, None),
+    };
+    // The whole surface is kept where it is computed anyway, for class breaks
+    let (data, slice_stats, surface) = match (&expression, &derived, target_pressure, mode) {
+        (_, _, Some(_), Some(_)) => {
+            return Err(RossbyError::InvalidParameter {
+                param: "mode".to_string(),
+                message: "mode cannot be combined with interpolated levels".to_string(),
+            })
+        }
+        (None, None, None, Some(mode)) => {
+            let _stage = info_span!("climatology").entered();
+            // Transform the whole surface, then keep the requested region
+            let surface = climatology::field(&state, &var_name, mode, &dim_indices)?;
+            let stats = surface.stats();
+            (surface.crop(bbox).values, stats, Some(surface))
+        }
+        (Some(expression), _, _, _) => {
+            let _stage = info_span!("extract").entered();
+            // Combine the whole surfaces, then keep the requested region
+            let surface = expression.field(extract_surface)?;
+            let stats = surface.stats();
+            (surface.crop(bbox).values, stats, Some(surface))
+        }
+        (None, Some(derived), _, _) => {
+            let _stage = info_span!("derive").entered();
+            // Differentiate the whole surfaces, then keep the requested region
+            let surface = derived.field(extract_surface)?;
+            let stats = surface.stats();
+            (surface.crop(bbox).values, stats, Some(surface))
+        }
+        (None, None, Some(target), _) => {
+            let _stage = info_span!("interpolate").entered();
+            // Interpolate the whole surface, then keep the requested region
+            let surface = interpolate_to_level(&state, &var_name, &dim_indices, target)?;
+            let stats = surface.stats();
+            (surface.crop(bbox).values, stats, Some(surface))
+        }
+        (None, None, None, None) => {
+            let _stage = info_span!("extract").entered();
+            // Get data slice for the specified dimensions and spatial bounds,
+            // with fill values and out-of-range values drawn as missing
+            let mut data = state.get_data_slice_with_dims(
+                &var_name,
+                adj_min_lon,
+                adj_min_lat,
+                adj_max_lon,
+                adj_max_lat,
+                &dim_indices,
+            )?;
+            MissingData::for_variable(state.get_variable_metadata_checked(&var_name)?)
+                .mask(data.iter_mut());
+            let stats = state
+                .slice_stats
+                .get_or_compute(&state, &var_name, &dim_indices)?;
+            (data, stats, None)
+        }
+    };
 
-    // Handle dateline crossing by duplicating data if needed
-    let mut _adjusted_lon_coords = lon_coords.to_vec();
-    if crosses_dateline && !data.is_empty() {
-        // Adjust the data array to handle dateline crossing
-        // Make sure we're using safe handling with proper error checking
-        match adjust_for_dateline_crossing(&data.view(), lon_coords, crosses_dateline) {
-            Ok((new_data, new_lon_coords)) => {
-                data = new_data;
-                _adjusted_lon_coords = new_lon_coords;
+    // Smoothing takes in the neighbours of the region, so the whole surface
+    // is smoothed and the region kept afterwards
+    let (mut data, slice_stats, surface) = match (smoothing, surface) {
+        (Some(smoothing), surface) => {
+            let mut surface = match surface {
+                Some(surface) => surface,
+                None => {
+                    let _stage = info_span!("extract").entered();
+                    HorizontalField::from_state(&state, &var_name, &dim_indices, None)?
+                }
+            };
+            let _stage = info_span!("smooth").entered();
+            smoothing.apply(&mut surface.values, &[0, 1]);
+            let stats = surface.stats();
+            (surface.crop(bbox).values, stats, Some(surface))
+        }
+        (None, surface) => (data, slice_stats, surface),
+    };
+
+    // Scale colors to the range of the whole slice, so that every region of
+    // the same slice shares one color scale and matches /stats. A slice
+    // without valid values has nothing to draw.
+    let value_range = slice_stats.value_range(&var_name)?;
+    let value_range = (value_range.0 as f32, value_range.1 as f32);
+    // Anomalies are centered on normal, so diverging colormaps split there
+    let value_range = match mode {
+        Some(mode) => {
+            let center = mode.center();
+            let spread = (value_range.0 - center)
+                .abs()
+                .max((value_range.1 - center).abs());
+            (center - spread, center + spread)
+        }
+        None => value_range,
+    };
+    // Presets fix the ends of the range, unless that leaves it empty for
+    // this slice
+    let value_range = match &preset {
+        Some(preset) => {
+            let range = (
+                preset.vmin.map_or(value_range.0, |v| v as f32),
+                preset.vmax.map_or(value_range.1, |v| v as f32),
+            );
+            if range.0 <= range.1 {
+                range
+            } else {
+                value_range
             }
-            Err(e) => {
-                eprintln!("Warning: Failed to adjust for dateline crossing: {}", e);
-                // Continue with the original data - better to show something than error out
+        }
+        None => value_range,
+    };
+    // Explicit ends of the range take precedence
+    let value_range = (
+        params.vmin.unwrap_or(value_range.0),
+        params.vmax.unwrap_or(value_range.1),
+    );
+    if value_range.0 > value_range.1 {
+        return Err(RossbyError::InvalidParameter {
+            param: if params.vmin.is_some() {
+                "vmin"
+            } else {
+                "vmax"
             }
+            .to_string(),
+            message: format!(
+                "Color range is empty: {} is above {}",
+                value_range.0, value_range.1
+            ),
+        });
+    }
+
+    // Classes are likewise broken on the distribution of the whole slice,
+    // and polar views sample the whole surface
+    let surface = match surface {
+        None if classification.is_some() || polar.is_some() => {
+            let _stage = info_span!("extract").entered();
+            Some(HorizontalField::from_state(
+                &state,
+                &var_name,
+                &dim_indices,
+                None,
+            )?)
+        }
+        surface => surface,
+    };
+    let mut class_breaks = None;
+    let colormap: Box<dyn Colormap> = match classification {
+        Some(classification) => {
+            let _stage = info_span!("classify").entered();
+            let values = surface
+                .iter()
+                .flat_map(|surface| surface.values.iter().copied());
+            let classified = ClassifiedColormap::new(colormap, classification.breaks(values));
+            class_breaks = Some(classified.header_value());
+            Box::new(classified)
+        }
+        None => colormap,
+    };
+
+    // Values outside the range are counted on the grid cells of the region,
+    // before they are duplicated or resampled
+    let mut clipped = None;
+    let colormap: Box<dyn Colormap> = match clip_colors {
+        Some((below, above)) => {
+            clipped = Some(count_clipped(data.iter().copied(), value_range));
+            Box::new(ClippedColormap::new(colormap, below, above))
+        }
+        None => colormap,
+    };
+
+    // The companion is sliced like the data (or taken whole for polar
+    // views), scaled from zero to the most uncertain value of the whole slice
+    let mut uncertainty_max = None;
+    let mut uncertainty_data = None;
+    if let Some(companion) = companion {
+        let _stage = info_span!("extract").entered();
+        uncertainty_max = Some(match params.uncertainty_max {
+            Some(max) => max,
+            None => state
+                .slice_stats
+                .get_or_compute(&state, companion, &dim_indices)?
+                .max
+                .unwrap_or(0.0) as f32,
+        });
+        if polar.is_none() {
+            let mut values = state.get_data_slice_with_dims(
+                companion,
+                adj_min_lon,
+                adj_min_lat,
+                adj_max_lon,
+                adj_max_lat,
+                &dim_indices,
+            )?;
+            MissingData::for_variable(state.get_variable_metadata_checked(companion)?)
+                .mask(values.iter_mut());
+            uncertainty_data = Some(values);
         }
     }
 
-    // Resample data if needed (when the target resolution differs significantly from the data resolution)
-    if resampling != "none" {
+    // Resample data if needed (when the target resolution differs significantly from the data resolution).
+    // Snapped images sample grid points exactly, which resampling would shift.
+    if resampling != "none" && snapped.is_none() && polar.is_none() {
         // Check if we need to resample
         let data_width = data.shape()[1];
         let data_height = data.shape()[0];
@@ -628,7 +1159,11 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
             let target_width = (width as f32 * 0.8).min(data_width as f32) as usize;
             let target_height = (height as f32 * 0.8).min(data_height as f32) as usize;
 
+            let _stage = info_span!("resample").entered();
             data = resample_data(&data.view(), target_width, target_height)?;
+            if let Some(values) = uncertainty_data.as_mut() {
+                *values = resample_data(&values.view(), target_width, target_height)?;
+            }
         }
     }
 
@@ -642,7 +1177,50 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
     );
 
     let image_gen_start = Instant::now();
-    let img = generate_image(data.view(), width, height, colormap.as_ref(), resampling)?;
+    let render_stage = info_span!("render").entered();
+    let mut img = match (polar, &surface) {
+        (Some(view), Some(surface)) => {
+            view.render(surface, width, height, colormap.as_ref(), value_range)
+        }
+        _ => generate_image(
+            data.view(),
+            width,
+            height,
+            colormap.as_ref(),
+            resampling,
+            scaling,
+            lat_span,
+            value_range,
+        )?,
+    };
+
+    if let (Some(style), Some(companion), Some(max)) =
+        (uncertainty_style, companion, uncertainty_max)
+    {
+        let levels = match (polar, &uncertainty_data) {
+            (Some(view), _) => {
+                let field = HorizontalField::from_state(&state, companion, &dim_indices, None)?;
+                view.render(&field, width, height, &UncertaintyLevels, (0.0, max))
+            }
+            (None, Some(values)) => generate_image(
+                values.view(),
+                width,
+                height,
+                &UncertaintyLevels,
+                resampling,
+                scaling,
+                lat_span,
+                (0.0, max),
+            )?,
+            (None, None) => unreachable!("the companion of cylindrical views is sliced"),
+        };
+        style.apply(&mut img, &levels);
+    }
+
+    if let Some(graticule) = &graticule {
+        graticule.draw(&mut img, lon_span, lat_span, scaling)?;
+    }
+    render_stage.exit();
 
     let image_gen_duration = image_gen_start.elapsed();
     debug!(
@@ -650,9 +1228,6 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
         "Image generation completed"
     );
 
-    // Note: Pole enhancement feature is not yet implemented
-    // This will be added in a future update
-
     // Encode the image to the specified format
     debug!(
         format = %format,
@@ -660,6 +1235,7 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
     );
 
     let encoding_start = Instant::now();
+    let encode_stage = info_span!("encode").entered();
     let mut buffer = Cursor::new(Vec::new());
 
     match format.as_str() {
@@ -675,9 +1251,14 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
                     message: format!("Failed to encode JPEG: {}", e),
                 })?;
         }
+        "geotiff" => {
+            let georeference = georeference.as_ref().expect("georeferenced above");
+            buffer = Cursor::new(geotiff::encode(&img, georeference)?);
+        }
         _ => unreachable!(), // We've already validated the format
     }
 
+    encode_stage.exit();
     let encoding_duration = encoding_start.elapsed();
     debug!(
         format = %format,
@@ -685,18 +1266,12 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
         "Image encoded successfully"
     );
 
-    // Set appropriate headers
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        match format.as_str() {
-            "png" => "image/png",
-            "jpeg" => "image/jpeg",
-            _ => unreachable!(),
-        }
-        .parse()
-        .unwrap(),
-    );
+    let content_type = HeaderValue::from_static(match format.as_str() {
+        "png" => "image/png",
+        "jpeg" => "image/jpeg",
+        "geotiff" => "image/tiff",
+        _ => unreachable!(),
+    });
 
     // Log overall processing time
     let total_duration = operation_start.elapsed();
@@ -711,8 +1286,78 @@ fn generate_image_response(state: Arc<AppState>, params: &ImageQuery) -> Result<
         "Image response generated"
     );
 
-    // Return the image
-    Ok((StatusCode::OK, headers, buffer.into_inner()).into_response())
+    // Return the image, resumable via Range
+    let _stage = info_span!("serialize").entered();
+    let mut response = artifact_response(request_headers, content_type, buffer.into_inner());
+    response
+        .headers_mut()
+        .insert(BOUNDS_HEADER, HeaderValue::from_static(bounds.as_str()));
+    if snapped.is_some() {
+        let bbox = format!(
+            "{},{},{},{}",
+            adj_min_lon, adj_min_lat, adj_max_lon, adj_max_lat
+        );
+        if let Ok(value) = HeaderValue::from_str(&bbox) {
+            response.headers_mut().insert(SNAPPED_BBOX_HEADER, value);
+        }
+    }
+    if let Some(value) = class_breaks.and_then(|breaks| HeaderValue::from_str(&breaks).ok()) {
+        response.headers_mut().insert(CLASS_BREAKS_HEADER, value);
+    }
+    if let Some((below, above)) = clipped {
+        let headers = response.headers_mut();
+        headers.insert(CLIPPED_BELOW_HEADER, HeaderValue::from(below));
+        headers.insert(CLIPPED_ABOVE_HEADER, HeaderValue::from(above));
+    }
+    let rendered_crs = match polar {
+        Some(view) => state.crs.is_wgs84().then(|| view.pole.crs()),
+        None => state.crs.rendered(scaling),
+    };
+    if let Some(value) = rendered_crs.and_then(|crs| HeaderValue::from_str(crs).ok()) {
+        response.headers_mut().insert(CRS_HEADER, value);
+    }
+    let range = format!("{},{}", value_range.0, value_range.1);
+    if let Ok(value) = HeaderValue::from_str(&range) {
+        response.headers_mut().insert(COLOR_RANGE_HEADER, value);
+    }
+    if let Some(value) = preset
+        .as_ref()
+        .and_then(|preset| HeaderValue::from_str(&preset.standard_name).ok())
+    {
+        response.headers_mut().insert(STYLE_PRESET_HEADER, value);
+    }
+    // A constant field (or an explicit range of one value) is drawn in the
+    // middle color of the colormap, which would otherwise pass for data
+    if value_range.0 == value_range.1 {
+        if let Ok(value) = HeaderValue::from_str(&value_range.0.to_string()) {
+            response.headers_mut().insert(FLAT_FIELD_HEADER, value);
+        }
+    }
+    if params.echo_params.unwrap_or(false) {
+        let mut resolved = QueryResolved::new();
+        for (dimension, &index) in &dim_indices {
+            let param = dim_params.get(dimension).map(String::as_str);
+            resolved.dimension(&state, dimension, param, &[index]);
+        }
+        let bbox_param = params.bbox.as_ref().map(|_| "bbox");
+        let columns = lon_indices(lon_coords, adj_min_lon as f64, adj_max_lon as f64);
+        let rows = range_indices(lat_coords, adj_min_lat as f64, adj_max_lat as f64);
+        resolved.dimension(&state, &var_meta.dimensions[lon_axis], bbox_param, &columns);
+        resolved.dimension(&state, &var_meta.dimensions[lat_axis], bbox_param, &rows);
+        let method = resampling_method(resampling, data.shape(), width, height);
+        resolved.interpolation = Some(method.to_string());
+        resolved.bounds = Some(bounds.as_str());
+        resolved.bbox = Some([
+            adj_min_lon as f64,
+            adj_min_lat as f64,
+            adj_max_lon as f64,
+            adj_max_lat as f64,
+        ]);
+        if let Some(value) = resolved.header_value() {
+            response.headers_mut().insert(QUERY_RESOLVED_HEADER, value);
+        }
+    }
+    Ok(response)
 }
 
 #[cfg(test)]
@@ -799,7 +1444,17 @@ mod tests {
 
         // Generate a 3x3 image with this data
         let colormap = colormaps::get_colormap("viridis").unwrap();
-        let img = generate_image(data.view(), 3, 3, colormap.as_ref(), "nearest").unwrap();
+        let img = generate_image(
+            data.view(),
+            3,
+            3,
+            colormap.as_ref(),
+            "nearest",
+            LatitudeScaling::PlateCarree,
+            (-10.0, 10.0),
+            (1.0, 9.0),
+        )
+        .unwrap();
 
         // Get the pixel values to check orientation
         let top_left = img.get_pixel(0, 0);
@@ -825,4 +1480,269 @@ mod tests {
         assert!(intensity(&bottom_left) < intensity(&bottom_right)); // West to East increases (direct x mapping)
         assert!(intensity(&top_right) < intensity(&bottom_right)); // South to North increases (direct y mapping)
     }
+
+    #[test]
+    fn test_separable_bicubic_rendering() {
+        let data = ndarray::Array2::from_shape_fn((5, 8), |(y, x)| {
+            (y as f32 * 1.3 + x as f32 * 0.7).sin() * 10.0
+        });
+        let flat: Vec<f32> = data.iter().cloned().collect();
+        let colormap = colormaps::get_colormap("viridis").unwrap();
+        let (width, height, lat_span, range) = (23, 17, (-60.0, 60.0), (-10.0, 10.0));
+        let scaling = LatitudeScaling::EqualArea;
+
+        let img = generate_image(
+            data.view(),
+            width,
+            height,
+            colormap.as_ref(),
+            "bicubic",
+            scaling,
+            lat_span,
+            range,
+        )
+        .unwrap();
+
+        // Every pixel matches interpolating it on its own
+        let interpolator = crate::interpolation::get_interpolator("bicubic").unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let data_x = x as f64 * 7.0 / (width - 1) as f64;
+                let data_y = scaling.data_row(y, height, lat_span, 5);
+                let value = interpolator
+                    .interpolate(&flat, &[5, 8], &[data_y, data_x])
+                    .unwrap();
+                let expected = colormap.map(value, range.0, range.1);
+                assert_eq!(img.get_pixel(x, y).0, expected, "pixel ({}, {})", x, y);
+            }
+        }
+        assert_eq!(resampling_method("auto", &[5, 8], 23, 17), "bicubic");
+        assert_eq!(resampling_method("auto", &[5, 8], 8, 5), "bilinear");
+    }
+
+    #[tokio::test]
+    async fn test_axis_order_independent_rendering() {
+        use crate::config::Config;
+        use crate::state::{Dimension, Metadata, Variable};
+        use axum::body::to_bytes;
+        use ndarray::{Array, IxDyn};
+
+        // The same field stored as (time, lat, lon) and as (lon, lat, time)
+        let render = |layout: [&str; 3]| {
+            let sizes = HashMap::from([("time", 2), ("lat", 3), ("lon", 4)]);
+            let canonical = Array::from_shape_fn(IxDyn(&[2, 3, 4]), |idx| {
+                (100 * idx[0] + 10 * idx[1] + idx[2]) as f32
+            });
+            let order: Vec<usize> = layout
+                .iter()
+                .map(|dim| {
+                    ["time", "lat", "lon"]
+                        .iter()
+                        .position(|d| d == dim)
+                        .unwrap()
+                })
+                .collect();
+            let data = canonical
+                .permuted_axes(order)
+                .as_standard_layout()
+                .to_owned();
+
+            let metadata = Metadata {
+                global_attributes: HashMap::new(),
+                dimensions: sizes
+                    .iter()
+                    .map(|(&name, &size)| {
+                        let dimension = Dimension {
+                            name: name.to_string(),
+                            size,
+                            is_unlimited: false,
+                        };
+                        (name.to_string(), dimension)
+                    })
+                    .collect(),
+                variables: HashMap::from([(
+                    "t".to_string(),
+                    Variable {
+                        name: "t".to_string(),
+                        dimensions: layout.iter().map(|d| d.to_string()).collect(),
+                        shape: layout.iter().map(|d| sizes[d]).collect(),
+                        attributes: HashMap::new(),
+                        dtype: "f32".to_string(),
+                    },
+                )]),
+                coordinates: HashMap::from([
+                    ("time".to_string(), vec![0.0, 1.0]),
+                    ("lat".to_string(), vec![-10.0, 0.0, 10.0]),
+                    ("lon".to_string(), vec![0.0, 10.0, 20.0, 30.0]),
+                ]),
+                groups: HashMap::new(),
+            };
+            let state = Arc::new(AppState::new(
+                Config::default(),
+                metadata,
+                HashMap::from([("t".to_string(), data)]),
+            ));
+            let params: ImageQuery =
+                serde_urlencoded::from_str("var=t&time=1&width=8&height=6").unwrap();
+            generate_image_response(state, &params, &HeaderMap::new()).unwrap()
+        };
+
+        let standard = to_bytes(render(["time", "lat", "lon"]).into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let permuted = to_bytes(render(["lon", "lat", "time"]).into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(standard, permuted);
+    }
+
+    #[tokio::test]
+    async fn test_staggered_grid_default_bbox() {
+        use crate::config::Config;
+        use crate::state::{Dimension, Metadata, Variable};
+        use axum::body::to_bytes;
+        use ndarray::{Array, IxDyn};
+
+        // `u` lies on a grid staggered by half a cell from `lat`/`lon`
+        let sizes = [("lat", 3), ("lon", 4), ("latitude", 3), ("longitude", 4)];
+        let variable = |name: &str, dims: [&str; 2]| Variable {
+            name: name.to_string(),
+            dimensions: dims.map(String::from).to_vec(),
+            shape: vec![3, 4],
+            attributes: HashMap::new(),
+            dtype: "f32".to_string(),
+        };
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: sizes
+                .iter()
+                .map(|&(name, size)| {
+                    let dimension = Dimension {
+                        name: name.to_string(),
+                        size,
+                        is_unlimited: false,
+                    };
+                    (name.to_string(), dimension)
+                })
+                .collect(),
+            variables: HashMap::from([
+                ("t".to_string(), variable("t", ["lat", "lon"])),
+                ("u".to_string(), variable("u", ["latitude", "longitude"])),
+            ]),
+            coordinates: HashMap::from([
+                ("lat".to_string(), vec![-10.0, 0.0, 10.0]),
+                ("lon".to_string(), vec![0.0, 10.0, 20.0, 30.0]),
+                ("latitude".to_string(), vec![-5.0, 5.0, 15.0]),
+                ("longitude".to_string(), vec![5.0, 15.0, 25.0, 35.0]),
+            ]),
+            groups: HashMap::new(),
+        };
+        let values = Array::from_shape_fn(IxDyn(&[3, 4]), |i| (10 * i[0] + i[1]) as f32);
+        let state = Arc::new(AppState::new(
+            Config::default(),
+            metadata,
+            HashMap::from([("t".to_string(), values.clone()), ("u".to_string(), values)]),
+        ));
+        assert_eq!(
+            state.get_variable_lat_lon_bounds("u").unwrap(),
+            (5.0, -5.0, 35.0, 15.0)
+        );
+
+        let render = |query: &str| {
+            let params: ImageQuery = serde_urlencoded::from_str(query).unwrap();
+            generate_image_response(state.clone(), &params, &HeaderMap::new()).unwrap()
+        };
+        let default = to_bytes(render("var=u&width=8&height=6").into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let own_grid = to_bytes(
+            render("var=u&width=8&height=6&bbox=5,-5,35,15").into_body(),
+            usize::MAX,
+        )
+        .await
+        .unwrap();
+        assert_eq!(default, own_grid);
+    }
+
+    #[tokio::test]
+    async fn test_flat_and_missing_fields() {
+        use crate::config::Config;
+        use crate::state::{Dimension, Metadata, Variable};
+        use axum::body::to_bytes;
+        use ndarray::{Array, IxDyn};
+
+        // A constant first time step and an all-missing second one
+        let sizes = HashMap::from([("time", 2), ("lat", 3), ("lon", 4)]);
+        let data =
+            Array::from_shape_fn(
+                IxDyn(&[2, 3, 4]),
+                |idx| {
+                    if idx[0] == 0 {
+                        5.0
+                    } else {
+                        f32::NAN
+                    }
+                },
+            );
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: sizes
+                .iter()
+                .map(|(&name, &size)| {
+                    let dimension = Dimension {
+                        name: name.to_string(),
+                        size,
+                        is_unlimited: false,
+                    };
+                    (name.to_string(), dimension)
+                })
+                .collect(),
+            variables: HashMap::from([(
+                "t".to_string(),
+                Variable {
+                    name: "t".to_string(),
+                    dimensions: vec!["time".to_string(), "lat".to_string(), "lon".to_string()],
+                    shape: vec![2, 3, 4],
+                    attributes: HashMap::new(),
+                    dtype: "f32".to_string(),
+                },
+            )]),
+            coordinates: HashMap::from([
+                ("time".to_string(), vec![0.0, 1.0]),
+                ("lat".to_string(), vec![-10.0, 0.0, 10.0]),
+                ("lon".to_string(), vec![0.0, 10.0, 20.0, 30.0]),
+            ]),
+            groups: HashMap::new(),
+        };
+        let state = Arc::new(AppState::new(
+            Config::default(),
+            metadata,
+            HashMap::from([("t".to_string(), data)]),
+        ));
+
+        let params: ImageQuery =
+            serde_urlencoded::from_str("var=t&time=0&width=8&height=6").unwrap();
+        let response = generate_image_response(state.clone(), &params, &HeaderMap::new()).unwrap();
+        assert_eq!(response.headers()[FLAT_FIELD_HEADER], "5");
+        assert_eq!(response.headers()[COLOR_RANGE_HEADER], "5,5");
+        let png = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let img = image::load_from_memory(&png).unwrap().to_rgba8();
+        let middle = colormaps::Viridis.map_normalized(0.5);
+        assert!(img.pixels().any(|pixel| pixel.0 == middle));
+        assert!(img
+            .pixels()
+            .all(|pixel| pixel.0 == middle || pixel.0 == [0, 0, 0, 0]));
+
+        let params: ImageQuery =
+            serde_urlencoded::from_str("var=t&time=0&width=8&height=6&vmin=0&vmax=10").unwrap();
+        let response = generate_image_response(state.clone(), &params, &HeaderMap::new()).unwrap();
+        assert!(response.headers().get(FLAT_FIELD_HEADER).is_none());
+
+        let params: ImageQuery =
+            serde_urlencoded::from_str("var=t&time=1&width=8&height=6").unwrap();
+        assert!(matches!(
+            generate_image_response(state, &params, &HeaderMap::new()),
+            Err(RossbyError::NoValidData { .. })
+        ));
+    }
 }
@@ -0,0 +1,519 @@
+//! Lagged correlation endpoint handler.
+//!
+//! Correlates the area-weighted mean series of two variables along a
+//! dimension (usually time) at a range of lags, for teleconnection analyses
+//! such as the response of rainfall to an ENSO index, and builds lead/lag
+//! composite maps: the mean anomaly of the second variable at a lag from
+//! the steps where the index series of the first is unusually high (or
+//! low). At lag `k`, the first variable at step `t` is paired with the
+//! second at step `t + k`, so positive lags have the second variable
+//! following the first. Fields are extracted one slice at a time, and every
+//! slice at most once per pass.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ndarray::{Array2, Zip};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, info_span};
+
+use crate::colormaps::parse_bbox;
+use crate::error::{Result, RossbyError};
+use crate::field::HorizontalField;
+use crate::handlers::correlate::{
+    area_weighted_mean, render_png, symmetric_range, CoMoments, PairSelection,
+};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::AppState;
+
+/// Most lags correlated in one request
+const MAX_LAGS: usize = 1001;
+
+/// Most lags composited in one request, each holding a field
+const MAX_COMPOSITE_LAGS: usize = 32;
+
+/// Default threshold of composite events, in standard deviations of the index
+const DEFAULT_THRESHOLD: f64 = 1.0;
+
+/// Query parameters for the lagged correlation endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct LagCorrQuery {
+    /// Variable whose area mean is the index series
+    pub var_a: String,
+    /// Variable correlated with the index, or composited
+    pub var_b: String,
+    /// Lags in steps of the compared dimension: a range like `-10..10`, a
+    /// comma-separated list or a single lag (default 0)
+    #[serde(default)]
+    pub lags: Option<String>,
+    /// Dimension along which the variables are lagged (default time)
+    #[serde(default)]
+    pub dim: Option<String>,
+    /// Result (series correlations or composite maps; default series)
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Threshold of composite events in standard deviations of the index,
+    /// above (positive) or below (negative) its mean (default 1)
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    /// Region averaged into the index series, as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Region of the second variable (default: that of `bbox`)
+    #[serde(default)]
+    pub bbox_b: Option<String>,
+    /// Output format of a composite map (json or png; default json)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Colormap name for `format=png`
+    #[serde(default)]
+    pub colormap: Option<String>,
+    /// Image width in pixels for `format=png`
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Image height in pixels for `format=png`
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Dimension selectors: a selection of the lagged dimension (default:
+    /// all of it) and single slices of the other non-horizontal dimensions
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Parse the `lags` parameter: `a..b` (inclusive), a list, or a single lag
+pub fn parse_lags(value: &str) -> Result<Vec<i64>> {
+    let invalid = |message: String| RossbyError::InvalidParameter {
+        param: "lags".to_string(),
+        message,
+    };
+    let parse = |text: &str| {
+        text.trim()
+            .parse::<i64>()
+            .map_err(|_| invalid(format!("Invalid lag: {}", text)))
+    };
+    let lags: Vec<i64> = match value.split_once("..") {
+        Some((first, last)) => {
+            let (first, last) = (parse(first)?, parse(last)?);
+            if first > last {
+                return Err(invalid(format!(
+                    "Lag range {}..{} is empty; the first lag must not exceed the last",
+                    first, last
+                )));
+            }
+            if last.abs_diff(first) >= MAX_LAGS as u64 {
+                return Err(invalid(format!(
+                    "At most {} lags can be computed",
+                    MAX_LAGS
+                )));
+            }
+            (first..=last).collect()
+        }
+        None => value.split(',').map(parse).collect::<Result<Vec<_>>>()?,
+    };
+    if lags.len() > MAX_LAGS {
+        return Err(invalid(format!(
+            "At most {} lags can be computed",
+            MAX_LAGS
+        )));
+    }
+    Ok(lags)
+}
+
+/// Co-moments of a series and another shifted by a lag
+///
+/// Pairs `a[t]` with `b[t + lag]`; pairs outside either series, or with a
+/// missing value, are skipped.
+pub fn lagged_moments(a: &[f64], b: &[f64], lag: i64) -> CoMoments {
+    let mut moments = CoMoments::default();
+    for (t, &a) in a.iter().enumerate() {
+        let Some(&b) = usize::try_from(t as i64 + lag).ok().and_then(|s| b.get(s)) else {
+            continue;
+        };
+        if a.is_finite() && b.is_finite() {
+            moments.push(a, b);
+        }
+    }
+    moments
+}
+
+/// Steps of an index series beyond a threshold in standard deviations
+///
+/// A positive threshold selects steps at or above `mean + threshold * std`,
+/// a negative one steps at or below it. Returns the mean and sample
+/// standard deviation of the finite values with the steps, or None with
+/// fewer than two finite values.
+pub fn composite_events(index: &[f64], threshold: f64) -> Option<(f64, f64, Vec<usize>)> {
+    let finite: Vec<f64> = index.iter().copied().filter(|v| v.is_finite()).collect();
+    if finite.len() < 2 {
+        return None;
+    }
+    let mean = finite.iter().sum::<f64>() / finite.len() as f64;
+    let variance =
+        finite.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (finite.len() - 1) as f64;
+    let std = variance.sqrt();
+    let bound = mean + threshold * std;
+    let events = index
+        .iter()
+        .enumerate()
+        .filter(|(_, &v)| {
+            v.is_finite()
+                && if threshold >= 0.0 {
+                    v >= bound
+                } else {
+                    v <= bound
+                }
+        })
+        .map(|(t, _)| t)
+        .collect();
+    Some((mean, std, events))
+}
+
+/// Running sums of the finite values of fields on one grid
+#[derive(Debug, Clone)]
+struct FieldSum {
+    sums: Array2<f64>,
+    counts: Array2<u32>,
+    fields: usize,
+}
+
+impl FieldSum {
+    fn new(shape: (usize, usize)) -> Self {
+        Self {
+            sums: Array2::zeros(shape),
+            counts: Array2::zeros(shape),
+            fields: 0,
+        }
+    }
+
+    fn add(&mut self, field: &HorizontalField) {
+        Zip::from(&mut self.sums)
+            .and(&mut self.counts)
+            .and(&field.values)
+            .for_each(|sum, count, &value| {
+                if value.is_finite() {
+                    *sum += value as f64;
+                    *count += 1;
+                }
+            });
+        self.fields += 1;
+    }
+
+    /// Mean of every cell, NaN where no value was finite
+    fn mean(&self) -> Array2<f64> {
+        Zip::from(&self.sums)
+            .and(&self.counts)
+            .map_collect(|&sum, &count| {
+                if count > 0 {
+                    sum / count as f64
+                } else {
+                    f64::NAN
+                }
+            })
+    }
+}
+
+/// Handle GET /lagcorr requests
+pub async fn lagcorr_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LagCorrQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/lagcorr",
+        request_id = %request_id,
+        var_a = %params.var_a,
+        var_b = %params.var_b,
+        lags = ?params.lags,
+        "Processing lagged correlation request"
+    );
+
+    match process_lagcorr_query(&state, &params) {
+        Ok(response) => {
+            info!(
+                endpoint = "/lagcorr",
+                request_id = %request_id,
+                var_a = %params.var_a,
+                var_b = %params.var_b,
+                duration_us = start_time.elapsed().as_micros() as u64,
+                "Lagged correlation request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/lagcorr",
+                &request_id,
+                Some(&format!(
+                    "var_a={}, var_b={}, lags={:?}",
+                    params.var_a, params.var_b, params.lags
+                )),
+            );
+            let status = match &error {
+                RossbyError::ImageGeneration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_lagcorr_query(state: &AppState, params: &LagCorrQuery) -> Result<Response> {
+    let lags = parse_lags(params.lags.as_deref().unwrap_or("0"))?;
+    let output = params.output.as_deref().unwrap_or("series").to_lowercase();
+    if output != "series" && output != "composite" {
+        return Err(RossbyError::InvalidParameter {
+            param: "output".to_string(),
+            message: format!(
+                "Unsupported output: {}. Valid values are 'series' or 'composite'",
+                output
+            ),
+        });
+    }
+    let format = params.format.as_deref().unwrap_or("json").to_lowercase();
+    if format != "json" && format != "png" {
+        return Err(RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: format!(
+                "Unsupported format: {}. Valid values are 'json' or 'png'",
+                format
+            ),
+        });
+    }
+    if format == "png" && (output != "composite" || lags.len() != 1) {
+        return Err(RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: "format=png renders the composite map of a single lag".to_string(),
+        });
+    }
+    if output == "composite" && lags.len() > MAX_COMPOSITE_LAGS {
+        return Err(RossbyError::InvalidParameter {
+            param: "lags".to_string(),
+            message: format!(
+                "At most {} lags can be composited at once",
+                MAX_COMPOSITE_LAGS
+            ),
+        });
+    }
+    let threshold = params.threshold.unwrap_or(DEFAULT_THRESHOLD);
+    if !threshold.is_finite() {
+        return Err(RossbyError::InvalidParameter {
+            param: "threshold".to_string(),
+            message: format!("Invalid threshold: {}", threshold),
+        });
+    }
+
+    let pair = PairSelection::parse(
+        state,
+        &params.var_a,
+        &params.var_b,
+        params.dim.as_deref(),
+        &params.dimension_params,
+    )?;
+    let parse_region = |bbox: Option<&str>| {
+        bbox.map(parse_bbox)
+            .transpose()
+            .map(|bbox| bbox.map(|(a, b, c, d)| (a as f64, b as f64, c as f64, d as f64)))
+    };
+    let bbox_a = parse_region(params.bbox.as_deref())?;
+    let bbox_b = match params.bbox_b.as_deref() {
+        Some(bbox) => parse_region(Some(bbox))?,
+        None => bbox_a,
+    };
+
+    // Area-mean series of both variables, and for composites the mean field
+    // of the second over all steps, which anomalies are taken from
+    let extract_stage = info_span!("extract").entered();
+    let mut series_a = Vec::with_capacity(pair.indices.len());
+    let mut series_b = Vec::with_capacity(pair.indices.len());
+    let mut all_steps: Option<FieldSum> = None;
+    let mut grid = None;
+    for &index in &pair.indices {
+        let dim_indices = pair.slice(index);
+        let a = HorizontalField::from_state(state, &params.var_a, &dim_indices, bbox_a)?;
+        let b = HorizontalField::from_state(state, &params.var_b, &dim_indices, bbox_b)?;
+        series_a.push(area_weighted_mean(&a).unwrap_or(f64::NAN));
+        series_b.push(area_weighted_mean(&b).unwrap_or(f64::NAN));
+        if output == "composite" {
+            all_steps
+                .get_or_insert_with(|| FieldSum::new(b.values.dim()))
+                .add(&b);
+            grid.get_or_insert_with(|| (b.lat.clone(), b.lon.clone()));
+        }
+    }
+    extract_stage.exit();
+
+    let coords = pair.coordinates(state);
+    let mut body = serde_json::json!({
+        "var_a": params.var_a,
+        "var_b": params.var_b,
+        "output": output,
+        "dim": {
+            "dimension": pair.dim,
+            "steps": pair.indices.len(),
+            "first": coords.first(),
+            "last": coords.last(),
+        },
+        "selection": pair.selection_json(state),
+    });
+
+    if output == "series" {
+        let _stage = info_span!("serialize").entered();
+        let lags_json: Vec<serde_json::Value> = lags
+            .iter()
+            .map(|&lag| {
+                let moments = lagged_moments(&series_a, &series_b, lag);
+                serde_json::json!({
+                    "lag": lag,
+                    "correlation": moments.correlation(),
+                    "pairs": moments.count,
+                })
+            })
+            .collect();
+        body["series"] = serde_json::json!({
+            "coords": coords,
+            "a": series_a,
+            "b": series_b,
+        });
+        body["lags"] = serde_json::json!(lags_json);
+        return Ok(Json(body).into_response());
+    }
+
+    let (mean, std, events) =
+        composite_events(&series_a, threshold).ok_or_else(|| RossbyError::InvalidParameter {
+            param: "var_a".to_string(),
+            message: "The index series needs at least two valid steps for composites".to_string(),
+        })?;
+    let (Some(all_steps), Some((lat, lon))) = (all_steps, grid) else {
+        return Err(RossbyError::InvalidParameter {
+            param: "dim".to_string(),
+            message: "No slices selected".to_string(),
+        });
+    };
+
+    // Every step needed by any lag is extracted once and added to the
+    // composites of the lags it follows an event by
+    let composite_stage = info_span!("composite").entered();
+    let mut needed: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (lag_position, &lag) in lags.iter().enumerate() {
+        for &event in &events {
+            let step = event as i64 + lag;
+            if (0..pair.indices.len() as i64).contains(&step) {
+                needed.entry(step as usize).or_default().push(lag_position);
+            }
+        }
+    }
+    let mut composites = vec![FieldSum::new(all_steps.sums.dim()); lags.len()];
+    for (step, lag_positions) in needed {
+        let dim_indices = pair.slice(pair.indices[step]);
+        let b = HorizontalField::from_state(state, &params.var_b, &dim_indices, bbox_b)?;
+        for lag_position in lag_positions {
+            composites[lag_position].add(&b);
+        }
+    }
+    let climatology = all_steps.mean();
+    let anomalies: Vec<HorizontalField> = composites
+        .iter()
+        .map(|composite| HorizontalField {
+            lat: lat.clone(),
+            lon: lon.clone(),
+            values: Zip::from(&composite.mean())
+                .and(&climatology)
+                .map_collect(|&mean, &normal| (mean - normal) as f32),
+        })
+        .collect();
+    composite_stage.exit();
+
+    if format == "png" {
+        let colormap = state.config.data.colormap(params.colormap.as_deref());
+        let field = &anomalies[0];
+        return render_png(
+            field,
+            params.width,
+            params.height,
+            colormap,
+            symmetric_range(field),
+        );
+    }
+
+    let _stage = info_span!("serialize").entered();
+    body["threshold"] = serde_json::json!(threshold);
+    body["index"] = serde_json::json!({ "mean": mean, "std": std });
+    body["events"] = serde_json::json!(events.iter().map(|&t| coords[t]).collect::<Vec<_>>());
+    body["lat"] = serde_json::json!(lat);
+    body["lon"] = serde_json::json!(lon);
+    body["shape"] = serde_json::json!(climatology.shape());
+    body["composites"] = lags
+        .iter()
+        .zip(composites.iter().zip(&anomalies))
+        .map(|(&lag, (composite, anomaly))| {
+            serde_json::json!({
+                "lag": lag,
+                "events": composite.fields,
+                "values": anomaly.values_to_json(),
+                "stats": anomaly.stats(),
+            })
+        })
+        .collect();
+    Ok(Json(body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lags() {
+        assert_eq!(parse_lags("-2..2").unwrap(), vec![-2, -1, 0, 1, 2]);
+        assert_eq!(parse_lags("0, 6,12").unwrap(), vec![0, 6, 12]);
+        assert_eq!(parse_lags("-3").unwrap(), vec![-3]);
+        for invalid in ["2..-2", "a..3", "1,x", "", "-1000..1000"] {
+            assert!(parse_lags(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_lagged_moments() {
+        // b follows a by two steps
+        let a = [1.0, 3.0, 2.0, 5.0, 4.0, 0.0];
+        let b = [9.0, 9.0, 1.0, 3.0, 2.0, 5.0];
+        let moments = lagged_moments(&a, &b, 2);
+        assert_eq!(moments.count, 4);
+        assert!((moments.correlation().unwrap() - 1.0).abs() < 1e-12);
+        // and a precedes b, seen from b
+        let moments = lagged_moments(&b, &a, -2);
+        assert!((moments.correlation().unwrap() - 1.0).abs() < 1e-12);
+
+        // Missing values and lags past the series are skipped
+        let moments = lagged_moments(&[1.0, f64::NAN, 3.0], &[1.0, 2.0, 3.0], 0);
+        assert_eq!(moments.count, 2);
+        assert_eq!(lagged_moments(&a, &b, 6).count, 0);
+    }
+
+    #[test]
+    fn test_composite_events() {
+        let index = [0.0, 1.0, 2.0, 3.0, 4.0, f64::NAN];
+        let (mean, std, events) = composite_events(&index, 1.0).unwrap();
+        assert_eq!(mean, 2.0);
+        assert!((std - 2.5f64.sqrt()).abs() < 1e-12);
+        assert_eq!(events, vec![4]);
+        assert_eq!(composite_events(&index, -0.5).unwrap().2, vec![0, 1]);
+        assert_eq!(composite_events(&index, 0.0).unwrap().2, vec![2, 3, 4]);
+        assert!(composite_events(&[1.0, f64::NAN], 1.0).is_none());
+    }
+}
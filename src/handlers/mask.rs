@@ -0,0 +1,392 @@
+//! Mask endpoint handler.
+//!
+//! Returns the cells of a horizontal slab that satisfy a threshold condition
+//! (e.g. `temperature > 300`), as run-length encoded JSON or a PNG bitmask,
+//! so web clients can highlight regions without transferring the float field.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use image::{GrayImage, Luma};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::colormaps::parse_bbox;
+use crate::error::{Result, RossbyError};
+use crate::field::{resolve_dimension_indices, HorizontalField};
+use crate::handlers::stats::selection_to_json;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::AppState;
+
+/// Query parameters for the mask endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaskQuery {
+    /// Variable name
+    pub var: String,
+    /// Value the variable is compared against
+    pub threshold: f64,
+    /// Comparison (gt, ge, lt, le, eq or ne; default gt)
+    #[serde(default)]
+    pub op: Option<String>,
+    /// Response encoding (rle or png; default rle)
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Bounding box as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Dimension selectors pinning non-horizontal dimensions (see `query::Selection`)
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Condition a cell must satisfy to be set in the mask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    /// Parse the `op` parameter (default: gt)
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("gt") => Ok(Comparison::Gt),
+            Some("ge") => Ok(Comparison::Ge),
+            Some("lt") => Ok(Comparison::Lt),
+            Some("le") => Ok(Comparison::Le),
+            Some("eq") => Ok(Comparison::Eq),
+            Some("ne") => Ok(Comparison::Ne),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "op".to_string(),
+                message: format!(
+                    "Unknown comparison: {}. Valid values are gt, ge, lt, le, eq and ne",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Name of the comparison as given in `op`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Comparison::Gt => "gt",
+            Comparison::Ge => "ge",
+            Comparison::Lt => "lt",
+            Comparison::Le => "le",
+            Comparison::Eq => "eq",
+            Comparison::Ne => "ne",
+        }
+    }
+
+    /// Whether a value satisfies the comparison; missing (NaN) values never do
+    pub fn matches(self, value: f32, threshold: f64) -> bool {
+        if value.is_nan() {
+            return false;
+        }
+        let value = value as f64;
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Ge => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Le => value <= threshold,
+            Comparison::Eq => value == threshold,
+            Comparison::Ne => value != threshold,
+        }
+    }
+}
+
+/// Encoding of the mask in the response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MaskEncoding {
+    /// JSON with run lengths
+    Rle,
+    /// Grayscale PNG, white where the condition holds
+    Png,
+}
+
+impl MaskEncoding {
+    /// Parse the `encoding` parameter (default: rle)
+    fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("rle") => Ok(MaskEncoding::Rle),
+            Some("png") => Ok(MaskEncoding::Png),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "encoding".to_string(),
+                message: format!(
+                    "Unknown encoding: {}. Valid values are 'rle' or 'png'",
+                    other
+                ),
+            }),
+        }
+    }
+}
+
+/// Boolean mask over a horizontal field, with rows running north to south
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    /// Number of rows (latitudes) and columns (longitudes)
+    pub shape: [usize; 2],
+    /// Cell values in row-major order
+    pub cells: Vec<bool>,
+    /// Latitudes of the first and last rows
+    pub lat: (f64, f64),
+    /// Longitudes of the first and last columns
+    pub lon: (f64, f64),
+}
+
+impl Mask {
+    /// Evaluate a comparison over every cell of a field
+    pub fn from_field(field: &HorizontalField, comparison: Comparison, threshold: f64) -> Self {
+        let (rows, cols) = field.values.dim();
+        let north_first = field.lat.first() >= field.lat.last();
+        let row_order: Vec<usize> = if north_first {
+            (0..rows).collect()
+        } else {
+            (0..rows).rev().collect()
+        };
+
+        let cells = row_order
+            .iter()
+            .flat_map(|&row| field.values.row(row).to_vec())
+            .map(|value| comparison.matches(value, threshold))
+            .collect();
+        let lat = match (row_order.first(), row_order.last()) {
+            (Some(&first), Some(&last)) => (field.lat[first], field.lat[last]),
+            _ => (f64::NAN, f64::NAN),
+        };
+        let lon = match (field.lon.first(), field.lon.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => (f64::NAN, f64::NAN),
+        };
+
+        Self {
+            shape: [rows, cols],
+            cells,
+            lat,
+            lon,
+        }
+    }
+
+    /// Number of cells satisfying the condition
+    pub fn count(&self) -> usize {
+        self.cells.iter().filter(|&&cell| cell).count()
+    }
+
+    /// Run lengths of alternating unset and set cells, starting with unset
+    ///
+    /// The first run is zero when the first cell is set, so runs at even
+    /// positions are always unset and runs at odd positions always set.
+    pub fn run_lengths(&self) -> Vec<usize> {
+        let mut runs = Vec::new();
+        let mut current = false;
+        let mut length = 0;
+        for &cell in &self.cells {
+            if cell != current {
+                runs.push(length);
+                current = cell;
+                length = 0;
+            }
+            length += 1;
+        }
+        if length > 0 {
+            runs.push(length);
+        }
+        runs
+    }
+
+    /// Encode the mask as a grayscale PNG, white where set and black elsewhere
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let [rows, cols] = self.shape;
+        let image = GrayImage::from_fn(cols as u32, rows as u32, |x, y| {
+            let set = self.cells[y as usize * cols + x as usize];
+            Luma([if set { 255 } else { 0 }])
+        });
+        let mut buffer = Cursor::new(Vec::new());
+        image
+            .write_to(&mut buffer, image::ImageFormat::Png)
+            .map_err(|e| RossbyError::ImageGeneration {
+                message: format!("Failed to encode PNG: {}", e),
+            })?;
+        Ok(buffer.into_inner())
+    }
+}
+
+/// Handle GET /mask requests
+pub async fn mask_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<MaskQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/mask",
+        request_id = %request_id,
+        var = %params.var,
+        threshold = params.threshold,
+        op = ?params.op,
+        "Processing mask request"
+    );
+
+    match process_mask_query(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/mask",
+                request_id = %request_id,
+                var = %params.var,
+                duration_us = duration.as_micros() as u64,
+                "Mask request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/mask",
+                &request_id,
+                Some(&format!(
+                    "var={}, threshold={}, op={:?}",
+                    params.var, params.threshold, params.op
+                )),
+            );
+            let status = match &error {
+                RossbyError::ImageGeneration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_mask_query(state: &AppState, params: &MaskQuery) -> Result<Response> {
+    let comparison = Comparison::parse(params.op.as_deref())?;
+    let encoding = MaskEncoding::parse(params.encoding.as_deref())?;
+    if !params.threshold.is_finite() {
+        return Err(RossbyError::InvalidParameter {
+            param: "threshold".to_string(),
+            message: "Threshold must be a finite number".to_string(),
+        });
+    }
+    if !state.has_variable(&params.var) {
+        return Err(RossbyError::InvalidVariables {
+            names: vec![params.var.clone()],
+        });
+    }
+
+    let bbox = params
+        .bbox
+        .as_deref()
+        .map(parse_bbox)
+        .transpose()?
+        .map(|(a, b, c, d)| (a as f64, b as f64, c as f64, d as f64));
+    let dim_indices = resolve_dimension_indices(state, &params.var, &params.dimension_params)?;
+    let field = HorizontalField::from_state(state, &params.var, &dim_indices, bbox)?;
+    let mask = Mask::from_field(&field, comparison, params.threshold);
+
+    if encoding == MaskEncoding::Png {
+        return Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, HeaderValue::from_static("image/png"))],
+            mask.to_png()?,
+        )
+            .into_response());
+    }
+
+    let selection: HashMap<String, (usize, f64)> = dim_indices
+        .iter()
+        .map(|(dim, &index)| {
+            let value = state
+                .get_coordinate(dim)
+                .and_then(|coords| coords.get(index).copied())
+                .unwrap_or(index as f64);
+            (dim.clone(), (index, value))
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "var": params.var,
+        "op": comparison.as_str(),
+        "threshold": params.threshold,
+        "selection": selection_to_json(&selection),
+        "shape": mask.shape,
+        "lat": [mask.lat.0, mask.lat.1],
+        "lon": [mask.lon.0, mask.lon.1],
+        "count": mask.count(),
+        "encoding": "rle",
+        "runs": mask.run_lengths(),
+    }))
+    .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn field() -> HorizontalField {
+        // Rows stored south to north
+        HorizontalField {
+            lat: vec![10.0, 20.0],
+            lon: vec![0.0, 10.0, 20.0],
+            values: array![[299.0, 301.0, 302.0], [f32::NAN, 300.0, 305.0]],
+        }
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert_eq!(Comparison::parse(None).unwrap(), Comparison::Gt);
+        assert_eq!(Comparison::parse(Some("LE")).unwrap(), Comparison::Le);
+        assert!(Comparison::parse(Some("above")).is_err());
+
+        assert!(Comparison::Gt.matches(301.0, 300.0));
+        assert!(!Comparison::Gt.matches(300.0, 300.0));
+        assert!(Comparison::Ge.matches(300.0, 300.0));
+        assert!(!Comparison::Ne.matches(f32::NAN, 300.0));
+    }
+
+    #[test]
+    fn test_mask_from_field() {
+        let mask = Mask::from_field(&field(), Comparison::Gt, 300.0);
+        assert_eq!(mask.shape, [2, 3]);
+        // North row first
+        assert_eq!(mask.cells, vec![false, false, true, false, true, true]);
+        assert_eq!(mask.lat, (20.0, 10.0));
+        assert_eq!(mask.lon, (0.0, 20.0));
+        assert_eq!(mask.count(), 3);
+        assert_eq!(mask.run_lengths(), vec![2, 1, 1, 2]);
+
+        let all = Mask::from_field(&field(), Comparison::Lt, 400.0);
+        assert_eq!(all.run_lengths(), vec![1, 5]);
+        let none = Mask::from_field(&field(), Comparison::Eq, 0.0);
+        assert_eq!(none.run_lengths(), vec![6]);
+    }
+
+    #[test]
+    fn test_mask_png() {
+        let mask = Mask::from_field(&field(), Comparison::Gt, 300.0);
+        let png = mask.to_png().unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.get_pixel(2, 0)[0], 255);
+        assert_eq!(image.get_pixel(0, 1)[0], 0);
+    }
+}
@@ -1,17 +1,54 @@
 //! Metadata endpoint handler.
 //!
-//! Returns JSON describing all variables, dimensions, and attributes of the loaded file.
+//! Returns JSON describing all variables, dimensions, and attributes of the loaded file,
+//! with the categories of categorical variables (see [`crate::categories`]), the
+//! uncertainty companions of variables (see [`crate::uncertainty`]), the
+//! configured named views (see [`crate::views`]) and the coordinate reference system
+//! (see [`crate::crs`]).
+//!
+//! Files with hundreds of variables make that a large document, so
+//! `/metadata/variables` lists lightweight summaries of the variables a page at
+//! a time, with search and sorting, and `/metadata/variables/{name}` returns
+//! the full metadata of one variable.
 
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
 use tracing::{debug, info};
 
-use crate::logging::generate_request_id;
-use crate::state::AppState;
+use crate::categories::variable_categories;
+use crate::error::{Result, RossbyError};
+use crate::forecast_time::ForecastAxes;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::{AppState, AttributeValue, Variable};
+use crate::uncertainty;
+
+/// Variables per page of the listing by default
+const DEFAULT_PER_PAGE: usize = 100;
+
+/// Largest page size of the listing
+const MAX_PER_PAGE: usize = 1000;
+
+/// Query parameters for metadata endpoint
+#[derive(Debug, Deserialize, Default)]
+pub struct MetadataQuery {
+    /// Language code for translated variable display attributes (e.g. "de")
+    #[serde(default)]
+    pub lang: Option<String>,
+}
 
 /// Handle GET /metadata requests
-pub async fn metadata_handler(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+pub async fn metadata_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<MetadataQuery>,
+) -> Json<serde_json::Value> {
     let request_id = generate_request_id();
     let start_time = Instant::now();
 
@@ -19,16 +56,12 @@ pub async fn metadata_handler(State(state): State<Arc<AppState>>) -> Json<serde_
     debug!(
         endpoint = "/metadata",
         request_id = %request_id,
+        lang = ?params.lang,
         "Processing metadata request"
     );
 
     // Generate response
-    let response = serde_json::json!({
-        "global_attributes": state.metadata.global_attributes,
-        "dimensions": state.metadata.dimensions,
-        "variables": state.metadata.variables,
-        "coordinates": state.metadata.coordinates,
-    });
+    let response = build_metadata_response(&state, params.lang.as_deref());
 
     // Log successful request
     let duration = start_time.elapsed();
@@ -45,6 +78,350 @@ pub async fn metadata_handler(State(state): State<Arc<AppState>>) -> Json<serde_
     Json(response)
 }
 
+/// Build the metadata JSON, localizing variable display attributes if a language is given
+fn build_metadata_response(state: &AppState, lang: Option<&str>) -> serde_json::Value {
+    let variables = listed_variables(state, lang);
+
+    // Named views come with the dimension sizes and coordinates of their own grid
+    let mut views = HashMap::new();
+    for (name, view) in state.views.iter() {
+        let dimensions: HashMap<_, _> = view
+            .metadata
+            .dimensions
+            .iter()
+            .map(|(dim, dimension)| (dim, dimension.size))
+            .collect();
+        views.insert(
+            name,
+            serde_json::json!({
+                "dimensions": dimensions,
+                "coordinates": view.metadata.coordinates,
+            }),
+        );
+    }
+
+    // Structured legends of categorical variables
+    let categories: HashMap<_, _> = state
+        .metadata
+        .variables
+        .iter()
+        .filter_map(|(name, var)| {
+            variable_categories(&state.config, name, var).map(|categories| (name, categories))
+        })
+        .collect();
+
+    // Companions holding the uncertainty of variables
+    let uncertainty: HashMap<_, _> = state
+        .metadata
+        .variables
+        .keys()
+        .filter_map(|name| {
+            uncertainty::companion(state, name)
+                .ok()
+                .flatten()
+                .map(|companion| (name, companion))
+        })
+        .collect();
+
+    // Dimensions renamed and coordinates fixed at load
+    let coordinate_fixes: HashMap<_, _> = state
+        .coordinate_changes
+        .iter()
+        .map(|change| (&change.dimension, change))
+        .collect();
+
+    // Positions in the file of the steps of sorted or deduplicated time axes
+    let time_normalization: HashMap<_, _> = state
+        .time_normalization
+        .iter()
+        .map(|normalization| (&normalization.dimension, normalization))
+        .collect();
+
+    // Forecast reference and lead time axes, with the valid time of each pair
+    let forecast = ForecastAxes::detect(state).map(|axes| axes.to_json(state));
+
+    serde_json::json!({
+        "global_attributes": state.metadata.global_attributes,
+        "dimensions": state.metadata.dimensions,
+        "variables": variables,
+        "coordinates": state.metadata.coordinates,
+        "groups": state.metadata.groups,
+        "categories": categories,
+        "uncertainty": uncertainty,
+        "views": views,
+        "crs": state.crs,
+        "coordinate_fixes": coordinate_fixes,
+        "time_normalization": time_normalization,
+        "forecast": forecast,
+    })
+}
+
+/// The variables of the dataset, localized if a language is given
+///
+/// Named views are listed as variables too.
+fn listed_variables<'a>(state: &'a AppState, lang: Option<&str>) -> HashMap<&'a String, Variable> {
+    let mut variables: HashMap<_, _> = state
+        .metadata
+        .variables
+        .iter()
+        .map(|(name, var)| {
+            let localized = match lang.and_then(|lang| state.config.translation_for(lang, name)) {
+                Some(translation) => var.with_translation(translation),
+                None => var.clone(),
+            };
+            (name, localized)
+        })
+        .collect();
+    for (name, view) in state.views.iter() {
+        if let Some(var) = view.get_variable_metadata(name) {
+            variables.insert(name, var.clone());
+        }
+    }
+    variables
+}
+
+/// Query parameters for the variable listing
+#[derive(Debug, Deserialize, Default)]
+pub struct VariablesQuery {
+    /// Page to return, starting at 1
+    #[serde(default)]
+    pub page: Option<usize>,
+    /// Variables per page (default 100, at most 1000)
+    #[serde(default)]
+    pub per_page: Option<usize>,
+    /// Case-insensitive text to find in the name, `long_name` or `standard_name`
+    #[serde(default)]
+    pub search: Option<String>,
+    /// Only list variables along this dimension
+    #[serde(default)]
+    pub dimension: Option<String>,
+    /// Sort order: `name` (default) or `size`, descending with a leading `-`
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// Language code for translated variable display attributes (e.g. "de")
+    #[serde(default)]
+    pub lang: Option<String>,
+}
+
+/// Handle GET /metadata/variables requests
+///
+/// Lists lightweight summaries of the variables, a page at a time, for files
+/// whose full metadata is too large to fetch at once.
+pub async fn variables_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<VariablesQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/metadata/variables",
+        request_id = %request_id,
+        page = ?params.page,
+        per_page = ?params.per_page,
+        search = ?params.search,
+        "Processing variable listing request"
+    );
+
+    match list_variables(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/metadata/variables",
+                request_id = %request_id,
+                duration_us = duration.as_micros() as u64,
+                total = response["total"].as_u64().unwrap_or_default(),
+                "Variable listing request successful"
+            );
+            Json(response).into_response()
+        }
+        Err(error) => metadata_error_response(error, "/metadata/variables", &request_id),
+    }
+}
+
+/// Handle GET /metadata/variables/{name} requests
+///
+/// Returns the full metadata of one variable, with the categories of
+/// categorical variables and the coordinates of its dimensions.
+pub async fn variable_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(params): Query<MetadataQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    debug!(
+        endpoint = "/metadata/variables",
+        request_id = %request_id,
+        var = %name,
+        lang = ?params.lang,
+        "Processing variable metadata request"
+    );
+
+    match describe_variable(&state, &name, params.lang.as_deref()) {
+        Ok(response) => Json(response).into_response(),
+        Err(error) => metadata_error_response(error, "/metadata/variables", &request_id),
+    }
+}
+
+/// Build one page of variable summaries
+fn list_variables(state: &AppState, params: &VariablesQuery) -> Result<serde_json::Value> {
+    let page = params.page.unwrap_or(1);
+    if page == 0 {
+        return Err(RossbyError::InvalidParameter {
+            param: "page".to_string(),
+            message: "Pages start at 1".to_string(),
+        });
+    }
+    let per_page = params.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if per_page == 0 || per_page > MAX_PER_PAGE {
+        return Err(RossbyError::InvalidParameter {
+            param: "per_page".to_string(),
+            message: format!("Must be between 1 and {}", MAX_PER_PAGE),
+        });
+    }
+    let sort = params.sort.as_deref().unwrap_or("name");
+    let (key, descending) = match sort.strip_prefix('-') {
+        Some(key) => (key, true),
+        None => (sort, false),
+    };
+    if key != "name" && key != "size" {
+        return Err(RossbyError::InvalidParameter {
+            param: "sort".to_string(),
+            message: format!(
+                "Unknown sort '{}'. Must be one of: name, size, -name, -size",
+                sort
+            ),
+        });
+    }
+
+    let search = params.search.as_deref().map(str::to_lowercase);
+    let mut variables: Vec<(&String, Variable)> = listed_variables(state, params.lang.as_deref())
+        .into_iter()
+        .filter(|(_, var)| {
+            params
+                .dimension
+                .as_ref()
+                .is_none_or(|dim| var.dimensions.contains(dim))
+        })
+        .filter(|(name, var)| {
+            search.as_deref().is_none_or(|search| {
+                std::iter::once(name.as_str())
+                    .chain(
+                        ["long_name", "standard_name"]
+                            .into_iter()
+                            .filter_map(|key| match var.attributes.get(key) {
+                                Some(AttributeValue::Text(text)) => Some(text.as_str()),
+                                _ => None,
+                            }),
+                    )
+                    .any(|text| text.to_lowercase().contains(search))
+            })
+        })
+        .collect();
+    // Ties in size are broken by name, so pages are stable
+    variables.sort_by_key(|(name, _)| *name);
+    if key == "size" {
+        variables.sort_by_key(|(_, var)| var.shape.iter().product::<usize>());
+    }
+    if descending {
+        variables.reverse();
+    }
+
+    let total = variables.len();
+    let summaries: Vec<_> = variables
+        .iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .map(|(name, var)| variable_summary(name, var))
+        .collect();
+
+    Ok(serde_json::json!({
+        "page": page,
+        "per_page": per_page,
+        "pages": total.div_ceil(per_page),
+        "total": total,
+        "variables": summaries,
+    }))
+}
+
+/// Lightweight description of a variable for listings
+fn variable_summary(name: &str, var: &Variable) -> serde_json::Value {
+    let text = |key: &str| match var.attributes.get(key) {
+        Some(AttributeValue::Text(text)) => Some(text.clone()),
+        _ => None,
+    };
+    serde_json::json!({
+        "name": name,
+        "dimensions": var.dimensions,
+        "shape": var.shape,
+        "size": var.shape.iter().product::<usize>(),
+        "dtype": var.dtype,
+        "long_name": text("long_name"),
+        "standard_name": text("standard_name"),
+        "units": text("units"),
+    })
+}
+
+/// Build the full metadata of one variable
+fn describe_variable(
+    state: &AppState,
+    name: &str,
+    lang: Option<&str>,
+) -> Result<serde_json::Value> {
+    let variables = listed_variables(state, lang);
+    let Some((name, var)) = variables
+        .into_iter()
+        .find(|(var_name, _)| *var_name == name)
+    else {
+        return Err(RossbyError::VariableNotFound {
+            name: name.to_string(),
+        });
+    };
+
+    // Views have coordinates of their own
+    let metadata = match state.views.iter().find(|(view, _)| *view == name) {
+        Some((_, view)) => &view.metadata,
+        None => &state.metadata,
+    };
+    let coordinates: HashMap<_, _> = var
+        .dimensions
+        .iter()
+        .filter_map(|dim| metadata.coordinates.get(dim).map(|values| (dim, values)))
+        .collect();
+    let categories = state
+        .metadata
+        .variables
+        .get(name)
+        .and_then(|meta| variable_categories(&state.config, name, meta));
+    let uncertainty = uncertainty::companion(state, name).ok().flatten();
+
+    Ok(serde_json::json!({
+        "name": name,
+        "variable": var,
+        "coordinates": coordinates,
+        "categories": categories,
+        "uncertainty": uncertainty,
+    }))
+}
+
+/// Build the error response of the variable endpoints
+fn metadata_error_response(error: RossbyError, endpoint: &str, request_id: &str) -> Response {
+    log_request_error(&error, endpoint, request_id, None);
+
+    let status = match &error {
+        RossbyError::VariableNotFound { .. } => StatusCode::NOT_FOUND,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "error": error.to_string(),
+            "request_id": request_id
+        })),
+    )
+        .into_response()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,6 +481,7 @@ mod tests {
             dimensions,
             variables,
             coordinates,
+            groups: HashMap::new(),
         };
 
         // Create data map (empty for this test)
@@ -156,4 +534,224 @@ mod tests {
             &[serde_json::json!(-180.0), serde_json::json!(180.0)]
         );
     }
+
+    #[test]
+    fn test_metadata_response_localized() {
+        let mut var_attributes = HashMap::new();
+        var_attributes.insert("units".to_string(), AttributeValue::Text("K".to_string()));
+
+        let mut variables = HashMap::new();
+        variables.insert(
+            "temperature".to_string(),
+            Variable {
+                name: "temperature".to_string(),
+                dimensions: vec![],
+                shape: vec![],
+                attributes: var_attributes,
+                dtype: "f32".to_string(),
+            },
+        );
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::new(),
+            variables,
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        };
+
+        let config: Config = serde_json::from_str(
+            r#"{"data": {"translations": {"de": {"temperature": {"long_name": "Temperatur", "units": "Kelvin"}}}}}"#,
+        )
+        .unwrap();
+        let state = AppState::new(config, metadata, HashMap::new());
+
+        let json = build_metadata_response(&state, Some("de"));
+        let attrs = &json["variables"]["temperature"]["attributes"];
+        assert_eq!(attrs["long_name"], "Temperatur");
+        assert_eq!(attrs["units"], "Kelvin");
+
+        // Without a language the file attributes are returned unchanged
+        let json = build_metadata_response(&state, None);
+        let attrs = &json["variables"]["temperature"]["attributes"];
+        assert_eq!(attrs["units"], "K");
+        assert!(attrs.get("long_name").is_none());
+    }
+
+    #[test]
+    fn test_metadata_response_categories() {
+        let mut var_attributes = HashMap::new();
+        var_attributes.insert(
+            "flag_values".to_string(),
+            AttributeValue::IntegerArray(vec![0, 1]),
+        );
+        var_attributes.insert(
+            "flag_meanings".to_string(),
+            AttributeValue::Text("sea land".to_string()),
+        );
+
+        let mut variables = HashMap::new();
+        variables.insert(
+            "lsm".to_string(),
+            Variable {
+                name: "lsm".to_string(),
+                dimensions: vec![],
+                shape: vec![],
+                attributes: var_attributes,
+                dtype: "f32".to_string(),
+            },
+        );
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::new(),
+            variables,
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        };
+        let state = AppState::new(Config::default(), metadata, HashMap::new());
+
+        let json = build_metadata_response(&state, None);
+        assert_eq!(
+            json["categories"],
+            serde_json::json!({
+                "lsm": [
+                    {"value": 0.0, "meaning": "sea"},
+                    {"value": 1.0, "meaning": "land"},
+                ]
+            })
+        );
+    }
+
+    fn create_listing_state() -> AppState {
+        let variable = |name: &str, dims: &[&str], shape: Vec<usize>, long_name: &str| Variable {
+            name: name.to_string(),
+            dimensions: dims.iter().map(|d| d.to_string()).collect(),
+            shape,
+            attributes: HashMap::from([(
+                "long_name".to_string(),
+                AttributeValue::Text(long_name.to_string()),
+            )]),
+            dtype: "f32".to_string(),
+        };
+        let variables = HashMap::from([
+            (
+                "u10".to_string(),
+                variable("u10", &["lat", "lon"], vec![2, 3], "10m U wind component"),
+            ),
+            (
+                "v10".to_string(),
+                variable("v10", &["lat", "lon"], vec![2, 3], "10m V wind component"),
+            ),
+            (
+                "t2m".to_string(),
+                variable(
+                    "t2m",
+                    &["time", "lat", "lon"],
+                    vec![4, 2, 3],
+                    "2m temperature",
+                ),
+            ),
+            (
+                "lat".to_string(),
+                variable("lat", &["lat"], vec![2], "latitude"),
+            ),
+        ]);
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::new(),
+            variables,
+            coordinates: HashMap::from([("lat".to_string(), vec![10.0, 20.0])]),
+            groups: HashMap::new(),
+        };
+        AppState::new(Config::default(), metadata, HashMap::new())
+    }
+
+    #[test]
+    fn test_list_variables() {
+        let state = create_listing_state();
+        let names = |json: &serde_json::Value| -> Vec<String> {
+            json["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|var| var["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let query = VariablesQuery {
+            per_page: Some(3),
+            ..Default::default()
+        };
+        let json = list_variables(&state, &query).unwrap();
+        assert_eq!(names(&json), ["lat", "t2m", "u10"]);
+        assert_eq!(json["total"], 4);
+        assert_eq!(json["pages"], 2);
+        assert_eq!(json["variables"][1]["size"], 24);
+        assert_eq!(json["variables"][1]["long_name"], "2m temperature");
+        let json = list_variables(
+            &state,
+            &VariablesQuery {
+                page: Some(2),
+                ..query
+            },
+        )
+        .unwrap();
+        assert_eq!(names(&json), ["v10"]);
+
+        let json = list_variables(
+            &state,
+            &VariablesQuery {
+                search: Some("WIND".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(names(&json), ["u10", "v10"]);
+
+        let json = list_variables(
+            &state,
+            &VariablesQuery {
+                dimension: Some("lon".to_string()),
+                sort: Some("-size".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(names(&json)[0], "t2m");
+
+        for query in [
+            VariablesQuery {
+                page: Some(0),
+                ..Default::default()
+            },
+            VariablesQuery {
+                per_page: Some(MAX_PER_PAGE + 1),
+                ..Default::default()
+            },
+            VariablesQuery {
+                sort: Some("units".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(list_variables(&state, &query).is_err());
+        }
+    }
+
+    #[test]
+    fn test_describe_variable() {
+        let state = create_listing_state();
+        let json = describe_variable(&state, "t2m", None).unwrap();
+        assert_eq!(json["variable"]["shape"], serde_json::json!([4, 2, 3]));
+        assert_eq!(
+            json["coordinates"],
+            serde_json::json!({"lat": [10.0, 20.0]})
+        );
+        assert!(json["categories"].is_null());
+
+        assert!(matches!(
+            describe_variable(&state, "missing", None),
+            Err(RossbyError::VariableNotFound { .. })
+        ));
+    }
 }
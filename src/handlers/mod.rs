@@ -2,14 +2,54 @@
 //!
 //! This module contains all the endpoint handlers for the web server.
 
+pub mod admin;
+pub mod cellstats;
+pub mod chart;
+pub mod correlate;
 pub mod data;
+pub mod debug;
+pub mod decompose;
+pub mod diff;
+pub mod exceedance;
+pub mod export;
+pub mod healthz;
 pub mod heartbeat;
 pub mod image;
+pub mod lagcorr;
+pub mod mask;
 pub mod metadata;
+pub mod panel;
 pub mod point;
+pub mod profile_series;
+pub mod sample;
+pub mod signing_key;
+pub mod stats;
+pub mod thumbnail;
+pub mod usage;
+pub mod wms;
 
+pub use admin::{flush_caches_handler, state_snapshot_handler};
+pub use cellstats::cellstats_handler;
+pub use chart::chart_handler;
+pub use correlate::correlate_handler;
 pub use data::data_handler;
+pub use debug::interpolate_debug_handler;
+pub use decompose::decompose_handler;
+pub use diff::diff_handler;
+pub use exceedance::exceedance_handler;
+pub use export::export_handler;
+pub use healthz::healthz_handler;
 pub use heartbeat::heartbeat_handler;
 pub use image::image_handler;
-pub use metadata::metadata_handler;
+pub use lagcorr::lagcorr_handler;
+pub use mask::mask_handler;
+pub use metadata::{metadata_handler, variable_handler, variables_handler};
+pub use panel::panel_handler;
 pub use point::point_handler;
+pub use profile_series::profile_series_handler;
+pub use sample::sample_handler;
+pub use signing_key::signing_key_handler;
+pub use stats::stats_handler;
+pub use thumbnail::thumbnail_handler;
+pub use usage::usage_handler;
+pub use wms::wms_handler;
@@ -0,0 +1,261 @@
+//! Panel endpoint handler.
+//!
+//! Renders several variables as a labeled grid in one PNG, each with its own
+//! color scale and legend (see [`crate::panel`]).
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use image::ImageFormat;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::artifact::artifact_response;
+use crate::colormaps::presets::STYLE_PRESET_HEADER;
+use crate::colormaps::{self, colormap::COLOR_RANGE_HEADER};
+use crate::error::{Result, RossbyError};
+use crate::handlers::image::{generate_image_response, style_preset, ImageQuery};
+use crate::handlers::thumbnail::default_title;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::panel::{compose, Panel};
+use crate::query::{no_variables, split_variables};
+use crate::state::{AppState, AttributeValue};
+use crate::thumbnail::draw_title;
+
+/// Default size of each panel in pixels
+const DEFAULT_WIDTH: u32 = 400;
+const DEFAULT_HEIGHT: u32 = 300;
+
+/// Largest panel side in pixels
+const MAX_SIZE: u32 = 1200;
+
+/// Largest number of variables on one panel image
+const MAX_PANELS: usize = 16;
+
+/// Query parameters for the panel endpoint
+#[derive(Debug, Deserialize)]
+pub struct PanelQuery {
+    /// Comma-separated variables, one panel each
+    #[serde(default)]
+    pub vars: String,
+    /// Panels per row (default: as many as make the grid roughly square)
+    pub cols: Option<String>,
+    /// Width of each panel in pixels
+    pub width: Option<String>,
+    /// Height of each panel in pixels
+    pub height: Option<String>,
+    /// Language code of the titles and legend units (e.g. "de")
+    pub lang: Option<String>,
+    /// Further `/image` parameters applied to every panel, e.g. `time`,
+    /// `bbox`, `colormap` or dimension selectors
+    #[serde(flatten)]
+    pub image_params: BTreeMap<String, String>,
+}
+
+/// Handle GET /panel requests
+pub async fn panel_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<PanelQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/panel",
+        request_id = %request_id,
+        vars = %params.vars,
+        cols = ?params.cols,
+        params = ?params.image_params,
+        "Processing panel request"
+    );
+
+    match panel_png(&state, &params).await {
+        Ok(png) => {
+            info!(
+                endpoint = "/panel",
+                request_id = %request_id,
+                vars = %params.vars,
+                duration_us = start_time.elapsed().as_micros() as u64,
+                "Panel request successful"
+            );
+            artifact_response(&headers, HeaderValue::from_static("image/png"), png)
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/panel",
+                &request_id,
+                Some(&format!("vars={}", params.vars)),
+            );
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Encoded panel image of a query
+async fn panel_png(state: &Arc<AppState>, params: &PanelQuery) -> Result<Vec<u8>> {
+    let vars = split_variables(&params.vars);
+    if vars.is_empty() {
+        return Err(no_variables("vars"));
+    }
+    if vars.len() > MAX_PANELS {
+        return Err(RossbyError::InvalidParameter {
+            param: "vars".to_string(),
+            message: format!(
+                "At most {} variables can be shown on one panel image, got {}",
+                MAX_PANELS,
+                vars.len()
+            ),
+        });
+    }
+    let default_cols = (vars.len() as f64).sqrt().ceil() as usize;
+    let cols = parse_bounded("cols", params.cols.as_deref(), default_cols, vars.len())?;
+    let max_size = MAX_SIZE as usize;
+    let width = parse_bounded(
+        "width",
+        params.width.as_deref(),
+        DEFAULT_WIDTH as usize,
+        max_size,
+    )?;
+    let height = parse_bounded(
+        "height",
+        params.height.as_deref(),
+        DEFAULT_HEIGHT as usize,
+        max_size,
+    )?;
+
+    // Legends show the colormap the panels are drawn with, which for
+    // panels styled by a preset is that of the preset
+    let colormap_name = state
+        .config
+        .data
+        .colormap(params.image_params.get("colormap").map(String::as_str));
+    let colormap = colormaps::get_colormap(colormap_name)?;
+
+    let mut rendered = Vec::with_capacity(vars.len());
+    let mut preset_colormaps = Vec::with_capacity(vars.len());
+    for var in &vars {
+        let mut image_params = params.image_params.clone();
+        image_params.insert("var".to_string(), var.clone());
+        image_params.insert("width".to_string(), width.to_string());
+        image_params.insert("height".to_string(), height.to_string());
+        image_params.insert("format".to_string(), "png".to_string());
+
+        let invalid = |e: &dyn std::fmt::Display| RossbyError::InvalidParameter {
+            param: "vars".to_string(),
+            message: format!("Cannot build image query for {}: {}", var, e),
+        };
+        let query = serde_urlencoded::to_string(&image_params).map_err(|e| invalid(&e))?;
+        let image_query: ImageQuery =
+            serde_urlencoded::from_str(&query).map_err(|e| invalid(&e))?;
+        let response = generate_image_response(state.clone(), &image_query, &HeaderMap::new())?;
+        let value_range =
+            color_range(response.headers()).ok_or_else(|| RossbyError::ImageGeneration {
+                message: format!("Rendered image of {} has no color range", var),
+            })?;
+        preset_colormaps.push(match response.headers().get(STYLE_PRESET_HEADER) {
+            Some(_) => style_preset(state, var)
+                .map(|preset| colormaps::get_colormap(&preset.colormap))
+                .transpose()?,
+            None => None,
+        });
+        let png = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|e| RossbyError::ImageGeneration {
+                message: format!("Failed to read rendered image: {}", e),
+            })?;
+        let mut image = image::load_from_memory_with_format(&png, ImageFormat::Png)
+            .map_err(|e| RossbyError::ImageGeneration {
+                message: format!("Failed to decode rendered image: {}", e),
+            })?
+            .to_rgba8();
+        let lang = params.lang.as_deref();
+        draw_title(&mut image, &default_title(state, var, &image_params, lang));
+
+        let units = match state
+            .get_localized_variable_metadata(var, lang)
+            .ok()
+            .and_then(|meta| meta.attributes.get("units").cloned())
+        {
+            Some(AttributeValue::Text(units)) => Some(units),
+            _ => None,
+        };
+        rendered.push((image, value_range, units));
+    }
+    let panels: Vec<Panel> = rendered
+        .into_iter()
+        .zip(&preset_colormaps)
+        .map(|((image, value_range, units), preset_colormap)| Panel {
+            image,
+            colormap: preset_colormap.as_deref().unwrap_or(colormap.as_ref()),
+            value_range,
+            units,
+        })
+        .collect();
+
+    let img = compose(&panels, cols);
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to encode PNG: {}", e),
+        })?;
+    Ok(buffer.into_inner())
+}
+
+/// Color range of a rendered image, from its `X-Rossby-Color-Range` header
+fn color_range(headers: &HeaderMap) -> Option<(f64, f64)> {
+    let value = headers.get(COLOR_RANGE_HEADER)?.to_str().ok()?;
+    let (min, max) = value.split_once(',')?;
+    Some((min.parse().ok()?, max.parse().ok()?))
+}
+
+/// Parse a count between 1 and `max`
+fn parse_bounded(param: &str, value: Option<&str>, default: usize, max: usize) -> Result<usize> {
+    let Some(value) = value else {
+        return Ok(default);
+    };
+    match value.trim().parse::<usize>() {
+        Ok(count) if (1..=max).contains(&count) => Ok(count),
+        _ => Err(RossbyError::InvalidParameter {
+            param: param.to_string(),
+            message: format!("{} must be between 1 and {}, got '{}'", param, max, value),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bounded() {
+        assert_eq!(parse_bounded("cols", None, 2, 3).unwrap(), 2);
+        assert_eq!(parse_bounded("cols", Some("3"), 2, 3).unwrap(), 3);
+        assert!(parse_bounded("cols", Some("4"), 2, 3).is_err());
+        assert!(parse_bounded("cols", Some("0"), 2, 3).is_err());
+        assert!(parse_bounded("width", Some("wide"), 400, 1200).is_err());
+    }
+
+    #[test]
+    fn test_color_range() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(color_range(&headers), None);
+        headers.insert(COLOR_RANGE_HEADER, HeaderValue::from_static("-2.5,30"));
+        assert_eq!(color_range(&headers), Some((-2.5, 30.0)));
+    }
+}
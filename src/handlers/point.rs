@@ -3,18 +3,25 @@
 //! Returns interpolated values for one or more variables at a specific point in space-time.
 
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::Query,
+    http::{HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use ndarray::IxDyn;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Instant;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, info_span};
 
+use crate::bounds::{Axis, BoundsMode, BOUNDS_HEADER};
 use crate::error::RossbyError;
+use crate::field::{LAT_NAMES, LON_NAMES};
 use crate::logging::{generate_request_id, log_request_error};
+use crate::nan_policy::{NanPolicy, NAN_HEADER};
+use crate::partial::VariableErrors;
+use crate::query::{no_variables, split_variables, Dataset, TimeParams};
+use crate::query_resolved::{QueryResolved, ResolvedPoint};
 use crate::state::AppState;
 
 /// Query parameters for point endpoint
@@ -63,6 +70,18 @@ pub struct PointQuery {
     pub vars: String,
     /// Interpolation method (nearest, bilinear, bicubic)
     pub interpolation: Option<String>,
+    /// Handling of coordinates outside the grid (error, clamp or wrap)
+    pub bounds: Option<String>,
+    /// Report failing variables in an `errors` section instead of failing the request
+    pub partial: Option<bool>,
+    /// Snapping of points on missing data (none or nearest_valid)
+    pub snap: Option<String>,
+    /// Return the values of the configured uncertainty companions as well
+    pub include_uncertainty: Option<bool>,
+    /// Encoding of NaN and infinity (null, string or omit)
+    pub nan: Option<String>,
+    /// Describe how the query was interpreted in a `query_resolved` section
+    pub echo_params: Option<bool>,
 }
 
 /// Response for point query
@@ -70,13 +89,29 @@ pub struct PointQuery {
 pub struct PointResponse {
     #[serde(flatten)]
     pub values: serde_json::Map<String, serde_json::Value>,
+    /// Failing variables of a partial response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<serde_json::Value>,
+    /// Grid cells that variables on missing data were snapped to
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub snapped: serde_json::Map<String, serde_json::Value>,
+    /// Values of the uncertainty companions of the variables, with
+    /// `include_uncertainty=true`
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub uncertainty: serde_json::Map<String, serde_json::Value>,
+    /// How the query was interpreted, with `echo_params=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_resolved: Option<QueryResolved>,
+    /// Bounds mode applied to the coordinates, returned as a header
+    #[serde(skip)]
+    pub bounds: BoundsMode,
+    /// NaN policy applied to the values, returned as a header
+    #[serde(skip)]
+    pub nan: NanPolicy,
 }
 
 /// Handle GET /point requests
-pub async fn point_handler(
-    State(state): State<Arc<AppState>>,
-    Query(params): Query<PointQuery>,
-) -> Response {
+pub async fn point_handler(Dataset(state): Dataset, Query(params): Query<PointQuery>) -> Response {
     let request_id = generate_request_id();
     let start_time = Instant::now();
 
@@ -93,7 +128,8 @@ pub async fn point_handler(
         "Processing point query"
     );
 
-    match process_point_query(state, params.clone()) {
+    let result = info_span!("interpolate").in_scope(|| process_point_query(state, params.clone()));
+    match result {
         Ok(response) => {
             // Log successful request
             let duration = start_time.elapsed();
@@ -104,7 +140,12 @@ pub async fn point_handler(
                 "Point query successful"
             );
 
-            Json(response).into_response()
+            let bounds = HeaderValue::from_static(response.bounds.as_str());
+            let nan = HeaderValue::from_static(response.nan.as_str());
+            let mut response = info_span!("serialize").in_scope(|| Json(response).into_response());
+            response.headers_mut().insert(BOUNDS_HEADER, bounds);
+            response.headers_mut().insert(NAN_HEADER, nan);
+            response
         }
         Err(error) => {
             // Log error
@@ -137,8 +178,6 @@ fn process_point_query(
     let mut longitude_idx: Option<usize> = None;
     #[allow(unused_assignments)]
     let mut latitude_idx: Option<usize> = None;
-    #[allow(unused_assignments)]
-    let mut time_idx: Option<usize> = None;
 
     // Get longitude using raw index or physical value
     #[allow(unused_assignments)]
@@ -213,87 +252,63 @@ fn process_point_query(
         lat_value = Some(lat);
     }
 
-    // Get time using raw index or physical value
-    if let Some(idx) = params.__time_index {
-        // Use raw index directly
-        if idx >= state.time_dim_size() {
-            return Err(RossbyError::IndexOutOfBounds {
-                param: "__time_index".to_string(),
-                value: idx.to_string(),
-                max: state.time_dim_size() - 1,
-            });
-        }
-
-        time_idx = Some(idx);
-    } else if let Some(idx) = params.time_index {
-        // Use deprecated time_index parameter (with warning)
-        warn!(
-            param = "time_index",
-            deprecated_since = "0.1.0",
-            replacement = "__time_index",
-            "The 'time_index' parameter is deprecated. Please use '__time_index' instead."
-        );
-
-        if idx >= state.time_dim_size() {
-            return Err(RossbyError::IndexOutOfBounds {
-                param: "time_index".to_string(),
-                value: idx.to_string(),
-                max: state.time_dim_size() - 1,
-            });
-        }
-
-        time_idx = Some(idx);
-    } else if let Some(time_val) = params.time.or(params._time) {
-        // Use physical time value - convert to index with exact match
-        // Get time coordinates
-        let _time_coords = state
-            .get_coordinate_checked("time")
-            .or_else(|_| state.get_coordinate_checked("_time"))?;
-
-        // Find exact match for time value
-        match state.find_coordinate_index_exact("time", time_val) {
-            Ok(idx) => time_idx = Some(idx),
-            Err(e) => return Err(e),
-        }
-    } else {
-        // Default to time index 0
-        time_idx = Some(0);
-    }
-
-    // Get time index (default to 0)
-    let time_index = time_idx.unwrap_or(0);
+    // Get time using raw index or physical value (default to index 0)
+    let time = TimeParams {
+        raw_index: params.__time_index,
+        value: params.time.or(params._time),
+        legacy_index: params.time_index,
+    };
+    let time_index = time.resolve(&state)?;
 
     // Get the list of variables to query
-    let variables: Vec<String> = params
-        .vars
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
+    let variables = split_variables(&params.vars);
 
     if variables.is_empty() {
-        return Err(RossbyError::InvalidParameter {
-            param: "vars".to_string(),
-            message: "No variables specified".to_string(),
-        });
+        return Err(no_variables("vars"));
     }
 
     // Get interpolation method (default to bilinear)
     let interpolation_method = params.interpolation.as_deref().unwrap_or("bilinear");
     let interpolator = crate::interpolation::get_interpolator(interpolation_method)?;
 
+    // Get the handling of coordinates outside the grid
+    let bounds = BoundsMode::from_request(params.bounds.as_deref(), &state.config.data.bounds)?;
+
+    // Get the encoding of values that are not finite
+    let nan = NanPolicy::from_request(params.nan.as_deref(), &state.config.data.json_nan)?;
+
+    // Report failing variables instead of failing the request
+    let partial = params.partial.unwrap_or(false);
+
+    // Snap points on missing data to the nearest cell holding a valid value
+    let snap_nearest_valid = match params.snap.as_deref().unwrap_or("none") {
+        "none" => false,
+        "nearest_valid" => true,
+        other => {
+            return Err(RossbyError::InvalidParameter {
+                param: "snap".to_string(),
+                message: format!(
+                    "Unknown snap mode '{}'. Supported modes: none, nearest_valid",
+                    other
+                ),
+            })
+        }
+    };
+
     // Results map
     let mut values = serde_json::Map::new();
 
-    // Process each variable
-    for var_name in variables {
+    // Interpolate one variable at the point, with the cell it was snapped to if any
+    let point_value = |var_name: &str| -> Result<(f32, Option<serde_json::Value>), RossbyError> {
         // Check if variable exists
-        if !state.has_variable(&var_name) {
-            return Err(RossbyError::VariableNotFound { name: var_name });
+        if !state.has_variable(var_name) {
+            return Err(RossbyError::VariableNotFound {
+                name: var_name.to_string(),
+            });
         }
 
         // Get variable dimensions
-        let dimensions = state.get_variable_dimensions(&var_name)?;
+        let dimensions = state.get_variable_dimensions(var_name)?;
 
         // Find dimension indices for lat, lon, and time with alias support
         let mut lat_dim_idx = None;
@@ -322,8 +337,16 @@ fn process_point_query(
             message: format!("Variable {} does not have a lon dimension", var_name),
         })?;
 
-        // Get the data array
-        let data = state.get_variable_checked(&var_name)?;
+        // Only the time step of the query is read, and converted if packed
+        let pins: Vec<(usize, usize)> = time_dim_idx
+            .map(|axis| (axis, time_index))
+            .into_iter()
+            .collect();
+        let data = state.get_variable_indexed(var_name, &pins)?;
+        let data = data.as_standard_layout();
+        // Positions of the horizontal axes once the time axis is removed
+        let lat_dim_idx = lat_dim_idx - usize::from(time_dim_idx.is_some_and(|t| t < lat_dim_idx));
+        let lon_dim_idx = lon_dim_idx - usize::from(time_dim_idx.is_some_and(|t| t < lon_dim_idx));
 
         // Get coordinates using dimension aliases
         let lon_coords = state
@@ -340,40 +363,15 @@ fn process_point_query(
         let lon_idx = if let Some(idx) = longitude_idx {
             idx as f64
         } else {
-            // Check if coordinates are within bounds
-            let lon = lon_value.unwrap();
-            if lon < *lon_coords.first().unwrap() || lon > *lon_coords.last().unwrap() {
-                return Err(RossbyError::InvalidCoordinates {
-                    message: format!(
-                        "Longitude {} is outside the range [{}, {}]",
-                        lon,
-                        lon_coords.first().unwrap(),
-                        lon_coords.last().unwrap()
-                    ),
-                });
-            }
-
-            // Find fractional index
+            // Resolve coordinates outside the grid, then find the fractional index
+            let lon = bounds.apply(Axis::Longitude, lon_value.unwrap(), lon_coords)?;
             crate::interpolation::common::coord_to_index(lon, lon_coords)?
         };
 
         let lat_idx = if let Some(idx) = latitude_idx {
             idx as f64
         } else {
-            // Check if coordinates are within bounds
-            let lat = lat_value.unwrap();
-            if lat < *lat_coords.first().unwrap() || lat > *lat_coords.last().unwrap() {
-                return Err(RossbyError::InvalidCoordinates {
-                    message: format!(
-                        "Latitude {} is outside the range [{}, {}]",
-                        lat,
-                        lat_coords.first().unwrap(),
-                        lat_coords.last().unwrap()
-                    ),
-                });
-            }
-
-            // Find fractional index
+            let lat = bounds.apply(Axis::Latitude, lat_value.unwrap(), lat_coords)?;
             crate::interpolation::common::coord_to_index(lat, lat_coords)?
         };
 
@@ -382,11 +380,6 @@ fn process_point_query(
         indices[lon_dim_idx] = lon_idx;
         indices[lat_dim_idx] = lat_idx;
 
-        // Set time index if present
-        if let Some(idx) = time_dim_idx {
-            indices[idx] = time_index as f64;
-        }
-
         // Get the raw data as a slice
         let data_slice = data.as_slice().ok_or_else(|| RossbyError::DataNotFound {
             message: format!(
@@ -397,15 +390,133 @@ fn process_point_query(
 
         // Interpolate the value
         let value = interpolator.interpolate(data_slice, data.shape(), &indices)?;
+        if value.is_finite() || !snap_nearest_valid {
+            return Ok((value, None));
+        }
 
-        // Add to results
-        values.insert(
-            var_name,
-            serde_json::Value::Number(serde_json::Number::from_f64(value as f64).unwrap()),
-        );
+        // Find the closest cell of the variable holding a valid value
+        let spatial = state
+            .spatial
+            .as_ref()
+            .ok_or_else(|| RossbyError::DataNotFound {
+                message: "No spatial index is available for snapping".to_string(),
+            })?;
+        let (n_lat, n_lon) = (data.shape()[lat_dim_idx], data.shape()[lon_dim_idx]);
+        let cell_indices = |cell: usize| {
+            let mut cell_indices = vec![0; data.ndim()];
+            cell_indices[lat_dim_idx] = cell / lon_coords.len();
+            cell_indices[lon_dim_idx] = cell % lon_coords.len();
+            cell_indices
+        };
+        let neighbor = spatial
+            .nearest_where(lat_value.unwrap(), lon_value.unwrap(), |cell| {
+                let cell_indices = cell_indices(cell);
+                cell_indices[lat_dim_idx] < n_lat
+                    && cell_indices[lon_dim_idx] < n_lon
+                    && data[IxDyn(&cell_indices)].is_finite()
+            })
+            .ok_or_else(|| RossbyError::DataNotFound {
+                message: format!("Variable {} has no valid value to snap to", var_name),
+            })?;
+
+        let cell_indices = cell_indices(neighbor.cell);
+        let snapped = serde_json::json!({
+            "lat": lat_coords[cell_indices[lat_dim_idx]],
+            "lon": lon_coords[cell_indices[lon_dim_idx]],
+            "distance_km": neighbor.distance_km,
+        });
+        Ok((data[IxDyn(&cell_indices)], Some(snapped)))
+    };
+
+    // Interpolate the uncertainty companion of a variable, if it has one
+    let include_uncertainty = params.include_uncertainty.unwrap_or(false);
+    let uncertainty_value = |var_name: &str| -> Result<Option<serde_json::Value>, RossbyError> {
+        if !include_uncertainty || !state.has_variable(var_name) {
+            return Ok(None);
+        }
+        match crate::uncertainty::companion(&state, var_name)? {
+            Some(companion) => {
+                let (value, _) = point_value(companion)?;
+                let mut json = serde_json::Map::new();
+                json.insert("variable".to_string(), companion.into());
+                nan.insert(&mut json, "value".to_string(), value as f64);
+                Ok(Some(json.into()))
+            }
+            None => Ok(None),
+        }
+    };
+
+    // Process each variable, setting failing ones aside in a partial response
+    let mut errors = VariableErrors::new();
+    let mut snapped = serde_json::Map::new();
+    let mut uncertainty = serde_json::Map::new();
+    let mut succeeded = 0;
+    for var_name in variables {
+        let result =
+            point_value(&var_name).and_then(|point| Ok((point, uncertainty_value(&var_name)?)));
+        match result {
+            Ok(((value, cell), companion)) => {
+                if let Some(cell) = cell {
+                    snapped.insert(var_name.clone(), cell);
+                }
+                if let Some(companion) = companion {
+                    uncertainty.insert(var_name.clone(), companion);
+                }
+                nan.insert(&mut values, var_name, value as f64);
+                succeeded += 1;
+            }
+            Err(error) if partial => errors.push(var_name, error),
+            Err(error) => return Err(error),
+        }
     }
 
-    Ok(PointResponse { values })
+    // A partial response still needs one variable that succeeded
+    let errors_json = partial.then(|| errors.to_json());
+    if succeeded == 0 {
+        return Err(errors
+            .into_first_error()
+            .unwrap_or_else(|| no_variables("vars")));
+    }
+
+    // Describe how the query was interpreted
+    let query_resolved = if params.echo_params.unwrap_or(false) {
+        let mut resolved = QueryResolved::new();
+        if let Ok(time_dim) = state.resolve_dimension("time") {
+            resolved.dimension(&state, time_dim, time.param(), &[time_index]);
+        }
+        let horizontal = |names: &[&'static str], label: &str| {
+            names
+                .iter()
+                .find_map(|name| state.resolve_dimension(name).ok())
+                .ok_or_else(|| RossbyError::DataNotFound {
+                    message: format!("The dataset has no {} dimension", label),
+                })
+        };
+        resolved.point = Some(ResolvedPoint::locate(
+            &state,
+            (
+                horizontal(&LON_NAMES, "longitude")?,
+                horizontal(&LAT_NAMES, "latitude")?,
+            ),
+            (lon_value.unwrap(), lat_value.unwrap()),
+            bounds,
+        )?);
+        resolved.interpolation = Some(interpolator.name().to_string());
+        resolved.bounds = Some(bounds.as_str());
+        Some(resolved)
+    } else {
+        None
+    };
+
+    Ok(PointResponse {
+        values,
+        errors: errors_json,
+        snapped,
+        uncertainty,
+        query_resolved,
+        bounds,
+        nan,
+    })
 }
 
 #[cfg(test)]
@@ -413,7 +524,7 @@ mod tests {
     use super::*;
     use crate::config::Config;
     use crate::state::{AttributeValue, Dimension, Metadata, Variable};
-    use ndarray::{Array, IxDyn};
+    use ndarray::Array;
     use std::collections::HashMap;
 
     // Helper function to create a test AppState
@@ -472,6 +583,7 @@ mod tests {
             dimensions,
             variables,
             coordinates,
+            groups: HashMap::new(),
         };
 
         // Create data map
@@ -541,6 +653,7 @@ mod tests {
             dimensions,
             variables,
             coordinates,
+            groups: HashMap::new(),
         };
 
         // Create data map
@@ -576,6 +689,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: Some("nearest".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params).unwrap();
@@ -596,6 +715,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: Some("bilinear".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params).unwrap();
@@ -623,6 +748,12 @@ mod tests {
             time_index: None,
             vars: "temperature,humidity".to_string(), // humidity doesn't exist
             interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -635,6 +766,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_partial_response() {
+        let state = create_test_state();
+        let query = |vars: &str| PointQuery {
+            lon: Some(100.0),
+            lat: Some(10.0),
+            time: None,
+            _longitude: None,
+            _latitude: None,
+            _time: None,
+            __longitude_index: None,
+            __latitude_index: None,
+            __time_index: None,
+            time_index: None,
+            vars: vars.to_string(),
+            interpolation: None,
+            bounds: None,
+            partial: Some(true),
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
+        };
+
+        // The failing variable is reported, the other one still answered
+        let result = process_point_query(state.clone(), query("temperature,humidity")).unwrap();
+        assert_eq!(
+            result.values.get("temperature").unwrap().as_f64(),
+            Some(1.0)
+        );
+        assert!(!result.values.contains_key("humidity"));
+        let errors = result.errors.unwrap();
+        assert_eq!(errors["humidity"]["kind"], "variable_not_found");
+
+        // Nothing failed: an empty errors section
+        let result = process_point_query(state.clone(), query("temperature")).unwrap();
+        assert_eq!(result.errors, Some(serde_json::json!({})));
+
+        // Nothing succeeded: the request fails
+        assert!(matches!(
+            process_point_query(state.clone(), query("humidity")),
+            Err(RossbyError::VariableNotFound { .. })
+        ));
+    }
+
     #[test]
     fn test_out_of_bounds() {
         let state = create_test_state();
@@ -653,6 +829,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -678,6 +860,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -690,6 +878,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bounds_modes() {
+        let state = create_test_state();
+        let query = |lon: f64, bounds: Option<&str>| PointQuery {
+            lon: Some(lon),
+            lat: Some(10.0),
+            time: None,
+            _longitude: None,
+            _latitude: None,
+            _time: None,
+            __longitude_index: None,
+            __latitude_index: None,
+            __time_index: None,
+            time_index: None,
+            vars: "temperature".to_string(),
+            interpolation: None,
+            bounds: bounds.map(str::to_string),
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
+        };
+
+        // Clamped to the eastern edge of the grid
+        let edge = process_point_query(state.clone(), query(120.0, None)).unwrap();
+        let clamped = process_point_query(state.clone(), query(130.0, Some("clamp"))).unwrap();
+        assert_eq!(clamped.values, edge.values);
+        assert_eq!(clamped.bounds, BoundsMode::Clamp);
+
+        // The test grid is regional, so longitudes cannot wrap
+        let result = process_point_query(state.clone(), query(110.0, Some("wrap")));
+        assert!(matches!(result, Err(RossbyError::InvalidParameter { .. })));
+
+        let result = process_point_query(state, query(110.0, Some("reflect")));
+        assert!(matches!(result, Err(RossbyError::InvalidParameter { .. })));
+    }
+
     #[test]
     fn test_invalid_interpolation() {
         let state = create_test_state();
@@ -707,6 +933,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: Some("invalid_method".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -736,6 +968,12 @@ mod tests {
             time_index: None,
             vars: "".to_string(), // Empty variable list
             interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -767,6 +1005,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: Some("nearest".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -801,6 +1045,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: Some("nearest".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state_with_aliases.clone(), params);
@@ -837,6 +1087,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: Some("nearest".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -868,6 +1124,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -900,6 +1162,12 @@ mod tests {
             time_index: Some(0), // Using deprecated parameter
             vars: "temperature".to_string(),
             interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -932,6 +1200,12 @@ mod tests {
             time_index: None,
             vars: "temperature".to_string(),
             interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
         };
 
         let result = process_point_query(state.clone(), params);
@@ -945,4 +1219,197 @@ mod tests {
             .unwrap();
         assert_eq!(value, 1.0);
     }
+
+    #[test]
+    fn test_snap_nearest_valid() {
+        // Put the point at lat=10, lon=100 on missing data
+        let state = create_test_state();
+        let mut data = state.data.clone();
+        data.get_mut("temperature").unwrap()[IxDyn(&[0, 0])] = f32::NAN;
+        let state = Arc::new(AppState::new(
+            Config::default(),
+            state.metadata.clone(),
+            data,
+        ));
+
+        let params = |snap: Option<&str>| PointQuery {
+            lon: Some(102.0),
+            lat: Some(11.0),
+            time: None,
+            _longitude: None,
+            _latitude: None,
+            _time: None,
+            __longitude_index: None,
+            __latitude_index: None,
+            __time_index: None,
+            time_index: None,
+            vars: "temperature".to_string(),
+            interpolation: Some("nearest".to_string()),
+            bounds: None,
+            partial: None,
+            snap: snap.map(str::to_string),
+            include_uncertainty: None,
+            nan: None,
+            echo_params: None,
+        };
+
+        // The closest valid cell is lat=10, lon=110 rather than lat=20, lon=100
+        let result = process_point_query(state.clone(), params(Some("nearest_valid"))).unwrap();
+        assert_eq!(result.values["temperature"].as_f64().unwrap(), 2.0);
+        let snapped = &result.snapped["temperature"];
+        assert_eq!(snapped["lat"], 10.0);
+        assert_eq!(snapped["lon"], 110.0);
+        let distance = snapped["distance_km"].as_f64().unwrap();
+        assert!(distance > 800.0 && distance < 1000.0, "{}", distance);
+
+        // Valid points are not snapped
+        let mut valid = params(Some("nearest_valid"));
+        valid.lon = Some(119.0);
+        let result = process_point_query(state.clone(), valid).unwrap();
+        assert_eq!(result.values["temperature"].as_f64().unwrap(), 3.0);
+        assert!(result.snapped.is_empty());
+
+        assert!(matches!(
+            process_point_query(state.clone(), params(Some("closest"))),
+            Err(RossbyError::InvalidParameter { param, .. }) if param == "snap"
+        ));
+    }
+
+    #[test]
+    fn test_include_uncertainty() {
+        let state = create_test_state();
+        let mut metadata = state.metadata.clone();
+        let mut data = state.data.clone();
+        let mut companion = metadata.variables["temperature"].clone();
+        companion.name = "temperature_stddev".to_string();
+        metadata
+            .variables
+            .insert("temperature_stddev".to_string(), companion);
+        data.insert(
+            "temperature_stddev".to_string(),
+            Array::from_shape_vec(IxDyn(&[2, 3]), vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6]).unwrap(),
+        );
+        let mut config = Config::default();
+        config
+            .data
+            .uncertainty
+            .insert("temperature".to_string(), "temperature_stddev".to_string());
+        let state = Arc::new(AppState::new(config, metadata, data));
+
+        let params = |include_uncertainty: Option<bool>| PointQuery {
+            lon: Some(110.0),
+            lat: Some(20.0),
+            time: None,
+            _longitude: None,
+            _latitude: None,
+            _time: None,
+            __longitude_index: None,
+            __latitude_index: None,
+            __time_index: None,
+            time_index: None,
+            vars: "temperature".to_string(),
+            interpolation: Some("nearest".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty,
+            nan: None,
+            echo_params: None,
+        };
+
+        let result = process_point_query(state.clone(), params(Some(true))).unwrap();
+        assert_eq!(result.values["temperature"].as_f64().unwrap(), 5.0);
+        let uncertainty = &result.uncertainty["temperature"];
+        assert_eq!(uncertainty["variable"], "temperature_stddev");
+        assert!((uncertainty["value"].as_f64().unwrap() - 0.5).abs() < 1e-6);
+
+        // Companions are only returned on request
+        let result = process_point_query(state, params(None)).unwrap();
+        assert!(result.uncertainty.is_empty());
+    }
+
+    #[test]
+    fn test_nan_policy() {
+        // Put the point at lat=10, lon=100 on missing data
+        let state = create_test_state();
+        let mut data = state.data.clone();
+        data.get_mut("temperature").unwrap()[IxDyn(&[0, 0])] = f32::NAN;
+        let state = Arc::new(AppState::new(
+            Config::default(),
+            state.metadata.clone(),
+            data,
+        ));
+
+        let params = |nan: Option<&str>| PointQuery {
+            lon: Some(100.0),
+            lat: Some(10.0),
+            time: None,
+            _longitude: None,
+            _latitude: None,
+            _time: None,
+            __longitude_index: None,
+            __latitude_index: None,
+            __time_index: None,
+            time_index: None,
+            vars: "temperature".to_string(),
+            interpolation: Some("nearest".to_string()),
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: nan.map(str::to_string),
+            echo_params: None,
+        };
+
+        let result = process_point_query(state.clone(), params(None)).unwrap();
+        assert_eq!(result.nan, NanPolicy::Null);
+        assert!(result.values["temperature"].is_null());
+
+        let result = process_point_query(state.clone(), params(Some("string"))).unwrap();
+        assert_eq!(result.values["temperature"], "NaN");
+
+        let result = process_point_query(state.clone(), params(Some("omit"))).unwrap();
+        assert!(result.values.is_empty());
+
+        assert!(matches!(
+            process_point_query(state.clone(), params(Some("zero"))),
+            Err(RossbyError::InvalidParameter { param, .. }) if param == "nan"
+        ));
+    }
+
+    #[test]
+    fn test_echo_params() {
+        let state = create_test_state();
+        let params = |echo_params: Option<bool>| PointQuery {
+            lon: None,
+            lat: Some(15.0),
+            time: None,
+            _longitude: None,
+            _latitude: None,
+            _time: None,
+            __longitude_index: Some(2),
+            __latitude_index: None,
+            __time_index: None,
+            time_index: None,
+            vars: "temperature".to_string(),
+            interpolation: None,
+            bounds: None,
+            partial: None,
+            snap: None,
+            include_uncertainty: None,
+            nan: None,
+            echo_params,
+        };
+
+        let result = process_point_query(state.clone(), params(None)).unwrap();
+        assert!(result.query_resolved.is_none());
+
+        let result = process_point_query(state.clone(), params(Some(true))).unwrap();
+        let resolved = result.query_resolved.unwrap();
+        let point = resolved.point.unwrap();
+        assert_eq!((point.lon, point.lon_index), (120.0, 2.0));
+        assert_eq!((point.lat, point.lat_index), (15.0, 0.5));
+        assert_eq!(resolved.interpolation.as_deref(), Some("bilinear"));
+        assert_eq!(resolved.bounds, Some("error"));
+    }
 }
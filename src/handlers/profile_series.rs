@@ -0,0 +1,930 @@
+//! Profile time series endpoint handler.
+//!
+//! Interpolates a 4D variable (e.g. ocean temperature on time, depth,
+//! latitude and longitude) at one horizontal location for every selected time
+//! step and vertical level, and returns the resulting time × level matrix as
+//! JSON, as an Arrow table, or rendered as a time-depth section through the
+//! same colormaps as `/image`.
+
+use arrow::array::{ArrayRef, Float32Array, Float64Array};
+use arrow::record_batch::RecordBatch;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use ndarray::Array2;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, info_span};
+
+use crate::bounds::{Axis, BoundsMode, BOUNDS_HEADER};
+use crate::colormaps::{self, LatitudeScaling};
+use crate::error::{Result, RossbyError};
+use crate::field::find_lat_lon_axes;
+use crate::handlers::image::generate_image;
+use crate::handlers::stats::selection_to_json;
+use crate::interpolation::{common::coord_to_index, get_interpolator, Interpolator};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::packed::VariableValues;
+use crate::query::Selection;
+use crate::slice_stats::{summarize, MissingData};
+use crate::state::{AppState, AttributeValue};
+
+/// Default image dimensions for `format=png`
+pub(crate) const DEFAULT_WIDTH: u32 = 800;
+pub(crate) const DEFAULT_HEIGHT: u32 = 400;
+
+/// Grid points taken on each side of the query point, enough for bicubic
+/// interpolation
+const STENCIL_RADIUS: usize = 2;
+
+/// Query parameters for the profile series endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProfileSeriesQuery {
+    /// Variable name
+    pub var: String,
+    /// Longitude of the profile
+    pub lon: f64,
+    /// Latitude of the profile
+    pub lat: f64,
+    /// Horizontal interpolation method (nearest, bilinear, bicubic; default bilinear)
+    #[serde(default)]
+    pub interpolation: Option<String>,
+    /// Handling of coordinates outside the grid (error, clamp or wrap)
+    #[serde(default)]
+    pub bounds: Option<String>,
+    /// Vertical dimension, if the variable has more than one non-time,
+    /// non-horizontal dimension
+    #[serde(default)]
+    pub vertical_dim: Option<String>,
+    /// Output format (json, arrow or png; default json)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Colormap name for `format=png`
+    #[serde(default)]
+    pub colormap: Option<String>,
+    /// Image width in pixels for `format=png`
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Image height in pixels for `format=png`
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Dimension selectors: time and vertical selections (default: all
+    /// slices) and single slices of any other non-horizontal dimensions
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// A variable interpolated at one location over time and the vertical
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileSeries {
+    /// Time dimension name and the selected time coordinates
+    pub time_dim: String,
+    pub times: Vec<f64>,
+    /// Vertical dimension name and the selected level coordinates
+    pub vertical_dim: String,
+    pub levels: Vec<f64>,
+    /// Values with one row per time step and one column per level; missing
+    /// values are NaN
+    pub values: Array2<f32>,
+}
+
+/// Horizontal position of a profile as fractional grid indices
+#[derive(Debug, Clone, Copy)]
+struct GridPoint {
+    lat_axis: usize,
+    lon_axis: usize,
+    lat_index: f64,
+    lon_index: f64,
+}
+
+/// Handle GET /profile_series requests
+pub async fn profile_series_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ProfileSeriesQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/profile_series",
+        request_id = %request_id,
+        var = %params.var,
+        lon = params.lon,
+        lat = params.lat,
+        "Processing profile series request"
+    );
+
+    match process_profile_series_query(&state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/profile_series",
+                request_id = %request_id,
+                var = %params.var,
+                duration_us = duration.as_micros() as u64,
+                "Profile series request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/profile_series",
+                &request_id,
+                Some(&format!(
+                    "var={}, lon={}, lat={}",
+                    params.var, params.lon, params.lat
+                )),
+            );
+            let status = match &error {
+                RossbyError::ImageGeneration { .. } | RossbyError::Conversion { .. } => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_profile_series_query(state: &AppState, params: &ProfileSeriesQuery) -> Result<Response> {
+    let format = params.format.as_deref().unwrap_or("json").to_lowercase();
+    if !["json", "arrow", "png"].contains(&format.as_str()) {
+        return Err(RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: format!(
+                "Unsupported format: {}. Valid values are 'json', 'arrow' or 'png'",
+                format
+            ),
+        });
+    }
+    let interpolator = get_interpolator(params.interpolation.as_deref().unwrap_or("bilinear"))?;
+    let bounds = BoundsMode::from_request(params.bounds.as_deref(), &state.config.data.bounds)?;
+    if !state.has_variable(&params.var) {
+        return Err(RossbyError::VariableNotFound {
+            name: params.var.clone(),
+        });
+    }
+
+    let extract_stage = info_span!("interpolate").entered();
+    let (series, pinned) = profile_series(state, params, bounds, interpolator.as_ref())?;
+    extract_stage.exit();
+
+    let mut response = match format.as_str() {
+        "png" => render_png(
+            &series,
+            params,
+            state.config.data.colormap(params.colormap.as_deref()),
+        )?,
+        "arrow" => {
+            let _stage = info_span!("serialize").entered();
+            (
+                StatusCode::OK,
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/vnd.apache.arrow.stream"),
+                )],
+                series_to_arrow(&params.var, &series)?,
+            )
+                .into_response()
+        }
+        _ => {
+            let _stage = info_span!("serialize").entered();
+            let var_meta = state.get_variable_metadata_checked(&params.var)?;
+            let units = match var_meta.attributes.get("units") {
+                Some(AttributeValue::Text(units)) => Some(units.clone()),
+                _ => None,
+            };
+            let selection: HashMap<String, (usize, f64)> = pinned
+                .iter()
+                .map(|(dim, &index)| (dim.clone(), (index, coordinate_value(state, dim, index))))
+                .collect();
+            Json(serde_json::json!({
+                "var": params.var,
+                "units": units,
+                "lon": params.lon,
+                "lat": params.lat,
+                "interpolation": interpolator.name(),
+                "time": {
+                    "dimension": series.time_dim,
+                    "values": series.times,
+                },
+                "vertical": {
+                    "dimension": series.vertical_dim,
+                    "values": series.levels,
+                },
+                "selection": selection_to_json(&selection),
+                "shape": series.values.shape(),
+                "values": values_to_json(&series.values),
+                "stats": summarize(series.values.iter().copied(), &MissingData::default()),
+            }))
+            .into_response()
+        }
+    };
+    response
+        .headers_mut()
+        .insert(BOUNDS_HEADER, HeaderValue::from_static(bounds.as_str()));
+    Ok(response)
+}
+
+/// Interpolate a variable at the query location for every selected time step
+/// and level
+///
+/// Returns the series and the indices of the other pinned dimensions.
+fn profile_series(
+    state: &AppState,
+    params: &ProfileSeriesQuery,
+    bounds: BoundsMode,
+    interpolator: &dyn Interpolator,
+) -> Result<(ProfileSeries, HashMap<String, usize>)> {
+    let var_meta = state.get_variable_metadata_checked(&params.var)?;
+    let dimensions = &var_meta.dimensions;
+    if find_lat_lon_axes(dimensions).is_none() {
+        return Err(RossbyError::InvalidParameter {
+            param: "var".to_string(),
+            message: format!(
+                "Variable '{}' has no latitude and longitude dimensions",
+                params.var
+            ),
+        });
+    }
+
+    let has_dimension = |dim: &str| dimensions.iter().any(|d| d == dim);
+    let time_dim = time_dimension(state, &params.var)?;
+    let vertical_dim = vertical_dimension(
+        state,
+        &params.var,
+        Some(&time_dim),
+        params.vertical_dim.as_deref(),
+    )?;
+
+    // Time and the vertical may be selected by any range or list, every other
+    // non-horizontal dimension is pinned to a single slice
+    let selection = Selection::parse(state, &params.dimension_params)?;
+    if let Some(selected) = selection.iter().find(|s| !has_dimension(&s.dimension)) {
+        return Err(RossbyError::InvalidParameter {
+            param: selected.param.clone(),
+            message: format!(
+                "Dimension '{}' is not a dimension of '{}'",
+                selected.dimension, params.var
+            ),
+        });
+    }
+    let all_indices = |dim: &str| -> Result<Vec<usize>> {
+        match selection.get(dim) {
+            Some(selected) => selected.resolve(state),
+            None => Ok((0..state.metadata.dimensions.get(dim).map_or(0, |d| d.size)).collect()),
+        }
+    };
+    let time_indices = all_indices(&time_dim)?;
+    let level_indices = all_indices(&vertical_dim)?;
+    let pinned = pin_dimensions(state, dimensions, &selection, &[&time_dim, &vertical_dim])?;
+
+    // Resolve the location once; every slice shares the horizontal grid
+    let point = GridPoint::locate(state, dimensions, params.lon, params.lat, bounds)?;
+
+    // Only the cells around the location are read, so packed values are
+    // converted one at a time
+    let data = state.get_variable_values_checked(&params.var)?;
+    let missing = MissingData::for_variable(var_meta);
+    let mut base = base_index(dimensions, &pinned);
+    let time_axis = dimensions.iter().position(|d| *d == time_dim).unwrap_or(0);
+    let level_axis = dimensions
+        .iter()
+        .position(|d| *d == vertical_dim)
+        .unwrap_or(0);
+
+    let mut values = Array2::from_elem((time_indices.len(), level_indices.len()), f32::NAN);
+    for (row, &time_index) in time_indices.iter().enumerate() {
+        for (column, &level_index) in level_indices.iter().enumerate() {
+            base[time_axis] = time_index;
+            base[level_axis] = level_index;
+            values[[row, column]] = interpolate_at(data, &base, point, &missing, interpolator)?;
+        }
+    }
+
+    let coordinates = |dim: &str, indices: &[usize]| -> Vec<f64> {
+        indices
+            .iter()
+            .map(|&index| coordinate_value(state, dim, index))
+            .collect()
+    };
+    Ok((
+        ProfileSeries {
+            times: coordinates(&time_dim, &time_indices),
+            levels: coordinates(&vertical_dim, &level_indices),
+            time_dim,
+            vertical_dim,
+            values,
+        },
+        pinned,
+    ))
+}
+
+/// Time dimension of a variable
+pub(crate) fn time_dimension(state: &AppState, var: &str) -> Result<String> {
+    let var_meta = state.get_variable_metadata_checked(var)?;
+    state
+        .resolve_dimension("time")
+        .ok()
+        .filter(|dim| var_meta.dimensions.iter().any(|d| d == dim))
+        .map(str::to_string)
+        .ok_or_else(|| RossbyError::InvalidParameter {
+            param: "var".to_string(),
+            message: format!("Variable '{}' has no time dimension", var),
+        })
+}
+
+/// Vertical dimension of a variable: the only non-time, non-horizontal
+/// dimension unless `requested` names one
+pub(crate) fn vertical_dimension(
+    state: &AppState,
+    var: &str,
+    time_dim: Option<&str>,
+    requested: Option<&str>,
+) -> Result<String> {
+    let var_meta = state.get_variable_metadata_checked(var)?;
+    let dimensions = &var_meta.dimensions;
+    let (lat_axis, lon_axis) = find_lat_lon_axes(dimensions).unwrap_or((usize::MAX, usize::MAX));
+    let others: Vec<&String> = dimensions
+        .iter()
+        .enumerate()
+        .filter(|&(axis, dim)| {
+            axis != lat_axis && axis != lon_axis && Some(dim.as_str()) != time_dim
+        })
+        .map(|(_, dim)| dim)
+        .collect();
+    match requested {
+        Some(name) => {
+            let dim = state.resolve_dimension(name)?;
+            if !others.iter().any(|d| d.as_str() == dim) {
+                return Err(RossbyError::InvalidParameter {
+                    param: "vertical_dim".to_string(),
+                    message: format!(
+                        "'{}' is not a vertical dimension of '{}'; candidates are {:?}",
+                        name, var, others
+                    ),
+                });
+            }
+            Ok(dim.to_string())
+        }
+        None => match others.as_slice() {
+            [dim] => Ok(dim.to_string()),
+            [] => Err(RossbyError::InvalidParameter {
+                param: "var".to_string(),
+                message: format!("Variable '{}' has no vertical dimension", var),
+            }),
+            _ => Err(RossbyError::InvalidParameter {
+                param: "vertical_dim".to_string(),
+                message: format!(
+                    "Variable '{}' has several candidate vertical dimensions {:?}; \
+                     name one with vertical_dim",
+                    var, others
+                ),
+            }),
+        },
+    }
+}
+
+/// Values of a variable interpolated at one location along one dimension
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PointSeries {
+    /// Dimension the series runs along
+    pub dimension: String,
+    /// Selected coordinates of that dimension
+    pub coordinates: Vec<f64>,
+    /// Interpolated values; missing values are NaN
+    pub values: Vec<f32>,
+    /// Indices of the other non-horizontal dimensions
+    pub pinned: HashMap<String, usize>,
+}
+
+/// Interpolate a variable at a location for every selected slice of one
+/// dimension (default: all), with the other non-horizontal dimensions pinned
+/// to single slices by `dimension_params`
+pub(crate) fn point_series(
+    state: &AppState,
+    var: &str,
+    location: (f64, f64),
+    dimension: &str,
+    dimension_params: &HashMap<String, String>,
+    bounds: BoundsMode,
+    interpolator: &dyn Interpolator,
+) -> Result<PointSeries> {
+    let mut coordinates = Vec::new();
+    let mut values = Vec::new();
+    let pinned = for_each_point_value(
+        state,
+        var,
+        location,
+        dimension,
+        dimension_params,
+        bounds,
+        interpolator,
+        |index, value| {
+            coordinates.push(coordinate_value(state, dimension, index));
+            values.push(value);
+        },
+    )?;
+
+    Ok(PointSeries {
+        dimension: dimension.to_string(),
+        coordinates,
+        values,
+        pinned,
+    })
+}
+
+/// Interpolate a variable at a location slice by slice, as [`point_series`]
+/// does, passing the index and value of every selected slice to `visit`
+/// without keeping the series
+///
+/// Returns the indices of the other non-horizontal dimensions.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn for_each_point_value(
+    state: &AppState,
+    var: &str,
+    (lon, lat): (f64, f64),
+    dimension: &str,
+    dimension_params: &HashMap<String, String>,
+    bounds: BoundsMode,
+    interpolator: &dyn Interpolator,
+    mut visit: impl FnMut(usize, f32),
+) -> Result<HashMap<String, usize>> {
+    let var_meta = state.get_variable_metadata_checked(var)?;
+    let dimensions = &var_meta.dimensions;
+    let axis = dimensions
+        .iter()
+        .position(|d| d == dimension)
+        .ok_or_else(|| RossbyError::InvalidParameter {
+            param: "var".to_string(),
+            message: format!("Variable '{}' has no dimension '{}'", var, dimension),
+        })?;
+
+    let selection = Selection::parse(state, dimension_params)?;
+    if let Some(selected) = selection
+        .iter()
+        .find(|s| !dimensions.contains(&s.dimension))
+    {
+        return Err(RossbyError::InvalidParameter {
+            param: selected.param.clone(),
+            message: format!(
+                "Dimension '{}' is not a dimension of '{}'",
+                selected.dimension, var
+            ),
+        });
+    }
+    let indices = match selection.get(dimension) {
+        Some(selected) => selected.resolve(state)?,
+        None => (0..var_meta.shape[axis]).collect(),
+    };
+    let pinned = pin_dimensions(state, dimensions, &selection, &[dimension])?;
+    let point = GridPoint::locate(state, dimensions, lon, lat, bounds)?;
+
+    let data = state.get_variable_values_checked(var)?;
+    let missing = MissingData::for_variable(var_meta);
+    let mut base = base_index(dimensions, &pinned);
+    for index in indices {
+        base[axis] = index;
+        visit(
+            index,
+            interpolate_at(data, &base, point, &missing, interpolator)?,
+        );
+    }
+    Ok(pinned)
+}
+
+/// Single slices of the non-horizontal dimensions other than `free`, index 0
+/// unless selected
+fn pin_dimensions(
+    state: &AppState,
+    dimensions: &[String],
+    selection: &Selection,
+    free: &[&str],
+) -> Result<HashMap<String, usize>> {
+    let (lat_axis, lon_axis) = find_lat_lon_axes(dimensions).unwrap_or((usize::MAX, usize::MAX));
+    let mut pinned = HashMap::new();
+    for (axis, dim_name) in dimensions.iter().enumerate() {
+        let selected = selection.get(dim_name);
+        if axis == lat_axis || axis == lon_axis {
+            if let Some(selected) = selected {
+                return Err(RossbyError::InvalidParameter {
+                    param: selected.param.clone(),
+                    message: "Horizontal dimensions cannot be selected; use lon and lat instead"
+                        .to_string(),
+                });
+            }
+        } else if !free.contains(&dim_name.as_str()) {
+            let index = selected.map(|s| s.resolve_single(state)).transpose()?;
+            pinned.insert(dim_name.clone(), index.unwrap_or(0));
+        }
+    }
+    Ok(pinned)
+}
+
+/// Index into a variable with the pinned dimensions set and the others 0
+fn base_index(dimensions: &[String], pinned: &HashMap<String, usize>) -> Vec<usize> {
+    dimensions
+        .iter()
+        .map(|dim_name| pinned.get(dim_name).copied().unwrap_or(0))
+        .collect()
+}
+
+impl GridPoint {
+    /// Fractional grid indices of a location on the horizontal axes of a
+    /// variable
+    fn locate(
+        state: &AppState,
+        dimensions: &[String],
+        lon: f64,
+        lat: f64,
+        bounds: BoundsMode,
+    ) -> Result<Self> {
+        let (lat_axis, lon_axis) =
+            find_lat_lon_axes(dimensions).ok_or_else(|| RossbyError::InvalidParameter {
+                param: "var".to_string(),
+                message: "The variable has no latitude and longitude dimensions".to_string(),
+            })?;
+        let lon_coords = state.get_coordinate_checked(&dimensions[lon_axis])?;
+        let lat_coords = state.get_coordinate_checked(&dimensions[lat_axis])?;
+        let lon = bounds.apply(Axis::Longitude, lon, lon_coords)?;
+        let lat = bounds.apply(Axis::Latitude, lat, lat_coords)?;
+        Ok(Self {
+            lat_axis,
+            lon_axis,
+            lat_index: coord_to_index(lat, lat_coords)?,
+            lon_index: coord_to_index(lon, lon_coords)?,
+        })
+    }
+}
+
+/// Interpolate one horizontal slice of `data` at a grid point
+///
+/// `base` pins every non-horizontal axis. The interpolation runs on the few
+/// grid points around the location, with missing values replaced by NaN, so
+/// fill values such as those below the sea floor never leak into the result;
+/// values interpolated from a missing neighbour are missing as well.
+fn interpolate_at(
+    data: VariableValues<'_>,
+    base: &[usize],
+    point: GridPoint,
+    missing: &MissingData,
+    interpolator: &dyn Interpolator,
+) -> Result<f32> {
+    let window = |index: f64, size: usize| {
+        let center = (index.max(0.0).floor() as usize).min(size.saturating_sub(1));
+        let first = center.saturating_sub(STENCIL_RADIUS - 1);
+        let last = (center + STENCIL_RADIUS).min(size.saturating_sub(1));
+        (first, last)
+    };
+    let shape = data.shape();
+    let (lat_first, lat_last) = window(point.lat_index, shape[point.lat_axis]);
+    let (lon_first, lon_last) = window(point.lon_index, shape[point.lon_axis]);
+
+    let rows = lat_last - lat_first + 1;
+    let columns = lon_last - lon_first + 1;
+    let mut stencil = Vec::with_capacity(rows * columns);
+    let mut index = base.to_vec();
+    for lat in lat_first..=lat_last {
+        for lon in lon_first..=lon_last {
+            index[point.lat_axis] = lat;
+            index[point.lon_axis] = lon;
+            let value = data.get(&index).unwrap_or(f32::NAN);
+            stencil.push(if missing.is_missing(value) {
+                f32::NAN
+            } else {
+                value
+            });
+        }
+    }
+
+    // A leading unit axis keeps the stencil clear of the special-cased 2D
+    // edge points of the bilinear interpolator
+    interpolator.interpolate(
+        &stencil,
+        &[1, rows, columns],
+        &[
+            0.0,
+            point.lat_index - lat_first as f64,
+            point.lon_index - lon_first as f64,
+        ],
+    )
+}
+
+/// Coordinate value of an index, or the index itself without coordinates
+pub(crate) fn coordinate_value(state: &AppState, dim: &str, index: usize) -> f64 {
+    state
+        .get_coordinate(dim)
+        .and_then(|coords| coords.get(index).copied())
+        .unwrap_or(index as f64)
+}
+
+/// Matrix rows as nested JSON arrays, with non-finite values as null
+fn values_to_json(values: &Array2<f32>) -> serde_json::Value {
+    values
+        .outer_iter()
+        .map(|row| {
+            row.iter()
+                .map(|&v| {
+                    serde_json::Number::from_f64(v as f64)
+                        .map(serde_json::Value::Number)
+                        .unwrap_or(serde_json::Value::Null)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Encode a series as an Arrow IPC stream with one row per time step and level
+///
+/// The value column carries the `shape` and `dimensions` metadata used by
+/// `/data`, so clients can reshape it into the time × level matrix.
+fn series_to_arrow(var: &str, series: &ProfileSeries) -> Result<Vec<u8>> {
+    let conversion = |message: String| RossbyError::Conversion { message };
+    let levels = series.levels.len();
+    let times: Vec<f64> = series
+        .times
+        .iter()
+        .flat_map(|&time| std::iter::repeat_n(time, levels))
+        .collect();
+    let level_column: Vec<f64> = series
+        .times
+        .iter()
+        .flat_map(|_| series.levels.iter().copied())
+        .collect();
+    let values: Vec<f32> = series.values.iter().copied().collect();
+
+    let metadata = HashMap::from([
+        (
+            "shape".to_string(),
+            serde_json::to_string(series.values.shape())?,
+        ),
+        (
+            "dimensions".to_string(),
+            serde_json::to_string(&[&series.time_dim, &series.vertical_dim])?,
+        ),
+    ]);
+    let schema = Arc::new(Schema::new(vec![
+        Field::new(&series.time_dim, DataType::Float64, false),
+        Field::new(&series.vertical_dim, DataType::Float64, false),
+        Field::new(var, DataType::Float32, false).with_metadata(metadata),
+    ]));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Float64Array::from(times)),
+        Arc::new(Float64Array::from(level_column)),
+        Arc::new(Float32Array::from(values)),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns)
+        .map_err(|e| conversion(format!("Failed to create Arrow record batch: {}", e)))?;
+
+    let mut output = Vec::new();
+    let mut writer = StreamWriter::try_new(&mut output, &schema)
+        .map_err(|e| conversion(format!("Failed to create Arrow IPC writer: {}", e)))?;
+    writer
+        .write(&batch)
+        .map_err(|e| conversion(format!("Failed to write Arrow record batch: {}", e)))?;
+    writer
+        .finish()
+        .map_err(|e| conversion(format!("Failed to finalize Arrow IPC stream: {}", e)))?;
+    Ok(output)
+}
+
+/// Render a series as a time-depth section
+///
+/// Time runs from left to right and the levels from top to bottom in their
+/// stored order, so a depth axis increasing downwards shows the surface at
+/// the top. The colormap spans the finite values of the series.
+fn render_png(
+    series: &ProfileSeries,
+    params: &ProfileSeriesQuery,
+    colormap: &str,
+) -> Result<Response> {
+    let width = params.width.unwrap_or(DEFAULT_WIDTH);
+    let height = params.height.unwrap_or(DEFAULT_HEIGHT);
+    if width < 2 || height < 2 || width > 8192 || height > 8192 {
+        return Err(RossbyError::InvalidParameter {
+            param: "width/height".to_string(),
+            message: "Image dimensions must be between 2 and 8192 pixels".to_string(),
+        });
+    }
+    if series.values.is_empty() {
+        return Err(RossbyError::InvalidParameter {
+            param: "time".to_string(),
+            message: "No time steps or levels selected".to_string(),
+        });
+    }
+    let colormap = colormaps::get_colormap(colormap)?;
+
+    let stats = summarize(series.values.iter().copied(), &MissingData::default());
+    let value_range = match (stats.min, stats.max) {
+        (Some(min), Some(max)) => (min as f32, max as f32),
+        _ => (0.0, 1.0),
+    };
+
+    let render_stage = info_span!("render").entered();
+    let section = series.values.t();
+    let img = generate_image(
+        section,
+        width,
+        height,
+        colormap.as_ref(),
+        "auto",
+        LatitudeScaling::PlateCarree,
+        (0.0, 0.0),
+        value_range,
+    )?;
+    render_stage.exit();
+
+    let _stage = info_span!("encode").entered();
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, image::ImageFormat::Png)
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to encode PNG: {}", e),
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("image/png"))],
+        buffer.into_inner(),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata, Variable};
+    use ndarray::{ArrayD, IxDyn};
+
+    const FILL: f32 = -999.0;
+
+    /// Ocean temperature on (time, depth, lat, lon) with the value
+    /// `time * 100 + depth * 10 + lat + lon`, and fill values below 30 m
+    /// at the last longitude
+    fn create_test_state() -> AppState {
+        let sizes = [("time", 3), ("depth", 4), ("lat", 3), ("lon", 4)];
+        let dimensions = sizes
+            .iter()
+            .map(|&(name, size)| {
+                (
+                    name.to_string(),
+                    Dimension {
+                        name: name.to_string(),
+                        size,
+                        is_unlimited: false,
+                    },
+                )
+            })
+            .collect();
+
+        let mut attributes = HashMap::new();
+        attributes.insert(
+            "units".to_string(),
+            AttributeValue::Text("degC".to_string()),
+        );
+        attributes.insert(
+            "_FillValue".to_string(),
+            AttributeValue::Number(FILL as f64),
+        );
+        let mut variables = HashMap::new();
+        variables.insert(
+            "thetao".to_string(),
+            Variable {
+                name: "thetao".to_string(),
+                dimensions: sizes.iter().map(|(name, _)| name.to_string()).collect(),
+                shape: sizes.iter().map(|&(_, size)| size).collect(),
+                attributes,
+                dtype: "f32".to_string(),
+            },
+        );
+
+        let mut coordinates = HashMap::new();
+        coordinates.insert("time".to_string(), vec![0.0, 24.0, 48.0]);
+        coordinates.insert("depth".to_string(), vec![0.0, 10.0, 30.0, 100.0]);
+        coordinates.insert("lat".to_string(), vec![-10.0, 0.0, 10.0]);
+        coordinates.insert("lon".to_string(), vec![0.0, 10.0, 20.0, 30.0]);
+
+        let values = ArrayD::from_shape_fn(IxDyn(&[3, 4, 3, 4]), |index| {
+            let (t, k, y, x) = (index[0], index[1], index[2], index[3]);
+            if k >= 2 && x == 3 {
+                FILL
+            } else {
+                (t * 100 + k * 10 + y + x) as f32
+            }
+        });
+        let mut data = HashMap::new();
+        data.insert("thetao".to_string(), values);
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables,
+            coordinates,
+            groups: HashMap::new(),
+        };
+        AppState::new(Config::default(), metadata, data)
+    }
+
+    fn query(lon: f64, lat: f64, extra: &[(&str, &str)]) -> ProfileSeriesQuery {
+        ProfileSeriesQuery {
+            var: "thetao".to_string(),
+            lon,
+            lat,
+            interpolation: None,
+            bounds: None,
+            vertical_dim: None,
+            format: None,
+            colormap: None,
+            width: None,
+            height: None,
+            dimension_params: extra
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn series(state: &AppState, params: &ProfileSeriesQuery) -> Result<ProfileSeries> {
+        let interpolator = get_interpolator(params.interpolation.as_deref().unwrap_or("bilinear"))?;
+        profile_series(state, params, BoundsMode::Error, interpolator.as_ref())
+            .map(|(series, _)| series)
+    }
+
+    #[test]
+    fn test_profile_series() {
+        let state = create_test_state();
+
+        // On a grid point the values are exact
+        let result = series(&state, &query(10.0, 0.0, &[])).unwrap();
+        assert_eq!(result.time_dim, "time");
+        assert_eq!(result.vertical_dim, "depth");
+        assert_eq!(result.times, vec![0.0, 24.0, 48.0]);
+        assert_eq!(result.levels, vec![0.0, 10.0, 30.0, 100.0]);
+        assert_eq!(result.values.dim(), (3, 4));
+        assert_eq!(result.values[[0, 0]], 2.0);
+        assert_eq!(result.values[[2, 3]], 232.0);
+
+        // Between grid points the value is interpolated horizontally
+        let result = series(&state, &query(15.0, 5.0, &[])).unwrap();
+        assert!((result.values[[1, 1]] - 113.0).abs() < 1e-4);
+
+        // Subsets of time and depth
+        let result = series(
+            &state,
+            &query(10.0, 0.0, &[("time", "24"), ("depth_range", "10,30")]),
+        )
+        .unwrap();
+        assert_eq!(result.times, vec![24.0]);
+        assert_eq!(result.levels, vec![10.0, 30.0]);
+        assert_eq!(result.values[[0, 0]], 112.0);
+    }
+
+    #[test]
+    fn test_missing_values() {
+        let state = create_test_state();
+
+        // Fill values below 30 m at 30°E become NaN instead of leaking into
+        // the interpolated value
+        let result = series(&state, &query(25.0, 0.0, &[])).unwrap();
+        assert!(result.values[[0, 1]].is_finite());
+        assert!(result.values[[0, 2]].is_nan());
+        assert!(result.values[[0, 3]].is_nan());
+
+        let mut params = query(30.0, 0.0, &[]);
+        params.interpolation = Some("nearest".to_string());
+        let result = series(&state, &params).unwrap();
+        assert_eq!(result.values[[0, 0]], 4.0);
+        assert!(result.values[[0, 2]].is_nan());
+    }
+
+    #[test]
+    fn test_profile_series_errors() {
+        let state = create_test_state();
+
+        assert!(series(&state, &query(45.0, 0.0, &[])).is_err());
+        assert!(series(&state, &query(10.0, 0.0, &[("lat", "0")])).is_err());
+        let mut params = query(10.0, 0.0, &[]);
+        params.vertical_dim = Some("time".to_string());
+        assert!(series(&state, &params).is_err());
+
+        let encoded = series_to_arrow("thetao", &series(&state, &query(0.0, 0.0, &[])).unwrap());
+        assert!(encoded.unwrap().len() > 100);
+    }
+}
@@ -0,0 +1,349 @@
+//! Seeded sampling endpoint handler.
+//!
+//! Draws `n` distinct cells of a variable at random, optionally within a
+//! selection, and streams their coordinates, raw indices and values as an
+//! Arrow IPC stream, one record batch at a time. The sample is determined by
+//! the seed (see [`crate::sampling`]): the same seed, size and selection
+//! draw the same cells of the same dataset, so QA pipelines can spot-check a
+//! dataset, and re-check what they flagged, without downloading it.
+
+use arrow::array::{ArrayRef, Float32Array, Float64Array, UInt64Array};
+use arrow::record_batch::RecordBatch;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::colormaps::parse_bbox;
+use crate::error::{Result, RossbyError};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::query::{Dataset, Selection};
+use crate::sampling::{sample_positions, SplitMix64};
+use crate::slice_stats::MissingData;
+use crate::state::AppState;
+
+/// Cells drawn unless `n` is given
+const DEFAULT_SAMPLE_SIZE: usize = 1000;
+
+/// Rows per record batch of the stream
+const BATCH_ROWS: usize = 65_536;
+
+/// Query parameters for the sampling endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct SampleQuery {
+    /// Variable name
+    pub var: String,
+    /// Number of cells to draw (default 1000)
+    #[serde(default)]
+    pub n: Option<usize>,
+    /// Seed of the random number generator (default 0)
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Bounding box as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Dimension selectors restricting the cells drawn from (default: all)
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// Cells drawn for a request
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    /// Dimensions of the variable
+    dimensions: Vec<String>,
+    /// Indices each dimension is drawn from
+    candidates: Vec<Vec<usize>>,
+    /// Number of cells drawn from
+    population: usize,
+    /// Positions drawn in the row-major order of the candidates
+    positions: Vec<usize>,
+}
+
+impl Sample {
+    /// Raw indices of the cell at a position
+    fn cell(&self, mut position: usize) -> Vec<usize> {
+        let mut index = vec![0; self.candidates.len()];
+        for (axis, candidates) in self.candidates.iter().enumerate().rev() {
+            index[axis] = candidates[position % candidates.len()];
+            position /= candidates.len();
+        }
+        index
+    }
+}
+
+/// Handle GET /sample requests
+pub async fn sample_handler(
+    Dataset(state): Dataset,
+    Query(params): Query<SampleQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/sample",
+        request_id = %request_id,
+        var = %params.var,
+        n = ?params.n,
+        seed = ?params.seed,
+        "Processing sample request"
+    );
+
+    match process_sample_query(state, &params) {
+        Ok(response) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/sample",
+                request_id = %request_id,
+                var = %params.var,
+                duration_us = duration.as_micros() as u64,
+                "Sample request successful"
+            );
+            response
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/sample",
+                &request_id,
+                Some(&format!(
+                    "var={}, n={:?}, seed={:?}",
+                    params.var, params.n, params.seed
+                )),
+            );
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn process_sample_query(state: Arc<AppState>, params: &SampleQuery) -> Result<Response> {
+    let n = params.n.unwrap_or(DEFAULT_SAMPLE_SIZE);
+    let max_points = state.config.server.max_data_points;
+    if n == 0 || n > max_points {
+        return Err(RossbyError::InvalidParameter {
+            param: "n".to_string(),
+            message: format!("Sample size must be between 1 and {}", max_points),
+        });
+    }
+    let seed = params.seed.unwrap_or(0);
+    let sample = draw_sample(&state, params, n, seed)?;
+
+    let schema = sample_schema(&params.var, &sample, seed)?;
+    let stream = sample_stream(state, params.var.clone(), sample, schema)?;
+    Ok((
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/vnd.apache.arrow.stream"),
+        )],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Draw the cells of a request from its selection
+fn draw_sample(state: &AppState, params: &SampleQuery, n: usize, seed: u64) -> Result<Sample> {
+    let var_meta = state.get_variable_metadata_checked(&params.var)?;
+    let dimensions = var_meta.dimensions.clone();
+
+    let mut selection = Selection::parse(state, &params.dimension_params)?;
+    if let Some(bbox) = params.bbox.as_deref() {
+        let (min_lon, min_lat, max_lon, max_lat) = parse_bbox(bbox)?;
+        let bbox = (
+            min_lon as f64,
+            min_lat as f64,
+            max_lon as f64,
+            max_lat as f64,
+        );
+        selection.add_bbox(state, "bbox", bbox)?;
+    }
+    if let Some(selected) = selection
+        .iter()
+        .find(|s| !dimensions.contains(&s.dimension))
+    {
+        return Err(RossbyError::InvalidParameter {
+            param: selected.param.clone(),
+            message: format!(
+                "Dimension '{}' is not a dimension of '{}'",
+                selected.dimension, params.var
+            ),
+        });
+    }
+
+    let shape = state.get_variable_values_checked(&params.var)?.shape();
+    let candidates = dimensions
+        .iter()
+        .zip(shape)
+        .map(|(dim, &size)| match selection.get(dim) {
+            Some(selected) => selected.resolve(state),
+            None => Ok((0..size).collect()),
+        })
+        .collect::<Result<Vec<Vec<usize>>>>()?;
+    let population = candidates
+        .iter()
+        .try_fold(1usize, |total, indices| total.checked_mul(indices.len()))
+        .ok_or_else(|| RossbyError::InvalidParameter {
+            param: "var".to_string(),
+            message: format!("Variable '{}' has too many cells to sample", params.var),
+        })?;
+
+    Ok(Sample {
+        dimensions,
+        candidates,
+        population,
+        positions: sample_positions(&mut SplitMix64::new(seed), population, n),
+    })
+}
+
+/// Schema of the stream: the coordinate and raw index of every dimension,
+/// then the value, with the seed and the number of cells drawn from in the
+/// schema metadata
+fn sample_schema(var: &str, sample: &Sample, seed: u64) -> Result<SchemaRef> {
+    let dimensions = &sample.dimensions;
+    let mut fields = Vec::new();
+    for dim in dimensions {
+        fields.push(Field::new(dim, DataType::Float64, false));
+    }
+    for dim in dimensions {
+        fields.push(Field::new(
+            format!("__{}_index", dim),
+            DataType::UInt64,
+            false,
+        ));
+    }
+    let mut metadata = HashMap::new();
+    metadata.insert("dimensions".to_string(), serde_json::to_string(dimensions)?);
+    fields.push(Field::new(var, DataType::Float32, true).with_metadata(metadata));
+
+    let mut metadata = HashMap::new();
+    metadata.insert("seed".to_string(), seed.to_string());
+    metadata.insert("population".to_string(), sample.population.to_string());
+    Ok(Arc::new(Schema::new(fields).with_metadata(metadata)))
+}
+
+/// Stream the sampled cells as Arrow IPC, one record batch of up to
+/// `BATCH_ROWS` cells at a time
+fn sample_stream(
+    state: Arc<AppState>,
+    var: String,
+    sample: Sample,
+    schema: SchemaRef,
+) -> Result<impl Stream<Item = std::result::Result<Bytes, std::io::Error>> + Send> {
+    let writer = StreamWriter::try_new(Vec::new(), &schema).map_err(arrow_error)?;
+    let batches = sample.positions.len().div_ceil(BATCH_ROWS);
+    let context = Arc::new((state, var, sample, schema));
+
+    // Each step writes one batch, the last one followed by the end of the
+    // stream; the first chunk also carries the schema
+    Ok(stream::unfold((Some(writer), 0), move |(writer, batch)| {
+        let context = context.clone();
+        async move {
+            let mut writer = writer?;
+            let (state, var, sample, schema) = &*context;
+            let mut write = || -> Result<Bytes> {
+                if batch < batches {
+                    let start = batch * BATCH_ROWS;
+                    let end = (start + BATCH_ROWS).min(sample.positions.len());
+                    let record_batch = sample_batch(state, var, sample, schema, start..end)?;
+                    writer.write(&record_batch).map_err(arrow_error)?;
+                }
+                if batch + 1 >= batches {
+                    writer.finish().map_err(arrow_error)?;
+                }
+                Ok(Bytes::from(std::mem::take(writer.get_mut())))
+            };
+            let chunk = write().map_err(std::io::Error::other);
+            let next = (batch + 1 < batches).then_some(writer);
+            Some((chunk, (next, batch + 1)))
+        }
+    }))
+}
+
+fn arrow_error(error: arrow::error::ArrowError) -> RossbyError {
+    RossbyError::Conversion {
+        message: format!("Failed to write Arrow stream: {}", error),
+    }
+}
+
+/// The record batch of the sampled cells in a range of the sample
+fn sample_batch(
+    state: &AppState,
+    var: &str,
+    sample: &Sample,
+    schema: &SchemaRef,
+    rows: std::ops::Range<usize>,
+) -> Result<RecordBatch> {
+    let values = state.get_variable_values_checked(var)?;
+    let missing = MissingData::for_variable(state.get_variable_metadata_checked(var)?);
+    let cells: Vec<Vec<usize>> = sample.positions[rows]
+        .iter()
+        .map(|&position| sample.cell(position))
+        .collect();
+
+    let mut columns: Vec<ArrayRef> = Vec::new();
+    for (axis, dim) in sample.dimensions.iter().enumerate() {
+        let coords = state.get_coordinate(dim);
+        let column: Float64Array = cells
+            .iter()
+            .map(|cell| {
+                let index = cell[axis];
+                Some(
+                    coords
+                        .and_then(|c| c.get(index).copied())
+                        .unwrap_or(index as f64),
+                )
+            })
+            .collect();
+        columns.push(Arc::new(column));
+    }
+    for axis in 0..sample.dimensions.len() {
+        let column: UInt64Array = cells.iter().map(|cell| Some(cell[axis] as u64)).collect();
+        columns.push(Arc::new(column));
+    }
+    let column: Float32Array = cells
+        .iter()
+        .map(|cell| values.get(cell).filter(|&value| !missing.is_missing(value)))
+        .collect();
+    columns.push(Arc::new(column));
+
+    RecordBatch::try_new(schema.clone(), columns).map_err(|e| RossbyError::Conversion {
+        message: format!("Failed to create record batch: {}", e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_cell() {
+        let sample = Sample {
+            dimensions: vec!["time".to_string(), "lat".to_string(), "lon".to_string()],
+            candidates: vec![vec![4], vec![0, 1, 2], vec![5, 7]],
+            population: 6,
+            positions: vec![0, 3, 5],
+        };
+        assert_eq!(sample.cell(0), vec![4, 0, 5]);
+        assert_eq!(sample.cell(3), vec![4, 1, 7]);
+        assert_eq!(sample.cell(5), vec![4, 2, 7]);
+    }
+}
@@ -0,0 +1,44 @@
+//! Signing key endpoint handler.
+//!
+//! Returns the public key verifying response signatures and the fingerprint
+//! of the dataset being served (see [`crate::signing`]).
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::logging::generate_request_id;
+use crate::signing::SIGNATURE_ALGORITHM;
+use crate::state::AppState;
+
+/// Handle GET /signing_key requests
+pub async fn signing_key_handler(State(state): State<Arc<AppState>>) -> Response {
+    let request_id = generate_request_id();
+    debug!(
+        endpoint = "/signing_key",
+        request_id = %request_id,
+        "Processing signing key request"
+    );
+
+    match state.signer.as_ref() {
+        Some(signer) => Json(serde_json::json!({
+            "algorithm": SIGNATURE_ALGORITHM,
+            "public_key": signer.public_key(),
+            "dataset_fingerprint": state.fingerprint(),
+        }))
+        .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "Response signing is disabled; set server.signing_key_file to enable it",
+                "request_id": request_id,
+            })),
+        )
+            .into_response(),
+    }
+}
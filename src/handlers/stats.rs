@@ -0,0 +1,475 @@
+//! Statistics endpoint handler.
+//!
+//! Returns summary statistics for a horizontal slab of a variable and, when a
+//! registered peer is named, statistics comparing it against the peer's field.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::colormaps::parse_bbox;
+use crate::error::{Result, RossbyError};
+use crate::federation::PeerClient;
+use crate::field::{find_lat_lon_axes, resolve_dimension_indices, HorizontalField};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::nan_policy::{NanPolicy, NAN_HEADER};
+use crate::query::Selection;
+use crate::query_resolved::{echo_requested, QueryResolved, QUERY_RESOLVED_KEY};
+use crate::state::AppState;
+
+/// Query parameters for the stats and diff endpoints
+#[derive(Debug, Deserialize, Clone)]
+pub struct FieldQuery {
+    /// Variable name
+    pub var: String,
+    /// Name of a registered peer to compare against
+    #[serde(default)]
+    pub peer: Option<String>,
+    /// Bounding box as "min_lon,min_lat,max_lon,max_lat"
+    #[serde(default)]
+    pub bbox: Option<String>,
+    /// Encoding of NaN and undefined statistics (null, string or omit)
+    #[serde(default)]
+    pub nan: Option<String>,
+    /// Describe how the query was interpreted in a `query_resolved` section
+    /// (true or false)
+    #[serde(default)]
+    pub echo_params: Option<String>,
+    /// Dimension selectors pinning non-horizontal dimensions (see `query::Selection`)
+    #[serde(flatten)]
+    pub dimension_params: HashMap<String, String>,
+}
+
+/// A local field together with the peer field regridded onto the same grid
+pub(crate) struct FieldComparison {
+    /// The local field
+    pub local: HorizontalField,
+    /// Selected index and coordinate value of every non-horizontal dimension
+    pub selection: HashMap<String, (usize, f64)>,
+    /// Peer name, the peer field on its own grid, and the regridded peer field
+    pub peer: Option<(String, HorizontalField, HorizontalField)>,
+}
+
+/// Handle GET /stats requests
+pub async fn stats_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<FieldQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/stats",
+        request_id = %request_id,
+        var = %params.var,
+        peer = ?params.peer,
+        bbox = ?params.bbox,
+        "Processing stats request"
+    );
+
+    match process_stats_query(&state, &params).await {
+        Ok((response, nan)) => {
+            let duration = start_time.elapsed();
+            info!(
+                endpoint = "/stats",
+                request_id = %request_id,
+                var = %params.var,
+                peer = ?params.peer,
+                duration_us = duration.as_micros() as u64,
+                "Stats request successful"
+            );
+            let mut response = Json(response).into_response();
+            response
+                .headers_mut()
+                .insert(NAN_HEADER, HeaderValue::from_static(nan.as_str()));
+            response
+        }
+        Err(error) => field_error_response(error, "/stats", &request_id, &params),
+    }
+}
+
+async fn process_stats_query(
+    state: &AppState,
+    params: &FieldQuery,
+) -> Result<(serde_json::Value, NanPolicy)> {
+    let nan = field_nan_policy(state, params)?;
+    let comparison = load_field_comparison(state, params).await?;
+
+    // Whole slices share their cached statistics with /image
+    let stats = match params.bbox {
+        Some(_) => comparison.local.stats(),
+        None => {
+            let indices = comparison
+                .selection
+                .iter()
+                .map(|(dim, &(index, _))| (dim.clone(), index))
+                .collect();
+            state
+                .slice_stats
+                .get_or_compute(state, &params.var, &indices)?
+        }
+    };
+
+    let mut response = serde_json::json!({
+        "var": params.var,
+        "selection": selection_to_json(&comparison.selection),
+        "shape": comparison.local.values.shape(),
+        "stats": stats,
+        "nan": nan.as_str(),
+    });
+    nan.undefined_members(&mut response["stats"]);
+    if let Some(resolved) = describe_field_query(state, params, &comparison)? {
+        response[QUERY_RESOLVED_KEY] = resolved.to_json();
+    }
+
+    if let Some((name, peer_field, regridded)) = &comparison.peer {
+        response["peer"] = serde_json::json!({
+            "name": name,
+            "shape": peer_field.values.shape(),
+            "stats": peer_field.stats(),
+            "comparison": comparison.local.compare(regridded)?,
+        });
+        nan.undefined_members(&mut response["peer"]["stats"]);
+        nan.undefined_members(&mut response["peer"]["comparison"]);
+    }
+
+    Ok((response, nan))
+}
+
+/// NaN policy of a field query: its `nan` parameter, else the configured default
+pub(crate) fn field_nan_policy(state: &AppState, params: &FieldQuery) -> Result<NanPolicy> {
+    NanPolicy::from_request(params.nan.as_deref(), &state.config.data.json_nan)
+}
+
+/// Extract the local field for a query and, if requested, the regridded peer field
+pub(crate) async fn load_field_comparison(
+    state: &AppState,
+    params: &FieldQuery,
+) -> Result<FieldComparison> {
+    if !state.has_variable(&params.var) {
+        return Err(RossbyError::InvalidVariables {
+            names: vec![params.var.clone()],
+        });
+    }
+
+    let bbox = params
+        .bbox
+        .as_deref()
+        .map(parse_bbox)
+        .transpose()?
+        .map(|(a, b, c, d)| (a as f64, b as f64, c as f64, d as f64));
+
+    let dim_indices = resolve_dimension_indices(state, &params.var, &params.dimension_params)?;
+    let local = HorizontalField::from_state(state, &params.var, &dim_indices, bbox)?;
+
+    // Record the physical coordinate of each pinned dimension so the peer can
+    // select the same slice on its own axes
+    let selection: HashMap<String, (usize, f64)> = dim_indices
+        .iter()
+        .map(|(dim, &index)| {
+            let value = state
+                .get_coordinate(dim)
+                .and_then(|coords| coords.get(index).copied())
+                .unwrap_or(index as f64);
+            (dim.clone(), (index, value))
+        })
+        .collect();
+
+    let peer = match &params.peer {
+        Some(name) => {
+            let client = peer_client(state, name)?;
+            let fixed: HashMap<String, f64> = selection
+                .iter()
+                .map(|(dim, &(_, value))| (dim.clone(), value))
+                .collect();
+            let peer_field = client.fetch_field(&params.var, &fixed).await?;
+            let regridded = peer_field.regrid(&local.lat, &local.lon);
+            Some((name.clone(), peer_field, regridded))
+        }
+        None => None,
+    };
+
+    Ok(FieldComparison {
+        local,
+        selection,
+        peer,
+    })
+}
+
+/// The echo of a field query (see [`crate::query_resolved`]), unless
+/// `echo_params` is not set: the slice pinned on every non-horizontal
+/// dimension and the grid points of the local field
+pub(crate) fn describe_field_query(
+    state: &AppState,
+    params: &FieldQuery,
+    comparison: &FieldComparison,
+) -> Result<Option<QueryResolved>> {
+    if !echo_requested(params.echo_params.as_deref())? {
+        return Ok(None);
+    }
+    let selection = Selection::parse(state, &params.dimension_params)?;
+    let pinned: HashMap<String, usize> = comparison
+        .selection
+        .iter()
+        .map(|(dim, &(index, _))| (dim.clone(), index))
+        .collect();
+    let mut resolved = QueryResolved::new();
+    resolved.pinned(state, &selection, &pinned);
+
+    let local = &comparison.local;
+    let var_meta = state.get_variable_metadata_checked(&params.var)?;
+    if let Some((lat_axis, lon_axis)) = find_lat_lon_axes(&var_meta.dimensions) {
+        let param = params.bbox.as_ref().map(|_| "bbox");
+        for (axis, values) in [(lon_axis, &local.lon), (lat_axis, &local.lat)] {
+            let dimension = &var_meta.dimensions[axis];
+            let coords = state.get_coordinate_checked(dimension)?;
+            let indices: Vec<usize> = values
+                .iter()
+                .filter_map(|value| coords.iter().position(|coord| coord == value))
+                .collect();
+            resolved.dimension(state, dimension, param, &indices);
+        }
+    }
+    if params.bbox.is_some() {
+        resolved.covered_bbox(&local.lon, &local.lat);
+    }
+    Ok(Some(resolved))
+}
+
+/// Create a client for a peer registered in the server configuration
+fn peer_client(state: &AppState, name: &str) -> Result<PeerClient> {
+    let url = state
+        .config
+        .server
+        .peers
+        .get(name)
+        .ok_or_else(|| RossbyError::InvalidParameter {
+            param: "peer".to_string(),
+            message: format!(
+                "Unknown peer '{}'. Registered peers: {:?}",
+                name,
+                state.config.server.peers.keys().collect::<Vec<_>>()
+            ),
+        })?;
+    Ok(PeerClient::new(name, url))
+}
+
+/// Describe the pinned dimensions as `{dim: {"index": i, "value": v}}`
+pub(crate) fn selection_to_json(selection: &HashMap<String, (usize, f64)>) -> serde_json::Value {
+    selection
+        .iter()
+        .map(|(dim, (index, value))| {
+            (
+                dim.clone(),
+                serde_json::json!({ "index": index, "value": value }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Build the error response shared by the field endpoints
+pub(crate) fn field_error_response(
+    error: RossbyError,
+    endpoint: &str,
+    request_id: &str,
+    params: &FieldQuery,
+) -> Response {
+    log_request_error(
+        &error,
+        endpoint,
+        request_id,
+        Some(&format!("var={}, peer={:?}", params.var, params.peer)),
+    );
+
+    let status = match &error {
+        RossbyError::Peer { .. } => StatusCode::BAD_GATEWAY,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    (
+        status,
+        Json(serde_json::json!({
+            "error": error.to_string(),
+            "request_id": request_id
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata, Variable};
+    use ndarray::{Array, IxDyn};
+
+    fn create_test_state() -> AppState {
+        let mut dimensions = HashMap::new();
+        for (name, size) in [("time", 2), ("lat", 2), ("lon", 3)] {
+            dimensions.insert(
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                },
+            );
+        }
+
+        let mut variables = HashMap::new();
+        variables.insert(
+            "temperature".to_string(),
+            Variable {
+                name: "temperature".to_string(),
+                dimensions: vec!["time".to_string(), "lat".to_string(), "lon".to_string()],
+                shape: vec![2, 2, 3],
+                attributes: HashMap::new(),
+                dtype: "f32".to_string(),
+            },
+        );
+
+        let mut coordinates = HashMap::new();
+        coordinates.insert("time".to_string(), vec![100.0, 200.0]);
+        coordinates.insert("lat".to_string(), vec![10.0, 20.0]);
+        coordinates.insert("lon".to_string(), vec![0.0, 10.0, 20.0]);
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables,
+            coordinates,
+            groups: HashMap::new(),
+        };
+
+        let values: Vec<f32> = (0..12).map(|v| v as f32).collect();
+        let mut data = HashMap::new();
+        data.insert(
+            "temperature".to_string(),
+            Array::from_shape_vec(IxDyn(&[2, 2, 3]), values).unwrap(),
+        );
+
+        AppState::new(Config::default(), metadata, data)
+    }
+
+    fn query(pairs: &[(&str, &str)]) -> FieldQuery {
+        FieldQuery {
+            var: "temperature".to_string(),
+            peer: None,
+            bbox: None,
+            nan: None,
+            echo_params: None,
+            dimension_params: pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_stats() {
+        let state = create_test_state();
+
+        let (response, _) = process_stats_query(&state, &query(&[("time", "200")]))
+            .await
+            .unwrap();
+        assert_eq!(response["selection"]["time"]["index"], 1);
+        assert_eq!(response["stats"]["count"], 6);
+        assert_eq!(response["stats"]["min"], 6.0);
+        assert_eq!(response["stats"]["max"], 11.0);
+        assert!(response.get("peer").is_none());
+
+        let mut params = query(&[("__time_index", "0")]);
+        params.bbox = Some("5,0,20,15".to_string());
+        let (response, _) = process_stats_query(&state, &params).await.unwrap();
+        assert_eq!(response["shape"], serde_json::json!([1, 2]));
+        assert_eq!(response["stats"]["mean"], 1.5);
+        assert_eq!(response["nan"], "null");
+    }
+
+    #[tokio::test]
+    async fn test_stats_nan_policy() {
+        let state = create_test_state();
+        let mut data = state.data.clone();
+        data.get_mut("temperature").unwrap().fill(f32::NAN);
+        let state = AppState::new(Config::default(), state.metadata.clone(), data);
+
+        // An all-missing slab has no minimum, mean, ...
+        let (response, nan) = process_stats_query(&state, &query(&[("__time_index", "0")]))
+            .await
+            .unwrap();
+        assert_eq!(nan, NanPolicy::Null);
+        assert_eq!(response["stats"]["count"], 0);
+        assert!(response["stats"]["mean"].is_null());
+
+        let mut params = query(&[("__time_index", "0")]);
+        params.nan = Some("string".to_string());
+        let (response, _) = process_stats_query(&state, &params).await.unwrap();
+        assert_eq!(response["stats"]["mean"], "NaN");
+        assert_eq!(response["nan"], "string");
+
+        params.nan = Some("omit".to_string());
+        let (response, _) = process_stats_query(&state, &params).await.unwrap();
+        assert_eq!(response["stats"], serde_json::json!({"count": 0}));
+
+        params.nan = Some("zero".to_string());
+        let err = process_stats_query(&state, &params).await.unwrap_err();
+        assert!(matches!(err, RossbyError::InvalidParameter { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_stats_echo_params() {
+        let state = create_test_state();
+
+        let mut params = query(&[("time", "200")]);
+        params.bbox = Some("5,0,20,15".to_string());
+        let (response, _) = process_stats_query(&state, &params).await.unwrap();
+        assert!(response.get("query_resolved").is_none());
+
+        params.echo_params = Some("true".to_string());
+        let (response, _) = process_stats_query(&state, &params).await.unwrap();
+        let resolved = &response["query_resolved"];
+        assert_eq!(resolved["dimensions"]["time"]["param"], "time");
+        assert_eq!(resolved["dimensions"]["time"]["start"], 1);
+        assert_eq!(resolved["dimensions"]["lon"]["param"], "bbox");
+        assert_eq!(resolved["dimensions"]["lon"]["start"], 1);
+        assert_eq!(resolved["dimensions"]["lon"]["count"], 2);
+        assert_eq!(resolved["dimensions"]["lat"]["count"], 1);
+        assert_eq!(
+            resolved["bbox"],
+            serde_json::json!([10.0, 10.0, 20.0, 10.0])
+        );
+
+        params.echo_params = Some("yes".to_string());
+        let err = process_stats_query(&state, &params).await.unwrap_err();
+        assert!(matches!(err, RossbyError::InvalidParameter { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_stats_errors() {
+        let state = create_test_state();
+
+        let err = process_stats_query(&state, &query(&[("__time_index", "5")]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RossbyError::IndexOutOfBounds { .. }));
+
+        let mut params = query(&[]);
+        params.peer = Some("nowhere".to_string());
+        let err = process_stats_query(&state, &params).await.unwrap_err();
+        assert!(matches!(err, RossbyError::InvalidParameter { .. }));
+
+        let mut params = query(&[]);
+        params.var = "missing".to_string();
+        let err = process_stats_query(&state, &params).await.unwrap_err();
+        assert!(matches!(err, RossbyError::InvalidVariables { .. }));
+    }
+}
@@ -0,0 +1,234 @@
+//! Thumbnail endpoint handler.
+//!
+//! Returns a small PNG preview of a variable with a title band, for link
+//! previews and catalog galleries (see [`crate::thumbnail`]).
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use image::ImageFormat;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::artifact::artifact_response;
+use crate::cf_time::time_units;
+use crate::error::{Result, RossbyError};
+use crate::handlers::image::{generate_image_response, ImageQuery};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::{AppState, AttributeValue};
+use crate::thumbnail::draw_title;
+
+/// Default thumbnail dimensions, the 16:9 size of most link previews
+pub(crate) const DEFAULT_WIDTH: u32 = 320;
+pub(crate) const DEFAULT_HEIGHT: u32 = 180;
+
+/// Largest thumbnail side in pixels (the width of an OpenGraph image)
+const MAX_SIZE: u32 = 1200;
+
+/// Query parameters selecting a time step
+const TIME_PARAMS: [&str; 3] = ["time", "time_index", "__time_index"];
+
+/// Query parameters for the thumbnail endpoint
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailQuery {
+    /// Variable name
+    #[serde(default)]
+    pub var: String,
+    /// Title drawn over the image (default: long name and time; empty for none)
+    pub title: Option<String>,
+    /// Language code of the default title (e.g. "de")
+    pub lang: Option<String>,
+    /// Image width in pixels
+    pub width: Option<String>,
+    /// Image height in pixels
+    pub height: Option<String>,
+    /// Further `/image` parameters, e.g. `colormap`, `bbox` or dimension selectors
+    #[serde(flatten)]
+    pub image_params: BTreeMap<String, String>,
+}
+
+/// Handle GET /thumbnail requests
+pub async fn thumbnail_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<ThumbnailQuery>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+
+    debug!(
+        endpoint = "/thumbnail",
+        request_id = %request_id,
+        var = %params.var,
+        title = ?params.title,
+        params = ?params.image_params,
+        "Processing thumbnail request"
+    );
+
+    match thumbnail_png(&state, &params).await {
+        Ok((png, cached)) => {
+            info!(
+                endpoint = "/thumbnail",
+                request_id = %request_id,
+                var = %params.var,
+                cached = cached,
+                duration_us = start_time.elapsed().as_micros() as u64,
+                "Thumbnail request successful"
+            );
+            artifact_response(
+                &headers,
+                HeaderValue::from_static("image/png"),
+                png.to_vec(),
+            )
+        }
+        Err(error) => {
+            log_request_error(&error, "/thumbnail", &request_id, None);
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": error.to_string(),
+                    "request_id": request_id
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Encoded thumbnail of a query, and whether it came from the cache
+async fn thumbnail_png(state: &Arc<AppState>, params: &ThumbnailQuery) -> Result<(Bytes, bool)> {
+    if params.var.is_empty() {
+        return Err(RossbyError::InvalidParameter {
+            param: "var".to_string(),
+            message: "A variable must be specified".to_string(),
+        });
+    }
+    let width = parse_size("width", params.width.as_deref(), DEFAULT_WIDTH)?;
+    let height = parse_size("height", params.height.as_deref(), DEFAULT_HEIGHT)?;
+
+    // The latest time step is the most useful preview of a dataset
+    let mut image_params = params.image_params.clone();
+    let time_steps = state.get_coordinate("time").map_or(0, Vec::len);
+    if time_steps > 0 && !TIME_PARAMS.iter().any(|p| image_params.contains_key(*p)) {
+        image_params.insert("__time_index".to_string(), (time_steps - 1).to_string());
+    }
+    image_params.insert("var".to_string(), params.var.clone());
+    image_params.insert("width".to_string(), width.to_string());
+    image_params.insert("height".to_string(), height.to_string());
+    image_params.insert("format".to_string(), "png".to_string());
+
+    let title = match &params.title {
+        Some(title) => title.clone(),
+        None => default_title(state, &params.var, &image_params, params.lang.as_deref()),
+    };
+    let invalid = |e: &dyn std::fmt::Display| RossbyError::InvalidParameter {
+        param: "var".to_string(),
+        message: format!("Cannot build image query: {}", e),
+    };
+    let query = serde_urlencoded::to_string(&image_params).map_err(|e| invalid(&e))?;
+    let key = format!("{}&title={}", query, title);
+    if let Some(png) = state.thumbnails.get(&key) {
+        return Ok((png, true));
+    }
+
+    let image_query: ImageQuery = serde_urlencoded::from_str(&query).map_err(|e| invalid(&e))?;
+    let response = generate_image_response(state.clone(), &image_query, &HeaderMap::new())?;
+    let rendered = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to read rendered image: {}", e),
+        })?;
+    let mut img = image::load_from_memory_with_format(&rendered, ImageFormat::Png)
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to decode rendered image: {}", e),
+        })?
+        .to_rgba8();
+    draw_title(&mut img, &title);
+
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to encode PNG: {}", e),
+        })?;
+    let png = Bytes::from(buffer.into_inner());
+    state.thumbnails.insert(key, png.clone());
+    Ok((png, false))
+}
+
+/// Parse a thumbnail side, between 1 and `MAX_SIZE` pixels
+fn parse_size(param: &str, value: Option<&str>, default: u32) -> Result<u32> {
+    let Some(value) = value else {
+        return Ok(default);
+    };
+    match value.trim().parse::<u32>() {
+        Ok(size) if (1..=MAX_SIZE).contains(&size) => Ok(size),
+        _ => Err(RossbyError::InvalidParameter {
+            param: param.to_string(),
+            message: format!(
+                "Thumbnail {} must be between 1 and {} pixels, got '{}'",
+                param, MAX_SIZE, value
+            ),
+        }),
+    }
+}
+
+/// The variable's long name (or name) in the given language, followed by
+/// the date of the time step
+pub(crate) fn default_title(
+    state: &AppState,
+    var: &str,
+    image_params: &BTreeMap<String, String>,
+    lang: Option<&str>,
+) -> String {
+    let name = match state
+        .get_localized_variable_metadata(var, lang)
+        .ok()
+        .and_then(|meta| meta.attributes.get("long_name").cloned())
+    {
+        Some(AttributeValue::Text(long_name)) if !long_name.trim().is_empty() => long_name,
+        _ => var.to_string(),
+    };
+
+    let coords = state.get_coordinate("time");
+    let time = match image_params.get("time") {
+        Some(value) => value.parse::<f64>().ok(),
+        None => ["__time_index", "time_index"]
+            .iter()
+            .find_map(|p| image_params.get(*p))
+            .and_then(|index| index.parse::<usize>().ok())
+            .and_then(|index| coords?.get(index).copied()),
+    };
+    let date = time.and_then(|time| {
+        let datetime = time_units(state, "time")?.datetime(time)?;
+        Some(datetime.format("%Y-%m-%d %H:%M").to_string())
+    });
+    match date {
+        Some(date) => format!("{} {}", name, date),
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("width", None, DEFAULT_WIDTH).unwrap(), 320);
+        assert_eq!(
+            parse_size("width", Some("640"), DEFAULT_WIDTH).unwrap(),
+            640
+        );
+        assert!(parse_size("width", Some("0"), DEFAULT_WIDTH).is_err());
+        assert!(parse_size("height", Some("4000"), DEFAULT_HEIGHT).is_err());
+        assert!(parse_size("height", Some("tall"), DEFAULT_HEIGHT).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+//! Usage endpoint handler.
+//!
+//! Returns the bytes the calling client has transferred in the current quota
+//! window and how much of its allowance remains.
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    Json,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::logging::generate_request_id;
+use crate::quota::{ClientId, Usage};
+use crate::state::AppState;
+
+/// Handle GET /usage requests
+///
+/// Requests to this endpoint are not counted against the quota, so clients
+/// can check their usage even after it is exhausted.
+pub async fn usage_handler(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Json<Usage> {
+    let request_id = generate_request_id();
+    let client = ClientId::from_request(&headers, connect_info.map(|info| info.0));
+
+    debug!(
+        endpoint = "/usage",
+        request_id = %request_id,
+        client = %client,
+        "Processing usage request"
+    );
+
+    Json(state.usage.usage(&client, &state.config.server.quotas))
+}
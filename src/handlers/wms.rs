@@ -0,0 +1,1137 @@
+//! WMS 1.3.0 endpoint handler.
+//!
+//! Lets Web Map Service clients such as QGIS, Leaflet and OpenLayers use
+//! rossby as their backend. `GetCapabilities` describes every variable on a
+//! latitude/longitude grid as a layer, from the dataset metadata, and
+//! `GetMap` translates the requested map into `/image` queries: each layer is
+//! rendered over the part of its grid the bounding box covers and placed on a
+//! canvas of the requested size, so tiles line up and areas outside the grid
+//! stay empty. Maps are drawn in CRS:84, EPSG:4326 (latitude first, as 1.3.0
+//! requires) or EPSG:3857; styles are colormap names. Errors are returned as
+//! OGC service exception reports.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::bounds::is_periodic_longitude;
+use crate::cf_time::time_units;
+use crate::colormaps::{
+    colormap::COLORMAP_NAMES, geoutil::mercator_y, get_colormap, MAX_MERCATOR_LAT,
+};
+use crate::error::{Result, RossbyError};
+use crate::field::find_lat_lon_axes;
+use crate::geotiff::WEB_MERCATOR_RADIUS;
+use crate::handlers::image::{
+    generate_image_response, level_dimension, style_preset, ImageQuery, LEVEL_NAMES,
+};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::{coordinate_bounds, AppState, AttributeValue};
+
+/// Supported protocol version
+const VERSION: &str = "1.3.0";
+
+/// Largest map side in pixels
+const MAX_SIZE: u32 = 8192;
+
+/// Most layers drawn on one map
+const MAX_LAYERS: usize = 16;
+
+/// Map formats of `GetMap`
+const MAP_FORMATS: [&str; 2] = ["image/png", "image/jpeg"];
+
+/// Coordinate reference systems maps can be drawn in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapCrs {
+    /// Longitude and latitude in degrees
+    Crs84,
+    /// Latitude and longitude in degrees (longitude first before 1.3.0)
+    Epsg4326,
+    /// Web Mercator in meters
+    WebMercator,
+}
+
+impl MapCrs {
+    /// Every supported CRS, as listed in the capabilities
+    const ALL: [MapCrs; 3] = [MapCrs::Crs84, MapCrs::Epsg4326, MapCrs::WebMercator];
+
+    /// Parse a `CRS` (or pre-1.3.0 `SRS`) parameter
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "CRS:84" => Ok(MapCrs::Crs84),
+            "EPSG:4326" => Ok(MapCrs::Epsg4326),
+            "EPSG:3857" | "EPSG:900913" => Ok(MapCrs::WebMercator),
+            _ => Err(RossbyError::InvalidParameter {
+                param: "crs".to_string(),
+                message: format!(
+                    "Unsupported CRS: {}. Valid values are 'CRS:84', 'EPSG:4326' or 'EPSG:3857'",
+                    value
+                ),
+            }),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MapCrs::Crs84 => "CRS:84",
+            MapCrs::Epsg4326 => "EPSG:4326",
+            MapCrs::WebMercator => "EPSG:3857",
+        }
+    }
+
+    /// Map coordinates (x east, y north) of a longitude and latitude
+    fn project(self, lon: f64, lat: f64) -> (f64, f64) {
+        match self {
+            MapCrs::Crs84 | MapCrs::Epsg4326 => (lon, lat),
+            MapCrs::WebMercator => (
+                WEB_MERCATOR_RADIUS * lon.to_radians(),
+                WEB_MERCATOR_RADIUS * mercator_y(lat),
+            ),
+        }
+    }
+
+    /// Longitude and latitude of map coordinates
+    fn unproject(self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            MapCrs::Crs84 | MapCrs::Epsg4326 => (x, y),
+            MapCrs::WebMercator => (
+                (x / WEB_MERCATOR_RADIUS).to_degrees(),
+                (y / WEB_MERCATOR_RADIUS).sinh().atan().to_degrees(),
+            ),
+        }
+    }
+}
+
+/// Extent of a map in its CRS, with x east and y north
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapExtent {
+    pub crs: MapCrs,
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl MapExtent {
+    /// Parse a `BBOX` parameter, in the axis order of the CRS and version
+    pub fn parse(value: &str, crs: MapCrs, version: &str) -> Result<Self> {
+        let invalid = |message: String| RossbyError::InvalidParameter {
+            param: "bbox".to_string(),
+            message,
+        };
+        let numbers = value
+            .split(',')
+            .map(|part| part.trim().parse::<f64>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| invalid(format!("Invalid bounding box: {}", value)))?;
+        let [a, b, c, d] = numbers[..] else {
+            return Err(invalid(format!(
+                "Bounding box must have four values, got {}",
+                value
+            )));
+        };
+        let latitude_first = crs == MapCrs::Epsg4326 && !version.starts_with("1.1");
+        let (min_x, min_y, max_x, max_y) = if latitude_first {
+            (b, a, d, c)
+        } else {
+            (a, b, c, d)
+        };
+        if ![a, b, c, d].iter().all(|v| v.is_finite()) || min_x >= max_x || min_y >= max_y {
+            return Err(invalid(format!(
+                "Bounding box minima must be below its maxima, got {}",
+                value
+            )));
+        }
+        Ok(Self {
+            crs,
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        })
+    }
+
+    /// Longitude and latitude bounds, as (west, south, east, north)
+    fn geographic(&self) -> (f64, f64, f64, f64) {
+        let (west, south) = self.crs.unproject(self.min_x, self.min_y);
+        let (east, north) = self.crs.unproject(self.max_x, self.max_y);
+        (west, south, east, north)
+    }
+
+    /// Pixel position (column, row) of a longitude and latitude on a map of
+    /// the given size, in units of pixels from its top left corner
+    fn pixel(&self, lon: f64, lat: f64, width: u32, height: u32) -> (f64, f64) {
+        let (x, y) = self.crs.project(lon, lat);
+        (
+            (x - self.min_x) / (self.max_x - self.min_x) * width as f64,
+            (self.max_y - y) / (self.max_y - self.min_y) * height as f64,
+        )
+    }
+}
+
+/// Smallest coordinate span covering an interval: from the last coordinate
+/// at or below its start to the first at or above its end
+///
+/// Returns None unless the span holds two distinct coordinates.
+pub fn covering_span(coords: &[f64], start: f64, end: f64) -> Option<(f64, f64)> {
+    let below = coords
+        .iter()
+        .copied()
+        .filter(|&c| c <= start)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let above = coords
+        .iter()
+        .copied()
+        .filter(|&c| c >= end)
+        .fold(f64::INFINITY, f64::min);
+    (below.is_finite() && above.is_finite() && below < above).then_some((below, above))
+}
+
+/// Parameters of a WMS request, with their names lowercased
+struct WmsParams(HashMap<String, String>);
+
+impl WmsParams {
+    fn new(raw: HashMap<String, String>) -> Self {
+        Self(
+            raw.into_iter()
+                .map(|(key, value)| (key.to_lowercase(), value))
+                .collect(),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    fn required(&self, name: &str) -> Result<&str> {
+        self.get(name)
+            .filter(|value| !value.trim().is_empty())
+            .ok_or_else(|| RossbyError::InvalidParameter {
+                param: name.to_string(),
+                message: format!("Missing required parameter {}", name.to_uppercase()),
+            })
+    }
+
+    fn size(&self, name: &str) -> Result<u32> {
+        let value = self.required(name)?;
+        value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|size| (1..=MAX_SIZE).contains(size))
+            .ok_or_else(|| RossbyError::InvalidParameter {
+                param: name.to_string(),
+                message: format!(
+                    "{} must be between 1 and {} pixels, got {}",
+                    name.to_uppercase(),
+                    MAX_SIZE,
+                    value
+                ),
+            })
+    }
+}
+
+/// Number of pixels a WMS request renders, for cost estimates
+pub fn estimate_pixels(query: &str) -> Option<u64> {
+    let params = WmsParams::new(serde_urlencoded::from_str(query).ok()?);
+    if !params.get("request")?.eq_ignore_ascii_case("GetMap") {
+        return None;
+    }
+    let layers = params.get("layers")?.split(',').count() as u64;
+    let width: u64 = params.get("width")?.trim().parse().ok()?;
+    let height: u64 = params.get("height")?.trim().parse().ok()?;
+    Some(width.saturating_mul(height).saturating_mul(layers))
+}
+
+/// Handle GET /wms requests
+pub async fn wms_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(raw): Query<HashMap<String, String>>,
+) -> Response {
+    let request_id = generate_request_id();
+    let start_time = Instant::now();
+    let params = WmsParams::new(raw);
+    let request = params.get("request").unwrap_or_default().to_string();
+
+    debug!(
+        endpoint = "/wms",
+        request_id = %request_id,
+        request = %request,
+        layers = ?params.get("layers"),
+        "Processing WMS request"
+    );
+
+    match process_wms_request(&state, &headers, &params).await {
+        Ok((content_type, body)) => {
+            info!(
+                endpoint = "/wms",
+                request_id = %request_id,
+                request = %request,
+                duration_us = start_time.elapsed().as_micros() as u64,
+                "WMS request successful"
+            );
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, HeaderValue::from_static(content_type))],
+                body,
+            )
+                .into_response()
+        }
+        Err(error) => {
+            log_request_error(
+                &error,
+                "/wms",
+                &request_id,
+                Some(&format!("request={}", request)),
+            );
+            let status = match &error {
+                RossbyError::ImageGeneration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (
+                status,
+                [(header::CONTENT_TYPE, HeaderValue::from_static("text/xml"))],
+                exception_report(&error, &request_id),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn process_wms_request(
+    state: &Arc<AppState>,
+    headers: &HeaderMap,
+    params: &WmsParams,
+) -> Result<(&'static str, Vec<u8>)> {
+    if let Some(service) = params.get("service") {
+        if !service.eq_ignore_ascii_case("WMS") {
+            return Err(RossbyError::InvalidParameter {
+                param: "service".to_string(),
+                message: format!("Unsupported service: {}. Only WMS is served", service),
+            });
+        }
+    }
+    let request = params.required("request")?;
+    if request.eq_ignore_ascii_case("GetCapabilities") {
+        Ok(("text/xml", capabilities(state, headers).into_bytes()))
+    } else if request.eq_ignore_ascii_case("GetMap") {
+        get_map(state, params).await
+    } else {
+        Err(RossbyError::InvalidParameter {
+            param: "request".to_string(),
+            message: format!(
+                "Unsupported request: {}. Valid requests are GetCapabilities and GetMap",
+                request
+            ),
+        })
+    }
+}
+
+/// Render the map of a `GetMap` request
+async fn get_map(state: &Arc<AppState>, params: &WmsParams) -> Result<(&'static str, Vec<u8>)> {
+    let version = params.get("version").unwrap_or(VERSION);
+    if version != VERSION && !version.starts_with("1.1") {
+        return Err(RossbyError::InvalidParameter {
+            param: "version".to_string(),
+            message: format!("Unsupported version: {}. Use {}", version, VERSION),
+        });
+    }
+    let layers: Vec<&str> = params
+        .required("layers")?
+        .split(',')
+        .map(str::trim)
+        .collect();
+    if layers.len() > MAX_LAYERS || layers.iter().any(|layer| layer.is_empty()) {
+        return Err(RossbyError::InvalidParameter {
+            param: "layers".to_string(),
+            message: format!("LAYERS must name 1 to {} layers", MAX_LAYERS),
+        });
+    }
+    let styles = parse_styles(params.get("styles").unwrap_or_default(), layers.len())?;
+    let crs = MapCrs::parse(params.get("crs").or(params.get("srs")).unwrap_or(""))?;
+    let extent = MapExtent::parse(params.required("bbox")?, crs, version)?;
+    let width = params.size("width")?;
+    let height = params.size("height")?;
+    let format = params.get("format").unwrap_or(MAP_FORMATS[0]).trim();
+    if !MAP_FORMATS.iter().any(|f| f.eq_ignore_ascii_case(format)) {
+        return Err(RossbyError::InvalidParameter {
+            param: "format".to_string(),
+            message: format!(
+                "Unsupported format: {}. Valid formats are {}",
+                format,
+                MAP_FORMATS.join(", ")
+            ),
+        });
+    }
+    let jpeg = format.eq_ignore_ascii_case("image/jpeg");
+    let transparent = match params.get("transparent") {
+        Some(value) => value.eq_ignore_ascii_case("true"),
+        None => false,
+    };
+    let background = parse_bgcolor(params.get("bgcolor"))?;
+
+    // Selectors shared by every layer: TIME, ELEVATION and DIM_<name>
+    let mut selectors = BTreeMap::new();
+    if let Some(time) = params.get("time") {
+        selectors.insert("time".to_string(), time_value(state, time)?.to_string());
+    }
+    if let Some(elevation) = params.get("elevation") {
+        selectors.insert("level".to_string(), elevation.trim().to_string());
+    }
+    for (key, value) in &params.0 {
+        if let Some(name) = key.strip_prefix("dim_") {
+            let dimension = state
+                .metadata
+                .dimensions
+                .keys()
+                .find(|dimension| dimension.eq_ignore_ascii_case(name))
+                .cloned()
+                .unwrap_or_else(|| name.to_string());
+            selectors.insert(dimension, value.trim().to_string());
+        }
+    }
+
+    let mut canvas = RgbaImage::new(width, height);
+    for (layer, style) in layers.iter().zip(&styles) {
+        draw_layer(
+            state,
+            &mut canvas,
+            &extent,
+            layer,
+            style.as_deref(),
+            &selectors,
+        )
+        .await?;
+    }
+
+    // Opaque maps show the background color through empty areas
+    if jpeg || !transparent {
+        let mut opaque = RgbaImage::from_pixel(width, height, background);
+        imageops::overlay(&mut opaque, &canvas, 0, 0);
+        canvas = opaque;
+    }
+    let mut buffer = Cursor::new(Vec::new());
+    let encoded = if jpeg {
+        image::DynamicImage::ImageRgba8(canvas)
+            .to_rgb8()
+            .write_to(&mut buffer, ImageFormat::Jpeg)
+    } else {
+        canvas.write_to(&mut buffer, ImageFormat::Png)
+    };
+    encoded.map_err(|e| RossbyError::ImageGeneration {
+        message: format!("Failed to encode map: {}", e),
+    })?;
+    Ok((
+        if jpeg { "image/jpeg" } else { "image/png" },
+        buffer.into_inner(),
+    ))
+}
+
+/// Colormaps of the `STYLES` parameter, None for the default style
+fn parse_styles(value: &str, layers: usize) -> Result<Vec<Option<String>>> {
+    if value.trim().is_empty() {
+        return Ok(vec![None; layers]);
+    }
+    let styles: Vec<&str> = value.split(',').map(str::trim).collect();
+    if styles.len() != layers {
+        return Err(RossbyError::InvalidParameter {
+            param: "styles".to_string(),
+            message: format!(
+                "STYLES must list one style per layer, got {} for {} layers",
+                styles.len(),
+                layers
+            ),
+        });
+    }
+    styles
+        .into_iter()
+        .map(|style| match style.to_lowercase().as_str() {
+            "" | "default" => Ok(None),
+            name if get_colormap(name).is_ok() => Ok(Some(name.to_string())),
+            _ => Err(RossbyError::InvalidParameter {
+                param: "styles".to_string(),
+                message: format!(
+                    "Unknown style: {}. Valid styles are {}, each also reversed with _r",
+                    style,
+                    COLORMAP_NAMES.join(", ")
+                ),
+            }),
+        })
+        .collect()
+}
+
+/// Parse a `BGCOLOR` parameter such as `0xFFFFFF` (white by default)
+fn parse_bgcolor(value: Option<&str>) -> Result<Rgba<u8>> {
+    let Some(value) = value else {
+        return Ok(Rgba([255, 255, 255, 255]));
+    };
+    let hex = value
+        .trim()
+        .strip_prefix("0x")
+        .or_else(|| value.trim().strip_prefix("0X"))
+        .filter(|hex| hex.len() == 6);
+    match hex.and_then(|hex| u32::from_str_radix(hex, 16).ok()) {
+        Some(rgb) => Ok(Rgba([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255])),
+        None => Err(RossbyError::InvalidParameter {
+            param: "bgcolor".to_string(),
+            message: format!(
+                "BGCOLOR must be a hexadecimal 0xRRGGBB color, got {}",
+                value
+            ),
+        }),
+    }
+}
+
+/// Time coordinate value of a `TIME` parameter, a number or an ISO 8601 date
+fn time_value(state: &AppState, value: &str) -> Result<f64> {
+    if let Ok(number) = value.trim().parse::<f64>() {
+        return Ok(number);
+    }
+    state
+        .time_coordinate_value("time", value)?
+        .ok_or_else(|| RossbyError::InvalidParameter {
+            param: "time".to_string(),
+            message: format!("Invalid time: {}. Use an ISO 8601 date", value),
+        })
+}
+
+/// Longitudes of a map drawn from one `/image` query of a layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LonPiece {
+    /// Western and eastern grid longitudes, the western one east of the
+    /// eastern one when the piece crosses the seam of a periodic grid
+    pub grid: (f64, f64),
+    /// The same longitudes unwrapped onto the map
+    pub map: (f64, f64),
+}
+
+/// Pieces of a longitude grid covering the map longitudes `west..east`
+///
+/// Periodic grids are repeated around the globe, and the covered columns
+/// split into pieces of less than a turn, sharing their edge columns, so no
+/// gap is left at the seam of the grid. Other grids are tried at their own
+/// longitudes and shifted a turn either way, so maps on -180..180 show
+/// grids stored on 0..360 and the other way around.
+pub fn longitude_pieces(lon_coords: &[f64], west: f64, east: f64) -> Vec<LonPiece> {
+    if !is_periodic_longitude(lon_coords) {
+        let (grid_west, grid_east) = lon_coords
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &c| {
+                (lo.min(c), hi.max(c))
+            });
+        return [-360.0, 0.0, 360.0]
+            .into_iter()
+            .filter_map(|shift| {
+                let start = (west - shift).max(grid_west);
+                let end = (east - shift).min(grid_east);
+                if start > end {
+                    return None;
+                }
+                let (min_lon, max_lon) = covering_span(lon_coords, start, end)?;
+                Some(LonPiece {
+                    grid: (min_lon, max_lon),
+                    map: (min_lon + shift, max_lon + shift),
+                })
+            })
+            .collect();
+    }
+
+    // Column `i` of the repeated grid lies at base[i mod n] + 360 * (i div n)
+    let mut base: Vec<f64> = lon_coords.to_vec();
+    base.sort_by(f64::total_cmp);
+    let n = base.len() as i64;
+    let unwrapped = |i: i64| base[i.rem_euclid(n) as usize] + 360.0 * i.div_euclid(n) as f64;
+    let turn = |lon: f64| ((lon - base[0]) / 360.0).floor() as i64;
+    let first = {
+        let k = turn(west);
+        let j = base
+            .iter()
+            .rposition(|&c| c + 360.0 * k as f64 <= west)
+            .unwrap_or(0);
+        k * n + j as i64
+    };
+    let last = {
+        let k = turn(east);
+        match base.iter().position(|&c| c + 360.0 * k as f64 >= east) {
+            Some(j) => k * n + j as i64,
+            None => (k + 1) * n,
+        }
+    };
+
+    let mut pieces = Vec::new();
+    let mut start = first;
+    while start < last {
+        let end = (start + n - 1).min(last);
+        pieces.push(LonPiece {
+            grid: (
+                base[start.rem_euclid(n) as usize],
+                base[end.rem_euclid(n) as usize],
+            ),
+            map: (unwrapped(start), unwrapped(end)),
+        });
+        start = end;
+    }
+    pieces
+}
+
+/// Render one layer over the part of the map its grid covers
+async fn draw_layer(
+    state: &Arc<AppState>,
+    canvas: &mut RgbaImage,
+    extent: &MapExtent,
+    layer: &str,
+    style: Option<&str>,
+    selectors: &BTreeMap<String, String>,
+) -> Result<()> {
+    let var_meta = state.get_variable_metadata_checked(layer)?;
+    let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+        RossbyError::VariableNotSuitableForImage {
+            name: layer.to_string(),
+        }
+    })?;
+    let lon_coords = state.get_coordinate_checked(&var_meta.dimensions[lon_axis])?;
+    let lat_coords = state.get_coordinate_checked(&var_meta.dimensions[lat_axis])?;
+    let (_, grid_south, _, grid_north) = coordinate_bounds(lon_coords, lat_coords)?;
+
+    let (west, south, east, north) = extent.geographic();
+    let (mut south, mut north) = (south.max(grid_south as f64), north.min(grid_north as f64));
+    if extent.crs == MapCrs::WebMercator {
+        south = south.max(-MAX_MERCATOR_LAT);
+        north = north.min(MAX_MERCATOR_LAT);
+    }
+    if south > north {
+        return Ok(());
+    }
+    let Some((min_lat, max_lat)) = covering_span(lat_coords, south, north) else {
+        return Ok(());
+    };
+
+    let (width, height) = canvas.dimensions();
+    for piece in longitude_pieces(lon_coords, west, east) {
+        // The rendered image has its edge pixel centers on the outermost
+        // coordinates, so it is sized and placed by their pixel centers
+        let (left, top) = extent.pixel(piece.map.0, max_lat, width, height);
+        let (right, bottom) = extent.pixel(piece.map.1, min_lat, width, height);
+        let columns = (right - left).round() as i64 + 1;
+        let rows = (bottom - top).round() as i64 + 1;
+        if columns < 2 || rows < 2 {
+            continue;
+        }
+        if columns > MAX_SIZE as i64 || rows > MAX_SIZE as i64 {
+            return Err(RossbyError::InvalidParameter {
+                param: "bbox".to_string(),
+                message: format!(
+                    "The map is zoomed in too far: a grid cell of {} would span more than {} pixels",
+                    layer, MAX_SIZE
+                ),
+            });
+        }
+
+        let mut image_params = selectors.clone();
+        // ELEVATION only applies to the layers that have levels
+        if level_dimension(state, &var_meta.dimensions).is_none() {
+            image_params.remove("level");
+        }
+        image_params.insert("var".to_string(), layer.to_string());
+        image_params.insert(
+            "bbox".to_string(),
+            format!("{},{},{},{}", piece.grid.0, min_lat, piece.grid.1, max_lat),
+        );
+        if piece.grid.0 > piece.grid.1 {
+            image_params.insert("wrap_longitude".to_string(), "true".to_string());
+        }
+        image_params.insert("width".to_string(), columns.to_string());
+        image_params.insert("height".to_string(), rows.to_string());
+        image_params.insert("format".to_string(), "png".to_string());
+        if extent.crs == MapCrs::WebMercator {
+            image_params.insert("projection".to_string(), "mercator".to_string());
+        }
+        if let Some(style) = style {
+            image_params.insert("colormap".to_string(), style.to_string());
+        }
+        let invalid = |e: &dyn std::fmt::Display| RossbyError::InvalidParameter {
+            param: "layers".to_string(),
+            message: format!("Cannot build image query for {}: {}", layer, e),
+        };
+        let query = serde_urlencoded::to_string(&image_params).map_err(|e| invalid(&e))?;
+        let image_query: ImageQuery =
+            serde_urlencoded::from_str(&query).map_err(|e| invalid(&e))?;
+        let response = generate_image_response(state.clone(), &image_query, &HeaderMap::new())?;
+        let rendered = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|e| RossbyError::ImageGeneration {
+                message: format!("Failed to read rendered image: {}", e),
+            })?;
+        let image = image::load_from_memory_with_format(&rendered, ImageFormat::Png)
+            .map_err(|e| RossbyError::ImageGeneration {
+                message: format!("Failed to decode rendered image: {}", e),
+            })?
+            .to_rgba8();
+        let (x, y) = ((left - 0.5).round() as i64, (top - 0.5).round() as i64);
+        imageops::overlay(canvas, &image, x, y);
+    }
+    Ok(())
+}
+
+/// Capabilities document describing the service and its layers
+fn capabilities(state: &AppState, headers: &HeaderMap) -> String {
+    let url = match headers.get(header::HOST).and_then(|h| h.to_str().ok()) {
+        Some(host) => {
+            let scheme = headers
+                .get("x-forwarded-proto")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or("http");
+            format!("{}://{}/wms?", scheme, host)
+        }
+        None => "/wms?".to_string(),
+    };
+    let globals = &state.metadata.global_attributes;
+    let title = text_attribute(globals, "title").unwrap_or("rossby");
+
+    let mut xml = String::new();
+    let _ = write!(
+        xml,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<WMS_Capabilities version="{version}" xmlns="http://www.opengis.net/wms" xmlns:xlink="http://www.w3.org/1999/xlink">
+<Service>
+<Name>WMS</Name>
+<Title>{title}</Title>
+"#,
+        version = VERSION,
+        title = escape(title),
+    );
+    if let Some(summary) =
+        text_attribute(globals, "summary").or_else(|| text_attribute(globals, "source"))
+    {
+        let _ = writeln!(xml, "<Abstract>{}</Abstract>", escape(summary));
+    }
+    let _ = write!(
+        xml,
+        r#"<OnlineResource xlink:type="simple" xlink:href="{url}"/>
+<LayerLimit>{layers}</LayerLimit>
+<MaxWidth>{size}</MaxWidth>
+<MaxHeight>{size}</MaxHeight>
+</Service>
+<Capability>
+<Request>
+<GetCapabilities>
+<Format>text/xml</Format>
+<DCPType><HTTP><Get><OnlineResource xlink:type="simple" xlink:href="{url}"/></Get></HTTP></DCPType>
+</GetCapabilities>
+<GetMap>
+"#,
+        url = escape(&url),
+        layers = MAX_LAYERS,
+        size = MAX_SIZE,
+    );
+    for format in MAP_FORMATS {
+        let _ = writeln!(xml, "<Format>{}</Format>", format);
+    }
+    let _ = write!(
+        xml,
+        r#"<DCPType><HTTP><Get><OnlineResource xlink:type="simple" xlink:href="{url}"/></Get></HTTP></DCPType>
+</GetMap>
+</Request>
+<Exception>
+<Format>XML</Format>
+</Exception>
+<Layer>
+<Title>{title}</Title>
+"#,
+        url = escape(&url),
+        title = escape(title),
+    );
+    for crs in MapCrs::ALL {
+        let _ = writeln!(xml, "<CRS>{}</CRS>", crs.name());
+    }
+
+    let mut names: Vec<&String> = state
+        .metadata
+        .variables
+        .iter()
+        .filter(|(_, var)| find_lat_lon_axes(&var.dimensions).is_some())
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    let mut layers = String::new();
+    let mut extent: Option<(f64, f64, f64, f64)> = None;
+    for name in names {
+        if let Some(bounds) = layer_xml(state, name, &mut layers) {
+            extent = Some(match extent {
+                Some((w, s, e, n)) => (
+                    w.min(bounds.0),
+                    s.min(bounds.1),
+                    e.max(bounds.2),
+                    n.max(bounds.3),
+                ),
+                None => bounds,
+            });
+        }
+    }
+    bounding_boxes_xml(&mut xml, extent.unwrap_or((-180.0, -90.0, 180.0, 90.0)));
+    xml.push_str(&layers);
+    xml.push_str("</Layer>\n</Capability>\n</WMS_Capabilities>\n");
+    xml
+}
+
+/// Append the description of a layer, returning its geographic bounds
+fn layer_xml(state: &AppState, name: &str, xml: &mut String) -> Option<(f64, f64, f64, f64)> {
+    let var = state.get_variable_metadata(name)?;
+    let bounds = state.get_variable_lat_lon_bounds(name).ok()?;
+    let bounds = geographic_bounds(bounds);
+
+    let _ = write!(
+        xml,
+        "<Layer queryable=\"0\">\n<Name>{}</Name>\n<Title>{}</Title>\n",
+        escape(name),
+        escape(text_attribute(&var.attributes, "long_name").unwrap_or(name)),
+    );
+    if let Some(units) = text_attribute(&var.attributes, "units") {
+        let _ = writeln!(xml, "<Abstract>Units: {}</Abstract>", escape(units));
+    }
+    bounding_boxes_xml(xml, bounds);
+
+    let (lat_axis, lon_axis) = find_lat_lon_axes(&var.dimensions)?;
+    for (axis, dimension) in var.dimensions.iter().enumerate() {
+        if axis == lat_axis || axis == lon_axis {
+            continue;
+        }
+        let Some(values) = state.get_coordinate(dimension) else {
+            continue;
+        };
+        let units = state
+            .get_variable_metadata(dimension)
+            .and_then(|coord| text_attribute(&coord.attributes, "units"))
+            .unwrap_or("none")
+            .to_string();
+        let (wms_name, units, values): (String, String, Vec<String>) =
+            match (dimension.as_str(), time_units(state, dimension)) {
+                ("time", Some(time)) => (
+                    "time".to_string(),
+                    "ISO8601".to_string(),
+                    values
+                        .iter()
+                        .map(|&v| time.iso(v).unwrap_or_else(|| v.to_string()))
+                        .collect(),
+                ),
+                (name, _) => {
+                    let wms_name = if name == "time" {
+                        "time".to_string()
+                    } else if LEVEL_NAMES.contains(&name) {
+                        "elevation".to_string()
+                    } else {
+                        name.to_lowercase()
+                    };
+                    (
+                        wms_name,
+                        units,
+                        values.iter().map(|v| v.to_string()).collect(),
+                    )
+                }
+            };
+        let Some(default) = values.first() else {
+            continue;
+        };
+        let _ = writeln!(
+            xml,
+            "<Dimension name=\"{}\" units=\"{}\" default=\"{}\">{}</Dimension>",
+            escape(&wms_name),
+            escape(&units),
+            escape(default),
+            escape(&values.join(","))
+        );
+    }
+
+    // The default style of a layer is the colormap of its style preset
+    let default_style = match style_preset(state, name) {
+        Some(preset) => preset.colormap.to_lowercase(),
+        None => state.config.data.colormap(None).to_lowercase(),
+    };
+    let styles = std::iter::once(default_style.as_str())
+        .chain(COLORMAP_NAMES.into_iter().filter(|&n| n != default_style));
+    for style in styles {
+        let _ = writeln!(
+            xml,
+            "<Style><Name>{}</Name><Title>{}</Title></Style>",
+            escape(style),
+            escape(style)
+        );
+    }
+    xml.push_str("</Layer>\n");
+    Some(bounds)
+}
+
+/// Text attribute of a variable or file
+fn text_attribute<'a>(
+    attributes: &'a HashMap<String, AttributeValue>,
+    name: &str,
+) -> Option<&'a str> {
+    match attributes.get(name) {
+        Some(AttributeValue::Text(text)) => Some(text),
+        _ => None,
+    }
+}
+
+/// Append the geographic and CRS:84/EPSG:4326 bounding boxes of bounds
+/// given as (west, south, east, north)
+fn bounding_boxes_xml(xml: &mut String, (west, south, east, north): (f64, f64, f64, f64)) {
+    let _ = write!(
+        xml,
+        "<EX_GeographicBoundingBox><westBoundLongitude>{west}</westBoundLongitude>\
+         <eastBoundLongitude>{east}</eastBoundLongitude>\
+         <southBoundLatitude>{south}</southBoundLatitude>\
+         <northBoundLatitude>{north}</northBoundLatitude></EX_GeographicBoundingBox>\n\
+         <BoundingBox CRS=\"CRS:84\" minx=\"{west}\" miny=\"{south}\" maxx=\"{east}\" maxy=\"{north}\"/>\n\
+         <BoundingBox CRS=\"EPSG:4326\" minx=\"{south}\" miny=\"{west}\" maxx=\"{north}\" maxy=\"{east}\"/>\n"
+    );
+}
+
+/// Grid bounds as geographic bounds within -180..180 degrees of longitude
+///
+/// Grids on 0..360 lie within -180..180 when shifted a turn west if they
+/// start at or past 180 degrees, and cover all longitudes otherwise.
+pub fn geographic_bounds(
+    (min_lon, min_lat, max_lon, max_lat): (f32, f32, f32, f32),
+) -> (f64, f64, f64, f64) {
+    let (mut west, mut east) = (min_lon as f64, max_lon as f64);
+    if east > 180.0 {
+        if west >= 180.0 {
+            west -= 360.0;
+            east -= 360.0;
+        } else {
+            west = -180.0;
+            east = 180.0;
+        }
+    }
+    (
+        west.max(-180.0),
+        (min_lat as f64).max(-90.0),
+        east.min(180.0),
+        (max_lat as f64).min(90.0),
+    )
+}
+
+/// Exception code of an error, as defined by WMS 1.3.0
+fn exception_code(error: &RossbyError) -> Option<&'static str> {
+    match error {
+        RossbyError::VariableNotFound { .. }
+        | RossbyError::InvalidVariables { .. }
+        | RossbyError::VariableNotSuitableForImage { .. } => Some("LayerNotDefined"),
+        RossbyError::PhysicalValueNotFound { .. } | RossbyError::IndexOutOfBounds { .. } => {
+            Some("InvalidDimensionValue")
+        }
+        RossbyError::InvalidParameter { param, .. } => match param.as_str() {
+            "request" => Some("OperationNotSupported"),
+            "crs" => Some("InvalidCRS"),
+            "format" => Some("InvalidFormat"),
+            "styles" | "colormap" => Some("StyleNotDefined"),
+            "time" | "level" => Some("InvalidDimensionValue"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Service exception report of an error
+///
+/// The parameter at fault, if known, is given as the locator, and the
+/// request id in a comment.
+fn exception_report(error: &RossbyError, request_id: &str) -> String {
+    let code = exception_code(error)
+        .map(|code| format!(" code=\"{}\"", code))
+        .unwrap_or_default();
+    let locator = match error {
+        RossbyError::InvalidParameter { param, .. } => {
+            format!(" locator=\"{}\"", escape(&param.to_uppercase()))
+        }
+        _ => String::new(),
+    };
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- request_id: {} -->
+<ServiceExceptionReport version="{}" xmlns="http://www.opengis.net/ogc">
+<ServiceException{}{}>{}</ServiceException>
+</ServiceExceptionReport>
+"#,
+        escape(request_id),
+        VERSION,
+        code,
+        locator,
+        escape(&error.to_string())
+    )
+}
+
+/// Escape text for XML content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_extent() {
+        // EPSG:4326 is latitude first in 1.3.0, longitude first before
+        let extent = MapExtent::parse("-90,-180,90,180", MapCrs::Epsg4326, "1.3.0").unwrap();
+        assert_eq!(extent.geographic(), (-180.0, -90.0, 180.0, 90.0));
+        let extent = MapExtent::parse("-180,-90,180,90", MapCrs::Epsg4326, "1.1.1").unwrap();
+        assert_eq!(extent.geographic(), (-180.0, -90.0, 180.0, 90.0));
+        assert_eq!(extent.pixel(0.0, 0.0, 360, 180), (180.0, 90.0));
+
+        let half = std::f64::consts::PI * WEB_MERCATOR_RADIUS;
+        let extent = MapExtent::parse(
+            &format!("{},{},{},{}", -half, -half, half, half),
+            MapCrs::WebMercator,
+            VERSION,
+        )
+        .unwrap();
+        let (west, south, east, north) = extent.geographic();
+        assert!((west + 180.0).abs() < 1e-9 && (east - 180.0).abs() < 1e-9);
+        assert!((north - MAX_MERCATOR_LAT).abs() < 1e-9 && (south + north).abs() < 1e-9);
+        let (column, row) = extent.pixel(90.0, 0.0, 256, 256);
+        assert!((column - 192.0).abs() < 1e-9 && (row - 128.0).abs() < 1e-9);
+
+        for invalid in ["0,0,1", "0,0,a,1", "1,0,0,1"] {
+            assert!(MapExtent::parse(invalid, MapCrs::Crs84, VERSION).is_err());
+        }
+        assert!(MapCrs::parse("EPSG:32633").is_err());
+    }
+
+    #[test]
+    fn test_covering_span() {
+        let coords = [0.0, 10.0, 20.0, 30.0];
+        assert_eq!(covering_span(&coords, 5.0, 15.0), Some((0.0, 20.0)));
+        assert_eq!(covering_span(&coords, 10.0, 20.0), Some((10.0, 20.0)));
+        assert_eq!(covering_span(&coords, 12.0, 13.0), Some((10.0, 20.0)));
+        assert_eq!(
+            covering_span(&[30.0, 20.0, 10.0], 12.0, 13.0),
+            Some((10.0, 20.0))
+        );
+        assert_eq!(covering_span(&coords, 10.0, 10.0), None);
+        assert_eq!(covering_span(&coords, 25.0, 35.0), None);
+    }
+
+    #[test]
+    fn test_longitude_pieces() {
+        // A periodic 0..350 grid covers -180..180 in two pieces, the first
+        // crossing its seam, sharing the column at 170 degrees
+        let global: Vec<f64> = (0..36).map(|i| i as f64 * 10.0).collect();
+        assert_eq!(
+            longitude_pieces(&global, -180.0, 180.0),
+            vec![
+                LonPiece {
+                    grid: (180.0, 170.0),
+                    map: (-180.0, 170.0)
+                },
+                LonPiece {
+                    grid: (170.0, 180.0),
+                    map: (170.0, 180.0)
+                },
+            ]
+        );
+        assert_eq!(
+            longitude_pieces(&global, -15.0, 5.0),
+            vec![LonPiece {
+                grid: (340.0, 10.0),
+                map: (-20.0, 10.0)
+            }]
+        );
+        assert_eq!(
+            longitude_pieces(&global, 355.0, 365.0),
+            vec![LonPiece {
+                grid: (350.0, 10.0),
+                map: (350.0, 370.0)
+            }]
+        );
+
+        // A regional grid is shifted a turn to meet the map
+        let regional = [200.0, 210.0, 220.0];
+        assert_eq!(
+            longitude_pieces(&regional, -155.0, 0.0),
+            vec![LonPiece {
+                grid: (200.0, 220.0),
+                map: (-160.0, -140.0)
+            }]
+        );
+        assert!(longitude_pieces(&regional, 0.0, 90.0).is_empty());
+    }
+
+    #[test]
+    fn test_geographic_bounds() {
+        assert_eq!(
+            geographic_bounds((0.0, -90.0, 350.0, 80.0)),
+            (-180.0, -90.0, 180.0, 80.0)
+        );
+        assert_eq!(
+            geographic_bounds((200.0, 0.0, 300.0, 10.0)),
+            (-160.0, 0.0, -60.0, 10.0)
+        );
+        assert_eq!(
+            geographic_bounds((-20.0, 30.0, 40.0, 70.0)),
+            (-20.0, 30.0, 40.0, 70.0)
+        );
+    }
+
+    #[test]
+    fn test_styles_and_colors() {
+        assert_eq!(parse_styles("", 2).unwrap(), vec![None, None]);
+        assert_eq!(
+            parse_styles("default,Plasma", 2).unwrap(),
+            vec![None, Some("plasma".to_string())]
+        );
+        assert_eq!(
+            parse_styles("RdYlBu_r", 1).unwrap(),
+            vec![Some("rdylbu_r".to_string())]
+        );
+        assert!(parse_styles("viridis", 2).is_err());
+        assert!(parse_styles("rainbow", 1).is_err());
+
+        assert_eq!(parse_bgcolor(None).unwrap(), Rgba([255, 255, 255, 255]));
+        assert_eq!(
+            parse_bgcolor(Some("0x102030")).unwrap(),
+            Rgba([16, 32, 48, 255])
+        );
+        assert!(parse_bgcolor(Some("#102030")).is_err());
+    }
+
+    #[test]
+    fn test_exception_report() {
+        let report = exception_report(
+            &RossbyError::InvalidParameter {
+                param: "crs".to_string(),
+                message: "Unsupported CRS: <x>".to_string(),
+            },
+            "abc",
+        );
+        assert!(report.contains("code=\"InvalidCRS\" locator=\"CRS\""));
+        assert!(report.contains("&lt;x&gt;"));
+        assert_eq!(
+            exception_code(&RossbyError::VariableNotFound {
+                name: "t".to_string()
+            }),
+            Some("LayerNotDefined")
+        );
+    }
+}
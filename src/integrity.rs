@@ -0,0 +1,517 @@
+//! Background self-check of the loaded data.
+//!
+//! A long-running server holds its dataset in memory for weeks, where a
+//! flipped bit would silently change every response built from it. With
+//! `data.self_check_interval_secs` set, a background task periodically
+//! verifies the data against checksums taken when it was loaded, and
+//! compares randomly sampled cells with the data file while the file is
+//! still readable. The outcome of the last check is reported by
+//! `GET /healthz`.
+//!
+//! The checksums cover the data of every variable in chunks of
+//! [`CHUNK_VALUES`] values, so a failed check names the variable and the
+//! range of values that changed. When appended time steps replace the
+//! state (see [`crate::append`]), checksums of the new state are taken
+//! before its first check.
+
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::data_loader::source_for;
+use crate::error::{Result, RossbyError};
+use crate::packed::VariableValues;
+use crate::sampling::SplitMix64;
+use crate::state::AppState;
+
+/// Number of values covered by each checksum
+pub const CHUNK_VALUES: usize = 1 << 16;
+
+/// Number of failures listed in a report
+const MAX_REPORTED_FAILURES: usize = 20;
+
+/// Checksums of the data arrays of a dataset state
+#[derive(Debug, Clone)]
+pub struct Checksums {
+    /// Generation of the state the checksums were taken of
+    generation: u64,
+    /// Checksums of consecutive chunks of each variable, by name
+    chunks: BTreeMap<String, Vec<u64>>,
+}
+
+impl Checksums {
+    /// Take the checksums of every variable of a state
+    pub fn of(state: &AppState) -> Self {
+        let chunks = state
+            .variable_values()
+            .map(|(name, array)| (name.clone(), chunk_checksums(array)))
+            .collect();
+        Self {
+            generation: state.generation,
+            chunks,
+        }
+    }
+
+    /// Number of chunks covered
+    pub fn len(&self) -> usize {
+        self.chunks.values().map(Vec::len).sum()
+    }
+
+    /// Whether no data is covered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Descriptions of the chunks of a state that no longer match
+    pub fn verify(&self, state: &AppState) -> Vec<String> {
+        let mut failures = Vec::new();
+        for (name, expected) in &self.chunks {
+            let Some(array) = state.get_variable_values(name) else {
+                failures.push(format!("{}: variable is missing", name));
+                continue;
+            };
+            let actual = chunk_checksums(array);
+            if actual.len() != expected.len() {
+                failures.push(format!("{}: size changed to {} values", name, array.len()));
+                continue;
+            }
+            for (chunk, _) in actual
+                .iter()
+                .zip(expected)
+                .enumerate()
+                .filter(|(_, (a, e))| a != e)
+            {
+                let start = chunk * CHUNK_VALUES;
+                let end = (start + CHUNK_VALUES).min(array.len());
+                failures.push(format!(
+                    "{}: values {}..{} changed since loading",
+                    name, start, end
+                ));
+            }
+        }
+        failures
+    }
+}
+
+/// Checksums of consecutive chunks of an array's values, in logical order
+fn chunk_checksums(array: VariableValues<'_>) -> Vec<u64> {
+    let mut checksums = Vec::with_capacity(array.len().div_ceil(CHUNK_VALUES));
+    let mut hasher = DefaultHasher::new();
+    let mut hashed = 0;
+    for value in array.iter() {
+        hasher.write_u32(value.to_bits());
+        hashed += 1;
+        if hashed == CHUNK_VALUES {
+            checksums.push(hasher.finish());
+            hasher = DefaultHasher::new();
+            hashed = 0;
+        }
+    }
+    if hashed > 0 {
+        checksums.push(hasher.finish());
+    }
+    checksums
+}
+
+/// How the sampled cells compared with the data file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceComparison {
+    /// Every sampled cell has the value read from the file
+    Matched,
+    /// Some sampled cells differ from the file
+    Mismatched,
+    /// The file could not be read
+    Unavailable,
+    /// The file format cannot be read one value at a time
+    Unsupported,
+    /// The dataset has no data file
+    NoFile,
+}
+
+/// Outcome of one self-check
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    /// When the check finished (ISO 8601)
+    pub checked_at: String,
+    /// Generation of the checked dataset state
+    pub generation: u64,
+    /// Whether no corruption was found
+    pub passed: bool,
+    /// Time the check took, in milliseconds
+    pub duration_ms: u64,
+    /// Number of checksummed chunks verified
+    pub chunks_verified: usize,
+    /// Number of random cells compared with the data file
+    pub cells_sampled: usize,
+    /// How the sampled cells compared with the data file
+    pub source: SourceComparison,
+    /// Why the data file could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_error: Option<String>,
+    /// What was found to be corrupted, if anything
+    pub failures: Vec<String>,
+}
+
+/// The last self-check report, shared with the `/healthz` handler
+#[derive(Debug, Clone, Default)]
+pub struct SelfCheckStatus {
+    last: Arc<RwLock<Option<CheckReport>>>,
+}
+
+impl SelfCheckStatus {
+    /// Status with no check run yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the report of a finished check
+    pub fn record(&self, report: CheckReport) {
+        *self.last.write() = Some(report);
+    }
+
+    /// Report of the last finished check, if any
+    pub fn last(&self) -> Option<CheckReport> {
+        self.last.read().clone()
+    }
+}
+
+/// Periodic checks of a dataset state
+#[derive(Debug)]
+pub struct SelfCheck {
+    /// Checksums of the state being checked
+    checksums: Checksums,
+    /// Number of cells compared with the data file per check
+    samples: usize,
+    /// Random number generator picking cells
+    rng: SplitMix64,
+}
+
+impl SelfCheck {
+    /// Start checking a state, taking its checksums
+    pub fn new(state: &AppState, samples: usize) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::with_seed(state, samples, seed)
+    }
+
+    /// Start checking a state, picking cells from a fixed seed
+    pub fn with_seed(state: &AppState, samples: usize, seed: u64) -> Self {
+        Self {
+            checksums: Checksums::of(state),
+            samples,
+            rng: SplitMix64::new(seed),
+        }
+    }
+
+    /// Check a state, taking new checksums first when it is a new generation
+    pub fn run(&mut self, state: &AppState) -> CheckReport {
+        let started = Instant::now();
+        if state.generation != self.checksums.generation {
+            self.checksums = Checksums::of(state);
+        }
+        let mut failures = self.checksums.verify(state);
+
+        let cells = self.sample_cells(state);
+        let (source, source_error) = match &state.config.data.file_path {
+            None => (SourceComparison::NoFile, None),
+            Some(path) => match compare_with_source(state, path, &cells) {
+                Ok(None) => (SourceComparison::Unsupported, None),
+                Ok(Some(mismatches)) if mismatches.is_empty() => (SourceComparison::Matched, None),
+                Ok(Some(mismatches)) => {
+                    failures.extend(mismatches);
+                    (SourceComparison::Mismatched, None)
+                }
+                Err(e) => (SourceComparison::Unavailable, Some(e.to_string())),
+            },
+        };
+
+        let passed = failures.is_empty();
+        failures.truncate(MAX_REPORTED_FAILURES);
+        CheckReport {
+            checked_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            generation: state.generation,
+            passed,
+            duration_ms: started.elapsed().as_millis() as u64,
+            chunks_verified: self.checksums.len(),
+            cells_sampled: cells.len(),
+            source,
+            source_error,
+            failures,
+        }
+    }
+
+    /// Random cells of the loaded variables, as (variable, index) pairs
+    fn sample_cells(&mut self, state: &AppState) -> Vec<(String, Vec<usize>)> {
+        let mut names: Vec<&String> = state
+            .variable_values()
+            .filter(|(_, array)| !array.is_empty())
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        if names.is_empty() {
+            return Vec::new();
+        }
+        (0..self.samples)
+            .map(|_| {
+                let name = names[self.rng.below(names.len())];
+                let index = state
+                    .get_variable_values(name)
+                    .map_or(&[][..], |array| array.shape())
+                    .iter()
+                    .map(|&len| self.rng.below(len))
+                    .collect();
+                (name.clone(), index)
+            })
+            .collect()
+    }
+}
+
+/// Compare cells of the loaded data with the values in the data file
+///
+/// Returns descriptions of the cells that differ, or `None` when the file
+/// format cannot be read one value at a time.
+fn compare_with_source(
+    state: &AppState,
+    path: &Path,
+    cells: &[(String, Vec<usize>)],
+) -> Result<Option<Vec<String>>> {
+    let source = source_for(path)?;
+    let mut by_variable: BTreeMap<&str, Vec<Vec<usize>>> = BTreeMap::new();
+    for (name, index) in cells {
+        by_variable.entry(name).or_default().push(index.clone());
+    }
+
+    let mut mismatches = Vec::new();
+    for (name, indices) in by_variable {
+        // Coordinates renamed or fixed at load differ from the file on purpose
+        if state
+            .coordinate_changes
+            .iter()
+            .any(|change| change.dimension == name)
+        {
+            continue;
+        }
+        let Some(values) = source.read_values(path, name, &indices)? else {
+            return Ok(None);
+        };
+        let array = state.get_variable_values_checked(name)?;
+        for (index, file_value) in indices.iter().zip(values) {
+            let loaded = array.get(index).unwrap_or(f32::NAN);
+            let same = loaded == file_value || (loaded.is_nan() && file_value.is_nan());
+            if !same {
+                mismatches.push(format!(
+                    "{}{:?}: {} in memory, {} in {}",
+                    name,
+                    index,
+                    loaded,
+                    file_value,
+                    path.display()
+                ));
+            }
+        }
+    }
+    Ok(Some(mismatches))
+}
+
+/// Check the current state every `interval` until the states channel closes
+///
+/// The receiver yields the state being served, which changes when time
+/// steps are appended. Checks run on a blocking thread, as they read all
+/// data.
+pub async fn run_self_checks(
+    mut states: watch::Receiver<Arc<AppState>>,
+    interval: Duration,
+    samples: usize,
+) {
+    let state = states.borrow_and_update().clone();
+    let check = tokio::task::spawn_blocking(move || SelfCheck::new(&state, samples)).await;
+    let mut check = match check {
+        Ok(check) => check,
+        Err(e) => {
+            error!(error = %e, "Failed to take data checksums");
+            return;
+        }
+    };
+    info!(
+        chunks = check.checksums.len(),
+        "Took data checksums for self-checks"
+    );
+
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let state = states.borrow().clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let report = check.run(&state);
+            state.self_check.record(report.clone());
+            (check, report)
+        })
+        .await
+        .map_err(|e| RossbyError::Server {
+            message: format!("Self-check task failed: {}", e),
+        });
+
+        match result {
+            Ok((returned, report)) => {
+                check = returned;
+                if report.passed {
+                    info!(
+                        generation = report.generation,
+                        duration_ms = report.duration_ms,
+                        source = ?report.source,
+                        "Self-check passed"
+                    );
+                } else {
+                    error!(
+                        generation = report.generation,
+                        failures = ?report.failures,
+                        "Self-check found corrupted data"
+                    );
+                }
+            }
+            Err(e) => {
+                warn!(error = %e, "Self-check did not complete");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata, Variable};
+    use ndarray::{Array, IxDyn};
+    use std::collections::HashMap;
+
+    /// State with a (time, x) variable of `len` values and no data file
+    fn state(len: usize) -> AppState {
+        let dims = [("time", 2), ("x", len / 2)];
+        let dimensions = dims
+            .iter()
+            .map(|&(name, size)| {
+                let dimension = Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                };
+                (name.to_string(), dimension)
+            })
+            .collect();
+        let variable = Variable {
+            name: "v".to_string(),
+            dimensions: vec!["time".to_string(), "x".to_string()],
+            shape: vec![2, len / 2],
+            attributes: HashMap::new(),
+            dtype: "f32".to_string(),
+        };
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables: HashMap::from([("v".to_string(), variable)]),
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        };
+        let values = Array::from_shape_fn(IxDyn(&[2, len / 2]), |i| (i[0] * len + i[1]) as f32);
+        AppState::new(
+            Config::default(),
+            metadata,
+            HashMap::from([("v".to_string(), values)]),
+        )
+    }
+
+    #[test]
+    fn test_checksums_find_changed_chunks() {
+        let mut state = state(3 * CHUNK_VALUES);
+        let checksums = Checksums::of(&state);
+        assert_eq!(checksums.len(), 3);
+        assert!(checksums.verify(&state).is_empty());
+
+        // Flip one bit of a value in the second chunk
+        let value = &mut state.data.get_mut("v").unwrap()[[0, CHUNK_VALUES + 7]];
+        *value = f32::from_bits(value.to_bits() ^ 1);
+        assert_eq!(
+            checksums.verify(&state),
+            vec![format!(
+                "v: values {}..{} changed since loading",
+                CHUNK_VALUES,
+                2 * CHUNK_VALUES
+            )]
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mut state = state(100);
+        let mut check = SelfCheck::with_seed(&state, 10, 42);
+        let report = check.run(&state);
+        assert!(report.passed);
+        assert_eq!(report.chunks_verified, 1);
+        assert_eq!(report.cells_sampled, 10);
+        assert_eq!(report.source, SourceComparison::NoFile);
+
+        state.data.get_mut("v").unwrap()[[1, 3]] = -1.0;
+        let report = check.run(&state);
+        assert!(!report.passed);
+        assert_eq!(report.failures.len(), 1);
+
+        // A new generation gets new checksums
+        let appended = AppState::new(state.config.clone(), state.metadata.clone(), state.data);
+        assert!(check.run(&appended).passed);
+    }
+
+    #[test]
+    fn test_compare_with_source() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("v.nc");
+        let mut state = state(100);
+        {
+            let mut file = netcdf::create(&path)?;
+            file.add_dimension("time", 2)?;
+            file.add_dimension("x", 50)?;
+            let mut var = file.add_variable::<f32>("v", &["time", "x"])?;
+            let values: Vec<f32> = state.data["v"].iter().copied().collect();
+            var.put_values(&values, ..)?;
+        }
+        state.config.data.file_path = Some(path.clone());
+
+        let cells = vec![("v".to_string(), vec![1, 3]), ("v".to_string(), vec![0, 0])];
+        assert_eq!(compare_with_source(&state, &path, &cells)?, Some(vec![]));
+
+        state.data.get_mut("v").unwrap()[[1, 3]] = -1.0;
+        let mismatches = compare_with_source(&state, &path, &cells)?.unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].starts_with("v[1, 3]: -1 in memory, 103 in"));
+
+        // A missing file makes the comparison unavailable, not a failure
+        let mut check = SelfCheck::with_seed(&state, 5, 7);
+        std::fs::remove_file(&path)?;
+        let report = check.run(&state);
+        assert_eq!(report.source, SourceComparison::Unavailable);
+        assert!(report.passed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status() {
+        let status = SelfCheckStatus::new();
+        assert!(status.last().is_none());
+        let shared = status.clone();
+        let state = state(10);
+        shared.record(SelfCheck::with_seed(&state, 1, 1).run(&state));
+        assert!(status.last().unwrap().passed);
+    }
+}
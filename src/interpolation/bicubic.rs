@@ -10,6 +10,15 @@
 //!
 //! While most commonly used for 2D data (hence "bicubic"), this implementation
 //! generalizes to N dimensions through recursive application.
+//!
+//! Resampling a whole 2D grid, as image rendering does, has a faster path:
+//! the kernel of an output pixel is the product of the kernels of its row and
+//! column, so [`resample_separable`] computes each row and column kernel once
+//! and applies them in two parallel passes, with the same result as
+//! interpolating every pixel.
+
+use ndarray::{Array2, ArrayView2};
+use rayon::prelude::*;
 
 use super::Interpolator;
 use crate::error::Result;
@@ -125,6 +134,87 @@ fn interpolate_nd(data: &[f32], shape: &[usize], indices: &[f64], dim: usize) ->
     Ok(result as f32)
 }
 
+/// Cubic kernel of one output sample along an axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CubicKernel {
+    /// Indices of the four input samples, clamped to the axis
+    pub positions: [usize; 4],
+    /// Weights of the four input samples
+    pub weights: [f64; 4],
+}
+
+impl CubicKernel {
+    /// Kernel at a fractional index along an axis of `size` samples
+    pub fn at(index: f64, size: usize) -> Self {
+        let idx = common::clamp_index(index, size);
+        let i = idx.floor() as usize;
+        let frac = idx - i as f64;
+        Self {
+            positions: [
+                i.saturating_sub(1),
+                i,
+                (i + 1).min(size - 1),
+                (i + 2).min(size - 1),
+            ],
+            weights: common::cubic_weights(frac),
+        }
+    }
+
+    /// Apply the kernel to the samples returned by `sample`
+    fn apply(&self, sample: impl Fn(usize) -> f32) -> f32 {
+        let mut result = 0.0;
+        for (&position, &weight) in self.positions.iter().zip(&self.weights) {
+            result += sample(position) as f64 * weight;
+        }
+        result as f32
+    }
+}
+
+/// Resample a 2D grid with separable bicubic kernels
+///
+/// `rows` and `cols` hold the kernel of each output row and column, for
+/// example computed with [`CubicKernel::at`]. The result has one element per
+/// pair of them and equals [`BicubicInterpolator`] at the same fractional
+/// indices, including the propagation of NaN, but every kernel is computed
+/// once and rows are resampled in parallel.
+pub fn resample_separable(
+    data: ArrayView2<f32>,
+    rows: &[CubicKernel],
+    cols: &[CubicKernel],
+) -> Array2<f32> {
+    let width = cols.len();
+
+    // Resample every input row to the output columns first
+    let mut horizontal = vec![0.0f32; data.nrows() * width];
+    if width > 0 {
+        horizontal
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, out)| {
+                let row = data.row(y);
+                for (value, kernel) in out.iter_mut().zip(cols) {
+                    *value = kernel.apply(|i| row[i]);
+                }
+            });
+    }
+
+    // Then combine the resampled input rows of each output row
+    let mut values = vec![0.0f32; rows.len() * width];
+    if width > 0 {
+        values
+            .par_chunks_mut(width)
+            .zip(rows.par_iter())
+            .for_each(|(out, kernel)| {
+                for (x, value) in out.iter_mut().enumerate() {
+                    *value = kernel.apply(|i| horizontal[i * width + x]);
+                }
+            });
+    }
+
+    Array2::from_shape_vec((rows.len(), width), values)
+        .expect("resampled values match the kernel counts")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -219,6 +309,51 @@ mod tests {
         assert!(v4 < v5);
     }
 
+    #[test]
+    fn test_resample_separable() {
+        // An irregular field with a missing value
+        let mut data = Array2::from_shape_fn((6, 9), |(y, x)| {
+            ((y * 7 + x * 3) % 11) as f32 * 1.7 - (x as f32).sqrt()
+        });
+        data[[4, 2]] = f32::NAN;
+        let flat: Vec<f32> = data.iter().cloned().collect();
+        let shape = [6, 9];
+
+        let row_indices = [-0.5, 0.0, 0.4, 1.25, 2.5, 4.99, 5.0, 7.0];
+        let col_indices = [0.0, 0.3, 1.0, 3.75, 6.5, 8.0, 8.2];
+        let rows: Vec<_> = row_indices
+            .iter()
+            .map(|&index| CubicKernel::at(index, shape[0]))
+            .collect();
+        let cols: Vec<_> = col_indices
+            .iter()
+            .map(|&index| CubicKernel::at(index, shape[1]))
+            .collect();
+
+        let resampled = resample_separable(data.view(), &rows, &cols);
+        assert_eq!(resampled.dim(), (row_indices.len(), col_indices.len()));
+        let interpolator = BicubicInterpolator;
+        for (y, &data_y) in row_indices.iter().enumerate() {
+            for (x, &data_x) in col_indices.iter().enumerate() {
+                let expected = interpolator
+                    .interpolate(&flat, &shape, &[data_y, data_x])
+                    .unwrap();
+                let actual = resampled[[y, x]];
+                assert!(
+                    actual.to_bits() == expected.to_bits(),
+                    "({}, {}): {} != {}",
+                    data_y,
+                    data_x,
+                    actual,
+                    expected
+                );
+            }
+        }
+        assert!(resampled[[5, 3]].is_nan());
+
+        assert_eq!(resample_separable(data.view(), &rows, &[]).dim(), (8, 0));
+    }
+
     #[test]
     fn test_bicubic_error_cases() {
         // Too small grid
@@ -7,6 +7,7 @@ pub mod bicubic;
 pub mod bilinear;
 pub mod common;
 pub mod nearest;
+pub mod stencil;
 
 use crate::error::Result;
 
@@ -0,0 +1,255 @@
+//! Interpolation stencils.
+//!
+//! A stencil lists what an interpolator reads to produce one value: the grid
+//! positions and weights along each axis, the raw values at every combination
+//! of those positions, and the values left after interpolating along all axes
+//! but the first. The interpolators reduce the last axis first and round to
+//! `f32` after each axis, so the intermediate values are exactly those they
+//! combine along the first axis.
+
+use ndarray::{ArrayViewD, IxDyn};
+
+use super::bicubic::CubicKernel;
+use super::common;
+use crate::error::{Result, RossbyError};
+
+/// Positions and weights of an interpolation along one axis
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisKernel {
+    /// Fractional index interpolated at
+    pub index: f64,
+    /// Indices of the grid points read
+    pub positions: Vec<usize>,
+    /// Weight of each grid point
+    pub weights: Vec<f64>,
+}
+
+impl AxisKernel {
+    /// Kernel of an interpolation method at a fractional index along an axis
+    /// of `size` points
+    pub fn new(method: &str, index: f64, size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(RossbyError::Interpolation {
+                message: "Cannot interpolate along an empty axis".to_string(),
+            });
+        }
+        let (positions, weights) = match method.to_lowercase().as_str() {
+            "nearest" => (
+                vec![common::clamp_index(index.round(), size) as usize],
+                vec![1.0],
+            ),
+            "bilinear" => {
+                let idx = common::clamp_index(index, size);
+                let i0 = idx.floor() as usize;
+                let i1 = (i0 + 1).min(size - 1);
+                if i0 == i1 {
+                    (vec![i0], vec![1.0])
+                } else {
+                    let (w0, w1) = common::linear_weight(idx - i0 as f64);
+                    (vec![i0, i1], vec![w0, w1])
+                }
+            }
+            "bicubic" => {
+                if size < 4 {
+                    return Err(RossbyError::Interpolation {
+                        message: format!(
+                            "Axis has size {}, but bicubic interpolation requires at least 4 points",
+                            size
+                        ),
+                    });
+                }
+                let kernel = CubicKernel::at(index, size);
+                (kernel.positions.to_vec(), kernel.weights.to_vec())
+            }
+            _ => {
+                return Err(RossbyError::InvalidParameter {
+                    param: "interpolation".to_string(),
+                    message: format!("Unknown interpolation method: {}", method),
+                })
+            }
+        };
+        Ok(Self {
+            index,
+            positions,
+            weights,
+        })
+    }
+}
+
+/// One grid point read by an interpolation
+#[derive(Debug, Clone, PartialEq)]
+pub struct StencilPoint {
+    /// Index of the point along each axis
+    pub indices: Vec<usize>,
+    /// Raw value at the point
+    pub value: f32,
+    /// Product of the weights of the point along each axis
+    pub weight: f64,
+}
+
+/// Everything read and computed to interpolate one value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stencil {
+    /// Kernel along each axis
+    pub axes: Vec<AxisKernel>,
+    /// Every combination of the positions of the kernels, last axis fastest
+    pub points: Vec<StencilPoint>,
+    /// Value interpolated along the remaining axes at each position of the
+    /// first axis
+    pub intermediate: Vec<f32>,
+    /// The interpolated value
+    pub value: f32,
+}
+
+/// Trace the interpolation of `data` at fractional `indices`
+pub fn trace(method: &str, data: ArrayViewD<f32>, indices: &[f64]) -> Result<Stencil> {
+    if indices.len() != data.ndim() || indices.is_empty() {
+        return Err(RossbyError::Interpolation {
+            message: format!(
+                "Dimension mismatch: indices has {} dimensions but data has {} dimensions",
+                indices.len(),
+                data.ndim()
+            ),
+        });
+    }
+    let axes = indices
+        .iter()
+        .zip(data.shape())
+        .map(|(&index, &size)| AxisKernel::new(method, index, size))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut points = Vec::new();
+    let mut position = Vec::with_capacity(axes.len());
+    collect_points(&data, &axes, &mut position, 1.0, &mut points);
+
+    let mut position = vec![0; axes.len()];
+    let intermediate: Vec<f32> = axes[0]
+        .positions
+        .iter()
+        .map(|&i| {
+            position[0] = i;
+            reduce(&data, &axes, &mut position, 1)
+        })
+        .collect();
+    let value = combine(&intermediate, &axes[0].weights);
+
+    Ok(Stencil {
+        axes,
+        points,
+        intermediate,
+        value,
+    })
+}
+
+/// Add the points of the kernels from axis `position.len()` on
+fn collect_points(
+    data: &ArrayViewD<f32>,
+    axes: &[AxisKernel],
+    position: &mut Vec<usize>,
+    weight: f64,
+    points: &mut Vec<StencilPoint>,
+) {
+    let Some(axis) = axes.get(position.len()) else {
+        points.push(StencilPoint {
+            indices: position.clone(),
+            value: data[IxDyn(position)],
+            weight,
+        });
+        return;
+    };
+    for (&i, &w) in axis.positions.iter().zip(&axis.weights) {
+        position.push(i);
+        collect_points(data, axes, position, weight * w, points);
+        position.pop();
+    }
+}
+
+/// Interpolate along the axes from `dim` on, with lower axes at `position`
+fn reduce(data: &ArrayViewD<f32>, axes: &[AxisKernel], position: &mut [usize], dim: usize) -> f32 {
+    let Some(axis) = axes.get(dim) else {
+        return data[IxDyn(position)];
+    };
+    let values: Vec<f32> = axis
+        .positions
+        .iter()
+        .map(|&i| {
+            position[dim] = i;
+            reduce(data, axes, position, dim + 1)
+        })
+        .collect();
+    combine(&values, &axis.weights)
+}
+
+/// Weighted sum of values, rounded to `f32` as the interpolators do
+fn combine(values: &[f32], weights: &[f64]) -> f32 {
+    if let ([value], [_]) = (values, weights) {
+        return *value;
+    }
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&value, &weight)| value as f64 * weight)
+        .sum::<f64>() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpolation::get_interpolator;
+    use ndarray::Array;
+
+    #[test]
+    fn test_axis_kernel() {
+        let kernel = AxisKernel::new("bilinear", 1.25, 5).unwrap();
+        assert_eq!(kernel.positions, vec![1, 2]);
+        assert_eq!(kernel.weights, vec![0.75, 0.25]);
+        assert_eq!(
+            AxisKernel::new("bilinear", 4.0, 5).unwrap().positions,
+            vec![4]
+        );
+        assert_eq!(
+            AxisKernel::new("nearest", 1.6, 5).unwrap().positions,
+            vec![2]
+        );
+        assert_eq!(
+            AxisKernel::new("bicubic", 0.5, 5).unwrap().positions,
+            vec![0, 0, 1, 2]
+        );
+        assert!(AxisKernel::new("bicubic", 0.5, 3).is_err());
+        assert!(AxisKernel::new("spline", 0.5, 5).is_err());
+    }
+
+    #[test]
+    fn test_trace_matches_interpolators() {
+        let data = Array::from_shape_fn(IxDyn(&[6, 7]), |index| {
+            ((index[0] * 13 + index[1] * 7) % 11) as f32 * 1.7 - 3.1
+        });
+        let shape = data.shape().to_vec();
+        let slice = data.as_slice().unwrap();
+
+        for method in ["nearest", "bilinear", "bicubic"] {
+            let interpolator = get_interpolator(method).unwrap();
+            for indices in [[0.3, 0.9], [2.6, 4.1], [5.0, 6.0], [4.5, 0.0], [1.0, 2.0]] {
+                let stencil = trace(method, data.view(), &indices).unwrap();
+                let expected = interpolator.interpolate(slice, &shape, &indices).unwrap();
+                assert_eq!(stencil.value, expected, "{} at {:?}", method, indices);
+
+                let points: usize = stencil.axes.iter().map(|a| a.positions.len()).product();
+                assert_eq!(stencil.points.len(), points);
+                assert_eq!(stencil.intermediate.len(), stencil.axes[0].positions.len());
+                let total: f64 = stencil.points.iter().map(|p| p.weight).sum();
+                assert!((total - 1.0).abs() < 1e-9);
+            }
+        }
+
+        // Bilinear between four points
+        let stencil = trace("bilinear", data.view(), &[2.5, 4.0]).unwrap();
+        assert_eq!(stencil.points.len(), 4);
+        assert_eq!(stencil.points[1].indices, vec![2, 5]);
+        assert_eq!(stencil.points[1].value, data[[2, 5]]);
+        assert_eq!(stencil.points[1].weight, 0.0);
+        assert_eq!(stencil.intermediate, vec![data[[2, 4]], data[[3, 4]]]);
+
+        assert!(trace("bilinear", data.view(), &[1.0]).is_err());
+    }
+}
@@ -0,0 +1,1158 @@
+//! Kerchunk reference files.
+//!
+//! A kerchunk reference file is a JSON document describing a Zarr v2 store
+//! whose chunks are byte ranges of other files, typically NetCDF-4/HDF5
+//! objects in cloud storage. `KerchunkSource` reads the store's metadata from
+//! the reference file and fetches only the referenced byte ranges, with HTTP
+//! range requests for remote objects, so a cloud-hosted archive can be served
+//! without downloading whole files.
+//!
+//! Chunk URLs may be local paths (relative paths resolve against the
+//! directory of the reference file), `file://`, `http(s)://`, or `s3://` and
+//! `gs://` URLs, which are read anonymously through the providers' public
+//! HTTPS endpoints. Chunks may be uncompressed or zlib/gzip compressed, with
+//! an optional shuffle filter, which covers references generated from
+//! NetCDF-4 and HDF5 files. Variables in Zarr groups are namespaced by their
+//! group path, as for NetCDF-4 groups.
+
+use base64::Engine;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use ndarray::{ArrayD, IxDyn, Slice};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::attribute_text::{decode_text, raw_bytes_name, sanitize_text, DecodedText};
+use crate::data_loader::{DataSource, LoadResult};
+use crate::error::{Result, RossbyError};
+use crate::memory_budget::{apply_budget, MemoryBudget};
+use crate::state::{AttributeValue, Dimension, Group, Metadata, Variable};
+
+/// Number of chunks fetched concurrently
+const CONCURRENT_FETCHES: usize = 16;
+
+/// Name of the Zarr attribute listing the dimensions of an array
+const ARRAY_DIMENSIONS: &str = "_ARRAY_DIMENSIONS";
+
+/// Kerchunk JSON reference files
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KerchunkSource;
+
+impl DataSource for KerchunkSource {
+    fn format_name(&self) -> &'static str {
+        "Kerchunk"
+    }
+
+    fn load(&self, path: &Path, budget: Option<&MemoryBudget>) -> LoadResult {
+        let text = std::fs::read_to_string(path)?;
+        let references = ReferenceSet::parse(&text)?;
+        let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        run_blocking(|| load_store(references, base_dir, budget))
+    }
+}
+
+/// Target of one key of a reference file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    /// Bytes stored in the reference file itself
+    Inline(Vec<u8>),
+    /// A whole file, or `length` bytes from `offset`
+    Range {
+        url: String,
+        range: Option<(u64, u64)>,
+    },
+}
+
+/// The keys of a reference file and what they point to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReferenceSet {
+    refs: BTreeMap<String, Reference>,
+}
+
+impl ReferenceSet {
+    /// Parse a version 0 or version 1 reference file
+    ///
+    /// Version 1 URL templates (`{{name}}`) are expanded. Generated `gen`
+    /// references are not supported.
+    pub fn parse(text: &str) -> Result<Self> {
+        let document: serde_json::Value = serde_json::from_str(text)?;
+        let (refs, templates) = match document.get("refs") {
+            Some(refs) => (refs, document.get("templates")),
+            None => (&document, None),
+        };
+        let refs = refs
+            .as_object()
+            .ok_or_else(|| reference_error("'refs' is not an object"))?;
+        if document
+            .get("gen")
+            .is_some_and(|gen| gen.as_array().is_some_and(|g| !g.is_empty()))
+        {
+            return Err(reference_error(
+                "Generated ('gen') references are not supported",
+            ));
+        }
+
+        let templates: HashMap<String, String> = templates
+            .and_then(|t| t.as_object())
+            .map(|t| {
+                t.iter()
+                    .filter_map(|(name, url)| Some((name.clone(), url.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let expand = |url: &str| {
+            templates
+                .iter()
+                .fold(url.to_string(), |url, (name, value)| {
+                    url.replace(&format!("{{{{{}}}}}", name), value)
+                })
+        };
+
+        let mut parsed = BTreeMap::new();
+        for (key, value) in refs {
+            let reference = match value {
+                serde_json::Value::String(text) => match text.strip_prefix("base64:") {
+                    Some(encoded) => Reference::Inline(
+                        base64::engine::general_purpose::STANDARD
+                            .decode(encoded)
+                            .map_err(|e| reference_error(format!("Key {}: {}", key, e)))?,
+                    ),
+                    None => Reference::Inline(text.as_bytes().to_vec()),
+                },
+                serde_json::Value::Array(parts) => {
+                    let url = parts
+                        .first()
+                        .and_then(|url| url.as_str())
+                        .ok_or_else(|| reference_error(format!("Key {} has no URL", key)))?;
+                    let range = match (parts.get(1), parts.get(2)) {
+                        (Some(offset), Some(length)) => Some((
+                            offset.as_u64().ok_or_else(|| {
+                                reference_error(format!("Key {} has an invalid offset", key))
+                            })?,
+                            length.as_u64().ok_or_else(|| {
+                                reference_error(format!("Key {} has an invalid length", key))
+                            })?,
+                        )),
+                        _ => None,
+                    };
+                    Reference::Range {
+                        url: expand(url),
+                        range,
+                    }
+                }
+                // Inline JSON metadata stored as an object rather than text
+                other => Reference::Inline(other.to_string().into_bytes()),
+            };
+            parsed.insert(key.clone(), reference);
+        }
+
+        Ok(Self { refs: parsed })
+    }
+
+    /// Reference of a key, if present
+    pub fn get(&self, key: &str) -> Option<&Reference> {
+        self.refs.get(key)
+    }
+
+    /// Paths of the arrays in the store (keys with a `.zarray`)
+    pub fn arrays(&self) -> Vec<String> {
+        self.refs
+            .keys()
+            .filter_map(|key| key.strip_suffix(".zarray"))
+            .map(|prefix| prefix.trim_end_matches('/').to_string())
+            .collect()
+    }
+
+    /// Paths of the groups below the root (keys with a `.zgroup`)
+    pub fn groups(&self) -> Vec<String> {
+        self.refs
+            .keys()
+            .filter_map(|key| key.strip_suffix("/.zgroup"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Parse the inline JSON metadata stored under a key
+    fn json(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        match self.refs.get(key) {
+            Some(Reference::Inline(bytes)) => match std::str::from_utf8(bytes) {
+                Ok(text) => Ok(Some(serde_json::from_str(&null_non_finite(text))?)),
+                Err(_) => {
+                    // Read the document byte for byte, so that the strings of
+                    // attributes written in another encoding (typically
+                    // Latin-1) can be decoded individually
+                    let text: String = bytes.iter().map(|&b| b as char).collect();
+                    let mut value = serde_json::from_str(&null_non_finite(&text))?;
+                    decode_byte_strings(&mut value);
+                    Ok(Some(value))
+                }
+            },
+            Some(Reference::Range { .. }) => Err(reference_error(format!(
+                "Metadata key {} must be stored inline",
+                key
+            ))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Numeric type of the elements of an array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DataType {
+    /// `f`, `i` or `u`
+    kind: char,
+    /// Size in bytes
+    size: usize,
+    big_endian: bool,
+}
+
+impl DataType {
+    /// Parse a NumPy type string such as `<f4` or `|u1`
+    fn parse(dtype: &str) -> Result<Self> {
+        let unsupported = || reference_error(format!("Unsupported data type: {}", dtype));
+        let mut chars = dtype.chars();
+        let big_endian = match chars.next() {
+            Some('>') => true,
+            Some('<') | Some('|') | Some('=') => false,
+            _ => return Err(unsupported()),
+        };
+        let kind = chars.next().ok_or_else(unsupported)?;
+        let size: usize = chars.as_str().parse().map_err(|_| unsupported())?;
+        let supported = match kind {
+            'f' => matches!(size, 4 | 8),
+            'i' | 'u' => matches!(size, 1 | 2 | 4 | 8),
+            _ => false,
+        };
+        if !supported {
+            return Err(unsupported());
+        }
+        Ok(Self {
+            kind,
+            size,
+            big_endian,
+        })
+    }
+
+    /// Name of the type as reported for NetCDF variables
+    fn name(&self) -> &'static str {
+        match (self.kind, self.size) {
+            ('f', 4) => "Float",
+            ('f', _) => "Double",
+            ('i', 1) => "Byte",
+            ('i', 2) => "Short",
+            ('i', 4) => "Int",
+            ('i', _) => "Int64",
+            (_, 1) => "UByte",
+            (_, 2) => "UShort",
+            (_, 4) => "UInt",
+            _ => "UInt64",
+        }
+    }
+
+    /// Decode raw bytes into values
+    fn decode(&self, bytes: &[u8]) -> Vec<f64> {
+        bytes
+            .chunks_exact(self.size)
+            .map(|raw| {
+                let mut buf = [0u8; 8];
+                buf[..self.size].copy_from_slice(raw);
+                if self.big_endian {
+                    buf[..self.size].reverse();
+                }
+                // `buf` now holds the value little-endian, in its low bytes
+                match (self.kind, self.size) {
+                    ('f', 4) => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    ('f', _) => f64::from_le_bytes(buf),
+                    ('i', size) => {
+                        // Sign-extend from the value's width
+                        let shift = 64 - 8 * size as u32;
+                        ((i64::from_le_bytes(buf) << shift) >> shift) as f64
+                    }
+                    _ => u64::from_le_bytes(buf) as f64,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Compression and filter codecs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    Zlib,
+    Gzip,
+    Shuffle(usize),
+}
+
+impl Codec {
+    /// Parse a numcodecs codec configuration
+    fn parse(config: &serde_json::Value) -> Result<Self> {
+        let id = config.get("id").and_then(|id| id.as_str()).unwrap_or("");
+        match id {
+            "zlib" => Ok(Codec::Zlib),
+            "gzip" => Ok(Codec::Gzip),
+            "shuffle" => Ok(Codec::Shuffle(
+                config
+                    .get("elementsize")
+                    .and_then(|size| size.as_u64())
+                    .unwrap_or(4) as usize,
+            )),
+            other => Err(reference_error(format!("Unsupported codec: {}", other))),
+        }
+    }
+
+    /// Undo the codec
+    fn decode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            Codec::Zlib => {
+                flate2::read::ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut out)?;
+            }
+            Codec::Gzip => {
+                flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut out)?;
+            }
+            Codec::Shuffle(size) if *size > 1 && bytes.len().is_multiple_of(*size) => {
+                // Byte k of element i is stored at k * count + i
+                let count = bytes.len() / size;
+                out = vec![0; bytes.len()];
+                for (j, &byte) in bytes.iter().enumerate() {
+                    let (k, i) = (j / count, j % count);
+                    out[i * size + k] = byte;
+                }
+            }
+            Codec::Shuffle(_) => out = bytes,
+        }
+        Ok(out)
+    }
+}
+
+/// The `.zarray` metadata of an array
+#[derive(Debug, Clone, PartialEq)]
+struct ArrayMeta {
+    shape: Vec<usize>,
+    chunks: Vec<usize>,
+    dtype: DataType,
+    compressor: Option<Codec>,
+    filters: Vec<Codec>,
+    fill_value: Option<f64>,
+    separator: String,
+}
+
+impl ArrayMeta {
+    fn parse(path: &str, zarray: &serde_json::Value) -> Result<Self> {
+        let invalid = |field: &str| reference_error(format!("{}/.zarray: invalid {}", path, field));
+        let sizes = |field: &str| -> Result<Vec<usize>> {
+            zarray
+                .get(field)
+                .and_then(|v| v.as_array())
+                .and_then(|v| v.iter().map(|n| n.as_u64().map(|n| n as usize)).collect())
+                .ok_or_else(|| invalid(field))
+        };
+
+        let shape = sizes("shape")?;
+        let chunks = sizes("chunks")?;
+        if chunks.len() != shape.len() || chunks.contains(&0) {
+            return Err(invalid("chunks"));
+        }
+        if zarray.get("order").and_then(|o| o.as_str()) == Some("F") {
+            return Err(reference_error(format!(
+                "{}: Fortran-ordered arrays are not supported",
+                path
+            )));
+        }
+
+        let dtype = DataType::parse(
+            zarray
+                .get("dtype")
+                .and_then(|d| d.as_str())
+                .ok_or_else(|| invalid("dtype"))?,
+        )?;
+        let compressor = match zarray.get("compressor") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(config) => Some(Codec::parse(config)?),
+        };
+        let filters = match zarray.get("filters").and_then(|f| f.as_array()) {
+            Some(filters) => filters.iter().map(Codec::parse).collect::<Result<_>>()?,
+            None => Vec::new(),
+        };
+        let fill_value = match zarray.get("fill_value") {
+            Some(serde_json::Value::Number(n)) => n.as_f64(),
+            Some(serde_json::Value::String(s)) => match s.as_str() {
+                "NaN" => Some(f64::NAN),
+                "Infinity" => Some(f64::INFINITY),
+                "-Infinity" => Some(f64::NEG_INFINITY),
+                _ => None,
+            },
+            _ => None,
+        };
+        let separator = zarray
+            .get("dimension_separator")
+            .and_then(|s| s.as_str())
+            .unwrap_or(".")
+            .to_string();
+
+        Ok(Self {
+            shape,
+            chunks,
+            dtype,
+            compressor,
+            filters,
+            fill_value,
+            separator,
+        })
+    }
+
+    /// Number of chunks along each axis
+    fn grid(&self) -> Vec<usize> {
+        self.shape
+            .iter()
+            .zip(&self.chunks)
+            .map(|(&size, &chunk)| size.div_ceil(chunk))
+            .collect()
+    }
+
+    /// Decode the stored bytes of one chunk into its values
+    fn decode_chunk(&self, mut bytes: Vec<u8>) -> Result<Vec<f64>> {
+        if let Some(compressor) = &self.compressor {
+            bytes = compressor.decode(bytes)?;
+        }
+        for filter in self.filters.iter().rev() {
+            bytes = filter.decode(bytes)?;
+        }
+        let expected = self.chunks.iter().product::<usize>();
+        let values = self.dtype.decode(&bytes);
+        if values.len() != expected {
+            return Err(reference_error(format!(
+                "Chunk has {} values, expected {}",
+                values.len(),
+                expected
+            )));
+        }
+        Ok(values)
+    }
+
+    /// Assemble decoded chunks, keyed by chunk grid index, into the full array
+    ///
+    /// Chunks missing from the store hold the fill value.
+    fn assemble(&self, mut chunks: HashMap<Vec<usize>, Vec<f64>>) -> Result<ArrayD<f64>> {
+        let fill = self.fill_value.unwrap_or(f64::NAN);
+        let mut array = ArrayD::from_elem(IxDyn(&self.shape), fill);
+
+        for index in grid_indices(&self.grid()) {
+            let Some(values) = chunks.remove(&index) else {
+                continue;
+            };
+            let chunk = ArrayD::from_shape_vec(IxDyn(&self.chunks), values).map_err(|e| {
+                reference_error(format!("Chunk {:?} has the wrong shape: {}", index, e))
+            })?;
+            let start: Vec<usize> = index.iter().zip(&self.chunks).map(|(i, c)| i * c).collect();
+            let len: Vec<usize> = start
+                .iter()
+                .zip(&self.chunks)
+                .zip(&self.shape)
+                .map(|((&s, &c), &size)| c.min(size - s))
+                .collect();
+
+            array
+                .slice_each_axis_mut(|ax| {
+                    let axis = ax.axis.index();
+                    Slice::from(start[axis]..start[axis] + len[axis])
+                })
+                .assign(&chunk.slice_each_axis(|ax| Slice::from(0..len[ax.axis.index()])));
+        }
+
+        Ok(array)
+    }
+}
+
+/// All indices of a chunk grid in row-major order
+fn grid_indices(grid: &[usize]) -> Vec<Vec<usize>> {
+    grid.iter().fold(vec![Vec::new()], |indices, &count| {
+        indices
+            .into_iter()
+            .flat_map(|prefix| {
+                (0..count).map(move |i| {
+                    let mut index = prefix.clone();
+                    index.push(i);
+                    index
+                })
+            })
+            .collect()
+    })
+}
+
+/// Decode the strings of a JSON document read one char per byte
+///
+/// Strings that are valid UTF-8 are restored exactly; the others are decoded
+/// lossily, with their raw bytes kept in a `<name>_raw_base64` sibling when
+/// they are object members.
+fn decode_byte_strings(value: &mut serde_json::Value) {
+    use serde_json::Value;
+
+    fn decode(text: &str) -> Option<DecodedText> {
+        let bytes: Option<Vec<u8>> = text.chars().map(|c| u8::try_from(c).ok()).collect();
+        bytes.map(|bytes| decode_text(&bytes))
+    }
+
+    match value {
+        Value::Object(members) => {
+            let mut raw_members = Vec::new();
+            for (name, member) in members.iter_mut() {
+                match member {
+                    Value::String(text) => {
+                        if let Some(decoded) = decode(text) {
+                            *text = decoded.text;
+                            if let Some(raw) = decoded.raw {
+                                raw_members.push((
+                                    raw_bytes_name(name),
+                                    base64::engine::general_purpose::STANDARD.encode(raw),
+                                ));
+                            }
+                        }
+                    }
+                    other => decode_byte_strings(other),
+                }
+            }
+            for (name, raw) in raw_members {
+                members.insert(name, Value::String(raw));
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(decode_byte_strings),
+        Value::String(text) => {
+            if let Some(decoded) = decode(text) {
+                *text = decoded.text;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace the non-standard `NaN`/`Infinity` tokens some writers emit with `null`
+fn null_non_finite(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if let Some(token) = ["-Infinity", "Infinity", "NaN"]
+            .iter()
+            .find(|token| rest.starts_with(*token))
+        {
+            out.push_str("null");
+            rest = &rest[token.len()..];
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Convert a JSON attribute value, skipping nulls and nested objects
+fn convert_attribute(value: &serde_json::Value) -> Option<AttributeValue> {
+    use serde_json::Value;
+    match value {
+        Value::String(text) => Some(AttributeValue::Text(sanitize_text(text))),
+        Value::Bool(b) => Some(AttributeValue::Number(*b as u8 as f64)),
+        Value::Number(n) => Some(match (n.as_i64(), n.as_u64()) {
+            // Keep integers that f64 cannot represent exactly
+            (Some(i), _) if i.unsigned_abs() > 1 << 53 => AttributeValue::Integer(i),
+            (None, Some(u)) => AttributeValue::UnsignedInteger(u),
+            _ => AttributeValue::Number(n.as_f64()?),
+        }),
+        Value::Array(items) if items.iter().all(Value::is_string) => {
+            Some(AttributeValue::TextArray(
+                items
+                    .iter()
+                    .filter_map(|v| v.as_str().map(sanitize_text))
+                    .collect(),
+            ))
+        }
+        Value::Array(items) if items.iter().all(Value::is_number) => Some(
+            AttributeValue::NumberArray(items.iter().filter_map(Value::as_f64).collect()),
+        ),
+        _ => None,
+    }
+}
+
+/// Convert the attributes of a `.zattrs` document
+fn convert_attributes(zattrs: Option<&serde_json::Value>) -> HashMap<String, AttributeValue> {
+    zattrs
+        .and_then(|attrs| attrs.as_object())
+        .map(|attrs| {
+            attrs
+                .iter()
+                .filter(|(name, _)| name.as_str() != ARRAY_DIMENSIONS)
+                .filter_map(|(name, value)| Some((name.clone(), convert_attribute(value)?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Split a store path into its group path and member name
+fn split_path(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+/// Join a group path and a member name
+fn join_path(group: &str, name: &str) -> String {
+    if group.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", group, name)
+    }
+}
+
+/// Resolve a dimension name used by an array in `group`
+///
+/// As in NetCDF-4, a dimension is defined by the nearest enclosing group
+/// holding an array of that name; otherwise it belongs to `group` itself.
+fn resolve_dimension(arrays: &BTreeSet<String>, group: &str, name: &str) -> String {
+    let mut scope = Some(group);
+    while let Some(path) = scope {
+        let candidate = join_path(path, name);
+        if arrays.contains(&candidate) {
+            return candidate;
+        }
+        scope = (!path.is_empty()).then(|| split_path(path).0);
+    }
+    join_path(group, name)
+}
+
+/// Build the metadata and read the data of a reference set
+///
+/// The metadata of every array is read first, so that the memory budget can
+/// be checked before any chunk is fetched.
+async fn load_store(
+    references: ReferenceSet,
+    base_dir: PathBuf,
+    budget: Option<&MemoryBudget>,
+) -> LoadResult {
+    let store = ChunkStore::new(base_dir);
+    let paths: BTreeSet<String> = references.arrays().into_iter().collect();
+
+    let mut dimensions: HashMap<String, Dimension> = HashMap::new();
+    let mut variables = HashMap::new();
+    let mut metas = BTreeMap::new();
+
+    for path in &paths {
+        let zarray = references
+            .json(&format!("{}/.zarray", path))?
+            .ok_or_else(|| reference_error(format!("{}/.zarray is missing", path)))?;
+        let meta = ArrayMeta::parse(path, &zarray)?;
+        if meta.shape.is_empty() {
+            warn!("Skipping scalar array: {}", path);
+            continue;
+        }
+
+        let zattrs = references.json(&format!("{}/.zattrs", path))?;
+        let (group, name) = split_path(path);
+        let dim_names: Vec<String> = match zattrs
+            .as_ref()
+            .and_then(|attrs| attrs.get(ARRAY_DIMENSIONS))
+            .and_then(|dims| dims.as_array())
+        {
+            Some(dims) => dims
+                .iter()
+                .map(|d| d.as_str().map(|d| resolve_dimension(&paths, group, d)))
+                .collect::<Option<_>>()
+                .ok_or_else(|| {
+                    reference_error(format!("{}: invalid {}", path, ARRAY_DIMENSIONS))
+                })?,
+            None => (0..meta.shape.len())
+                .map(|i| join_path(group, &format!("{}_dim_{}", name, i)))
+                .collect(),
+        };
+        if dim_names.len() != meta.shape.len() {
+            return Err(reference_error(format!(
+                "{}: {} dimensions for a {}-dimensional array",
+                path,
+                dim_names.len(),
+                meta.shape.len()
+            )));
+        }
+        for (dim_name, &size) in dim_names.iter().zip(&meta.shape) {
+            let dimension = dimensions.entry(dim_name.clone()).or_insert(Dimension {
+                name: dim_name.clone(),
+                size,
+                is_unlimited: false,
+            });
+            if dimension.size != size {
+                return Err(reference_error(format!(
+                    "Dimension {} has size {} in {} but {} elsewhere",
+                    dim_name, size, path, dimension.size
+                )));
+            }
+        }
+
+        let mut attributes = convert_attributes(zattrs.as_ref());
+        if let (Some(fill), false) = (meta.fill_value, attributes.contains_key("_FillValue")) {
+            if fill.is_finite() {
+                attributes.insert("_FillValue".to_string(), AttributeValue::Number(fill));
+            }
+        }
+        variables.insert(
+            path.clone(),
+            Variable {
+                name: path.clone(),
+                dimensions: dim_names,
+                shape: meta.shape.clone(),
+                attributes,
+                dtype: meta.dtype.name().to_string(),
+            },
+        );
+        metas.insert(path.clone(), meta);
+    }
+
+    let mut groups = HashMap::new();
+    for path in references.groups() {
+        let attributes =
+            convert_attributes(references.json(&format!("{}/.zattrs", path))?.as_ref());
+        let members = |names: Vec<&String>| -> Vec<String> {
+            names
+                .into_iter()
+                .filter(|name| split_path(name).0 == path)
+                .cloned()
+                .collect()
+        };
+        let group_paths = references.groups();
+        groups.insert(
+            path.clone(),
+            Group {
+                path: path.clone(),
+                attributes,
+                dimensions: members(dimensions.keys().collect()),
+                variables: members(variables.keys().collect()),
+                groups: members(group_paths.iter().collect()),
+            },
+        );
+    }
+
+    let mut metadata = Metadata {
+        global_attributes: convert_attributes(references.json(".zattrs")?.as_ref()),
+        dimensions,
+        variables,
+        coordinates: HashMap::new(),
+        groups,
+    };
+    apply_budget(budget, &mut metadata)?;
+
+    let mut data = HashMap::new();
+    for (path, meta) in &metas {
+        let Some(var) = metadata.variables.get(path) else {
+            continue;
+        };
+        let values = read_array(&references, &store, path, meta).await?;
+        if var.dimensions.len() == 1 && var.dimensions[0] == *path {
+            let coordinates = values.iter().copied().collect();
+            metadata.coordinates.insert(path.clone(), coordinates);
+        }
+        data.insert(path.clone(), values.mapv(|v| v as f32));
+        debug!(variable = %path, shape = ?meta.shape, "Loaded referenced array");
+    }
+
+    for (name, dimension) in &metadata.dimensions {
+        if !metadata.coordinates.contains_key(name) {
+            metadata.coordinates.insert(
+                name.clone(),
+                (0..dimension.size).map(|i| i as f64).collect(),
+            );
+            warn!("Created default coordinates for dimension: {}", name);
+        }
+    }
+    Ok((metadata, data))
+}
+
+/// Fetch, decode and assemble the chunks of one array
+async fn read_array(
+    references: &ReferenceSet,
+    store: &ChunkStore,
+    path: &str,
+    meta: &ArrayMeta,
+) -> Result<ArrayD<f64>> {
+    let present: Vec<(Vec<usize>, &Reference)> = grid_indices(&meta.grid())
+        .into_iter()
+        .filter_map(|index| {
+            let suffix: Vec<String> = index.iter().map(usize::to_string).collect();
+            let key = format!("{}/{}", path, suffix.join(&meta.separator));
+            references.get(&key).map(|reference| (index, reference))
+        })
+        .collect();
+
+    let chunks: HashMap<Vec<usize>, Vec<f64>> = stream::iter(present)
+        .map(|(index, reference)| async move {
+            let bytes = store.read(reference).await?;
+            Ok::<_, RossbyError>((index, meta.decode_chunk(bytes)?))
+        })
+        .buffer_unordered(CONCURRENT_FETCHES)
+        .try_collect()
+        .await?;
+
+    meta.assemble(chunks)
+}
+
+/// Reads referenced byte ranges from local files and object stores
+struct ChunkStore {
+    base_dir: PathBuf,
+    client: reqwest::Client,
+}
+
+impl ChunkStore {
+    fn new(base_dir: PathBuf) -> Self {
+        Self {
+            base_dir,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Read the bytes a reference points to
+    async fn read(&self, reference: &Reference) -> Result<Vec<u8>> {
+        let (url, range) = match reference {
+            Reference::Inline(bytes) => return Ok(bytes.clone()),
+            Reference::Range { url, range } => (url.as_str(), *range),
+        };
+
+        match http_url(url) {
+            Some(http) => self.read_http(&http, range).await,
+            None => self.read_file(url, range),
+        }
+    }
+
+    fn read_file(&self, url: &str, range: Option<(u64, u64)>) -> Result<Vec<u8>> {
+        let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+        let path = self.base_dir.join(path);
+        let mut file = std::fs::File::open(&path)
+            .map_err(|e| reference_error(format!("Cannot open {}: {}", path.display(), e)))?;
+        let mut bytes = Vec::new();
+        match range {
+            Some((offset, length)) => {
+                file.seek(SeekFrom::Start(offset))?;
+                bytes.resize(length as usize, 0);
+                file.read_exact(&mut bytes)?;
+            }
+            None => {
+                file.read_to_end(&mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    async fn read_http(&self, url: &str, range: Option<(u64, u64)>) -> Result<Vec<u8>> {
+        let mut request = self.client.get(url);
+        if let Some((offset, length)) = range {
+            request = request.header(
+                reqwest::header::RANGE,
+                format!("bytes={}-{}", offset, offset + length.max(1) - 1),
+            );
+        }
+        let fetch_error = |e: reqwest::Error| reference_error(format!("Fetching {}: {}", url, e));
+        let response = request.send().await.map_err(fetch_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(reference_error(format!(
+                "Fetching {}: HTTP {}",
+                url, status
+            )));
+        }
+        let bytes = response.bytes().await.map_err(fetch_error)?;
+
+        // A server ignoring the range returns the whole object
+        match range {
+            Some((offset, length)) if status != reqwest::StatusCode::PARTIAL_CONTENT => {
+                let start = offset as usize;
+                let end = start + length as usize;
+                bytes
+                    .get(start..end)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| reference_error(format!("{} is shorter than referenced", url)))
+            }
+            _ => Ok(bytes.to_vec()),
+        }
+    }
+}
+
+/// HTTP(S) URL of a referenced object, or `None` for local files
+///
+/// `s3://` and `gs://` URLs map to the public endpoints of their buckets.
+pub fn http_url(url: &str) -> Option<String> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Some(url.to_string());
+    }
+    if let Some(rest) = url.strip_prefix("s3://") {
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        return Some(format!("https://{}.s3.amazonaws.com/{}", bucket, key));
+    }
+    if let Some(rest) = url.strip_prefix("gs://") {
+        return Some(format!("https://storage.googleapis.com/{}", rest));
+    }
+    None
+}
+
+/// Run a future to completion from synchronous loading code
+///
+/// The loader runs before (or outside) the server's runtime, so the future
+/// gets a runtime of its own on a scoped thread, where it is also created.
+fn run_blocking<T: Send, F: Future<Output = Result<T>>>(
+    future: impl FnOnce() -> F + Send,
+) -> Result<T> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?
+                    .block_on(future())
+            })
+            .join()
+            .unwrap_or_else(|_| Err(reference_error("Reference loading thread panicked")))
+    })
+}
+
+fn reference_error(message: impl Into<String>) -> RossbyError {
+    RossbyError::Reference {
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_data_types() {
+        let le_f4 = DataType::parse("<f4").unwrap();
+        assert_eq!(le_f4.decode(&1.5f32.to_le_bytes()), vec![1.5]);
+        let be_i2 = DataType::parse(">i2").unwrap();
+        assert_eq!(be_i2.decode(&(-300i16).to_be_bytes()), vec![-300.0]);
+        let u1 = DataType::parse("|u1").unwrap();
+        assert_eq!(u1.decode(&[255, 1]), vec![255.0, 1.0]);
+        let le_i8 = DataType::parse("<i8").unwrap();
+        assert_eq!(le_i8.decode(&(-2i64).to_le_bytes()), vec![-2.0]);
+
+        for invalid in ["<c8", "<f2", "S10", ""] {
+            assert!(DataType::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let values: Vec<u8> = [1u16, 2, 3].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let shuffled = vec![1, 2, 3, 0, 0, 0];
+        assert_eq!(Codec::Shuffle(2).decode(shuffled).unwrap(), values);
+    }
+
+    #[test]
+    fn test_null_non_finite() {
+        assert_eq!(
+            null_non_finite(r#"{"a": NaN, "b": [-Infinity, 1], "c": "NaN \" Infinity"}"#),
+            r#"{"a": null, "b": [null, 1], "c": "NaN \" Infinity"}"#
+        );
+    }
+
+    #[test]
+    fn test_http_url() {
+        assert_eq!(
+            http_url("s3://era5-pds/2020/t2m.nc").as_deref(),
+            Some("https://era5-pds.s3.amazonaws.com/2020/t2m.nc")
+        );
+        assert_eq!(
+            http_url("gs://bucket/a.nc").as_deref(),
+            Some("https://storage.googleapis.com/bucket/a.nc")
+        );
+        assert_eq!(http_url("data/a.nc"), None);
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let references = ReferenceSet::parse(
+            r#"{
+                "version": 1,
+                "templates": {"u": "s3://bucket/file.nc"},
+                "refs": {
+                    ".zgroup": "{\"zarr_format\": 2}",
+                    "t/.zarray": {"shape": [2]},
+                    "t/0": ["{{u}}", 100, 8],
+                    "t/1": "base64:AAAAAA=="
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(references.arrays(), vec!["t"]);
+        assert_eq!(
+            references.get("t/0"),
+            Some(&Reference::Range {
+                url: "s3://bucket/file.nc".to_string(),
+                range: Some((100, 8)),
+            })
+        );
+        assert_eq!(references.get("t/1"), Some(&Reference::Inline(vec![0; 4])));
+
+        // Version 0 files are the bare references
+        let v0 = ReferenceSet::parse(r#"{"t/.zarray": "{}"}"#).unwrap();
+        assert_eq!(v0.arrays(), vec!["t"]);
+    }
+
+    #[test]
+    fn test_latin1_attributes() {
+        // A Latin-1 history next to a UTF-8 title, as in converted old files
+        let mut zattrs = b"{\"history\": \"Cr\xe9\xe9 par M\xe9t\xe9o-France\", ".to_vec();
+        zattrs.extend_from_slice("\"title\": \"Zürich\", \"flags\": [\"a\"]}".as_bytes());
+        let references = ReferenceSet::parse(
+            &serde_json::json!({
+                "version": 1,
+                "refs": {".zattrs": format!(
+                    "base64:{}",
+                    base64::engine::general_purpose::STANDARD.encode(&zattrs)
+                )}
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let attributes = convert_attributes(references.json(".zattrs").unwrap().as_ref());
+        assert!(matches!(
+            attributes.get("history"),
+            Some(AttributeValue::Text(history))
+                if history == "Cr\u{fffd}\u{fffd} par M\u{fffd}t\u{fffd}o-France"
+        ));
+        assert!(matches!(
+            attributes.get("title"),
+            Some(AttributeValue::Text(title)) if title == "Zürich"
+        ));
+        let Some(AttributeValue::Text(raw)) = attributes.get("history_raw_base64") else {
+            panic!("Missing raw bytes of the history attribute");
+        };
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD
+                .decode(raw)
+                .unwrap(),
+            b"Cr\xe9\xe9 par M\xe9t\xe9o-France"
+        );
+        assert!(!attributes.contains_key("title_raw_base64"));
+    }
+
+    #[test]
+    fn test_load_references() -> Result<()> {
+        let dir = tempdir().unwrap();
+
+        // A 3x4 temperature field in 2x2 chunks, zlib compressed after a
+        // shuffle, stored back to back in one "remote" object. The last
+        // chunk is missing and reads as the fill value.
+        let values: Vec<f32> = (0..12).map(|v| 280.0 + v as f32).collect();
+        let field = ArrayD::from_shape_vec(IxDyn(&[3, 4]), values).unwrap();
+        let mut object = Vec::new();
+        let mut refs = serde_json::Map::new();
+        for index in [[0, 0], [0, 1], [1, 0]] {
+            let mut chunk = ArrayD::from_elem(IxDyn(&[2, 2]), -999.0f32);
+            for i in 0..2 {
+                for j in 0..2 {
+                    let (r, c) = (index[0] * 2 + i, index[1] * 2 + j);
+                    if r < 3 {
+                        chunk[[i, j]] = field[[r, c]];
+                    }
+                }
+            }
+            let raw: Vec<u8> = chunk.iter().flat_map(|v| v.to_le_bytes()).collect();
+            let count = raw.len() / 4;
+            let mut shuffled = vec![0; raw.len()];
+            for (n, &byte) in raw.iter().enumerate() {
+                shuffled[(n % 4) * count + n / 4] = byte;
+            }
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&shuffled).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            refs.insert(
+                format!("t2m/{}.{}", index[0], index[1]),
+                serde_json::json!(["archive.bin", object.len(), compressed.len()]),
+            );
+            object.extend(compressed);
+        }
+        std::fs::write(dir.path().join("archive.bin"), &object)?;
+
+        let coordinate = |values: &[f64]| {
+            let raw: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+            format!(
+                "base64:{}",
+                base64::engine::general_purpose::STANDARD.encode(raw)
+            )
+        };
+        refs.insert(".zgroup".into(), r#"{"zarr_format": 2}"#.into());
+        refs.insert(".zattrs".into(), r#"{"title": "Kerchunk test"}"#.into());
+        refs.insert(
+            "t2m/.zarray".into(),
+            serde_json::json!({
+                "shape": [3, 4], "chunks": [2, 2], "dtype": "<f4", "order": "C",
+                "compressor": {"id": "zlib", "level": 4},
+                "filters": [{"id": "shuffle", "elementsize": 4}],
+                "fill_value": -999.0, "zarr_format": 2
+            })
+            .to_string()
+            .into(),
+        );
+        refs.insert(
+            "t2m/.zattrs".into(),
+            r#"{"_ARRAY_DIMENSIONS": ["lat", "lon"], "units": "K", "_FillValue": NaN}"#.into(),
+        );
+        for (name, values) in [
+            ("lat", vec![10.0, 20.0, 30.0]),
+            ("lon", vec![0.0, 1.0, 2.0, 3.0]),
+        ] {
+            refs.insert(
+                format!("{}/.zarray", name),
+                serde_json::json!({
+                    "shape": [values.len()], "chunks": [values.len()], "dtype": "<f8",
+                    "compressor": null, "fill_value": null, "zarr_format": 2
+                })
+                .to_string()
+                .into(),
+            );
+            refs.insert(
+                format!("{}/.zattrs", name),
+                serde_json::json!({"_ARRAY_DIMENSIONS": [name]})
+                    .to_string()
+                    .into(),
+            );
+            refs.insert(format!("{}/0", name), coordinate(&values).into());
+        }
+        let reference_path = dir.path().join("refs.json");
+        std::fs::write(
+            &reference_path,
+            serde_json::json!({"version": 1, "refs": refs}).to_string(),
+        )?;
+
+        let (metadata, data) = KerchunkSource.load(&reference_path, None)?;
+        assert!(matches!(
+            metadata.global_attributes.get("title"),
+            Some(AttributeValue::Text(title)) if title == "Kerchunk test"
+        ));
+        let t2m = &metadata.variables["t2m"];
+        assert_eq!(t2m.dimensions, vec!["lat", "lon"]);
+        assert!(matches!(
+            t2m.attributes.get("_FillValue"),
+            Some(AttributeValue::Number(fill)) if *fill == -999.0
+        ));
+        assert_eq!(metadata.coordinates["lat"], vec![10.0, 20.0, 30.0]);
+        assert_eq!(metadata.dimensions["lon"].size, 4);
+
+        let values = &data["t2m"];
+        assert_eq!(values.shape(), &[3, 4]);
+        assert_eq!(values[[0, 0]], 280.0);
+        assert_eq!(values[[1, 3]], 287.0);
+        assert_eq!(values[[2, 1]], 289.0);
+        // Missing chunk
+        assert_eq!(values[[2, 3]], -999.0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,731 @@
+//! Variables read from the data file on demand.
+//!
+//! By default every variable is read into memory when the server starts,
+//! which limits the files it can serve to the memory available. With
+//! `data.storage: "lazy"`, only the metadata and the coordinate variables are
+//! read at startup, and the values of the other variables are read from the
+//! file when a request needs them (see [`crate::packed::VariableValues`]).
+//!
+//! Reads of one step of the leading dimension, such as the horizontal slices
+//! of `/image` and tiles or `/point` at one time, go through a cache of
+//! recently read slabs of that dimension, bounded by `data.lazy_cache_bytes`.
+//! Other reads of part of a variable, such as point time series and `/data`
+//! selections, read the smallest hyperslab holding the values they take.
+//! Operations over whole variables (statistics over all time steps,
+//! computed climatologies, views, fingerprints) read the whole variable.
+//!
+//! The file is kept open between reads, and its reads run on a thread where
+//! blocking does not hold up the other requests. The file is reopened after
+//! a read fails, so a file replaced on disk is read again.
+//!
+//! Slabs are checked for corruption: a checksum of every slab is kept from
+//! its first read, and a slab whose values differ when it is read again
+//! (after it left the cache, or by a whole-variable read) is rejected.
+//! NetCDF-4 files written with HDF5's Fletcher-32 filter have their chunks
+//! verified by the library as well, which fails the read of a corrupt chunk.
+//!
+//! A value that cannot be read, e.g. because the file has gone or a slab is
+//! corrupt, is logged and recorded as a [`ReadFailure`] of the request, and
+//! [`read_failure_middleware`] answers the request with `502 Bad Gateway`
+//! naming the region that failed, instead of serving the values as missing.
+//! Caches derived from the values read are flushed, so they do not keep
+//! anything computed from the failed read. The checksums are kept until the
+//! `slabs` cache is flushed with `POST /admin/caches/flush`, which operators
+//! do after correcting the file.
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ndarray::{Array, ArrayView, Axis, IxDyn};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::runtime::RuntimeFlavor;
+use tracing::error;
+
+use crate::error::{Result, RossbyError};
+use crate::handlers::admin::CacheKind;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::query::select_values;
+use crate::schema::SCHEMA_VERSION;
+use crate::state::AppState;
+
+/// Values up to which steps of the leading dimension are read as one slab
+const SLAB_VALUES: usize = 1 << 18;
+
+/// Where the values of variables are held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageMode {
+    /// Every variable is read into memory at startup
+    Memory,
+    /// Variables are read from the file on demand
+    Lazy,
+}
+
+impl StorageMode {
+    /// Parse the `data.storage` setting
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "memory" => Ok(StorageMode::Memory),
+            "lazy" => Ok(StorageMode::Lazy),
+            other => Err(RossbyError::Config {
+                message: format!("Invalid storage: {}. Must be one of: memory, lazy", other),
+            }),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Read failures of the request being handled
+    static READ_FAILURES: RefCell<Vec<ReadFailure>>;
+}
+
+/// Why a region of a variable could not be served
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// The file could not be read
+    ReadError,
+    /// The values differ from those first read
+    ChecksumMismatch,
+}
+
+/// A region of a variable that could not be read
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReadFailure {
+    /// Name of the variable in the file
+    pub variable: String,
+    /// First index of the region along each dimension
+    pub start: Vec<usize>,
+    /// Number of indices of the region along each dimension
+    pub count: Vec<usize>,
+    pub reason: FailureReason,
+    pub message: String,
+}
+
+impl ReadFailure {
+    /// Log the failure and record it for the request being handled, if any
+    fn report(self) {
+        error!(
+            var = %self.variable,
+            start = ?self.start,
+            count = ?self.count,
+            reason = ?self.reason,
+            error = %self.message,
+            "Failed to read variable from file"
+        );
+        let _ = READ_FAILURES.try_with(|failures| failures.borrow_mut().push(self));
+    }
+}
+
+/// Recently read slabs of the lazy variables of a file, the least recently
+/// used evicted first, the checksums of every slab read and the open file
+#[derive(Debug)]
+pub struct SlabCache {
+    /// Bytes the cached values may take
+    capacity_bytes: u64,
+    slabs: Mutex<CachedSlabs>,
+    /// Checksum of each slab from its first read, kept after eviction
+    checksums: Mutex<HashMap<(String, usize), u64>>,
+    /// The file, opened on the first read
+    file: Mutex<Option<Arc<netcdf::File>>>,
+}
+
+#[derive(Debug, Default)]
+struct CachedSlabs {
+    values: HashMap<(String, usize), Arc<Vec<f32>>>,
+    /// Keys from the least to the most recently used
+    order: VecDeque<(String, usize)>,
+    bytes: u64,
+}
+
+impl SlabCache {
+    /// Empty cache holding up to `capacity_bytes` of values
+    pub fn new(capacity_bytes: u64) -> Self {
+        Self {
+            capacity_bytes,
+            slabs: Mutex::new(CachedSlabs::default()),
+            checksums: Mutex::new(HashMap::new()),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// The open file at `path`, opening it if it is not
+    fn file(&self, path: &Path) -> Result<Arc<netcdf::File>> {
+        let mut file = self.file.lock();
+        if let Some(file) = file.as_ref() {
+            return Ok(file.clone());
+        }
+        let opened = Arc::new(netcdf::open(path).map_err(|e| RossbyError::NetCdf {
+            message: format!("Failed to open {}: {}", path.display(), e),
+        })?);
+        *file = Some(opened.clone());
+        Ok(opened)
+    }
+
+    /// Close the file, so that the next read opens it again
+    fn close_file(&self) {
+        self.file.lock().take();
+    }
+
+    /// Drop the cached slabs, their checksums and the open file, returning
+    /// how many slabs were cached
+    pub fn clear(&self) -> usize {
+        let cleared = {
+            let mut slabs = self.slabs.lock();
+            let cleared = slabs.values.len();
+            *slabs = CachedSlabs::default();
+            cleared
+        };
+        self.checksums.lock().clear();
+        self.close_file();
+        cleared
+    }
+
+    /// Check the values of a slab against the checksum of its first read,
+    /// recording the checksum if this is the first
+    fn verify(&self, key: &(String, usize), values: &[f32]) -> bool {
+        let checksum = checksum(values);
+        *self.checksums.lock().entry(key.clone()).or_insert(checksum) == checksum
+    }
+
+    /// Bytes the cached values take
+    pub fn bytes(&self) -> u64 {
+        self.slabs.lock().bytes
+    }
+
+    fn get(&self, key: &(String, usize)) -> Option<Arc<Vec<f32>>> {
+        let mut slabs = self.slabs.lock();
+        let values = slabs.values.get(key).cloned()?;
+        if let Some(position) = slabs.order.iter().position(|k| k == key) {
+            let key = slabs.order.remove(position).expect("position is in bounds");
+            slabs.order.push_back(key);
+        }
+        Some(values)
+    }
+
+    fn insert(&self, key: (String, usize), values: Arc<Vec<f32>>) {
+        let bytes = slab_bytes(&values);
+        if bytes > self.capacity_bytes {
+            return;
+        }
+        let mut slabs = self.slabs.lock();
+        if slabs.values.contains_key(&key) {
+            return;
+        }
+        while slabs.bytes + bytes > self.capacity_bytes {
+            let Some(oldest) = slabs.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = slabs.values.remove(&oldest) {
+                slabs.bytes -= slab_bytes(&evicted);
+            }
+        }
+        slabs.bytes += bytes;
+        slabs.order.push_back(key.clone());
+        slabs.values.insert(key, values);
+    }
+}
+
+fn slab_bytes(values: &[f32]) -> u64 {
+    std::mem::size_of_val(values) as u64
+}
+
+/// Checksum of the bits of values, for comparing reads of the same slab
+fn checksum(values: &[f32]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for value in values {
+        hasher.write_u32(value.to_bits());
+    }
+    hasher.finish()
+}
+
+/// A variable of a file, read on demand
+#[derive(Debug, Clone)]
+pub struct LazyVariable {
+    path: PathBuf,
+    /// Name of the variable in the file
+    name: String,
+    shape: Vec<usize>,
+    /// Steps of the leading dimension per slab
+    steps_per_slab: usize,
+    cache: Arc<SlabCache>,
+}
+
+impl LazyVariable {
+    /// The variable `name` of the file at `path`, of the given shape
+    pub fn new(path: &Path, name: &str, shape: Vec<usize>, cache: Arc<SlabCache>) -> Self {
+        let step_len = shape.iter().skip(1).product::<usize>().max(1);
+        Self {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            shape,
+            steps_per_slab: (SLAB_VALUES / step_len).max(1),
+            cache,
+        }
+    }
+
+    /// Shape of the variable
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Values of the hyperslab starting at `start` with `count` values along
+    /// each axis, in row-major order and converted to `f32` as when loading
+    pub fn read(&self, start: &[usize], count: &[usize]) -> Result<Vec<f32>> {
+        let read = || -> Result<Vec<f32>> {
+            let file = self.cache.file(&self.path)?;
+            let var = file
+                .variable(&self.name)
+                .ok_or_else(|| RossbyError::VariableNotFound {
+                    name: self.name.clone(),
+                })?;
+            let values: Vec<f64> = var.get_values((start, count))?;
+            Ok(values.into_iter().map(|value| value as f32).collect())
+        };
+        let values = blocking(read);
+        if values.is_err() {
+            self.cache.close_file();
+        }
+        values
+    }
+
+    /// The slab cache the variable reads through
+    pub fn cache(&self) -> &Arc<SlabCache> {
+        &self.cache
+    }
+
+    /// [`LazyVariable::read`], with the values missing and the failure
+    /// reported if they cannot be read
+    fn read_or_missing(&self, start: &[usize], count: &[usize]) -> Vec<f32> {
+        self.read(start, count).unwrap_or_else(|e| {
+            self.fail(start, count, FailureReason::ReadError, e.to_string());
+            vec![f32::NAN; count.iter().product()]
+        })
+    }
+
+    /// Report a region that could not be read
+    fn fail(&self, start: &[usize], count: &[usize], reason: FailureReason, message: String) {
+        ReadFailure {
+            variable: self.name.clone(),
+            start: start.to_vec(),
+            count: count.to_vec(),
+            reason,
+            message,
+        }
+        .report();
+    }
+
+    /// The values of a slab read from the file and checked against its
+    /// checksum, or `None` if they cannot be read or are corrupt
+    fn read_slab(&self, slab: usize) -> Option<Vec<f32>> {
+        let (start, count) = self.slab_extent(slab);
+        let values = match self.read(&start, &count) {
+            Ok(values) => values,
+            Err(e) => {
+                self.fail(&start, &count, FailureReason::ReadError, e.to_string());
+                return None;
+            }
+        };
+        if !self.cache.verify(&(self.name.clone(), slab), &values) {
+            self.cache.close_file();
+            self.fail(
+                &start,
+                &count,
+                FailureReason::ChecksumMismatch,
+                "The values differ from those first read from the file".to_string(),
+            );
+            return None;
+        }
+        Some(values)
+    }
+
+    /// Number of steps of the leading dimension
+    fn steps(&self) -> usize {
+        self.shape.first().copied().unwrap_or(1)
+    }
+
+    /// Number of values in one step of the leading dimension
+    fn step_len(&self) -> usize {
+        self.shape.iter().skip(1).product()
+    }
+
+    fn slab_count(&self) -> usize {
+        self.steps().div_ceil(self.steps_per_slab)
+    }
+
+    /// Start and count of a slab
+    fn slab_extent(&self, slab: usize) -> (Vec<usize>, Vec<usize>) {
+        let mut start = vec![0; self.shape.len()];
+        let mut count = self.shape.clone();
+        if let (Some(first), Some(steps)) = (start.first_mut(), count.first_mut()) {
+            *first = slab * self.steps_per_slab;
+            *steps = self.steps_per_slab.min(*steps - *first);
+        }
+        (start, count)
+    }
+
+    /// The values of a slab, from the cache if it has them
+    fn slab(&self, slab: usize) -> Arc<Vec<f32>> {
+        let key = (self.name.clone(), slab);
+        if let Some(values) = self.cache.get(&key) {
+            return values;
+        }
+        match self.read_slab(slab) {
+            Some(values) => {
+                let values = Arc::new(values);
+                self.cache.insert(key, values.clone());
+                values
+            }
+            None => {
+                let (_, count) = self.slab_extent(slab);
+                Arc::new(vec![f32::NAN; count.iter().product()])
+            }
+        }
+    }
+
+    /// The value at an index, if it is within the shape
+    pub fn get(&self, index: &[usize]) -> Option<f32> {
+        if index.len() != self.shape.len() || index.iter().zip(&self.shape).any(|(i, n)| i >= n) {
+            return None;
+        }
+        let Some((&step, rest)) = index.split_first() else {
+            return self.slab(0).first().copied();
+        };
+        let mut offset = (step % self.steps_per_slab) * self.step_len();
+        let mut stride = 1;
+        for (&i, &n) in rest.iter().zip(&self.shape[1..]).rev() {
+            offset += i * stride;
+            stride *= n;
+        }
+        self.slab(step / self.steps_per_slab).get(offset).copied()
+    }
+
+    /// All values in logical order, read a slab at a time without caching
+    pub fn iter(&self) -> impl Iterator<Item = f32> + '_ {
+        (0..self.slab_count()).flat_map(move |slab| {
+            self.read_slab(slab).unwrap_or_else(|| {
+                let (_, count) = self.slab_extent(slab);
+                vec![f32::NAN; count.iter().product()]
+            })
+        })
+    }
+
+    /// All values, read a slab at a time
+    pub fn to_array(&self) -> Array<f32, IxDyn> {
+        Array::from_shape_vec(IxDyn(&self.shape), self.iter().collect())
+            .expect("read values fill the shape")
+    }
+
+    /// The values with the given axes removed at the given indices, sorted
+    /// from the highest axis down
+    ///
+    /// With the leading axis removed, the step is taken from its cached
+    /// slab; otherwise only the remaining values are read.
+    pub fn index_axes(&self, indices: &[(usize, usize)]) -> Array<f32, IxDyn> {
+        if let Some(&(_, step)) = indices.iter().find(|&&(axis, _)| axis == 0) {
+            let slab = self.slab(step / self.steps_per_slab);
+            let mut shape = self.shape.clone();
+            shape[0] = slab.len() / self.step_len().max(1);
+            let view = ArrayView::from_shape(IxDyn(&shape), &slab).expect("slab fills its shape");
+            let mut view = view.index_axis_move(Axis(0), step % self.steps_per_slab);
+            for &(axis, index) in indices.iter().filter(|&&(axis, _)| axis > 0) {
+                view = view.index_axis_move(Axis(axis - 1), index);
+            }
+            return view.to_owned();
+        }
+
+        let mut start = vec![0; self.shape.len()];
+        let mut count = self.shape.clone();
+        for &(axis, index) in indices {
+            start[axis] = index;
+            count[axis] = 1;
+        }
+        let values = self.read_or_missing(&start, &count);
+        let shape: Vec<usize> = count
+            .iter()
+            .enumerate()
+            .filter(|(axis, _)| !indices.iter().any(|&(a, _)| a == *axis))
+            .map(|(_, &n)| n)
+            .collect();
+        Array::from_shape_vec(IxDyn(&shape), values).expect("read values fill the shape")
+    }
+
+    /// The values at the selected indices of each dimension (see
+    /// [`select_values`]), reading the smallest hyperslab holding them
+    pub fn select(
+        &self,
+        dimensions: &[String],
+        selected_indices: &HashMap<String, Vec<usize>>,
+    ) -> Array<f32, IxDyn> {
+        if dimensions.len() != self.shape.len() {
+            return select_values(self.to_array().view(), dimensions, selected_indices);
+        }
+        let mut start = Vec::with_capacity(dimensions.len());
+        let mut count = Vec::with_capacity(dimensions.len());
+        let mut shifted = HashMap::new();
+        for (dim, &size) in dimensions.iter().zip(&self.shape) {
+            match selected_indices.get(dim) {
+                Some(indices) => {
+                    let first = indices.iter().copied().min().unwrap_or(0);
+                    let last = indices.iter().copied().max().map_or(first, |i| i + 1);
+                    start.push(first);
+                    count.push(last - first);
+                    shifted.insert(dim.clone(), indices.iter().map(|i| i - first).collect());
+                }
+                None => {
+                    start.push(0);
+                    count.push(size);
+                }
+            }
+        }
+        let values = self.read_or_missing(&start, &count);
+        let view =
+            ArrayView::from_shape(IxDyn(&count), &values).expect("read values fill the shape");
+        select_values(view, dimensions, &shifted)
+    }
+}
+
+/// Run a blocking read of the file
+///
+/// On the multi-threaded runtime the worker thread hands its other tasks to
+/// the rest of the runtime while the read blocks it. Reads outside a runtime
+/// or on a current-thread runtime, such as those already on the blocking
+/// pool of [`tokio::task::spawn_blocking`], run as they are.
+fn blocking<R>(read: impl FnOnce() -> R) -> R {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(read)
+        }
+        _ => read(),
+    }
+}
+
+/// Drop the slab caches of the lazy variables of a state, with their
+/// checksums and open files, returning how many slabs were cached
+pub fn flush_slab_caches(state: &AppState) -> usize {
+    let mut caches: Vec<&Arc<SlabCache>> = Vec::new();
+    for var in state.lazy.values() {
+        if !caches.iter().any(|cache| Arc::ptr_eq(cache, var.cache())) {
+            caches.push(var.cache());
+        }
+    }
+    caches.into_iter().map(|cache| cache.clear()).sum()
+}
+
+/// Run `f`, failing with the regions it could not read, if any
+///
+/// For reads outside the request handlers, such as the chunks of a streamed
+/// response body.
+pub fn checked_reads<R>(f: impl FnOnce() -> R) -> Result<R> {
+    let (result, failures) = READ_FAILURES.sync_scope(RefCell::new(Vec::new()), || {
+        let result = f();
+        (result, READ_FAILURES.with(|failures| failures.take()))
+    });
+    match failures.into_iter().next() {
+        None => Ok(result),
+        Some(failure) => Err(failure.into()),
+    }
+}
+
+impl From<ReadFailure> for RossbyError {
+    fn from(failure: ReadFailure) -> Self {
+        RossbyError::CorruptData {
+            variable: failure.variable,
+            message: format!(
+                "region start {:?} count {:?}: {}",
+                failure.start, failure.count, failure.message
+            ),
+        }
+    }
+}
+
+/// Answer requests that failed to read values with `502 Bad Gateway`
+///
+/// The response names the variable and region of each failure. Caches that
+/// may hold values derived from the failed reads are flushed.
+pub async fn read_failure_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let endpoint = request.uri().path().to_string();
+    let (response, failures) = READ_FAILURES
+        .scope(RefCell::new(Vec::new()), async {
+            let response = next.run(request).await;
+            (response, READ_FAILURES.with(|failures| failures.take()))
+        })
+        .await;
+    if failures.is_empty() {
+        return response;
+    }
+
+    // The slab checksums are kept, so the corrupt slabs stay rejected
+    for kind in CacheKind::ALL {
+        if kind != CacheKind::Slabs {
+            kind.flush(&state);
+        }
+    }
+    let request_id = generate_request_id();
+    let error = RossbyError::from(failures[0].clone());
+    log_request_error(&error, &endpoint, &request_id, None);
+    (
+        StatusCode::BAD_GATEWAY,
+        Json(serde_json::json!({
+            "error": error.to_string(),
+            "kind": error.kind(),
+            "failed_regions": failures,
+            "request_id": request_id,
+            "schema_version": SCHEMA_VERSION
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// A (time, lat, lon) variable of 3 x 2 x 4 values counting from 0
+    fn create_test_file(path: &Path) -> Result<()> {
+        let mut file = netcdf::create(path)?;
+        file.add_dimension("time", 3)?;
+        file.add_dimension("lat", 2)?;
+        file.add_dimension("lon", 4)?;
+        file.add_variable::<i16>("v", &["time", "lat", "lon"])?
+            .put_values(&(0..24).collect::<Vec<i16>>(), ..)?;
+        Ok(())
+    }
+
+    fn expected() -> Array<f32, IxDyn> {
+        Array::from_shape_fn(IxDyn(&[3, 2, 4]), |idx| {
+            (idx[0] * 8 + idx[1] * 4 + idx[2]) as f32
+        })
+    }
+
+    #[test]
+    fn test_storage_mode() {
+        assert_eq!(StorageMode::parse("memory").unwrap(), StorageMode::Memory);
+        assert_eq!(StorageMode::parse("lazy").unwrap(), StorageMode::Lazy);
+        assert!(StorageMode::parse("mmap").is_err());
+    }
+
+    #[test]
+    fn test_lazy_reads() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("lazy.nc");
+        create_test_file(&path)?;
+        let cache = Arc::new(SlabCache::new(1024));
+        let var = LazyVariable::new(&path, "v", vec![3, 2, 4], cache.clone());
+        let expected = expected();
+
+        assert_eq!(var.get(&[2, 1, 3]), Some(23.0));
+        assert_eq!(var.get(&[3, 0, 0]), None);
+        assert_eq!(var.get(&[0, 0]), None);
+        assert_eq!(var.to_array(), expected);
+        assert!(var.iter().eq(expected.iter().copied()));
+
+        // A time step from its slab, a time series from the file
+        assert_eq!(
+            var.index_axes(&[(1, 1), (0, 2)]),
+            expected.index_axis(Axis(0), 2).index_axis(Axis(0), 1)
+        );
+        assert_eq!(
+            var.index_axes(&[(2, 3), (1, 0)]).as_slice().unwrap(),
+            &[3.0, 11.0, 19.0]
+        );
+
+        let dimensions = ["time", "lat", "lon"].map(String::from);
+        let selected = HashMap::from([
+            ("time".to_string(), vec![2, 0]),
+            ("lon".to_string(), vec![1]),
+        ]);
+        assert_eq!(
+            var.select(&dimensions, &selected),
+            select_values(expected.view(), &dimensions, &selected)
+        );
+
+        // The small variable is one slab of 96 bytes
+        assert_eq!(cache.bytes(), 96);
+
+        // Flushed slabs are read again, from the file opened again
+        assert_eq!(cache.clear(), 1);
+        assert_eq!(cache.bytes(), 0);
+        assert_eq!(var.get(&[2, 1, 3]), Some(23.0));
+
+        // Values that cannot be read are missing
+        let missing = LazyVariable::new(&path, "w", vec![3, 2, 4], cache);
+        assert!(missing.get(&[0, 0, 0]).unwrap().is_nan());
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_slabs() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("corrupt.nc");
+        create_test_file(&path)?;
+
+        // Nothing is cached, so every read goes to the file
+        let var = LazyVariable::new(&path, "v", vec![3, 2, 4], Arc::new(SlabCache::new(0)));
+        assert_eq!(checked_reads(|| var.get(&[1, 0, 0]))?, Some(8.0));
+
+        // The file changes under the server, which reads it again once the
+        // file it holds open is closed
+        var.cache.close_file();
+        netcdf::append(&path)?
+            .variable_mut("v")
+            .unwrap()
+            .put_value(-1i16, [1, 0, 0])?;
+        let err = checked_reads(|| var.get(&[2, 1, 3])).unwrap_err();
+        assert!(matches!(err, RossbyError::CorruptData { .. }));
+        assert!(err.to_string().contains("start [0, 0, 0] count [3, 2, 4]"));
+        assert!(var.to_array().iter().all(|v| v.is_nan()));
+
+        // Flushing the cache forgets the checksums of the old values
+        assert_eq!(var.cache.clear(), 0);
+        assert_eq!(checked_reads(|| var.get(&[1, 0, 0]))?, Some(-1.0));
+
+        // Read errors are reported the same way
+        let missing = LazyVariable::new(&path, "w", vec![3, 2, 4], Arc::new(SlabCache::new(0)));
+        let (values, failures) = READ_FAILURES.sync_scope(RefCell::new(Vec::new()), || {
+            let values = missing.index_axes(&[(2, 1)]);
+            (values, READ_FAILURES.with(|failures| failures.take()))
+        });
+        assert!(values.iter().all(|v| v.is_nan()));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].reason, FailureReason::ReadError);
+        assert_eq!(failures[0].start, vec![0, 0, 1]);
+        assert_eq!(failures[0].count, vec![3, 2, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_slab_cache_eviction() {
+        let cache = SlabCache::new(32);
+        let slab = |len| Arc::new(vec![0.0; len]);
+        cache.insert(("a".to_string(), 0), slab(4));
+        cache.insert(("a".to_string(), 1), slab(4));
+        assert_eq!(cache.bytes(), 32);
+
+        // The least recently used slab goes first
+        assert!(cache.get(&("a".to_string(), 0)).is_some());
+        cache.insert(("b".to_string(), 0), slab(2));
+        assert!(cache.get(&("a".to_string(), 1)).is_none());
+        assert!(cache.get(&("a".to_string(), 0)).is_some());
+        assert_eq!(cache.bytes(), 24);
+
+        // Slabs larger than the cache are not kept
+        cache.insert(("c".to_string(), 0), slab(9));
+        assert!(cache.get(&("c".to_string(), 0)).is_none());
+        assert_eq!(cache.bytes(), 24);
+    }
+}
@@ -19,14 +19,75 @@
 //! - **API Layer**: Exposes data through a RESTful HTTP API
 //! - **Processing**: Supports multiple interpolation methods and colormap rendering
 
+pub mod access_log;
+pub mod append;
+pub mod arithmetic;
+pub mod artifact;
+pub mod attribute_text;
+pub mod bench;
+pub mod bitround;
+pub mod body_limit;
+pub mod bounds;
+pub mod categories;
+pub mod cf_check;
+pub mod cf_time;
+pub mod climatology;
 pub mod colormaps;
 pub mod config;
+pub mod coord_index;
+pub mod coordinate_fixes;
+pub mod cost;
+pub mod crs;
 pub mod data_loader;
+pub mod datasets;
+pub mod dynamics;
+pub mod endpoint_switches;
 pub mod error;
+pub mod federation;
+pub mod field;
+pub mod filter;
+pub mod filters;
+pub mod fleet;
+pub mod forecast_time;
+pub mod generation;
+pub mod geometry;
+pub mod geotiff;
+pub mod grid_snap;
 pub mod handlers;
+pub mod integrity;
 pub mod interpolation;
+pub mod kerchunk;
+pub mod lazy;
 pub mod logging;
+pub mod memory_budget;
+pub mod nan_policy;
+pub mod netcdf_writer;
+pub mod packed;
+pub mod panel;
+pub mod partial;
+pub mod products;
+pub mod profiling;
+pub mod query;
+pub mod query_resolved;
+pub mod quota;
+pub mod replay;
+pub mod running_stats;
+pub mod sampling;
+pub mod schema;
+pub mod signing;
+pub mod single_flight;
+pub mod slice_stats;
+pub mod snapshot;
+pub mod spatial;
 pub mod state;
+pub mod thumbnail;
+pub mod tidy;
+pub mod tiles;
+pub mod time_axis;
+pub mod uncertainty;
+pub mod vertical;
+pub mod views;
+pub mod warmup;
 
 pub use config::Config;
 pub use error::{Result, RossbyError};
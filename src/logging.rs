@@ -8,6 +8,7 @@ use tracing::{debug, error, info};
 use uuid::Uuid;
 
 use crate::error::RossbyError;
+use crate::profiling::ProfileLayer;
 
 /// Generate a unique request ID for tracing
 pub fn generate_request_id() -> String {
@@ -190,15 +191,16 @@ pub fn log_request_success(endpoint: &str, request_id: &str, status: u16, durati
 
 /// Set up logging with appropriate formatting and level
 pub fn setup_logging() -> Result<(), RossbyError> {
-    use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+    use tracing_subscriber::{filter::filter_fn, fmt, prelude::*, EnvFilter};
 
     // Use RUST_LOG env var if set, otherwise use info level
     let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
 
-    // Initialize the tracing subscriber
+    // Initialize the tracing subscriber. The log level only filters output;
+    // request profiling sees stage spans at any level.
     tracing_subscriber::registry()
-        .with(fmt::layer().with_target(true))
-        .with(env_filter)
+        .with(fmt::layer().with_target(true).with_filter(env_filter))
+        .with(ProfileLayer.with_filter(filter_fn(|metadata| metadata.is_span())))
         .try_init()
         .map_err(|e| RossbyError::Server {
             message: format!("Failed to initialize logging: {}", e),
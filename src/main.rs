@@ -2,17 +2,53 @@
 //!
 //! This is the main entry point for the rossby application.
 
-use axum::{routing::get, Router};
+use axum::{
+    extract::Request,
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use clap::Parser;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio::signal;
+use tokio::sync::watch;
+use tower::{service_fn, ServiceExt};
 use tower_http::cors::CorsLayer;
-use tracing::info;
+use tracing::{info, warn};
 
+use rossby::access_log::access_log_middleware;
+use rossby::append::append_from_file;
+use rossby::bench::{BenchArgs, BENCH_COMMAND};
+use rossby::body_limit::{body_limit_middleware, handler_body_limit};
 use rossby::data_loader::load_netcdf;
+use rossby::datasets::{dataset_access_middleware, load_dataset, mount_path, DatasetAccess};
+use rossby::endpoint_switches::endpoint_switch_middleware;
+use rossby::fleet::{RouteArgs, ROUTE_COMMAND};
+use rossby::generation::generation_middleware;
 use rossby::handlers::{
-    data_handler, heartbeat_handler, image_handler, metadata_handler, point_handler,
+    cellstats_handler, chart_handler, correlate_handler, data_handler, decompose_handler,
+    diff_handler, exceedance_handler, export_handler, flush_caches_handler, healthz_handler,
+    heartbeat_handler, image_handler, interpolate_debug_handler, lagcorr_handler, mask_handler,
+    metadata_handler, panel_handler, point_handler, profile_series_handler, sample_handler,
+    signing_key_handler, state_snapshot_handler, stats_handler, thumbnail_handler, usage_handler,
+    variable_handler, variables_handler, wms_handler,
 };
+use rossby::integrity::run_self_checks;
+use rossby::lazy::read_failure_middleware;
+use rossby::products::product_middleware;
+use rossby::profiling::profile_middleware;
+use rossby::quota::quota_middleware;
+use rossby::replay::{ReplayArgs, REPLAY_COMMAND};
+use rossby::schema::schema_middleware;
+use rossby::signing::{signing_middleware, ResponseSigner};
+use rossby::single_flight::single_flight_middleware;
+use rossby::snapshot::{DumpStateArgs, DUMP_STATE_COMMAND};
+use rossby::state::AppState;
+use rossby::tiles::{TileArgs, PREGENERATE_COMMAND};
+use rossby::warmup::warm_up;
 use rossby::{
     generate_request_id, log_data_loaded, log_request_error, setup_logging, start_timed_operation,
     Config, Result, RossbyError,
@@ -23,6 +59,71 @@ async fn main() -> Result<()> {
     // Initialize logging with default configuration
     setup_logging()?;
 
+    // `rossby pregenerate-tiles ...` renders static tiles instead of serving
+    if std::env::args().nth(1).as_deref() == Some(PREGENERATE_COMMAND) {
+        let args = TileArgs::parse_from(std::env::args().skip(1));
+        return rossby::tiles::run(args).await.inspect_err(|e| {
+            log_request_error(
+                e,
+                PREGENERATE_COMMAND,
+                &generate_request_id(),
+                Some("Tile pregeneration failed"),
+            );
+        });
+    }
+
+    // `rossby replay ...` replays an access log against another instance
+    if std::env::args().nth(1).as_deref() == Some(REPLAY_COMMAND) {
+        let args = ReplayArgs::parse_from(std::env::args().skip(1));
+        return rossby::replay::run(args).await.inspect_err(|e| {
+            log_request_error(
+                e,
+                REPLAY_COMMAND,
+                &generate_request_id(),
+                Some("Replay failed"),
+            );
+        });
+    }
+
+    // `rossby bench ...` measures the endpoints on a synthetic dataset
+    if std::env::args().nth(1).as_deref() == Some(BENCH_COMMAND) {
+        let args = BenchArgs::parse_from(std::env::args().skip(1));
+        return rossby::bench::run(args).await.inspect_err(|e| {
+            log_request_error(
+                e,
+                BENCH_COMMAND,
+                &generate_request_id(),
+                Some("Benchmarks failed"),
+            );
+        });
+    }
+
+    // `rossby dump-state ...` writes a snapshot of the serving state
+    if std::env::args().nth(1).as_deref() == Some(DUMP_STATE_COMMAND) {
+        let args = DumpStateArgs::parse_from(std::env::args().skip(1));
+        return rossby::snapshot::run(args).await.inspect_err(|e| {
+            log_request_error(
+                e,
+                DUMP_STATE_COMMAND,
+                &generate_request_id(),
+                Some("State snapshot failed"),
+            );
+        });
+    }
+
+    // `rossby route ...` forwards requests to instances sharded by time
+    if std::env::args().nth(1).as_deref() == Some(ROUTE_COMMAND) {
+        let args = RouteArgs::parse_from(std::env::args().skip(1));
+        return rossby::fleet::run(args).await.inspect_err(|e| {
+            log_request_error(
+                e,
+                ROUTE_COMMAND,
+                &generate_request_id(),
+                Some("Routing failed"),
+            );
+        });
+    }
+
     info!(
         version = env!("CARGO_PKG_VERSION"),
         "Starting rossby server"
@@ -52,6 +153,16 @@ async fn main() -> Result<()> {
     })?;
     // _guard logs when dropped
 
+    // Load the response signing key before the data, so a bad key fails fast
+    let signer = ResponseSigner::from_config(&config.server).inspect_err(|e| {
+        log_request_error(
+            e,
+            "startup",
+            &generate_request_id(),
+            Some("Failed to load signing key"),
+        );
+    })?;
+
     // Set log level from config if not already set via environment
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", &config.log_level);
@@ -66,7 +177,7 @@ async fn main() -> Result<()> {
     // Load NetCDF data and create application state
     let _data_load_guard = start_timed_operation("data_load", Some(&netcdf_path.to_string_lossy()));
 
-    let app_state = load_netcdf(&netcdf_path, config.clone()).inspect_err(|e| {
+    let mut app_state = load_netcdf(&netcdf_path, config.clone()).inspect_err(|e| {
         log_request_error(
             e,
             "startup",
@@ -85,6 +196,17 @@ async fn main() -> Result<()> {
         );
     })?;
 
+    // Fingerprint the dataset up front rather than on the first signed response
+    if let Some(signer) = signer {
+        let _guard = start_timed_operation("dataset_fingerprint", None);
+        info!(
+            fingerprint = app_state.fingerprint(),
+            public_key = %signer.public_key(),
+            "Signing responses"
+        );
+        app_state.signer = Some(signer);
+    }
+
     // Calculate approximate memory usage
     let total_memory = app_state
         .data
@@ -114,18 +236,71 @@ async fn main() -> Result<()> {
     // Wrap in Arc for sharing
     let state = Arc::new(app_state);
 
-    // Build the router
-    let app = Router::new()
-        .route("/metadata", get(metadata_handler))
-        .route("/point", get(point_handler))
-        .route("/image", get(image_handler))
-        .route("/heartbeat", get(heartbeat_handler))
-        .route("/data", get(data_handler))
-        .layer(CorsLayer::permissive())
-        // Add tracing layer for request/response logging
-        // Temporarily commenting out due to type issues
-        // .layer(create_http_trace_layer())
-        .with_state(state);
+    // Datasets served alongside share the signing key
+    let signer = state.signer.clone();
+
+    // The state being served, replaced as time steps are appended
+    let states = watch::Sender::new(state.clone());
+    spawn_self_checks(&state, states.subscribe());
+
+    // Build the router, swapped for a new one as time steps are appended
+    let mut app = match config.data.append_interval_secs {
+        Some(interval) => {
+            info!(
+                interval_secs = interval,
+                "Polling the data file for appended time steps"
+            );
+            let current = Arc::new(RwLock::new(build_router(state.clone())));
+            tokio::spawn(watch_appends(
+                state,
+                netcdf_path.clone(),
+                Duration::from_secs(interval),
+                current.clone(),
+                states,
+            ));
+            Router::new().fallback_service(service_fn(move |request: Request| {
+                let router = current.read().unwrap().clone();
+                router.oneshot(request)
+            }))
+        }
+        None => build_router(state),
+    };
+
+    // Serve the further datasets under their own paths
+    for (name, dataset) in &config.datasets {
+        info!(
+            dataset = %name,
+            file_path = %dataset.file_path.display(),
+            "Loading dataset"
+        );
+        let mut dataset_state = load_dataset(&config, name, dataset).inspect_err(|e| {
+            log_request_error(
+                e,
+                "startup",
+                &generate_request_id(),
+                Some(&format!("Failed to load dataset {}", name)),
+            );
+        })?;
+        dataset_state.signer = signer.clone();
+        let dataset_state = Arc::new(dataset_state);
+        spawn_self_checks(&dataset_state, watch::channel(dataset_state.clone()).1);
+        let access = Arc::new(DatasetAccess::new(name, dataset));
+        let router = build_router(dataset_state).layer(middleware::from_fn_with_state(
+            access,
+            dataset_access_middleware,
+        ));
+        app = app.nest(&mount_path(name), router);
+    }
+
+    // Prime caches with the configured requests while serving
+    if !config.server.warmup.requests.is_empty() {
+        info!(
+            requests = config.server.warmup.requests.len(),
+            concurrency = config.server.warmup.concurrency,
+            "Starting warm-up"
+        );
+        tokio::spawn(warm_up(app.clone(), config.server.warmup.clone()));
+    }
 
     // Create the server address
     let addr = SocketAddr::from((
@@ -169,17 +344,177 @@ async fn main() -> Result<()> {
     );
 
     // Start the server with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_future)
-        .await
-        .map_err(|e| RossbyError::Server {
-            message: format!("Server error: {}", e),
-        })?;
+    // Serve with peer addresses so anonymous clients can be accounted by IP
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_future)
+    .await
+    .map_err(|e| RossbyError::Server {
+        message: format!("Server error: {}", e),
+    })?;
 
     info!("Server has been gracefully shut down");
     Ok(())
 }
 
+/// Build the router serving a dataset state
+fn build_router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/metadata", get(metadata_handler))
+        .route("/metadata/variables", get(variables_handler))
+        .route("/metadata/variables/:name", get(variable_handler))
+        .route("/point", get(point_handler))
+        .route("/debug/interpolate", get(interpolate_debug_handler))
+        .route("/image", get(image_handler))
+        .route("/heartbeat", get(heartbeat_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/data", get(data_handler))
+        .route("/stats", get(stats_handler))
+        .route("/diff", get(diff_handler))
+        .route("/mask", get(mask_handler))
+        .route("/exceedance", get(exceedance_handler))
+        .route("/correlate", get(correlate_handler))
+        .route("/lagcorr", get(lagcorr_handler))
+        .route("/profile_series", get(profile_series_handler))
+        .route("/chart", get(chart_handler))
+        .route("/decompose", get(decompose_handler))
+        .route("/cellstats", get(cellstats_handler))
+        .route("/sample", get(sample_handler))
+        .route("/export", get(export_handler))
+        .route("/thumbnail", get(thumbnail_handler))
+        .route("/panel", get(panel_handler))
+        .route("/wms", get(wms_handler))
+        .route("/usage", get(usage_handler))
+        .route("/signing_key", get(signing_key_handler))
+        .route("/admin/caches/flush", post(flush_caches_handler))
+        .route("/admin/state", get(state_snapshot_handler))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            read_failure_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            single_flight_middleware,
+        ))
+        .layer(middleware::from_fn(schema_middleware))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            endpoint_switch_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            product_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            quota_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            generation_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            profile_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            body_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            signing_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            access_log_middleware,
+        ))
+        .layer(handler_body_limit(&state.config.server.body_limits))
+        .layer(CorsLayer::permissive())
+        // Add tracing layer for request/response logging
+        // Temporarily commenting out due to type issues
+        // .layer(create_http_trace_layer())
+        .with_state(state)
+}
+
+/// Poll the data file for appended time steps, swapping in a router serving
+/// the extended dataset whenever new steps are found
+async fn watch_appends(
+    mut state: Arc<AppState>,
+    path: PathBuf,
+    interval: Duration,
+    router: Arc<RwLock<Router>>,
+    states: watch::Sender<Arc<AppState>>,
+) {
+    let modified_at = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut modified = modified_at(&path);
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let current = modified_at(&path);
+        if current == modified {
+            continue;
+        }
+        modified = current;
+
+        let (previous, file) = (state.clone(), path.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            let appended = append_from_file(&previous, &file)?;
+            if let Some(appended) = appended.as_ref().filter(|a| a.state.signer.is_some()) {
+                // Fingerprint the new steps before serving signed responses
+                appended.state.fingerprint();
+            }
+            Ok(appended)
+        })
+        .await
+        .unwrap_or_else(|e| {
+            Err(RossbyError::Server {
+                message: format!("Append task failed: {}", e),
+            })
+        });
+
+        match result {
+            Ok(Some(appended)) => {
+                state = Arc::new(appended.state);
+                *router.write().unwrap() = build_router(state.clone());
+                states.send_replace(state.clone());
+                info!(
+                    steps = appended.steps,
+                    time_steps = state.time_dim_size(),
+                    generation = state.generation,
+                    "Appended time steps from {}",
+                    path.display()
+                );
+            }
+            Ok(None) => {}
+            Err(e) => warn!(
+                error = %e,
+                "Failed to append time steps from {}",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Start the background self-checks of a dataset, if configured
+///
+/// `states` yields the state being served, which appends replace.
+fn spawn_self_checks(state: &AppState, states: watch::Receiver<Arc<AppState>>) {
+    if let Some(interval) = state.config.data.self_check_interval_secs {
+        info!(
+            interval_secs = interval,
+            "Running background self-checks of the data"
+        );
+        tokio::spawn(run_self_checks(
+            states,
+            Duration::from_secs(interval),
+            state.config.data.self_check_samples,
+        ));
+    }
+}
+
 /// Wait for a shutdown signal
 async fn shutdown_signal() {
     let ctrl_c = async {
@@ -0,0 +1,317 @@
+//! Memory budget of the loaded dataset.
+//!
+//! Every variable is read into memory as `f32`, so the memory a file needs is
+//! known from its metadata before any data is read: four bytes per value of
+//! each variable, plus eight per coordinate value. Variables kept packed with
+//! `data.keep_packed` (see [`crate::packed`]) take half that once loaded, but
+//! are read as `f32` first, so the estimate is the peak while loading. With
+//! `data.max_memory_bytes` set, the loader checks this estimate first and,
+//! rather than being killed by the operating system halfway through,
+//! either refuses to load with a [`RossbyError::MemoryBudgetExceeded`]
+//! naming the largest variables, or (with `data.on_memory_exceeded:
+//! "exclude"`) leaves out variables until the rest fits.
+//!
+//! Variables are left out in reverse order of `data.memory_priority`:
+//! unlisted variables first, largest first, then the listed ones from the
+//! end of the list. Coordinate variables are always loaded.
+
+use std::collections::{BTreeSet, HashMap};
+use tracing::{info, warn};
+
+use crate::config::DataConfig;
+use crate::error::{Result, RossbyError};
+use crate::state::{Metadata, Variable};
+
+/// Number of variables named in a budget error
+const LARGEST_REPORTED: usize = 5;
+
+/// What to do when a file does not fit in the budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnExceeded {
+    /// Refuse to load the file
+    Error,
+    /// Leave out low-priority variables until the rest fits
+    Exclude,
+}
+
+impl OnExceeded {
+    /// Parse the `data.on_memory_exceeded` setting
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "error" => Ok(OnExceeded::Error),
+            "exclude" => Ok(OnExceeded::Exclude),
+            other => Err(RossbyError::Config {
+                message: format!(
+                    "Invalid on_memory_exceeded: {}. Must be one of: error, exclude",
+                    other
+                ),
+            }),
+        }
+    }
+}
+
+/// Upper bound on the memory of the loaded data
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryBudget {
+    /// Bytes the data arrays and coordinates may take
+    pub max_bytes: u64,
+    /// What to do when the file does not fit
+    pub on_exceeded: OnExceeded,
+    /// Variables in order of importance
+    pub priority: Vec<String>,
+}
+
+impl MemoryBudget {
+    /// The budget configured in the data section, if any
+    pub fn from_config(config: &DataConfig) -> Result<Option<Self>> {
+        let Some(max_bytes) = config.max_memory_bytes else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            max_bytes,
+            on_exceeded: OnExceeded::parse(&config.on_memory_exceeded)?,
+            priority: config.memory_priority.clone(),
+        }))
+    }
+
+    /// Variables to leave out so that the rest of a file fits
+    ///
+    /// Fails when the file does not fit and variables may not be left out,
+    /// or when it does not fit even with only its coordinates.
+    pub fn plan(&self, metadata: &Metadata) -> Result<BTreeSet<String>> {
+        // Every dimension gets coordinates, read or made up by the loader
+        let coordinate_bytes: u64 = metadata
+            .dimensions
+            .values()
+            .map(|dim| dim.size as u64 * 8)
+            .sum();
+        let sizes: HashMap<&str, u64> = metadata
+            .variables
+            .iter()
+            .map(|(name, var)| (name.as_str(), variable_bytes(var)))
+            .collect();
+        let required = coordinate_bytes + sizes.values().sum::<u64>();
+
+        let mut excluded = BTreeSet::new();
+        if required <= self.max_bytes {
+            return Ok(excluded);
+        }
+        let exceeded = || {
+            let mut largest: Vec<(String, u64)> = sizes
+                .iter()
+                .map(|(name, &bytes)| (name.to_string(), bytes))
+                .collect();
+            largest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            largest.truncate(LARGEST_REPORTED);
+            RossbyError::MemoryBudgetExceeded {
+                required,
+                budget: self.max_bytes,
+                largest,
+            }
+        };
+        if self.on_exceeded == OnExceeded::Error {
+            return Err(exceeded());
+        }
+
+        let mut remaining = required;
+        for name in self.exclusion_order(metadata, &sizes) {
+            if remaining <= self.max_bytes {
+                break;
+            }
+            remaining -= sizes[name];
+            excluded.insert(name.to_string());
+        }
+        if remaining > self.max_bytes {
+            return Err(exceeded());
+        }
+        Ok(excluded)
+    }
+
+    /// Variables that may be left out, least important first
+    fn exclusion_order<'a>(
+        &self,
+        metadata: &'a Metadata,
+        sizes: &HashMap<&str, u64>,
+    ) -> Vec<&'a str> {
+        let rank = |name: &str| self.priority.iter().position(|p| p == name);
+        let mut order: Vec<&str> = metadata
+            .variables
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !metadata.dimensions.contains_key(*name))
+            .collect();
+        order.sort_by(|a, b| match (rank(a), rank(b)) {
+            (None, None) => sizes[b].cmp(&sizes[a]).then_with(|| a.cmp(b)),
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (Some(a), Some(b)) => b.cmp(&a),
+        });
+        order
+    }
+}
+
+/// Bytes a variable takes once loaded
+pub fn variable_bytes(var: &Variable) -> u64 {
+    var.shape
+        .iter()
+        .fold(std::mem::size_of::<f32>() as u64, |bytes, &len| {
+            bytes.saturating_mul(len as u64)
+        })
+}
+
+/// Check a file's metadata against the budget before its data is read
+///
+/// Variables left out are removed from the metadata, so they are neither
+/// read nor listed.
+pub fn apply_budget(budget: Option<&MemoryBudget>, metadata: &mut Metadata) -> Result<()> {
+    let Some(budget) = budget else {
+        return Ok(());
+    };
+    let excluded = budget.plan(metadata)?;
+    for name in &excluded {
+        metadata.variables.remove(name);
+    }
+    for group in metadata.groups.values_mut() {
+        group.variables.retain(|name| !excluded.contains(name));
+    }
+    if excluded.is_empty() {
+        info!(
+            budget = budget.max_bytes,
+            "Dataset fits in the memory budget"
+        );
+    } else {
+        warn!(
+            budget = budget.max_bytes,
+            excluded = ?excluded,
+            "Left out variables to fit the memory budget"
+        );
+    }
+    Ok(())
+}
+
+/// Human-readable byte count, e.g. `1.5 GiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Dimension;
+
+    /// Metadata of a (time, lat, lon) file with 3D variables of the given names
+    fn metadata(names: &[&str]) -> Metadata {
+        let sizes = [("time", 10), ("lat", 100), ("lon", 100)];
+        let dimensions = sizes
+            .iter()
+            .map(|&(name, size)| {
+                let dimension = Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                };
+                (name.to_string(), dimension)
+            })
+            .collect();
+        let variable = |name: &str, dims: &[(&str, usize)]| {
+            let var = Variable {
+                name: name.to_string(),
+                dimensions: dims.iter().map(|(d, _)| d.to_string()).collect(),
+                shape: dims.iter().map(|&(_, s)| s).collect(),
+                attributes: HashMap::new(),
+                dtype: "f32".to_string(),
+            };
+            (name.to_string(), var)
+        };
+        let mut variables: HashMap<String, Variable> = sizes
+            .iter()
+            .map(|&(name, size)| variable(name, &[(name, size)]))
+            .collect();
+        variables.extend(names.iter().map(|name| variable(name, &sizes)));
+        Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables,
+            coordinates: sizes
+                .iter()
+                .map(|&(name, size)| (name.to_string(), vec![0.0; size]))
+                .collect(),
+            groups: HashMap::new(),
+        }
+    }
+
+    fn budget(max_bytes: u64, on_exceeded: OnExceeded, priority: &[&str]) -> MemoryBudget {
+        MemoryBudget {
+            max_bytes,
+            on_exceeded,
+            priority: priority.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        // Each 3D variable takes 400 kB, coordinates about 2 kB
+        let metadata = metadata(&["t2m", "tp", "u10"]);
+        let fits = budget(2_000_000, OnExceeded::Error, &[]);
+        assert!(fits.plan(&metadata).unwrap().is_empty());
+
+        let error = budget(1_000_000, OnExceeded::Error, &[])
+            .plan(&metadata)
+            .unwrap_err();
+        match &error {
+            RossbyError::MemoryBudgetExceeded {
+                required,
+                budget,
+                largest,
+            } => {
+                assert_eq!(*required, 3 * 400_000 + 210 * 4 + 210 * 8);
+                assert_eq!(*budget, 1_000_000);
+                assert_eq!(largest[0], ("t2m".to_string(), 400_000));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(error.to_string().contains("t2m (390.6 KiB)"));
+
+        // Unlisted variables go first, then the end of the priority list
+        let excluded = budget(1_000_000, OnExceeded::Exclude, &["u10", "t2m"])
+            .plan(&metadata)
+            .unwrap();
+        assert_eq!(excluded, BTreeSet::from(["tp".to_string()]));
+        let excluded = budget(500_000, OnExceeded::Exclude, &["u10", "t2m"])
+            .plan(&metadata)
+            .unwrap();
+        assert_eq!(
+            excluded,
+            BTreeSet::from(["t2m".to_string(), "tp".to_string()])
+        );
+
+        // Coordinates are never left out
+        assert!(budget(1_000, OnExceeded::Exclude, &[])
+            .plan(&metadata)
+            .is_err());
+    }
+
+    #[test]
+    fn test_apply_budget() {
+        let mut metadata = metadata(&["t2m", "tp"]);
+        let limit = budget(500_000, OnExceeded::Exclude, &["tp"]);
+        apply_budget(Some(&limit), &mut metadata).unwrap();
+        assert!(metadata.variables.contains_key("tp"));
+        assert!(!metadata.variables.contains_key("t2m"));
+        assert!(metadata.variables.contains_key("lat"));
+
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024 / 2), "1.5 GiB");
+    }
+}
@@ -0,0 +1,206 @@
+//! Encoding of non-finite numbers in JSON responses.
+//!
+//! JSON has no NaN or infinity, so `/point`, `/data?format=json`, `/stats`
+//! and `/diff` write them according to one policy, chosen per request with
+//! `nan=null|string|omit` or by default with `data.json_nan` in the
+//! configuration:
+//!
+//! - `null` (the default) writes `null`,
+//! - `string` writes the strings `"NaN"`, `"Infinity"` and `"-Infinity"`,
+//! - `omit` leaves the member out of its object. Array elements keep their
+//!   position and are written as `null`.
+//!
+//! Missing values and undefined statistics (e.g. the mean of an empty slab)
+//! count as NaN. The policy applied is returned in the `X-Rossby-Nan`
+//! response header, and in the metadata of `/data` and `/stats` responses.
+
+use ndarray::Array2;
+use serde_json::{Map, Number, Value};
+
+use crate::error::{Result, RossbyError};
+
+/// Response header naming the NaN policy applied to a request
+pub const NAN_HEADER: &str = "x-rossby-nan";
+
+/// How non-finite numbers are written in JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NanPolicy {
+    /// Write `null`
+    #[default]
+    Null,
+    /// Write `"NaN"`, `"Infinity"` or `"-Infinity"`
+    String,
+    /// Leave object members out, and write array elements as `null`
+    Omit,
+}
+
+impl NanPolicy {
+    /// Parse a policy name
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "null" => Ok(NanPolicy::Null),
+            "string" => Ok(NanPolicy::String),
+            "omit" => Ok(NanPolicy::Omit),
+            other => Err(RossbyError::InvalidParameter {
+                param: "nan".to_string(),
+                message: format!(
+                    "Unknown NaN policy: {}. Valid values are 'null', 'string' or 'omit'",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Policy of a request: the `nan` parameter if given, else the configured default
+    pub fn from_request(param: Option<&str>, default: &str) -> Result<Self> {
+        Self::parse(param.unwrap_or(default))
+    }
+
+    /// Name of the policy as given in `nan`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NanPolicy::Null => "null",
+            NanPolicy::String => "string",
+            NanPolicy::Omit => "omit",
+        }
+    }
+
+    /// JSON value of an object member, or None if it is left out
+    pub fn member(self, value: f64) -> Option<Value> {
+        match Number::from_f64(value) {
+            Some(number) => Some(Value::Number(number)),
+            None if self == NanPolicy::Omit => None,
+            None => Some(self.element(value)),
+        }
+    }
+
+    /// JSON value of an array element
+    pub fn element(self, value: f64) -> Value {
+        match (Number::from_f64(value), self) {
+            (Some(number), _) => Value::Number(number),
+            (None, NanPolicy::String) => Value::String(non_finite_name(value).to_string()),
+            (None, _) => Value::Null,
+        }
+    }
+
+    /// Append an array element to JSON written by hand
+    pub fn write_element(self, value: f32, out: &mut String) {
+        if value.is_finite() {
+            out.push_str(&value.to_string());
+        } else if self == NanPolicy::String {
+            out.push('"');
+            out.push_str(non_finite_name(value as f64));
+            out.push('"');
+        } else {
+            out.push_str("null");
+        }
+    }
+
+    /// Insert a member into an object, unless the policy leaves it out
+    pub fn insert(self, object: &mut Map<String, Value>, key: String, value: f64) {
+        if let Some(value) = self.member(value) {
+            object.insert(key, value);
+        }
+    }
+
+    /// Rows of a 2D array as nested JSON arrays
+    pub fn rows(self, values: &Array2<f32>) -> Value {
+        values
+            .outer_iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&v| self.element(v as f64))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// Apply the policy to the undefined (`null`) members of a serialized
+    /// statistics object
+    pub fn undefined_members(self, value: &mut Value) {
+        let Value::Object(object) = value else {
+            return;
+        };
+        match self {
+            NanPolicy::Null => {}
+            NanPolicy::String => {
+                for member in object.values_mut().filter(|member| member.is_null()) {
+                    *member = Value::String(non_finite_name(f64::NAN).to_string());
+                }
+            }
+            NanPolicy::Omit => object.retain(|_, member| !member.is_null()),
+        }
+    }
+}
+
+/// Name of a non-finite number in `string` policy
+fn non_finite_name(value: f64) -> &'static str {
+    if value == f64::INFINITY {
+        "Infinity"
+    } else if value == f64::NEG_INFINITY {
+        "-Infinity"
+    } else {
+        "NaN"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(NanPolicy::parse("String").unwrap(), NanPolicy::String);
+        assert_eq!(
+            NanPolicy::from_request(None, "omit").unwrap(),
+            NanPolicy::Omit
+        );
+        assert_eq!(
+            NanPolicy::from_request(Some("null"), "omit").unwrap(),
+            NanPolicy::Null
+        );
+        assert!(NanPolicy::parse("zero").is_err());
+    }
+
+    #[test]
+    fn test_members_and_elements() {
+        assert_eq!(NanPolicy::Null.member(f64::NAN), Some(Value::Null));
+        assert_eq!(NanPolicy::String.member(f64::NAN), Some(json!("NaN")));
+        assert_eq!(NanPolicy::Omit.member(f64::INFINITY), None);
+        assert_eq!(NanPolicy::Omit.member(1.5), Some(json!(1.5)));
+
+        assert_eq!(NanPolicy::Omit.element(f64::NAN), Value::Null);
+        assert_eq!(
+            NanPolicy::String.element(f64::NEG_INFINITY),
+            json!("-Infinity")
+        );
+
+        let mut out = String::new();
+        for value in [1.5, f32::INFINITY, f32::NAN] {
+            NanPolicy::String.write_element(value, &mut out);
+            out.push(',');
+        }
+        assert_eq!(out, r#"1.5,"Infinity","NaN","#);
+        out.clear();
+        NanPolicy::Omit.write_element(f32::NAN, &mut out);
+        assert_eq!(out, "null");
+    }
+
+    #[test]
+    fn test_undefined_members() {
+        let stats = json!({"count": 0, "min": null, "mean": null});
+        let mut omitted = stats.clone();
+        NanPolicy::Omit.undefined_members(&mut omitted);
+        assert_eq!(omitted, json!({"count": 0}));
+
+        let mut named = stats.clone();
+        NanPolicy::String.undefined_members(&mut named);
+        assert_eq!(named, json!({"count": 0, "min": "NaN", "mean": "NaN"}));
+
+        let mut kept = stats.clone();
+        NanPolicy::Null.undefined_members(&mut kept);
+        assert_eq!(kept, stats);
+    }
+}
@@ -0,0 +1,507 @@
+//! Streaming writer of NetCDF classic files.
+//!
+//! `/export` writes subsets of the dataset as NetCDF without building the
+//! file in memory or on disk. The 64-bit offset variant of the classic
+//! format (CDF-2) lays a file out as a header, then the values of the
+//! fixed-size variables one after another, then the records of the unlimited
+//! dimension, each holding one step of every record variable. The header
+//! gives the offset of every variable, and those follow from the shapes
+//! alone, so the header is written first and the values after it in file
+//! order, one step of the outermost dimension of a variable at a time
+//! ([`Chunk`]): the writer holds a single step whatever the size of the file.
+//!
+//! Variables are written as `float` or `double`, so no value needs padding.
+
+use crate::error::{Result, RossbyError};
+
+/// Magic number of the 64-bit offset format
+const MAGIC: &[u8; 4] = b"CDF\x02";
+
+/// Tags of the header lists
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+
+/// Type codes
+const NC_CHAR: u32 = 2;
+const NC_INT: u32 = 4;
+const NC_FLOAT: u32 = 5;
+const NC_DOUBLE: u32 = 6;
+
+/// Largest size of a variable, or of one record of it, in bytes
+const MAX_VSIZE: u64 = u32::MAX as u64 - 3;
+
+/// Type of the values of a variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NcType {
+    /// 32-bit float
+    Float,
+    /// 64-bit float
+    Double,
+}
+
+impl NcType {
+    fn code(self) -> u32 {
+        match self {
+            NcType::Float => NC_FLOAT,
+            NcType::Double => NC_DOUBLE,
+        }
+    }
+
+    /// Bytes per value
+    pub fn size(self) -> usize {
+        match self {
+            NcType::Float => 4,
+            NcType::Double => 8,
+        }
+    }
+}
+
+/// Value of an attribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum NcAttributeValue {
+    Text(String),
+    Ints(Vec<i32>),
+    Floats(Vec<f32>),
+    Doubles(Vec<f64>),
+}
+
+/// A named attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct NcAttribute {
+    pub name: String,
+    pub value: NcAttributeValue,
+}
+
+/// A dimension of the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct NcDimension {
+    pub name: String,
+    pub len: usize,
+}
+
+/// A variable of the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct NcVariable {
+    pub name: String,
+    /// Positions of its dimensions in the dimensions of the file
+    pub dimensions: Vec<usize>,
+    pub nc_type: NcType,
+    pub attributes: Vec<NcAttribute>,
+}
+
+/// One step of the outermost dimension of a variable (the whole variable
+/// for scalars), the unit in which values are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    /// Position of the variable in the variables of the file
+    pub variable: usize,
+    /// Index along its outermost dimension
+    pub step: usize,
+}
+
+/// Layout of a classic file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClassicFile {
+    pub dimensions: Vec<NcDimension>,
+    /// Position of the unlimited dimension, which must be the outermost
+    /// dimension of every variable using it (None = no unlimited dimension)
+    pub record_dimension: Option<usize>,
+    pub attributes: Vec<NcAttribute>,
+    pub variables: Vec<NcVariable>,
+}
+
+impl ClassicFile {
+    /// Whether a variable is stored in the records
+    pub fn is_record(&self, variable: usize) -> bool {
+        self.record_dimension.is_some()
+            && self.variables[variable].dimensions.first() == self.record_dimension.as_ref()
+    }
+
+    /// Number of values in each chunk of a variable
+    pub fn chunk_len(&self, variable: usize) -> usize {
+        let dimensions = &self.variables[variable].dimensions;
+        dimensions
+            .iter()
+            .skip(1)
+            .map(|&dim| self.dimensions[dim].len)
+            .product()
+    }
+
+    /// Bytes of a chunk in the file
+    pub fn chunk_size(&self, chunk: Chunk) -> u64 {
+        (self.chunk_len(chunk.variable) * self.variables[chunk.variable].nc_type.size()) as u64
+    }
+
+    /// Number of chunks of a variable
+    fn steps(&self, variable: usize) -> usize {
+        self.variables[variable]
+            .dimensions
+            .first()
+            .map_or(1, |&dim| self.dimensions[dim].len)
+    }
+
+    /// Bytes of a variable in the file, per record for record variables
+    fn vsize(&self, variable: usize) -> u64 {
+        let steps = if self.is_record(variable) {
+            1
+        } else {
+            self.steps(variable)
+        };
+        (steps * self.chunk_len(variable) * self.variables[variable].nc_type.size()) as u64
+    }
+
+    fn num_records(&self) -> usize {
+        self.record_dimension
+            .map_or(0, |dim| self.dimensions[dim].len)
+    }
+
+    /// Check that the layout can be written in the classic format
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(RossbyError::Conversion { message });
+        let names = self
+            .dimensions
+            .iter()
+            .map(|d| &d.name)
+            .chain(self.variables.iter().map(|v| &v.name));
+        for name in names {
+            if !is_valid_name(name) {
+                return invalid(format!("'{}' is not a valid NetCDF name", name));
+            }
+        }
+        if u32::try_from(self.num_records()).is_err() {
+            return invalid("Too many records for the NetCDF classic format".to_string());
+        }
+        for (position, var) in self.variables.iter().enumerate() {
+            if var
+                .dimensions
+                .iter()
+                .any(|&dim| dim >= self.dimensions.len())
+            {
+                return invalid(format!("Variable {} has an unknown dimension", var.name));
+            }
+            let record_inside = var
+                .dimensions
+                .iter()
+                .skip(1)
+                .any(|&dim| Some(dim) == self.record_dimension);
+            if record_inside {
+                return invalid(format!(
+                    "The unlimited dimension must be the outermost dimension of {}",
+                    var.name
+                ));
+            }
+            if self.vsize(position) > MAX_VSIZE {
+                return invalid(format!(
+                    "Variable {} is too large for the NetCDF classic format",
+                    var.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Offsets of the values of each variable, and the size of the file
+    fn offsets(&self, header_len: u64) -> (Vec<u64>, u64) {
+        let mut begins = vec![0; self.variables.len()];
+        let mut offset = header_len;
+        for variable in (0..self.variables.len()).filter(|&v| !self.is_record(v)) {
+            begins[variable] = offset;
+            offset += self.vsize(variable);
+        }
+        let mut record_size = 0;
+        for variable in (0..self.variables.len()).filter(|&v| self.is_record(v)) {
+            begins[variable] = offset + record_size;
+            record_size += self.vsize(variable);
+        }
+        (begins, offset + record_size * self.num_records() as u64)
+    }
+
+    /// The header, followed in the file by the chunks of [`ClassicFile::chunks`]
+    pub fn header(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        let header_len = self.encode_header(&vec![0; self.variables.len()]).len() as u64;
+        let (begins, _) = self.offsets(header_len);
+        Ok(self.encode_header(&begins))
+    }
+
+    /// Size of the whole file in bytes
+    pub fn file_size(&self) -> Result<u64> {
+        let header_len = self.header()?.len() as u64;
+        Ok(self.offsets(header_len).1)
+    }
+
+    /// The chunks of every variable in file order: the fixed-size variables
+    /// one after another, then record by record
+    pub fn chunks(&self) -> impl Iterator<Item = Chunk> + '_ {
+        let fixed = (0..self.variables.len())
+            .filter(|&v| !self.is_record(v))
+            .flat_map(|variable| {
+                (0..self.steps(variable)).map(move |step| Chunk { variable, step })
+            });
+        let records = (0..self.num_records()).flat_map(move |step| {
+            (0..self.variables.len())
+                .filter(|&v| self.is_record(v))
+                .map(move |variable| Chunk { variable, step })
+        });
+        fixed.chain(records)
+    }
+
+    fn encode_header(&self, begins: &[u64]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        put_u32(&mut out, self.num_records() as u32);
+
+        if self.dimensions.is_empty() {
+            put_absent(&mut out);
+        } else {
+            put_u32(&mut out, NC_DIMENSION);
+            put_u32(&mut out, self.dimensions.len() as u32);
+            for (position, dim) in self.dimensions.iter().enumerate() {
+                put_name(&mut out, &dim.name);
+                let len = if Some(position) == self.record_dimension {
+                    0
+                } else {
+                    dim.len
+                };
+                put_u32(&mut out, len as u32);
+            }
+        }
+
+        put_attributes(&mut out, &self.attributes);
+
+        if self.variables.is_empty() {
+            put_absent(&mut out);
+        } else {
+            put_u32(&mut out, NC_VARIABLE);
+            put_u32(&mut out, self.variables.len() as u32);
+            for (position, var) in self.variables.iter().enumerate() {
+                put_name(&mut out, &var.name);
+                put_u32(&mut out, var.dimensions.len() as u32);
+                for &dim in &var.dimensions {
+                    put_u32(&mut out, dim as u32);
+                }
+                put_attributes(&mut out, &var.attributes);
+                put_u32(&mut out, var.nc_type.code());
+                put_u32(&mut out, self.vsize(position) as u32);
+                out.extend_from_slice(&begins[position].to_be_bytes());
+            }
+        }
+        out
+    }
+}
+
+/// Whether a name is valid in the classic format
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        && !name.contains('/')
+        && !name.chars().any(char::is_control)
+        && !name.ends_with(' ')
+}
+
+/// Values of a `float` chunk as written to the file
+pub fn encode_floats(values: impl IntoIterator<Item = f32>) -> Vec<u8> {
+    values.into_iter().flat_map(f32::to_be_bytes).collect()
+}
+
+/// Values of a `double` chunk as written to the file
+pub fn encode_doubles(values: impl IntoIterator<Item = f64>) -> Vec<u8> {
+    values.into_iter().flat_map(f64::to_be_bytes).collect()
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_absent(out: &mut Vec<u8>) {
+    put_u32(out, 0);
+    put_u32(out, 0);
+}
+
+/// Bytes padded with zeros to a multiple of four
+fn put_padded(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes);
+    out.resize(out.len() + (4 - bytes.len() % 4) % 4, 0);
+}
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    put_u32(out, name.len() as u32);
+    put_padded(out, name.as_bytes());
+}
+
+fn put_attributes(out: &mut Vec<u8>, attributes: &[NcAttribute]) {
+    if attributes.is_empty() {
+        put_absent(out);
+        return;
+    }
+    put_u32(out, NC_ATTRIBUTE);
+    put_u32(out, attributes.len() as u32);
+    for attribute in attributes {
+        put_name(out, &attribute.name);
+        let (code, count, bytes) = match &attribute.value {
+            NcAttributeValue::Text(text) => (NC_CHAR, text.len(), text.as_bytes().to_vec()),
+            NcAttributeValue::Ints(values) => (
+                NC_INT,
+                values.len(),
+                values.iter().flat_map(|v| v.to_be_bytes()).collect(),
+            ),
+            NcAttributeValue::Floats(values) => (
+                NC_FLOAT,
+                values.len(),
+                encode_floats(values.iter().copied()),
+            ),
+            NcAttributeValue::Doubles(values) => (
+                NC_DOUBLE,
+                values.len(),
+                encode_doubles(values.iter().copied()),
+            ),
+        };
+        put_u32(out, code);
+        put_u32(out, count as u32);
+        put_padded(out, &bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// A (time, x) float variable with time unlimited, its double time
+    /// coordinate and a fixed-size (x) variable
+    fn layout() -> ClassicFile {
+        let attribute = |name: &str, value| NcAttribute {
+            name: name.to_string(),
+            value,
+        };
+        ClassicFile {
+            dimensions: vec![
+                NcDimension {
+                    name: "time".to_string(),
+                    len: 3,
+                },
+                NcDimension {
+                    name: "x".to_string(),
+                    len: 2,
+                },
+            ],
+            record_dimension: Some(0),
+            attributes: vec![attribute(
+                "title",
+                NcAttributeValue::Text("Export".to_string()),
+            )],
+            variables: vec![
+                NcVariable {
+                    name: "time".to_string(),
+                    dimensions: vec![0],
+                    nc_type: NcType::Double,
+                    attributes: vec![attribute(
+                        "units",
+                        NcAttributeValue::Text("hours since 2000-01-01".to_string()),
+                    )],
+                },
+                NcVariable {
+                    name: "mask".to_string(),
+                    dimensions: vec![1],
+                    nc_type: NcType::Float,
+                    attributes: Vec::new(),
+                },
+                NcVariable {
+                    name: "t2m".to_string(),
+                    dimensions: vec![0, 1],
+                    nc_type: NcType::Float,
+                    attributes: vec![
+                        attribute("_FillValue", NcAttributeValue::Floats(vec![-999.0])),
+                        attribute("keepbits", NcAttributeValue::Ints(vec![7])),
+                    ],
+                },
+            ],
+        }
+    }
+
+    /// The file of [`layout`], written chunk by chunk
+    fn write(file: &ClassicFile) -> Vec<u8> {
+        let mut bytes = file.header().unwrap();
+        for chunk in file.chunks() {
+            bytes.extend(match chunk.variable {
+                0 => encode_doubles([chunk.step as f64 * 6.0]),
+                1 => encode_floats([1.0, 0.0]),
+                _ => encode_floats((0..2).map(|x| (chunk.step * 10 + x) as f32)),
+            });
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_chunk_order() {
+        let file = layout();
+        let chunks: Vec<(usize, usize)> = file.chunks().map(|c| (c.variable, c.step)).collect();
+        assert_eq!(
+            chunks,
+            vec![(1, 0), (0, 0), (2, 0), (0, 1), (2, 1), (0, 2), (2, 2)]
+        );
+        assert_eq!(file.chunk_len(2), 2);
+        assert_eq!(
+            file.chunk_size(Chunk {
+                variable: 0,
+                step: 1
+            }),
+            8
+        );
+        assert_eq!(
+            file.chunk_size(Chunk {
+                variable: 2,
+                step: 1
+            }),
+            8
+        );
+        assert!(file.is_record(0) && !file.is_record(1));
+    }
+
+    #[test]
+    fn test_written_file_reads_back() {
+        let file = layout();
+        let bytes = write(&file);
+        assert_eq!(&bytes[..4], b"CDF\x02");
+        assert_eq!(bytes.len() as u64, file.file_size().unwrap());
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("export.nc");
+        std::fs::write(&path, &bytes).unwrap();
+        let nc = netcdf::open(&path).unwrap();
+        assert!(nc.dimension("time").unwrap().is_unlimited());
+        let values = |name: &str| nc.variable(name).unwrap().get_values::<f64, _>(..).unwrap();
+        assert_eq!(values("time"), vec![0.0, 6.0, 12.0]);
+        assert_eq!(values("mask"), vec![1.0, 0.0]);
+        assert_eq!(values("t2m"), vec![0.0, 1.0, 10.0, 11.0, 20.0, 21.0]);
+        let units: String = nc
+            .variable("time")
+            .unwrap()
+            .attribute_value("units")
+            .unwrap()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(units, "hours since 2000-01-01");
+        let keepbits = nc
+            .variable("t2m")
+            .unwrap()
+            .attribute_value("keepbits")
+            .unwrap()
+            .unwrap();
+        assert!(matches!(keepbits, netcdf::AttributeValue::Int(7)));
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        let mut file = layout();
+        file.variables[2].name = "group/t2m".to_string();
+        assert!(file.header().is_err());
+
+        let mut file = layout();
+        file.variables[2].dimensions = vec![1, 0];
+        assert!(file.header().is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Packed 16-bit variables kept packed in memory.
+//!
+//! Reanalyses and model archives commonly store fields as `short` integers
+//! with `scale_factor` and `add_offset` attributes. Every variable is held as
+//! `f32` by default, which doubles the memory of such fields. With
+//! `data.keep_packed`, they are held as the `i16` values of the file instead,
+//! halving their memory, and converted to `f32` when read. The read paths
+//! that take part of a variable (horizontal slices for `/image` and tiles,
+//! the fields of `/stats` and the like, `/point` and `/data` selections)
+//! convert only the values they take; others convert the whole variable.
+//!
+//! The converted values are the raw values of the file, as held for any
+//! other variable, so responses are the same either way: `scale_factor` and
+//! `add_offset` are applied where they are for every variable, e.g. when
+//! `/data` serializes values. The price is the conversion on every read,
+//! measured against the memory saved by `benches/packed_variables.rs`.
+
+use ndarray::{Array, ArrayView, Axis, CowArray, IxDyn};
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::error::{Result, RossbyError};
+use crate::lazy::LazyVariable;
+use crate::query::{select_values, select_values_with};
+use crate::state::Metadata;
+
+/// Attributes marking a variable as packed
+const PACKING_ATTRIBUTES: [&str; 2] = ["scale_factor", "add_offset"];
+
+/// The values of a variable as held by the server
+#[derive(Debug, Clone, Copy)]
+pub enum VariableValues<'a> {
+    /// Values held as `f32`
+    Unpacked(&'a Array<f32, IxDyn>),
+    /// Packed values held as the `i16` of the file
+    Packed(&'a Array<i16, IxDyn>),
+    /// Values read from the file on demand (see [`crate::lazy`])
+    Lazy(&'a LazyVariable),
+}
+
+impl<'a> VariableValues<'a> {
+    /// Shape of the variable
+    pub fn shape(&self) -> &'a [usize] {
+        match *self {
+            VariableValues::Unpacked(array) => array.shape(),
+            VariableValues::Packed(array) => array.shape(),
+            VariableValues::Lazy(var) => var.shape(),
+        }
+    }
+
+    /// Number of values
+    pub fn len(&self) -> usize {
+        self.shape().iter().product()
+    }
+
+    /// Whether the variable has no values
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes the values take in memory, none for values read on demand
+    pub fn memory_bytes(&self) -> usize {
+        match self {
+            VariableValues::Unpacked(array) => array.len() * std::mem::size_of::<f32>(),
+            VariableValues::Packed(array) => array.len() * std::mem::size_of::<i16>(),
+            VariableValues::Lazy(_) => 0,
+        }
+    }
+
+    /// The value at an index, if it is within the shape
+    pub fn get(&self, index: &[usize]) -> Option<f32> {
+        match self {
+            VariableValues::Unpacked(array) => array.get(index).copied(),
+            VariableValues::Packed(array) => array.get(index).map(|&value| f32::from(value)),
+            VariableValues::Lazy(var) => var.get(index),
+        }
+    }
+
+    /// All values in logical order
+    pub fn iter(&self) -> Box<dyn Iterator<Item = f32> + 'a> {
+        match *self {
+            VariableValues::Unpacked(array) => Box::new(array.iter().copied()),
+            VariableValues::Packed(array) => Box::new(array.iter().map(|&value| f32::from(value))),
+            VariableValues::Lazy(var) => Box::new(var.iter()),
+        }
+    }
+
+    /// All values as `f32`, borrowed unless they are packed or read on demand
+    pub fn to_f32(&self) -> CowArray<'a, f32, IxDyn> {
+        match *self {
+            VariableValues::Unpacked(array) => CowArray::from(array.view()),
+            VariableValues::Packed(array) => CowArray::from(unpack(array.view())),
+            VariableValues::Lazy(var) => CowArray::from(var.to_array()),
+        }
+    }
+
+    /// The values with the given axes removed at the given indices
+    ///
+    /// Only the remaining values of packed variables are converted, and only
+    /// those of variables read on demand are read. Indices must be within
+    /// the shape.
+    pub fn index_axes(&self, indices: &[(usize, usize)]) -> CowArray<'a, f32, IxDyn> {
+        // From the highest axis down, so the positions of lower axes stay valid
+        let mut indices = indices.to_vec();
+        indices.sort_unstable_by_key(|&(axis, _)| std::cmp::Reverse(axis));
+        match *self {
+            VariableValues::Unpacked(array) => CowArray::from(index_axes(array.view(), &indices)),
+            VariableValues::Packed(array) => {
+                CowArray::from(unpack(index_axes(array.view(), &indices)))
+            }
+            VariableValues::Lazy(var) => CowArray::from(var.index_axes(&indices)),
+        }
+    }
+
+    /// The values at the selected indices of each dimension (see
+    /// [`select_values`]), converting or reading only those
+    pub fn select(
+        &self,
+        dimensions: &[String],
+        selected_indices: &HashMap<String, Vec<usize>>,
+    ) -> Array<f32, IxDyn> {
+        match self {
+            VariableValues::Unpacked(array) => {
+                select_values(array.view(), dimensions, selected_indices)
+            }
+            VariableValues::Packed(array) => {
+                select_values_with(array.view(), dimensions, selected_indices, |&v| {
+                    f32::from(v)
+                })
+            }
+            VariableValues::Lazy(var) => var.select(dimensions, selected_indices),
+        }
+    }
+}
+
+fn index_axes<'a, A>(
+    mut view: ArrayView<'a, A, IxDyn>,
+    indices: &[(usize, usize)],
+) -> ArrayView<'a, A, IxDyn> {
+    for &(axis, index) in indices {
+        view = view.index_axis_move(Axis(axis), index);
+    }
+    view
+}
+
+/// Convert packed values to `f32`
+pub fn unpack(values: ArrayView<'_, i16, IxDyn>) -> Array<f32, IxDyn> {
+    values.mapv(f32::from)
+}
+
+/// Whether a variable carries packing attributes
+pub fn is_packed(metadata: &Metadata, name: &str) -> bool {
+    metadata.variables.get(name).is_some_and(|var| {
+        !metadata.coordinates.contains_key(name)
+            && PACKING_ATTRIBUTES
+                .iter()
+                .any(|key| var.attributes.contains_key(*key))
+    })
+}
+
+/// Move the packed variables of a loaded file to `i16` arrays
+///
+/// Variables are only moved when every value converts back exactly, as the
+/// integers read from `short` variables do, so the values served do not
+/// change.
+pub fn pack_variables(
+    metadata: &Metadata,
+    data: &mut HashMap<String, Array<f32, IxDyn>>,
+) -> HashMap<String, Array<i16, IxDyn>> {
+    let mut names: Vec<String> = data
+        .keys()
+        .filter(|name| is_packed(metadata, name))
+        .cloned()
+        .collect();
+    names.sort();
+
+    let mut packed = HashMap::new();
+    for name in names {
+        let Some(values) = data.get(&name).and_then(|array| pack(array).ok()) else {
+            continue;
+        };
+        data.remove(&name);
+        info!(
+            var = %name,
+            saved_bytes = values.len() * (std::mem::size_of::<f32>() - std::mem::size_of::<i16>()),
+            "Keeping packed variable as 16-bit integers"
+        );
+        packed.insert(name, values);
+    }
+    packed
+}
+
+/// Convert values to `i16`, failing unless every value converts back exactly
+pub fn pack(values: &Array<f32, IxDyn>) -> Result<Array<i16, IxDyn>> {
+    let mut packed = Vec::with_capacity(values.len());
+    for &value in values.iter() {
+        let integer = value as i16;
+        if f32::from(integer) != value {
+            return Err(RossbyError::Conversion {
+                message: format!("{} is not a 16-bit integer", value),
+            });
+        }
+        packed.push(integer);
+    }
+    Ok(Array::from_shape_vec(values.raw_dim(), packed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AttributeValue, Variable};
+
+    fn create_test_metadata() -> Metadata {
+        let variable = |attributes: HashMap<String, AttributeValue>| Variable {
+            name: String::new(),
+            dimensions: vec!["time".to_string(), "x".to_string()],
+            shape: vec![2, 3],
+            attributes,
+            dtype: "Basic(Short)".to_string(),
+        };
+        let packing = HashMap::from([
+            ("scale_factor".to_string(), AttributeValue::Number(0.01)),
+            ("add_offset".to_string(), AttributeValue::Number(273.15)),
+        ]);
+        Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::new(),
+            variables: HashMap::from([
+                ("t2m".to_string(), variable(packing.clone())),
+                ("sst".to_string(), variable(packing)),
+                ("mask".to_string(), variable(HashMap::new())),
+            ]),
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_pack_variables() {
+        let metadata = create_test_metadata();
+        let values = |values: Vec<f32>| Array::from_shape_vec(IxDyn(&[2, 3]), values).unwrap();
+        let mut data = HashMap::from([
+            (
+                "t2m".to_string(),
+                values(vec![-32767.0, -1.0, 0.0, 1.0, 2.0, 32767.0]),
+            ),
+            // Not integers, so kept as they are
+            (
+                "sst".to_string(),
+                values(vec![0.5, 1.0, 2.0, 3.0, 4.0, 5.0]),
+            ),
+            ("mask".to_string(), values(vec![0.0; 6])),
+        ]);
+        let original = data["t2m"].clone();
+
+        let packed = pack_variables(&metadata, &mut data);
+        assert_eq!(packed.keys().collect::<Vec<_>>(), ["t2m"]);
+        assert!(!data.contains_key("t2m"));
+        assert!(data.contains_key("sst") && data.contains_key("mask"));
+
+        let values = VariableValues::Packed(&packed["t2m"]);
+        assert_eq!(values.memory_bytes(), 12);
+        assert_eq!(values.to_f32().to_owned(), original);
+        assert_eq!(
+            values.iter().collect::<Vec<_>>(),
+            original.iter().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(values.get(&[1, 2]), Some(32767.0));
+        assert_eq!(values.get(&[2, 0]), None);
+    }
+
+    #[test]
+    fn test_read_packed_values() {
+        let original =
+            Array::from_shape_vec(IxDyn(&[2, 3]), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        let packed = pack(&original).unwrap();
+        let (packed, unpacked) = (
+            VariableValues::Packed(&packed),
+            VariableValues::Unpacked(&original),
+        );
+
+        // Both read the same values, whatever part is read
+        for values in [packed, unpacked] {
+            let row = values.index_axes(&[(0, 1)]);
+            assert_eq!(row.as_slice().unwrap(), [4.0, 5.0, 6.0]);
+            let cell = values.index_axes(&[(0, 1), (1, 2)]);
+            assert_eq!(cell.iter().copied().collect::<Vec<_>>(), [6.0]);
+
+            let dimensions = ["time".to_string(), "x".to_string()];
+            let selection = HashMap::from([("x".to_string(), vec![0, 2])]);
+            let selected = values.select(&dimensions, &selection);
+            assert_eq!(selected.shape(), [2, 2]);
+            assert_eq!(
+                selected.iter().copied().collect::<Vec<_>>(),
+                [1.0, 3.0, 4.0, 6.0]
+            );
+        }
+
+        assert!(pack(&Array::from_elem(IxDyn(&[1]), 40000.0)).is_err());
+        assert!(pack(&Array::from_elem(IxDyn(&[1]), f32::NAN)).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Multi-variable panel images.
+//!
+//! `/panel` renders several variables with the `/image` code and arranges
+//! them in a grid on one PNG, each with its own title and a color bar
+//! labeled with the ends of its color range, e.g. for a daily briefing image
+//! that would otherwise be assembled from many `/image` calls.
+
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use crate::colormaps::graticule::{draw_text, text_width, GLYPH_HEIGHT};
+use crate::colormaps::Colormap;
+
+/// Space between panels and around the grid in pixels
+pub const PANEL_GAP: u32 = 8;
+
+/// Height of the color bar under each panel
+const BAR_HEIGHT: u32 = 10;
+
+/// Scale of the legend labels
+const LABEL_SCALE: u32 = 2;
+
+/// Space between the color bar and its labels
+const LABEL_GAP: u32 = 4;
+
+/// Height of the legend under each panel
+pub const LEGEND_HEIGHT: u32 = PANEL_GAP + BAR_HEIGHT + LABEL_GAP + GLYPH_HEIGHT * LABEL_SCALE;
+
+/// Color behind the panels
+const BACKGROUND: [u8; 4] = [255, 255, 255, 255];
+
+/// Color of the legend labels
+const LABEL_COLOR: [u8; 4] = [0, 0, 0, 255];
+
+/// A rendered variable with the legend drawn under it
+pub struct Panel<'a> {
+    /// The rendered image, with its title
+    pub image: RgbaImage,
+    /// Colormap of the image
+    pub colormap: &'a dyn Colormap,
+    /// Values mapped to the ends of the colormap
+    pub value_range: (f64, f64),
+    /// Units shown between the range labels
+    pub units: Option<String>,
+}
+
+/// Arrange panels in rows of `cols`, each above its legend
+///
+/// Every cell of the grid is as large as the largest panel; panels are
+/// drawn at the top-left corner of their cell.
+pub fn compose(panels: &[Panel], cols: usize) -> RgbaImage {
+    let cols = cols.clamp(1, panels.len().max(1));
+    let rows = panels.len().div_ceil(cols);
+    let cell_width = panels.iter().map(|p| p.image.width()).max().unwrap_or(0);
+    let cell_height = panels.iter().map(|p| p.image.height()).max().unwrap_or(0) + LEGEND_HEIGHT;
+
+    let width = cols as u32 * (cell_width + PANEL_GAP) + PANEL_GAP;
+    let height = rows as u32 * (cell_height + PANEL_GAP) + PANEL_GAP;
+    let mut img = ImageBuffer::from_pixel(width, height, Rgba(BACKGROUND));
+    for (i, panel) in panels.iter().enumerate() {
+        let x = PANEL_GAP + (i % cols) as u32 * (cell_width + PANEL_GAP);
+        let y = PANEL_GAP + (i / cols) as u32 * (cell_height + PANEL_GAP);
+        image::imageops::overlay(&mut img, &panel.image, x as i64, y as i64);
+        draw_legend(&mut img, panel, x, y + panel.image.height());
+    }
+    img
+}
+
+/// Draw the color bar of a panel with its top-left corner at (x, y), the
+/// minimum labeled on the left, the maximum on the right and the units in
+/// the middle
+fn draw_legend(img: &mut RgbaImage, panel: &Panel, x: u32, y: u32) {
+    let width = panel.image.width();
+    let (min, max) = panel.value_range;
+    let bar_top = y + PANEL_GAP;
+    for dx in 0..width {
+        let fraction = if width > 1 {
+            dx as f32 / (width - 1) as f32
+        } else {
+            0.5
+        };
+        let color = Rgba(panel.colormap.map_normalized(fraction));
+        for dy in 0..BAR_HEIGHT {
+            img.put_pixel(x + dx, bar_top + dy, color);
+        }
+    }
+
+    let label_top = (bar_top + BAR_HEIGHT + LABEL_GAP) as i64;
+    let min_label = format_value(min);
+    let max_label = format_value(max);
+    let max_width = text_width(&max_label, LABEL_SCALE);
+    draw_text(
+        img,
+        &min_label,
+        x as i64,
+        label_top,
+        LABEL_SCALE,
+        LABEL_COLOR,
+    );
+    draw_text(
+        img,
+        &max_label,
+        (x + width) as i64 - max_width as i64,
+        label_top,
+        LABEL_SCALE,
+        LABEL_COLOR,
+    );
+
+    // Units go between the labels, if there is room for them
+    if let Some(units) = panel.units.as_deref().filter(|u| !u.trim().is_empty()) {
+        let units_width = text_width(units, LABEL_SCALE);
+        let free = text_width(&min_label, LABEL_SCALE) + max_width + 2 * PANEL_GAP;
+        if units_width + free <= width {
+            let left = x + (width - units_width) / 2;
+            draw_text(img, units, left as i64, label_top, LABEL_SCALE, LABEL_COLOR);
+        }
+    }
+}
+
+/// Legend label of a value, with four significant digits
+pub fn format_value(value: f64) -> String {
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-3..1e6).contains(&magnitude) {
+        return format!("{:.2e}", value);
+    }
+    let decimals = if magnitude == 0.0 {
+        0
+    } else {
+        (3 - magnitude.log10().floor() as i32).max(0) as usize
+    };
+    let label = format!("{:.*}", decimals, value);
+    // Trailing zeros carry no information on a legend
+    if label.contains('.') {
+        label
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colormaps::Viridis;
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(273.15), "273.1");
+        assert_eq!(format_value(0.0), "0");
+        assert_eq!(format_value(-12.5), "-12.5");
+        assert_eq!(format_value(1013.0), "1013");
+        assert_eq!(format_value(0.0012341), "0.001234");
+        assert_eq!(format_value(101325.0), "101325");
+        assert_eq!(format_value(2.5e7), "2.50e7");
+    }
+
+    #[test]
+    fn test_compose() {
+        let panel = |color: [u8; 4]| Panel {
+            image: ImageBuffer::from_pixel(40, 30, Rgba(color)),
+            colormap: &Viridis,
+            value_range: (0.0, 10.0),
+            units: Some("K".to_string()),
+        };
+        let panels = [
+            panel([255, 0, 0, 255]),
+            panel([0, 255, 0, 255]),
+            panel([0, 0, 255, 255]),
+        ];
+        let img = compose(&panels, 2);
+
+        let cell_height = 30 + LEGEND_HEIGHT;
+        assert_eq!(img.width(), 2 * (40 + PANEL_GAP) + PANEL_GAP);
+        assert_eq!(img.height(), 2 * (cell_height + PANEL_GAP) + PANEL_GAP);
+
+        // Panels fill the grid row by row, the last cell stays empty
+        assert_eq!(img.get_pixel(PANEL_GAP, PANEL_GAP).0, [255, 0, 0, 255]);
+        assert_eq!(
+            img.get_pixel(2 * PANEL_GAP + 40, PANEL_GAP).0,
+            [0, 255, 0, 255]
+        );
+        let second_row = 2 * PANEL_GAP + cell_height;
+        assert_eq!(img.get_pixel(PANEL_GAP, second_row).0, [0, 0, 255, 255]);
+        assert_eq!(img.get_pixel(2 * PANEL_GAP + 40, second_row).0, BACKGROUND);
+
+        // The color bar runs from the low to the high end of the colormap
+        let bar = PANEL_GAP + 30 + PANEL_GAP;
+        assert_eq!(img.get_pixel(PANEL_GAP, bar).0, Viridis.map_normalized(0.0));
+        assert_eq!(
+            img.get_pixel(PANEL_GAP + 39, bar).0,
+            Viridis.map_normalized(1.0)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Error-tolerant multi-variable responses.
+//!
+//! By default a `/point` or `/data` request fails as a whole when any of its
+//! variables fails, e.g. because a variable is missing or lacks a selected
+//! dimension. With `partial=true` the failing variables are left out and
+//! reported per variable in an `errors` section instead:
+//!
+//! ```json
+//! "errors": {
+//!   "t850": {"kind": "variable_not_found", "error": "Variable not found: t850"}
+//! }
+//! ```
+//!
+//! A request whose variables all fail still fails, with the error of the
+//! first variable. Errors not tied to a variable, such as invalid
+//! coordinates or selectors, always fail the request.
+
+use crate::error::RossbyError;
+
+/// Failures of individual variables in a partial response
+#[derive(Debug, Default)]
+pub struct VariableErrors {
+    entries: Vec<(String, RossbyError)>,
+}
+
+impl VariableErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the failure of a variable
+    pub fn push(&mut self, var_name: impl Into<String>, error: RossbyError) {
+        self.entries.push((var_name.into(), error));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Error of the whole request when no variable succeeded
+    pub fn into_first_error(self) -> Option<RossbyError> {
+        self.entries.into_iter().next().map(|(_, error)| error)
+    }
+
+    /// The `errors` section, keyed by variable name
+    pub fn to_json(&self) -> serde_json::Value {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(var_name, error)| {
+                (
+                    var_name.clone(),
+                    serde_json::json!({
+                        "kind": error.kind(),
+                        "error": error.to_string(),
+                    }),
+                )
+            })
+            .collect();
+        serde_json::Value::Object(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variable_errors() {
+        let mut errors = VariableErrors::new();
+        assert!(errors.is_empty());
+        assert_eq!(errors.to_json(), serde_json::json!({}));
+
+        errors.push(
+            "t850",
+            RossbyError::VariableNotFound {
+                name: "t850".to_string(),
+            },
+        );
+        errors.push(
+            "sst",
+            RossbyError::DataNotFound {
+                message: "Variable sst does not have a lat dimension".to_string(),
+            },
+        );
+        assert_eq!(errors.len(), 2);
+
+        let json = errors.to_json();
+        assert_eq!(json["t850"]["kind"], "variable_not_found");
+        assert_eq!(json["t850"]["error"], "Variable not found: t850");
+        assert_eq!(json["sst"]["kind"], "data_not_found");
+
+        assert!(matches!(
+            errors.into_first_error(),
+            Some(RossbyError::VariableNotFound { .. })
+        ));
+    }
+}
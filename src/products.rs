@@ -0,0 +1,357 @@
+//! Named query templates ("products").
+//!
+//! Operators define products in the `data.products` config section, each a
+//! set of query parameters such as the variable, bounding box, colormap and
+//! image size. A request carrying `product=<name>` has the template merged
+//! into its query before it reaches the handler, so URLs for operational
+//! products stay stable while their styling evolves. Parameters given in the
+//! request take precedence over the template.
+//!
+//! Physical dimension values may also be given as `latest` or `earliest`,
+//! which resolve to the largest or smallest coordinate value of that
+//! dimension, e.g. `/image?product=europe_t2m_map&time=latest`. Values and
+//! `<dim>_range` bounds of time dimensions may be given as ISO 8601 dates,
+//! e.g. `time=2023-06-01T12:00:00Z` or `time_range=2023-06-01,2023-06-30`,
+//! which are converted to coordinate values with the CF units and calendar of
+//! the time coordinate.
+
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+use crate::cf_time::parse_datetime;
+use crate::error::{Result, RossbyError};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::schema::SCHEMA_VERSION;
+use crate::state::AppState;
+
+/// Query parameter naming a product
+pub const PRODUCT_PARAM: &str = "product";
+
+/// Dimension value resolving to the largest coordinate value
+const LATEST: &str = "latest";
+
+/// Dimension value resolving to the smallest coordinate value
+const EARLIEST: &str = "earliest";
+
+/// Query parameter value for a template entry, if it is a scalar
+pub fn template_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Expand `product`, `latest`/`earliest` and dates in a query string
+///
+/// Returns `None` when the query uses none of them and can be passed on
+/// unchanged.
+/// Queries that are not valid form encoding are also left for the handler to
+/// reject.
+pub fn expand_query(state: &AppState, query: &str) -> Result<Option<String>> {
+    let params: Vec<(String, String)> = match serde_urlencoded::from_str(query) {
+        Ok(params) => params,
+        Err(_) => return Ok(None),
+    };
+
+    let product = params
+        .iter()
+        .find(|(key, _)| key == PRODUCT_PARAM)
+        .map(|(_, value)| value.clone());
+    let has_keyword = params
+        .iter()
+        .any(|(_, value)| value == LATEST || value == EARLIEST);
+    let has_date = params
+        .iter()
+        .any(|(_, value)| value.split(',').any(|part| parse_datetime(part).is_some()));
+    if product.is_none() && !has_keyword && !has_date {
+        return Ok(None);
+    }
+
+    let mut expanded = Vec::with_capacity(params.len());
+    if let Some(name) = &product {
+        let template = state.config.data.products.get(name).ok_or_else(|| {
+            let mut defined: Vec<&String> = state.config.data.products.keys().collect();
+            defined.sort();
+            RossbyError::InvalidParameter {
+                param: PRODUCT_PARAM.to_string(),
+                message: format!(
+                    "Unknown product '{}'. Defined products: {:?}",
+                    name, defined
+                ),
+            }
+        })?;
+
+        // Sort so that the expanded query is deterministic
+        let mut entries: Vec<_> = template.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        for (key, value) in entries {
+            if params.iter().any(|(param, _)| param == key) {
+                continue;
+            }
+            if let Some(value) = template_value(value) {
+                expanded.push((key.clone(), value));
+            }
+        }
+    }
+    expanded.extend(params.into_iter().filter(|(key, _)| key != PRODUCT_PARAM));
+
+    for (key, value) in &mut expanded {
+        if value != LATEST && value != EARLIEST {
+            continue;
+        }
+        // Only physical dimension values are resolved; anything else is left
+        // for the handler to interpret
+        if let Ok(dimension) = state.resolve_dimension(key) {
+            let (min, max) = state.coordinate_bounds(dimension)?;
+            *value = if value == LATEST { max } else { min }.to_string();
+        }
+    }
+
+    for (key, value) in &mut expanded {
+        if let Some(converted) = convert_dates(state, key, value)? {
+            *value = converted;
+        }
+    }
+
+    serde_urlencoded::to_string(&expanded)
+        .map(Some)
+        .map_err(|e| RossbyError::Server {
+            message: format!("Failed to encode expanded query: {}", e),
+        })
+}
+
+/// Convert the ISO 8601 dates in a `<dim>` or `<dim>_range` value to
+/// coordinate values, if any
+///
+/// Each comma-separated part is converted on its own, so lists of values and
+/// the bounds of a range may mix dates and numbers, and a range step is kept.
+fn convert_dates(state: &AppState, key: &str, value: &str) -> Result<Option<String>> {
+    if !value.split(',').any(|part| parse_datetime(part).is_some()) {
+        return Ok(None);
+    }
+    let dimension = match state.resolve_dimension(key) {
+        Ok(dimension) => dimension,
+        Err(_) => match key
+            .strip_suffix("_range")
+            .and_then(|name| state.resolve_dimension(name).ok())
+        {
+            Some(dimension) => dimension,
+            // Not a dimension selector; left for the handler to interpret
+            None => return Ok(None),
+        },
+    };
+
+    let mut parts = Vec::new();
+    for part in value.split(',') {
+        let converted = state
+            .time_coordinate_value(dimension, part)
+            .map_err(|e| match e {
+                RossbyError::InvalidParameter { message, .. } => RossbyError::InvalidParameter {
+                    param: key.to_string(),
+                    message,
+                },
+                other => other,
+            })?;
+        parts.push(match converted {
+            Some(number) => number.to_string(),
+            None => part.to_string(),
+        });
+    }
+    Ok(Some(parts.join(",")))
+}
+
+/// Rewrite requests that name a product, a `latest`/`earliest` value or a date
+pub async fn product_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let query = match request.uri().query() {
+        Some(query) => query,
+        None => return next.run(request).await,
+    };
+
+    match expand_query(&state, query) {
+        Ok(None) => next.run(request).await,
+        Ok(Some(expanded)) => {
+            let path = request.uri().path();
+            match format!("{}?{}", path, expanded).parse::<Uri>() {
+                Ok(uri) => {
+                    *request.uri_mut() = uri;
+                    next.run(request).await
+                }
+                Err(e) => product_error_response(
+                    RossbyError::Server {
+                        message: format!("Failed to rewrite request URI: {}", e),
+                    },
+                    request.uri().path(),
+                ),
+            }
+        }
+        Err(error) => product_error_response(error, request.uri().path()),
+    }
+}
+
+/// Build the error response for a query that could not be expanded
+fn product_error_response(error: RossbyError, endpoint: &str) -> Response {
+    let request_id = generate_request_id();
+    log_request_error(&error, endpoint, &request_id, None);
+
+    let status = match &error {
+        RossbyError::Server { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    (
+        status,
+        Json(serde_json::json!({
+            "error": error.to_string(),
+            "request_id": request_id,
+            "schema_version": SCHEMA_VERSION
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{AttributeValue, Dimension, Metadata, Variable};
+    use std::collections::HashMap;
+
+    fn create_test_state() -> AppState {
+        let mut config = Config::default();
+        config.data.products.insert(
+            "europe_t2m_map".to_string(),
+            HashMap::from([
+                ("var".to_string(), serde_json::json!("t2m")),
+                ("bbox".to_string(), serde_json::json!("-25,34,45,72")),
+                ("width".to_string(), serde_json::json!(1024)),
+                ("grid".to_string(), serde_json::json!(true)),
+            ]),
+        );
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::from([(
+                "time".to_string(),
+                Dimension {
+                    name: "time".to_string(),
+                    size: 3,
+                    is_unlimited: true,
+                },
+            )]),
+            variables: HashMap::from([(
+                "time".to_string(),
+                Variable {
+                    name: "time".to_string(),
+                    dimensions: vec!["time".to_string()],
+                    shape: vec![3],
+                    attributes: HashMap::from([(
+                        "units".to_string(),
+                        AttributeValue::Text("hours since 2000-01-01".to_string()),
+                    )]),
+                    dtype: "f64".to_string(),
+                },
+            )]),
+            coordinates: HashMap::from([("time".to_string(), vec![0.0, 6.0, 12.5])]),
+            groups: HashMap::new(),
+        };
+
+        AppState::new(config, metadata, HashMap::new())
+    }
+
+    fn expand(state: &AppState, query: &str) -> Option<Vec<(String, String)>> {
+        expand_query(state, query)
+            .unwrap()
+            .map(|q| serde_urlencoded::from_str(&q).unwrap())
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_product() {
+        let state = create_test_state();
+
+        assert_eq!(expand(&state, "var=t2m&time=6"), None);
+
+        assert_eq!(
+            expand(&state, "product=europe_t2m_map&width=512&time=latest"),
+            Some(pairs(&[
+                ("bbox", "-25,34,45,72"),
+                ("grid", "true"),
+                ("var", "t2m"),
+                ("width", "512"),
+                ("time", "12.5"),
+            ]))
+        );
+
+        assert_eq!(
+            expand(&state, "var=t2m&time=earliest"),
+            Some(pairs(&[("var", "t2m"), ("time", "0")]))
+        );
+
+        // Only dimension values are resolved
+        assert_eq!(
+            expand(&state, "var=latest&time=6&product=europe_t2m_map"),
+            Some(pairs(&[
+                ("bbox", "-25,34,45,72"),
+                ("grid", "true"),
+                ("width", "1024"),
+                ("var", "latest"),
+                ("time", "6"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_expand_dates() {
+        let state = create_test_state();
+
+        assert_eq!(
+            expand(&state, "var=t2m&time=2000-01-01T06:00:00Z"),
+            Some(pairs(&[("var", "t2m"), ("time", "6")]))
+        );
+        assert_eq!(
+            expand(&state, "time_range=2000-01-01,2000-01-01T12:30:00+00:00,2"),
+            Some(pairs(&[("time_range", "0,12.5,2")]))
+        );
+
+        // Dates in other parameters are left for the handler
+        assert_eq!(
+            expand(&state, "label=2000-01-01"),
+            Some(pairs(&[("label", "2000-01-01")]))
+        );
+
+        // Dimensions without time units cannot be selected by date
+        let mut state = state;
+        state.metadata.variables.clear();
+        let err = expand_query(&state, "time=2000-01-01").unwrap_err();
+        assert!(matches!(
+            err,
+            RossbyError::InvalidParameter { ref param, .. } if param == "time"
+        ));
+    }
+
+    #[test]
+    fn test_unknown_product() {
+        let state = create_test_state();
+        let err = expand_query(&state, "product=asia_map").unwrap_err();
+        assert!(matches!(err, RossbyError::InvalidParameter { .. }));
+        assert!(err.to_string().contains("europe_t2m_map"));
+    }
+}
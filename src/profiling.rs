@@ -0,0 +1,356 @@
+//! Per-request stage profiling.
+//!
+//! With `--profile` (or `server.profile.enabled`), a sample of requests runs
+//! inside a root `request` span. Handlers open a span for each stage of their
+//! work (`extract`, `interpolate`, `render`, `encode`, `serialize`, ...), and
+//! `ProfileLayer` accumulates the time spent in each of them. When the request
+//! completes, the breakdown is logged under the `rossby::profile` target and,
+//! if `server.profile.output_dir` is set, written as folded stacks
+//! (`<profile id>.folded`) that `inferno-flamegraph` or `flamegraph.pl` turn
+//! into a flamegraph. Profiled responses carry the profile id in
+//! `X-Profile-Id`.
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{info, warn, Instrument, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::logging::generate_request_id;
+use crate::state::AppState;
+
+/// Target of the root span and of the logged breakdown
+pub const PROFILE_TARGET: &str = "rossby::profile";
+
+/// Response header carrying the id of a profiled request
+pub const PROFILE_ID_HEADER: &str = "x-profile-id";
+
+/// Name of the root span of a profiled request
+const ROOT_SPAN: &str = "request";
+
+/// Number of requests seen by the profiling middleware, for sampling
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Profiles of completed requests, waiting to be reported by the middleware
+///
+/// Subscribers cannot emit events or do I/O from their own callbacks, so the
+/// layer hands finished profiles over here.
+static COMPLETED: Mutex<BTreeMap<String, RequestProfile>> = Mutex::new(BTreeMap::new());
+
+/// Stage timings of one completed request
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestProfile {
+    /// Profile id, also returned in `X-Profile-Id`
+    pub id: String,
+    /// Request method and path, the root frame of every stack
+    pub label: String,
+    /// Total time the request was being processed
+    pub total: Duration,
+    /// Time spent in each span name, including nested spans
+    pub stages: BTreeMap<String, Duration>,
+    /// Self time of each span stack, as `;`-separated frames
+    pub stacks: BTreeMap<String, Duration>,
+}
+
+impl RequestProfile {
+    /// Render the stacks in the folded format read by flamegraph tools
+    ///
+    /// Sample counts are microseconds.
+    pub fn folded(&self) -> String {
+        self.stacks.iter().filter(|(_, time)| !time.is_zero()).fold(
+            String::new(),
+            |mut out, (stack, time)| {
+                let _ = writeln!(out, "{} {}", stack, time.as_micros());
+                out
+            },
+        )
+    }
+
+    /// One-line summary of the stage timings, e.g. `extract=1.250ms render=4.000ms`
+    pub fn summary(&self) -> String {
+        self.stages
+            .iter()
+            .map(|(stage, time)| format!("{}={:.3}ms", stage, time.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Busy time of a span, tracked while it belongs to a profiled request
+#[derive(Debug, Default)]
+struct SpanTiming {
+    busy: Duration,
+    children: Duration,
+    entered_at: Option<Instant>,
+}
+
+/// Fields of the root span
+#[derive(Default)]
+struct RootFields {
+    id: String,
+    method: String,
+    path: String,
+}
+
+impl Visit for RootFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{:?}", value));
+    }
+}
+
+impl RootFields {
+    fn record(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "profile_id" => self.id = value,
+            "method" => self.method = value,
+            "path" => self.path = value,
+            _ => {}
+        }
+    }
+}
+
+/// Tracing layer timing the spans of profiled requests
+///
+/// Only spans opened inside a `request` span with the `rossby::profile`
+/// target are timed, so unsampled requests cost nothing beyond span creation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProfileLayer;
+
+impl<S> Layer<S> for ProfileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+
+        let metadata = attrs.metadata();
+        if metadata.target() == PROFILE_TARGET && metadata.name() == ROOT_SPAN {
+            let mut fields = RootFields::default();
+            attrs.record(&mut fields);
+            let mut extensions = span.extensions_mut();
+            extensions.insert(RequestProfile {
+                id: fields.id,
+                label: format!("{} {}", fields.method, fields.path),
+                ..Default::default()
+            });
+            extensions.insert(SpanTiming::default());
+            return;
+        }
+
+        let profiled = span
+            .scope()
+            .skip(1)
+            .any(|ancestor| ancestor.extensions().get::<RequestProfile>().is_some());
+        if profiled {
+            span.extensions_mut().insert(SpanTiming::default());
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                timing.entered_at = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                if let Some(entered_at) = timing.entered_at.take() {
+                    timing.busy += entered_at.elapsed();
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let timing = match span.extensions_mut().remove::<SpanTiming>() {
+            Some(timing) => timing,
+            None => return,
+        };
+        let self_time = timing.busy.saturating_sub(timing.children);
+
+        if let Some(mut profile) = span.extensions_mut().remove::<RequestProfile>() {
+            profile.total = timing.busy;
+            *profile.stacks.entry(profile.label.clone()).or_default() += self_time;
+            if let Ok(mut completed) = COMPLETED.lock() {
+                completed.insert(profile.id.clone(), profile);
+            }
+            return;
+        }
+
+        if let Some(parent) = span.parent() {
+            if let Some(parent_timing) = parent.extensions_mut().get_mut::<SpanTiming>() {
+                parent_timing.children += timing.busy;
+            }
+        }
+
+        // Frames from the request's root span down to this span
+        let mut frames: Vec<&'static str> = Vec::new();
+        for ancestor in span.scope() {
+            if let Some(profile) = ancestor.extensions_mut().get_mut::<RequestProfile>() {
+                frames.reverse();
+                let stack = format!("{};{}", profile.label, frames.join(";"));
+                *profile.stacks.entry(stack).or_default() += self_time;
+                *profile.stages.entry(span.name().to_string()).or_default() += timing.busy;
+                return;
+            }
+            frames.push(ancestor.name());
+        }
+    }
+}
+
+/// Remove and return the profile of a completed request
+pub fn take_profile(id: &str) -> Option<RequestProfile> {
+    COMPLETED.lock().ok()?.remove(id)
+}
+
+/// Whether the request numbered `counter` falls in a `sample_rate` sample
+///
+/// Spreads sampled requests evenly: a rate of 0.25 profiles every fourth.
+pub fn is_sampled(counter: u64, sample_rate: f64) -> bool {
+    ((counter + 1) as f64 * sample_rate).floor() > (counter as f64 * sample_rate).floor()
+}
+
+/// Write the folded stacks of a profile to `<dir>/<profile id>.folded`
+pub fn write_folded(dir: &Path, profile: &RequestProfile) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join(format!("{}.folded", profile.id)), profile.folded())
+}
+
+/// Run a sample of requests inside a profiled root span and report them
+pub async fn profile_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config.server.profile;
+    if !config.enabled {
+        return next.run(request).await;
+    }
+    let counter = REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed);
+    if !is_sampled(counter, config.sample_rate) {
+        return next.run(request).await;
+    }
+
+    let profile_id = generate_request_id();
+    let path = request.uri().path().to_string();
+    let span = tracing::info_span!(
+        target: PROFILE_TARGET,
+        ROOT_SPAN,
+        profile_id = %profile_id,
+        method = %request.method(),
+        path = %path,
+    );
+    let mut response = next.run(request).instrument(span).await;
+
+    // The root span has closed, so the profile is complete unless no
+    // `ProfileLayer` is installed
+    let profile = match take_profile(&profile_id) {
+        Some(profile) => profile,
+        None => return response,
+    };
+
+    info!(
+        target: PROFILE_TARGET,
+        profile_id = %profile.id,
+        endpoint = %path,
+        total_ms = profile.total.as_secs_f64() * 1000.0,
+        stages = %profile.summary(),
+        "Request profile"
+    );
+    if let Some(dir) = &config.output_dir {
+        if let Err(e) = write_folded(dir, &profile) {
+            warn!(
+                profile_id = %profile.id,
+                dir = %dir.display(),
+                error = %e,
+                "Failed to write request profile"
+            );
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&profile.id) {
+        response.headers_mut().insert(PROFILE_ID_HEADER, value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_is_sampled() {
+        assert!((0..10).all(|n| is_sampled(n, 1.0)));
+        let sampled: Vec<u64> = (0..12).filter(|&n| is_sampled(n, 0.25)).collect();
+        assert_eq!(sampled, vec![3, 7, 11]);
+    }
+
+    #[test]
+    fn test_profile_layer() {
+        let subscriber = tracing_subscriber::registry().with(ProfileLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            // Spans outside a profiled request are ignored
+            tracing::info_span!("render").in_scope(|| {});
+
+            let root = tracing::info_span!(
+                target: PROFILE_TARGET,
+                ROOT_SPAN,
+                profile_id = "test-profile",
+                method = "GET",
+                path = "/image",
+            );
+            root.in_scope(|| {
+                tracing::info_span!("extract").in_scope(|| {
+                    std::thread::sleep(Duration::from_millis(2));
+                });
+                tracing::info_span!("render").in_scope(|| {
+                    tracing::info_span!("encode").in_scope(|| {
+                        std::thread::sleep(Duration::from_millis(2));
+                    });
+                });
+            });
+        });
+
+        let profile = take_profile("test-profile").unwrap();
+        assert!(take_profile("test-profile").is_none());
+        assert_eq!(profile.label, "GET /image");
+
+        let stages: Vec<&str> = profile.stages.keys().map(String::as_str).collect();
+        assert_eq!(stages, vec!["encode", "extract", "render"]);
+        assert!(profile.stages["render"] >= profile.stages["encode"]);
+        assert!(profile.total >= profile.stages["extract"] + profile.stages["render"]);
+
+        assert!(profile.stacks["GET /image;extract"] >= Duration::from_millis(2));
+        assert!(profile.stacks["GET /image;render;encode"] >= Duration::from_millis(2));
+        let folded = profile.folded();
+        assert!(folded.contains("GET /image;render;encode "));
+        assert!(profile.summary().starts_with("encode="));
+    }
+}
@@ -0,0 +1,1465 @@
+//! Typed parsing of dimension selection query parameters.
+//!
+//! Endpoints accept free-form dimension selectors alongside their fixed
+//! parameters. This module turns those raw key/value pairs into a
+//! [`Selection`]: one validated [`Selector`] per dimension, keyed by the
+//! file-specific dimension name. Unknown or malformed parameters are rejected
+//! with an error naming the offending parameter and, where possible, the
+//! closest valid parameter name.
+//!
+//! Recognized forms, where `<dim>` is a file-specific name, a canonical alias
+//! or an underscore-prefixed canonical name:
+//!
+//! - `<dim>=<value>` or `<dim>=<v1>,<v2>,...` select by physical value (nearest match)
+//! - `<dim>_range=<start>,<end>[,<step>]` select a closed physical value range
+//! - `__<dim>_index=<i>` or `__<dim>_index=<i1>,<i2>,...` select by raw index
+//! - `__<dim>_index_range=<start>,<end>[,<step>]` select a closed raw index range
+//! - `time_index=<i>` legacy raw index on the time dimension
+//! - `valid_time=<value>` the forecast reference time and lead time valid at a
+//!   time (see [`crate::forecast_time`])
+//!
+//! The optional `<step>` of a range is a stride in grid points.
+//!
+//! Selector values are parsed from a [`SelectorInput`], which deserializes
+//! from the comma-separated text of a query string as well as from the JSON
+//! numbers and arrays of configuration and request bodies (e.g.
+//! `{"level": [500, 850], "lat_range": [30, 40, 2]}`), so both go through
+//! the same validation: [`Selection::parse`] takes query parameters and
+//! [`Selection::from_inputs`] deserialized values.
+//!
+//! A bounding box is added to a selection with [`Selection::add_bbox`]. It
+//! selects the grid points inside it on the latitude and longitude
+//! dimensions, crossing the seam of the longitude axis when its western edge
+//! lies east of its eastern edge (see [`crate::geometry`]).
+//!
+//! [`select_values`] extracts the resolved indices from a variable's data,
+//! copying only the selected values.
+//!
+//! The parameters most endpoints share are parsed here as well, so that they
+//! mean the same everywhere and fail with the same messages:
+//! [`split_variables`] splits variable lists, [`TimeParams`] resolves the
+//! single time step of `/point` and `/image`, and the [`Dataset`] and
+//! [`TimeStep`] extractors resolve the dataset (a view's or the server's) and
+//! time step of a request before its handler runs, rejecting invalid
+//! queries with a `400` JSON error.
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use ndarray::{Array, ArrayView, ArrayViewMut, Axis, IxDyn, Slice};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::error::{Result, RossbyError};
+use crate::field::{LAT_NAMES, LON_NAMES};
+use crate::forecast_time::{ForecastAxes, VALID_TIME_PARAM};
+use crate::geometry::{lon_indices, range_indices};
+use crate::logging::{generate_request_id, log_request_error};
+use crate::state::AppState;
+use crate::views::state_for_variables;
+
+/// Selection of a single dimension
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    /// A single slice by physical value (nearest match)
+    Value(f64),
+    /// Several slices by physical value (nearest match for each)
+    Values(Vec<f64>),
+    /// A closed physical value range, taking every `step`-th grid point
+    ValueRange { start: f64, end: f64, step: usize },
+    /// A single slice by raw index
+    Index(usize),
+    /// Several slices by raw index
+    Indices(Vec<usize>),
+    /// A closed raw index range, taking every `step`-th index
+    IndexRange {
+        start: usize,
+        end: usize,
+        step: usize,
+    },
+    /// The grid points within closed bounds, crossing the seam of longitude
+    /// dimensions when `min` is greater than `max`
+    Bounds { min: f64, max: f64 },
+}
+
+/// Raw value of a dimension selector: the text of a query parameter (e.g.
+/// `500,850`), or a JSON number or array of numbers (e.g. `[500, 850]`)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum SelectorInput {
+    /// A single number
+    Number(f64),
+    /// A list of numbers, e.g. the start, end and step of a range
+    Numbers(Vec<f64>),
+    /// Comma-separated text
+    Text(String),
+}
+
+impl SelectorInput {
+    /// The selector input of a JSON value: a number, an array of numbers or
+    /// text
+    pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+        SelectorInput::deserialize(value).ok()
+    }
+
+    /// The comma-separated elements of the input
+    fn parts(&self) -> Vec<Part<'_>> {
+        match self {
+            SelectorInput::Number(value) => vec![Part::Number(*value)],
+            SelectorInput::Numbers(values) => values.iter().copied().map(Part::Number).collect(),
+            SelectorInput::Text(text) => text.split(',').map(Part::Text).collect(),
+        }
+    }
+
+    /// Whether the input holds no value at all
+    fn is_empty(&self) -> bool {
+        match self {
+            SelectorInput::Number(_) => false,
+            SelectorInput::Numbers(values) => values.is_empty(),
+            SelectorInput::Text(text) => text.trim().is_empty(),
+        }
+    }
+}
+
+impl From<&str> for SelectorInput {
+    fn from(text: &str) -> Self {
+        SelectorInput::Text(text.to_string())
+    }
+}
+
+impl fmt::Display for SelectorInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectorInput::Number(value) => write!(f, "{}", value),
+            SelectorInput::Numbers(values) => {
+                let parts: Vec<String> = values.iter().map(f64::to_string).collect();
+                write!(f, "{}", parts.join(","))
+            }
+            SelectorInput::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// One element of a selector input
+#[derive(Debug, Clone, Copy)]
+enum Part<'a> {
+    Number(f64),
+    Text(&'a str),
+}
+
+impl fmt::Display for Part<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Part::Number(value) => write!(f, "{}", value),
+            Part::Text(text) => write!(f, "{}", text.trim()),
+        }
+    }
+}
+
+/// A selector together with the dimension and query parameter it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionSelection {
+    /// File-specific dimension name
+    pub dimension: String,
+    /// Query parameter that produced this selection (used in error messages)
+    pub param: String,
+    /// The parsed selector
+    pub selector: Selector,
+}
+
+impl DimensionSelection {
+    /// Resolve the selector to the raw indices it selects, in selection order
+    pub fn resolve(&self, state: &AppState) -> Result<Vec<usize>> {
+        let size = state
+            .metadata
+            .dimensions
+            .get(&self.dimension)
+            .map(|d| d.size)
+            .unwrap_or(0);
+
+        let check = |index: usize| -> Result<usize> {
+            if index >= size {
+                Err(RossbyError::IndexOutOfBounds {
+                    param: self.param.clone(),
+                    value: index.to_string(),
+                    max: size.saturating_sub(1),
+                })
+            } else {
+                Ok(index)
+            }
+        };
+
+        match &self.selector {
+            Selector::Value(value) => {
+                Ok(vec![state.find_coordinate_index(&self.dimension, *value)?])
+            }
+            Selector::Values(values) => values
+                .iter()
+                .map(|&value| state.find_coordinate_index(&self.dimension, value))
+                .collect(),
+            Selector::ValueRange { start, end, step } => {
+                let a = state.find_coordinate_index(&self.dimension, *start)?;
+                let b = state.find_coordinate_index(&self.dimension, *end)?;
+                Ok((a.min(b)..=a.max(b)).step_by(*step).collect())
+            }
+            Selector::Index(index) => Ok(vec![check(*index)?]),
+            Selector::Indices(indices) => indices.iter().map(|&i| check(i)).collect(),
+            Selector::IndexRange { start, end, step } => {
+                check(*start)?;
+                check(*end)?;
+                Ok((*start..=*end).step_by(*step).collect())
+            }
+            Selector::Bounds { min, max } => {
+                let coords = state.get_coordinate_checked(&self.dimension)?;
+                let is_longitude = LON_NAMES.iter().any(|name| {
+                    state
+                        .resolve_dimension(name)
+                        .is_ok_and(|dim| dim == self.dimension)
+                });
+                let indices = if is_longitude {
+                    lon_indices(coords, *min, *max)
+                } else {
+                    range_indices(coords, *min, *max)
+                };
+                if indices.is_empty() {
+                    return Err(RossbyError::InvalidParameter {
+                        param: self.param.clone(),
+                        message: format!(
+                            "No grid points of dimension '{}' lie within [{}, {}]",
+                            self.dimension, min, max
+                        ),
+                    });
+                }
+                Ok(indices)
+            }
+        }
+    }
+
+    /// Resolve a selector that must pick exactly one slice
+    pub fn resolve_single(&self, state: &AppState) -> Result<usize> {
+        match self.resolve(state)?.as_slice() {
+            [index] => Ok(*index),
+            _ => Err(RossbyError::InvalidParameter {
+                param: self.param.clone(),
+                message: format!(
+                    "Only a single slice of dimension '{}' can be selected here",
+                    self.dimension
+                ),
+            }),
+        }
+    }
+}
+
+/// All dimension selections of a query, keyed by file-specific dimension name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    dimensions: BTreeMap<String, DimensionSelection>,
+}
+
+impl Selection {
+    /// Parse dimension selectors from the free-form parameters of a query
+    ///
+    /// Every parameter must be a recognized selector for a dimension of the
+    /// dataset, and each dimension may be selected at most once.
+    pub fn parse(state: &AppState, params: &HashMap<String, String>) -> Result<Self> {
+        let inputs: HashMap<String, SelectorInput> = params
+            .iter()
+            .map(|(key, value)| (key.clone(), SelectorInput::from(value.as_str())))
+            .collect();
+        Self::from_inputs(state, &inputs)
+    }
+
+    /// Parse dimension selectors keyed by parameter name, e.g. deserialized
+    /// from a JSON object
+    ///
+    /// The parameter names and rules are those of [`Selection::parse`].
+    pub fn from_inputs(state: &AppState, params: &HashMap<String, SelectorInput>) -> Result<Self> {
+        let mut selection = Selection::default();
+
+        // Sort so that errors are reported deterministically
+        let mut keys: Vec<&String> = params.keys().collect();
+        keys.sort();
+
+        // A valid time pins the forecast axes left unselected by the others
+        let valid_time = keys
+            .iter()
+            .position(|key| *key == VALID_TIME_PARAM && state.resolve_dimension(key).is_err())
+            .map(|position| keys.remove(position));
+
+        for key in keys {
+            let (dimension, selector) = parse_parameter(state, key, &params[key])?;
+
+            if let Some(existing) = selection.dimensions.get(&dimension) {
+                return Err(RossbyError::InvalidParameter {
+                    param: key.clone(),
+                    message: format!(
+                        "Dimension '{}' is already selected by '{}'",
+                        dimension, existing.param
+                    ),
+                });
+            }
+            selection.dimensions.insert(
+                dimension.clone(),
+                DimensionSelection {
+                    dimension,
+                    param: key.clone(),
+                    selector,
+                },
+            );
+        }
+
+        if let Some(key) = valid_time {
+            selection.add_valid_time(state, &params[key])?;
+        }
+
+        Ok(selection)
+    }
+
+    /// Select the forecast reference time and lead time valid at a time
+    fn add_valid_time(&mut self, state: &AppState, value: &SelectorInput) -> Result<()> {
+        let axes = ForecastAxes::detect(state).ok_or_else(|| RossbyError::InvalidParameter {
+            param: VALID_TIME_PARAM.to_string(),
+            message: "The dataset has no forecast reference time and lead time dimensions"
+                .to_string(),
+        })?;
+        let valid_time = parse_single::<f64>(VALID_TIME_PARAM, value, "a number")?;
+        let pinned = |dimension: &str| {
+            self.get(dimension)
+                .map(|selected| selected.resolve_single(state))
+                .transpose()
+        };
+        let (reference, lead) = (pinned(&axes.reference)?, pinned(&axes.lead)?);
+        if reference.is_some() && lead.is_some() {
+            return Err(RossbyError::InvalidParameter {
+                param: VALID_TIME_PARAM.to_string(),
+                message: format!(
+                    "'{}' and '{}' are both selected already",
+                    axes.reference, axes.lead
+                ),
+            });
+        }
+
+        let indices = axes.resolve(state, valid_time, reference, lead)?;
+        for (dimension, index) in [(&axes.reference, indices.0), (&axes.lead, indices.1)] {
+            self.dimensions
+                .entry(dimension.clone())
+                .or_insert_with(|| DimensionSelection {
+                    dimension: dimension.clone(),
+                    param: VALID_TIME_PARAM.to_string(),
+                    selector: Selector::Index(index),
+                });
+        }
+        Ok(())
+    }
+
+    /// Select the grid points inside `(min_lon, min_lat, max_lon, max_lat)`
+    /// on the latitude and longitude dimensions
+    ///
+    /// Neither dimension may be selected otherwise.
+    pub fn add_bbox(
+        &mut self,
+        state: &AppState,
+        param: &str,
+        (min_lon, min_lat, max_lon, max_lat): (f64, f64, f64, f64),
+    ) -> Result<()> {
+        let axes = [
+            (&LAT_NAMES, "latitude", min_lat, max_lat),
+            (&LON_NAMES, "longitude", min_lon, max_lon),
+        ];
+        for (names, label, min, max) in axes {
+            let dimension = names
+                .iter()
+                .find_map(|name| state.resolve_dimension(name).ok())
+                .ok_or_else(|| RossbyError::InvalidParameter {
+                    param: param.to_string(),
+                    message: format!("The dataset has no {} dimension", label),
+                })?;
+            if let Some(existing) = self.dimensions.get(dimension) {
+                return Err(RossbyError::InvalidParameter {
+                    param: param.to_string(),
+                    message: format!(
+                        "Dimension '{}' is already selected by '{}'",
+                        dimension, existing.param
+                    ),
+                });
+            }
+            self.dimensions.insert(
+                dimension.to_string(),
+                DimensionSelection {
+                    dimension: dimension.to_string(),
+                    param: param.to_string(),
+                    selector: Selector::Bounds { min, max },
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Selection for a file-specific dimension name, if any
+    pub fn get(&self, dimension: &str) -> Option<&DimensionSelection> {
+        self.dimensions.get(dimension)
+    }
+
+    /// Iterate over the selections in dimension name order
+    pub fn iter(&self) -> impl Iterator<Item = &DimensionSelection> {
+        self.dimensions.values()
+    }
+
+    /// Whether no dimension is selected
+    pub fn is_empty(&self) -> bool {
+        self.dimensions.is_empty()
+    }
+
+    /// Resolve every selection to raw indices
+    pub fn resolve(&self, state: &AppState) -> Result<HashMap<String, Vec<usize>>> {
+        self.iter()
+            .map(|s| Ok((s.dimension.clone(), s.resolve(state)?)))
+            .collect()
+    }
+}
+
+/// Values of an array selected along its dimensions, dropping dimensions
+/// with a single selected index
+///
+/// Single indices and evenly spaced ascending indices (ranges, with or
+/// without a step) are taken as views of the array. Other index lists are
+/// gathered from that view straight into the result, so the selected values
+/// are copied exactly once and nothing but the result is allocated; the
+/// whole array is never copied.
+pub fn select_values<A: Clone + Default>(
+    array: ArrayView<'_, A, IxDyn>,
+    dimensions: &[String],
+    selected_indices: &HashMap<String, Vec<usize>>,
+) -> Array<A, IxDyn> {
+    select_values_with(array, dimensions, selected_indices, A::clone)
+}
+
+/// [`select_values`], converting every value with `convert` as it is copied
+pub fn select_values_with<A, B: Default>(
+    array: ArrayView<'_, A, IxDyn>,
+    dimensions: &[String],
+    selected_indices: &HashMap<String, Vec<usize>>,
+    convert: impl Fn(&A) -> B + Copy,
+) -> Array<B, IxDyn> {
+    // From the highest axis down, so the positions of lower axes stay valid
+    let mut view = array;
+    let mut gathered = Vec::new();
+    for (axis, dim_name) in dimensions.iter().enumerate().rev() {
+        match selected_indices.get(dim_name).map(Vec::as_slice) {
+            None => {}
+            Some([index]) => view = view.index_axis_move(Axis(axis), *index),
+            Some(indices) => match evenly_spaced(indices) {
+                Some(slice) => view.slice_axis_inplace(Axis(axis), slice),
+                None => gathered.push((axis, indices)),
+            },
+        }
+    }
+
+    // Positions of the gathered axes once single indices are dropped, in
+    // ascending order
+    let dropped_below = |axis: usize| {
+        dimensions[..axis]
+            .iter()
+            .filter(|dim| selected_indices.get(*dim).is_some_and(|i| i.len() == 1))
+            .count()
+    };
+    let mut gathered: Vec<(usize, &[usize])> = gathered
+        .into_iter()
+        .map(|(axis, indices)| (axis - dropped_below(axis), indices))
+        .collect();
+    gathered.sort_by_key(|&(axis, _)| axis);
+
+    let mut shape = view.shape().to_vec();
+    for &(axis, indices) in &gathered {
+        shape[axis] = indices.len();
+    }
+    let mut result = Array::default(IxDyn(&shape));
+    gather_into(view, result.view_mut(), &gathered, 0, convert);
+    result
+}
+
+/// Copy the values of `view` at the gathered indices into `out`
+///
+/// `gathered` lists axes in ascending order; `depth` of them have already
+/// been indexed away, shifting the remaining ones down.
+fn gather_into<A, B>(
+    view: ArrayView<'_, A, IxDyn>,
+    mut out: ArrayViewMut<'_, B, IxDyn>,
+    gathered: &[(usize, &[usize])],
+    depth: usize,
+    convert: impl Fn(&A) -> B + Copy,
+) {
+    let Some((&(axis, indices), rest)) = gathered.split_first() else {
+        out.zip_mut_with(&view, |out, value| *out = convert(value));
+        return;
+    };
+    let axis = Axis(axis - depth);
+    for (position, &index) in indices.iter().enumerate() {
+        gather_into(
+            view.index_axis(axis, index),
+            out.index_axis_mut(axis, position),
+            rest,
+            depth + 1,
+            convert,
+        );
+    }
+}
+
+/// Slice taking evenly spaced ascending indices, if they are
+fn evenly_spaced(indices: &[usize]) -> Option<Slice> {
+    let (&first, &last) = (indices.first()?, indices.last()?);
+    let step = match indices.get(1) {
+        Some(&second) => second.checked_sub(first).filter(|&step| step > 0)?,
+        None => 1,
+    };
+    indices
+        .windows(2)
+        .all(|pair| pair[1] == pair[0] + step)
+        .then(|| Slice::new(first as isize, Some(last as isize + 1), step as isize))
+}
+
+/// Parse one query parameter into a dimension and its selector
+fn parse_parameter(
+    state: &AppState,
+    key: &str,
+    value: &SelectorInput,
+) -> Result<(String, Selector)> {
+    // Physical value(s), e.g. time=1672531200 or level=500,850
+    if let Ok(dimension) = state.resolve_dimension(key) {
+        let values = parse_list::<f64>(key, value, "a number")?;
+        let selector = match values.as_slice() {
+            [single] => Selector::Value(*single),
+            _ => Selector::Values(values),
+        };
+        return Ok((dimension.to_string(), selector));
+    }
+
+    // Legacy raw time index
+    if key == "time_index" {
+        let dimension = ["time", "t"]
+            .iter()
+            .find_map(|name| state.resolve_dimension(name).ok())
+            .ok_or_else(|| RossbyError::InvalidParameter {
+                param: key.to_string(),
+                message: "The dataset has no time dimension".to_string(),
+            })?;
+        let index = parse_single::<usize>(key, value, "an integer index")?;
+        return Ok((dimension.to_string(), Selector::Index(index)));
+    }
+
+    // Raw index range, e.g. __time_index_range=0,10
+    if let Some(name) = key
+        .strip_prefix("__")
+        .and_then(|s| s.strip_suffix("_index_range"))
+    {
+        if let Ok(dimension) = state.resolve_dimension(name) {
+            let (start, end, step) = parse_range::<usize>(key, value, "an integer index")?;
+            if start > end {
+                return Err(RossbyError::InvalidParameter {
+                    param: key.to_string(),
+                    message: format!("Start index {} is greater than end index {}", start, end),
+                });
+            }
+            return Ok((
+                dimension.to_string(),
+                Selector::IndexRange { start, end, step },
+            ));
+        }
+    }
+
+    // Raw index or indices, e.g. __lon_index=2
+    if let Some(name) = key
+        .strip_prefix("__")
+        .and_then(|s| s.strip_suffix("_index"))
+    {
+        if let Ok(dimension) = state.resolve_dimension(name) {
+            let indices = parse_list::<usize>(key, value, "an integer index")?;
+            let selector = match indices.as_slice() {
+                [single] => Selector::Index(*single),
+                _ => Selector::Indices(indices),
+            };
+            return Ok((dimension.to_string(), selector));
+        }
+    }
+
+    // Physical value range, e.g. lat_range=30,40
+    if let Some(name) = key.strip_suffix("_range") {
+        if let Ok(dimension) = state.resolve_dimension(name) {
+            let (start, end, step) = parse_range::<f64>(key, value, "a number")?;
+            return Ok((
+                dimension.to_string(),
+                Selector::ValueRange { start, end, step },
+            ));
+        }
+    }
+
+    Err(unknown_parameter(state, key))
+}
+
+/// Values that can appear in a selector
+trait SelectorValue: std::str::FromStr + Copy {
+    fn is_valid(&self) -> bool;
+
+    /// The value of a JSON number, if it is one of these values
+    fn from_number(value: f64) -> Option<Self>;
+}
+
+impl SelectorValue for f64 {
+    fn is_valid(&self) -> bool {
+        self.is_finite()
+    }
+
+    fn from_number(value: f64) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl SelectorValue for usize {
+    fn is_valid(&self) -> bool {
+        true
+    }
+
+    fn from_number(value: f64) -> Option<Self> {
+        (value >= 0.0 && value.fract() == 0.0 && value < usize::MAX as f64)
+            .then_some(value as usize)
+    }
+}
+
+fn parse_scalar<T: SelectorValue>(key: &str, raw: &str, expected: &str) -> Result<T> {
+    parse_part(key, Part::Text(raw), expected)
+}
+
+fn parse_part<T: SelectorValue>(key: &str, part: Part<'_>, expected: &str) -> Result<T> {
+    let value = match part {
+        Part::Number(value) => T::from_number(value),
+        Part::Text(raw) => raw.trim().parse::<T>().ok(),
+    };
+    value
+        .filter(SelectorValue::is_valid)
+        .ok_or_else(|| RossbyError::InvalidParameter {
+            param: key.to_string(),
+            message: format!("Could not parse '{}' as {}", part, expected),
+        })
+}
+
+fn parse_single<T: SelectorValue>(key: &str, input: &SelectorInput, expected: &str) -> Result<T> {
+    match input.parts().as_slice() {
+        [part] => parse_part(key, *part, expected),
+        _ => Err(RossbyError::InvalidParameter {
+            param: key.to_string(),
+            message: format!("Expected a single value, got: '{}'", input),
+        }),
+    }
+}
+
+fn parse_list<T: SelectorValue>(
+    key: &str,
+    input: &SelectorInput,
+    expected: &str,
+) -> Result<Vec<T>> {
+    if input.is_empty() {
+        return Err(RossbyError::InvalidParameter {
+            param: key.to_string(),
+            message: "A value is required".to_string(),
+        });
+    }
+    input
+        .parts()
+        .into_iter()
+        .map(|part| parse_part(key, part, expected))
+        .collect()
+}
+
+fn parse_range<T: SelectorValue>(
+    key: &str,
+    input: &SelectorInput,
+    expected: &str,
+) -> Result<(T, T, usize)> {
+    let parts = input.parts();
+    let (start, end, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, None),
+        [start, end, step] => (*start, *end, Some(*step)),
+        _ => {
+            return Err(RossbyError::InvalidParameter {
+                param: key.to_string(),
+                message: format!(
+                    "Range must be '<start>,<end>' or '<start>,<end>,<step>', got: '{}'",
+                    input
+                ),
+            })
+        }
+    };
+
+    let step = match step {
+        Some(step) => parse_part::<usize>(key, step, "a positive integer step")?,
+        None => 1,
+    };
+    if step == 0 {
+        return Err(RossbyError::InvalidParameter {
+            param: key.to_string(),
+            message: "Range step must be at least 1".to_string(),
+        });
+    }
+
+    Ok((
+        parse_part(key, start, expected)?,
+        parse_part(key, end, expected)?,
+        step,
+    ))
+}
+
+/// Build the error for a parameter that is not a valid selector
+fn unknown_parameter(state: &AppState, key: &str) -> RossbyError {
+    let candidates = valid_parameter_names(state);
+    let suggestion = candidates
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= (key.len() / 3).max(2))
+        .min();
+
+    let message = match suggestion {
+        Some((_, candidate)) => format!("Unknown parameter. Did you mean '{}'?", candidate),
+        None => {
+            let mut dims: Vec<&String> = state.metadata.dimensions.keys().collect();
+            dims.sort();
+            format!(
+                "Unknown parameter. Dimension selectors are '<dim>', '<dim>_range', \
+                 '__<dim>_index' or '__<dim>_index_range' for dimensions {:?}",
+                dims
+            )
+        }
+    };
+
+    RossbyError::InvalidParameter {
+        param: key.to_string(),
+        message,
+    }
+}
+
+/// Every selector parameter name accepted for the loaded dataset
+fn valid_parameter_names(state: &AppState) -> Vec<String> {
+    let mut names: Vec<&str> = state
+        .metadata
+        .dimensions
+        .keys()
+        .map(String::as_str)
+        .collect();
+    names.extend(
+        state
+            .config
+            .data
+            .dimension_aliases
+            .keys()
+            .map(String::as_str),
+    );
+    names.sort();
+    names.dedup();
+
+    let mut params = vec!["time_index".to_string()];
+    if ForecastAxes::detect(state).is_some() {
+        params.push(VALID_TIME_PARAM.to_string());
+    }
+    for name in names {
+        params.push(name.to_string());
+        params.push(format!("{}_range", name));
+        params.push(format!("__{}_index", name));
+        params.push(format!("__{}_index_range", name));
+    }
+    params
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Split a comma-separated variable list
+///
+/// Commas inside parentheses separate the arguments of derived variables
+/// such as `vorticity(u,v)` rather than variables.
+pub fn split_variables(list: &str) -> Vec<String> {
+    let mut variables = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                variables.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    variables.push(&list[start..]);
+
+    variables
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Error of a query naming no variable in `param`
+pub fn no_variables(param: &str) -> RossbyError {
+    RossbyError::InvalidParameter {
+        param: param.to_string(),
+        message: "At least one variable must be specified".to_string(),
+    }
+}
+
+/// Time step parameters shared by the endpoints rendering or sampling a
+/// single time step
+///
+/// `__time_index` takes precedence over `time` (or `_time`), which takes
+/// precedence over the deprecated `time_index`; without any of them the
+/// first time step is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeParams {
+    /// Raw index given with `__time_index`
+    pub raw_index: Option<usize>,
+    /// Physical value given with `time` or `_time`
+    pub value: Option<f64>,
+    /// Raw index given with the deprecated `time_index`
+    pub legacy_index: Option<usize>,
+}
+
+impl TimeParams {
+    /// Read the time step parameters from the raw parameters of a query
+    pub fn from_query(params: &HashMap<String, String>) -> Result<Self> {
+        let value = match (params.get("time"), params.get("_time")) {
+            (Some(raw), _) => Some(parse_scalar("time", raw, "a number")?),
+            (None, Some(raw)) => Some(parse_scalar("_time", raw, "a number")?),
+            (None, None) => None,
+        };
+        Ok(Self {
+            raw_index: params
+                .get("__time_index")
+                .map(|raw| parse_scalar("__time_index", raw, "a non-negative integer"))
+                .transpose()?,
+            value,
+            legacy_index: params
+                .get("time_index")
+                .map(|raw| parse_scalar("time_index", raw, "a non-negative integer"))
+                .transpose()?,
+        })
+    }
+
+    /// Whether the query selects a time step at all
+    pub fn is_given(&self) -> bool {
+        self.raw_index.is_some() || self.value.is_some() || self.legacy_index.is_some()
+    }
+
+    /// Parameter selecting the time step, or None if the first one is used
+    pub fn param(&self) -> Option<&'static str> {
+        match (self.raw_index, self.value, self.legacy_index) {
+            (Some(_), _, _) => Some("__time_index"),
+            (None, Some(_), _) => Some("time"),
+            (None, None, Some(_)) => Some("time_index"),
+            (None, None, None) => None,
+        }
+    }
+
+    /// Index of the selected time step
+    ///
+    /// Indices are checked against the size of the time dimension, values
+    /// must match a time step within the configured time tolerance.
+    pub fn resolve(&self, state: &AppState) -> Result<usize> {
+        let (param, index) = match (self.raw_index, self.value, self.legacy_index) {
+            (Some(index), _, _) => ("__time_index", index),
+            (None, Some(value), _) => return state.find_time_index(value),
+            (None, None, Some(index)) => {
+                warn!(
+                    param = "time_index",
+                    deprecated_since = "0.1.0",
+                    replacement = "__time_index",
+                    "The 'time_index' parameter is deprecated. Please use '__time_index' instead."
+                );
+                ("time_index", index)
+            }
+            (None, None, None) => return Ok(0),
+        };
+        let size = state.time_dim_size();
+        if index >= size {
+            return Err(RossbyError::IndexOutOfBounds {
+                param: param.to_string(),
+                value: index.to_string(),
+                max: size.saturating_sub(1),
+            });
+        }
+        Ok(index)
+    }
+}
+
+/// Error of a query rejected by an extractor, answered like the errors of
+/// the handlers
+#[derive(Debug)]
+pub struct QueryRejection {
+    /// Path of the rejected request
+    pub endpoint: String,
+    pub error: RossbyError,
+}
+
+impl QueryRejection {
+    fn new(parts: &Parts) -> impl FnOnce(RossbyError) -> Self + '_ {
+        |error| Self {
+            endpoint: parts.uri.path().to_string(),
+            error,
+        }
+    }
+}
+
+impl IntoResponse for QueryRejection {
+    fn into_response(self) -> Response {
+        let request_id = generate_request_id();
+        log_request_error(&self.error, &self.endpoint, &request_id, None);
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": self.error.to_string(),
+                "request_id": request_id
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Raw parameters of a request's query string
+fn raw_query(parts: &Parts) -> Result<HashMap<String, String>> {
+    serde_urlencoded::from_str(parts.uri.query().unwrap_or("")).map_err(|error| {
+        RossbyError::InvalidParameter {
+            param: "query".to_string(),
+            message: format!("Invalid query string: {}", error),
+        }
+    })
+}
+
+/// Extractor of the dataset serving a request
+///
+/// Requests naming a view in `vars` (or `var`) are served from the view's
+/// dataset, all others from the server's (see
+/// [`crate::views::state_for_variables`]).
+#[derive(Debug, Clone)]
+pub struct Dataset(pub Arc<AppState>);
+
+impl Dataset {
+    fn resolve(state: &Arc<AppState>, params: &HashMap<String, String>) -> Result<Arc<AppState>> {
+        let (param, list) = match (params.get("vars"), params.get("var")) {
+            (Some(list), _) => ("vars", list),
+            (None, Some(var)) => ("var", var),
+            (None, None) => return Ok(state.clone()),
+        };
+        let names = split_variables(list);
+        state_for_variables(state, param, names.iter().map(String::as_str))
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Dataset {
+    type Rejection = QueryRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let reject = QueryRejection::new(parts);
+        raw_query(parts)
+            .and_then(|params| Dataset::resolve(state, &params))
+            .map(Dataset)
+            .map_err(reject)
+    }
+}
+
+/// Extractor of the time step selected by a request, resolved with
+/// [`TimeParams::resolve`] against the dataset serving it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeStep(pub usize);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for TimeStep {
+    type Rejection = QueryRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let reject = QueryRejection::new(parts);
+        raw_query(parts)
+            .and_then(|params| {
+                let dataset = Dataset::resolve(state, &params)?;
+                TimeParams::from_query(&params)?.resolve(&dataset)
+            })
+            .map(TimeStep)
+            .map_err(reject)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata};
+    use ndarray::{Array, Axis, IxDyn};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::collections::HashMap;
+
+    /// Allocator tracking the bytes allocated by each thread and their peak,
+    /// so tests running in parallel do not disturb each other
+    struct PeakAllocator;
+
+    thread_local! {
+        static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+        static PEAK: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for PeakAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                let _ = ALLOCATED.try_with(|allocated| {
+                    let now = allocated.get() + layout.size();
+                    allocated.set(now);
+                    let _ = PEAK.try_with(|peak| peak.set(peak.get().max(now)));
+                });
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            let _ = ALLOCATED
+                .try_with(|allocated| allocated.set(allocated.get().saturating_sub(layout.size())));
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: PeakAllocator = PeakAllocator;
+
+    /// Run `f`, returning its result and the most bytes it had allocated at
+    /// once on this thread
+    fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATED.with(Cell::get);
+        PEAK.with(|peak| peak.set(before));
+        let result = f();
+        (result, PEAK.with(Cell::get) - before)
+    }
+
+    fn create_test_state() -> AppState {
+        let mut dimensions = HashMap::new();
+        for (name, size) in [("time", 5), ("lat", 3), ("lon", 4)] {
+            dimensions.insert(
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                },
+            );
+        }
+
+        let mut coordinates = HashMap::new();
+        coordinates.insert("time".to_string(), vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        coordinates.insert("lat".to_string(), vec![35.0, 36.0, 37.0]);
+        coordinates.insert("lon".to_string(), vec![139.0, 140.0, 141.0, 142.0]);
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables: HashMap::new(),
+            coordinates,
+            groups: HashMap::new(),
+        };
+
+        let mut config = Config::default();
+        config
+            .data
+            .dimension_aliases
+            .insert("latitude".to_string(), "lat".to_string());
+        config
+            .data
+            .dimension_aliases
+            .insert("longitude".to_string(), "lon".to_string());
+
+        AppState::new(config, metadata, HashMap::new())
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_selectors() {
+        let state = create_test_state();
+        let selection = Selection::parse(
+            &state,
+            &params(&[
+                ("time", "2"),
+                ("latitude_range", "35,37"),
+                ("__longitude_index_range", "0,3,2"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            selection.get("time").unwrap().selector,
+            Selector::Value(2.0)
+        );
+        assert_eq!(
+            selection.get("lat").unwrap().selector,
+            Selector::ValueRange {
+                start: 35.0,
+                end: 37.0,
+                step: 1
+            }
+        );
+        assert_eq!(
+            selection.get("lon").unwrap().param,
+            "__longitude_index_range"
+        );
+
+        let resolved = selection.resolve(&state).unwrap();
+        assert_eq!(resolved["time"], vec![2]);
+        assert_eq!(resolved["lat"], vec![0, 1, 2]);
+        assert_eq!(resolved["lon"], vec![0, 2]);
+    }
+
+    #[test]
+    fn test_parse_lists_and_legacy_index() {
+        let state = create_test_state();
+        let selection = Selection::parse(
+            &state,
+            &params(&[
+                ("time_index", "4"),
+                ("__lon_index", "3,1"),
+                ("lat", "36.9,35"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(selection.get("time").unwrap().selector, Selector::Index(4));
+        assert_eq!(
+            selection.get("lon").unwrap().selector,
+            Selector::Indices(vec![3, 1])
+        );
+        let resolved = selection.resolve(&state).unwrap();
+        assert_eq!(resolved["lat"], vec![2, 0]);
+        assert_eq!(resolved["lon"], vec![3, 1]);
+        assert!(selection
+            .get("lat")
+            .unwrap()
+            .resolve_single(&state)
+            .is_err());
+        assert_eq!(
+            selection
+                .get("time")
+                .unwrap()
+                .resolve_single(&state)
+                .unwrap(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let state = create_test_state();
+        let message = |pairs: &[(&str, &str)]| match Selection::parse(&state, &params(pairs)) {
+            Err(RossbyError::InvalidParameter { param, message }) => (param, message),
+            other => panic!("Expected InvalidParameter, got {:?}", other),
+        };
+
+        let (param, msg) = message(&[("lat_rnage", "35,36")]);
+        assert_eq!(param, "lat_rnage");
+        assert!(msg.contains("Did you mean 'lat_range'?"));
+
+        let (_, msg) = message(&[("pressure", "500")]);
+        assert!(msg.contains("Dimension selectors are"));
+
+        let (param, msg) = message(&[("time", "abc")]);
+        assert_eq!(param, "time");
+        assert!(msg.contains("'abc'"));
+
+        let (_, msg) = message(&[("lat_range", "35")]);
+        assert!(msg.contains("<start>,<end>"));
+
+        let (_, msg) = message(&[("__time_index_range", "3,1")]);
+        assert!(msg.contains("greater than"));
+
+        let (_, msg) = message(&[("__time_index_range", "0,4,0")]);
+        assert!(msg.contains("step"));
+
+        let (_, msg) = message(&[("time", "NaN")]);
+        assert!(msg.contains("as a number"));
+
+        let (_, msg) = message(&[("lat", "35"), ("latitude", "36")]);
+        assert!(msg.contains("already selected"));
+
+        let selection = Selection::parse(&state, &params(&[("__time_index", "9")])).unwrap();
+        assert!(matches!(
+            selection.resolve(&state),
+            Err(RossbyError::IndexOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_selectors_from_json() {
+        let state = create_test_state();
+        let inputs: HashMap<String, SelectorInput> = serde_json::from_value(serde_json::json!({
+            "time": [1, 3],
+            "lat_range": "35,37,2",
+            "__lon_index_range": [0, 3, 2],
+        }))
+        .unwrap();
+        let selection = Selection::from_inputs(&state, &inputs).unwrap();
+        assert_eq!(
+            selection.get("time").unwrap().selector,
+            Selector::Values(vec![1.0, 3.0])
+        );
+        assert_eq!(
+            selection.get("lat").unwrap().selector,
+            Selector::ValueRange {
+                start: 35.0,
+                end: 37.0,
+                step: 2
+            }
+        );
+        // JSON and query string selectors parse alike
+        let query = Selection::parse(
+            &state,
+            &params(&[
+                ("time", "1,3"),
+                ("lat_range", "35,37,2"),
+                ("__lon_index_range", "0,3,2"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(selection, query);
+
+        let invalid = |value: serde_json::Value| {
+            let inputs: HashMap<String, SelectorInput> = serde_json::from_value(value).unwrap();
+            Selection::from_inputs(&state, &inputs)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(invalid(serde_json::json!({"__time_index": 1.5})).contains("'1.5'"));
+        assert!(invalid(serde_json::json!({"__time_index": -1})).contains("'-1'"));
+        assert!(invalid(serde_json::json!({"lat_range": [35]})).contains("<start>,<end>"));
+        assert!(invalid(serde_json::json!({"time": []})).contains("A value is required"));
+        assert!(invalid(serde_json::json!({"time_index": [1, 2]})).contains("single value"));
+        assert!(serde_json::from_value::<SelectorInput>(serde_json::json!(true)).is_err());
+    }
+
+    #[test]
+    fn test_add_bbox() {
+        let state = create_test_state();
+        let mut selection = Selection::parse(&state, &params(&[("time_index", "0")])).unwrap();
+        selection
+            .add_bbox(&state, "bbox", (139.5, 35.5, 141.5, 37.0))
+            .unwrap();
+        let resolved = selection.resolve(&state).unwrap();
+        assert_eq!(resolved["lat"], vec![1, 2]);
+        assert_eq!(resolved["lon"], vec![1, 2]);
+
+        // Crossing the seam takes both ends of the longitude axis, eastward
+        let mut selection = Selection::default();
+        selection
+            .add_bbox(&state, "bbox", (141.0, 35.0, 139.5, 36.0))
+            .unwrap();
+        let resolved = selection.resolve(&state).unwrap();
+        assert_eq!(resolved["lon"], vec![2, 3, 0]);
+
+        let mut selection = Selection::parse(&state, &params(&[("lat", "35")])).unwrap();
+        assert!(selection
+            .add_bbox(&state, "bbox", (139.0, 35.0, 142.0, 37.0))
+            .is_err());
+        let mut selection = Selection::default();
+        selection
+            .add_bbox(&state, "bbox", (139.0, 40.0, 142.0, 45.0))
+            .unwrap();
+        assert!(selection.resolve(&state).is_err());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("lat_range", "lat_range"), 0);
+        assert_eq!(edit_distance("lat_rnage", "lat_range"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_split_variables() {
+        assert_eq!(
+            split_variables(" t2m, vorticity(u,v),,sst "),
+            vec!["t2m", "vorticity(u,v)", "sst"]
+        );
+        assert!(split_variables(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_time_params() {
+        let state = create_test_state();
+        let resolve = |pairs: &[(&str, &str)]| {
+            TimeParams::from_query(&params(pairs))
+                .and_then(|time| time.resolve(&state))
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(resolve(&[]), Ok(0));
+        assert_eq!(resolve(&[("_time", "3")]), Ok(3));
+        // Raw indices win over values, which win over the legacy index
+        assert_eq!(resolve(&[("__time_index", "1"), ("time", "3")]), Ok(1));
+        assert_eq!(resolve(&[("time", "3"), ("time_index", "2")]), Ok(3));
+        assert_eq!(resolve(&[("time_index", "2")]), Ok(2));
+
+        // Errors name the parameter given
+        assert!(resolve(&[("__time_index", "5")])
+            .unwrap_err()
+            .contains("__time_index"));
+        assert!(resolve(&[("time_index", "9")])
+            .unwrap_err()
+            .contains("time_index"));
+        assert!(resolve(&[("time", "2.5")]).is_err());
+        assert!(resolve(&[("__time_index", "-1")])
+            .unwrap_err()
+            .contains("__time_index"));
+    }
+
+    #[tokio::test]
+    async fn test_time_step_extractor() {
+        let state = Arc::new(create_test_state());
+        let extract = |uri: &'static str| {
+            let state = state.clone();
+            async move {
+                let (mut parts, _) = axum::http::Request::get(uri).body(()).unwrap().into_parts();
+                TimeStep::from_request_parts(&mut parts, &state).await
+            }
+        };
+
+        assert_eq!(extract("/image?var=t&time=4").await.unwrap(), TimeStep(4));
+        let rejection = extract("/point?__time_index=7").await.unwrap_err();
+        assert_eq!(rejection.endpoint, "/point");
+        assert_eq!(rejection.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_select_values() {
+        let array = Array::from_shape_fn(IxDyn(&[5, 3, 4]), |i| {
+            (i[0] * 100 + i[1] * 10 + i[2]) as f32
+        });
+        let dimensions = ["time", "lat", "lon"].map(String::from);
+
+        // Copying and selecting axis by axis gives the same values
+        let naive = |selected: &HashMap<String, Vec<usize>>| {
+            let mut result = array.clone();
+            for (axis, dim) in dimensions.iter().enumerate().rev() {
+                result = match selected.get(dim).map(Vec::as_slice) {
+                    Some([index]) => result.index_axis(Axis(axis), *index).to_owned(),
+                    Some(indices) => result.select(Axis(axis), indices),
+                    None => result,
+                };
+            }
+            result
+        };
+        let selections = [
+            vec![],
+            vec![("time", vec![2])],
+            vec![("time", vec![1, 2, 3]), ("lon", vec![0, 2])],
+            vec![
+                ("time", vec![4, 0]),
+                ("lat", vec![1]),
+                ("lon", vec![3, 1, 1]),
+            ],
+            vec![
+                ("time", vec![0, 1, 3]),
+                ("lat", vec![2, 0]),
+                ("lon", vec![2]),
+            ],
+            vec![("lat", vec![])],
+        ];
+        for selection in selections {
+            let selected: HashMap<String, Vec<usize>> = selection
+                .into_iter()
+                .map(|(dim, indices)| (dim.to_string(), indices))
+                .collect();
+            assert_eq!(
+                select_values(array.view(), &dimensions, &selected),
+                naive(&selected),
+                "{:?}",
+                selected
+            );
+        }
+    }
+    #[test]
+    fn test_select_values_peak_allocation() {
+        // A 0.25° global field of 12 time steps, 50 MB as f32
+        let shape = [12, 721, 1440];
+        let array = Array::from_shape_fn(IxDyn(&shape), |i| (i[0] + i[1] + i[2]) as f32);
+        let packed = array.mapv(|v| v as i16);
+        let dimensions = ["time", "lat", "lon"].map(String::from);
+
+        // Index lists on every axis, which are gathered rather than viewed
+        let selected: HashMap<String, Vec<usize>> = [
+            ("time", vec![0, 5, 11]),
+            ("lat", (100..400).rev().collect::<Vec<_>>()),
+            ("lon", (0..1440).filter(|i| i % 3 != 0).collect()),
+        ]
+        .into_iter()
+        .map(|(dim, indices)| (dim.to_string(), indices))
+        .collect();
+        let result_bytes = 3 * 300 * 960 * std::mem::size_of::<f32>();
+
+        let (values, peak) =
+            peak_allocation(|| select_values(array.view(), &dimensions, &selected));
+        assert_eq!(values.shape(), &[3, 300, 960]);
+        assert_eq!(values[[1, 0, 0]], (5 + 399 + 1) as f32);
+        assert!(
+            peak <= result_bytes + 4096,
+            "peak {} bytes for a {} byte result",
+            peak,
+            result_bytes
+        );
+
+        // Packed values are converted as they are copied
+        let (values, peak) = peak_allocation(|| {
+            crate::packed::VariableValues::Packed(&packed).select(&dimensions, &selected)
+        });
+        assert_eq!(values[[1, 0, 0]], (5 + 399 + 1) as f32);
+        assert!(
+            peak <= result_bytes + 4096,
+            "peak {} bytes for a {} byte result",
+            peak,
+            result_bytes
+        );
+
+        // Views alone copy only the selection
+        let selected = HashMap::from([
+            ("time".to_string(), vec![3]),
+            ("lat".to_string(), (0..721).step_by(2).collect()),
+        ]);
+        let (values, peak) =
+            peak_allocation(|| select_values(array.view(), &dimensions, &selected));
+        assert_eq!(values.shape(), &[361, 1440]);
+        assert!(peak <= values.len() * std::mem::size_of::<f32>() + 4096);
+    }
+}
@@ -0,0 +1,318 @@
+//! Echo of how the server interpreted a request.
+//!
+//! With `echo_params=true`, `/point`, `/image`, `/data`, `/stats`, `/diff`
+//! and `/cellstats` describe how they resolved the query in a
+//! `query_resolved` section:
+//!
+//! - `dimensions`: every dimension sliced, by file-specific name, with its
+//!   canonical name, the query parameter that selected it (`null` when it
+//!   was taken in full or defaulted to its first index), the raw indices
+//!   chosen and the coordinate values of the first and last of them,
+//! - `point`: the location sampled after bounds handling, with its
+//!   fractional grid indices,
+//! - `interpolation` and `bounds`: the methods applied,
+//! - `bbox`: the effective bounding box after bounds and dateline handling,
+//!   as `[min_lon, min_lat, max_lon, max_lat]`.
+//!
+//! Arrow output of `/data` carries the section as JSON in its schema
+//! metadata, and images (`/image`, agreement maps of `/diff`) as compact
+//! JSON in the `X-Rossby-Query-Resolved` response header.
+//! Indices are listed only when they are not evenly spaced; evenly spaced
+//! runs are given by their `start`, `end` and `step`.
+
+use axum::http::HeaderValue;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::bounds::{Axis, BoundsMode};
+use crate::error::{Result, RossbyError};
+use crate::interpolation::common::coord_to_index;
+use crate::query::Selection;
+use crate::state::AppState;
+
+/// Query parameter requesting the echo
+pub const ECHO_PARAM: &str = "echo_params";
+
+/// Key of the echo in JSON responses
+pub const QUERY_RESOLVED_KEY: &str = "query_resolved";
+
+/// Response header carrying the echo of image responses
+pub const QUERY_RESOLVED_HEADER: &str = "x-rossby-query-resolved";
+
+/// Parse the `echo_params` parameter (default: false)
+pub fn echo_requested(value: Option<&str>) -> Result<bool> {
+    match value {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(other) => Err(RossbyError::InvalidParameter {
+            param: ECHO_PARAM.to_string(),
+            message: format!(
+                "Invalid value: {}. Valid values are 'true' or 'false'",
+                other
+            ),
+        }),
+    }
+}
+
+/// How a request was interpreted
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QueryResolved {
+    /// Dimensions sliced, by file-specific name
+    pub dimensions: BTreeMap<String, ResolvedDimension>,
+    /// Location sampled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub point: Option<ResolvedPoint>,
+    /// Interpolation or resampling method applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<String>,
+    /// Handling of coordinates outside the grid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<&'static str>,
+    /// Effective bounding box as `[min_lon, min_lat, max_lon, max_lat]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<[f64; 4]>,
+}
+
+/// Raw indices chosen for one dimension
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResolvedDimension {
+    /// Canonical name of the dimension, if it has one
+    pub canonical: Option<String>,
+    /// Query parameter that selected the dimension
+    pub param: Option<String>,
+    /// Number of indices chosen
+    pub count: usize,
+    /// First index chosen
+    pub start: usize,
+    /// Last index chosen
+    pub end: usize,
+    /// Stride between the indices, if they are evenly spaced
+    pub step: Option<usize>,
+    /// The indices, if they are not evenly spaced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indices: Option<Vec<usize>>,
+    /// Coordinate values at the first and last index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<[f64; 2]>,
+}
+
+/// A location sampled between grid points
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ResolvedPoint {
+    /// Longitude after bounds handling
+    pub lon: f64,
+    /// Latitude after bounds handling
+    pub lat: f64,
+    /// Fractional index on the longitude dimension
+    pub lon_index: f64,
+    /// Fractional index on the latitude dimension
+    pub lat_index: f64,
+}
+
+impl ResolvedPoint {
+    /// Resolve a location on the given horizontal dimensions
+    pub fn locate(
+        state: &AppState,
+        (lon_dim, lat_dim): (&str, &str),
+        (lon, lat): (f64, f64),
+        bounds: BoundsMode,
+    ) -> Result<Self> {
+        let lon_coords = state.get_coordinate_checked(lon_dim)?;
+        let lat_coords = state.get_coordinate_checked(lat_dim)?;
+        let lon = bounds.apply(Axis::Longitude, lon, lon_coords)?;
+        let lat = bounds.apply(Axis::Latitude, lat, lat_coords)?;
+        Ok(Self {
+            lon,
+            lat,
+            lon_index: coord_to_index(lon, lon_coords)?,
+            lat_index: coord_to_index(lat, lat_coords)?,
+        })
+    }
+}
+
+impl QueryResolved {
+    /// An empty echo
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the indices chosen for a dimension, and the parameter that
+    /// selected it if any
+    ///
+    /// Empty index lists are ignored.
+    pub fn dimension(
+        &mut self,
+        state: &AppState,
+        dimension: &str,
+        param: Option<&str>,
+        indices: &[usize],
+    ) {
+        let (Some(&start), Some(&end)) = (indices.first(), indices.last()) else {
+            return;
+        };
+        let step = match indices {
+            [_] => Some(1),
+            [first, second, ..] => second
+                .checked_sub(*first)
+                .filter(|&step| step > 0)
+                .filter(|&step| indices.windows(2).all(|pair| pair[1] == pair[0] + step)),
+            [] => None,
+        };
+        let values = state
+            .get_coordinate(dimension)
+            .and_then(|coords| Some([*coords.get(start)?, *coords.get(end)?]));
+        self.dimensions.insert(
+            dimension.to_string(),
+            ResolvedDimension {
+                canonical: state
+                    .get_canonical_dimension_name(dimension)
+                    .map(str::to_string),
+                param: param.map(str::to_string),
+                count: indices.len(),
+                start,
+                end,
+                step,
+                indices: step.is_none().then(|| indices.to_vec()),
+                values,
+            },
+        );
+    }
+
+    /// Record the indices chosen for every dimension of a resolved
+    /// selection, with the parameters of `selection`
+    pub fn selection(
+        &mut self,
+        state: &AppState,
+        selection: &Selection,
+        indices: &HashMap<String, Vec<usize>>,
+    ) {
+        for (dimension, indices) in indices {
+            let param = selection.get(dimension).map(|s| s.param.as_str());
+            self.dimension(state, dimension, param, indices);
+        }
+    }
+
+    /// Record single slices pinned on dimensions, with the parameters of
+    /// `selection`
+    pub fn pinned(
+        &mut self,
+        state: &AppState,
+        selection: &Selection,
+        indices: &HashMap<String, usize>,
+    ) {
+        for (dimension, &index) in indices {
+            let param = selection.get(dimension).map(|s| s.param.as_str());
+            self.dimension(state, dimension, param, &[index]);
+        }
+    }
+
+    /// Set the bounding box to the extent of the grid points selected, given
+    /// their longitudes in eastward order and their latitudes
+    pub fn covered_bbox(&mut self, lon: &[f64], lat: &[f64]) {
+        let (Some(&west), Some(&east)) = (lon.first(), lon.last()) else {
+            return;
+        };
+        let south = lat.iter().copied().fold(f64::INFINITY, f64::min);
+        let north = lat.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        if south <= north {
+            self.bbox = Some([west, south, east, north]);
+        }
+    }
+
+    /// The echo as JSON
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    /// The echo as a response header value, if it can be written as one
+    pub fn header_value(&self) -> Option<HeaderValue> {
+        HeaderValue::from_str(&self.to_json().to_string()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata};
+
+    fn create_test_state() -> AppState {
+        let mut dimensions = HashMap::new();
+        let mut coordinates = HashMap::new();
+        for (name, values) in [
+            ("lat", vec![-10.0, 0.0, 10.0]),
+            ("lon", vec![0.0, 90.0, 180.0, 270.0]),
+            ("time", (0..10).map(|t| t as f64).collect()),
+        ] {
+            dimensions.insert(
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size: values.len(),
+                    is_unlimited: false,
+                },
+            );
+            coordinates.insert(name.to_string(), values);
+        }
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables: HashMap::new(),
+            coordinates,
+            groups: HashMap::new(),
+        };
+        let mut config = Config::default();
+        config
+            .data
+            .dimension_aliases
+            .insert("latitude".to_string(), "lat".to_string());
+        AppState::new(config, metadata, HashMap::new())
+    }
+
+    #[test]
+    fn test_resolved_dimensions() {
+        let state = create_test_state();
+        let params = HashMap::from([("__time_index_range".to_string(), "2,8,3".to_string())]);
+        let selection = Selection::parse(&state, &params).unwrap();
+        let mut resolved = QueryResolved::new();
+        resolved.selection(&state, &selection, &selection.resolve(&state).unwrap());
+        resolved.dimension(&state, "lon", None, &[3, 0]);
+        resolved.dimension(&state, "lat", None, &[]);
+
+        let json = resolved.to_json();
+        let time = &json["dimensions"]["time"];
+        assert_eq!(time["param"], "__time_index_range");
+        assert_eq!(time["count"], 3);
+        assert_eq!(time["step"], 3);
+        assert!(time.get("indices").is_none());
+        assert_eq!(time["values"], serde_json::json!([2.0, 8.0]));
+
+        // Indices not evenly spaced are listed
+        let lon = &json["dimensions"]["lon"];
+        assert!(lon["param"].is_null());
+        assert!(lon["step"].is_null());
+        assert_eq!(lon["indices"], serde_json::json!([3, 0]));
+        assert!(json["dimensions"].get("lat").is_none());
+        assert!(json.get("bbox").is_none());
+
+        assert!(resolved.header_value().is_some());
+    }
+
+    #[test]
+    fn test_resolved_point() {
+        let state = create_test_state();
+        let point =
+            ResolvedPoint::locate(&state, ("lon", "lat"), (45.0, 20.0), BoundsMode::Clamp).unwrap();
+        assert_eq!(point.lat, 10.0);
+        assert_eq!(point.lat_index, 2.0);
+        assert_eq!(point.lon_index, 0.5);
+    }
+
+    #[test]
+    fn test_echo_requested() {
+        assert!(!echo_requested(None).unwrap());
+        assert!(echo_requested(Some("true")).unwrap());
+        assert!(echo_requested(Some("yes")).is_err());
+    }
+}
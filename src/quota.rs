@@ -0,0 +1,378 @@
+//! Bandwidth accounting and per-client quotas.
+//!
+//! Every request is attributed to a client, identified by its `X-API-Key`
+//! header or otherwise by its IP address. The bytes received and served are
+//! recorded in one-minute buckets, so usage can be summed over a rolling
+//! window (one day by default). When `server.quotas` sets a limit, clients
+//! that have used up their allowance get a `429 Too Many Requests` response
+//! until enough old traffic leaves the window.
+//!
+//! Endpoints with a weight in `server.quotas.weights` are additionally
+//! charged for the work a request causes, estimated before it runs (see
+//! [`crate::cost`]). A request whose estimated cost would exceed the
+//! remaining allowance is rejected up front.
+
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::QuotaConfig;
+use crate::cost::estimate_cells;
+use crate::error::RossbyError;
+use crate::logging::{generate_request_id, log_request_error};
+use crate::schema::SCHEMA_VERSION;
+use crate::state::AppState;
+
+/// Header carrying the client's API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Width of a usage bucket in seconds
+const BUCKET_SECS: u64 = 60;
+
+/// Endpoints that are neither counted nor blocked
+const EXEMPT_PATHS: &[&str] = &["/usage"];
+
+/// Identity that usage is accounted against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientId {
+    /// Client presenting an API key
+    ApiKey(String),
+    /// Anonymous client identified by its address
+    Ip(IpAddr),
+    /// Client whose address is unavailable
+    Unknown,
+}
+
+impl ClientId {
+    /// Identify a client from its request headers and peer address
+    pub fn from_request(headers: &HeaderMap, addr: Option<SocketAddr>) -> Self {
+        let key = headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|key| !key.is_empty());
+
+        match (key, addr) {
+            (Some(key), _) => ClientId::ApiKey(key.to_string()),
+            (None, Some(addr)) => ClientId::Ip(addr.ip()),
+            (None, None) => ClientId::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientId::ApiKey(key) => write!(f, "key:{}", key),
+            ClientId::Ip(ip) => write!(f, "ip:{}", ip),
+            ClientId::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Usage of a client over the current window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Usage {
+    /// Client identity (`key:<api key>` or `ip:<address>`)
+    pub client: String,
+    /// Bytes received and served within the window
+    pub used_bytes: u64,
+    /// Requests made within the window
+    pub requests: u64,
+    /// Byte allowance for the window (None = unlimited)
+    pub limit_bytes: Option<u64>,
+    /// Bytes left before requests are rejected (None = unlimited)
+    pub remaining_bytes: Option<u64>,
+    /// Length of the rolling window in seconds
+    pub window_secs: u64,
+    /// Seconds until the oldest recorded traffic leaves the window
+    pub resets_in_secs: u64,
+}
+
+impl Usage {
+    /// Whether the client has used up its allowance
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining_bytes == Some(0)
+    }
+}
+
+/// Traffic recorded for one client, oldest bucket first
+#[derive(Debug, Default)]
+struct ClientUsage {
+    /// (bucket start in seconds since the epoch, bytes, requests)
+    buckets: VecDeque<(u64, u64, u64)>,
+}
+
+impl ClientUsage {
+    /// Drop buckets that ended before the window starting at `now - window`
+    fn prune(&mut self, now: u64, window: u64) {
+        while let Some(&(start, _, _)) = self.buckets.front() {
+            if start + BUCKET_SECS <= now.saturating_sub(window) {
+                self.buckets.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Rolling per-client byte counters shared by all requests
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    clients: Arc<Mutex<HashMap<ClientId, ClientUsage>>>,
+}
+
+impl UsageTracker {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one request of `bytes` for a client
+    pub fn record(&self, client: &ClientId, bytes: u64, config: &QuotaConfig) {
+        self.record_at(client, bytes, config, unix_now());
+    }
+
+    /// Usage of a client over the current window
+    pub fn usage(&self, client: &ClientId, config: &QuotaConfig) -> Usage {
+        self.usage_at(client, config, unix_now())
+    }
+
+    fn record_at(&self, client: &ClientId, bytes: u64, config: &QuotaConfig, now: u64) {
+        let bucket = now - now % BUCKET_SECS;
+        let mut clients = self.clients.lock();
+
+        // Forget clients whose traffic has left the window entirely
+        clients.retain(|_, usage| {
+            usage.prune(now, config.window_secs);
+            !usage.buckets.is_empty()
+        });
+
+        let usage = clients.entry(client.clone()).or_default();
+        match usage.buckets.back_mut() {
+            Some((start, total, requests)) if *start == bucket => {
+                *total += bytes;
+                *requests += 1;
+            }
+            _ => usage.buckets.push_back((bucket, bytes, 1)),
+        }
+    }
+
+    fn usage_at(&self, client: &ClientId, config: &QuotaConfig, now: u64) -> Usage {
+        let window = config.window_secs;
+        let mut clients = self.clients.lock();
+
+        let (used_bytes, requests, resets_in_secs) = match clients.get_mut(client) {
+            Some(usage) => {
+                usage.prune(now, window);
+                let used = usage.buckets.iter().map(|&(_, bytes, _)| bytes).sum();
+                let requests = usage.buckets.iter().map(|&(_, _, count)| count).sum();
+                let resets = usage
+                    .buckets
+                    .front()
+                    .map(|&(start, _, _)| (start + BUCKET_SECS + window).saturating_sub(now))
+                    .unwrap_or(0);
+                (used, requests, resets)
+            }
+            None => (0, 0, 0),
+        };
+
+        let limit_bytes = config.limit_for(client);
+        Usage {
+            client: client.to_string(),
+            used_bytes,
+            requests,
+            limit_bytes,
+            remaining_bytes: limit_bytes.map(|limit| limit.saturating_sub(used_bytes)),
+            window_secs: window,
+            resets_in_secs,
+        }
+    }
+}
+
+/// Account request and response sizes and reject clients over quota
+///
+/// Request bytes are the request target plus any declared body; response
+/// bytes are the size of the response body.
+pub async fn quota_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if EXEMPT_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let config = &state.config.server.quotas;
+    let addr = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    let client = ClientId::from_request(request.headers(), addr);
+
+    let path = request.uri().path().to_string();
+    let work_cost = if config.weights.contains_key(&path) {
+        let cells = estimate_cells(&state, &path, request.uri().query().unwrap_or(""));
+        config.work_cost(&path, cells)
+    } else {
+        0
+    };
+
+    let usage = state.usage.usage(&client, config);
+    let affordable = usage
+        .remaining_bytes
+        .is_none_or(|remaining| work_cost <= remaining);
+    if usage.is_exhausted() || !affordable {
+        return quota_exceeded_response(usage, &path);
+    }
+
+    let request_bytes = request.uri().to_string().len() as u64
+        + request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0);
+
+    let response = next.run(request).await;
+
+    let size_hint = response.body().size_hint();
+    let response_bytes = size_hint.exact().unwrap_or(size_hint.lower());
+    state
+        .usage
+        .record(&client, request_bytes + response_bytes + work_cost, config);
+
+    response
+}
+
+/// Build the structured 429 response for a client over quota
+fn quota_exceeded_response(usage: Usage, endpoint: &str) -> Response {
+    let request_id = generate_request_id();
+    let error = RossbyError::QuotaExceeded {
+        client: usage.client.clone(),
+        used: usage.used_bytes,
+        limit: usage.limit_bytes.unwrap_or(0),
+    };
+    log_request_error(&error, endpoint, &request_id, None);
+
+    let retry_after = usage.resets_in_secs.max(1);
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(serde_json::json!({
+            "error": error.to_string(),
+            "request_id": request_id,
+            "schema_version": SCHEMA_VERSION,
+            "usage": usage,
+        })),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(daily_bytes: Option<u64>) -> QuotaConfig {
+        QuotaConfig {
+            daily_bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_client_identification() {
+        let addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            ClientId::from_request(&headers, Some(addr)).to_string(),
+            "ip:10.0.0.1"
+        );
+        assert_eq!(ClientId::from_request(&headers, None), ClientId::Unknown);
+
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("team-a"));
+        assert_eq!(
+            ClientId::from_request(&headers, Some(addr)),
+            ClientId::ApiKey("team-a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let tracker = UsageTracker::new();
+        let config = quota(Some(1000));
+        let client = ClientId::ApiKey("a".to_string());
+        let day = config.window_secs;
+
+        tracker.record_at(&client, 300, &config, 60);
+        tracker.record_at(&client, 500, &config, 90);
+        tracker.record_at(&client, 200, &config, 3600);
+
+        let usage = tracker.usage_at(&client, &config, 3600);
+        assert_eq!(usage.used_bytes, 1000);
+        assert_eq!(usage.requests, 3);
+        assert_eq!(usage.remaining_bytes, Some(0));
+        assert!(usage.is_exhausted());
+        assert_eq!(usage.resets_in_secs, 60 + BUCKET_SECS + day - 3600);
+
+        // The first bucket leaves the window one day after it ended
+        let usage = tracker.usage_at(&client, &config, 120 + day);
+        assert_eq!(usage.used_bytes, 200);
+        assert_eq!(usage.remaining_bytes, Some(800));
+
+        // Other clients are accounted separately
+        let other = ClientId::Ip("127.0.0.1".parse().unwrap());
+        assert_eq!(tracker.usage_at(&other, &config, 3600).used_bytes, 0);
+    }
+
+    #[test]
+    fn test_unlimited_and_per_key_limits() {
+        let tracker = UsageTracker::new();
+        let mut config = quota(None);
+        let client = ClientId::ApiKey("vip".to_string());
+        tracker.record_at(&client, 5000, &config, 0);
+
+        let usage = tracker.usage_at(&client, &config, 0);
+        assert_eq!(usage.limit_bytes, None);
+        assert!(!usage.is_exhausted());
+
+        config.daily_bytes = Some(1000);
+        config.keys.insert("vip".to_string(), 10_000);
+        assert_eq!(
+            tracker.usage_at(&client, &config, 0).remaining_bytes,
+            Some(5000)
+        );
+    }
+
+    #[test]
+    fn test_work_cost() {
+        let mut config = quota(None);
+        config.weights.insert("/image".to_string(), 0.25);
+        assert_eq!(config.work_cost("/image", 800 * 600), 120_000);
+        assert_eq!(config.work_cost("/image", 1), 1);
+        assert_eq!(config.work_cost("/point", 5), 0);
+    }
+}
@@ -0,0 +1,470 @@
+//! Replay of recorded traffic against another instance.
+//!
+//! `rossby replay access.jsonl --target http://staging:8000` sends every
+//! `GET` request of an access log (see [`crate::access_log`]) to the target
+//! and compares the responses with the recorded ones: status codes, body
+//! checksums of successful responses, and latencies. This validates a
+//! refactored or reconfigured deployment against real production traffic.
+//!
+//! Error bodies carry a fresh request id, so only the bodies of successful
+//! responses are compared. Other methods are skipped, as they may change the
+//! target's state. Replayed latencies include the network round trip to the
+//! target, so only large slowdowns are reported, and they do not count as
+//! differences. The command fails when any status, body or request differs.
+
+use clap::Parser;
+use futures::stream::{self, StreamExt};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::access_log::AccessRecord;
+use crate::artifact::sha256_hex;
+use crate::error::{Result, RossbyError};
+
+/// Command-line subcommand that replays an access log
+pub const REPLAY_COMMAND: &str = "replay";
+
+/// Timeout applied to every replayed request
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Slowdowns smaller than this are never reported, whatever the ratio
+const MIN_SLOWDOWN_MS: f64 = 5.0;
+
+/// Command-line arguments of `rossby replay`
+#[derive(Parser, Debug)]
+#[command(name = "rossby replay", bin_name = "rossby replay")]
+#[command(about = "Replay an access log against another instance and compare the responses")]
+pub struct ReplayArgs {
+    /// Access log to replay, as written with `server.access_log`
+    pub log: PathBuf,
+
+    /// Base URL of the instance to replay against, e.g. http://staging:8000
+    #[arg(long)]
+    pub target: String,
+
+    /// Number of requests in flight at once
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// Report replayed requests slower than this factor of the recorded latency
+    #[arg(long, default_value_t = 2.0)]
+    pub slowdown: f64,
+
+    /// Replay only the first records of the log
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+/// Response of a replayed request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replayed {
+    /// Response status code
+    pub status: u16,
+    /// Time until the response body was received
+    pub duration_ms: f64,
+    /// Size of the response body
+    pub bytes: u64,
+    /// Hex-encoded SHA-256 of the response body
+    pub sha256: String,
+}
+
+/// How a replayed response differs from the recorded one
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// The status codes differ
+    Status { recorded: u16, replayed: u16 },
+    /// Both requests succeeded with different bodies
+    Body {
+        recorded_bytes: u64,
+        replayed_bytes: u64,
+    },
+    /// The replayed request was much slower
+    Slower { recorded_ms: f64, replayed_ms: f64 },
+    /// The replayed request could not be sent or its body not received
+    Failed(String),
+}
+
+impl Difference {
+    /// Whether the difference makes the replay fail
+    pub fn is_mismatch(&self) -> bool {
+        !matches!(self, Difference::Slower { .. })
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Status { recorded, replayed } => {
+                write!(f, "status {} -> {}", recorded, replayed)
+            }
+            Difference::Body {
+                recorded_bytes,
+                replayed_bytes,
+            } => write!(
+                f,
+                "body checksum differs ({} -> {} bytes)",
+                recorded_bytes, replayed_bytes
+            ),
+            Difference::Slower {
+                recorded_ms,
+                replayed_ms,
+            } => write!(f, "latency {:.1}ms -> {:.1}ms", recorded_ms, replayed_ms),
+            Difference::Failed(message) => write!(f, "request failed: {}", message),
+        }
+    }
+}
+
+/// Compare a replayed response with the recorded one
+pub fn compare(record: &AccessRecord, replayed: &Replayed, slowdown: f64) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    if record.status != replayed.status {
+        differences.push(Difference::Status {
+            recorded: record.status,
+            replayed: replayed.status,
+        });
+    } else if (200..300).contains(&record.status) && record.sha256 != replayed.sha256 {
+        differences.push(Difference::Body {
+            recorded_bytes: record.bytes,
+            replayed_bytes: replayed.bytes,
+        });
+    }
+    if replayed.duration_ms > record.duration_ms * slowdown
+        && replayed.duration_ms - record.duration_ms >= MIN_SLOWDOWN_MS
+    {
+        differences.push(Difference::Slower {
+            recorded_ms: record.duration_ms,
+            replayed_ms: replayed.duration_ms,
+        });
+    }
+    differences
+}
+
+/// Counts and latencies of a replay
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplaySummary {
+    /// Requests sent to the target
+    pub replayed: usize,
+    /// Records not replayed because of their method
+    pub skipped: usize,
+    /// Requests answered with another status code
+    pub status_mismatches: usize,
+    /// Successful requests answered with another body
+    pub body_mismatches: usize,
+    /// Requests that could not be completed
+    pub failed: usize,
+    /// Requests reported as much slower
+    pub slower: usize,
+    /// Recorded latencies of the replayed requests
+    pub recorded_ms: Vec<f64>,
+    /// Latencies of the replayed requests
+    pub replayed_ms: Vec<f64>,
+}
+
+impl ReplaySummary {
+    /// Account for one replayed request
+    pub fn add(
+        &mut self,
+        record: &AccessRecord,
+        replayed: Option<&Replayed>,
+        differences: &[Difference],
+    ) {
+        self.replayed += 1;
+        self.recorded_ms.push(record.duration_ms);
+        if let Some(replayed) = replayed {
+            self.replayed_ms.push(replayed.duration_ms);
+        }
+        for difference in differences {
+            match difference {
+                Difference::Status { .. } => self.status_mismatches += 1,
+                Difference::Body { .. } => self.body_mismatches += 1,
+                Difference::Slower { .. } => self.slower += 1,
+                Difference::Failed(_) => self.failed += 1,
+            }
+        }
+    }
+
+    /// Number of requests whose responses differ
+    pub fn differing(&self) -> usize {
+        self.status_mismatches + self.body_mismatches + self.failed
+    }
+}
+
+impl fmt::Display for ReplaySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Replayed {} requests ({} skipped)",
+            self.replayed, self.skipped
+        )?;
+        writeln!(f, "  status mismatches: {}", self.status_mismatches)?;
+        writeln!(f, "  body mismatches:   {}", self.body_mismatches)?;
+        writeln!(f, "  failed:            {}", self.failed)?;
+        writeln!(f, "  much slower:       {}", self.slower)?;
+        for (label, p) in [("p50", 0.5), ("p95", 0.95)] {
+            let recorded = percentile(&self.recorded_ms, p);
+            let replayed = percentile(&self.replayed_ms, p);
+            if let (Some(recorded), Some(replayed)) = (recorded, replayed) {
+                writeln!(
+                    f,
+                    "  latency {}:       {:.1}ms recorded, {:.1}ms replayed",
+                    label, recorded, replayed
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Nearest-rank percentile of some values
+pub(crate) fn percentile(values: &[f64], p: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Read the records of an access log, returning the `GET` records and the
+/// number of records skipped for their method
+pub fn read_log(path: &Path, limit: Option<usize>) -> Result<(Vec<AccessRecord>, usize)> {
+    let text = std::fs::read_to_string(path)?;
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if limit.is_some_and(|limit| records.len() + skipped >= limit) {
+            break;
+        }
+        let record: AccessRecord =
+            serde_json::from_str(line).map_err(|e| RossbyError::InvalidParameter {
+                param: "log".to_string(),
+                message: format!("Invalid record on line {}: {}", number + 1, e),
+            })?;
+        if record.method.eq_ignore_ascii_case("GET") {
+            records.push(record);
+        } else {
+            skipped += 1;
+        }
+    }
+    Ok((records, skipped))
+}
+
+/// Send one recorded request to the target
+async fn replay_request(
+    client: &reqwest::Client,
+    target: &str,
+    record: &AccessRecord,
+) -> std::result::Result<Replayed, String> {
+    let url = format!("{}{}", target, record.path_and_query());
+    let started = Instant::now();
+    let response = client
+        .get(&url)
+        .timeout(REPLAY_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status().as_u16();
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    Ok(Replayed {
+        status,
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        bytes: body.len() as u64,
+        sha256: sha256_hex(&body),
+    })
+}
+
+/// Replay the requests of an access log against a target instance
+///
+/// Each difference is printed as it is found, followed by a summary.
+pub async fn replay(
+    records: Vec<AccessRecord>,
+    target: &str,
+    concurrency: usize,
+    slowdown: f64,
+) -> ReplaySummary {
+    let client = reqwest::Client::new();
+    let target = target.trim_end_matches('/');
+    let mut results = stream::iter(records)
+        .map(|record| {
+            let client = &client;
+            async move {
+                let replayed = replay_request(client, target, &record).await;
+                (record, replayed)
+            }
+        })
+        .buffered(concurrency);
+
+    let mut summary = ReplaySummary::default();
+    while let Some((record, replayed)) = results.next().await {
+        let differences = match &replayed {
+            Ok(replayed) => compare(&record, replayed, slowdown),
+            Err(message) => vec![Difference::Failed(message.clone())],
+        };
+        for difference in &differences {
+            println!(
+                "{} {}: {}",
+                record.method,
+                record.path_and_query(),
+                difference
+            );
+        }
+        summary.add(&record, replayed.as_ref().ok(), &differences);
+    }
+    summary
+}
+
+/// Replay an access log as requested on the command line
+pub async fn run(args: ReplayArgs) -> Result<()> {
+    if args.concurrency == 0 {
+        return Err(RossbyError::InvalidParameter {
+            param: "concurrency".to_string(),
+            message: "Concurrency must be greater than 0".to_string(),
+        });
+    }
+    if !(args.slowdown.is_finite() && args.slowdown > 0.0) {
+        return Err(RossbyError::InvalidParameter {
+            param: "slowdown".to_string(),
+            message: "Slowdown must be a positive number".to_string(),
+        });
+    }
+    if !args.target.starts_with("http://") && !args.target.starts_with("https://") {
+        return Err(RossbyError::InvalidParameter {
+            param: "target".to_string(),
+            message: format!(
+                "Invalid target URL: {}. Must start with http:// or https://",
+                args.target
+            ),
+        });
+    }
+
+    let (records, skipped) = read_log(&args.log, args.limit)?;
+    info!(
+        log = %args.log.display(),
+        target = %args.target,
+        records = records.len(),
+        "Replaying access log"
+    );
+
+    let mut summary = replay(records, &args.target, args.concurrency, args.slowdown).await;
+    summary.skipped = skipped;
+    print!("{}", summary);
+
+    match summary.differing() {
+        0 => Ok(()),
+        differing => Err(RossbyError::ReplayMismatch {
+            differing,
+            replayed: summary.replayed,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status: u16, duration_ms: f64, body: &[u8]) -> AccessRecord {
+        AccessRecord {
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            method: "GET".to_string(),
+            path: "/point".to_string(),
+            query: "lon=0&lat=0&vars=t2m".to_string(),
+            status,
+            duration_ms,
+            bytes: body.len() as u64,
+            sha256: sha256_hex(body),
+        }
+    }
+
+    fn replayed(status: u16, duration_ms: f64, body: &[u8]) -> Replayed {
+        Replayed {
+            status,
+            duration_ms,
+            bytes: body.len() as u64,
+            sha256: sha256_hex(body),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let recorded = record(200, 10.0, b"{\"t2m\":280.5}");
+        assert!(compare(&recorded, &replayed(200, 12.0, b"{\"t2m\":280.5}"), 2.0).is_empty());
+
+        assert_eq!(
+            compare(&recorded, &replayed(200, 10.0, b"{\"t2m\":281.0}"), 2.0),
+            vec![Difference::Body {
+                recorded_bytes: 13,
+                replayed_bytes: 13
+            }]
+        );
+        assert_eq!(
+            compare(&recorded, &replayed(500, 10.0, b"{}"), 2.0),
+            vec![Difference::Status {
+                recorded: 200,
+                replayed: 500
+            }]
+        );
+
+        // Error bodies carry request ids and are not compared
+        let recorded_error = record(400, 1.0, b"{\"request_id\":\"a\"}");
+        assert!(compare(
+            &recorded_error,
+            &replayed(400, 1.0, b"{\"request_id\":\"b\"}"),
+            2.0
+        )
+        .is_empty());
+
+        // Slowdowns count only above both the factor and the floor
+        let slower = compare(&recorded, &replayed(200, 30.0, b"{\"t2m\":280.5}"), 2.0);
+        assert_eq!(slower.len(), 1);
+        assert!(!slower[0].is_mismatch());
+        let quick = record(200, 0.5, b"{}");
+        assert!(compare(&quick, &replayed(200, 3.0, b"{}"), 2.0).is_empty());
+    }
+
+    #[test]
+    fn test_read_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.jsonl");
+        let mut post = record(200, 1.0, b"{}");
+        post.method = "POST".to_string();
+        let lines = [record(200, 1.0, b"{}"), post, record(404, 1.0, b"{}")]
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        std::fs::write(&path, lines).unwrap();
+
+        let (records, skipped) = read_log(&path, None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(skipped, 1);
+        assert_eq!(records[1].status, 404);
+
+        let (records, skipped) = read_log(&path, Some(1)).unwrap();
+        assert_eq!((records.len(), skipped), (1, 0));
+
+        std::fs::write(&path, "{\"method\":\"GET\"}\n").unwrap();
+        let error = read_log(&path, None).unwrap_err();
+        assert!(error.to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut summary = ReplaySummary::default();
+        let recorded = record(200, 10.0, b"{}");
+        summary.add(&recorded, Some(&replayed(200, 11.0, b"{}")), &[]);
+        let failure = Difference::Failed("connection refused".to_string());
+        summary.add(&recorded, None, std::slice::from_ref(&failure));
+        assert_eq!(summary.replayed, 2);
+        assert_eq!(summary.differing(), 1);
+        assert_eq!(percentile(&summary.replayed_ms, 0.5), Some(11.0));
+        assert!(summary.to_string().contains("failed:            1"));
+        assert_eq!(percentile(&[], 0.5), None);
+        assert_eq!(percentile(&[3.0, 1.0, 2.0, 4.0], 0.95), Some(4.0));
+    }
+}
@@ -0,0 +1,237 @@
+//! Statistics of values seen one at a time, in constant memory.
+//!
+//! [`RunningStats`] keeps the count, extremes, mean and variance of a stream
+//! (Welford's algorithm), and [`P2Quantile`] estimates one percentile with
+//! the P² algorithm of Jain and Chlamtac (1985), which tracks five markers
+//! instead of keeping the values. Percentile estimates are exact for up to
+//! five values and converge to the true percentile as the stream grows.
+
+use crate::error::{Result, RossbyError};
+use crate::field::FieldStats;
+
+/// Count, extremes, mean and variance of the finite values of a stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunningStats {
+    count: usize,
+    min: f64,
+    max: f64,
+    mean: f64,
+    /// Sum of squared differences from the mean
+    m2: f64,
+}
+
+impl RunningStats {
+    /// Statistics of an empty stream
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a value; non-finite values are skipped
+    pub fn push(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Number of finite values seen
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Summary of the values seen, with the population standard deviation
+    pub fn stats(&self) -> FieldStats {
+        let defined = |value: f64| (self.count > 0).then_some(value);
+        FieldStats {
+            count: self.count,
+            min: defined(self.min),
+            max: defined(self.max),
+            mean: defined(self.mean),
+            std: defined((self.m2 / self.count.max(1) as f64).sqrt()),
+        }
+    }
+}
+
+/// Streaming estimate of one percentile of the finite values of a stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct P2Quantile {
+    /// Percentile as a fraction in (0, 1)
+    p: f64,
+    /// Marker heights; the first values seen until there are five
+    heights: Vec<f64>,
+    /// Actual marker positions (0-based)
+    positions: [f64; 5],
+    /// Desired marker positions
+    desired: [f64; 5],
+    /// Increments of the desired positions per value
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    /// Estimator of the `percentile`-th percentile, between 0 and 100
+    /// exclusive
+    pub fn new(percentile: f64) -> Result<Self> {
+        if !(percentile > 0.0 && percentile < 100.0) {
+            return Err(RossbyError::InvalidParameter {
+                param: "percentiles".to_string(),
+                message: format!(
+                    "Percentile {} is out of range; use min and max for 0 and 100",
+                    percentile
+                ),
+            });
+        }
+        let p = percentile / 100.0;
+        Ok(Self {
+            p,
+            heights: Vec::with_capacity(5),
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired: [0.0, 2.0 * p, 4.0 * p, 2.0 + 2.0 * p, 4.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        })
+    }
+
+    /// Add a value; non-finite values are skipped
+    pub fn push(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        if self.heights.len() < 5 {
+            self.heights.push(value);
+            if self.heights.len() == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+
+        // Cell of the new value, extending the extreme markers if needed
+        let q = &mut self.heights;
+        let cell = if value < q[0] {
+            q[0] = value;
+            0
+        } else if value >= q[4] {
+            q[4] = value;
+            3
+        } else {
+            (1..5).find(|&i| value < q[i]).map_or(3, |i| i - 1)
+        };
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(&self.increments) {
+            *desired += increment;
+        }
+
+        // Move the middle markers towards their desired positions
+        for i in 1..4 {
+            let offset = self.desired[i] - self.positions[i];
+            let n = &self.positions;
+            if (offset >= 1.0 && n[i + 1] - n[i] > 1.0)
+                || (offset <= -1.0 && n[i - 1] - n[i] < -1.0)
+            {
+                let step = offset.signum();
+                let parabolic = self.parabolic(i, step);
+                let q = &self.heights;
+                let height = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                    parabolic
+                } else {
+                    self.linear(i, step)
+                };
+                self.heights[i] = height;
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    /// Estimated percentile, or None before any value
+    pub fn value(&self) -> Option<f64> {
+        match self.heights.len() {
+            0 => None,
+            5 if self.positions[4] > 4.0 => Some(self.heights[2]),
+            _ => {
+                // Few values: interpolate between the sorted values
+                let mut sorted = self.heights.clone();
+                sorted.sort_by(f64::total_cmp);
+                let rank = self.p * (sorted.len() - 1) as f64;
+                let (below, fraction) = (rank.floor() as usize, rank.fract());
+                let above = (below + 1).min(sorted.len() - 1);
+                Some(sorted[below] + fraction * (sorted[above] - sorted[below]))
+            }
+        }
+    }
+
+    /// Piecewise-parabolic prediction of marker `i` moved by `step`
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + step / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    /// Linear prediction of marker `i` moved by `step`
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        let j = if step > 0.0 { i + 1 } else { i - 1 };
+        q[i] + step * (q[j] - q[i]) / (n[j] - n[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_stats() {
+        let mut stats = RunningStats::new();
+        assert_eq!(stats.stats().mean, None);
+        for value in [2.0, 4.0, f64::NAN, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(value);
+        }
+        let summary = stats.stats();
+        assert_eq!(summary.count, 8);
+        assert_eq!(summary.min, Some(2.0));
+        assert_eq!(summary.max, Some(9.0));
+        assert_eq!(summary.mean, Some(5.0));
+        assert!((summary.std.unwrap() - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_p2_quantile() {
+        // Exact for few values
+        let mut median = P2Quantile::new(50.0).unwrap();
+        assert_eq!(median.value(), None);
+        for value in [3.0, 1.0, 2.0] {
+            median.push(value);
+        }
+        assert_eq!(median.value(), Some(2.0));
+
+        // Close to the true percentiles of a long stream
+        let mut estimators: Vec<P2Quantile> = [10.0, 50.0, 90.0]
+            .iter()
+            .map(|&p| P2Quantile::new(p).unwrap())
+            .collect();
+        for i in 0..10_000u64 {
+            // A permutation of 0..10000
+            let value = ((i * 7_919) % 10_000) as f64;
+            for estimator in &mut estimators {
+                estimator.push(value);
+            }
+        }
+        for (estimator, expected) in estimators.iter().zip([1_000.0, 5_000.0, 9_000.0]) {
+            let estimate = estimator.value().unwrap();
+            assert!((estimate - expected).abs() < 100.0, "{}", estimate);
+        }
+
+        assert!(P2Quantile::new(0.0).is_err());
+        assert!(P2Quantile::new(100.0).is_err());
+    }
+}
@@ -0,0 +1,75 @@
+//! Reproducible random sampling.
+//!
+//! [`SplitMix64`] is a small, fast generator whose sequence is fully
+//! determined by its seed, so that a sample can be drawn again with the same
+//! seed, e.g. by a QA pipeline re-checking the cells it flagged.
+//! [`sample_positions`] draws distinct positions with Floyd's algorithm, in
+//! memory proportional to the sample rather than the population.
+
+use std::collections::HashSet;
+
+/// SplitMix64 pseudo-random number generator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    /// Generator starting from a seed
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Next number of the sequence
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed number below `bound`, which must not be zero
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+/// Distinct positions below `population`, `n` of them or all if fewer, in
+/// increasing order
+pub fn sample_positions(rng: &mut SplitMix64, population: usize, n: usize) -> Vec<usize> {
+    let n = n.min(population);
+    let mut chosen = HashSet::with_capacity(n);
+    for j in population - n..population {
+        let position = rng.below(j + 1);
+        if !chosen.insert(position) {
+            chosen.insert(j);
+        }
+    }
+    let mut positions: Vec<usize> = chosen.into_iter().collect();
+    positions.sort_unstable();
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_positions() {
+        let sample = sample_positions(&mut SplitMix64::new(42), 1000, 50);
+        assert_eq!(sample.len(), 50);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(sample.iter().all(|&position| position < 1000));
+
+        // The same seed draws the same sample, another seed another one
+        assert_eq!(sample_positions(&mut SplitMix64::new(42), 1000, 50), sample);
+        assert_ne!(sample_positions(&mut SplitMix64::new(7), 1000, 50), sample);
+
+        assert_eq!(
+            sample_positions(&mut SplitMix64::new(1), 5, 10),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert!(sample_positions(&mut SplitMix64::new(1), 0, 10).is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+//! Versioned, canonical JSON responses.
+//!
+//! Every JSON object response carries a `schema_version` field, and its keys
+//! are written in a canonical order: the keys of every object sorted
+//! lexicographically, at every level. The same response is thus always the
+//! same bytes, so clients with strict schemas and diff-based tests do not
+//! break on incidental reordering between releases.
+//!
+//! Breaking changes to the shape of responses will come with a new schema
+//! version. Clients pin the version they were written against with the
+//! `schema_version` query parameter or the `X-Rossby-Schema-Version` request
+//! header; versions this server cannot produce are rejected with
+//! `400 Bad Request` rather than answered in another shape. The version of
+//! every JSON response is also returned in the `X-Rossby-Schema-Version`
+//! response header.
+//!
+//! Buffered responses of the handlers are canonicalized here. Streamed
+//! responses (the JSON format of `/data`) are written in canonical order by
+//! their handlers, and the rejections of the middlewares around the handlers
+//! (quotas, generations, body limits, products) include [`SCHEMA_VERSION`]
+//! themselves, so quotas account for the bytes actually sent.
+
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::warn;
+
+use crate::error::{Result, RossbyError};
+use crate::logging::{generate_request_id, log_request_error};
+
+/// Schema version of the JSON responses of this release
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Schema versions the server can produce
+pub const SUPPORTED_SCHEMA_VERSIONS: [u32; 1] = [SCHEMA_VERSION];
+
+/// Query parameter and response field naming the schema version
+pub const SCHEMA_VERSION_PARAM: &str = "schema_version";
+
+/// Request and response header naming the schema version
+pub const SCHEMA_VERSION_HEADER: &str = "x-rossby-schema-version";
+
+/// Split `schema_version` off a query string
+///
+/// Returns the requested version, if any, and the remaining query to pass on
+/// to the handler. Queries without the parameter are returned as `None` so
+/// the request can be forwarded unchanged.
+pub fn take_schema_version(query: &str) -> Result<Option<(u32, String)>> {
+    let params: Vec<(String, String)> = match serde_urlencoded::from_str(query) {
+        Ok(params) => params,
+        Err(_) => return Ok(None),
+    };
+    if !params.iter().any(|(key, _)| key == SCHEMA_VERSION_PARAM) {
+        return Ok(None);
+    }
+
+    let mut requested = None;
+    let mut rest = Vec::with_capacity(params.len());
+    for (key, value) in params {
+        if key != SCHEMA_VERSION_PARAM {
+            rest.push((key, value));
+            continue;
+        }
+        let version = parse_version(SCHEMA_VERSION_PARAM, &value)?;
+        if requested.is_some_and(|r| r != version) {
+            return Err(RossbyError::InvalidParameter {
+                param: SCHEMA_VERSION_PARAM.to_string(),
+                message: "Conflicting schema versions requested".to_string(),
+            });
+        }
+        requested = Some(version);
+    }
+
+    let rest = serde_urlencoded::to_string(&rest).map_err(|e| RossbyError::Server {
+        message: format!("Failed to encode query: {}", e),
+    })?;
+    Ok(requested.map(|version| (version, rest)))
+}
+
+/// Parse a requested schema version, which must be one the server supports
+fn parse_version(param: &str, value: &str) -> Result<u32> {
+    let unsupported = || RossbyError::InvalidParameter {
+        param: param.to_string(),
+        message: format!(
+            "Unsupported schema version '{}'. Supported versions: {:?}",
+            value, SUPPORTED_SCHEMA_VERSIONS
+        ),
+    };
+    let version = value.trim().parse::<u32>().map_err(|_| unsupported())?;
+    if !SUPPORTED_SCHEMA_VERSIONS.contains(&version) {
+        return Err(unsupported());
+    }
+    Ok(version)
+}
+
+/// The schema version requested in the headers of a request, if any
+fn header_version(headers: &HeaderMap) -> Result<Option<u32>> {
+    headers
+        .get(SCHEMA_VERSION_HEADER)
+        .map(|value| {
+            let value = value.to_str().unwrap_or_default();
+            parse_version(SCHEMA_VERSION_HEADER, value)
+        })
+        .transpose()
+}
+
+/// Canonical bytes of a JSON body, with the schema version added to objects
+///
+/// Objects are held in sorted maps by `serde_json`, so writing a parsed value
+/// back orders the keys of every object.
+pub fn canonical_json(body: &[u8], version: u32) -> Result<Vec<u8>> {
+    let mut value: serde_json::Value = serde_json::from_slice(body)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert(SCHEMA_VERSION_PARAM.to_string(), version.into());
+    }
+    Ok(serde_json::to_vec(&value)?)
+}
+
+/// Whether a response carries a JSON body
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(';').next().unwrap_or_default().trim() == "application/json"
+        })
+}
+
+/// Negotiate the schema version and canonicalize JSON responses
+pub async fn schema_middleware(mut request: Request, next: Next) -> Response {
+    let from_query = match request.uri().query().map(take_schema_version) {
+        Some(Ok(taken)) => taken,
+        Some(Err(error)) => return schema_error_response(error, request.uri().path()),
+        None => None,
+    };
+    let from_header = match header_version(request.headers()) {
+        Ok(version) => version,
+        Err(error) => return schema_error_response(error, request.uri().path()),
+    };
+    let version = from_query
+        .as_ref()
+        .map(|(version, _)| *version)
+        .or(from_header)
+        .unwrap_or(SCHEMA_VERSION);
+
+    // Handlers do not see the parameter
+    if let Some((_, rest)) = from_query {
+        let path = request.uri().path();
+        let uri = if rest.is_empty() {
+            path.parse::<Uri>()
+        } else {
+            format!("{}?{}", path, rest).parse::<Uri>()
+        };
+        match uri {
+            Ok(uri) => *request.uri_mut() = uri,
+            Err(e) => {
+                let error = RossbyError::Server {
+                    message: format!("Failed to rewrite request URI: {}", e),
+                };
+                return schema_error_response(error, request.uri().path());
+            }
+        }
+    }
+
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if !is_json(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert(SCHEMA_VERSION_HEADER, HeaderValue::from(version));
+    // Streamed bodies are written in canonical order by their handlers
+    if body.size_hint().exact().is_none() {
+        return Response::from_parts(parts, body);
+    }
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(path = %path, error = %e, "Failed to buffer JSON response");
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return response;
+        }
+    };
+    match canonical_json(&body, version) {
+        Ok(canonical) => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(canonical))
+        }
+        Err(e) => {
+            warn!(path = %path, error = %e, "Response is not valid JSON; sent as is");
+            Response::from_parts(parts, Body::from(body))
+        }
+    }
+}
+
+/// Build the error response for a rejected schema version
+fn schema_error_response(error: RossbyError, endpoint: &str) -> Response {
+    let request_id = generate_request_id();
+    log_request_error(&error, endpoint, &request_id, None);
+
+    let status = match &error {
+        RossbyError::Server { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "error": error.to_string(),
+            "request_id": request_id,
+            "schema_version": SCHEMA_VERSION,
+            "supported_schema_versions": SUPPORTED_SCHEMA_VERSIONS,
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_schema_version() {
+        assert_eq!(take_schema_version("var=t2m").unwrap(), None);
+        assert_eq!(
+            take_schema_version("var=t2m&schema_version=1&time=0").unwrap(),
+            Some((1, "var=t2m&time=0".to_string()))
+        );
+        for invalid in [
+            "schema_version=2",
+            "schema_version=latest",
+            "schema_version=0",
+        ] {
+            assert!(matches!(
+                take_schema_version(invalid),
+                Err(RossbyError::InvalidParameter { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let body = br#"{"zeta": {"b": 1, "a": [{"y": 2, "x": 1}]}, "alpha": null}"#;
+        let canonical = canonical_json(body, 1).unwrap();
+        assert_eq!(
+            String::from_utf8(canonical).unwrap(),
+            r#"{"alpha":null,"schema_version":1,"zeta":{"a":[{"x":1,"y":2}],"b":1}}"#
+        );
+        // Non-object bodies are only reordered
+        assert_eq!(
+            canonical_json(br#"[{"b":1,"a":2}]"#, 1).unwrap(),
+            br#"[{"a":2,"b":1}]"#
+        );
+    }
+}
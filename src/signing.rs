@@ -0,0 +1,296 @@
+//! Detached Ed25519 signatures of responses.
+//!
+//! With `server.signing_key_file` set, every successful (`200 OK`) response
+//! other than the operational endpoints carries a detached Ed25519 signature
+//! in `X-Rossby-Signature`. The signature covers the dataset fingerprint
+//! (`X-Rossby-Dataset-Fingerprint`, the SHA-256 of the loaded metadata and
+//! data), the request path and query as received (`X-Rossby-Signed-Request`)
+//! and the SHA-256 of the response body, so a downstream pipeline can prove
+//! that an extraction was served from a specific dataset version. The
+//! signed message is
+//!
+//! ```text
+//! rossby-signature-v1\n<fingerprint>\n<path?query>\n<body sha256>
+//! ```
+//!
+//! with hex digests, and the public key is served by `GET /signing_key`.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::artifact::sha256_hex;
+use crate::config::ServerConfig;
+use crate::error::{Result, RossbyError};
+use crate::state::AppState;
+
+/// Header carrying the base64-encoded signature of a response
+pub const SIGNATURE_HEADER: &str = "x-rossby-signature";
+
+/// Header carrying the fingerprint of the dataset that served a response
+pub const FINGERPRINT_HEADER: &str = "x-rossby-dataset-fingerprint";
+
+/// Header carrying the request path and query covered by the signature
+pub const SIGNED_REQUEST_HEADER: &str = "x-rossby-signed-request";
+
+/// Signature scheme, as reported by `GET /signing_key`
+pub const SIGNATURE_ALGORITHM: &str = "ed25519";
+
+/// First line of every signed message, versioning its layout
+const SIGNATURE_CONTEXT: &str = "rossby-signature-v1";
+
+/// Operational endpoints whose responses are not signed
+const UNSIGNED_PATHS: &[&str] = &["/heartbeat", "/healthz", "/usage", "/signing_key"];
+
+/// Signs responses with the configured Ed25519 key
+#[derive(Clone)]
+pub struct ResponseSigner {
+    key: SigningKey,
+}
+
+impl fmt::Debug for ResponseSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseSigner")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+impl ResponseSigner {
+    /// Signer with the key derived from a 32-byte secret seed
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(seed),
+        }
+    }
+
+    /// Signer with the secret seed stored in a file, hex or base64 encoded
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let invalid = |message: String| RossbyError::Config {
+            message: format!("Invalid signing key {}: {}", path.display(), message),
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let text = text.trim();
+        let bytes = decode_hex(text)
+            .or_else(|| base64::engine::general_purpose::STANDARD.decode(text).ok())
+            .ok_or_else(|| invalid("expected a hex or base64 encoded seed".to_string()))?;
+        let seed: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| invalid(format!("expected 32 bytes, got {}", bytes.len())))?;
+        Ok(Self::from_seed(&seed))
+    }
+
+    /// Signer configured in `server.signing_key_file`, if any
+    pub fn from_config(config: &ServerConfig) -> Result<Option<Self>> {
+        config
+            .signing_key_file
+            .as_deref()
+            .map(Self::from_file)
+            .transpose()
+    }
+
+    /// Base64-encoded public key verifying the signatures
+    pub fn public_key(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(self.key.verifying_key().to_bytes())
+    }
+
+    /// Base64-encoded signature of a response body
+    pub fn sign(&self, fingerprint: &str, request: &str, body: &[u8]) -> String {
+        let signature = self.key.sign(&signed_message(fingerprint, request, body));
+        base64::engine::general_purpose::STANDARD.encode(signature.to_bytes())
+    }
+}
+
+/// Message signed for a response body
+pub fn signed_message(fingerprint: &str, request: &str, body: &[u8]) -> Vec<u8> {
+    format!(
+        "{}\n{}\n{}\n{}",
+        SIGNATURE_CONTEXT,
+        fingerprint,
+        request,
+        sha256_hex(body)
+    )
+    .into_bytes()
+}
+
+/// Check a response signature against a base64-encoded public key
+pub fn verify(
+    public_key: &str,
+    signature: &str,
+    fingerprint: &str,
+    request: &str,
+    body: &[u8],
+) -> Result<()> {
+    let invalid = |message: &str| RossbyError::InvalidParameter {
+        param: "signature".to_string(),
+        message: message.to_string(),
+    };
+    let engine = base64::engine::general_purpose::STANDARD;
+    let public_key: [u8; 32] = engine
+        .decode(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("Public key must be 32 base64-encoded bytes"))?;
+    let public_key =
+        VerifyingKey::from_bytes(&public_key).map_err(|_| invalid("Invalid public key"))?;
+    let signature: [u8; 64] = engine
+        .decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("Signature must be 64 base64-encoded bytes"))?;
+    public_key
+        .verify(
+            &signed_message(fingerprint, request, body),
+            &Signature::from_bytes(&signature),
+        )
+        .map_err(|_| invalid("Signature does not match the response"))
+}
+
+/// Sign successful responses when a signing key is configured
+pub async fn signing_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(signer) = state.signer.as_ref() else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path();
+    if UNSIGNED_PATHS.contains(&path) || path.starts_with("/admin/") {
+        return next.run(request).await;
+    }
+    let signed_request = request
+        .uri()
+        .path_and_query()
+        .map_or_else(|| path.to_string(), |pq| pq.as_str().to_string());
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(request = %signed_request, error = %e, "Failed to buffer response for signing");
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return response;
+        }
+    };
+
+    let fingerprint = state.fingerprint();
+    let signature = signer.sign(fingerprint, &signed_request, &body);
+    for (name, value) in [
+        (SIGNATURE_HEADER, signature.as_str()),
+        (FINGERPRINT_HEADER, fingerprint),
+        (SIGNED_REQUEST_HEADER, signed_request.as_str()),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            parts.headers.insert(name, value);
+        }
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Decode a hex string, if it is one
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = ResponseSigner::from_seed(&[7; 32]);
+        let public_key = signer.public_key();
+        let body = b"{\"t2m\":280.5}";
+        let signature = signer.sign("abc123", "/point?lon=0&lat=0&vars=t2m", body);
+
+        verify(
+            &public_key,
+            &signature,
+            "abc123",
+            "/point?lon=0&lat=0&vars=t2m",
+            body,
+        )
+        .unwrap();
+
+        // Any change to the dataset, the query or the payload is detected
+        assert!(verify(
+            &public_key,
+            &signature,
+            "abc124",
+            "/point?lon=0&lat=0&vars=t2m",
+            body
+        )
+        .is_err());
+        assert!(verify(
+            &public_key,
+            &signature,
+            "abc123",
+            "/point?lon=1&lat=0&vars=t2m",
+            body
+        )
+        .is_err());
+        assert!(verify(
+            &public_key,
+            &signature,
+            "abc123",
+            "/point?lon=0&lat=0&vars=t2m",
+            b"{}"
+        )
+        .is_err());
+        assert!(verify(
+            &public_key,
+            "bm90IGEgc2lnbmF0dXJl",
+            "abc123",
+            "/point",
+            body
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signing.key");
+
+        std::fs::write(&path, format!("{}\n", "07".repeat(32))).unwrap();
+        let hex = ResponseSigner::from_file(&path).unwrap();
+        assert_eq!(
+            hex.public_key(),
+            ResponseSigner::from_seed(&[7; 32]).public_key()
+        );
+
+        let seed = base64::engine::general_purpose::STANDARD.encode([7; 32]);
+        std::fs::write(&path, seed).unwrap();
+        let base64 = ResponseSigner::from_file(&path).unwrap();
+        assert_eq!(base64.public_key(), hex.public_key());
+
+        std::fs::write(&path, "0707").unwrap();
+        let error = ResponseSigner::from_file(&path).unwrap_err();
+        assert!(error.to_string().contains("expected 32 bytes, got 2"));
+        assert!(ResponseSigner::from_file(&dir.path().join("missing.key")).is_err());
+    }
+}
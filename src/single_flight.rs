@@ -0,0 +1,395 @@
+//! Coalescing of identical in-flight requests.
+//!
+//! Dashboards often issue the same expensive request from many clients at
+//! once, e.g. five viewers opening the same world map. Identical concurrent
+//! `GET` requests to `/image`, `/stats` and `/data` share one computation:
+//! the first starts it, the others wait for it, and all receive the same
+//! response, the others marked with an `X-Rossby-Coalesced` header. Requests
+//! are identical when their path, query parameters (in any order),
+//! `Range`/`If-Range` headers and API keys are, so that a response computed
+//! under the scopes of one key is never handed to another.
+//!
+//! The computation runs on a task of its own, so it completes even if the
+//! client that started it disconnects, and its response is buffered to be
+//! handed to every waiting request. Streamed responses, e.g. `/data` tables
+//! of several record batches, are not buffered: they are handed to the
+//! request that started the computation, and the requests that joined it
+//! compute their own. Requests arriving after it completed
+//! start a new one; nothing is cached. The middleware sits inside the
+//! product, quota and signing layers, so each client is still accounted for
+//! and answered on its own. Coalescing is disabled with
+//! `server.coalesce_requests: false`.
+
+use axum::{
+    body::{to_bytes, Body, Bytes, HttpBody},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use parking_lot::{Mutex, MutexGuard};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tracing::{debug, warn, Instrument};
+
+use crate::artifact::sha256_hex;
+use crate::quota::API_KEY_HEADER;
+use crate::state::AppState;
+
+/// Response header marking a response shared with an identical request
+pub const COALESCED_HEADER: &str = "x-rossby-coalesced";
+
+/// Endpoints whose identical concurrent requests are coalesced
+pub const COALESCED_ENDPOINTS: [&str; 3] = ["/image", "/stats", "/data"];
+
+/// Request headers that change the responses of the coalesced endpoints
+const KEY_HEADERS: [HeaderName; 3] = [
+    header::RANGE,
+    header::IF_RANGE,
+    HeaderName::from_static(API_KEY_HEADER),
+];
+
+/// A buffered response, handed to every request it answers
+#[derive(Debug, Clone)]
+pub struct SharedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// What a computation hands to the requests that joined it
+#[derive(Debug)]
+pub enum FlightOutcome {
+    /// A buffered response for every request
+    Shared(SharedResponse),
+    /// The response was streamed to the request that started the
+    /// computation; the others compute their own
+    Streamed,
+}
+
+impl SharedResponse {
+    /// Buffer the body of a response
+    pub async fn buffer(response: Response) -> Self {
+        let (parts, body) = response.into_parts();
+        match to_bytes(body, usize::MAX).await {
+            Ok(body) => Self {
+                status: parts.status,
+                headers: parts.headers,
+                body,
+            },
+            Err(error) => Self::error(format!("Failed to read the response: {}", error)),
+        }
+    }
+
+    /// Internal error answering every waiting request
+    fn error(message: String) -> Self {
+        let body = serde_json::json!({ "error": message }).to_string();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            headers,
+            body: Bytes::from(body),
+        }
+    }
+
+    /// A response of its own for one of the requests
+    pub fn to_response(&self) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// A computation other requests can join
+pub type Flight = Shared<BoxFuture<'static, Arc<FlightOutcome>>>;
+
+/// The computation of a request key, in flight or yet to be started
+pub enum FlightEntry<'a> {
+    /// An identical request is being computed
+    Joined(Flight),
+    /// No identical request is in flight; holds the computations locked
+    /// until one is started
+    Vacant(VacantFlight<'a>),
+}
+
+/// A request key without a computation in flight
+pub struct VacantFlight<'a> {
+    in_flight: &'a InFlightRequests,
+    flights: MutexGuard<'a, HashMap<String, Flight>>,
+    key: String,
+}
+
+impl VacantFlight<'_> {
+    /// Start `compute` for the key, for identical requests to join
+    pub fn start<F>(mut self, compute: F) -> Flight
+    where
+        F: Future<Output = FlightOutcome> + Send + 'static,
+    {
+        let flight = self.in_flight.spawn(self.key.clone(), compute);
+        self.flights.insert(self.key, flight.clone());
+        flight
+    }
+}
+
+/// Computations in flight, by request key
+#[derive(Clone, Default)]
+pub struct InFlightRequests {
+    flights: Arc<Mutex<HashMap<String, Flight>>>,
+}
+
+impl fmt::Debug for InFlightRequests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InFlightRequests")
+            .field("in_flight", &self.len())
+            .finish()
+    }
+}
+
+impl InFlightRequests {
+    /// Create an empty set of computations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of computations in flight
+    pub fn len(&self) -> usize {
+        self.flights.lock().len()
+    }
+
+    /// Whether no computation is in flight
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The computation of an identical request in flight, or the vacant
+    /// entry to start one
+    pub fn entry(&self, key: String) -> FlightEntry<'_> {
+        let flights = self.flights.lock();
+        match flights.get(&key) {
+            Some(flight) => FlightEntry::Joined(flight.clone()),
+            None => FlightEntry::Vacant(VacantFlight {
+                in_flight: self,
+                flights,
+                key,
+            }),
+        }
+    }
+
+    /// Answer a request, joining the computation of an identical one in
+    /// flight or starting `compute`
+    ///
+    /// Returns the outcome and whether it was shared with an earlier
+    /// request.
+    pub async fn run<F>(&self, key: String, compute: F) -> (Arc<FlightOutcome>, bool)
+    where
+        F: Future<Output = FlightOutcome> + Send + 'static,
+    {
+        match self.entry(key) {
+            FlightEntry::Joined(flight) => (flight.await, true),
+            FlightEntry::Vacant(vacant) => (vacant.start(compute).await, false),
+        }
+    }
+
+    /// Spawn a computation, removing it from the flights once it completes
+    fn spawn<F>(&self, key: String, compute: F) -> Flight
+    where
+        F: Future<Output = FlightOutcome> + Send + 'static,
+    {
+        let flights = self.flights.clone();
+        // Stages of the computation are profiled with the request starting it
+        let task = tokio::spawn(
+            async move {
+                let response = compute.await;
+                flights.lock().remove(&key);
+                response
+            }
+            .in_current_span(),
+        );
+        async move {
+            Arc::new(task.await.unwrap_or_else(|error| {
+                warn!(error = %error, "Coalesced request failed");
+                FlightOutcome::Shared(SharedResponse::error("The request failed".to_string()))
+            }))
+        }
+        .boxed()
+        .shared()
+    }
+}
+
+/// Key identifying identical requests, or None for requests that are not
+/// coalesced
+pub fn flight_key(request: &Request) -> Option<String> {
+    if request.method() != Method::GET {
+        return None;
+    }
+    let path = request.uri().path();
+    if !COALESCED_ENDPOINTS.contains(&path) {
+        return None;
+    }
+
+    // Parameters in any order are the same request
+    let query = request.uri().query().unwrap_or("");
+    let query = match serde_urlencoded::from_str::<Vec<(String, String)>>(query) {
+        Ok(mut params) => {
+            params.sort();
+            serde_urlencoded::to_string(params).unwrap_or_else(|_| query.to_string())
+        }
+        Err(_) => query.to_string(),
+    };
+    let mut key = format!("{}?{}", path, query);
+    for name in &KEY_HEADERS {
+        if let Some(value) = request.headers().get(name) {
+            key.push('\n');
+            key.push_str(name.as_str());
+            key.push(':');
+            // Keys are logged with coalesced requests, API keys only hashed
+            if name == API_KEY_HEADER {
+                key.push_str(&sha256_hex(value.as_bytes()));
+            } else {
+                key.push_str(&String::from_utf8_lossy(value.as_bytes()));
+            }
+        }
+    }
+    Some(key)
+}
+
+/// Share one computation between identical concurrent requests
+pub async fn single_flight_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = match flight_key(&request) {
+        Some(key) if state.config.server.coalesce_requests => key,
+        _ => return next.run(request).await,
+    };
+    // A streamed response is handed to the request starting the computation
+    // through `started`; requests joining it keep theirs to compute their own
+    let (sender, started) = oneshot::channel();
+    let (flight, joined) = match state.in_flight.entry(key.clone()) {
+        FlightEntry::Joined(flight) => (flight, Some((request, next))),
+        FlightEntry::Vacant(vacant) => {
+            let flight = vacant.start(async move {
+                let response = next.run(request).await;
+                if response.body().size_hint().exact().is_some() {
+                    FlightOutcome::Shared(SharedResponse::buffer(response).await)
+                } else {
+                    let _ = sender.send(response);
+                    FlightOutcome::Streamed
+                }
+            });
+            (flight, None)
+        }
+    };
+
+    match (&*flight.await, joined) {
+        (FlightOutcome::Shared(shared), None) => shared.to_response(),
+        (FlightOutcome::Shared(shared), Some(_)) => {
+            debug!(key = %key, "Served a coalesced request");
+            let mut response = shared.to_response();
+            response
+                .headers_mut()
+                .insert(COALESCED_HEADER, HeaderValue::from_static("true"));
+            response
+        }
+        (FlightOutcome::Streamed, None) => started.await.unwrap_or_else(|_| {
+            SharedResponse::error("The request failed".to_string()).to_response()
+        }),
+        (FlightOutcome::Streamed, Some((request, next))) => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn request(uri: &str) -> Request {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_flight_key() {
+        let key = flight_key(&request("/image?var=t2m&width=800")).unwrap();
+        assert_eq!(
+            flight_key(&request("/image?width=800&var=t2m")),
+            Some(key.clone())
+        );
+        assert_ne!(flight_key(&request("/image?var=t2m&width=400")), Some(key));
+
+        let mut ranged = request("/data?vars=t2m&format=arrow");
+        ranged
+            .headers_mut()
+            .insert(header::RANGE, HeaderValue::from_static("bytes=0-99"));
+        assert_ne!(
+            flight_key(&ranged),
+            flight_key(&request("/data?vars=t2m&format=arrow"))
+        );
+
+        // Responses under the scopes of one API key are not shared with another
+        let keyed = |api_key: &'static str| {
+            let mut request = request("/data?vars=t2m&max_points=5e3");
+            request
+                .headers_mut()
+                .insert(API_KEY_HEADER, HeaderValue::from_static(api_key));
+            flight_key(&request).unwrap()
+        };
+        assert_eq!(keyed("team-a"), keyed("team-a"));
+        assert_ne!(keyed("team-a"), keyed("team-b"));
+        assert!(!keyed("team-a").contains("team-a"));
+        assert_ne!(
+            Some(keyed("team-a")),
+            flight_key(&request("/data?vars=t2m&max_points=5e3"))
+        );
+
+        assert_eq!(flight_key(&request("/point?vars=t2m")), None);
+        let post = Request::post("/data").body(Body::empty()).unwrap();
+        assert_eq!(flight_key(&post), None);
+    }
+
+    #[tokio::test]
+    async fn test_identical_requests_share_a_computation() {
+        let in_flight = InFlightRequests::new();
+        let computations = Arc::new(AtomicUsize::new(0));
+        let compute = || {
+            let computations = computations.clone();
+            async move {
+                computations.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                FlightOutcome::Shared(SharedResponse {
+                    status: StatusCode::OK,
+                    headers: HeaderMap::new(),
+                    body: Bytes::from_static(b"map"),
+                })
+            }
+        };
+
+        let results =
+            futures::future::join_all((0..5).map(|_| in_flight.run("k".to_string(), compute())))
+                .await;
+        assert_eq!(computations.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            results.iter().filter(|(_, coalesced)| *coalesced).count(),
+            4
+        );
+        assert!(results.iter().all(|(outcome, _)| matches!(
+            &**outcome,
+            FlightOutcome::Shared(response) if response.body == "map"
+        )));
+        assert!(in_flight.is_empty());
+
+        // Completed computations are not cached
+        in_flight.run("k".to_string(), compute()).await;
+        assert_eq!(computations.load(Ordering::SeqCst), 2);
+    }
+}
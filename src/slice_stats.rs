@@ -0,0 +1,328 @@
+//! Missing-data aware statistics over horizontal slices.
+//!
+//! Values are loaded as stored in the file, so `_FillValue` and
+//! `missing_value` markers and values outside `valid_min`/`valid_max`/
+//! `valid_range` are still present in memory. Left in, a fill value such as
+//! `9.96921e36` swamps the color range of an image. `MissingData` captures
+//! these rules for a variable, and `SliceStatsCache` memoizes the statistics
+//! of each (variable, non-horizontal indices) slice so that `/image` and
+//! `/stats` report the same range for the same slice without rescanning it.
+
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, FieldStats, HorizontalField};
+use crate::state::{AppState, AttributeValue, Variable};
+
+/// Number of slices whose statistics are kept before the cache is reset
+const MAX_CACHED_SLICES: usize = 4096;
+
+/// Rules identifying missing values of a variable
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MissingData {
+    /// Values marking missing data (`_FillValue` and `missing_value`)
+    fill_values: Vec<f32>,
+    /// Smallest valid value
+    valid_min: Option<f32>,
+    /// Largest valid value
+    valid_max: Option<f32>,
+}
+
+impl MissingData {
+    /// Read the missing-data attributes of a variable
+    ///
+    /// `valid_min` and `valid_max` take precedence over `valid_range`.
+    pub fn from_attributes(attributes: &HashMap<String, AttributeValue>) -> Self {
+        let numbers = |name: &str| {
+            attributes
+                .get(name)
+                .and_then(AttributeValue::as_f64_vec)
+                .unwrap_or_default()
+        };
+
+        let fill_values = ["_FillValue", "missing_value"]
+            .iter()
+            .flat_map(|name| numbers(name))
+            .map(|v| v as f32)
+            .collect();
+
+        let (range_min, range_max) = match numbers("valid_range").as_slice() {
+            [min, max] => (Some(*min as f32), Some(*max as f32)),
+            _ => (None, None),
+        };
+        let scalar = |name: &str| numbers(name).first().map(|&v| v as f32);
+        let valid_min = scalar("valid_min").or(range_min);
+        let valid_max = scalar("valid_max").or(range_max);
+
+        Self {
+            fill_values,
+            valid_min,
+            valid_max,
+        }
+    }
+
+    /// Missing-data rules of a variable's metadata
+    pub fn for_variable(var_meta: &Variable) -> Self {
+        Self::from_attributes(&var_meta.attributes)
+    }
+
+    /// Whether a value is missing: non-finite, a fill value, or out of range
+    pub fn is_missing(&self, value: f32) -> bool {
+        !value.is_finite()
+            || self.fill_values.contains(&value)
+            || self.valid_min.is_some_and(|min| value < min)
+            || self.valid_max.is_some_and(|max| value > max)
+    }
+
+    /// Replace missing values with NaN
+    pub fn mask<'a>(&self, values: impl IntoIterator<Item = &'a mut f32>) {
+        if *self == Self::default() {
+            return;
+        }
+        for value in values {
+            if self.is_missing(*value) {
+                *value = f32::NAN;
+            }
+        }
+    }
+}
+
+/// Summary statistics over the values that are not missing
+pub fn summarize(values: impl IntoIterator<Item = f32>, missing: &MissingData) -> FieldStats {
+    let valid: Vec<f64> = values
+        .into_iter()
+        .filter(|&v| !missing.is_missing(v))
+        .map(|v| v as f64)
+        .collect();
+
+    if valid.is_empty() {
+        return FieldStats {
+            count: 0,
+            min: None,
+            max: None,
+            mean: None,
+            std: None,
+        };
+    }
+
+    let n = valid.len() as f64;
+    let mean = valid.iter().sum::<f64>() / n;
+    let variance = valid.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+
+    FieldStats {
+        count: valid.len(),
+        min: valid.iter().copied().reduce(f64::min),
+        max: valid.iter().copied().reduce(f64::max),
+        mean: Some(mean),
+        std: Some(variance.sqrt()),
+    }
+}
+
+/// Identity of a horizontal slice: a variable with every other dimension pinned
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SliceKey {
+    var: String,
+    indices: BTreeMap<String, usize>,
+}
+
+/// Statistics of whole horizontal slices, computed once per slice
+#[derive(Debug, Clone, Default)]
+pub struct SliceStatsCache {
+    entries: Arc<RwLock<HashMap<SliceKey, FieldStats>>>,
+}
+
+impl SliceStatsCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Statistics of the full latitude/longitude slice of a variable
+    ///
+    /// `dim_indices` pins the non-horizontal dimensions as in
+    /// [`HorizontalField::from_state`]; missing entries use index 0.
+    pub fn get_or_compute(
+        &self,
+        state: &AppState,
+        var_name: &str,
+        dim_indices: &HashMap<String, usize>,
+    ) -> Result<FieldStats> {
+        let var_meta = state.get_variable_metadata_checked(var_name)?;
+        let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+            RossbyError::VariableNotSuitableForImage {
+                name: var_name.to_string(),
+            }
+        })?;
+
+        let indices: BTreeMap<String, usize> = var_meta
+            .dimensions
+            .iter()
+            .enumerate()
+            .filter(|&(axis, _)| axis != lat_axis && axis != lon_axis)
+            .map(|(_, dim)| (dim.clone(), dim_indices.get(dim).copied().unwrap_or(0)))
+            .collect();
+        let key = SliceKey {
+            var: var_name.to_string(),
+            indices,
+        };
+
+        if let Some(stats) = self.entries.read().get(&key) {
+            return Ok(stats.clone());
+        }
+
+        let pinned: HashMap<String, usize> = key.indices.clone().into_iter().collect();
+        let stats = HorizontalField::from_state(state, var_name, &pinned, None)?.stats();
+
+        let mut entries = self.entries.write();
+        if entries.len() >= MAX_CACHED_SLICES {
+            entries.clear();
+        }
+        entries.insert(key, stats.clone());
+        Ok(stats)
+    }
+
+    /// Drop every cached slice, returning how many there were
+    pub fn clear(&self) -> usize {
+        std::mem::take(&mut *self.entries.write()).len()
+    }
+
+    /// Number of cached slices
+    pub fn len(&self) -> usize {
+        self.entries.read().len()
+    }
+
+    /// Whether no slice is cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.read().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata};
+    use ndarray::{Array, IxDyn};
+
+    #[test]
+    fn test_missing_data_attributes() {
+        let attributes = HashMap::from([
+            ("_FillValue".to_string(), AttributeValue::Number(-9999.0)),
+            (
+                "missing_value".to_string(),
+                AttributeValue::NumberArray(vec![1e20, -1.0]),
+            ),
+            (
+                "valid_range".to_string(),
+                AttributeValue::IntegerArray(vec![-100, 100]),
+            ),
+            ("valid_max".to_string(), AttributeValue::Integer(50)),
+        ]);
+        let missing = MissingData::from_attributes(&attributes);
+
+        for value in [-9999.0, 1e20, -1.0, -101.0, 51.0, f32::NAN, f32::INFINITY] {
+            assert!(missing.is_missing(value), "{} should be missing", value);
+        }
+        for value in [-100.0, 0.0, 50.0] {
+            assert!(!missing.is_missing(value), "{} should be valid", value);
+        }
+
+        let mut values = [1.0, -9999.0, 2.0];
+        missing.mask(values.iter_mut());
+        assert!(values[1].is_nan());
+
+        let none = MissingData::from_attributes(&HashMap::new());
+        assert!(!none.is_missing(9.96921e36));
+    }
+
+    #[test]
+    fn test_summarize() {
+        let missing = MissingData {
+            fill_values: vec![9.96921e36],
+            ..Default::default()
+        };
+        let stats = summarize([1.0, 9.96921e36, 3.0, f32::NAN], &missing);
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.min, Some(1.0));
+        assert_eq!(stats.max, Some(3.0));
+        assert_eq!(stats.mean, Some(2.0));
+        assert_eq!(stats.std, Some(1.0));
+
+        assert_eq!(summarize([f32::NAN], &missing).min, None);
+    }
+
+    #[test]
+    fn test_slice_stats_cache() {
+        let mut dimensions = HashMap::new();
+        for (name, size) in [("time", 2), ("lat", 2), ("lon", 2)] {
+            dimensions.insert(
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                },
+            );
+        }
+        let variables = HashMap::from([(
+            "t2m".to_string(),
+            Variable {
+                name: "t2m".to_string(),
+                dimensions: vec!["time".to_string(), "lat".to_string(), "lon".to_string()],
+                shape: vec![2, 2, 2],
+                attributes: HashMap::from([(
+                    "_FillValue".to_string(),
+                    AttributeValue::Number(-999.0),
+                )]),
+                dtype: "f32".to_string(),
+            },
+        )]);
+        let coordinates = HashMap::from([
+            ("time".to_string(), vec![0.0, 1.0]),
+            ("lat".to_string(), vec![0.0, 10.0]),
+            ("lon".to_string(), vec![0.0, 10.0]),
+        ]);
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables,
+            coordinates,
+            groups: HashMap::new(),
+        };
+        let values = vec![1.0, 2.0, -999.0, 4.0, 10.0, 20.0, 30.0, -999.0];
+        let data = HashMap::from([(
+            "t2m".to_string(),
+            Array::from_shape_vec(IxDyn(&[2, 2, 2]), values).unwrap(),
+        )]);
+        let state = AppState::new(Config::default(), metadata, data);
+
+        let cache = SliceStatsCache::new();
+        let first = cache
+            .get_or_compute(&state, "t2m", &HashMap::new())
+            .unwrap();
+        assert_eq!(
+            (first.count, first.min, first.max),
+            (3, Some(1.0), Some(4.0))
+        );
+
+        let second = cache
+            .get_or_compute(&state, "t2m", &HashMap::from([("time".to_string(), 1)]))
+            .unwrap();
+        assert_eq!((second.min, second.max), (Some(10.0), Some(30.0)));
+
+        // Unpinned dimensions and explicit index 0 are the same slice
+        cache
+            .get_or_compute(&state, "t2m", &HashMap::from([("time".to_string(), 0)]))
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        assert!(cache
+            .get_or_compute(&state, "t2m", &HashMap::from([("time".to_string(), 2)]))
+            .is_err());
+
+        assert_eq!(cache.clear(), 2);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+//! Point-in-time snapshots of the serving state.
+//!
+//! `rossby dump-state file.nc --out state.json.gz` loads a dataset as the
+//! server would and writes a machine-readable description of it, and
+//! `GET /admin/state` returns the same description of the running server.
+//! A snapshot holds the configuration, the metadata with every coordinate
+//! array, and the shape and SHA-256 checksum of the values of every
+//! variable, so a bug report can pin down exactly what was being served
+//! without shipping the data itself.
+//!
+//! Secrets are redacted from the configuration: the admin token, the API
+//! keys of quotas and datasets, wherever they appear (including dataset
+//! overrides). Objects are written with sorted keys, so snapshots of the
+//! same state are byte-for-byte identical apart from `created_at`.
+
+use chrono::Utc;
+use clap::Parser;
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::config::Config;
+use crate::data_loader::load_netcdf;
+use crate::error::Result;
+use crate::schema::SCHEMA_VERSION;
+use crate::state::AppState;
+
+/// Command-line subcommand that writes a snapshot
+pub const DUMP_STATE_COMMAND: &str = "dump-state";
+
+/// Replacement of redacted configuration values
+pub const REDACTED: &str = "<redacted>";
+
+/// Bytes of values hashed at a time
+const CHECKSUM_CHUNK_BYTES: usize = 1 << 20;
+
+/// Command-line arguments of `rossby dump-state`
+#[derive(Parser, Debug)]
+#[command(name = "rossby dump-state", bin_name = "rossby dump-state")]
+#[command(about = "Write a description of the serving state of a dataset")]
+pub struct DumpStateArgs {
+    /// Path to the NetCDF file to describe
+    pub netcdf_file: PathBuf,
+
+    /// File to write the snapshot to, gzip-compressed if it ends in .gz
+    #[arg(long, default_value = "state.json.gz")]
+    pub out: PathBuf,
+
+    /// Path to JSON configuration file
+    #[arg(short, long, env = "ROSSBY_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+/// Describe the serving state of a dataset
+pub fn snapshot(state: &AppState) -> Result<Value> {
+    let mut config = serde_json::to_value(&state.config)?;
+    redact(&mut config);
+
+    let variables: Map<String, Value> = state
+        .variable_values()
+        .map(|(name, array)| {
+            let checksum = json!({
+                "shape": array.shape(),
+                "sha256": values_sha256(array.iter()),
+            });
+            (name.clone(), checksum)
+        })
+        .collect();
+
+    Ok(json!({
+        "checksums": variables,
+        "config": config,
+        "coordinate_fixes": state.coordinate_changes,
+        "created_at": Utc::now().to_rfc3339(),
+        "crs": state.crs,
+        "fingerprint": state.fingerprint(),
+        "generation": state.generation,
+        "metadata": state.metadata,
+        "rossby_version": env!("CARGO_PKG_VERSION"),
+        "schema_version": SCHEMA_VERSION,
+        "self_check": state.self_check.last(),
+        "time_normalization": state.time_normalization,
+        "views": state.views.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+    }))
+}
+
+/// Hex-encoded SHA-256 of little-endian values, such as those of an array in
+/// logical order
+pub fn values_sha256(values: impl IntoIterator<Item = f32>) -> String {
+    let mut hasher = Sha256::new();
+    let mut buffer = Vec::with_capacity(CHECKSUM_CHUNK_BYTES);
+    for value in values {
+        buffer.extend_from_slice(&value.to_le_bytes());
+        if buffer.len() >= CHECKSUM_CHUNK_BYTES {
+            hasher.update(&buffer);
+            buffer.clear();
+        }
+    }
+    hasher.update(&buffer);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Replace the secrets of a serialized configuration
+///
+/// Applied to every object, so dataset overrides are redacted the same way
+/// as the server-wide configuration.
+pub fn redact(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                match (key.as_str(), &mut *value) {
+                    (_, Value::Null) => {}
+                    ("admin_token", value) => *value = Value::from(REDACTED),
+                    ("api_keys", Value::Array(keys)) => {
+                        keys.iter_mut().for_each(|key| *key = Value::from(REDACTED))
+                    }
+                    ("quotas", Value::Object(quotas)) => {
+                        if let Some(Value::Object(keys)) = quotas.get_mut("keys") {
+                            // API keys are the names of the limits
+                            redact_names(keys);
+                        }
+                        quotas.values_mut().for_each(redact);
+                    }
+                    // API keys are the names of the scope lists
+                    ("api_key_scopes", Value::Object(scopes)) => redact_names(scopes),
+                    (_, value) => redact(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Replace the names of the entries of an object, keeping their values
+fn redact_names(object: &mut Map<String, Value>) {
+    *object = std::mem::take(object)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, value))| (format!("{} {}", REDACTED, i + 1), value))
+        .collect();
+}
+
+/// Write a snapshot as JSON, gzip-compressed if the path ends in `.gz`
+pub fn write_snapshot(snapshot: &Value, path: &Path) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        serde_json::to_writer(&mut encoder, snapshot)?;
+        encoder.finish()?.flush()?;
+    } else {
+        let mut file = file;
+        serde_json::to_writer(&mut file, snapshot)?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+/// Load a dataset and write its snapshot
+pub async fn run(args: DumpStateArgs) -> Result<()> {
+    let config = Config::with_file(args.config.as_ref())?;
+    config.validate()?;
+
+    info!(
+        file_path = %args.netcdf_file.display(),
+        "Loading NetCDF file"
+    );
+    let state = load_netcdf(&args.netcdf_file, config)?;
+    state.validate()?;
+
+    write_snapshot(&snapshot(&state)?, &args.out)?;
+    info!(
+        out = %args.out.display(),
+        variables = state.variable_values().count(),
+        "State snapshot written"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{Array, IxDyn};
+
+    #[test]
+    fn test_redact() {
+        let mut config = json!({
+            "server": {
+                "admin_token": "s3cret",
+                "quotas": {"daily_bytes": 100, "keys": {"key-a": 10, "key-b": 20}},
+                "api_key_scopes": {"key-a": ["max_points"]},
+            },
+            "datasets": {
+                "era5": {
+                    "api_keys": ["key-c"],
+                    "overrides": {"server": {"admin_token": "other"}},
+                },
+                "open": {"api_keys": null},
+            },
+        });
+        redact(&mut config);
+        assert_eq!(
+            config,
+            json!({
+                "server": {
+                    "admin_token": REDACTED,
+                    "quotas": {
+                        "daily_bytes": 100,
+                        "keys": {"<redacted> 1": 10, "<redacted> 2": 20},
+                    },
+                    "api_key_scopes": {"<redacted> 1": ["max_points"]},
+                },
+                "datasets": {
+                    "era5": {
+                        "api_keys": [REDACTED],
+                        "overrides": {"server": {"admin_token": REDACTED}},
+                    },
+                    "open": {"api_keys": null},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_values_sha256() {
+        let array = Array::from_shape_vec(IxDyn(&[2, 2]), vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+        let bytes: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let sha256 = |array: &Array<f32, IxDyn>| values_sha256(array.iter().copied());
+        assert_eq!(sha256(&array), crate::artifact::sha256_hex(&bytes));
+        // Values are hashed in logical order, whatever the memory layout
+        let transposed = array.t().to_owned();
+        assert_ne!(sha256(&transposed), sha256(&array));
+        assert_eq!(sha256(&transposed.t().to_owned()), sha256(&array));
+    }
+}
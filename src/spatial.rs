@@ -0,0 +1,236 @@
+//! Nearest-cell lookups on the sphere.
+//!
+//! `SpatialIndex` is a KD-tree over the centres of the horizontal grid cells,
+//! stored as unit vectors so that distances are measured through the sphere
+//! rather than in degrees: neighbours across the antimeridian or over a pole
+//! are found like any other. Each cell is identified by an id, `lat_index *
+//! n_lon + lon_index` for a rectilinear grid, and lookups take O(log n) time.
+//!
+//! The index is built with the dataset state at load time. Searches can skip
+//! cells with a predicate, which `/point?snap=nearest_valid` uses to find the
+//! closest cell holding a valid value when the requested point falls on
+//! missing data, such as a coastal point of an ocean field.
+
+/// Mean Earth radius in kilometres
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// A cell found by a search, with its great-circle distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    /// Id of the cell
+    pub cell: usize,
+    /// Great-circle distance from the query point
+    pub distance_km: f64,
+}
+
+/// KD-tree over the cell centres of a horizontal grid
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    /// Cell centres as unit vectors, ordered as an implicit balanced tree
+    points: Vec<[f64; 3]>,
+    /// Id of the cell at each position of `points`
+    cells: Vec<usize>,
+}
+
+impl SpatialIndex {
+    /// Index the cells of arbitrary centres, given as (lat, lon) in degrees
+    ///
+    /// The id of each cell is its position in `centres`. Centres with
+    /// non-finite coordinates are left out.
+    pub fn from_points(centres: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        let mut entries: Vec<([f64; 3], usize)> = centres
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (lat, lon))| lat.is_finite() && lon.is_finite())
+            .map(|(cell, (lat, lon))| (unit_vector(lat, lon), cell))
+            .collect();
+        build(&mut entries, 0);
+        let (points, cells) = entries.into_iter().unzip();
+        Self { points, cells }
+    }
+
+    /// Index the cells of a rectilinear grid, with ids `lat_index * lons.len() + lon_index`
+    pub fn from_rectilinear(lats: &[f64], lons: &[f64]) -> Self {
+        Self::from_points(
+            lats.iter()
+                .flat_map(|&lat| lons.iter().map(move |&lon| (lat, lon))),
+        )
+    }
+
+    /// Number of indexed cells
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Whether no cell is indexed
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The cell closest to a point
+    pub fn nearest(&self, lat: f64, lon: f64) -> Option<Neighbor> {
+        self.nearest_where(lat, lon, |_| true)
+    }
+
+    /// The cell closest to a point among those accepted by `accept`
+    ///
+    /// Subtrees are pruned by distance only, so a predicate rejecting most
+    /// cells around the point makes the search visit correspondingly more.
+    pub fn nearest_where(
+        &self,
+        lat: f64,
+        lon: f64,
+        accept: impl Fn(usize) -> bool,
+    ) -> Option<Neighbor> {
+        if !lat.is_finite() || !lon.is_finite() {
+            return None;
+        }
+        let target = unit_vector(lat, lon);
+        let mut best: Option<(f64, usize)> = None;
+        self.search(0, self.points.len(), 0, &target, &accept, &mut best);
+        best.map(|(squared_chord, cell)| Neighbor {
+            cell,
+            distance_km: chord_to_km(squared_chord.sqrt()),
+        })
+    }
+
+    /// Search the subtree stored in `points[start..end]`, split on `axis`
+    fn search(
+        &self,
+        start: usize,
+        end: usize,
+        axis: usize,
+        target: &[f64; 3],
+        accept: &impl Fn(usize) -> bool,
+        best: &mut Option<(f64, usize)>,
+    ) {
+        if start >= end {
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        let point = &self.points[mid];
+        let squared = squared_distance(point, target);
+        if best.is_none_or(|(distance, _)| squared < distance) && accept(self.cells[mid]) {
+            *best = Some((squared, self.cells[mid]));
+        }
+
+        let offset = target[axis] - point[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, mid), (mid + 1, end))
+        } else {
+            ((mid + 1, end), (start, mid))
+        };
+        let next_axis = (axis + 1) % 3;
+        self.search(near.0, near.1, next_axis, target, accept, best);
+        if best.is_none_or(|(distance, _)| offset * offset < distance) {
+            self.search(far.0, far.1, next_axis, target, accept, best);
+        }
+    }
+}
+
+/// Arrange entries as an implicit KD-tree: the median of each range along
+/// its axis sits in the middle, smaller entries before it and larger after
+fn build(entries: &mut [([f64; 3], usize)], axis: usize) {
+    if entries.len() <= 1 {
+        return;
+    }
+    let mid = entries.len() / 2;
+    entries.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    let (before, rest) = entries.split_at_mut(mid);
+    let next_axis = (axis + 1) % 3;
+    build(before, next_axis);
+    build(&mut rest[1..], next_axis);
+}
+
+/// Unit vector of a point given in degrees
+fn unit_vector(lat: f64, lon: f64) -> [f64; 3] {
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn squared_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+/// Great-circle distance of a chord between two unit vectors
+fn chord_to_km(chord: f64) -> f64 {
+    2.0 * (chord / 2.0).min(1.0).asin() * EARTH_RADIUS_KM
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nearest cell found by checking every cell
+    fn brute_force(
+        centres: &[(f64, f64)],
+        lat: f64,
+        lon: f64,
+        accept: impl Fn(usize) -> bool,
+    ) -> Option<usize> {
+        let target = unit_vector(lat, lon);
+        (0..centres.len())
+            .filter(|&cell| accept(cell))
+            .min_by(|&a, &b| {
+                let distance = |cell: usize| {
+                    squared_distance(&unit_vector(centres[cell].0, centres[cell].1), &target)
+                };
+                distance(a).total_cmp(&distance(b))
+            })
+    }
+
+    #[test]
+    fn test_nearest_matches_brute_force() {
+        let lats: Vec<f64> = (0..19).map(|i| -90.0 + i as f64 * 10.0).collect();
+        let lons: Vec<f64> = (0..36).map(|i| i as f64 * 10.0).collect();
+        let index = SpatialIndex::from_rectilinear(&lats, &lons);
+        assert_eq!(index.len(), 19 * 36);
+        let centres: Vec<(f64, f64)> = lats
+            .iter()
+            .flat_map(|&lat| lons.iter().map(move |&lon| (lat, lon)))
+            .collect();
+
+        let land = |cell: usize| !(cell * 7).is_multiple_of(5);
+        for (lat, lon) in [
+            (0.0, 0.0),
+            (43.2, 7.9),
+            (-33.0, 151.0),
+            (51.5, -0.1),
+            (88.0, 123.0),
+            (-12.5, 355.1),
+        ] {
+            let nearest = index.nearest(lat, lon).unwrap();
+            assert_eq!(
+                Some(nearest.cell),
+                brute_force(&centres, lat, lon, |_| true),
+                "({}, {})",
+                lat,
+                lon
+            );
+            let valid = index.nearest_where(lat, lon, land).unwrap();
+            assert_eq!(Some(valid.cell), brute_force(&centres, lat, lon, land));
+            assert!(valid.distance_km >= nearest.distance_km);
+        }
+    }
+
+    #[test]
+    fn test_nearest_across_antimeridian() {
+        let index = SpatialIndex::from_points([(0.0, 179.5), (0.0, 170.0), (f64::NAN, 0.0)]);
+        assert_eq!(index.len(), 2);
+
+        let nearest = index.nearest(0.0, -179.5).unwrap();
+        assert_eq!(nearest.cell, 0);
+        // One degree of longitude at the equator
+        assert!((nearest.distance_km - 111.19).abs() < 0.01);
+
+        assert_eq!(
+            index
+                .nearest_where(0.0, -179.5, |cell| cell != 0)
+                .unwrap()
+                .cell,
+            1
+        );
+        assert!(index.nearest_where(0.0, 0.0, |_| false).is_none());
+        assert!(SpatialIndex::from_points([]).nearest(0.0, 0.0).is_none());
+    }
+}
@@ -3,13 +3,41 @@
 //! This module defines the shared state that is passed to all handlers,
 //! containing the loaded NetCDF data and metadata.
 
-use ndarray::{Array, IxDyn};
+use chrono::{DateTime, Utc};
+use ndarray::{Array, CowArray, IxDyn};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::sync::Arc;
-
-use crate::config::Config;
+use std::sync::{Arc, OnceLock};
+
+use crate::cf_time;
+use crate::climatology::ClimatologyStore;
+use crate::config::{Config, VariableTranslation};
+use crate::coord_index::CoordinateIndex;
+use crate::coordinate_fixes::CoordinateChange;
+use crate::crs::Crs;
 use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, LAT_NAMES, LON_NAMES};
+use crate::generation::next_generation;
+use crate::geometry::{lon_indices, seam_crossing};
+use crate::integrity::SelfCheckStatus;
+use crate::lazy::LazyVariable;
+use crate::packed::{pack_variables, VariableValues};
+use crate::quota::UsageTracker;
+use crate::signing::ResponseSigner;
+use crate::single_flight::InFlightRequests;
+use crate::slice_stats::SliceStatsCache;
+use crate::spatial::SpatialIndex;
+use crate::thumbnail::ThumbnailCache;
+use crate::time_axis::TimeNormalization;
+use crate::vertical::PressureFieldCache;
+use crate::views::ViewStore;
+
+/// Number of valid values listed when a coordinate value is not found
+const NEARBY_VALUES: usize = 5;
+
+/// Bytes of data hashed at once when fingerprinting a dataset
+const FINGERPRINT_CHUNK_BYTES: usize = 1 << 16;
 
 /// Metadata about a NetCDF dimension
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,16 +65,72 @@ pub struct Variable {
     pub dtype: String,
 }
 
+impl Variable {
+    /// Return a copy of this variable with translated display attributes applied
+    pub fn with_translation(&self, translation: &VariableTranslation) -> Variable {
+        let mut localized = self.clone();
+        let overlays = [
+            ("long_name", &translation.long_name),
+            ("units", &translation.units),
+            ("description", &translation.description),
+        ];
+        for (key, value) in overlays {
+            if let Some(text) = value {
+                localized
+                    .attributes
+                    .insert(key.to_string(), AttributeValue::Text(text.clone()));
+            }
+        }
+        localized
+    }
+}
+
 /// Possible attribute values in NetCDF
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum AttributeValue {
     /// String attribute
     Text(String),
+    /// 64-bit signed integer attribute, kept exact
+    Integer(i64),
+    /// 64-bit unsigned integer attribute, kept exact
+    UnsignedInteger(u64),
     /// Numeric attribute (stored as f64 for simplicity)
     Number(f64),
+    /// Array of 64-bit signed integers
+    IntegerArray(Vec<i64>),
+    /// Array of 64-bit unsigned integers
+    UnsignedIntegerArray(Vec<u64>),
     /// Array of numbers
     NumberArray(Vec<f64>),
+    /// Array of strings (NC_STRING attributes with several values)
+    TextArray(Vec<String>),
+}
+
+impl AttributeValue {
+    /// Numeric value of a scalar attribute, if it is numeric
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            AttributeValue::Number(n) => Some(*n),
+            AttributeValue::Integer(n) => Some(*n as f64),
+            AttributeValue::UnsignedInteger(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+
+    /// Numeric values of a scalar or array attribute, if it is numeric
+    pub fn as_f64_vec(&self) -> Option<Vec<f64>> {
+        match self {
+            AttributeValue::IntegerArray(values) => {
+                Some(values.iter().map(|&v| v as f64).collect())
+            }
+            AttributeValue::UnsignedIntegerArray(values) => {
+                Some(values.iter().map(|&v| v as f64).collect())
+            }
+            AttributeValue::NumberArray(values) => Some(values.clone()),
+            scalar => scalar.as_f64().map(|v| vec![v]),
+        }
+    }
 }
 
 /// Complete metadata for a NetCDF file
@@ -60,6 +144,27 @@ pub struct Metadata {
     pub variables: HashMap<String, Variable>,
     /// Coordinate variables (subset of variables that match dimension names)
     pub coordinates: HashMap<String, Vec<f64>>,
+    /// NetCDF-4 groups below the root group, keyed by path (e.g. "forecast/surface")
+    #[serde(default)]
+    pub groups: HashMap<String, Group>,
+}
+
+/// A NetCDF-4 group
+///
+/// Dimensions and variables defined in a group are stored in `Metadata` under
+/// names namespaced by the group path, e.g. `forecast/surface/t2m`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    /// Path of the group from the root (e.g. "forecast/surface")
+    pub path: String,
+    /// Group attributes
+    pub attributes: HashMap<String, AttributeValue>,
+    /// Namespaced names of the dimensions defined in this group
+    pub dimensions: Vec<String>,
+    /// Namespaced names of the variables defined in this group
+    pub variables: Vec<String>,
+    /// Paths of the direct subgroups
+    pub groups: Vec<String>,
 }
 
 /// The main application state shared across all handlers
@@ -71,8 +176,46 @@ pub struct AppState {
     pub metadata: Metadata,
     /// Loaded data arrays
     pub data: HashMap<String, Array<f32, IxDyn>>,
+    /// Packed variables kept as their 16-bit integers (see [`crate::packed`])
+    pub packed: HashMap<String, Array<i16, IxDyn>>,
+    /// Variables read from the data file on demand (see [`crate::lazy`])
+    pub lazy: HashMap<String, LazyVariable>,
     /// Reverse dimension aliases mapping (canonical name -> file-specific name)
     dimension_aliases_reverse: HashMap<String, String>,
+    /// Lookup indices for coordinate arrays (file-specific name -> index)
+    coordinate_indices: HashMap<String, CoordinateIndex>,
+    /// Bytes transferred per client, for quota enforcement
+    pub usage: UsageTracker,
+    /// Statistics of horizontal slices, computed on first use
+    pub slice_stats: SliceStatsCache,
+    /// Pressure fields derived from parametric vertical coordinates
+    pub pressure_fields: PressureFieldCache,
+    /// Rendered thumbnails, kept for repeated previews
+    pub thumbnails: ThumbnailCache,
+    /// Expensive requests in flight, shared by identical concurrent requests
+    pub in_flight: InFlightRequests,
+    /// Climatological normals, supplied or computed on first use
+    pub climatology: ClimatologyStore,
+    /// Outcome of the last background self-check of the data
+    pub self_check: SelfCheckStatus,
+    /// Named views, each served from a dataset of its own
+    pub views: ViewStore,
+    /// Generation of the loaded dataset, increasing with every state built
+    pub generation: u64,
+    /// When the state was built
+    pub loaded_at: DateTime<Utc>,
+    /// Signer of responses (None = responses are not signed)
+    pub signer: Option<ResponseSigner>,
+    /// Index of the horizontal grid cells (None = no latitude and longitude)
+    pub spatial: Option<SpatialIndex>,
+    /// Coordinate reference system of the latitude and longitude coordinates
+    pub crs: Crs,
+    /// How dimensions were renamed and coordinates fixed when loaded
+    pub coordinate_changes: Vec<CoordinateChange>,
+    /// How time axes were sorted and deduplicated when loaded
+    pub time_normalization: Vec<TimeNormalization>,
+    /// SHA-256 fingerprint of the metadata and data, computed on first use
+    fingerprint: OnceLock<String>,
 }
 
 impl AppState {
@@ -88,12 +231,54 @@ impl AppState {
             dimension_aliases_reverse.insert(canonical.clone(), file_specific.clone());
         }
 
-        Self {
+        // The configured CRS is checked by Config::validate
+        let crs =
+            Crs::detect(config.data.crs.as_deref(), &metadata).unwrap_or_else(|_| Crs::wgs84());
+
+        // Precompute coordinate lookup indices
+        let coordinate_indices = metadata
+            .coordinates
+            .iter()
+            .map(|(name, values)| (name.clone(), CoordinateIndex::new(values)))
+            .collect();
+
+        let mut state = Self {
             config,
             metadata,
             data,
+            packed: HashMap::new(),
+            lazy: HashMap::new(),
             dimension_aliases_reverse,
+            coordinate_indices,
+            usage: UsageTracker::new(),
+            slice_stats: SliceStatsCache::new(),
+            pressure_fields: PressureFieldCache::new(),
+            thumbnails: ThumbnailCache::new(),
+            in_flight: InFlightRequests::new(),
+            climatology: ClimatologyStore::new(),
+            self_check: SelfCheckStatus::new(),
+            views: ViewStore::new(),
+            generation: next_generation(),
+            loaded_at: Utc::now(),
+            signer: None,
+            spatial: None,
+            crs,
+            coordinate_changes: Vec::new(),
+            time_normalization: Vec::new(),
+            fingerprint: OnceLock::new(),
+        };
+
+        // Index the horizontal grid for nearest-cell lookups
+        let lat = state
+            .get_coordinate_checked("lat")
+            .or_else(|_| state.get_coordinate_checked("latitude"));
+        let lon = state
+            .get_coordinate_checked("lon")
+            .or_else(|_| state.get_coordinate_checked("longitude"));
+        if let (Ok(lat), Ok(lon)) = (lat, lon) {
+            state.spatial = Some(SpatialIndex::from_rectilinear(lat, lon));
         }
+        state
     }
 
     /// Resolve a dimension name to its file-specific name
@@ -154,20 +339,87 @@ impl AppState {
         Arc::new(Self::new(config, metadata, data))
     }
 
-    /// Get a variable's data array
-    pub fn get_variable(&self, name: &str) -> Option<&Array<f32, IxDyn>> {
-        self.data.get(name)
+    /// Get a variable's data array, converted to `f32` if it is packed
+    pub fn get_variable(&self, name: &str) -> Option<CowArray<'_, f32, IxDyn>> {
+        self.get_variable_values(name).map(|values| values.to_f32())
     }
 
     /// Get a variable's data array with error handling
-    pub fn get_variable_checked(&self, name: &str) -> Result<&Array<f32, IxDyn>> {
+    pub fn get_variable_checked(&self, name: &str) -> Result<CowArray<'_, f32, IxDyn>> {
+        self.get_variable_values_checked(name)
+            .map(|values| values.to_f32())
+    }
+
+    /// Get a variable's values as held, packed, unpacked or on demand
+    pub fn get_variable_values(&self, name: &str) -> Option<VariableValues<'_>> {
         self.data
             .get(name)
+            .map(VariableValues::Unpacked)
+            .or_else(|| self.packed.get(name).map(VariableValues::Packed))
+            .or_else(|| self.lazy.get(name).map(VariableValues::Lazy))
+    }
+
+    /// Get a variable's values as held with error handling
+    pub fn get_variable_values_checked(&self, name: &str) -> Result<VariableValues<'_>> {
+        self.get_variable_values(name)
             .ok_or_else(|| RossbyError::DataNotFound {
                 message: format!("Variable not found: {}", name),
             })
     }
 
+    /// Get a variable's data array with some axes removed at given indices
+    ///
+    /// Only the remaining values of packed variables are converted. Fails
+    /// with [`RossbyError::IndexOutOfBounds`] for indices beyond the shape.
+    pub fn get_variable_indexed(
+        &self,
+        name: &str,
+        indices: &[(usize, usize)],
+    ) -> Result<CowArray<'_, f32, IxDyn>> {
+        let values = self.get_variable_values_checked(name)?;
+        let var_meta = self.get_variable_metadata_checked(name)?;
+        for &(axis, index) in indices {
+            let size = values.shape().get(axis).copied().unwrap_or(0);
+            if index >= size {
+                return Err(RossbyError::IndexOutOfBounds {
+                    param: var_meta
+                        .dimensions
+                        .get(axis)
+                        .cloned()
+                        .unwrap_or_else(|| axis.to_string()),
+                    value: index.to_string(),
+                    max: size.saturating_sub(1),
+                });
+            }
+        }
+        Ok(values.index_axes(indices))
+    }
+
+    /// The values of every loaded variable, packed, unpacked or on demand
+    pub fn variable_values(&self) -> impl Iterator<Item = (&String, VariableValues<'_>)> {
+        self.data
+            .iter()
+            .map(|(name, array)| (name, VariableValues::Unpacked(array)))
+            .chain(
+                self.packed
+                    .iter()
+                    .map(|(name, array)| (name, VariableValues::Packed(array))),
+            )
+            .chain(
+                self.lazy
+                    .iter()
+                    .map(|(name, var)| (name, VariableValues::Lazy(var))),
+            )
+    }
+
+    /// Keep packed variables as 16-bit integers, if `data.keep_packed` is set
+    pub fn pack_variables(&mut self) {
+        if self.config.data.keep_packed {
+            let packed = pack_variables(&self.metadata, &mut self.data);
+            self.packed.extend(packed);
+        }
+    }
+
     /// Get coordinate values for a dimension
     pub fn get_coordinate(&self, name: &str) -> Option<&Vec<f64>> {
         if let Ok(file_specific) = self.resolve_dimension(name) {
@@ -203,6 +455,22 @@ impl AppState {
             })
     }
 
+    /// Get variable metadata with display attributes localized for a language
+    ///
+    /// Falls back to the attributes from the file when `lang` is `None` or no
+    /// translation is configured for the variable.
+    pub fn get_localized_variable_metadata(
+        &self,
+        name: &str,
+        lang: Option<&str>,
+    ) -> Result<Variable> {
+        let var_meta = self.get_variable_metadata_checked(name)?;
+        match lang.and_then(|lang| self.config.translation_for(lang, name)) {
+            Some(translation) => Ok(var_meta.with_translation(translation)),
+            None => Ok(var_meta.clone()),
+        }
+    }
+
     /// Check if a variable exists
     pub fn has_variable(&self, name: &str) -> bool {
         self.metadata.variables.contains_key(name)
@@ -213,73 +481,115 @@ impl AppState {
         self.metadata.coordinates.contains_key(name)
     }
 
+    /// Get the precomputed lookup index for a coordinate
+    fn coordinate_index_checked(&self, dim_name: &str) -> Result<&CoordinateIndex> {
+        let file_specific = self.resolve_dimension(dim_name)?;
+        self.coordinate_indices
+            .get(file_specific)
+            .ok_or_else(|| RossbyError::DataNotFound {
+                message: format!("Coordinate not found: {}", file_specific),
+            })
+    }
+
+    /// Smallest and largest coordinate value of a dimension
+    pub fn coordinate_bounds(&self, dim_name: &str) -> Result<(f64, f64)> {
+        self.coordinate_index_checked(dim_name)?
+            .bounds()
+            .ok_or_else(|| RossbyError::DataNotFound {
+                message: format!("Coordinate {} is empty", dim_name),
+            })
+    }
+
+    /// Coordinate value of an ISO 8601 date such as `2023-06-01T12:00:00Z`
+    /// along a time dimension
+    ///
+    /// Returns `None` when `text` is not a date. Dates on dimensions without
+    /// decodable CF time units, e.g. with a 360-day calendar, are rejected.
+    pub fn time_coordinate_value(&self, dim_name: &str, text: &str) -> Result<Option<f64>> {
+        let Some(datetime) = cf_time::parse_datetime(text) else {
+            return Ok(None);
+        };
+        let units =
+            cf_time::time_units(self, dim_name).ok_or_else(|| RossbyError::InvalidParameter {
+                param: dim_name.to_string(),
+                message: format!(
+                    "Cannot select {} by date: it has no CF time units with a Gregorian \
+                     calendar. Give a coordinate value instead",
+                    dim_name
+                ),
+            })?;
+        Ok(Some(units.offset(datetime)))
+    }
+
     /// Find the index of a coordinate value within its array
     /// Returns the nearest index if exact match is not found
     pub fn find_coordinate_index(&self, dim_name: &str, value: f64) -> Result<usize> {
-        let _file_specific = self.resolve_dimension(dim_name)?;
-        let coords = self.get_coordinate_checked(dim_name)?;
+        let index = self.coordinate_index_checked(dim_name)?;
 
         // Early return for empty coordinates (shouldn't happen in valid files)
-        if coords.is_empty() {
-            return Err(RossbyError::DataNotFound {
-                message: format!("Coordinate {} is empty", dim_name),
-            });
-        }
+        let (min, max) = index.bounds().ok_or_else(|| RossbyError::DataNotFound {
+            message: format!("Coordinate {} is empty", dim_name),
+        })?;
 
         // Check if the value is out of bounds
-        if value < coords[0] || value > coords[coords.len() - 1] {
+        if !(min..=max).contains(&value) {
             return Err(RossbyError::InvalidCoordinates {
                 message: format!(
                     "Coordinate value {} is outside the range of {} ({} to {})",
-                    value,
-                    dim_name,
-                    coords[0],
-                    coords[coords.len() - 1]
+                    value, dim_name, min, max
                 ),
             });
         }
 
         // Find the index of the closest coordinate
-        let mut closest_idx = 0;
-        let mut min_diff = f64::MAX;
-
-        for (i, &coord) in coords.iter().enumerate() {
-            let diff = (coord - value).abs();
-            if diff < min_diff {
-                min_diff = diff;
-                closest_idx = i;
-            }
-        }
-
-        Ok(closest_idx)
+        index
+            .nearest(value)
+            .ok_or_else(|| RossbyError::InvalidCoordinates {
+                message: format!("Coordinate value {} is not valid for {}", value, dim_name),
+            })
     }
 
     /// Find the index of a coordinate value within its array using exact match
     /// Returns an error if the value is not found
     pub fn find_coordinate_index_exact(&self, dim_name: &str, value: f64) -> Result<usize> {
-        let file_specific = self.resolve_dimension(dim_name)?;
-        let coords = self.get_coordinate_checked(file_specific)?;
+        let index = self.coordinate_index_checked(dim_name)?;
 
         // Early return for empty coordinates (shouldn't happen in valid files)
-        if coords.is_empty() {
+        if index.bounds().is_none() {
             return Err(RossbyError::DataNotFound {
                 message: format!("Coordinate {} is empty", dim_name),
             });
         }
 
         // Find the exact match
-        for (i, &coord) in coords.iter().enumerate() {
-            if (coord - value).abs() < f64::EPSILON {
-                return Ok(i);
-            }
-        }
+        index
+            .exact(value)
+            .ok_or_else(|| RossbyError::PhysicalValueNotFound {
+                dimension: dim_name.to_string(),
+                value,
+                available: index.nearby(value, NEARBY_VALUES),
+            })
+    }
 
-        // No exact match found
-        Err(RossbyError::PhysicalValueNotFound {
-            dimension: dim_name.to_string(),
-            value,
-            available: coords.clone(),
-        })
+    /// Find the index of a time value, allowing for `data.time_tolerance`
+    ///
+    /// The nearest time step within the tolerance matches, so times that
+    /// went through client float formatting still find their step. Other
+    /// values are an error listing the nearest valid times.
+    pub fn find_time_index(&self, value: f64) -> Result<usize> {
+        let index = self.coordinate_index_checked("time")?;
+        if index.bounds().is_none() {
+            return Err(RossbyError::DataNotFound {
+                message: "Coordinate time is empty".to_string(),
+            });
+        }
+        index
+            .within(value, &self.config.data.time_tolerance)
+            .ok_or_else(|| RossbyError::PhysicalValueNotFound {
+                dimension: "time".to_string(),
+                value,
+                available: index.nearby(value, NEARBY_VALUES),
+            })
     }
 
     /// Get the variable dimensions
@@ -311,31 +621,34 @@ impl AppState {
             .or_else(|_| self.get_coordinate_checked("_latitude"))
             .or_else(|_| self.get_coordinate_checked("latitude"))?;
 
-        if lon_coords.is_empty() || lat_coords.is_empty() {
-            return Err(RossbyError::DataNotFound {
-                message: "Latitude or longitude coordinates are empty".to_string(),
-            });
-        }
-
-        // Find min/max values using iterators
-        let min_lon = lon_coords
-            .iter()
-            .fold(f64::INFINITY, |min, &val| min.min(val)) as f32;
-        let max_lon = lon_coords
-            .iter()
-            .fold(f64::NEG_INFINITY, |max, &val| max.max(val)) as f32;
-        let min_lat = lat_coords
-            .iter()
-            .fold(f64::INFINITY, |min, &val| min.min(val)) as f32;
-        let max_lat = lat_coords
-            .iter()
-            .fold(f64::NEG_INFINITY, |max, &val| max.max(val)) as f32;
+        coordinate_bounds(lon_coords, lat_coords)
+    }
 
-        Ok((min_lon, min_lat, max_lon, max_lat))
+    /// Get the lat/lon boundaries of a variable's own horizontal coordinates
+    ///
+    /// Variables on a staggered grid (e.g. velocities at cell faces) have
+    /// coordinates of their own, offset by half a cell from those of
+    /// [`Self::get_lat_lon_bounds`], so their full extent is taken from them.
+    pub fn get_variable_lat_lon_bounds(&self, var_name: &str) -> Result<(f32, f32, f32, f32)> {
+        let var_meta = self.get_variable_metadata_checked(var_name)?;
+        let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+            RossbyError::VariableNotSuitableForImage {
+                name: var_name.to_string(),
+            }
+        })?;
+        coordinate_bounds(
+            self.get_coordinate_checked(&var_meta.dimensions[lon_axis])?,
+            self.get_coordinate_checked(&var_meta.dimensions[lat_axis])?,
+        )
     }
 
     /// Extract a 2D data slice for a variable at a given time and spatial bounds
     /// with support for additional dimensions
+    ///
+    /// The latitude and longitude axes are found from the variable's dimension
+    /// names, so they may be stored in any order and position (e.g.
+    /// `(lat, lon, time)` or `(time, lon, lat)`). The result always has
+    /// latitude rows and longitude columns.
     pub fn get_data_slice_with_dims(
         &self,
         var_name: &str,
@@ -345,49 +658,23 @@ impl AppState {
         max_lat: f32,
         dim_indices: &HashMap<String, usize>,
     ) -> Result<Array<f32, ndarray::Ix2>> {
-        // Get the variable data
-        let var_data = self.get_variable_checked(var_name)?;
-
         // Get the variable dimensions
         let var_meta = self.get_variable_metadata_checked(var_name)?;
         let dimensions = &var_meta.dimensions;
 
-        // Find the indices for lat and lon in the dimensions
-        let mut lat_dim_idx_opt = None;
-        let mut lon_dim_idx_opt = None;
-
-        for (i, dim) in dimensions.iter().enumerate() {
-            if dim == "lat" || dim == "latitude" {
-                lat_dim_idx_opt = Some(i);
-            } else if dim == "lon" || dim == "longitude" {
-                lon_dim_idx_opt = Some(i);
-            }
-        }
-
-        // Ensure we have lat and lon dimensions
-        let lat_dim_idx = lat_dim_idx_opt.ok_or_else(|| RossbyError::DataNotFound {
-            message: format!(
-                "Variable {} does not have a latitude dimension (looking for 'lat' or 'latitude')",
-                var_name
-            ),
-        })?;
-
-        let lon_dim_idx = lon_dim_idx_opt.ok_or_else(|| RossbyError::DataNotFound {
-            message: format!("Variable {} does not have a longitude dimension (looking for 'lon' or 'longitude')", var_name),
-        })?;
-
-        // Get coordinate arrays - try both common naming conventions
-        let lon_coords = if self.metadata.coordinates.contains_key("lon") {
-            self.get_coordinate_checked("lon")?
-        } else {
-            self.get_coordinate_checked("longitude")?
-        };
+        // Find the latitude and longitude axes from the dimension metadata;
+        // they may be stored in any order and at any position
+        let (lat_dim_idx, lon_dim_idx) =
+            find_lat_lon_axes(dimensions).ok_or_else(|| RossbyError::DataNotFound {
+                message: format!(
+                    "Variable {} does not have both latitude and longitude dimensions \
+                     (looking for {:?} and {:?})",
+                    var_name, LAT_NAMES, LON_NAMES
+                ),
+            })?;
 
-        let lat_coords = if self.metadata.coordinates.contains_key("lat") {
-            self.get_coordinate_checked("lat")?
-        } else {
-            self.get_coordinate_checked("latitude")?
-        };
+        let lat_coords = self.get_coordinate_checked(&dimensions[lat_dim_idx])?;
+        let lon_coords = self.get_coordinate_checked(&dimensions[lon_dim_idx])?;
 
         // Check for empty coordinate arrays
         if lon_coords.is_empty() || lat_coords.is_empty() {
@@ -395,10 +682,15 @@ impl AppState {
             return Ok(Array::from_elem((0, 0), 0.0));
         }
 
-        // Find index ranges for the bounding box with safety checks
-        // Handle the case of dateline crossing (min_lon > max_lon)
-        let (min_lon_idx, max_lon_idx) = if min_lon <= max_lon {
-            // Normal case - no dateline crossing
+        // A bbox crossing the seam of the longitude axis takes the columns on
+        // either side of it, in eastward order (see `crate::geometry`)
+        let crossing = min_lon > max_lon
+            || seam_crossing(lon_coords, min_lon as f64, max_lon as f64).is_some();
+        let lon_selection =
+            crossing.then(|| lon_indices(lon_coords, min_lon as f64, max_lon as f64));
+        let (min_lon_idx, max_lon_idx) = if crossing {
+            (0, lon_coords.len() - 1)
+        } else {
             let min_idx = lon_coords
                 .iter()
                 .position(|&lon| lon as f32 >= min_lon)
@@ -410,11 +702,6 @@ impl AppState {
                 .unwrap_or(lon_coords.len() - 1);
 
             (min_idx, max_idx)
-        } else {
-            // Dateline crossing case - treat as empty slice for now
-            // The actual handling of dateline crossing happens in the image handler
-            // through adjust_for_dateline_crossing function
-            (0, 0)
         };
 
         let min_lat_idx = lat_coords
@@ -427,122 +714,38 @@ impl AppState {
             .rposition(|&lat| lat as f32 <= max_lat)
             .unwrap_or(lat_coords.len() - 1);
 
-        // Special handling for dateline crossing: if min_lon > max_lon and we're returning an empty slice
-        if min_lon > max_lon {
-            // Return a minimal valid slice that the image handler can work with
-            return Ok(Array::from_elem((max_lat_idx - min_lat_idx + 1, 1), 0.0));
-        }
-
-        // Create a mutable clone of the data array to work with
-        let mut data_array = var_data.to_owned();
-
-        // Track dimension indices as we go - they will change as we slice
-        // Start with the definite indices we just extracted
-        let mut current_lat_idx_opt = Some(lat_dim_idx);
-        let mut current_lon_idx_opt = Some(lon_dim_idx);
-
-        // Process non-lat/lon dimensions first
-        // Sort dimensions by index in descending order so we can slice without affecting indices
-        let mut non_lat_lon_dims: Vec<(usize, String)> = dimensions
+        // Pin every non-lat/lon dimension, using index 0 for dimensions that
+        // were not selected
+        let pins: Vec<(usize, usize)> = dimensions
             .iter()
             .enumerate()
-            .filter(|(i, _)| *i != lat_dim_idx && *i != lon_dim_idx)
-            .map(|(i, name)| (i, name.clone()))
+            .filter(|&(axis, _)| axis != lat_dim_idx && axis != lon_dim_idx)
+            .map(|(axis, dim_name)| (axis, dim_indices.get(dim_name).copied().unwrap_or(0)))
             .collect();
-
-        // Sort in reverse order (highest index first) so we can remove dimensions without affecting indices
-        non_lat_lon_dims.sort_by(|a, b| b.0.cmp(&a.0));
-
-        // Slice each non-lat/lon dimension, with automatic removal counting
-        for (removed_count, (dim_idx, dim_name)) in non_lat_lon_dims.into_iter().enumerate() {
-            // Get the index to slice at (default to 0 if not provided)
-            let index = dim_indices.get(&dim_name).copied().unwrap_or(0);
-
-            // Use index_axis to select just the slice at the specified index
-            // Adjust the dimension index based on how many dimensions we've already removed
-            // Make sure we don't underflow when calculating the adjusted dimension index
-            let adjusted_dim_idx = if dim_idx >= removed_count {
-                dim_idx - removed_count
-            } else {
-                // This should never happen with properly sorted indices,
-                // but we're being defensive against underflow
-                tracing::warn!(
-                    original_dim_idx = dim_idx,
-                    removed_count = removed_count,
-                    "Dimension index underflow prevented"
-                );
-                0
-            };
-            data_array = data_array.index_axis_move(ndarray::Axis(adjusted_dim_idx), index);
-
-            // Update lat/lon indices to account for the removed dimension
-            current_lat_idx_opt = current_lat_idx_opt.map(|idx| {
-                if idx > dim_idx {
-                    // If lat dimension is after this one, decrement its index
-                    // This is safe because we've already checked idx > dim_idx
-                    idx - 1
-                } else {
-                    // Otherwise, keep the same index
-                    idx
-                }
-            });
-
-            current_lon_idx_opt = current_lon_idx_opt.map(|idx| {
-                if idx > dim_idx {
-                    // If lon dimension is after this one, decrement its index
-                    // This is safe because we've already checked idx > dim_idx
-                    idx - 1
-                } else {
-                    // Otherwise, keep the same index
-                    idx
-                }
-            });
-        }
+        let var_data = self.get_variable_indexed(var_name, &pins)?;
+        let view = var_data.view();
 
         // After slicing all non-lat/lon dimensions, we should have just lat and lon left
-        if data_array.ndim() != 2 {
-            return Err(RossbyError::DataNotFound {
-                message: format!(
-                    "Expected a 2D array after slicing all non-lat/lon dimensions, got {}D",
-                    data_array.ndim()
-                ),
-            });
+        let mut plane =
+            view.into_dimensionality::<ndarray::Ix2>()
+                .map_err(|_| RossbyError::DataNotFound {
+                    message: "Expected a 2D array after slicing all non-lat/lon dimensions"
+                        .to_string(),
+                })?;
+
+        // Always return latitude as rows and longitude as columns
+        if lon_dim_idx < lat_dim_idx {
+            plane = plane.reversed_axes();
         }
 
-        // Now slice the lat/lon dimensions
-        // We need to determine which dimension is which in our 2D array
-        let lat_idx = current_lat_idx_opt.ok_or_else(|| RossbyError::DataNotFound {
-            message: "Lost track of latitude dimension during slicing".to_string(),
-        })?;
-
-        let lon_idx = current_lon_idx_opt.ok_or_else(|| RossbyError::DataNotFound {
-            message: "Lost track of longitude dimension during slicing".to_string(),
-        })?;
-
-        let lat_is_first = lat_idx < lon_idx;
-        let lon_is_first = lon_idx < lat_idx;
-
-        // Create a slice of the lat/lon region
-        let result = if lat_is_first {
-            // Latitude is the first dimension (rows), longitude is the second (columns)
-            data_array.slice(ndarray::s![
-                min_lat_idx..=max_lat_idx,
-                min_lon_idx..=max_lon_idx
-            ])
-        } else if lon_is_first {
-            // Longitude is the first dimension (rows), latitude is the second (columns)
-            data_array.slice(ndarray::s![
-                min_lon_idx..=max_lon_idx,
-                min_lat_idx..=max_lat_idx
-            ])
-        } else {
-            // Should not happen given our checks above
-            return Err(RossbyError::DataNotFound {
-                message: "Could not determine dimension order after slicing".to_string(),
-            });
-        };
-        // Convert the result to a 2D array and return it
-        Ok(result.to_owned().into_dimensionality::<ndarray::Ix2>()?)
+        let plane = plane.slice(ndarray::s![
+            min_lat_idx..=max_lat_idx,
+            min_lon_idx..=max_lon_idx
+        ]);
+        Ok(match lon_selection {
+            Some(columns) => plane.select(ndarray::Axis(1), &columns),
+            None => plane.to_owned(),
+        })
     }
 
     /// Extract a 2D data slice for a variable at a given time and spatial bounds
@@ -570,6 +773,42 @@ impl AppState {
         self.get_data_slice_with_dims(var_name, min_lon, min_lat, max_lon, max_lat, &dim_indices)
     }
 
+    /// Hex-encoded SHA-256 fingerprint of the loaded dataset
+    ///
+    /// The fingerprint covers the metadata and the values of every variable,
+    /// so it changes whenever the served dataset does. It is computed on first
+    /// use, which reads all data once.
+    pub fn fingerprint(&self) -> &str {
+        self.fingerprint.get_or_init(|| {
+            let mut hasher = Sha256::new();
+            // Objects serialize with sorted keys, whatever the HashMap order
+            let metadata = serde_json::to_value(&self.metadata).unwrap_or_default();
+            hasher.update(metadata.to_string().as_bytes());
+
+            // Packed variables hash as the values they are read as
+            let mut variables: Vec<_> = self.variable_values().collect();
+            variables.sort_by_key(|(name, _)| *name);
+            let mut buffer = Vec::with_capacity(FINGERPRINT_CHUNK_BYTES);
+            for (name, array) in variables {
+                hasher.update(name.as_bytes());
+                hasher.update([0]);
+                for &len in array.shape() {
+                    hasher.update((len as u64).to_le_bytes());
+                }
+                for value in array.iter() {
+                    buffer.extend_from_slice(&value.to_le_bytes());
+                    if buffer.len() >= FINGERPRINT_CHUNK_BYTES {
+                        hasher.update(&buffer);
+                        buffer.clear();
+                    }
+                }
+                hasher.update(&buffer);
+                buffer.clear();
+            }
+            format!("{:x}", hasher.finalize())
+        })
+    }
+
     /// Validate that the application state is consistent and ready for use
     pub fn validate(&self) -> Result<()> {
         // Ensure we have at least one variable
@@ -595,7 +834,7 @@ impl AppState {
 
         // Validate that the data arrays match their metadata shape
         for (var_name, var) in &self.metadata.variables {
-            if let Some(data) = self.data.get(var_name) {
+            if let Some(data) = self.get_variable_values(var_name) {
                 let shape = data.shape();
                 if shape.len() != var.shape.len() {
                     return Err(RossbyError::DataNotFound {
@@ -623,6 +862,32 @@ impl AppState {
     }
 }
 
+/// Boundaries `(min_lon, min_lat, max_lon, max_lat)` of longitude and
+/// latitude coordinates
+pub fn coordinate_bounds(lon_coords: &[f64], lat_coords: &[f64]) -> Result<(f32, f32, f32, f32)> {
+    if lon_coords.is_empty() || lat_coords.is_empty() {
+        return Err(RossbyError::DataNotFound {
+            message: "Latitude or longitude coordinates are empty".to_string(),
+        });
+    }
+
+    // Find min/max values using iterators
+    let min_lon = lon_coords
+        .iter()
+        .fold(f64::INFINITY, |min, &val| min.min(val)) as f32;
+    let max_lon = lon_coords
+        .iter()
+        .fold(f64::NEG_INFINITY, |max, &val| max.max(val)) as f32;
+    let min_lat = lat_coords
+        .iter()
+        .fold(f64::INFINITY, |min, &val| min.min(val)) as f32;
+    let max_lat = lat_coords
+        .iter()
+        .fold(f64::NEG_INFINITY, |max, &val| max.max(val)) as f32;
+
+    Ok((min_lon, min_lat, max_lon, max_lat))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -640,6 +905,23 @@ mod tests {
         let array = AttributeValue::NumberArray(vec![1.0, 2.0, 3.0]);
         let json = serde_json::to_string(&array).unwrap();
         assert_eq!(json, "[1.0,2.0,3.0]");
+
+        let integer = AttributeValue::UnsignedInteger(u64::MAX - 1);
+        let json = serde_json::to_string(&integer).unwrap();
+        assert_eq!(json, "18446744073709551614");
+
+        let texts = AttributeValue::TextArray(vec!["a".to_string(), "b".to_string()]);
+        let json = serde_json::to_string(&texts).unwrap();
+        assert_eq!(json, r#"["a","b"]"#);
+
+        // Untagged deserialization keeps integers exact and floats as numbers
+        let value: AttributeValue = serde_json::from_str("-9223372036854775807").unwrap();
+        assert!(matches!(
+            value,
+            AttributeValue::Integer(-9_223_372_036_854_775_807)
+        ));
+        let value: AttributeValue = serde_json::from_str("42.5").unwrap();
+        assert_eq!(value.as_f64(), Some(42.5));
     }
 
     #[test]
@@ -649,6 +931,7 @@ mod tests {
             dimensions: HashMap::new(),
             variables: HashMap::new(),
             coordinates: HashMap::new(),
+            groups: HashMap::new(),
         };
 
         metadata.dimensions.insert(
@@ -663,4 +946,176 @@ mod tests {
         assert_eq!(metadata.dimensions.get("time").unwrap().size, 10);
         assert!(metadata.dimensions.get("time").unwrap().is_unlimited);
     }
+
+    #[test]
+    fn test_localized_variable_metadata() {
+        let mut attributes = HashMap::new();
+        attributes.insert(
+            "long_name".to_string(),
+            AttributeValue::Text("2 metre temperature".to_string()),
+        );
+        attributes.insert("units".to_string(), AttributeValue::Text("K".to_string()));
+
+        let mut variables = HashMap::new();
+        variables.insert(
+            "t2m".to_string(),
+            Variable {
+                name: "t2m".to_string(),
+                dimensions: vec![],
+                shape: vec![],
+                attributes,
+                dtype: "f32".to_string(),
+            },
+        );
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::new(),
+            variables,
+            coordinates: HashMap::new(),
+            groups: HashMap::new(),
+        };
+
+        let mut config = Config::default();
+        let mut german = HashMap::new();
+        german.insert(
+            "t2m".to_string(),
+            VariableTranslation {
+                long_name: Some("2 m Temperatur".to_string()),
+                ..Default::default()
+            },
+        );
+        config.data.translations.insert("de".to_string(), german);
+
+        let state = AppState::new(config, metadata, HashMap::new());
+
+        let localized = state
+            .get_localized_variable_metadata("t2m", Some("de"))
+            .unwrap();
+        match localized.attributes.get("long_name") {
+            Some(AttributeValue::Text(text)) => assert_eq!(text, "2 m Temperatur"),
+            other => panic!("unexpected long_name: {:?}", other),
+        }
+        // Units were not translated and keep the value from the file
+        match localized.attributes.get("units") {
+            Some(AttributeValue::Text(text)) => assert_eq!(text, "K"),
+            other => panic!("unexpected units: {:?}", other),
+        }
+
+        // Unknown languages fall back to the original attributes
+        let fallback = state
+            .get_localized_variable_metadata("t2m", Some("ja"))
+            .unwrap();
+        match fallback.attributes.get("long_name") {
+            Some(AttributeValue::Text(text)) => assert_eq!(text, "2 metre temperature"),
+            other => panic!("unexpected long_name: {:?}", other),
+        }
+
+        assert!(state
+            .get_localized_variable_metadata("missing", Some("de"))
+            .is_err());
+    }
+
+    /// State with a variable `t` stored in the given dimension order, whose
+    /// value at (time, lat, lon) indices (t, i, j) is `100 t + 10 i + j`
+    fn create_permuted_state(layout: [&str; 3]) -> AppState {
+        let sizes = HashMap::from([("time", 2), ("lat", 3), ("lon", 4)]);
+        let canonical = Array::from_shape_fn(IxDyn(&[2, 3, 4]), |idx| {
+            (100 * idx[0] + 10 * idx[1] + idx[2]) as f32
+        });
+        let order: Vec<usize> = layout
+            .iter()
+            .map(|dim| {
+                ["time", "lat", "lon"]
+                    .iter()
+                    .position(|d| d == dim)
+                    .unwrap()
+            })
+            .collect();
+        let data = canonical
+            .permuted_axes(order)
+            .as_standard_layout()
+            .to_owned();
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: sizes
+                .iter()
+                .map(|(&name, &size)| {
+                    (
+                        name.to_string(),
+                        Dimension {
+                            name: name.to_string(),
+                            size,
+                            is_unlimited: false,
+                        },
+                    )
+                })
+                .collect(),
+            variables: HashMap::from([(
+                "t".to_string(),
+                Variable {
+                    name: "t".to_string(),
+                    dimensions: layout.iter().map(|d| d.to_string()).collect(),
+                    shape: layout.iter().map(|d| sizes[d]).collect(),
+                    attributes: HashMap::new(),
+                    dtype: "f32".to_string(),
+                },
+            )]),
+            coordinates: HashMap::from([
+                ("time".to_string(), vec![0.0, 1.0]),
+                ("lat".to_string(), vec![-10.0, 0.0, 10.0]),
+                ("lon".to_string(), vec![0.0, 10.0, 20.0, 30.0]),
+            ]),
+            groups: HashMap::new(),
+        };
+
+        AppState::new(
+            Config::default(),
+            metadata,
+            HashMap::from([("t".to_string(), data)]),
+        )
+    }
+
+    #[test]
+    fn test_data_slice_axis_order() {
+        let dim_indices = HashMap::from([("time".to_string(), 1)]);
+        let expected = ndarray::array![[111.0, 112.0], [121.0, 122.0]];
+
+        for layout in [
+            ["time", "lat", "lon"],
+            ["time", "lon", "lat"],
+            ["lat", "lon", "time"],
+            ["lon", "lat", "time"],
+            ["lat", "time", "lon"],
+        ] {
+            let state = create_permuted_state(layout);
+            let slice = state
+                .get_data_slice_with_dims("t", 5.0, -5.0, 25.0, 15.0, &dim_indices)
+                .unwrap();
+            assert_eq!(slice, expected, "layout {:?}", layout);
+        }
+
+        let state = create_permuted_state(["lon", "lat", "time"]);
+        let out_of_range = HashMap::from([("time".to_string(), 2)]);
+        assert!(matches!(
+            state.get_data_slice_with_dims("t", 0.0, -10.0, 30.0, 10.0, &out_of_range),
+            Err(RossbyError::IndexOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let layout = ["time", "lat", "lon"];
+        let fingerprint = create_permuted_state(layout).fingerprint().to_string();
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(create_permuted_state(layout).fingerprint(), fingerprint);
+
+        let mut state = create_permuted_state(layout);
+        state.data.get_mut("t").unwrap()[[0, 0, 0]] += 0.5;
+        assert_ne!(state.fingerprint(), fingerprint);
+        assert_ne!(
+            create_permuted_state(["lat", "lon", "time"]).fingerprint(),
+            fingerprint
+        );
+    }
 }
@@ -0,0 +1,145 @@
+//! Small cached previews of variables.
+//!
+//! `/thumbnail` renders a variable with the `/image` code at a small size
+//! and draws a title band over its top edge, for link previews (OpenGraph
+//! `og:image`) and catalog galleries. Previews are requested over and over
+//! with the same parameters, so the encoded PNGs are cached per dataset
+//! generation; `ThumbnailCache` holds them until it fills up or is flushed.
+
+use bytes::Bytes;
+use image::RgbaImage;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::colormaps::graticule::{blend_pixel, draw_text, text_width, GLYPH_HEIGHT};
+
+/// Number of thumbnails kept before the cache is reset
+const MAX_CACHED_THUMBNAILS: usize = 256;
+
+/// Color of the band behind the title
+const TITLE_BACKGROUND: [u8; 4] = [0, 0, 0, 160];
+
+/// Color of the title text
+const TITLE_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// Space around the title in image pixels
+const TITLE_MARGIN: u32 = 4;
+
+/// Encoded thumbnails, keyed by their normalized query
+#[derive(Debug, Clone, Default)]
+pub struct ThumbnailCache {
+    entries: Arc<RwLock<HashMap<String, Bytes>>>,
+}
+
+impl ThumbnailCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached thumbnail of a query
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        self.entries.read().get(key).cloned()
+    }
+
+    /// Keep a rendered thumbnail
+    pub fn insert(&self, key: String, png: Bytes) {
+        let mut entries = self.entries.write();
+        if entries.len() >= MAX_CACHED_THUMBNAILS {
+            entries.clear();
+        }
+        entries.insert(key, png);
+    }
+
+    /// Drop every cached thumbnail, returning how many there were
+    pub fn clear(&self) -> usize {
+        std::mem::take(&mut *self.entries.write()).len()
+    }
+
+    /// Number of cached thumbnails
+    pub fn len(&self) -> usize {
+        self.entries.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Draw a title on a translucent band along the top edge of an image
+///
+/// The text is drawn at twice the font size on images wide enough for it,
+/// and shortened with `...` when it does not fit.
+pub fn draw_title(img: &mut RgbaImage, title: &str) {
+    let title = title.trim();
+    if title.is_empty() {
+        return;
+    }
+    let scale = if img.width() >= 240 { 2 } else { 1 };
+    let available = img.width().saturating_sub(2 * TITLE_MARGIN);
+
+    let mut text = title.to_string();
+    if text_width(&text, scale) > available {
+        let mut chars: Vec<char> = title.chars().collect();
+        while !chars.is_empty() {
+            chars.pop();
+            text = chars.iter().collect::<String>().trim_end().to_string() + "...";
+            if text_width(&text, scale) <= available {
+                break;
+            }
+        }
+    }
+
+    let band_height = GLYPH_HEIGHT * scale + 2 * TITLE_MARGIN;
+    for y in 0..band_height.min(img.height()) {
+        for x in 0..img.width() {
+            blend_pixel(img, x as i64, y as i64, TITLE_BACKGROUND);
+        }
+    }
+    draw_text(
+        img,
+        &text,
+        TITLE_MARGIN as i64,
+        TITLE_MARGIN as i64,
+        scale,
+        TITLE_COLOR,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    #[test]
+    fn test_draw_title() {
+        let mut img: RgbaImage = ImageBuffer::from_pixel(320, 180, Rgba([0, 0, 255, 255]));
+        draw_title(&mut img, "Temperature 1982-01-02");
+
+        // The band darkens the top edge, with white text on it
+        let band = *img.get_pixel(319, 0);
+        assert!(band[2] < 255 && band[3] == 255);
+        let lit = (0..18)
+            .flat_map(|y| (0..320).map(move |x| (x, y)))
+            .filter(|&(x, y)| *img.get_pixel(x, y) == Rgba(TITLE_COLOR))
+            .count();
+        assert!(lit > 0);
+        assert_eq!(*img.get_pixel(0, 179), Rgba([0, 0, 255, 255]));
+
+        // An empty title leaves the image untouched
+        let mut plain: RgbaImage = ImageBuffer::from_pixel(32, 18, Rgba([0, 0, 255, 255]));
+        draw_title(&mut plain, " ");
+        assert!(plain.pixels().all(|p| *p == Rgba([0, 0, 255, 255])));
+    }
+
+    #[test]
+    fn test_thumbnail_cache() {
+        let cache = ThumbnailCache::new();
+        cache.insert("var=t2m".to_string(), Bytes::from_static(b"png"));
+        assert_eq!(cache.get("var=t2m").unwrap(), Bytes::from_static(b"png"));
+        assert!(cache.get("var=tp").is_none());
+        assert_eq!(cache.clear(), 1);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+//! Long-format ("tidy") Arrow IPC files for DataFrame libraries.
+//!
+//! `/data?format=polars-ipc` returns an Arrow IPC *file* rather than a
+//! stream, so `polars.read_ipc(url)` (or `pandas.read_feather`) loads it in
+//! one call. The table has one row per value: a column per dimension with the
+//! coordinate of the row, a categorical `variable` column naming the variable
+//! and a `value` column. Variables of different dimensionality share the
+//! table; dimensions a variable lacks are null in its rows.
+//!
+//! Cells masked out by a `filter` (see [`crate::filter`]) have no row.
+//!
+//! Time coordinates with CF units are written as categorical ISO 8601 strings
+//! rather than raw offsets. The `variable` and `value` columns are renamed
+//! with a trailing `_` when a dimension already has that name.
+
+use arrow::array::{
+    ArrayRef, DictionaryArray, Float32Array, Float64Array, Int32Array, StringArray,
+};
+use arrow::datatypes::Int32Type;
+use arrow::record_batch::RecordBatch;
+use arrow_ipc::writer::{FileWriter, IpcWriteOptions};
+use arrow_schema::{DataType, Field, Schema};
+use ndarray::{Array, IxDyn};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::cf_time::TimeUnits;
+use crate::error::{Result, RossbyError};
+
+/// Content type of Arrow IPC files
+pub const ARROW_FILE_CONTENT_TYPE: &str = "application/vnd.apache.arrow.file";
+
+/// A variable to write into a tidy table
+pub struct TidyVariable<'a> {
+    /// Name of the variable
+    pub name: &'a str,
+    /// Extracted values
+    pub data: &'a Array<f32, IxDyn>,
+    /// Dimension names of the axes of `data`
+    pub axes: &'a [String],
+    /// All dimensions of the variable, including those selected down to a
+    /// single coordinate and therefore not axes of `data`
+    pub dimensions: &'a [String],
+    /// Which elements of `data`, in logical order, get a row (None = all)
+    pub keep: Option<&'a [bool]>,
+}
+
+impl TidyVariable<'_> {
+    /// Positions of the elements of `data` that get a row
+    fn rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.data.len()).filter(|&i| self.keep.is_none_or(|keep| keep[i]))
+    }
+}
+
+/// Where the coordinates of a dimension column come from for one variable
+enum Source {
+    /// An axis of the data, advancing every `stride` elements
+    Axis { stride: usize, len: usize },
+    /// A dimension selected down to its first coordinate
+    Fixed,
+    /// A dimension the variable does not have
+    Missing,
+}
+
+impl Source {
+    fn new(variable: &TidyVariable, dim: &str) -> Self {
+        match variable.axes.iter().position(|axis| axis == dim) {
+            Some(axis) => Source::Axis {
+                stride: variable.data.shape()[axis + 1..].iter().product(),
+                len: variable.data.shape()[axis],
+            },
+            None if variable.dimensions.iter().any(|d| d == dim) => Source::Fixed,
+            None => Source::Missing,
+        }
+    }
+
+    /// Index of the coordinate of element `i`
+    fn index(&self, i: usize) -> Option<usize> {
+        match self {
+            Source::Axis { stride, len } => Some((i / stride) % len),
+            Source::Fixed => Some(0),
+            Source::Missing => None,
+        }
+    }
+}
+
+/// Name that collides with none of `taken`, by appending underscores
+fn unique_name(name: &str, taken: &[String]) -> String {
+    let mut name = name.to_string();
+    while taken.contains(&name) {
+        name.push('_');
+    }
+    name
+}
+
+/// Write variables as a long-format Arrow IPC file
+///
+/// There is one dimension column per dimension of any variable that has
+/// coordinates in `coordinates`, in order of first appearance. Dimensions
+/// for which `time_units` returns units are written as dictionary-encoded
+/// ISO 8601 strings, others as `Float64`. `metadata` becomes the schema
+/// metadata, and the file is written with `options` (e.g. its buffer
+/// compression).
+pub fn tidy_ipc_file(
+    variables: &[TidyVariable],
+    coordinates: &HashMap<String, Vec<f64>>,
+    time_units: impl Fn(&str) -> Option<TimeUnits>,
+    metadata: HashMap<String, String>,
+    options: IpcWriteOptions,
+) -> Result<Vec<u8>> {
+    let conversion = |message: String| RossbyError::Conversion { message };
+
+    let mut dimensions: Vec<String> = Vec::new();
+    for variable in variables {
+        for dim in variable.dimensions {
+            if coordinates.contains_key(dim) && !dimensions.contains(dim) {
+                dimensions.push(dim.clone());
+            }
+        }
+    }
+    let variable_column = unique_name("variable", &dimensions);
+    let value_column = unique_name("value", &dimensions);
+
+    let mut fields = Vec::new();
+    let mut columns: Vec<ArrayRef> = Vec::new();
+    for dim in &dimensions {
+        let coords = &coordinates[dim];
+        let indices = variables.iter().flat_map(|variable| {
+            let source = Source::new(variable, dim);
+            variable.rows().map(move |i| source.index(i))
+        });
+        match time_units(dim) {
+            Some(units) => {
+                let keys: Int32Array = indices
+                    .map(|index| index.map(|index| index as i32))
+                    .collect();
+                let values: StringArray = coords.iter().map(|&v| units.iso(v)).collect();
+                let array = DictionaryArray::<Int32Type>::try_new(keys, Arc::new(values))
+                    .map_err(|e| conversion(format!("Invalid time column {}: {}", dim, e)))?;
+                fields.push(Field::new(
+                    dim,
+                    DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                    true,
+                ));
+                columns.push(Arc::new(array));
+            }
+            None => {
+                let array: Float64Array = indices
+                    .map(|index| index.map(|index| coords[index]))
+                    .collect();
+                fields.push(Field::new(dim, DataType::Float64, true));
+                columns.push(Arc::new(array));
+            }
+        }
+    }
+
+    // The variable name of each row, as a categorical column
+    let keys: Int32Array = variables
+        .iter()
+        .enumerate()
+        .flat_map(|(index, variable)| variable.rows().map(move |_| index as i32))
+        .map(Some)
+        .collect();
+    let names: StringArray = variables.iter().map(|v| Some(v.name)).collect();
+    let array = DictionaryArray::<Int32Type>::try_new(keys, Arc::new(names))
+        .map_err(|e| conversion(format!("Invalid variable column: {}", e)))?;
+    fields.push(Field::new(
+        &variable_column,
+        DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+        false,
+    ));
+    columns.push(Arc::new(array));
+
+    let values: Float32Array = variables
+        .iter()
+        .flat_map(|variable| {
+            let values = variable.data.iter().copied();
+            values
+                .zip(0..)
+                .filter(|&(_, i)| variable.keep.is_none_or(|keep| keep[i]))
+                .map(|(value, _)| value)
+        })
+        .map(Some)
+        .collect();
+    fields.push(Field::new(&value_column, DataType::Float32, false));
+    columns.push(Arc::new(values));
+
+    let schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+    let batch = RecordBatch::try_new(schema.clone(), columns)
+        .map_err(|e| conversion(format!("Failed to create Arrow record batch: {}", e)))?;
+
+    let mut output = Vec::new();
+    let mut writer = FileWriter::try_new_with_options(&mut output, &schema, options)
+        .map_err(|e| conversion(format!("Failed to create Arrow IPC file writer: {}", e)))?;
+    writer
+        .write(&batch)
+        .map_err(|e| conversion(format!("Failed to write Arrow record batch: {}", e)))?;
+    writer
+        .finish()
+        .map_err(|e| conversion(format!("Failed to finalize Arrow IPC file: {}", e)))?;
+    drop(writer);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array as _, AsArray};
+    use arrow_ipc::reader::FileReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_tidy_ipc_file() {
+        // A 2x2 (time, lat) variable and a 1D (lat) variable at one time
+        let t2m = Array::from_shape_vec(IxDyn(&[2, 2]), vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+        let mask = Array::from_shape_vec(IxDyn(&[2]), vec![0.0, 1.0]).unwrap();
+        let t2m_dims = vec!["time".to_string(), "value".to_string()];
+        let mask_dims = vec!["value".to_string()];
+        let coordinates = HashMap::from([
+            ("time".to_string(), vec![0.0, 24.0]),
+            ("value".to_string(), vec![10.0, 20.0]),
+        ]);
+        let variables = [
+            TidyVariable {
+                name: "t2m",
+                data: &t2m,
+                axes: &t2m_dims,
+                dimensions: &t2m_dims,
+                keep: None,
+            },
+            TidyVariable {
+                name: "mask",
+                data: &mask,
+                axes: &mask_dims,
+                dimensions: &mask_dims,
+                keep: None,
+            },
+        ];
+        let units = TimeUnits::parse("hours since 2000-01-01").unwrap();
+        let bytes = tidy_ipc_file(
+            &variables,
+            &coordinates,
+            |dim| (dim == "time").then_some(units),
+            HashMap::new(),
+            IpcWriteOptions::default(),
+        )
+        .unwrap();
+
+        let reader = FileReader::try_new(Cursor::new(bytes), None).unwrap();
+        let schema = reader.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["time", "value", "variable", "value_"]);
+
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 6);
+
+        // Time is categorical, and null for the variable without it
+        let time = batch.column(0).as_dictionary::<Int32Type>();
+        let labels = time.values().as_string::<i32>();
+        assert_eq!(
+            labels.value(time.keys().value(2) as usize),
+            "2000-01-02T00:00:00Z"
+        );
+        assert!(time.is_null(4));
+
+        let lat = batch
+            .column(1)
+            .as_primitive::<arrow::datatypes::Float64Type>();
+        assert_eq!(
+            lat.values().to_vec(),
+            vec![10.0, 20.0, 10.0, 20.0, 10.0, 20.0]
+        );
+        let variable = batch.column(2).as_dictionary::<Int32Type>();
+        assert_eq!(variable.keys().values().to_vec(), vec![0, 0, 0, 0, 1, 1]);
+        let value = batch
+            .column(3)
+            .as_primitive::<arrow::datatypes::Float32Type>();
+        assert_eq!(value.value(5), 1.0);
+
+        // Filtered cells get no row
+        let keep = [true, false, false, true];
+        let filtered = [TidyVariable {
+            keep: Some(&keep),
+            ..variables[0]
+        }];
+        let bytes = tidy_ipc_file(
+            &filtered,
+            &coordinates,
+            |_| None,
+            HashMap::new(),
+            IpcWriteOptions::default(),
+        )
+        .unwrap();
+        let mut reader = FileReader::try_new(Cursor::new(bytes), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let time = batch
+            .column(0)
+            .as_primitive::<arrow::datatypes::Float64Type>();
+        assert_eq!(time.values().to_vec(), vec![0.0, 24.0]);
+        let value = batch
+            .column(3)
+            .as_primitive::<arrow::datatypes::Float32Type>();
+        assert_eq!(value.values().to_vec(), vec![1.0, 4.0]);
+    }
+}
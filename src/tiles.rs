@@ -0,0 +1,492 @@
+//! Static XYZ tile pregeneration.
+//!
+//! `rossby pregenerate-tiles file.nc --vars t2m --zoom 0-5 --out ./tiles`
+//! renders Web Mercator map tiles of the listed variables into
+//! `<out>/<var>/<z>/<x>/<y>.png` and exits, so small deployments can publish
+//! static maps to object storage without running the server at all.
+//!
+//! Tiles are rendered by the same code as `/image`, with
+//! `projection=mercator` and colors scaled to the whole slice, so
+//! neighbouring tiles match each other and the live endpoint. Tiles that do not overlap the grid are not written; tiles
+//! partially covering it are transparent outside the grid.
+
+use clap::Parser;
+use image::{ImageFormat, RgbaImage};
+use std::io::Cursor;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::arithmetic::variable_metadata;
+use crate::bounds::is_periodic_longitude;
+use crate::colormaps::geoutil::mercator_y;
+use crate::config::Config;
+use crate::data_loader::load_netcdf;
+use crate::error::{Result, RossbyError};
+use crate::field::find_lat_lon_axes;
+use crate::geometry::{lon_indices, unwrap_longitudes, wrap_into_range};
+use crate::handlers::image::{generate_image_response, ImageQuery};
+use crate::state::AppState;
+
+/// Command-line subcommand that pregenerates tiles
+pub const PREGENERATE_COMMAND: &str = "pregenerate-tiles";
+
+/// Highest zoom level that can be pregenerated
+pub const MAX_ZOOM: u8 = 16;
+
+/// Command-line arguments of `rossby pregenerate-tiles`
+#[derive(Parser, Debug)]
+#[command(
+    name = "rossby pregenerate-tiles",
+    bin_name = "rossby pregenerate-tiles"
+)]
+#[command(about = "Render a static XYZ tile tree without running the server")]
+pub struct TileArgs {
+    /// Path to the NetCDF file to render
+    pub netcdf_file: PathBuf,
+
+    /// Comma-separated variables to render
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub vars: Vec<String>,
+
+    /// Zoom levels to render, as a range "min-max" or a single level
+    #[arg(long, default_value = "0-3")]
+    pub zoom: String,
+
+    /// Directory to write the tile tree to
+    #[arg(long, default_value = "tiles")]
+    pub out: PathBuf,
+
+    /// Path to JSON configuration file
+    #[arg(short, long, env = "ROSSBY_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Colormap name (e.g., viridis, plasma, coolwarm)
+    #[arg(long)]
+    pub colormap: Option<String>,
+
+    /// Time index (0-based) to render
+    #[arg(long, default_value_t = 0)]
+    pub time_index: usize,
+
+    /// Width and height of the tiles in pixels
+    #[arg(long, default_value_t = 256)]
+    pub tile_size: u32,
+}
+
+/// What to pregenerate
+#[derive(Debug, Clone)]
+pub struct TileOptions {
+    pub vars: Vec<String>,
+    pub zooms: RangeInclusive<u8>,
+    pub out: PathBuf,
+    pub colormap: Option<String>,
+    pub time_index: usize,
+    pub tile_size: u32,
+}
+
+impl TileOptions {
+    /// Options from the command-line arguments
+    pub fn from_args(args: &TileArgs) -> Result<Self> {
+        if args.tile_size == 0 {
+            return Err(RossbyError::InvalidParameter {
+                param: "tile-size".to_string(),
+                message: "Tile size must be greater than 0".to_string(),
+            });
+        }
+        Ok(Self {
+            vars: args.vars.clone(),
+            zooms: parse_zoom_range(&args.zoom)?,
+            out: args.out.clone(),
+            colormap: args.colormap.clone(),
+            time_index: args.time_index,
+            tile_size: args.tile_size,
+        })
+    }
+}
+
+/// Number of tiles written and skipped by a run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TileSummary {
+    /// Tiles rendered and written
+    pub written: usize,
+    /// Tiles outside the grid or failing to render
+    pub skipped: usize,
+}
+
+/// Parse a zoom range such as "0-5", or a single zoom level
+pub fn parse_zoom_range(text: &str) -> Result<RangeInclusive<u8>> {
+    let invalid = |message: String| RossbyError::InvalidParameter {
+        param: "zoom".to_string(),
+        message,
+    };
+    let level = |part: &str| {
+        part.trim()
+            .parse::<u8>()
+            .map_err(|_| invalid(format!("Invalid zoom level: {}", part)))
+    };
+
+    let (min, max) = match text.split_once('-') {
+        Some((min, max)) => (level(min)?, level(max)?),
+        None => (level(text)?, level(text)?),
+    };
+    if min > max || max > MAX_ZOOM {
+        return Err(invalid(format!(
+            "Zoom range must be 'min-max' with 0 <= min <= max <= {}, got '{}'",
+            MAX_ZOOM, text
+        )));
+    }
+    Ok(min..=max)
+}
+
+/// Bounds of an XYZ tile as `(min_lon, min_lat, max_lon, max_lat)`
+///
+/// Tile `(0, 0)` is the north-western tile; rows grow southwards.
+pub fn tile_bounds(z: u8, x: u32, y: u32) -> (f64, f64, f64, f64) {
+    let n = (1u64 << z) as f64;
+    let lon = |x: f64| x / n * 360.0 - 180.0;
+    let lat = |y: f64| {
+        (std::f64::consts::PI * (1.0 - 2.0 * y / n))
+            .sinh()
+            .atan()
+            .to_degrees()
+    };
+    (
+        lon(x as f64),
+        lat(y as f64 + 1.0),
+        lon(x as f64 + 1.0),
+        lat(y as f64),
+    )
+}
+
+/// Load a dataset and pregenerate its tiles
+pub async fn run(args: TileArgs) -> Result<()> {
+    let options = TileOptions::from_args(&args)?;
+    let config = Config::with_file(args.config.as_ref())?;
+    config.validate()?;
+
+    info!(
+        file_path = %args.netcdf_file.display(),
+        "Loading NetCDF file"
+    );
+    let state = load_netcdf(&args.netcdf_file, config)?;
+    state.validate()?;
+
+    let summary = pregenerate(Arc::new(state), &options).await?;
+    info!(
+        written = summary.written,
+        skipped = summary.skipped,
+        out = %options.out.display(),
+        "Tile pregeneration complete"
+    );
+    Ok(())
+}
+
+/// Render the tile tree of every variable
+pub async fn pregenerate(state: Arc<AppState>, options: &TileOptions) -> Result<TileSummary> {
+    // Check every variable before writing any tile
+    let extents = options
+        .vars
+        .iter()
+        .map(|var| GridExtent::of(&state, var))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut summary = TileSummary::default();
+    for (var, extent) in options.vars.iter().zip(&extents) {
+        for z in options.zooms.clone() {
+            let n = 1u32 << z;
+            for x in 0..n {
+                for y in 0..n {
+                    match render_tile(&state, var, extent, (z, x, y), options).await {
+                        Ok(Some(png)) => {
+                            let dir = options
+                                .out
+                                .join(var)
+                                .join(z.to_string())
+                                .join(x.to_string());
+                            std::fs::create_dir_all(&dir)?;
+                            std::fs::write(dir.join(format!("{}.png", y)), png)?;
+                            summary.written += 1;
+                        }
+                        Ok(None) => summary.skipped += 1,
+                        Err(error) => {
+                            warn!(
+                                var = %var,
+                                tile = %format!("{}/{}/{}", z, x, y),
+                                error = %error,
+                                "Skipping tile that failed to render"
+                            );
+                            summary.skipped += 1;
+                        }
+                    }
+                }
+            }
+            info!(var = %var, zoom = z, "Rendered zoom level");
+        }
+    }
+    Ok(summary)
+}
+
+/// Horizontal extent of a variable's grid
+#[derive(Debug, Clone)]
+struct GridExtent {
+    lon: (f64, f64),
+    lat: (f64, f64),
+    /// Whether the first grid row is the southernmost one
+    south_first: bool,
+    /// Longitudes of a global grid, whose columns wrap around the seam
+    periodic_lon: Option<Vec<f64>>,
+}
+
+impl GridExtent {
+    fn of(state: &AppState, var: &str) -> Result<Self> {
+        let var_meta = variable_metadata(state, var)?;
+        let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+            RossbyError::VariableNotSuitableForImage {
+                name: var.to_string(),
+            }
+        })?;
+        let lon = state.get_coordinate_checked(&var_meta.dimensions[lon_axis])?;
+        let lat = state.get_coordinate_checked(&var_meta.dimensions[lat_axis])?;
+        let range = |coords: &[f64]| {
+            let min = coords.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = coords.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            (min, max)
+        };
+        Ok(Self {
+            lon: range(lon),
+            lat: range(lat),
+            south_first: lat.first() < lat.last(),
+            periodic_lon: is_periodic_longitude(lon).then(|| lon.to_vec()),
+        })
+    }
+
+    /// Longitude pieces of a tile covered by the grid
+    ///
+    /// Returns `(tile_min_lon, tile_max_lon, grid_min_lon, grid_max_lon)`:
+    /// the span of the grid columns of each piece in tile longitudes, and
+    /// the bbox selecting them in the convention of the grid (e.g. 0..360).
+    /// On a global grid, a tile spanning the seam is one piece whose bbox
+    /// crosses it (`grid_min_lon > grid_max_lon`), so the columns on either
+    /// side are rendered together; a tile spanning the globe is split in
+    /// halves. On other grids, no piece crosses the seam.
+    fn lon_pieces(&self, (min_lon, max_lon): (f64, f64)) -> Vec<(f64, f64, f64, f64)> {
+        let Some(lon) = &self.periodic_lon else {
+            return [0.0, 360.0, -360.0]
+                .into_iter()
+                .filter_map(|shift| {
+                    let (min, max) = overlap((min_lon + shift, max_lon + shift), self.lon)?;
+                    Some((min - shift, max - shift, min, max))
+                })
+                .collect();
+        };
+
+        let halves = if max_lon - min_lon >= 360.0 {
+            let middle = (min_lon + max_lon) / 2.0;
+            vec![(min_lon, middle), (middle, max_lon)]
+        } else {
+            vec![(min_lon, max_lon)]
+        };
+        halves
+            .into_iter()
+            .filter_map(|(tile_min, tile_max)| {
+                let (west, east) = (
+                    wrap_into_range(tile_min, lon),
+                    wrap_into_range(tile_max, lon),
+                );
+                let columns: Vec<f64> = lon_indices(lon, west, east)
+                    .into_iter()
+                    .map(|j| lon[j])
+                    .collect();
+                let columns = unwrap_longitudes(&columns);
+                let (first, last) = (*columns.first()?, *columns.last()?);
+                (first < last).then_some((
+                    tile_min + first - west,
+                    tile_min + last - west,
+                    west,
+                    east,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Intersection of two closed intervals
+fn overlap(a: (f64, f64), b: (f64, f64)) -> Option<(f64, f64)> {
+    let (min, max) = (a.0.max(b.0), a.1.min(b.1));
+    (min < max).then_some((min, max))
+}
+
+/// Render one tile as PNG, or `None` if it does not overlap the grid
+async fn render_tile(
+    state: &Arc<AppState>,
+    var: &str,
+    extent: &GridExtent,
+    (z, x, y): (u8, u32, u32),
+    options: &TileOptions,
+) -> Result<Option<Vec<u8>>> {
+    let size = options.tile_size;
+    let (min_lon, min_lat, max_lon, max_lat) = tile_bounds(z, x, y);
+    let Some((lat0, lat1)) = overlap((min_lat, max_lat), extent.lat) else {
+        return Ok(None);
+    };
+
+    // Pixel rows and columns of the tile covered by the grid
+    let row = |lat: f64| {
+        ((mercator_y(max_lat) - mercator_y(lat)) / (mercator_y(max_lat) - mercator_y(min_lat))
+            * size as f64)
+            .round() as u32
+    };
+    let column = |lon: f64| ((lon - min_lon) / (max_lon - min_lon) * size as f64).round() as u32;
+    let (top, bottom) = (row(lat1), row(lat0));
+
+    let mut tile = RgbaImage::new(size, size);
+    let mut rendered = false;
+    for (lon0, lon1, grid_lon0, grid_lon1) in extent.lon_pieces((min_lon, max_lon)) {
+        let (left, right) = (column(lon0), column(lon1));
+        if right <= left || bottom <= top {
+            continue;
+        }
+        let piece = render_piece(
+            state,
+            var,
+            (grid_lon0, lat0, grid_lon1, lat1),
+            (right - left, bottom - top),
+            options,
+        )
+        .await?;
+        // `/image` draws the grid rows in order, but tiles have north up
+        let piece = if extent.south_first {
+            image::imageops::flip_vertical(&piece)
+        } else {
+            piece
+        };
+        image::imageops::overlay(&mut tile, &piece, left as i64, top as i64);
+        rendered = true;
+    }
+    if !rendered {
+        return Ok(None);
+    }
+
+    let mut buffer = Cursor::new(Vec::new());
+    tile.write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to encode PNG: {}", e),
+        })?;
+    Ok(Some(buffer.into_inner()))
+}
+
+/// Render a region with the `/image` code
+async fn render_piece(
+    state: &Arc<AppState>,
+    var: &str,
+    (min_lon, min_lat, max_lon, max_lat): (f64, f64, f64, f64),
+    (width, height): (u32, u32),
+    options: &TileOptions,
+) -> Result<RgbaImage> {
+    let mut params = vec![
+        ("var", var.to_string()),
+        (
+            "bbox",
+            format!("{},{},{},{}", min_lon, min_lat, max_lon, max_lat),
+        ),
+        ("width", width.to_string()),
+        ("height", height.to_string()),
+        ("time_index", options.time_index.to_string()),
+        ("projection", "mercator".to_string()),
+        ("format", "png".to_string()),
+        // Absorbs rounding at the edges of the grid
+        ("bounds", "clamp".to_string()),
+    ];
+    if min_lon > max_lon {
+        // The piece crosses the seam of a global grid
+        params.push(("wrap_longitude", "true".to_string()));
+    }
+    if let Some(colormap) = &options.colormap {
+        params.push(("colormap", colormap.clone()));
+    }
+    let invalid = |e: &dyn std::fmt::Display| RossbyError::InvalidParameter {
+        param: "vars".to_string(),
+        message: format!("Cannot build image query for {}: {}", var, e),
+    };
+    let query = serde_urlencoded::to_string(&params).map_err(|e| invalid(&e))?;
+    let query: ImageQuery = serde_urlencoded::from_str(&query).map_err(|e| invalid(&e))?;
+
+    let response = generate_image_response(state.clone(), &query, &Default::default())?;
+    let png = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to read rendered image: {}", e),
+        })?;
+    image::load_from_memory_with_format(&png, ImageFormat::Png)
+        .map(|image| image.to_rgba8())
+        .map_err(|e| RossbyError::ImageGeneration {
+            message: format!("Failed to decode rendered image: {}", e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_zoom_range() {
+        assert_eq!(parse_zoom_range("0-5").unwrap(), 0..=5);
+        assert_eq!(parse_zoom_range("3").unwrap(), 3..=3);
+        assert!(parse_zoom_range("5-2").is_err());
+        assert!(parse_zoom_range("0-40").is_err());
+        assert!(parse_zoom_range("low").is_err());
+    }
+
+    #[test]
+    fn test_tile_bounds() {
+        let (min_lon, min_lat, max_lon, max_lat) = tile_bounds(0, 0, 0);
+        assert_eq!((min_lon, max_lon), (-180.0, 180.0));
+        assert!((max_lat - crate::colormaps::MAX_MERCATOR_LAT).abs() < 1e-9);
+        assert!((min_lat + crate::colormaps::MAX_MERCATOR_LAT).abs() < 1e-9);
+
+        // The south-eastern tile of zoom 1 ends at the equator
+        let (min_lon, _, max_lon, max_lat) = tile_bounds(1, 1, 1);
+        assert_eq!((min_lon, max_lon), (0.0, 180.0));
+        assert!(max_lat.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lon_pieces() {
+        let lon: Vec<f64> = (0..36).map(|i| i as f64 * 10.0).collect();
+        let global = GridExtent {
+            lon: (0.0, 350.0),
+            lat: (-90.0, 90.0),
+            south_first: true,
+            periodic_lon: Some(lon),
+        };
+        // The western half crosses the seam, including the column at 360
+        assert_eq!(
+            global.lon_pieces((-180.0, 180.0)),
+            vec![(-180.0, 0.0, 180.0, 0.0), (0.0, 180.0, 0.0, 180.0)]
+        );
+        assert_eq!(
+            global.lon_pieces((-90.0, 0.0)),
+            vec![(-90.0, 0.0, 270.0, 0.0)]
+        );
+        // Columns on either side of the seam are rendered as one piece
+        assert_eq!(
+            global.lon_pieces((-15.0, 15.0)),
+            vec![(-10.0, 10.0, 345.0, 15.0)]
+        );
+
+        // A regional grid in 0..360 longitudes, seen from a western tile
+        let regional = GridExtent {
+            lon: (200.0, 300.0),
+            lat: (0.0, 60.0),
+            south_first: false,
+            periodic_lon: None,
+        };
+        assert_eq!(
+            regional.lon_pieces((-180.0, -90.0)),
+            vec![(-160.0, -90.0, 200.0, 270.0)]
+        );
+        assert!(regional.lon_pieces((0.0, 90.0)).is_empty());
+    }
+}
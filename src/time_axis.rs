@@ -0,0 +1,288 @@
+//! Normalization of irregular time axes at load.
+//!
+//! Archives concatenated from several runs can have time stamps out of order
+//! or repeated, where selection by time value and time ranges are not well
+//! defined. When a dataset is loaded, every time axis (the dimension `time`
+//! resolves to, or one whose coordinate has CF time units) is sorted and its
+//! duplicate stamps are dropped, keeping the first or last occurrence in the
+//! file as set by `data.duplicate_times`, or rejecting the file with
+//! `error`. Every variable along the axis is reordered the same way.
+//!
+//! Each change is logged, and the position in the file of every remaining
+//! step is kept, so clients can map indices back to the original file (see
+//! the `time_normalization` section of `/metadata`).
+
+use ndarray::{Array, Axis, IxDyn};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::cf_time::TimeUnits;
+use crate::config::DataConfig;
+use crate::error::{Result, RossbyError};
+use crate::state::Metadata;
+
+/// Which of several steps with the same time stamp to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateTimes {
+    /// The step that comes first in the file
+    First,
+    /// The step that comes last in the file, e.g. a rerun appended later
+    Last,
+    /// Refuse to load the file
+    Error,
+}
+
+impl DuplicateTimes {
+    /// Parse the `data.duplicate_times` setting
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "first" => Ok(DuplicateTimes::First),
+            "last" => Ok(DuplicateTimes::Last),
+            "error" => Ok(DuplicateTimes::Error),
+            other => Err(RossbyError::Config {
+                message: format!(
+                    "Invalid duplicate_times: {}. Must be one of: first, last, error",
+                    other
+                ),
+            }),
+        }
+    }
+}
+
+/// How the steps of a time axis were reordered and deduplicated
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeNormalization {
+    /// Name of the time dimension
+    pub dimension: String,
+    /// Position in the file of each step as served
+    pub original_indices: Vec<usize>,
+    /// Positions in the file of the steps dropped as duplicates
+    pub dropped: Vec<usize>,
+    /// Whether the remaining steps were out of order in the file
+    pub reordered: bool,
+}
+
+/// Sort and deduplicate the time axes of a loaded file
+///
+/// Returns how each time axis that was not already strictly increasing has
+/// been changed.
+pub fn normalize_time_axes(
+    metadata: &mut Metadata,
+    data: &mut HashMap<String, Array<f32, IxDyn>>,
+    config: &DataConfig,
+) -> Result<Vec<TimeNormalization>> {
+    let duplicates = DuplicateTimes::parse(&config.duplicate_times)?;
+    let time_name = config
+        .dimension_aliases
+        .get("time")
+        .map_or("time", String::as_str);
+    let mut dimensions: Vec<String> = metadata
+        .coordinates
+        .keys()
+        .filter(|dim| *dim == time_name || is_time_axis(metadata, dim))
+        .cloned()
+        .collect();
+    dimensions.sort();
+
+    let mut normalizations = Vec::new();
+    for dimension in dimensions {
+        let Some(normalization) = plan(&dimension, &metadata.coordinates[&dimension], duplicates)?
+        else {
+            continue;
+        };
+        warn!(
+            dimension = %dimension,
+            dropped = normalization.dropped.len(),
+            reordered = normalization.reordered,
+            steps = normalization.original_indices.len(),
+            "Time axis is not strictly increasing; sorted and deduplicated"
+        );
+        apply(metadata, data, &normalization);
+        normalizations.push(normalization);
+    }
+    Ok(normalizations)
+}
+
+/// Whether a coordinate has CF time units
+fn is_time_axis(metadata: &Metadata, dim: &str) -> bool {
+    metadata
+        .variables
+        .get(dim)
+        .and_then(|var| TimeUnits::from_attributes(&var.attributes))
+        .is_some()
+}
+
+/// The steps of a time axis to keep, in increasing time order, or `None`
+/// when it is strictly increasing already
+fn plan(
+    dimension: &str,
+    times: &[f64],
+    duplicates: DuplicateTimes,
+) -> Result<Option<TimeNormalization>> {
+    if times.windows(2).all(|pair| pair[0] < pair[1]) {
+        return Ok(None);
+    }
+
+    // A stable sort keeps repeated stamps in file order
+    let mut order: Vec<usize> = (0..times.len()).collect();
+    order.sort_by(|&a, &b| times[a].total_cmp(&times[b]));
+
+    let mut original_indices: Vec<usize> = Vec::with_capacity(order.len());
+    let mut dropped = Vec::new();
+    for index in order {
+        let repeated = original_indices
+            .last()
+            .is_some_and(|&kept| times[kept] == times[index]);
+        if !repeated {
+            original_indices.push(index);
+            continue;
+        }
+        match duplicates {
+            DuplicateTimes::First => dropped.push(index),
+            DuplicateTimes::Last => {
+                if let Some(kept) = original_indices.last_mut() {
+                    dropped.push(std::mem::replace(kept, index));
+                }
+            }
+            DuplicateTimes::Error => {
+                return Err(RossbyError::DataNotFound {
+                    message: format!(
+                        "Time dimension {} repeats the time stamp {} (set data.duplicate_times to first or last to keep one)",
+                        dimension, times[index]
+                    ),
+                })
+            }
+        }
+    }
+    dropped.sort_unstable();
+
+    let reordered = original_indices.windows(2).any(|pair| pair[0] > pair[1]);
+    Ok(Some(TimeNormalization {
+        dimension: dimension.to_string(),
+        original_indices,
+        dropped,
+        reordered,
+    }))
+}
+
+/// Reorder the coordinate and every variable along a time axis
+fn apply(
+    metadata: &mut Metadata,
+    data: &mut HashMap<String, Array<f32, IxDyn>>,
+    normalization: &TimeNormalization,
+) {
+    let dimension = &normalization.dimension;
+    let order = &normalization.original_indices;
+    if let Some(times) = metadata.coordinates.get_mut(dimension) {
+        *times = order.iter().map(|&i| times[i]).collect();
+    }
+    if let Some(dim) = metadata.dimensions.get_mut(dimension) {
+        dim.size = order.len();
+    }
+    for (name, var) in metadata.variables.iter_mut() {
+        let Some(axis) = var.dimensions.iter().position(|dim| dim == dimension) else {
+            continue;
+        };
+        var.shape[axis] = order.len();
+        if let Some(array) = data.get_mut(name) {
+            *array = array.select(Axis(axis), order);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Dimension, Variable};
+
+    fn create_test_data(times: Vec<f64>) -> (Metadata, HashMap<String, Array<f32, IxDyn>>) {
+        let steps = times.len();
+        let variable = |name: &str, dimensions: &[&str], shape: Vec<usize>| Variable {
+            name: name.to_string(),
+            dimensions: dimensions.iter().map(|d| d.to_string()).collect(),
+            shape,
+            attributes: HashMap::new(),
+            dtype: "f32".to_string(),
+        };
+        let dimension = |name: &str, size: usize| Dimension {
+            name: name.to_string(),
+            size,
+            is_unlimited: false,
+        };
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions: HashMap::from([
+                ("time".to_string(), dimension("time", steps)),
+                ("lat".to_string(), dimension("lat", 2)),
+            ]),
+            variables: HashMap::from([
+                ("time".to_string(), variable("time", &["time"], vec![steps])),
+                (
+                    "t2m".to_string(),
+                    variable("t2m", &["time", "lat"], vec![steps, 2]),
+                ),
+            ]),
+            coordinates: HashMap::from([
+                ("time".to_string(), times.clone()),
+                ("lat".to_string(), vec![0.0, 1.0]),
+            ]),
+            groups: HashMap::new(),
+        };
+        // Each value encodes the position of its step in the file
+        let t2m = Array::from_shape_fn(IxDyn(&[steps, 2]), |index| {
+            (index[0] * 10 + index[1]) as f32
+        });
+        let data = HashMap::from([
+            (
+                "time".to_string(),
+                Array::from_shape_vec(IxDyn(&[steps]), times.iter().map(|&t| t as f32).collect())
+                    .unwrap(),
+            ),
+            ("t2m".to_string(), t2m),
+        ]);
+        (metadata, data)
+    }
+
+    #[test]
+    fn test_normalize_time_axes() {
+        let config = DataConfig::default();
+        // Regular axes are left alone
+        let (mut metadata, mut data) = create_test_data(vec![0.0, 6.0, 12.0]);
+        let normalizations = normalize_time_axes(&mut metadata, &mut data, &config).unwrap();
+        assert!(normalizations.is_empty());
+
+        let (mut metadata, mut data) = create_test_data(vec![0.0, 12.0, 6.0, 12.0, 18.0]);
+        let normalizations = normalize_time_axes(&mut metadata, &mut data, &config).unwrap();
+        assert_eq!(
+            normalizations,
+            vec![TimeNormalization {
+                dimension: "time".to_string(),
+                original_indices: vec![0, 2, 1, 4],
+                dropped: vec![3],
+                reordered: true,
+            }]
+        );
+        assert_eq!(metadata.coordinates["time"], vec![0.0, 6.0, 12.0, 18.0]);
+        assert_eq!(metadata.dimensions["time"].size, 4);
+        assert_eq!(metadata.variables["t2m"].shape, vec![4, 2]);
+        let t2m = &data["t2m"];
+        assert_eq!(
+            t2m.iter().copied().collect::<Vec<_>>(),
+            vec![0.0, 1.0, 20.0, 21.0, 10.0, 11.0, 40.0, 41.0]
+        );
+        assert_eq!(data["time"].as_slice().unwrap(), [0.0, 6.0, 12.0, 18.0]);
+    }
+
+    #[test]
+    fn test_duplicate_policies() {
+        let times = [0.0, 6.0, 6.0, 12.0];
+        let last = plan("time", &times, DuplicateTimes::Last).unwrap().unwrap();
+        assert_eq!(last.original_indices, vec![0, 2, 3]);
+        assert_eq!(last.dropped, vec![1]);
+        assert!(!last.reordered);
+
+        assert!(plan("time", &times, DuplicateTimes::Error).is_err());
+        assert!(DuplicateTimes::parse("latest").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Uncertainty companion variables.
+//!
+//! Ensemble and reanalysis products often ship the spread of a variable as a
+//! variable of its own, e.g. `t2m_stddev` next to `t2m`. Declaring the pair
+//! in `data.uncertainty` lets `/point` return the companion value next to
+//! each value with `include_uncertainty=true`, and `/image` show it with
+//! `uncertainty=alpha` (fading uncertain pixels) or `uncertainty=hatch`
+//! (hatching them). A companion must have the same dimensions as its
+//! variable, so that it is sliced the same way.
+
+use image::RgbaImage;
+
+use crate::colormaps::Colormap;
+use crate::error::{Result, RossbyError};
+use crate::state::AppState;
+
+/// Opacity of the most uncertain pixels with `uncertainty=alpha`
+const MIN_OPACITY: f32 = 0.25;
+
+/// Distance in pixels between hatch lines, and the width of the lines
+const HATCH_SPACING: u32 = 8;
+const HATCH_WIDTH: u32 = 2;
+
+/// Normalized uncertainty from which pixels are hatched
+const HATCH_THRESHOLD: f32 = 0.5;
+
+/// Color of hatch lines, blended over the pixels by its alpha
+const HATCH_COLOR: [u8; 4] = [32, 32, 32, 160];
+
+/// Companion variable holding the uncertainty of `var`, if one is configured
+///
+/// Fails if the companion is not loaded or its dimensions differ from those
+/// of the variable.
+pub fn companion<'a>(state: &'a AppState, var: &str) -> Result<Option<&'a str>> {
+    let Some(companion) = state.config.data.uncertainty.get(var) else {
+        return Ok(None);
+    };
+    let var_meta = state.get_variable_metadata_checked(var)?;
+    let companion_meta =
+        state
+            .get_variable_metadata(companion)
+            .ok_or_else(|| RossbyError::DataNotFound {
+                message: format!(
+                    "Uncertainty variable '{}' of '{}' is not loaded",
+                    companion, var
+                ),
+            })?;
+    if companion_meta.dimensions != var_meta.dimensions {
+        return Err(RossbyError::DataNotFound {
+            message: format!(
+                "Uncertainty variable '{}' has dimensions {:?}, '{}' has {:?}",
+                companion, companion_meta.dimensions, var, var_meta.dimensions
+            ),
+        });
+    }
+    Ok(Some(companion.as_str()))
+}
+
+/// How `/image` shows the uncertainty of the rendered values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UncertaintyStyle {
+    /// Uncertain pixels fade out, down to a quarter of their opacity
+    Alpha,
+    /// Pixels at least half as uncertain as `uncertainty_max` are hatched
+    Hatch,
+}
+
+impl UncertaintyStyle {
+    /// Parse the `uncertainty` parameter
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "alpha" => Ok(UncertaintyStyle::Alpha),
+            "hatch" => Ok(UncertaintyStyle::Hatch),
+            other => Err(RossbyError::InvalidParameter {
+                param: "uncertainty".to_string(),
+                message: format!(
+                    "Unknown uncertainty style: {}. Valid values are 'alpha' or 'hatch'",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Modulate a rendered image by the uncertainty of its pixels
+    ///
+    /// `uncertainty` is the companion rendered to the same size with
+    /// [`UncertaintyLevels`]; its transparent pixels leave the image as is.
+    pub fn apply(self, img: &mut RgbaImage, uncertainty: &RgbaImage) {
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let level = uncertainty.get_pixel(x, y);
+            if level[3] == 0 {
+                continue;
+            }
+            let level = level[0] as f32 / 255.0;
+            match self {
+                UncertaintyStyle::Alpha => {
+                    let opacity = 1.0 - level * (1.0 - MIN_OPACITY);
+                    pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+                }
+                UncertaintyStyle::Hatch => {
+                    if level >= HATCH_THRESHOLD && (x + y) % HATCH_SPACING < HATCH_WIDTH {
+                        let weight = HATCH_COLOR[3] as f32 / 255.0;
+                        for channel in 0..3 {
+                            pixel[channel] = (pixel[channel] as f32 * (1.0 - weight)
+                                + HATCH_COLOR[channel] as f32 * weight)
+                                .round() as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Colormap rendering normalized uncertainty into the red channel, so that
+/// the companion is resampled exactly like the image it modulates
+pub struct UncertaintyLevels;
+
+impl Colormap for UncertaintyLevels {
+    fn map_normalized(&self, value: f32) -> [u8; 4] {
+        [(value.clamp(0.0, 1.0) * 255.0).round() as u8, 0, 0, 255]
+    }
+
+    fn name(&self) -> &str {
+        "uncertainty"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn levels(values: &[f32]) -> RgbaImage {
+        let mut img = RgbaImage::new(values.len() as u32, 1);
+        for (x, &value) in values.iter().enumerate() {
+            let color = if value.is_nan() {
+                [0, 0, 0, 0]
+            } else {
+                UncertaintyLevels.map_normalized(value)
+            };
+            img.put_pixel(x as u32, 0, Rgba(color));
+        }
+        img
+    }
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(
+            UncertaintyStyle::parse("Alpha").unwrap(),
+            UncertaintyStyle::Alpha
+        );
+        assert_eq!(
+            UncertaintyStyle::parse("hatch").unwrap(),
+            UncertaintyStyle::Hatch
+        );
+        assert!(UncertaintyStyle::parse("blur").is_err());
+    }
+
+    #[test]
+    fn test_alpha_fades_uncertain_pixels() {
+        let mut img = RgbaImage::from_pixel(3, 1, Rgba([200, 100, 50, 255]));
+        UncertaintyStyle::Alpha.apply(&mut img, &levels(&[0.0, 1.0, f32::NAN]));
+        assert_eq!(img.get_pixel(0, 0)[3], 255);
+        assert_eq!(img.get_pixel(1, 0)[3], 64);
+        assert_eq!(img.get_pixel(2, 0)[3], 255);
+        assert_eq!(img.get_pixel(1, 0)[0], 200);
+    }
+
+    #[test]
+    fn test_hatch_marks_uncertain_pixels() {
+        let original = Rgba([200, 200, 200, 255]);
+        let mut img = RgbaImage::from_pixel(4, 1, original);
+        UncertaintyStyle::Hatch.apply(&mut img, &levels(&[0.9, 0.9, 0.9, 0.1]));
+        // Only pixels on hatch lines change
+        assert_ne!(*img.get_pixel(0, 0), original);
+        assert_ne!(*img.get_pixel(1, 0), original);
+        assert_eq!(*img.get_pixel(2, 0), original);
+        assert_eq!(img.get_pixel(0, 0)[3], 255);
+
+        let mut img = RgbaImage::from_pixel(1, 1, original);
+        UncertaintyStyle::Hatch.apply(&mut img, &levels(&[0.1]));
+        assert_eq!(*img.get_pixel(0, 0), original);
+    }
+}
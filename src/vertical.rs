@@ -0,0 +1,596 @@
+//! Vertical coordinate transforms for model-level data.
+//!
+//! Atmospheric models write their output on terrain-following model levels,
+//! described by CF parametric vertical coordinates: the level coordinate
+//! variable carries a `standard_name` such as
+//! `atmosphere_hybrid_sigma_pressure_coordinate` and a `formula_terms`
+//! attribute naming the coefficient and surface pressure variables. From these
+//! the pressure of every grid point can be derived as `p = a + b * ps`.
+//!
+//! `PressureFormula` reads such a coordinate, `PressureFieldCache` keeps the
+//! derived 3D pressure fields (one per surface pressure slice), and
+//! [`interpolate_to_level`] interpolates a variable to a pressure surface,
+//! linearly in log-pressure. Heights are converted to pressure with the ICAO
+//! standard atmosphere, so height surfaces are approximate.
+
+use ndarray::{Array3, ArrayView3, Ix3};
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::error::{Result, RossbyError};
+use crate::field::{find_lat_lon_axes, HorizontalField};
+use crate::slice_stats::MissingData;
+use crate::state::{AppState, AttributeValue};
+
+/// Standard name of hybrid sigma-pressure coordinates
+const HYBRID_SIGMA_PRESSURE: &str = "atmosphere_hybrid_sigma_pressure_coordinate";
+
+/// Standard name of sigma coordinates
+const SIGMA: &str = "atmosphere_sigma_coordinate";
+
+/// Number of derived pressure fields kept before the cache is reset
+const MAX_CACHED_PRESSURE_FIELDS: usize = 8;
+
+/// Kind of vertical coordinate a requested level refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelType {
+    /// The native level coordinate of the variable
+    Model,
+    /// Pressure in hPa
+    Pressure,
+    /// Height above mean sea level in meters
+    Height,
+}
+
+impl LevelType {
+    /// Parse the `level_type` query parameter (defaults to model levels)
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(str::to_lowercase).as_deref() {
+            None | Some("model") => Ok(LevelType::Model),
+            Some("pressure") => Ok(LevelType::Pressure),
+            Some("height") => Ok(LevelType::Height),
+            Some(other) => Err(RossbyError::InvalidParameter {
+                param: "level_type".to_string(),
+                message: format!(
+                    "Unknown level type '{}'. Must be one of: model, pressure, height",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Target pressure in Pa for a level given in this type's units
+    ///
+    /// Returns `None` for model levels, which are selected directly.
+    pub fn target_pressure(&self, level: f64) -> Option<f64> {
+        match self {
+            LevelType::Model => None,
+            LevelType::Pressure => Some(level * 100.0),
+            LevelType::Height => Some(standard_atmosphere_pressure(level)),
+        }
+    }
+}
+
+/// Pressure in Pa at a height in meters in the ICAO standard atmosphere
+///
+/// Uses the troposphere lapse rate up to 11 km and the isothermal lower
+/// stratosphere above.
+pub fn standard_atmosphere_pressure(height: f64) -> f64 {
+    const TROPOPAUSE: f64 = 11_000.0;
+    const TROPOPAUSE_PRESSURE: f64 = 22_632.06;
+
+    if height <= TROPOPAUSE {
+        101_325.0 * (1.0 - 2.255_77e-5 * height).powf(5.255_88)
+    } else {
+        TROPOPAUSE_PRESSURE * (-1.576_88e-4 * (height - TROPOPAUSE)).exp()
+    }
+}
+
+/// Pressure of model levels as `p(k) = a(k) + b(k) * ps`, in Pa
+#[derive(Debug, Clone, PartialEq)]
+pub struct PressureFormula {
+    /// Model level dimension the formula applies to
+    pub level_dim: String,
+    /// Surface pressure variable
+    pub ps_var: String,
+    /// Pressure term per level, in Pa
+    a: Vec<f64>,
+    /// Surface pressure weight per level
+    b: Vec<f64>,
+    /// Factor converting surface pressure to Pa
+    ps_to_pa: f64,
+}
+
+impl PressureFormula {
+    /// Read the formula of a level dimension from its coordinate variable
+    ///
+    /// Returns `None` if the coordinate is not a supported parametric
+    /// vertical coordinate.
+    pub fn from_coordinate(state: &AppState, level_dim: &str) -> Result<Option<Self>> {
+        let coord_meta = match state.get_variable_metadata(level_dim) {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        let standard_name = match coord_meta.attributes.get("standard_name") {
+            Some(AttributeValue::Text(name)) => name.as_str(),
+            _ => return Ok(None),
+        };
+        if standard_name != HYBRID_SIGMA_PRESSURE && standard_name != SIGMA {
+            return Ok(None);
+        }
+
+        let terms = match coord_meta.attributes.get("formula_terms") {
+            Some(AttributeValue::Text(terms)) => parse_formula_terms(level_dim, terms)?,
+            _ => {
+                return Err(RossbyError::DataNotFound {
+                    message: format!(
+                        "Vertical coordinate {} has no formula_terms attribute",
+                        level_dim
+                    ),
+                })
+            }
+        };
+        let term = |name: &str| -> Result<&String> {
+            terms.get(name).ok_or_else(|| RossbyError::DataNotFound {
+                message: format!("formula_terms of {} do not define '{}'", level_dim, name),
+            })
+        };
+
+        let levels = state
+            .get_variable_metadata_checked(level_dim)?
+            .shape
+            .first()
+            .copied()
+            .unwrap_or(0);
+        let ps_var = term("ps")?.clone();
+
+        let (a, b) = if standard_name == SIGMA {
+            let sigma = coefficients(state, term("sigma")?, levels, 1.0)?;
+            let ptop = scalar_pressure(state, term("ptop")?)?;
+            let a = sigma.iter().map(|s| ptop * (1.0 - s)).collect();
+            (a, sigma)
+        } else if let Some(ap) = terms.get("ap") {
+            let ap = coefficients(state, ap, levels, pressure_scale(state, ap)?)?;
+            (ap, coefficients(state, term("b")?, levels, 1.0)?)
+        } else {
+            let p0 = scalar_pressure(state, term("p0")?)?;
+            let a = coefficients(state, term("a")?, levels, p0)?;
+            (a, coefficients(state, term("b")?, levels, 1.0)?)
+        };
+
+        Ok(Some(Self {
+            level_dim: level_dim.to_string(),
+            ps_to_pa: pressure_scale(state, &ps_var)?,
+            ps_var,
+            a,
+            b,
+        }))
+    }
+
+    /// Find the pressure formula of a variable's vertical dimension
+    pub fn for_variable(state: &AppState, var_name: &str) -> Result<Self> {
+        let var_meta = state.get_variable_metadata_checked(var_name)?;
+        for dim in &var_meta.dimensions {
+            if let Some(formula) = Self::from_coordinate(state, dim)? {
+                return Ok(formula);
+            }
+        }
+        Err(RossbyError::InvalidParameter {
+            param: "level_type".to_string(),
+            message: format!(
+                "Variable '{}' has no hybrid sigma-pressure or sigma level dimension \
+                 with surface pressure to derive pressure from",
+                var_name
+            ),
+        })
+    }
+
+    /// Pressure of level `k` in Pa over a surface pressure in Pa
+    pub fn pressure(&self, k: usize, ps: f64) -> f64 {
+        self.a[k] + self.b[k] * ps
+    }
+}
+
+/// Parse `formula_terms` of the form "a: hyam b: hybm p0: P0 ps: PS"
+///
+/// Variables are looked up in the group of the level coordinate.
+fn parse_formula_terms(level_dim: &str, terms: &str) -> Result<HashMap<String, String>> {
+    let group = level_dim.rsplit_once('/').map(|(group, _)| group);
+    let tokens: Vec<&str> = terms.split_whitespace().collect();
+
+    tokens
+        .chunks(2)
+        .map(|pair| match pair {
+            [term, var] if term.ends_with(':') => {
+                let var = match group {
+                    Some(group) => format!("{}/{}", group, var),
+                    None => var.to_string(),
+                };
+                Ok((term.trim_end_matches(':').to_string(), var))
+            }
+            _ => Err(RossbyError::Conversion {
+                message: format!("Invalid formula_terms for {}: '{}'", level_dim, terms),
+            }),
+        })
+        .collect()
+}
+
+/// Factor converting a pressure variable to Pa, from its `units` attribute
+fn pressure_scale(state: &AppState, var_name: &str) -> Result<f64> {
+    let units = state
+        .get_variable_metadata_checked(var_name)?
+        .attributes
+        .get("units");
+    match units {
+        None => Ok(1.0),
+        Some(AttributeValue::Text(units)) => match units.trim() {
+            "Pa" | "pa" | "1" | "" => Ok(1.0),
+            "hPa" | "hpa" | "mbar" | "mb" | "millibar" => Ok(100.0),
+            "kPa" | "kpa" => Ok(1000.0),
+            other => Err(RossbyError::Conversion {
+                message: format!("Unsupported pressure units for {}: '{}'", var_name, other),
+            }),
+        },
+        Some(_) => Ok(1.0),
+    }
+}
+
+/// Values of a 1D coefficient variable over the levels, multiplied by `scale`
+fn coefficients(state: &AppState, var_name: &str, levels: usize, scale: f64) -> Result<Vec<f64>> {
+    let data = state.get_variable_checked(var_name)?;
+    if data.len() != levels {
+        return Err(RossbyError::Conversion {
+            message: format!(
+                "Coefficient {} has {} values for {} levels",
+                var_name,
+                data.len(),
+                levels
+            ),
+        });
+    }
+    Ok(data.iter().map(|&v| v as f64 * scale).collect())
+}
+
+/// Value of a scalar pressure variable in Pa
+fn scalar_pressure(state: &AppState, var_name: &str) -> Result<f64> {
+    let data = state.get_variable_checked(var_name)?;
+    let value = data
+        .iter()
+        .next()
+        .ok_or_else(|| RossbyError::DataNotFound {
+            message: format!("Pressure variable {} is empty", var_name),
+        })?;
+    Ok(*value as f64 * pressure_scale(state, var_name)?)
+}
+
+/// Identity of a derived pressure field
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PressureKey {
+    level_dim: String,
+    ps_var: String,
+    ps_indices: BTreeMap<String, usize>,
+}
+
+/// Derived 3D pressure fields (`[level, lat, lon]`, in Pa), computed on first use
+#[derive(Debug, Clone, Default)]
+pub struct PressureFieldCache {
+    entries: Arc<RwLock<HashMap<PressureKey, Arc<Array3<f64>>>>>,
+}
+
+impl PressureFieldCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pressure of every grid point with the surface pressure slice pinned by
+    /// `dim_indices` (missing entries use index 0)
+    pub fn get_or_compute(
+        &self,
+        state: &AppState,
+        formula: &PressureFormula,
+        dim_indices: &HashMap<String, usize>,
+    ) -> Result<Arc<Array3<f64>>> {
+        let ps_meta = state.get_variable_metadata_checked(&formula.ps_var)?;
+        let ps_indices: BTreeMap<String, usize> = ps_meta
+            .dimensions
+            .iter()
+            .filter_map(|dim| dim_indices.get(dim).map(|&index| (dim.clone(), index)))
+            .collect();
+        let key = PressureKey {
+            level_dim: formula.level_dim.clone(),
+            ps_var: formula.ps_var.clone(),
+            ps_indices,
+        };
+
+        if let Some(field) = self.entries.read().get(&key) {
+            return Ok(field.clone());
+        }
+
+        let pinned: HashMap<String, usize> = key.ps_indices.clone().into_iter().collect();
+        let ps = HorizontalField::from_state(state, &formula.ps_var, &pinned, None)?;
+        let (rows, cols) = ps.values.dim();
+        let field = Arc::new(Array3::from_shape_fn(
+            (formula.a.len(), rows, cols),
+            |(k, j, i)| formula.pressure(k, ps.values[[j, i]] as f64 * formula.ps_to_pa),
+        ));
+
+        let mut entries = self.entries.write();
+        if entries.len() >= MAX_CACHED_PRESSURE_FIELDS {
+            entries.clear();
+        }
+        entries.insert(key, field.clone());
+        Ok(field)
+    }
+
+    /// Drop every cached field, returning how many there were
+    pub fn clear(&self) -> usize {
+        std::mem::take(&mut *self.entries.write()).len()
+    }
+}
+
+/// Interpolate a model-level variable to a pressure surface
+///
+/// `dim_indices` pins every dimension other than latitude, longitude and the
+/// model level (missing entries use index 0). Values are interpolated linearly
+/// in log-pressure between the two levels bracketing `target_pa`; grid points
+/// where the surface lies outside the column (e.g. below ground) are NaN.
+pub fn interpolate_to_level(
+    state: &AppState,
+    var_name: &str,
+    dim_indices: &HashMap<String, usize>,
+    target_pa: f64,
+) -> Result<HorizontalField> {
+    let formula = PressureFormula::for_variable(state, var_name)?;
+    let var_meta = state.get_variable_metadata_checked(var_name)?;
+    let (lat_axis, lon_axis) = find_lat_lon_axes(&var_meta.dimensions).ok_or_else(|| {
+        RossbyError::VariableNotSuitableForImage {
+            name: var_name.to_string(),
+        }
+    })?;
+    let level_axis = var_meta
+        .dimensions
+        .iter()
+        .position(|dim| *dim == formula.level_dim)
+        .ok_or_else(|| RossbyError::DimensionNotFound {
+            name: formula.level_dim.clone(),
+            available: var_meta.dimensions.clone(),
+            aliases: HashMap::new(),
+        })?;
+
+    // Pin everything but the level and horizontal axes; packed values are
+    // converted for the remaining cube only
+    let pins: Vec<(usize, usize)> = var_meta
+        .dimensions
+        .iter()
+        .enumerate()
+        .filter(|&(axis, _)| axis != lat_axis && axis != lon_axis && axis != level_axis)
+        .map(|(axis, dim_name)| (axis, dim_indices.get(dim_name).copied().unwrap_or(0)))
+        .collect();
+    let data = state.get_variable_indexed(var_name, &pins)?;
+    let view = data.view();
+
+    // Order the remaining axes as [level, lat, lon]
+    let rank = |axis: usize| {
+        [level_axis, lat_axis, lon_axis]
+            .iter()
+            .filter(|&&other| other < axis)
+            .count()
+    };
+    let cube: ArrayView3<f32> = view
+        .into_dimensionality::<Ix3>()
+        .map_err(|e| RossbyError::Conversion {
+            message: format!("Expected a 3D level/latitude/longitude cube: {}", e),
+        })?
+        .permuted_axes([rank(level_axis), rank(lat_axis), rank(lon_axis)]);
+
+    let pressure = state
+        .pressure_fields
+        .get_or_compute(state, &formula, dim_indices)?;
+    if pressure.dim() != cube.dim() {
+        return Err(RossbyError::Conversion {
+            message: format!(
+                "Shape of {} {:?} does not match the pressure field {:?} derived from {}",
+                var_name,
+                cube.dim(),
+                pressure.dim(),
+                formula.ps_var
+            ),
+        });
+    }
+
+    let missing = MissingData::for_variable(var_meta);
+    let (levels, rows, cols) = cube.dim();
+    let log_target = target_pa.ln();
+    let values = ndarray::Array2::from_shape_fn((rows, cols), |(j, i)| {
+        (0..levels.saturating_sub(1))
+            .find_map(|k| {
+                let (p0, p1) = (pressure[[k, j, i]], pressure[[k + 1, j, i]]);
+                if !(p0.min(p1)..=p0.max(p1)).contains(&target_pa) {
+                    return None;
+                }
+                let (v0, v1) = (cube[[k, j, i]], cube[[k + 1, j, i]]);
+                if missing.is_missing(v0) || missing.is_missing(v1) {
+                    return Some(f32::NAN);
+                }
+                let weight = if p0 == p1 {
+                    0.0
+                } else {
+                    (log_target - p0.ln()) / (p1.ln() - p0.ln())
+                };
+                Some(v0 + (v1 - v0) * weight as f32)
+            })
+            .unwrap_or(f32::NAN)
+    });
+
+    Ok(HorizontalField {
+        lat: state
+            .get_coordinate_checked(&var_meta.dimensions[lat_axis])?
+            .clone(),
+        lon: state
+            .get_coordinate_checked(&var_meta.dimensions[lon_axis])?
+            .clone(),
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::{Dimension, Metadata, Variable};
+    use ndarray::{Array, IxDyn};
+
+    fn variable(
+        name: &str,
+        dims: &[&str],
+        shape: &[usize],
+        attributes: &[(&str, &str)],
+    ) -> Variable {
+        Variable {
+            name: name.to_string(),
+            dimensions: dims.iter().map(|d| d.to_string()).collect(),
+            shape: shape.to_vec(),
+            attributes: attributes
+                .iter()
+                .map(|(k, v)| (k.to_string(), AttributeValue::Text(v.to_string())))
+                .collect(),
+            dtype: "f32".to_string(),
+        }
+    }
+
+    /// Three hybrid levels over a 1x2 grid with surface pressures of 1000 and 800 hPa
+    fn create_test_state() -> AppState {
+        let mut dimensions = HashMap::new();
+        for (name, size) in [("lev", 3), ("lat", 1), ("lon", 2)] {
+            dimensions.insert(
+                name.to_string(),
+                Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                },
+            );
+        }
+
+        let variables = [
+            variable(
+                "lev",
+                &["lev"],
+                &[3],
+                &[
+                    ("standard_name", HYBRID_SIGMA_PRESSURE),
+                    ("formula_terms", "a: hyam b: hybm p0: P0 ps: PS"),
+                ],
+            ),
+            variable("hyam", &["lev"], &[3], &[]),
+            variable("hybm", &["lev"], &[3], &[]),
+            variable("P0", &[], &[], &[("units", "Pa")]),
+            variable("PS", &["lat", "lon"], &[1, 2], &[("units", "hPa")]),
+            variable("T", &["lev", "lat", "lon"], &[3, 1, 2], &[]),
+            variable("lat", &["lat"], &[1], &[]),
+            variable("lon", &["lon"], &[2], &[]),
+        ]
+        .into_iter()
+        .map(|v| (v.name.clone(), v))
+        .collect();
+
+        let coordinates = HashMap::from([
+            ("lev".to_string(), vec![0.0, 1.0, 2.0]),
+            ("lat".to_string(), vec![45.0]),
+            ("lon".to_string(), vec![0.0, 10.0]),
+        ]);
+
+        // Pure pressure level at 200 hPa, then two sigma levels at 0.5 and 1.0
+        let arrays: [(&str, &[usize], Vec<f32>); 8] = [
+            ("lev", &[3], vec![0.0, 1.0, 2.0]),
+            ("hyam", &[3], vec![0.2, 0.0, 0.0]),
+            ("hybm", &[3], vec![0.0, 0.5, 1.0]),
+            ("P0", &[], vec![100_000.0]),
+            ("PS", &[1, 2], vec![1000.0, 800.0]),
+            (
+                "T",
+                &[3, 1, 2],
+                vec![220.0, 210.0, 260.0, 250.0, 290.0, 280.0],
+            ),
+            ("lat", &[1], vec![45.0]),
+            ("lon", &[2], vec![0.0, 10.0]),
+        ];
+        let data = arrays
+            .into_iter()
+            .map(|(name, shape, values)| {
+                (
+                    name.to_string(),
+                    Array::from_shape_vec(IxDyn(shape), values).unwrap(),
+                )
+            })
+            .collect();
+
+        let metadata = Metadata {
+            global_attributes: HashMap::new(),
+            dimensions,
+            variables,
+            coordinates,
+            groups: HashMap::new(),
+        };
+        AppState::new(Config::default(), metadata, data)
+    }
+
+    #[test]
+    fn test_formula_terms() {
+        let state = create_test_state();
+        let formula = PressureFormula::for_variable(&state, "T").unwrap();
+        assert_eq!(formula.level_dim, "lev");
+        assert_eq!(formula.ps_var, "PS");
+        assert!((formula.pressure(0, 100_000.0) - 20_000.0).abs() < 1e-2);
+        assert!((formula.pressure(1, 80_000.0) - 40_000.0).abs() < 1e-2);
+
+        assert!(PressureFormula::from_coordinate(&state, "lat")
+            .unwrap()
+            .is_none());
+        assert!(PressureFormula::for_variable(&state, "PS").is_err());
+
+        assert_eq!(
+            parse_formula_terms("forecast/lev", "ap: ap b: b ps: ps").unwrap()["ps"],
+            "forecast/ps"
+        );
+        assert!(parse_formula_terms("lev", "a: hyam b:").is_err());
+    }
+
+    #[test]
+    fn test_interpolate_to_pressure() {
+        let state = create_test_state();
+
+        // 500 hPa is a model level in the first column; in the second column
+        // (levels at 200, 400 and 800 hPa) it lies between levels 1 and 2
+        let field = interpolate_to_level(&state, "T", &HashMap::new(), 50_000.0).unwrap();
+        assert!((field.values[[0, 0]] - 260.0).abs() < 1e-4);
+        let weight = ((50_000f64).ln() - (40_000f64).ln()) / ((80_000f64).ln() - (40_000f64).ln());
+        let expected = 250.0 + 30.0 * weight as f32;
+        assert!((field.values[[0, 1]] - expected).abs() < 1e-4);
+
+        // 900 hPa is below the surface of the second column
+        let field = interpolate_to_level(&state, "T", &HashMap::new(), 90_000.0).unwrap();
+        assert!(field.values[[0, 0]].is_finite());
+        assert!(field.values[[0, 1]].is_nan());
+    }
+
+    #[test]
+    fn test_level_types() {
+        assert_eq!(LevelType::parse(None).unwrap(), LevelType::Model);
+        assert_eq!(
+            LevelType::parse(Some("Pressure")).unwrap(),
+            LevelType::Pressure
+        );
+        assert!(LevelType::parse(Some("isentropic")).is_err());
+
+        assert_eq!(LevelType::Pressure.target_pressure(500.0), Some(50_000.0));
+        assert_eq!(LevelType::Model.target_pressure(3.0), None);
+        let sea_level = LevelType::Height.target_pressure(0.0).unwrap();
+        assert!((sea_level - 101_325.0).abs() < 1e-6);
+        assert!((standard_atmosphere_pressure(5_500.0) - 50_500.0).abs() < 200.0);
+        assert!((standard_atmosphere_pressure(11_000.0) - 22_632.0).abs() < 5.0);
+        assert!((standard_atmosphere_pressure(16_000.0) - 10_287.0).abs() < 5.0);
+    }
+}
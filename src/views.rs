@@ -0,0 +1,850 @@
+//! Named views: preset selections served as virtual variables.
+//!
+//! Operators define views in the `data.views` config section, each a
+//! variable with an optional bounding box, dimension selectors and time
+//! aggregation, e.g. the daily mean of `t2m` over Europe. A view is built
+//! once when the dataset is loaded (and again when time steps are appended)
+//! into a small dataset of its own, holding one variable named after the
+//! view on the cropped grid and aggregated time axis. `/image` and `/data`
+//! requests naming a view are served from that dataset, so heavy selections
+//! get stable names and are only computed once, and `/metadata` lists the
+//! views alongside the file's variables.
+//!
+//! Aggregated values are unpacked (`scale_factor`/`add_offset`) and missing
+//! values are NaN, so the view variable drops the packing and missing-data
+//! attributes of its source.
+//!
+//! Means weight every time step by the length of the interval it stands
+//! for, so a 12-hourly step counts twice as much as a 6-hourly one on an
+//! irregular axis. The intervals come from the time bounds variable named by
+//! the `bounds` attribute of the time coordinate when there is one, and are
+//! otherwise inferred from the spacing of the steps, reaching halfway to the
+//! neighbouring steps. The weighting applied is recorded in the
+//! `time_weights` attribute of the view variable.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use ndarray::{stack, Array, ArrayD, Axis, IxDyn, Zip};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::cf_time::{time_units, TimeUnits};
+use crate::climatology::unpack;
+use crate::colormaps::parse_bbox;
+use crate::config::ViewConfig;
+use crate::error::{Result, RossbyError};
+use crate::field::find_lat_lon_axes;
+use crate::geometry::{lon_indices, range_indices, unwrap_longitudes};
+use crate::query::{Selection, SelectorInput};
+use crate::state::{AppState, AttributeValue, Dimension, Metadata, Variable};
+
+/// Attributes describing packing and missing data, dropped from aggregates
+const PACKING_ATTRIBUTES: [&str; 7] = [
+    "scale_factor",
+    "add_offset",
+    "_FillValue",
+    "missing_value",
+    "valid_min",
+    "valid_max",
+    "valid_range",
+];
+
+/// Calendar period time steps are aggregated over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// Calendar day (UTC)
+    Daily,
+    /// Calendar month
+    Monthly,
+}
+
+/// Statistic of the time steps in a period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Mean,
+    Min,
+    Max,
+    Sum,
+}
+
+/// Weighting of the time steps of an aggregated mean
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeWeights {
+    /// Lengths of the intervals of the time bounds variable
+    Bounds,
+    /// Lengths of the intervals inferred from the spacing of the steps
+    Spacing,
+    /// Every step counts the same
+    Uniform,
+}
+
+impl TimeWeights {
+    /// Parse the `time_weights` of a view: `interval` (the default) weights
+    /// by bounds or spacing, `uniform` not at all
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim() {
+            "interval" => Ok(TimeWeights::Spacing),
+            "uniform" => Ok(TimeWeights::Uniform),
+            other => Err(RossbyError::InvalidParameter {
+                param: "time_weights".to_string(),
+                message: format!(
+                    "Unknown time weighting: {}. Must be interval or uniform",
+                    other
+                ),
+            }),
+        }
+    }
+
+    /// Name recorded in the `time_weights` attribute
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeWeights::Bounds => "bounds",
+            TimeWeights::Spacing => "spacing",
+            TimeWeights::Uniform => "uniform",
+        }
+    }
+}
+
+/// Time aggregation of a view, e.g. `daily_mean`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeAggregation {
+    pub period: Period,
+    pub aggregate: Aggregate,
+}
+
+impl TimeAggregation {
+    /// Parse a `<period>_<statistic>` aggregation
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = || RossbyError::InvalidParameter {
+            param: "time_agg".to_string(),
+            message: format!(
+                "Unknown time aggregation: {}. Must be daily_ or monthly_ followed by \
+                 mean, min, max or sum",
+                value
+            ),
+        };
+        let (period, aggregate) = value.trim().split_once('_').ok_or_else(invalid)?;
+        let period = match period {
+            "daily" => Period::Daily,
+            "monthly" => Period::Monthly,
+            _ => return Err(invalid()),
+        };
+        let aggregate = match aggregate {
+            "mean" => Aggregate::Mean,
+            "min" => Aggregate::Min,
+            "max" => Aggregate::Max,
+            "sum" => Aggregate::Sum,
+            _ => return Err(invalid()),
+        };
+        Ok(Self { period, aggregate })
+    }
+
+    /// CF `cell_methods` entry of the aggregate, e.g. `time: mean`
+    fn cell_method(&self, time_dim: &str) -> String {
+        let method = match self.aggregate {
+            Aggregate::Mean => "mean",
+            Aggregate::Min => "minimum",
+            Aggregate::Max => "maximum",
+            Aggregate::Sum => "sum",
+        };
+        let interval = match self.period {
+            Period::Daily => "1 day",
+            Period::Monthly => "1 month",
+        };
+        format!("{}: {} (interval: {})", time_dim, method, interval)
+    }
+
+    /// Start of the period containing a date and time
+    fn period_start(&self, datetime: NaiveDateTime) -> NaiveDateTime {
+        let date = datetime.date();
+        let start = match self.period {
+            Period::Daily => date,
+            Period::Monthly => NaiveDate::from_ymd_opt(date.year(), date.month(), 1)
+                .expect("the first of a month is a valid date"),
+        };
+        start
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+    }
+}
+
+/// The views of a dataset, each built into a dataset of its own
+#[derive(Debug, Clone, Default)]
+pub struct ViewStore {
+    views: HashMap<String, Arc<AppState>>,
+}
+
+impl ViewStore {
+    /// Store without views
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the views configured for a dataset
+    pub fn build(state: &AppState) -> Result<Self> {
+        let mut views = HashMap::new();
+        for (name, view) in &state.config.data.views {
+            let built = build_view(state, name, view).map_err(|e| RossbyError::Config {
+                message: format!("Failed to build view '{}': {}", name, e),
+            })?;
+            info!(
+                view = %name,
+                var = %view.var,
+                shape = ?built.metadata.variables[name].shape,
+                "Built view"
+            );
+            views.insert(name.clone(), Arc::new(built));
+        }
+        Ok(Self { views })
+    }
+
+    /// The dataset serving a view
+    pub fn get(&self, name: &str) -> Option<&Arc<AppState>> {
+        self.views.get(name)
+    }
+
+    /// Number of views
+    pub fn len(&self) -> usize {
+        self.views.len()
+    }
+
+    /// Whether there are no views
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    /// Views by name
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Arc<AppState>)> {
+        self.views.iter()
+    }
+}
+
+/// The dataset serving a request for the given variables
+///
+/// Requests naming a view are served from the view's dataset, and may not
+/// name any other variable; all others are served from `state`.
+pub fn state_for_variables<'a>(
+    state: &Arc<AppState>,
+    param: &str,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Result<Arc<AppState>> {
+    let names: Vec<&str> = names.into_iter().collect();
+    let Some(view) = names.iter().find(|name| state.views.get(name).is_some()) else {
+        return Ok(state.clone());
+    };
+    if let Some(other) = names.iter().find(|name| *name != view) {
+        return Err(RossbyError::InvalidParameter {
+            param: param.to_string(),
+            message: format!(
+                "View '{}' cannot be requested together with '{}'",
+                view, other
+            ),
+        });
+    }
+    Ok(state.views.get(view).cloned().expect("the view exists"))
+}
+
+/// Build the dataset of one view
+fn build_view(state: &AppState, name: &str, view: &ViewConfig) -> Result<AppState> {
+    if state.metadata.variables.contains_key(name) {
+        return Err(RossbyError::Config {
+            message: "the name is already a variable of the dataset".to_string(),
+        });
+    }
+    let var = state.get_variable_metadata_checked(&view.var)?;
+    let (lat_axis, lon_axis) = find_lat_lon_axes(&var.dimensions).ok_or_else(|| {
+        RossbyError::VariableNotSuitableForImage {
+            name: view.var.clone(),
+        }
+    })?;
+
+    // Indices kept along every dimension: the bounding box on the horizontal
+    // ones, the selectors on the others
+    let params: HashMap<String, SelectorInput> = view
+        .select
+        .iter()
+        .filter_map(|(param, value)| Some((param.clone(), SelectorInput::from_json(value)?)))
+        .collect();
+    let selection = Selection::from_inputs(state, &params)?;
+    if let Some(selected) = selection
+        .iter()
+        .find(|s| !var.dimensions.contains(&s.dimension))
+    {
+        return Err(RossbyError::InvalidParameter {
+            param: selected.param.clone(),
+            message: format!(
+                "Dimension '{}' is not a dimension of '{}'",
+                selected.dimension, view.var
+            ),
+        });
+    }
+    let bbox = view.bbox.as_deref().map(parse_bbox).transpose()?;
+    let mut indices = Vec::with_capacity(var.dimensions.len());
+    for (axis, dim) in var.dimensions.iter().enumerate() {
+        let coords = state.get_coordinate(dim);
+        let kept: Vec<usize> = if axis == lat_axis || axis == lon_axis {
+            if let Some(selected) = selection.get(dim) {
+                return Err(RossbyError::InvalidParameter {
+                    param: selected.param.clone(),
+                    message: "Horizontal dimensions cannot be selected; use bbox instead"
+                        .to_string(),
+                });
+            }
+            let (min, max) = match bbox {
+                Some((min_lon, _, max_lon, _)) if axis == lon_axis => (min_lon, max_lon),
+                Some((_, min_lat, _, max_lat)) => (min_lat, max_lat),
+                None => (f32::NEG_INFINITY, f32::INFINITY),
+            };
+            let coords = coords.ok_or_else(|| RossbyError::DataNotFound {
+                message: format!("Dimension {} has no coordinates", dim),
+            })?;
+            let coords = &coords[..var.shape[axis]];
+            if axis == lon_axis {
+                lon_indices(coords, min as f64, max as f64)
+            } else {
+                range_indices(coords, min as f64, max as f64)
+            }
+        } else {
+            match selection.get(dim) {
+                Some(selected) => selected.resolve(state)?,
+                None => (0..var.shape[axis]).collect(),
+            }
+        };
+        if kept.is_empty() {
+            return Err(RossbyError::InvalidParameter {
+                param: "bbox".to_string(),
+                message: format!("The view selects no values of dimension {}", dim),
+            });
+        }
+        indices.push(kept);
+    }
+
+    let mut values = state.get_variable_checked(&view.var)?.into_owned();
+    for (axis, kept) in indices.iter().enumerate() {
+        if !kept.iter().copied().eq(0..values.len_of(Axis(axis))) {
+            values = values.select(Axis(axis), kept);
+        }
+    }
+    // Longitudes of a bbox crossing the seam are made continuous across it
+    let mut coordinates: Vec<Option<Vec<f64>>> = var
+        .dimensions
+        .iter()
+        .zip(&indices)
+        .enumerate()
+        .map(|(axis, (dim, kept))| {
+            let coords = state.get_coordinate(dim)?;
+            let kept: Vec<f64> = kept.iter().map(|&i| coords[i]).collect();
+            Some(if axis == lon_axis {
+                unwrap_longitudes(&kept)
+            } else {
+                kept
+            })
+        })
+        .collect();
+
+    // Aggregate the time steps by calendar period
+    let mut attributes = var.attributes.clone();
+    if let Some(time_agg) = &view.time_agg {
+        let time_agg = TimeAggregation::parse(time_agg)?;
+        let time_dim = state.resolve_dimension("time")?;
+        let axis = var
+            .dimensions
+            .iter()
+            .position(|dim| dim == time_dim)
+            .ok_or_else(|| RossbyError::InvalidParameter {
+                param: "time_agg".to_string(),
+                message: format!("Variable '{}' has no time dimension", view.var),
+            })?;
+        let units = time_units(state, time_dim).ok_or_else(|| RossbyError::InvalidParameter {
+            param: "time_agg".to_string(),
+            message: format!("Time dimension {} has no decodable CF units", time_dim),
+        })?;
+        let times = coordinates[axis].take().unwrap_or_default();
+        let weighting = view
+            .time_weights
+            .as_deref()
+            .map(TimeWeights::parse)
+            .transpose()?
+            .unwrap_or(TimeWeights::Spacing);
+        let (weights, weighting) = time_weights(state, time_dim, &indices[axis], &times, weighting);
+        let (starts, groups) = group_by_period(&times, &units, &time_agg)?;
+        values = aggregate(
+            &unpack(var, values),
+            axis,
+            &groups,
+            time_agg.aggregate,
+            &weights,
+        )?;
+        coordinates[axis] = Some(starts);
+
+        for name in PACKING_ATTRIBUTES {
+            attributes.remove(name);
+        }
+        let method = time_agg.cell_method(time_dim);
+        let cell_methods = match attributes.get("cell_methods") {
+            Some(AttributeValue::Text(existing)) => format!("{} {}", existing, method),
+            _ => method,
+        };
+        attributes.insert(
+            "cell_methods".to_string(),
+            AttributeValue::Text(cell_methods),
+        );
+        if time_agg.aggregate == Aggregate::Mean {
+            attributes.insert(
+                "time_weights".to_string(),
+                AttributeValue::Text(weighting.as_str().to_string()),
+            );
+        }
+    }
+    attributes.insert(
+        "view_of".to_string(),
+        AttributeValue::Text(view.var.clone()),
+    );
+    if let Some(bbox) = &view.bbox {
+        attributes.insert("view_bbox".to_string(), AttributeValue::Text(bbox.clone()));
+    }
+
+    // The view's dataset: the view variable and the coordinate variables of
+    // its dimensions, under their original names
+    let mut metadata = Metadata {
+        global_attributes: state.metadata.global_attributes.clone(),
+        dimensions: HashMap::new(),
+        variables: HashMap::new(),
+        coordinates: HashMap::new(),
+        groups: HashMap::new(),
+    };
+    let mut data = HashMap::new();
+    for ((dim, coords), &size) in var.dimensions.iter().zip(coordinates).zip(values.shape()) {
+        let is_unlimited = state
+            .metadata
+            .dimensions
+            .get(dim)
+            .is_some_and(|d| d.is_unlimited);
+        metadata.dimensions.insert(
+            dim.clone(),
+            Dimension {
+                name: dim.clone(),
+                size,
+                is_unlimited,
+            },
+        );
+        let Some(coords) = coords else {
+            continue;
+        };
+        if let Some(coord_var) = state.get_variable_metadata(dim) {
+            metadata.variables.insert(
+                dim.clone(),
+                Variable {
+                    shape: vec![size],
+                    ..coord_var.clone()
+                },
+            );
+            let values = coords.iter().map(|&c| c as f32).collect::<Vec<_>>();
+            data.insert(dim.clone(), ArrayD::from_shape_vec(IxDyn(&[size]), values)?);
+        }
+        metadata.coordinates.insert(dim.clone(), coords);
+    }
+    metadata.variables.insert(
+        name.to_string(),
+        Variable {
+            name: name.to_string(),
+            dimensions: var.dimensions.clone(),
+            shape: values.shape().to_vec(),
+            attributes,
+            dtype: if view.time_agg.is_some() {
+                "f32".to_string()
+            } else {
+                var.dtype.clone()
+            },
+        },
+    );
+    data.insert(name.to_string(), values);
+
+    let mut config = state.config.clone();
+    config.data.views.clear();
+    let mut view_state = AppState::new(config, metadata, data);
+    view_state.crs = state.crs.clone();
+    view_state.validate()?;
+    Ok(view_state)
+}
+
+/// Group time steps by calendar period
+///
+/// Returns the start of every period, encoded in the time units, and the
+/// positions of the steps falling into each, in time order.
+fn group_by_period(
+    times: &[f64],
+    units: &TimeUnits,
+    time_agg: &TimeAggregation,
+) -> Result<(Vec<f64>, Vec<Vec<usize>>)> {
+    let mut periods: BTreeMap<NaiveDateTime, Vec<usize>> = BTreeMap::new();
+    for (position, &time) in times.iter().enumerate() {
+        let datetime = units
+            .datetime(time)
+            .ok_or_else(|| RossbyError::Conversion {
+                message: format!("Cannot decode time value {}", time),
+            })?;
+        periods
+            .entry(time_agg.period_start(datetime))
+            .or_default()
+            .push(position);
+    }
+    let starts = periods
+        .keys()
+        .map(|start| {
+            let seconds = (*start - units.reference).num_milliseconds() as f64 / 1_000.0;
+            seconds / units.seconds_per_unit
+        })
+        .collect();
+    Ok((starts, periods.into_values().collect()))
+}
+
+/// Weight of every selected time step and the weighting they come from
+///
+/// `Spacing` takes the time bounds variable when there is one with an
+/// interval for every step, and falls back on the spacing of `times`.
+fn time_weights(
+    state: &AppState,
+    time_dim: &str,
+    kept: &[usize],
+    times: &[f64],
+    weighting: TimeWeights,
+) -> (Vec<f64>, TimeWeights) {
+    if weighting == TimeWeights::Uniform {
+        return (vec![1.0; times.len()], weighting);
+    }
+    if let Some(weights) = bounds_weights(state, time_dim, kept) {
+        return (weights, TimeWeights::Bounds);
+    }
+    (spacing_weights(times), TimeWeights::Spacing)
+}
+
+/// Lengths of the intervals of the time bounds variable at the kept steps
+fn bounds_weights(state: &AppState, time_dim: &str, kept: &[usize]) -> Option<Vec<f64>> {
+    let bounds_var = match state
+        .get_variable_metadata(time_dim)?
+        .attributes
+        .get("bounds")?
+    {
+        AttributeValue::Text(name) => name,
+        _ => return None,
+    };
+    let bounds = state.get_variable_checked(bounds_var).ok()?;
+    let meta = state.get_variable_metadata(bounds_var)?;
+    if meta.dimensions.first().map(String::as_str) != Some(time_dim) || bounds.shape().len() != 2 {
+        return None;
+    }
+    let last = bounds.shape()[1].checked_sub(1)?;
+    let weights: Vec<f64> = kept
+        .iter()
+        .map(|&step| {
+            let start = *bounds.get([step, 0])? as f64;
+            let end = *bounds.get([step, last])? as f64;
+            Some((end - start).abs())
+        })
+        .collect::<Option<_>>()?;
+    weights
+        .iter()
+        .all(|w| w.is_finite() && *w > 0.0)
+        .then_some(weights)
+}
+
+/// Lengths of the intervals reaching halfway to the neighbouring steps, the
+/// first and last steps reaching as far on their open side
+fn spacing_weights(times: &[f64]) -> Vec<f64> {
+    let n = times.len();
+    if n < 2 {
+        return vec![1.0; n];
+    }
+    (0..n)
+        .map(|i| {
+            let before = if i > 0 {
+                times[i] - times[i - 1]
+            } else {
+                times[1] - times[0]
+            };
+            let after = if i + 1 < n {
+                times[i + 1] - times[i]
+            } else {
+                times[n - 1] - times[n - 2]
+            };
+            ((before + after) / 2.0).abs()
+        })
+        .collect()
+}
+
+/// Aggregate groups of slices along an axis, ignoring NaN
+///
+/// Means weight each step by its entry in `weights`. Cells without a valid
+/// value in a group are NaN.
+fn aggregate(
+    values: &Array<f32, IxDyn>,
+    axis: usize,
+    groups: &[Vec<usize>],
+    aggregate: Aggregate,
+    weights: &[f64],
+) -> Result<Array<f32, IxDyn>> {
+    let mut shape = values.shape().to_vec();
+    shape.remove(axis);
+    let initial = match aggregate {
+        Aggregate::Mean | Aggregate::Sum => 0.0,
+        Aggregate::Min => f64::INFINITY,
+        Aggregate::Max => f64::NEG_INFINITY,
+    };
+
+    let mut periods = Vec::with_capacity(groups.len());
+    for steps in groups {
+        let mut accumulated = Array::<f64, IxDyn>::from_elem(IxDyn(&shape), initial);
+        let mut totals = Array::<f64, IxDyn>::zeros(IxDyn(&shape));
+        for &step in steps {
+            let weight = weights[step];
+            Zip::from(&mut accumulated)
+                .and(&mut totals)
+                .and(values.index_axis(Axis(axis), step))
+                .for_each(|accumulated, total, &value| {
+                    if value.is_nan() {
+                        return;
+                    }
+                    *total += weight;
+                    let value = value as f64;
+                    *accumulated = match aggregate {
+                        Aggregate::Mean => *accumulated + value * weight,
+                        Aggregate::Sum => *accumulated + value,
+                        Aggregate::Min => accumulated.min(value),
+                        Aggregate::Max => accumulated.max(value),
+                    };
+                });
+        }
+        let period = Zip::from(&accumulated)
+            .and(&totals)
+            .map_collect(|&accumulated, &total| match aggregate {
+                _ if total == 0.0 => f32::NAN,
+                Aggregate::Mean => (accumulated / total) as f32,
+                _ => accumulated as f32,
+            });
+        periods.push(period);
+    }
+
+    let views: Vec<_> = periods.iter().map(|period| period.view()).collect();
+    Ok(stack(Axis(axis), &views)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// Four 12-hourly steps of a (time, lat, lon) variable on a 2x3 grid
+    fn state(views: HashMap<String, ViewConfig>) -> AppState {
+        state_with_times(views, vec![0.0, 12.0, 24.0, 36.0])
+    }
+
+    /// Four steps at the given hours of a (time, lat, lon) variable
+    fn state_with_times(views: HashMap<String, ViewConfig>, times: Vec<f64>) -> AppState {
+        let sizes = [("time", 4), ("lat", 2), ("lon", 3)];
+        let dimensions = sizes
+            .iter()
+            .map(|&(name, size)| {
+                let dimension = Dimension {
+                    name: name.to_string(),
+                    size,
+                    is_unlimited: false,
+                };
+                (name.to_string(), dimension)
+            })
+            .collect();
+        let coordinates = HashMap::from([
+            ("time".to_string(), times),
+            ("lat".to_string(), vec![40.0, 50.0]),
+            ("lon".to_string(), vec![0.0, 10.0, 20.0]),
+        ]);
+        let coordinate = |name: &str, size: usize, units: &str| Variable {
+            name: name.to_string(),
+            dimensions: vec![name.to_string()],
+            shape: vec![size],
+            attributes: HashMap::from([(
+                "units".to_string(),
+                AttributeValue::Text(units.to_string()),
+            )]),
+            dtype: "f64".to_string(),
+        };
+        let mut t2m = coordinate("t2m", 0, "K");
+        t2m.dimensions = vec!["time".to_string(), "lat".to_string(), "lon".to_string()];
+        t2m.shape = vec![4, 2, 3];
+        t2m.attributes
+            .insert("_FillValue".to_string(), AttributeValue::Number(-999.0));
+        let variables = HashMap::from([
+            (
+                "time".to_string(),
+                coordinate("time", 4, "hours since 2000-01-01"),
+            ),
+            ("lat".to_string(), coordinate("lat", 2, "degrees_north")),
+            ("lon".to_string(), coordinate("lon", 3, "degrees_east")),
+            ("t2m".to_string(), t2m),
+        ]);
+        let mut values = Array::from_shape_fn(IxDyn(&[4, 2, 3]), |i| {
+            (i[0] * 100 + i[1] * 10 + i[2]) as f32
+        });
+        values[[1, 0, 1]] = -999.0;
+
+        let mut config = Config::default();
+        config.data.views = views;
+        AppState::new(
+            config,
+            Metadata {
+                global_attributes: HashMap::new(),
+                dimensions,
+                variables,
+                coordinates,
+                groups: HashMap::new(),
+            },
+            HashMap::from([("t2m".to_string(), values)]),
+        )
+    }
+
+    fn view(bbox: Option<&str>, time_agg: Option<&str>) -> ViewConfig {
+        ViewConfig {
+            var: "t2m".to_string(),
+            bbox: bbox.map(str::to_string),
+            time_agg: time_agg.map(str::to_string),
+            time_weights: None,
+            select: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_daily_mean_view() {
+        let views = HashMap::from([(
+            "t2m_east_daily".to_string(),
+            view(Some("5,0,25,90"), Some("daily_mean")),
+        )]);
+        let state = state(views);
+        let store = ViewStore::build(&state).unwrap();
+        let view = store.get("t2m_east_daily").unwrap();
+
+        let var = &view.metadata.variables["t2m_east_daily"];
+        assert_eq!(var.shape, vec![2, 2, 2]);
+        assert!(matches!(
+            var.attributes.get("cell_methods"),
+            Some(AttributeValue::Text(methods)) if methods == "time: mean (interval: 1 day)"
+        ));
+        assert!(!var.attributes.contains_key("_FillValue"));
+        assert_eq!(view.get_coordinate("time").unwrap(), &vec![0.0, 24.0]);
+        assert_eq!(view.get_coordinate("lon").unwrap(), &vec![10.0, 20.0]);
+
+        let values = &view.data["t2m_east_daily"];
+        // Mean of steps 0 and 1 at lat 40, lon 20
+        assert_eq!(values[[0, 0, 1]], 52.0);
+        // The fill value at step 1 is left out
+        assert_eq!(values[[0, 0, 0]], 1.0);
+        assert_eq!(values[[1, 1, 1]], 262.0);
+    }
+
+    #[test]
+    fn test_time_weighted_mean() {
+        assert_eq!(
+            spacing_weights(&[0.0, 6.0, 18.0, 36.0]),
+            vec![6.0, 9.0, 15.0, 18.0]
+        );
+        assert_eq!(spacing_weights(&[5.0]), vec![1.0]);
+
+        // Steps at 0h, 6h and 18h make up the first day
+        let daily = |time_weights: Option<&str>| {
+            let mut view = view(Some("5,0,25,90"), Some("daily_mean"));
+            view.time_weights = time_weights.map(str::to_string);
+            HashMap::from([("t2m_daily".to_string(), view)])
+        };
+        let times = vec![0.0, 6.0, 18.0, 36.0];
+        let state = state_with_times(daily(None), times.clone());
+        let store = ViewStore::build(&state).unwrap();
+        let view = store.get("t2m_daily").unwrap();
+        // (2 * 6 + 102 * 9 + 202 * 15) / 30 at lat 40, lon 20
+        assert_eq!(view.data["t2m_daily"][[0, 0, 1]], 132.0);
+        assert!(matches!(
+            view.metadata.variables["t2m_daily"].attributes.get("time_weights"),
+            Some(AttributeValue::Text(weights)) if weights == "spacing"
+        ));
+
+        let state = state_with_times(daily(Some("uniform")), times.clone());
+        let store = ViewStore::build(&state).unwrap();
+        assert_eq!(
+            store.get("t2m_daily").unwrap().data["t2m_daily"][[0, 0, 1]],
+            102.0
+        );
+
+        // Intervals of a time bounds variable take precedence
+        let mut state = state_with_times(daily(None), times);
+        let time = state.metadata.variables.get_mut("time").unwrap();
+        time.attributes.insert(
+            "bounds".to_string(),
+            AttributeValue::Text("time_bnds".to_string()),
+        );
+        let mut time_bnds = state.metadata.variables["t2m"].clone();
+        time_bnds.name = "time_bnds".to_string();
+        time_bnds.dimensions = vec!["time".to_string(), "nv".to_string()];
+        time_bnds.shape = vec![4, 2];
+        time_bnds.attributes.clear();
+        state
+            .metadata
+            .variables
+            .insert("time_bnds".to_string(), time_bnds);
+        let bounds = vec![0.0, 1.0, 1.0, 2.0, 2.0, 4.0, 30.0, 42.0];
+        state.data.insert(
+            "time_bnds".to_string(),
+            Array::from_shape_vec(IxDyn(&[4, 2]), bounds).unwrap(),
+        );
+        let store = ViewStore::build(&state).unwrap();
+        let view = store.get("t2m_daily").unwrap();
+        // (2 * 1 + 102 * 1 + 202 * 2) / 4
+        assert_eq!(view.data["t2m_daily"][[0, 0, 1]], 127.0);
+        assert!(matches!(
+            view.metadata.variables["t2m_daily"].attributes.get("time_weights"),
+            Some(AttributeValue::Text(weights)) if weights == "bounds"
+        ));
+
+        assert!(TimeWeights::parse("linear").is_err());
+    }
+
+    #[test]
+    fn test_views_without_aggregation() {
+        let views = HashMap::from([
+            ("t2m_north".to_string(), view(Some("-180,45,180,90"), None)),
+            ("t2m_daily_max".to_string(), view(None, Some("daily_max"))),
+        ]);
+        let state = Arc::new(state(views));
+        let mut state_with_views = (*state).clone();
+        state_with_views.views = ViewStore::build(&state).unwrap();
+        let state = Arc::new(state_with_views);
+
+        let north = state.views.get("t2m_north").unwrap();
+        assert_eq!(north.metadata.variables["t2m_north"].shape, vec![4, 1, 3]);
+        assert_eq!(north.data["t2m_north"][[1, 0, 2]], 112.0);
+        let max = state.views.get("t2m_daily_max").unwrap();
+        assert_eq!(max.data["t2m_daily_max"][[0, 0, 1]], 1.0);
+
+        // Requests are served from the view's dataset
+        let served = state_for_variables(&state, "vars", ["t2m_north"]).unwrap();
+        assert!(served.has_variable("t2m_north"));
+        let served = state_for_variables(&state, "vars", ["t2m"]).unwrap();
+        assert!(Arc::ptr_eq(&served, &state));
+        assert!(state_for_variables(&state, "vars", ["t2m_north", "t2m"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_views() {
+        assert!(TimeAggregation::parse("hourly_mean").is_err());
+        assert!(TimeAggregation::parse("daily_median").is_err());
+        assert_eq!(
+            TimeAggregation::parse("monthly_sum").unwrap(),
+            TimeAggregation {
+                period: Period::Monthly,
+                aggregate: Aggregate::Sum,
+            }
+        );
+
+        let clash = HashMap::from([("t2m".to_string(), view(None, None))]);
+        assert!(ViewStore::build(&state(clash)).is_err());
+        let empty = HashMap::from([("empty".to_string(), view(Some("100,0,120,90"), None))]);
+        assert!(ViewStore::build(&state(empty)).is_err());
+    }
+}
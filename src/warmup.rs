@@ -0,0 +1,112 @@
+//! Warm-up requests run at startup.
+//!
+//! Several responses are expensive the first time they are served: the
+//! statistics fixing the color range of an `/image` slice, climatological
+//! normals, thumbnails and pressure fields are computed on first use and
+//! cached with the dataset. Operators list representative requests in
+//! `server.warmup.requests`, e.g. the world maps of key variables at the
+//! latest time step, and they are sent through the router right after the
+//! data is loaded, several at once, so the first users after a restart find
+//! the caches primed.
+//!
+//! Warm-up runs in the background while the server accepts connections.
+//! Requests go through the same middleware as those of clients, so products
+//! and `latest` are expanded, and each outcome is logged; failures are
+//! reported but never stop the server.
+
+use axum::body::{to_bytes, Body};
+use axum::http::Request;
+use axum::Router;
+use futures::stream::{self, StreamExt};
+use std::time::Instant;
+use tower::ServiceExt;
+use tracing::{info, warn};
+
+use crate::config::WarmupConfig;
+
+/// Outcome of the warm-up requests
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WarmupSummary {
+    /// Requests answered with a success status
+    pub succeeded: usize,
+    /// Requests that failed or were answered with an error status
+    pub failed: usize,
+    /// Time until every request was answered
+    pub duration_ms: f64,
+}
+
+/// Send the configured warm-up requests through a router
+///
+/// Up to `concurrency` requests are served at once, each on a task of its
+/// own so they spread over the worker threads. Response bodies are read to
+/// the end, as streamed responses only do their work when consumed.
+pub async fn warm_up(router: Router, config: WarmupConfig) -> WarmupSummary {
+    let started = Instant::now();
+    let mut results = stream::iter(config.requests)
+        .map(|path| tokio::spawn(warm_up_request(router.clone(), path)))
+        .buffer_unordered(config.concurrency.max(1));
+
+    let mut summary = WarmupSummary::default();
+    while let Some(result) = results.next().await {
+        match result {
+            Ok(true) => summary.succeeded += 1,
+            Ok(false) => summary.failed += 1,
+            Err(error) => {
+                warn!(error = %error, "Warm-up request panicked");
+                summary.failed += 1;
+            }
+        }
+    }
+    summary.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    info!(
+        succeeded = summary.succeeded,
+        failed = summary.failed,
+        duration_ms = summary.duration_ms,
+        "Warm-up complete"
+    );
+    summary
+}
+
+/// Send one warm-up request, returning whether it succeeded
+async fn warm_up_request(router: Router, path: String) -> bool {
+    let started = Instant::now();
+    let request = match Request::get(&path).body(Body::empty()) {
+        Ok(request) => request,
+        Err(error) => {
+            warn!(path = %path, error = %error, "Invalid warm-up request");
+            return false;
+        }
+    };
+    let response = match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(error) => match error {},
+    };
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await;
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match body {
+        Ok(body) if status.is_success() => {
+            info!(
+                path = %path,
+                status = status.as_u16(),
+                bytes = body.len(),
+                duration_ms = duration_ms,
+                "Warm-up request served"
+            );
+            true
+        }
+        Ok(_) => {
+            warn!(
+                path = %path,
+                status = status.as_u16(),
+                duration_ms = duration_ms,
+                "Warm-up request failed"
+            );
+            false
+        }
+        Err(error) => {
+            warn!(path = %path, error = %error, "Warm-up response could not be read");
+            false
+        }
+    }
+}
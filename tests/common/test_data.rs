@@ -285,6 +285,7 @@ pub fn create_test_weather_nc(path: &Path) -> Result<()> {
     let lon_size = 36; // 10 degree resolution
     let lat_size = 18; // 10 degree resolution
     let time_steps = 5; // 5 time steps
+    let depth_values: Vec<f32> = vec![0.0, 50.0, 200.0, 1000.0]; // Ocean levels in meters
 
     // Create a new NetCDF file
     let mut file = netcdf::create(path)?;
@@ -293,6 +294,7 @@ pub fn create_test_weather_nc(path: &Path) -> Result<()> {
     let _lon_dim = file.add_dimension("lon", lon_size)?;
     let _lat_dim = file.add_dimension("lat", lat_size)?;
     let _time_dim = file.add_unlimited_dimension("time")?;
+    let _depth_dim = file.add_dimension("depth", depth_values.len())?;
 
     // Add file attributes
     file.add_attribute("title", "Rossby Test Weather Data")?;
@@ -442,6 +444,37 @@ pub fn create_test_weather_nc(path: &Path) -> Result<()> {
         humidity_var.put_values(&humidity_data, &[.., .., ..])?;
     }
 
+    // Add and configure the depth variable
+    {
+        let mut depth_var = file.add_variable::<f32>("depth", &["depth"])?;
+        depth_var.put_attribute("units", "m")?;
+        depth_var.put_attribute("long_name", "Depth")?;
+        depth_var.put_attribute("positive", "down")?;
+        depth_var.put_values(&depth_values, [..])?;
+    }
+
+    // Add an ocean temperature that cools with depth and warms over time
+    {
+        let mut ocean_data = Vec::with_capacity(total_size * depth_values.len());
+        for t in 0..time_steps {
+            for depth in &depth_values {
+                for lat in &lat_values {
+                    for _ in 0..lon_size {
+                        let surface = 28.0 * (1.0 - (lat / 90.0).abs());
+                        ocean_data.push(surface * (-depth / 500.0).exp() + 2.0 + t as f32 * 0.1);
+                    }
+                }
+            }
+        }
+
+        let mut ocean_var =
+            file.add_variable::<f32>("ocean_temperature", &["time", "depth", "lat", "lon"])?;
+        ocean_var.put_attribute("units", "degC")?;
+        ocean_var.put_attribute("long_name", "Sea Water Temperature")?;
+        ocean_var.put_attribute("standard_name", "sea_water_temperature")?;
+        ocean_var.put_values(&ocean_data, [.., .., .., ..])?;
+    }
+
     Ok(())
 }
 
@@ -5,6 +5,7 @@
 mod common;
 
 use common::{http_client, image_utils, test_data};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Once;
 
@@ -16,8 +17,8 @@ static TEST_ADDR: OnceCell<SocketAddr> = OnceCell::new();
 static TEST_TEMP_DIR: OnceCell<tempfile::TempDir> = OnceCell::new();
 static TEST_FILE_PATH: OnceCell<String> = OnceCell::new();
 
-/// Start a test server on a specified port
-async fn start_test_server() -> SocketAddr {
+/// Start a test server on a specified port with the given configuration
+async fn start_test_server(mut config: rossby::Config) -> SocketAddr {
     // Initialize test data and get temp directory
     let _temp_dir = TEST_TEMP_DIR.get_or_init(|| {
         let dir = tempfile::tempdir().unwrap();
@@ -48,50 +49,46 @@ async fn start_test_server() -> SocketAddr {
 
     // Start the server
     tokio::spawn(async move {
-        // Create a minimal config
-        let config = rossby::Config {
-            server: rossby::config::ServerConfig {
-                host: "127.0.0.1".to_string(),
-                port: bound_addr.port(),
-                workers: Some(1),
-                discovery_url: None,
-                max_data_points: 10_000_000, // Default 10 million points
-            },
-            ..Default::default()
-        };
+        // Serve on the bound address with a single worker
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = bound_addr.port();
+        config.server.workers = Some(1);
+        // At most 10 million points, unless a test lowers the limit
+        config.server.max_data_points = config.server.max_data_points.min(10_000_000);
 
         // Load the test NetCDF file
-        let app_state =
+        let mut app_state =
             rossby::data_loader::load_netcdf(std::path::Path::new(file_path), config.clone())
                 .expect("Failed to load test NetCDF file");
+        app_state.signer = rossby::signing::ResponseSigner::from_config(&config.server)
+            .expect("Failed to load signing key");
 
         let state = std::sync::Arc::new(app_state);
 
-        // Create the router
-        let app = axum::Router::new()
-            .route(
-                "/metadata",
-                axum::routing::get(rossby::handlers::metadata_handler),
-            )
-            .route(
-                "/point",
-                axum::routing::get(rossby::handlers::point_handler),
-            )
-            .route(
-                "/image",
-                axum::routing::get(rossby::handlers::image_handler),
-            )
-            .route(
-                "/heartbeat",
-                axum::routing::get(rossby::handlers::heartbeat_handler),
-            )
-            .route("/data", axum::routing::get(rossby::handlers::data_handler))
-            .layer(tower_http::cors::CorsLayer::permissive())
-            .with_state(state);
+        // Create the router, with the further datasets under their own paths
+        let mut app = build_test_router(state.clone());
+        for (name, dataset) in &config.datasets {
+            let mut dataset_state = rossby::datasets::load_dataset(&config, name, dataset)
+                .expect("Failed to load dataset");
+            dataset_state.signer = state.signer.clone();
+            let access = std::sync::Arc::new(rossby::datasets::DatasetAccess::new(name, dataset));
+            let router = build_test_router(std::sync::Arc::new(dataset_state)).layer(
+                axum::middleware::from_fn_with_state(
+                    access,
+                    rossby::datasets::dataset_access_middleware,
+                ),
+            );
+            app = app.nest(&rossby::datasets::mount_path(name), router);
+        }
 
         println!("Test server started on {}", bound_addr);
 
-        axum::serve(listener, app).await.expect("Server error");
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .expect("Server error");
     });
 
     // The server will take some time to start up fully
@@ -100,10 +97,174 @@ async fn start_test_server() -> SocketAddr {
     bound_addr
 }
 
+/// Build the router of a dataset state, as the server does
+fn build_test_router(state: std::sync::Arc<rossby::state::AppState>) -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/metadata",
+            axum::routing::get(rossby::handlers::metadata_handler),
+        )
+        .route(
+            "/metadata/variables",
+            axum::routing::get(rossby::handlers::variables_handler),
+        )
+        .route(
+            "/metadata/variables/:name",
+            axum::routing::get(rossby::handlers::variable_handler),
+        )
+        .route(
+            "/point",
+            axum::routing::get(rossby::handlers::point_handler),
+        )
+        .route(
+            "/debug/interpolate",
+            axum::routing::get(rossby::handlers::interpolate_debug_handler),
+        )
+        .route(
+            "/image",
+            axum::routing::get(rossby::handlers::image_handler),
+        )
+        .route(
+            "/heartbeat",
+            axum::routing::get(rossby::handlers::heartbeat_handler),
+        )
+        .route(
+            "/healthz",
+            axum::routing::get(rossby::handlers::healthz_handler),
+        )
+        .route("/data", axum::routing::get(rossby::handlers::data_handler))
+        .route(
+            "/stats",
+            axum::routing::get(rossby::handlers::stats_handler),
+        )
+        .route("/diff", axum::routing::get(rossby::handlers::diff_handler))
+        .route("/mask", axum::routing::get(rossby::handlers::mask_handler))
+        .route(
+            "/exceedance",
+            axum::routing::get(rossby::handlers::exceedance_handler),
+        )
+        .route(
+            "/correlate",
+            axum::routing::get(rossby::handlers::correlate_handler),
+        )
+        .route(
+            "/lagcorr",
+            axum::routing::get(rossby::handlers::lagcorr_handler),
+        )
+        .route(
+            "/profile_series",
+            axum::routing::get(rossby::handlers::profile_series_handler),
+        )
+        .route(
+            "/chart",
+            axum::routing::get(rossby::handlers::chart_handler),
+        )
+        .route(
+            "/decompose",
+            axum::routing::get(rossby::handlers::decompose_handler),
+        )
+        .route(
+            "/cellstats",
+            axum::routing::get(rossby::handlers::cellstats_handler),
+        )
+        .route(
+            "/sample",
+            axum::routing::get(rossby::handlers::sample_handler),
+        )
+        .route(
+            "/export",
+            axum::routing::get(rossby::handlers::export_handler),
+        )
+        .route(
+            "/thumbnail",
+            axum::routing::get(rossby::handlers::thumbnail_handler),
+        )
+        .route(
+            "/panel",
+            axum::routing::get(rossby::handlers::panel_handler),
+        )
+        .route("/wms", axum::routing::get(rossby::handlers::wms_handler))
+        .route(
+            "/usage",
+            axum::routing::get(rossby::handlers::usage_handler),
+        )
+        .route(
+            "/signing_key",
+            axum::routing::get(rossby::handlers::signing_key_handler),
+        )
+        .route(
+            "/admin/caches/flush",
+            axum::routing::post(rossby::handlers::flush_caches_handler),
+        )
+        .route(
+            "/admin/state",
+            axum::routing::get(rossby::handlers::state_snapshot_handler),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::lazy::read_failure_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::single_flight::single_flight_middleware,
+        ))
+        .layer(axum::middleware::from_fn(rossby::schema::schema_middleware))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::endpoint_switches::endpoint_switch_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::products::product_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::quota::quota_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::generation::generation_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::profiling::profile_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::body_limit::body_limit_middleware,
+        ))
+        .layer(axum::middleware::from_fn(rossby::schema::schema_middleware))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::signing::signing_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rossby::access_log::access_log_middleware,
+        ))
+        .layer(rossby::body_limit::handler_body_limit(
+            &state.config.server.body_limits,
+        ))
+        .layer(tower_http::cors::CorsLayer::permissive())
+        .with_state(state)
+}
+
 /// Initialize a new test server for each test
 async fn init_test_environment() -> SocketAddr {
+    init_test_environment_with_peers(HashMap::new()).await
+}
+
+/// Initialize a new test server with federation peers
+async fn init_test_environment_with_peers(peers: HashMap<String, String>) -> SocketAddr {
+    let mut config = rossby::Config::default();
+    config.server.peers = peers;
+    init_test_environment_with_config(config).await
+}
+
+/// Initialize a new test server with the given configuration
+async fn init_test_environment_with_config(config: rossby::Config) -> SocketAddr {
     // Always start a new server for each test
-    let server_addr = start_test_server().await;
+    let server_addr = start_test_server(config).await;
 
     println!(
         "Test server started, waiting for it to be ready at {}",
@@ -180,6 +341,177 @@ async fn test_metadata_endpoint() {
     assert!(variables.get("humidity").is_some());
 }
 
+#[tokio::test]
+async fn test_metadata_variables() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(&addr, "/metadata/variables?per_page=1&search=wind")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["total"], 2);
+    assert_eq!(json["pages"], 2);
+    assert_eq!(json["variables"].as_array().unwrap().len(), 1);
+    assert_eq!(json["variables"][0]["name"], "u_wind");
+    assert_eq!(json["variables"][0]["standard_name"], "eastward_wind");
+    assert!(json["variables"][0].get("attributes").is_none());
+
+    let response = http_client::get(&addr, "/metadata/variables/temperature")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["name"], "temperature");
+    assert!(json["variable"]["attributes"].is_object());
+    assert!(json["coordinates"]["lat"].is_array());
+
+    let response = http_client::get(&addr, "/metadata/variables/missing")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn test_schema_version() {
+    let addr = init_test_environment().await;
+
+    // Keys are sorted at every level, with the schema version among them
+    let response = http_client::get(&addr, "/metadata")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("x-rossby-schema-version").unwrap(),
+        "1"
+    );
+    let body = response.text().await.expect("Failed to read body");
+    let json: serde_json::Value = serde_json::from_str(&body).expect("Failed to parse JSON");
+    assert_eq!(json["schema_version"], 1);
+    assert_eq!(serde_json::to_string(&json).unwrap(), body);
+    let categories = body.find("\"categories\"").unwrap();
+    let variables = body.find("\"variables\"").unwrap();
+    assert!(categories < variables);
+
+    // Streamed data is written in the same order, and the version may be
+    // requested on any endpoint
+    let body = http_client::get(
+        &addr,
+        "/data?vars=temperature,humidity&time_index=0&format=json&schema_version=1",
+    )
+    .await
+    .expect("Failed to make request")
+    .text()
+    .await
+    .expect("Failed to read body");
+    let json: serde_json::Value = serde_json::from_str(&body).expect("Failed to parse JSON");
+    assert_eq!(json["schema_version"], 1);
+    let humidity = body.find("\"humidity\": [").unwrap();
+    let temperature = body.find("\"temperature\": [").unwrap();
+    let metadata = body.find("\"metadata\"").unwrap();
+    assert!(humidity < temperature && temperature < metadata);
+
+    // Versions the server cannot produce are rejected
+    let response = http_client::get(&addr, "/metadata?schema_version=2")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["supported_schema_versions"], serde_json::json!([1]));
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/metadata", addr))
+        .header("X-Rossby-Schema-Version", "3")
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_crs_metadata() {
+    let addr = init_test_environment().await;
+
+    // The test file has no grid mapping, so WGS 84 is assumed
+    let json: serde_json::Value = http_client::get(&addr, "/metadata")
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["crs"]["identifier"], "EPSG:4326");
+    assert_eq!(json["crs"]["source"], "assumed");
+
+    let response = http_client::get(&addr, "/image?var=temperature&width=64&height=32")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("x-rossby-crs").unwrap(), "EPSG:4326");
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&width=64&height=32&projection=mercator",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.headers().get("x-rossby-crs").unwrap(), "EPSG:3857");
+
+    // A configured CRS takes precedence
+    let mut config = rossby::Config::default();
+    config.data.crs = Some("EPSG:4258".to_string());
+    let addr = init_test_environment_with_config(config).await;
+    let json: serde_json::Value = http_client::get(&addr, "/metadata")
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(json["crs"]["identifier"], "EPSG:4258");
+    assert_eq!(json["crs"]["source"], "config");
+}
+
+#[tokio::test]
+async fn test_polar_images() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(&addr, "/image?var=temperature&projection=arctic&width=128")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("x-rossby-crs").unwrap(), "EPSG:3995");
+    let bytes = response.bytes().await.expect("Failed to read body");
+    let img = image::load_from_memory(&bytes)
+        .expect("Failed to load image from memory")
+        .to_rgba8();
+    // A square disk around the pole, transparent outside the cutoff
+    assert_eq!(img.dimensions(), (128, 128));
+    assert_eq!(img.get_pixel(64, 64)[3], 255);
+    assert_eq!(img.get_pixel(0, 0)[3], 0);
+    assert_eq!(img.get_pixel(127, 127)[3], 0);
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&projection=antarctic&lat_cutoff=-45&width=96&height=64",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("x-rossby-crs").unwrap(), "EPSG:3031");
+    let bytes = response.bytes().await.expect("Failed to read body");
+    let img = image::load_from_memory(&bytes).expect("Failed to load image from memory");
+    assert_eq!((img.width(), img.height()), (96, 64));
+
+    for path in [
+        "/image?var=temperature&projection=arctic&bbox=0,60,90,90",
+        "/image?var=temperature&projection=arctic&grid=true",
+        "/image?var=temperature&projection=arctic&lat_cutoff=90",
+        "/image?var=temperature&projection=gnomonic",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", path);
+    }
+}
+
 #[tokio::test]
 async fn test_point_endpoint() {
     // Initialize test environment
@@ -244,6 +576,42 @@ async fn test_point_endpoint() {
         .as_str()
         .unwrap()
         .contains("outside the range"));
+
+    // Times off by float formatting noise still select their time step
+    let response = http_client::get(
+        &addr,
+        "/point?lon=190.0&lat=10.0&time=2.0000001&vars=temperature",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    // Times between steps are rejected, listing the nearest valid times
+    let response = http_client::get(&addr, "/point?lon=190.0&lat=10.0&time=2.5&vars=temperature")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    let message = json["error"].as_str().unwrap();
+    assert!(
+        message.contains("Nearest available values: [") && message.contains("2.0, 3.0"),
+        "{}",
+        message
+    );
+}
+
+#[tokio::test]
+async fn test_healthz_endpoint() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(&addr, "/healthz")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["self_check"]["enabled"], false);
+    assert!(json["self_check"]["last"].is_null());
 }
 
 #[tokio::test]
@@ -344,6 +712,117 @@ async fn test_image_endpoint() {
     assert_eq!(response.status(), 400);
 }
 
+#[tokio::test]
+async fn test_image_geotiff() {
+    use tiff::decoder::Decoder;
+    use tiff::tags::Tag;
+
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&format=geotiff",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/tiff");
+    let bytes = response.bytes().await.unwrap();
+    let mut decoder = Decoder::new(std::io::Cursor::new(bytes.to_vec())).unwrap();
+    assert_eq!(decoder.dimensions().unwrap(), (100, 80));
+    let keys = decoder.get_tag_u16_vec(Tag::GeoKeyDirectoryTag).unwrap();
+    assert!(keys.chunks(4).any(|key| key == [2048, 0, 1, 4326]));
+
+    // The centers of the corner pixels lie on the corner grid points
+    let source = netcdf::open(TEST_FILE_PATH.get().unwrap()).unwrap();
+    let coords = |name: &str| {
+        source
+            .variable(name)
+            .unwrap()
+            .get_values::<f64, _>(..)
+            .unwrap()
+    };
+    let (lons, lats) = (coords("lon"), coords("lat"));
+    let (x, dx, y, dy) = match decoder.find_tag(Tag::ModelTiepointTag).unwrap() {
+        Some(_) => {
+            let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag).unwrap();
+            let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).unwrap();
+            (tiepoint[3], scale[0], tiepoint[4], -scale[1])
+        }
+        None => {
+            let matrix = decoder
+                .get_tag_f64_vec(Tag::ModelTransformationTag)
+                .unwrap();
+            (matrix[3], matrix[0], matrix[7], matrix[5])
+        }
+    };
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-4;
+    assert!(close(x + dx / 2.0, lons[0]));
+    assert!(close(x + dx * 99.5, lons[lons.len() - 1]));
+    assert!(close(y + dy / 2.0, lats[0]));
+    assert!(close(y + dy * 79.5, lats[lats.len() - 1]));
+
+    // Projections without an affine transform cannot be written
+    for path in [
+        "/image?var=temperature&time_index=0&format=geotiff&projection=equalarea",
+        "/image?var=temperature&time_index=0&format=geotiff&projection=arctic",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_smoothing() {
+    let addr = init_test_environment().await;
+    let get_json = move |path: &'static str| async move {
+        http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request")
+            .json::<serde_json::Value>()
+            .await
+            .expect("Failed to parse JSON")
+    };
+
+    // A boxcar averages every cell with its neighbours
+    let native = get_json("/data?vars=temperature&time_index=0&format=json").await;
+    let smoothed =
+        get_json("/data?vars=temperature&time_index=0&format=json&smooth=boxcar:3").await;
+    let n_lon = native["metadata"]["shapes"][0][1].as_u64().unwrap() as usize;
+    let value = |json: &serde_json::Value, row: usize, col: usize| {
+        json["data"]["temperature"][row * n_lon + col]
+            .as_f64()
+            .unwrap()
+    };
+    let mean = (0..3)
+        .flat_map(|row| (0..3).map(move |col| (row, col)))
+        .map(|(row, col)| value(&native, row, col))
+        .sum::<f64>()
+        / 9.0;
+    assert!((value(&smoothed, 1, 1) - mean).abs() < 1e-3);
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&smooth=gaussian:2",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    for path in [
+        "/data?vars=temperature&time_index=0&smooth=boxcar:2",
+        "/data?vars=temperature&time_index=0&smooth=median:3",
+        "/image?var=temperature&time_index=0&smooth=gaussian:0",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", path);
+    }
+}
+
 #[tokio::test]
 async fn test_data_endpoint() {
     // Initialize test environment
@@ -407,6 +886,52 @@ async fn test_data_endpoint() {
 
     assert_eq!(response.status(), 200);
 
+    // The layout reorders the axes of the returned data
+    let native: serde_json::Value =
+        http_client::get(&addr, "/data?vars=temperature&time_index=0&format=json")
+            .await
+            .expect("Failed to make request")
+            .json()
+            .await
+            .expect("Failed to parse JSON");
+    let reordered: serde_json::Value = http_client::get(
+        &addr,
+        "/data?vars=temperature&time_index=0&format=json&layout=longitude,latitude",
+    )
+    .await
+    .expect("Failed to make request")
+    .json()
+    .await
+    .expect("Failed to parse JSON");
+    assert_eq!(
+        native["metadata"]["dimensions"],
+        serde_json::json!(["lat", "lon"])
+    );
+    assert_eq!(
+        reordered["metadata"]["dimensions"],
+        serde_json::json!(["lon", "lat"])
+    );
+    let native_shape = native["metadata"]["shapes"][0].as_array().unwrap();
+    let reordered_shape = reordered["metadata"]["shapes"][0].as_array().unwrap();
+    assert_eq!(native_shape[0], reordered_shape[1]);
+    assert_eq!(native_shape[1], reordered_shape[0]);
+    let n_lon = native_shape[1].as_u64().unwrap() as usize;
+    let n_lat = native_shape[0].as_u64().unwrap() as usize;
+    // Element (lat=1, lon=2) moves to (lon=2, lat=1)
+    assert_eq!(
+        native["data"]["temperature"][n_lon + 2],
+        reordered["data"]["temperature"][2 * n_lat + 1]
+    );
+
+    // A layout leaving out a dimension of the output is rejected
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature&time_index=0&format=json&layout=latitude",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+
     // Test basic query with JSON format
     let response = http_client::get(&addr, "/data?vars=temperature&time_index=0&format=json")
         .await
@@ -525,6 +1050,33 @@ async fn test_data_endpoint() {
 
     assert_eq!(status, 400, "Expected 400 status for nonexistent variable");
 
+    // Test error case - unknown limit policy
+    let response = http_client::get(&addr, "/data?vars=temperature&on_limit=truncate")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+
+    // Test error case - raised point limit without a scoped API key
+    let response = http_client::get(&addr, "/data?vars=temperature&max_points=5e7")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+
+    // Test error case - misspelled dimension selector
+    let response = http_client::get(&addr, "/data?vars=temperature&lat_rnage=10,30")
+        .await
+        .expect("Failed to make request");
+
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    let error = body["error"].as_str().unwrap();
+    assert!(error.contains("lat_rnage"), "Unexpected error: {}", error);
+    assert!(
+        error.contains("Did you mean 'lat_range'?"),
+        "Unexpected error: {}",
+        error
+    );
+
     // Test error case - missing required parameter
     let response = http_client::get(&addr, "/data")
         .await
@@ -596,6 +1148,56 @@ async fn test_image_geography_features() {
     .expect("Failed to make request");
     assert_eq!(response.status(), 200); // Should succeed with wrap_longitude
 
+    // Test pole enhancement and equal-area scaling
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&enhance_poles=true",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&projection=equalarea",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&projection=mercator",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&projection=gnomonic",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+
+    // Test graticule overlays
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&grid=true&grid_spacing=10&grid_color=ff000080",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&width=100&height=80&grid=true&grid_color=red",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+
     // Test upsampling/downsampling
     let response = http_client::get(
         &addr,
@@ -612,3 +1214,3413 @@ async fn test_image_geography_features() {
     let img = image::load_from_memory(&bytes).expect("Failed to load image from memory");
     assert!(image_utils::assert_image_dimensions(&img, 800, 600).is_ok());
 }
+
+#[tokio::test]
+async fn test_stats_and_diff_with_peer() {
+    // The peer serves the same file, so the comparison must be exact
+    let peer_addr = init_test_environment().await;
+    let mut peers = HashMap::new();
+    peers.insert("mirror".to_string(), format!("http://{}", peer_addr));
+    let addr = init_test_environment_with_peers(peers).await;
+
+    // Local statistics only
+    let response = http_client::get(&addr, "/stats?var=temperature&time=2")
+        .await
+        .expect("Failed to get stats");
+    assert_eq!(response.status().as_u16(), 200);
+    let json: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json["selection"]["time"]["index"], 2);
+    assert_eq!(json["stats"]["count"], 18 * 36);
+    assert!(json.get("peer").is_none());
+
+    // Statistics compared against the peer
+    let response = http_client::get(&addr, "/stats?var=temperature&time=2&peer=mirror")
+        .await
+        .expect("Failed to get stats");
+    assert_eq!(response.status().as_u16(), 200);
+    let json: serde_json::Value = response.json().await.unwrap();
+    let comparison = &json["peer"]["comparison"];
+    assert_eq!(comparison["count"], 18 * 36);
+    assert!(comparison["rmse"].as_f64().unwrap().abs() < 1e-6);
+
+    // Difference field over a bounding box
+    let response = http_client::get(
+        &addr,
+        "/diff?var=temperature&__time_index=1&peer=mirror&bbox=0,-20,50,20",
+    )
+    .await
+    .expect("Failed to get diff");
+    assert_eq!(response.status().as_u16(), 200);
+    let json: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json["shape"], serde_json::json!([5, 6]));
+    for row in json["values"].as_array().unwrap() {
+        for value in row.as_array().unwrap() {
+            assert!(value.as_f64().unwrap().abs() < 1e-6);
+        }
+    }
+
+    // Categorical agreement of the same field: every cell agrees
+    let response = http_client::get(
+        &addr,
+        "/diff?var=temperature&__time_index=1&peer=mirror&bbox=0,-20,50,20&mode=categorical",
+    )
+    .await
+    .expect("Failed to get diff");
+    assert_eq!(response.status().as_u16(), 200);
+    let json: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json["mode"], "categorical");
+    assert_eq!(json["count"], 30);
+    assert_eq!(json["agreeing"], 30);
+    assert_eq!(json["agreement_rate"], 1.0);
+    assert_eq!(json["transitions"], serde_json::json!([]));
+    assert!(json["agreement"][0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|cell| cell == true));
+
+    let response = http_client::get(
+        &addr,
+        "/diff?var=temperature&peer=mirror&bbox=0,-20,50,20&mode=categorical&encoding=png",
+    )
+    .await
+    .expect("Failed to get diff");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.headers()["x-rossby-transitions"], "");
+    let bytes = response.bytes().await.unwrap();
+    let img = image::load_from_memory(&bytes).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (6, 5));
+    assert!(img.pixels().all(|p| p.0 == [210, 210, 210, 255]));
+
+    // PNG output is only available for categorical diffs
+    let response = http_client::get(&addr, "/diff?var=temperature&peer=mirror&encoding=png")
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    // Diff requires a peer, and unknown peers are rejected
+    let response = http_client::get(&addr, "/diff?var=temperature")
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let response = http_client::get(&addr, "/diff?var=temperature&peer=unknown")
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    // An unreachable peer is reported as a gateway error
+    let mut peers = HashMap::new();
+    peers.insert("down".to_string(), "http://127.0.0.1:9".to_string());
+    let addr = init_test_environment_with_peers(peers).await;
+    let response = http_client::get(&addr, "/diff?var=temperature&peer=down")
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 502);
+}
+
+#[tokio::test]
+async fn test_uncertainty_companions() {
+    let mut config = rossby::Config::default();
+    config
+        .data
+        .uncertainty
+        .insert("temperature".to_string(), "humidity".to_string());
+    let addr = init_test_environment_with_config(config).await;
+
+    let metadata: serde_json::Value = http_client::get_json(&addr, "/metadata")
+        .await
+        .expect("Failed to get metadata");
+    assert_eq!(metadata["uncertainty"]["temperature"], "humidity");
+
+    // The companion is returned next to its variable on request
+    let point = "/point?lon=15&lat=0&vars=temperature,humidity&interpolation=nearest";
+    let json: serde_json::Value = http_client::get_json(&addr, point)
+        .await
+        .expect("Failed to query point");
+    assert!(json.get("uncertainty").is_none());
+    let json: serde_json::Value =
+        http_client::get_json(&addr, &format!("{}&include_uncertainty=true", point))
+            .await
+            .expect("Failed to query point");
+    assert_eq!(json["uncertainty"]["temperature"]["variable"], "humidity");
+    assert_eq!(
+        json["uncertainty"]["temperature"]["value"],
+        json["humidity"]
+    );
+    assert!(json["uncertainty"].get("humidity").is_none());
+
+    // Uncertain pixels are faded or hatched
+    let image = |query: &str| {
+        let path = format!("/image?var=temperature&width=72&height=36{}", query);
+        async move {
+            let response = http_client::get(&addr, &path)
+                .await
+                .expect("Failed to make request");
+            assert_eq!(response.status(), 200, "{}", path);
+            let bytes = response.bytes().await.expect("Failed to read body");
+            image::load_from_memory(&bytes)
+                .expect("Failed to load image from memory")
+                .to_rgba8()
+        }
+    };
+    let plain = image("").await;
+    let faded = image("&uncertainty=alpha").await;
+    let hatched = image("&uncertainty=hatch&uncertainty_max=1").await;
+    assert!(plain.pixels().all(|p| p[3] == 255));
+    assert!(faded.pixels().any(|p| p[3] < 255));
+    assert!(faded
+        .pixels()
+        .zip(plain.pixels())
+        .all(|(f, p)| f[0] == p[0] && f[1] == p[1] && f[2] == p[2]));
+    assert_ne!(hatched, plain);
+    assert!(hatched.pixels().all(|p| p[3] == 255));
+
+    for path in [
+        "/image?var=pressure&uncertainty=alpha",
+        "/image?var=temperature&uncertainty=blur",
+        "/image?var=temperature&uncertainty=alpha&uncertainty_max=0",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_usage_quotas() {
+    // Any traffic exhausts the anonymous allowance, including the readiness probe
+    let mut config = rossby::Config::default();
+    config.server.quotas = rossby::config::QuotaConfig {
+        daily_bytes: Some(1),
+        keys: HashMap::from([("big".to_string(), 1_000_000_000)]),
+        ..Default::default()
+    };
+    let addr = init_test_environment_with_config(config).await;
+
+    // Usage can be checked even when the quota is exhausted
+    let response = http_client::get(&addr, "/usage")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let usage: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(usage["client"], "ip:127.0.0.1");
+    assert_eq!(usage["limit_bytes"], 1);
+    assert_eq!(usage["remaining_bytes"], 0);
+    assert!(usage["used_bytes"].as_u64().unwrap() > 0);
+
+    let response = http_client::get(&addr, "/metadata")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert!(body["error"].as_str().unwrap().contains("Quota exceeded"));
+    assert_eq!(body["usage"]["client"], "ip:127.0.0.1");
+
+    // A key with its own allowance is accounted separately
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{}/metadata", addr))
+        .header("X-API-Key", "big")
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let served = response.bytes().await.expect("Failed to read body").len() as u64;
+
+    let usage: serde_json::Value = client
+        .get(format!("http://{}/usage", addr))
+        .header("X-API-Key", "big")
+        .send()
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(usage["client"], "key:big");
+    assert_eq!(usage["requests"], 1);
+    assert!(usage["used_bytes"].as_u64().unwrap() > served);
+    assert_eq!(usage["limit_bytes"], 1_000_000_000);
+}
+
+#[tokio::test]
+async fn test_weighted_quotas() {
+    // Rendered pixels cost one byte each on top of the transferred bytes
+    let mut config = rossby::Config::default();
+    config.server.quotas = rossby::config::QuotaConfig {
+        keys: HashMap::from([("bot".to_string(), 1_000_000)]),
+        weights: HashMap::from([("/image".to_string(), 1.0)]),
+        ..Default::default()
+    };
+    let addr = init_test_environment_with_config(config).await;
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        client
+            .get(format!("http://{}{}", addr, path))
+            .header("X-API-Key", "bot")
+            .send()
+    };
+
+    let response = get("/image?var=temperature&width=100&height=100")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let usage: serde_json::Value = get("/usage")
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert!(usage["used_bytes"].as_u64().unwrap() > 10_000);
+
+    // A 4K image would exceed the remaining allowance and is rejected up front
+    let response = get("/image?var=temperature&width=3840&height=2160")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 429);
+
+    // Cheaper requests still go through
+    let response = get("/point?lon=0&lat=0&vars=temperature")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_resumable_downloads() {
+    let addr = init_test_environment().await;
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/data?vars=temperature&time_index=0", addr);
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["accept-ranges"], "bytes");
+    let checksum = response.headers()["x-content-sha256"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let full = response.bytes().await.expect("Failed to read body");
+    assert_eq!(checksum, rossby::artifact::sha256_hex(&full));
+
+    // Resume after the first 100 bytes and reassemble the artifact
+    let response = client
+        .get(&url)
+        .header("Range", "bytes=100-")
+        .header("If-Range", &etag)
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 206);
+    assert_eq!(
+        response.headers()["content-range"],
+        format!("bytes 100-{}/{}", full.len() - 1, full.len()).as_str()
+    );
+    assert_eq!(response.headers()["x-content-sha256"], checksum.as_str());
+    let rest = response.bytes().await.expect("Failed to read body");
+    let mut resumed = full[..100].to_vec();
+    resumed.extend_from_slice(&rest);
+    assert_eq!(rossby::artifact::sha256_hex(&resumed), checksum);
+
+    // Rendered images are served the same way
+    let response = client
+        .get(format!(
+            "http://{}/image?var=temperature&time_index=0&width=50&height=25",
+            addr
+        ))
+        .header("Range", "bytes=-8")
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 206);
+    assert!(response.headers().contains_key("x-content-sha256"));
+    assert_eq!(response.bytes().await.unwrap().len(), 8);
+}
+
+#[tokio::test]
+async fn test_named_products() {
+    let mut config = rossby::Config::default();
+    config.data.products.insert(
+        "small_map".to_string(),
+        HashMap::from([
+            ("var".to_string(), serde_json::json!("temperature")),
+            ("width".to_string(), serde_json::json!(64)),
+            ("height".to_string(), serde_json::json!(32)),
+            ("colormap".to_string(), serde_json::json!("plasma")),
+        ]),
+    );
+    let addr = init_test_environment_with_config(config).await;
+
+    // Request parameters override the template, and time=latest picks the last step
+    let response = http_client::get(&addr, "/image?product=small_map&time=latest&height=40")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let checksum = response.headers()["x-content-sha256"].clone();
+    let bytes = response.bytes().await.expect("Failed to read body");
+    let img = image::load_from_memory(&bytes).expect("Failed to load image from memory");
+    assert!(image_utils::assert_image_dimensions(&img, 64, 40).is_ok());
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&width=64&height=40&colormap=plasma&time=4",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.headers()["x-content-sha256"], checksum);
+
+    let response = http_client::get(&addr, "/image?product=missing")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert!(body["error"].as_str().unwrap().contains("small_map"));
+}
+
+#[tokio::test]
+async fn test_iso_time_parameters() {
+    let addr = init_test_environment().await;
+    let get_text = |path: String| async move {
+        let response = http_client::get(&addr, &path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", path);
+        response.text().await.expect("Failed to read body")
+    };
+
+    // The test file counts days since 1982-01-01
+    assert_eq!(
+        get_text("/point?lon=190&lat=10&vars=temperature&time=1982-01-03T00:00:00Z".into()).await,
+        get_text("/point?lon=190&lat=10&vars=temperature&time=2".into()).await
+    );
+    assert_eq!(
+        get_text("/data?vars=temperature&time_range=1982-01-02,1982-01-04&format=json".into())
+            .await,
+        get_text("/data?vars=temperature&time_range=1,3&format=json".into()).await
+    );
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time=1982-01-02T12:00:00%2B12:00&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let checksum = response.headers()["x-content-sha256"].clone();
+    let response = http_client::get(&addr, "/image?var=temperature&time=1&width=64&height=32")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.headers()["x-content-sha256"], checksum);
+
+    // Dates between time steps are rejected like the values they stand for
+    let response = http_client::get(
+        &addr,
+        "/point?lon=190&lat=10&vars=temperature&time=1982-01-02T12:00:00Z",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_request_profiling() {
+    use tracing_subscriber::prelude::*;
+
+    // Profiles are collected by the tracing layer that setup_logging installs
+    let _ = tracing_subscriber::registry()
+        .with(rossby::profiling::ProfileLayer)
+        .try_init();
+
+    let profile_dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let mut config = rossby::Config::default();
+    config.server.profile.enabled = true;
+    config.server.profile.output_dir = Some(profile_dir.path().to_path_buf());
+    let addr = init_test_environment_with_config(config).await;
+
+    let response = http_client::get(&addr, "/image?var=temperature&width=64&height=32")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let profile_id = response.headers()["x-profile-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let folded = std::fs::read_to_string(profile_dir.path().join(format!("{}.folded", profile_id)))
+        .expect("Failed to read profile");
+    for stage in ["extract", "render", "encode"] {
+        let stack = format!("GET /image;{} ", stage);
+        assert!(folded.contains(&stack), "missing {} in {}", stage, folded);
+    }
+}
+
+#[tokio::test]
+async fn test_dataset_generation() {
+    let addr = init_test_environment().await;
+    let other_addr = init_test_environment().await;
+
+    let generation_of = |response: &reqwest::Response| -> u64 {
+        response.headers()["x-rossby-generation"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+
+    let response = http_client::get(&addr, "/heartbeat")
+        .await
+        .expect("Failed to make request");
+    let generation = generation_of(&response);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(body["dataset"]["generation"], generation);
+
+    // Every state loaded by the process gets its own generation
+    let response = http_client::get(&other_addr, "/heartbeat")
+        .await
+        .expect("Failed to make request");
+    assert_ne!(generation_of(&response), generation);
+
+    // A burst of guarded requests across endpoints is served coherently
+    let paths = [
+        "/metadata",
+        "/point?lon=0&lat=0&time=0&vars=temperature",
+        "/image?var=temperature&width=32&height=16",
+        "/data?vars=temperature&time=0",
+        "/stats?var=temperature",
+    ];
+    let requests = (0..20).map(|i| {
+        let path = paths[i % paths.len()];
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let path = format!("{}{}require_generation={}", path, separator, generation);
+        async move { http_client::get(&addr, &path).await }
+    });
+    for response in futures::future::join_all(requests).await {
+        let response = response.expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", response.url());
+        assert_eq!(generation_of(&response), generation);
+    }
+
+    // A client holding a stale generation is told to start over
+    let response = http_client::get(
+        &addr,
+        &format!("/metadata?require_generation={}", generation + 1000),
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 409);
+    assert_eq!(generation_of(&response), generation);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(body["generation"], generation);
+
+    let response = http_client::get(&addr, "/metadata?require_generation=latest")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+/// Append one time step to every time-dependent variable of a file, with
+/// the time coordinate advanced by one and the values set to 1000
+fn append_time_step(path: &std::path::Path) -> Result<(), netcdf::Error> {
+    let mut file = netcdf::append(path)?;
+    let steps = file.dimension("time").expect("file has a time axis").len();
+    let variables: Vec<(String, Vec<usize>)> = file
+        .variables()
+        .filter(|var| var.dimensions().first().map(|dim| dim.name()) == Some("time".into()))
+        .map(|var| {
+            (
+                var.name(),
+                var.dimensions().iter().map(|dim| dim.len()).collect(),
+            )
+        })
+        .collect();
+    let last_time: f32 = file.variable("time").unwrap().get_value([steps - 1])?;
+    for (name, shape) in variables {
+        let mut var = file.variable_mut(&name).unwrap();
+        if name == "time" {
+            var.put_value(last_time + 1.0, [steps])?;
+            continue;
+        }
+        let mut start = vec![0; shape.len()];
+        let mut count = shape.clone();
+        start[0] = steps;
+        count[0] = 1;
+        let values = vec![1000.0f32; count.iter().product()];
+        var.put_values(&values, (start.as_slice(), count.as_slice()))?;
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_reload() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rolling.nc");
+    test_data::create_test_weather_nc(&path).unwrap();
+    let old = std::sync::Arc::new(
+        rossby::data_loader::load_netcdf(&path, rossby::Config::default())
+            .expect("Failed to load test NetCDF file"),
+    );
+    append_time_step(&path).unwrap();
+    let appended = rossby::append::append_from_file(&old, &path)
+        .unwrap()
+        .expect("The file has a new time step");
+    assert_eq!(appended.steps, 1);
+    let new = std::sync::Arc::new(appended.state);
+    assert_ne!(old.generation, new.generation);
+
+    // Each generation answers with its own time axis and latest step
+    let get = |router: axum::Router, path: String| async move {
+        let request = axum::http::Request::get(path)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let generation: u64 = response.headers()["x-rossby-generation"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        (status, generation, body)
+    };
+    let paths = |state: &rossby::state::AppState| {
+        let latest = state.time_dim_size() - 1;
+        [
+            "/metadata".to_string(),
+            format!(
+                "/point?lon=190&lat=10&time_index={}&vars=temperature",
+                latest
+            ),
+        ]
+    };
+    let mut expected = HashMap::new();
+    for state in [&old, &new] {
+        for path in paths(state) {
+            let (status, _, body) = get(build_test_router(state.clone()), path.clone()).await;
+            assert_eq!(status, 200, "{}", path);
+            expected.insert((state.generation, path), body);
+        }
+    }
+    assert_eq!(
+        expected[&(old.generation, "/metadata".to_string())]["dimensions"]["time"]["size"],
+        5
+    );
+    assert_eq!(
+        expected[&(new.generation, "/metadata".to_string())]["dimensions"]["time"]["size"],
+        6
+    );
+
+    // The router is swapped under requests in flight, as with
+    // `append_interval_secs`
+    let current = std::sync::Arc::new(std::sync::RwLock::new(build_test_router(old.clone())));
+    let app = axum::Router::new().fallback_service(tower::service_fn({
+        let current = current.clone();
+        move |request: axum::extract::Request| {
+            let router = current.read().unwrap().clone();
+            router.oneshot(request)
+        }
+    }));
+    let spawn = |i: usize| {
+        let state = if i.is_multiple_of(2) { &old } else { &new };
+        let paths = paths(state);
+        let path = paths[i % paths.len()].clone();
+        let guarded = format!(
+            "{}{}require_generation={}",
+            path,
+            if path.contains('?') { '&' } else { '?' },
+            state.generation
+        );
+        let (generation, app) = (state.generation, app.clone());
+        tokio::spawn(async move { (generation, path, get(app, guarded).await) })
+    };
+    let mut requests: Vec<_> = (0..40).map(spawn).collect();
+    *current.write().unwrap() = build_test_router(new.clone());
+    requests.extend((40..80).map(spawn));
+
+    // Every guarded request is refused or answered by the generation it
+    // requires, never with data of the other one
+    let mut refused = 0;
+    for request in requests {
+        let (required, path, (status, generation, body)) = request.await.unwrap();
+        match status.as_u16() {
+            200 => {
+                assert_eq!(generation, required, "{}", path);
+                assert_eq!(body, expected[&(required, path.clone())], "{}", path);
+            }
+            409 => {
+                assert_ne!(generation, required, "{}", path);
+                assert_eq!(body["generation"], generation);
+                refused += 1;
+            }
+            other => panic!("{} answered {}: {}", path, other, body),
+        }
+    }
+    // Requests sent after the swap requiring the old generation are refused
+    assert!(refused >= 20);
+}
+
+#[tokio::test]
+async fn test_variable_arithmetic() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature-temperature&time_index=0&format=json",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    let values = body["data"]["temperature-temperature"].as_array().unwrap();
+    assert!(!values.is_empty());
+    assert!(values.iter().all(|v| v.as_f64() == Some(0.0)));
+    assert_eq!(
+        body["metadata"]["variables"]["temperature-temperature"]["long_name"],
+        "temperature - temperature"
+    );
+
+    // The explicit form names the same expression
+    let response = http_client::get(
+        &addr,
+        "/data?var_a=temperature&var_b=temperature&op=add&time_index=0&format=json",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert!(body["data"]["temperature+temperature"].is_array());
+
+    let response = http_client::get(
+        &addr,
+        "/image?var_a=temperature&var_b=temperature&op=sub&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature%2Bhumidity&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    for path in [
+        "/image?var=temperature-missing",
+        "/image?var_a=temperature&op=sub",
+        "/data?vars=temperature&var_a=temperature&var_b=temperature&op=pow",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_mask_endpoint() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(&addr, "/mask?var=temperature&threshold=-1000&time_index=0")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    let shape = body["shape"].as_array().unwrap();
+    let cells = shape[0].as_u64().unwrap() * shape[1].as_u64().unwrap();
+    assert_eq!(body["count"].as_u64(), Some(cells));
+    assert_eq!(body["runs"], serde_json::json!([0, cells]));
+
+    let response = http_client::get(
+        &addr,
+        "/mask?var=temperature&threshold=-1000&op=lt&encoding=png",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+    let bytes = response.bytes().await.expect("Failed to read body");
+    let img = image::load_from_memory(&bytes)
+        .expect("Failed to load image from memory")
+        .to_luma8();
+    assert!(img.pixels().all(|p| p[0] == 0));
+
+    let response = http_client::get(&addr, "/mask?var=temperature&threshold=0&op=above")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_exceedance_endpoint() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(
+        &addr,
+        "/exceedance?var=temperature&threshold=-1000&statistic=count&time_range=1,3",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(body["time"]["steps"], 3);
+    assert_eq!(body["time"]["first"], 1.0);
+    assert_eq!(body["stats"]["min"], 3.0);
+    assert_eq!(body["stats"]["max"], 3.0);
+
+    let response = http_client::get(
+        &addr,
+        "/exceedance?var=temperature&threshold=1e9&format=png&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+    let bytes = response.bytes().await.expect("Failed to read body");
+    let img = image::load_from_memory(&bytes).expect("Failed to load image from memory");
+    assert!(image_utils::assert_image_dimensions(&img, 64, 32).is_ok());
+
+    let response = http_client::get(
+        &addr,
+        "/exceedance?var=temperature&threshold=0&statistic=median",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_correlate_endpoint() {
+    let addr = init_test_environment().await;
+
+    // Both variables follow the same sine in time, so every cell correlates
+    let response = http_client::get(
+        &addr,
+        "/correlate?var_a=temperature&var_b=u_wind&bbox=0,-30,90,30",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(body["dim"]["dimension"], "time");
+    assert_eq!(body["dim"]["steps"], 5);
+    assert!(body["stats"]["min"].as_f64().unwrap() > 0.99);
+
+    let response = http_client::get(
+        &addr,
+        "/correlate?var_a=temperature&var_b=u_wind&output=mean&time_range=1,3",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(body["dim"]["steps"], 3);
+    assert!(body["value"].as_f64().unwrap() > 0.99);
+    assert!(body.get("values").is_none());
+
+    let response = http_client::get(
+        &addr,
+        "/correlate?var_a=temperature&var_b=humidity&statistic=covariance&format=png&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+
+    let response = http_client::get(&addr, "/correlate?var_a=temperature&var_b=u_wind&dim=lat")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_lagcorr_endpoint() {
+    let addr = init_test_environment().await;
+
+    // Both variables follow the same sine in time, so their means do at lag 0
+    let response = http_client::get(&addr, "/lagcorr?var_a=temperature&var_b=u_wind&lags=-1..1")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert_eq!(body["dim"]["steps"], 5);
+    assert_eq!(body["series"]["a"].as_array().unwrap().len(), 5);
+    let lags = body["lags"].as_array().unwrap();
+    assert_eq!(lags.len(), 3);
+    assert_eq!(lags[1]["lag"], 0);
+    assert_eq!(lags[1]["pairs"], 5);
+    assert_eq!(lags[0]["pairs"], 4);
+    assert!(lags[1]["correlation"].as_f64().unwrap() > 0.99);
+
+    let response = http_client::get(
+        &addr,
+        "/lagcorr?var_a=temperature&var_b=humidity&output=composite&lags=0,1&threshold=0.5",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+    assert!(!body["events"].as_array().unwrap().is_empty());
+    let composites = body["composites"].as_array().unwrap();
+    assert_eq!(composites.len(), 2);
+    assert_eq!(
+        composites[0]["values"].as_array().unwrap().len(),
+        body["lat"].as_array().unwrap().len()
+    );
+
+    let response = http_client::get(
+        &addr,
+        "/lagcorr?var_a=temperature&var_b=humidity&output=composite&format=png&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+
+    for query in [
+        "var_a=temperature&var_b=u_wind&lags=3..-3",
+        "var_a=temperature&var_b=u_wind&format=png",
+        "var_a=temperature&var_b=missing",
+    ] {
+        let response = http_client::get(&addr, &format!("/lagcorr?{}", query))
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_named_views() {
+    let mut config = rossby::Config::default();
+    config.data.views = HashMap::from([(
+        "temperature_ne_monthly".to_string(),
+        rossby::config::ViewConfig {
+            var: "temperature".to_string(),
+            bbox: Some("0,0,90,40".to_string()),
+            time_agg: Some("monthly_mean".to_string()),
+            time_weights: None,
+            select: HashMap::new(),
+        },
+    )]);
+    let addr = init_test_environment_with_config(config).await;
+
+    // The view is listed with the variables, on its own grid
+    let metadata: serde_json::Value = http_client::get(&addr, "/metadata")
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    let view = &metadata["variables"]["temperature_ne_monthly"];
+    assert_eq!(view["attributes"]["view_of"], "temperature");
+    assert_eq!(view["shape"], serde_json::json!([1, 5, 10]));
+    assert_eq!(
+        metadata["views"]["temperature_ne_monthly"]["dimensions"]["time"],
+        1
+    );
+
+    // All five daily steps fall into January 1982
+    let body: serde_json::Value = http_client::get(
+        &addr,
+        "/data?vars=temperature_ne_monthly&time_index=0&format=json",
+    )
+    .await
+    .expect("Failed to make request")
+    .json()
+    .await
+    .expect("Failed to parse JSON");
+    assert_eq!(body["metadata"]["shapes"][0], serde_json::json!([5, 10]));
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature_ne_monthly&width=40&height=20",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+
+    // A view cannot be mixed with the file's variables
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature_ne_monthly,humidity&format=json",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_body_limits() {
+    let mut config = rossby::Config::default();
+    config.server.body_limits.max_bytes = 1024;
+    config.server.body_limits.decompress = true;
+    config.server.body_limits.max_decompressed_bytes = 4096;
+    let addr = init_test_environment_with_config(config).await;
+    let client = http_client::create_test_client();
+    let url = http_client::build_url(&addr, "/heartbeat");
+
+    let response = client
+        .get(url.clone())
+        .body(vec![b'x'; 512])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let response = client
+        .get(url.clone())
+        .body(vec![b'x'; 2048])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["limit_bytes"].as_u64(), Some(1024));
+    assert!(body["request_id"].is_string());
+
+    // A small gzip body expanding past the decompressed limit
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    std::io::Write::write_all(&mut encoder, &[0; 1 << 16]).unwrap();
+    let bomb = encoder.finish().unwrap();
+    assert!(bomb.len() < 1024);
+    let response = client
+        .get(url)
+        .header("content-encoding", "gzip")
+        .body(bomb)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["limit_bytes"].as_u64(), Some(4096));
+}
+
+#[tokio::test]
+async fn test_bounds_modes() {
+    let addr = init_test_environment().await;
+    let bounds_of = |response: &reqwest::Response| {
+        response
+            .headers()
+            .get("x-rossby-bounds")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+
+    // The test grid covers longitudes 0..350 and latitudes -90..80
+    let response = http_client::get(&addr, "/point?lon=355&lat=0&vars=temperature")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let response = http_client::get(&addr, "/point?lon=-10&lat=0&vars=temperature&bounds=wrap")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(bounds_of(&response).as_deref(), Some("wrap"));
+    let wrapped: serde_json::Value = response.json().await.unwrap();
+    let direct: serde_json::Value =
+        http_client::get_json(&addr, "/point?lon=350&lat=0&vars=temperature")
+            .await
+            .unwrap();
+    assert_eq!(wrapped, direct);
+
+    let response = http_client::get(&addr, "/point?lon=0&lat=85&vars=temperature&bounds=clamp")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(bounds_of(&response).as_deref(), Some("clamp"));
+
+    let global = "/image?var=temperature&width=100&height=80&bbox=0,-90,360,90";
+    let response = http_client::get(&addr, global).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let response = http_client::get(&addr, &format!("{}&bounds=clamp", global))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(bounds_of(&response).as_deref(), Some("clamp"));
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&width=100&height=80&bbox=-20,-30,20,30&bounds=wrap",
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(bounds_of(&response).as_deref(), Some("wrap"));
+}
+
+#[tokio::test]
+async fn test_derived_dynamics() {
+    let addr = init_test_environment().await;
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature,gradient_x(temperature)&time_index=0&format=json",
+    )
+    .await
+    .expect("Failed to get derived data");
+    let values = body["data"]["temperature"].as_array().unwrap();
+    let gradient = body["data"]["gradient_x(temperature)"].as_array().unwrap();
+    assert_eq!(values.len(), gradient.len());
+    assert_eq!(
+        body["metadata"]["variables"]["gradient_x(temperature)"]["units"],
+        "K m-1"
+    );
+
+    // A single latitude still uses its neighbours for the derivative
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=vorticity(temperature,humidity)&time_index=0&lat=0&format=json",
+    )
+    .await
+    .expect("Failed to get vorticity");
+    let vorticity = body["data"]["vorticity(temperature,humidity)"]
+        .as_array()
+        .unwrap();
+    assert_eq!(vorticity.len(), 36);
+    assert!(vorticity.iter().all(|v| v.is_number()));
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=divergence(temperature,humidity)&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+
+    for path in [
+        "/image?var=vorticity(temperature)",
+        "/data?vars=gradient_y(missing)",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_pregenerate_tiles() {
+    // Starting a server creates the shared test file
+    let _addr = init_test_environment().await;
+    let file_path = TEST_FILE_PATH.get().expect("Test file path not set");
+    let state = rossby::data_loader::load_netcdf(
+        std::path::Path::new(file_path),
+        rossby::Config::default(),
+    )
+    .expect("Failed to load test NetCDF file");
+
+    let out = tempfile::tempdir().unwrap();
+    let options = rossby::tiles::TileOptions {
+        vars: vec!["temperature".to_string()],
+        zooms: 0..=1,
+        out: out.path().to_path_buf(),
+        colormap: None,
+        time_index: 0,
+        tile_size: 64,
+    };
+    let summary = rossby::tiles::pregenerate(std::sync::Arc::new(state), &options)
+        .await
+        .expect("Failed to pregenerate tiles");
+    assert_eq!(summary.written, 5);
+    assert_eq!(summary.skipped, 0);
+
+    let tile = image::open(out.path().join("temperature/0/0/0.png"))
+        .expect("Missing root tile")
+        .to_rgba8();
+    assert_eq!(tile.dimensions(), (64, 64));
+    // The grid ends at 80N, short of the top of the Mercator map
+    assert_eq!(tile.get_pixel(16, 0)[3], 0);
+    assert_eq!(tile.get_pixel(16, 32)[3], 255);
+    assert_eq!(tile.get_pixel(48, 32)[3], 255);
+    assert!(out.path().join("temperature/1/1/1.png").exists());
+
+    let options = rossby::tiles::TileOptions {
+        vars: vec!["missing".to_string()],
+        ..options
+    };
+    let state = rossby::data_loader::load_netcdf(
+        std::path::Path::new(file_path),
+        rossby::Config::default(),
+    )
+    .unwrap();
+    assert!(
+        rossby::tiles::pregenerate(std::sync::Arc::new(state), &options)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_image_classes() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&width=64&height=32&classes=jenks:5",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let breaks: Vec<f64> = response
+        .headers()
+        .get("x-rossby-class-breaks")
+        .expect("Missing class breaks header")
+        .to_str()
+        .unwrap()
+        .split(',')
+        .map(|b| b.parse().unwrap())
+        .collect();
+    assert_eq!(breaks.len(), 6);
+    assert!(breaks.windows(2).all(|w| w[0] < w[1]));
+
+    // Breaks come from the whole slice, so a region shares them
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&width=64&height=32&classes=jenks:5&bbox=0,0,90,60",
+    )
+    .await
+    .expect("Failed to make request");
+    let header = response.headers().get("x-rossby-class-breaks").unwrap();
+    let regional: Vec<f64> = header
+        .to_str()
+        .unwrap()
+        .split(',')
+        .map(|b| b.parse().unwrap())
+        .collect();
+    assert_eq!(regional, breaks);
+
+    let response = http_client::get(&addr, "/image?var=temperature")
+        .await
+        .expect("Failed to make request");
+    assert!(response.headers().get("x-rossby-class-breaks").is_none());
+
+    for classes in ["jenks:1", "jenks:x", "kmeans:4"] {
+        let response = http_client::get(
+            &addr,
+            &format!("/image?var=temperature&classes={}", classes),
+        )
+        .await
+        .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", classes);
+    }
+}
+
+#[tokio::test]
+async fn test_image_mark_clipped() {
+    let addr = init_test_environment().await;
+
+    let clipped = |response: &reqwest::Response, header: &str| -> usize {
+        response
+            .headers()
+            .get(header)
+            .expect("Missing clipped header")
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+
+    // Temperatures span roughly 268-308 K, so both ends saturate
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&width=64&height=32&vmin=280&vmax=290&mark_clipped=true",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert!(clipped(&response, "x-rossby-clipped-below") > 0);
+    assert!(clipped(&response, "x-rossby-clipped-above") > 0);
+
+    // The default range covers the slice, so nothing is clipped
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&width=64&height=32&mark_clipped=true&clip_above_color=ff000080",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(clipped(&response, "x-rossby-clipped-below"), 0);
+    assert_eq!(clipped(&response, "x-rossby-clipped-above"), 0);
+
+    let response = http_client::get(&addr, "/image?var=temperature&vmin=280&vmax=290")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("x-rossby-clipped-below").is_none());
+
+    for query in [
+        "vmin=290&vmax=280",
+        "vmin=400",
+        "mark_clipped=true&clip_below_color=blue",
+    ] {
+        let response = http_client::get(&addr, &format!("/image?var=temperature&{}", query))
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_image_style_presets() {
+    let addr = init_test_environment().await;
+
+    let header = |response: &reqwest::Response, name: &str| -> Option<String> {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+    };
+
+    // Temperature (air_temperature in K) is drawn over a fixed range
+    let response = http_client::get(&addr, "/image?var=temperature&width=64&height=32")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        header(&response, "x-rossby-style-preset").as_deref(),
+        Some("air_temperature")
+    );
+    assert_eq!(
+        header(&response, "x-rossby-color-range").as_deref(),
+        Some("220,320")
+    );
+
+    // Precipitation starts at zero and ends at the slice maximum
+    let response = http_client::get(&addr, "/image?var=precipitation&width=64&height=32")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        header(&response, "x-rossby-style-preset").as_deref(),
+        Some("precipitation_rate")
+    );
+    assert!(header(&response, "x-rossby-color-range")
+        .unwrap()
+        .starts_with("0,"));
+
+    // Any style parameter turns the preset off
+    for query in ["colormap=viridis", "vmax=300", "classes=jenks:5"] {
+        let response = http_client::get(
+            &addr,
+            &format!("/image?var=temperature&width=64&height=32&{}", query),
+        )
+        .await
+        .expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", query);
+        assert_eq!(
+            header(&response, "x-rossby-style-preset"),
+            None,
+            "{}",
+            query
+        );
+        assert_ne!(
+            header(&response, "x-rossby-color-range").as_deref(),
+            Some("220,320"),
+            "{}",
+            query
+        );
+    }
+
+    // WMS layers offer their preset colormap as the default style
+    let response = http_client::get(&addr, "/wms?SERVICE=WMS&REQUEST=GetCapabilities")
+        .await
+        .expect("Failed to make request");
+    let capabilities = response.text().await.unwrap();
+    assert!(capabilities.contains("<Style><Name>rdylbu_r</Name>"));
+}
+
+#[tokio::test]
+async fn test_image_vertical_levels() {
+    let addr = init_test_environment().await;
+
+    let resolved_depth = |response: &reqwest::Response| -> serde_json::Value {
+        let header = response
+            .headers()
+            .get("x-rossby-query-resolved")
+            .expect("Missing query_resolved header");
+        let resolved: serde_json::Value = serde_json::from_slice(header.as_bytes()).unwrap();
+        resolved["dimensions"]["depth"].clone()
+    };
+    let color_range = |response: &reqwest::Response| -> String {
+        response.headers()["x-rossby-color-range"]
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+
+    // Ocean temperature has levels at 0, 50, 200 and 1000 m and cools with depth
+    let base = "/image?var=ocean_temperature&width=64&height=32&echo_params=true";
+    let response = http_client::get(&addr, &format!("{}&level=200", base))
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let depth = resolved_depth(&response);
+    assert_eq!(depth["param"], "level");
+    assert_eq!(depth["start"], 2);
+    assert_eq!(depth["values"], serde_json::json!([200.0, 200.0]));
+    let by_value = color_range(&response);
+
+    let response = http_client::get(&addr, &format!("{}&__level_index=2", base))
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(resolved_depth(&response)["param"], "__level_index");
+    assert_eq!(color_range(&response), by_value);
+
+    let response = http_client::get(&addr, base)
+        .await
+        .expect("Failed to make request");
+    assert_ne!(color_range(&response), by_value);
+
+    // Levels off the coordinate, indices past the last level and levels of
+    // variables without a vertical dimension are rejected
+    for (query, message) in [
+        (
+            "var=ocean_temperature&level=100",
+            "depth=100. Nearest available values: [0.0, 50.0, 200.0, 1000.0]",
+        ),
+        (
+            "var=ocean_temperature&__level_index=4",
+            "__level_index=4, max allowed is 3",
+        ),
+        (
+            "var=temperature&level=200",
+            "'temperature' has no vertical dimension",
+        ),
+    ] {
+        let response = http_client::get(&addr, &format!("/image?{}", query))
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+        let body: serde_json::Value = response.json().await.unwrap();
+        let error = body["error"].as_str().unwrap();
+        assert!(error.contains(message), "{}: {}", query, error);
+    }
+}
+
+#[tokio::test]
+async fn test_data_filter() {
+    let addr = init_test_environment().await;
+    let selection = "time_index=0&lat_range=-30,30&lon_range=0,90";
+
+    let json: serde_json::Value = http_client::get_json(
+        &addr,
+        &format!("/data?vars=humidity,temperature&{}&format=json", selection),
+    )
+    .await
+    .expect("Failed to make request");
+    let all = json["data"]["temperature"].as_array().unwrap().clone();
+    let warm = all.iter().filter(|t| t.as_f64().unwrap() >= 295.0).count();
+    assert!(warm > 0 && warm < all.len());
+
+    // Cells failing the condition are masked in every variable
+    let json: serde_json::Value = http_client::get_json(
+        &addr,
+        &format!(
+            "/data?vars=humidity,temperature&{}&format=json&filter=temperature%3E%3D295",
+            selection
+        ),
+    )
+    .await
+    .expect("Failed to make request");
+    for var in ["humidity", "temperature"] {
+        let values = json["data"][var].as_array().unwrap();
+        assert_eq!(values.len(), all.len());
+        assert_eq!(
+            values.iter().filter(|v| !v.is_null()).count(),
+            warm,
+            "{}",
+            var
+        );
+    }
+
+    // Long-format files drop the filtered rows
+    let bytes = http_client::get(
+        &addr,
+        &format!(
+            "/data?vars=humidity&{}&format=polars-ipc&filter=temperature%3E%3D295",
+            selection
+        ),
+    )
+    .await
+    .expect("Failed to make request")
+    .bytes()
+    .await
+    .unwrap();
+    let mut reader = arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(bytes), None)
+        .expect("Failed to read Arrow IPC file");
+    assert_eq!(reader.next().unwrap().unwrap().num_rows(), warm);
+
+    for filter in ["temperature", "missing==0", "lat==0", "temperature==warm"] {
+        let response = http_client::get(
+            &addr,
+            &format!("/data?vars=humidity&{}&filter={}", selection, filter),
+        )
+        .await
+        .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", filter);
+    }
+}
+
+#[tokio::test]
+async fn test_partial_responses() {
+    let addr = init_test_environment().await;
+
+    // Without partial=true one missing variable fails the whole request
+    let response = http_client::get(&addr, "/point?lon=0&lat=0&vars=temperature,t850")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/point?lon=0&lat=0&vars=temperature,t850,humidity&partial=true",
+    )
+    .await
+    .expect("Failed to get partial point response");
+    assert!(body["temperature"].is_number());
+    assert!(body["humidity"].is_number());
+    assert!(body.get("t850").is_none());
+    assert_eq!(body["errors"]["t850"]["kind"], "variable_not_found");
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature,t850&time_index=0&format=json&partial=true",
+    )
+    .await
+    .expect("Failed to get partial data response");
+    assert!(body["data"]["temperature"].is_array());
+    assert!(body["data"].get("t850").is_none());
+    assert_eq!(body["metadata"]["query"]["vars"], "temperature");
+    assert_eq!(
+        body["metadata"]["errors"]["t850"]["error"],
+        "Variable not found: t850"
+    );
+
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature,t850&time_index=0&partial=true",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    // A request none of whose variables succeeds still fails
+    for path in [
+        "/point?lon=0&lat=0&vars=t850&partial=true",
+        "/data?vars=t850&time_index=0&format=json&partial=true",
+        "/data?vars=temperature&partial=maybe",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_chart_endpoint() {
+    let addr = init_test_environment().await;
+
+    let spec: serde_json::Value = http_client::get_json(
+        &addr,
+        "/chart?type=timeseries&var=temperature&lon=15&lat=0&time_range=1,3",
+    )
+    .await
+    .expect("Failed to get timeseries chart");
+    assert_eq!(
+        spec["$schema"],
+        "https://vega.github.io/schema/vega-lite/v5.json"
+    );
+    assert_eq!(spec["mark"]["type"], "line");
+    assert_eq!(spec["encoding"]["x"]["field"], "time");
+    assert_eq!(spec["encoding"]["y"]["field"], "temperature");
+    let values = spec["data"]["values"].as_array().unwrap();
+    assert_eq!(values.len(), 3);
+    assert!(values[0]["temperature"].is_number());
+    assert_eq!(spec["usermeta"]["var"], "temperature");
+
+    let spec: serde_json::Value = http_client::get_json(
+        &addr,
+        "/chart?type=profile&var=ocean_temperature&lon=15&lat=0&time_index=2",
+    )
+    .await
+    .expect("Failed to get profile chart");
+    assert_eq!(spec["encoding"]["y"]["field"], "depth");
+    assert_eq!(spec["data"]["values"].as_array().unwrap().len(), 4);
+    assert_eq!(spec["usermeta"]["selection"]["time"]["index"], 2);
+
+    let spec: serde_json::Value = http_client::get_json(
+        &addr,
+        "/chart?type=histogram&var=temperature&bins=5&title=Spread",
+    )
+    .await
+    .expect("Failed to get histogram chart");
+    assert_eq!(spec["title"], "Spread");
+    assert_eq!(spec["mark"]["type"], "bar");
+    let bins = spec["data"]["values"].as_array().unwrap();
+    assert_eq!(bins.len(), 5);
+    let total: u64 = bins.iter().map(|b| b["count"].as_u64().unwrap()).sum();
+    assert_eq!(total, 36 * 18);
+
+    for path in [
+        "/chart?type=pie&var=temperature",
+        "/chart?type=timeseries&var=temperature&lon=15",
+        "/chart?type=profile&var=temperature&lon=15&lat=0",
+        "/chart?type=histogram&var=temperature&bins=0",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_decompose_endpoint() {
+    let addr = init_test_environment().await;
+
+    let json: serde_json::Value =
+        http_client::get_json(&addr, "/decompose?var=temperature&lon=15&lat=0&period=2")
+            .await
+            .expect("Failed to decompose series");
+    assert_eq!(json["period"], 2);
+    assert_eq!(json["time"]["dimension"], "time");
+    assert_eq!(json["time"]["values"].as_array().unwrap().len(), 5);
+    assert_eq!(json["seasonal_pattern"].as_array().unwrap().len(), 2);
+
+    let component = |name: &str| -> Vec<Option<f64>> {
+        json[name]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_f64())
+            .collect()
+    };
+    let observed = component("observed");
+    let trend = component("trend");
+    let seasonal = component("seasonal");
+    let residual = component("residual");
+    // The moving average does not reach the ends of the series
+    assert_eq!(trend[0], None);
+    assert_eq!(trend[4], None);
+    for i in 1..4 {
+        let sum = trend[i].unwrap() + seasonal[i].unwrap() + residual[i].unwrap();
+        assert!((sum - observed[i].unwrap()).abs() < 1e-3);
+    }
+
+    for path in [
+        "/decompose?var=temperature&lon=15&lat=0&period=3",
+        "/decompose?var=temperature&lon=15&lat=0&period=1",
+        "/decompose?var=temperature&lon=15&period=2",
+        "/decompose?var=missing&lon=15&lat=0&period=2",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_cellstats_endpoint() {
+    let addr = init_test_environment().await;
+
+    let json: serde_json::Value =
+        http_client::get_json(&addr, "/cellstats?var=temperature&lon=15&lat=0")
+            .await
+            .expect("Failed to get cell statistics");
+    assert_eq!(json["time"]["dimension"], "time");
+    assert_eq!(json["time"]["steps"], 5);
+    assert_eq!(json["time"]["first"], 0.0);
+    assert_eq!(json["time"]["last"], 4.0);
+    assert_eq!(json["stats"]["count"], 5);
+    let min = json["stats"]["min"].as_f64().unwrap();
+    let max = json["stats"]["max"].as_f64().unwrap();
+    let median = json["percentiles"]["50"].as_f64().unwrap();
+    assert!(min <= median && median <= max);
+    assert_eq!(json["percentiles"].as_object().unwrap().len(), 5);
+
+    // The test file covers January 1982 only
+    let json: serde_json::Value = http_client::get_json(
+        &addr,
+        "/cellstats?var=temperature&lon=15&lat=0&season=djf&percentiles=10,90",
+    )
+    .await
+    .expect("Failed to get seasonal cell statistics");
+    assert_eq!(json["season"], "djf");
+    assert_eq!(json["time"]["steps"], 5);
+    assert!(json["percentiles"]["10"].as_f64().is_some());
+
+    let json: serde_json::Value =
+        http_client::get_json(&addr, "/cellstats?var=temperature&lon=15&lat=0&season=jja")
+            .await
+            .expect("Failed to get empty cell statistics");
+    assert_eq!(json["time"]["steps"], 0);
+    assert!(json["stats"]["mean"].is_null());
+    assert!(json["percentiles"]["50"].is_null());
+
+    for path in [
+        "/cellstats?var=temperature&lon=15&lat=0&percentiles=0",
+        "/cellstats?var=temperature&lon=15&lat=0&season=summer",
+        "/cellstats?var=missing&lon=15&lat=0",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_sample_endpoint() {
+    use arrow::array::AsArray;
+    use arrow::datatypes::UInt64Type;
+
+    let addr = init_test_environment().await;
+
+    let fetch = |path: &'static str| async move {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", path);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/vnd.apache.arrow.stream"
+        );
+        response.bytes().await.unwrap()
+    };
+
+    // The same seed draws the same cells, another seed other ones
+    let bytes = fetch("/sample?var=temperature&n=50&seed=42").await;
+    assert_eq!(fetch("/sample?var=temperature&n=50&seed=42").await, bytes);
+    assert_ne!(fetch("/sample?var=temperature&n=50&seed=7").await, bytes);
+
+    let reader =
+        arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes.clone()), None)
+            .expect("Failed to read Arrow IPC stream");
+    let schema = reader.schema();
+    assert_eq!(
+        schema.metadata().get("seed").map(String::as_str),
+        Some("42")
+    );
+    assert!(schema.field_with_name("temperature").is_ok());
+    assert!(schema.field_with_name("__time_index").is_ok());
+    let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+    assert_eq!(rows, 50);
+
+    // Selections restrict the cells drawn from
+    let bytes = fetch("/sample?var=temperature&n=50&seed=42&__time_index=2").await;
+    let reader = arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes), None)
+        .expect("Failed to read Arrow IPC stream");
+    for batch in reader {
+        let batch = batch.unwrap();
+        let column = batch
+            .column_by_name("__time_index")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert!(column.values().iter().all(|&index| index == 2));
+    }
+
+    for path in [
+        "/sample?var=temperature&n=0",
+        "/sample?var=temperature&n=abc",
+        "/sample?var=missing",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_export_endpoint() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(&addr, "/export?vars=temperature&__time_index_range=1,2")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/x-netcdf");
+    let length: usize = response.headers()["content-length"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let bytes = response.bytes().await.unwrap();
+    assert_eq!(bytes.len(), length);
+
+    // The exported file holds the selected steps of the served file
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("export.nc");
+    std::fs::write(&path, &bytes).unwrap();
+    let exported = netcdf::open(&path).expect("Failed to open exported file");
+    let source = netcdf::open(TEST_FILE_PATH.get().unwrap()).unwrap();
+    let time = exported.dimension("time").unwrap();
+    assert!(time.is_unlimited());
+    assert_eq!(time.len(), 2);
+
+    let values = |file: &netcdf::File, name: &str| {
+        file.variable(name)
+            .unwrap()
+            .get_values::<f32, _>(..)
+            .unwrap()
+    };
+    let source_values = values(&source, "temperature");
+    let step = source_values.len() / source.dimension("time").unwrap().len();
+    assert_eq!(
+        values(&exported, "temperature"),
+        source_values[step..3 * step].to_vec()
+    );
+    assert_eq!(values(&exported, "lat"), values(&source, "lat"));
+    assert_eq!(
+        values(&exported, "time"),
+        values(&source, "time")[1..3].to_vec()
+    );
+
+    // CF metadata is carried over, and the export recorded
+    let text = |file: &netcdf::File, name: &str| match file.attribute(name).unwrap().value() {
+        Ok(netcdf::AttributeValue::Str(text)) => text,
+        other => panic!("{} is not text: {:?}", name, other),
+    };
+    assert_eq!(text(&exported, "Conventions"), "CF-1.8");
+    assert_eq!(text(&exported, "title"), text(&source, "title"));
+    assert!(text(&exported, "history").contains("/export?__time_index_range=1,2&vars=temperature"));
+    let temperature = exported.variable("temperature").unwrap();
+    match temperature.attribute("standard_name").unwrap().value() {
+        Ok(netcdf::AttributeValue::Str(name)) => assert_eq!(name, "air_temperature"),
+        other => panic!("standard_name is not text: {:?}", other),
+    }
+
+    // The file passes the CF checks, so strict exports succeed
+    let response = http_client::get(
+        &addr,
+        "/export?vars=temperature&__time_index_range=1,2&strict=true",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-rossby-cf-issues"], "0");
+
+    // The file carries its checksum, and resumes from any byte
+    let client = reqwest::Client::new();
+    let url = format!(
+        "http://{}/export?vars=temperature&__time_index_range=1,2",
+        addr
+    );
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.headers()["accept-ranges"], "bytes");
+    assert_eq!(
+        response.headers()["x-content-sha256"],
+        rossby::artifact::sha256_hex(&bytes).as_str()
+    );
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(response.bytes().await.unwrap(), bytes);
+    for start in [100, bytes.len() - 10] {
+        let response = client
+            .get(&url)
+            .header("Range", format!("bytes={}-", start))
+            .header("If-Range", &etag)
+            .send()
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 206);
+        assert_eq!(
+            response.headers()["content-range"],
+            format!("bytes {}-{}/{}", start, bytes.len() - 1, bytes.len()).as_str()
+        );
+        assert_eq!(response.bytes().await.unwrap(), bytes.slice(start..));
+    }
+    let response = client
+        .get(&url)
+        .header("Range", format!("bytes={}-", bytes.len()))
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 416);
+
+    // Bit-rounded exports record the bits kept
+    let response = http_client::get(
+        &addr,
+        "/export?vars=temperature&__time_index_range=1,2&keepbits=3",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let path = dir.path().join("rounded.nc");
+    std::fs::write(&path, response.bytes().await.unwrap()).unwrap();
+    let rounded = netcdf::open(&path).expect("Failed to open exported file");
+    let temperature = rounded.variable("temperature").unwrap();
+    assert!(matches!(
+        temperature.attribute("keepbits").unwrap().value(),
+        Ok(netcdf::AttributeValue::Int(3))
+    ));
+    assert!(values(&rounded, "temperature")
+        .iter()
+        .all(|v| !v.is_finite() || v.to_bits() & ((1 << 20) - 1) == 0));
+    assert_eq!(values(&rounded, "lat"), values(&source, "lat"));
+
+    for path in [
+        "/export",
+        "/export?vars=temperature&keepbits=30",
+        "/export?vars=missing",
+        "/export?vars=temperature&format=zarr",
+        "/export?vars=temperature,temperature",
+        "/export?vars=temperature&strict=yes",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+#[tokio::test]
+async fn test_echo_params() {
+    let addr = init_test_environment().await;
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/point?lon=190.0&lat=10.0&__time_index=2&vars=temperature&echo_params=true",
+    )
+    .await
+    .expect("Failed to get point response");
+    let resolved = &body["query_resolved"];
+    assert_eq!(resolved["dimensions"]["time"]["param"], "__time_index");
+    assert_eq!(resolved["dimensions"]["time"]["start"], 2);
+    assert_eq!(resolved["point"]["lon"], 190.0);
+    assert_eq!(resolved["interpolation"], "bilinear");
+
+    // Indices on both sides of the seam are listed, with the bbox they cover
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature&time_index=0&bbox=340,10,10,30&format=json&echo_params=true",
+    )
+    .await
+    .expect("Failed to get data response");
+    let resolved = &body["query_resolved"];
+    assert_eq!(resolved["dimensions"]["time"]["param"], "time_index");
+    assert_eq!(resolved["dimensions"]["lon"]["param"], "bbox");
+    assert_eq!(resolved["dimensions"]["lon"]["count"], 4);
+    assert!(resolved["dimensions"]["lon"]["step"].is_null());
+    assert_eq!(
+        resolved["dimensions"]["lon"]["values"],
+        serde_json::json!([340.0, 10.0])
+    );
+    assert_eq!(resolved["dimensions"]["lat"]["step"], 1);
+    assert_eq!(
+        resolved["bbox"],
+        serde_json::json!([340.0, 10.0, 10.0, 30.0])
+    );
+
+    // Without echo_params the section is left out
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature&time_index=0&lat_range=10,30&format=json",
+    )
+    .await
+    .expect("Failed to get data response");
+    assert!(body.get("query_resolved").is_none());
+
+    // Images carry the echo in a header
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=1&width=64&height=32&bbox=0,-30,90,30&echo_params=true",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let header = response
+        .headers()
+        .get("x-rossby-query-resolved")
+        .expect("Missing query_resolved header");
+    let resolved: serde_json::Value = serde_json::from_slice(header.as_bytes()).unwrap();
+    assert_eq!(resolved["dimensions"]["time"]["param"], "time_index");
+    assert_eq!(resolved["dimensions"]["lat"]["param"], "bbox");
+    assert_eq!(
+        resolved["bbox"],
+        serde_json::json!([0.0, -30.0, 90.0, 30.0])
+    );
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/cellstats?var=temperature&lon=15&lat=0&__time_index_range=1,3&echo_params=true",
+    )
+    .await
+    .expect("Failed to get cell statistics");
+    let resolved = &body["query_resolved"];
+    assert_eq!(
+        resolved["dimensions"]["time"]["param"],
+        "__time_index_range"
+    );
+    assert_eq!(resolved["dimensions"]["time"]["count"], 3);
+    assert_eq!(resolved["point"]["lat"], 0.0);
+
+    let response = http_client::get(
+        &addr,
+        "/stats?var=temperature&time_index=0&echo_params=maybe",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_profile_series_endpoint() {
+    let addr = init_test_environment().await;
+
+    let body: serde_json::Value =
+        http_client::get_json(&addr, "/profile_series?var=ocean_temperature&lon=15&lat=0")
+            .await
+            .expect("Failed to get profile series");
+    assert_eq!(body["time"]["dimension"], "time");
+    assert_eq!(body["vertical"]["dimension"], "depth");
+    assert_eq!(body["vertical"]["values"][3], 1000.0);
+    assert_eq!(body["shape"], serde_json::json!([5, 4]));
+    assert_eq!(body["units"], "degC");
+    let values = body["values"].as_array().unwrap();
+    assert_eq!(values.len(), 5);
+    // Colder with depth, warmer over time
+    let first = values[0].as_array().unwrap();
+    assert!(first[0].as_f64().unwrap() > first[3].as_f64().unwrap());
+    assert!(values[4][0].as_f64().unwrap() > values[0][0].as_f64().unwrap());
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/profile_series?var=ocean_temperature&lon=15&lat=0&time_range=1,3&depth=200",
+    )
+    .await
+    .expect("Failed to get profile series subset");
+    assert_eq!(body["shape"], serde_json::json!([3, 1]));
+
+    let response = http_client::get(
+        &addr,
+        "/profile_series?var=ocean_temperature&lon=15&lat=0&format=arrow",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/vnd.apache.arrow.stream"
+    );
+
+    let response = http_client::get(
+        &addr,
+        "/profile_series?var=ocean_temperature&lon=15&lat=0&format=png&width=100&height=50",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let img = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+    assert_eq!((img.width(), img.height()), (100, 50));
+
+    // Surface variables have no vertical dimension
+    for path in [
+        "/profile_series?var=temperature&lon=15&lat=0",
+        "/profile_series?var=ocean_temperature&lon=15&lat=95",
+        "/profile_series?var=ocean_temperature&lon=15&lat=0&format=csv",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_fleet_router() {
+    // Two instances of the same file, each serving part of the time axis
+    let direct = init_test_environment().await;
+    let other = init_test_environment().await;
+    let manifest: rossby::fleet::FleetManifest = serde_json::from_value(serde_json::json!({
+        "backends": [
+            {"name": "late", "url": format!("http://{}", other), "start": 2, "end": 4},
+            {"name": "early", "url": format!("http://{}", direct), "start": 0, "end": 1},
+        ]
+    }))
+    .unwrap();
+    let fleet = rossby::fleet::Fleet::new(manifest.validated().unwrap());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, rossby::fleet::router(std::sync::Arc::new(fleet)))
+            .await
+            .expect("Router error");
+    });
+
+    // Series spanning both backends are merged
+    let path = "/profile_series?var=ocean_temperature&lon=15&lat=0";
+    let expected: serde_json::Value = http_client::get_json(&direct, path).await.unwrap();
+    let response = http_client::get(&addr, path).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-rossby-backend"], "early,late");
+    let merged: serde_json::Value = response.json().await.unwrap();
+    for key in ["time", "shape", "values", "stats"] {
+        assert_eq!(merged[key], expected[key], "{}", key);
+    }
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/profile_series?var=ocean_temperature&lon=15&lat=0&time_range=1,2&depth=200",
+    )
+    .await
+    .unwrap();
+    assert_eq!(body["time"]["values"], serde_json::json!([1.0, 2.0]));
+    assert_eq!(body["shape"], serde_json::json!([2, 1]));
+
+    // Other requests are answered by the backend serving their time
+    let response = http_client::get(&addr, "/point?lon=10&lat=20&vars=temperature&time=3")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-rossby-backend"], "late");
+
+    for path in [
+        "/point?lon=10&lat=20&vars=temperature&time=0,3",
+        "/point?lon=10&lat=20&vars=temperature&__time_index=3",
+    ] {
+        let response = http_client::get(&addr, path).await.unwrap();
+        assert_eq!(response.status(), 400, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_data_json_coords() {
+    let addr = init_test_environment().await;
+
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature&time_range=1,2&lat_range=10,30&lon=30&format=json&coords=true",
+    )
+    .await
+    .expect("Failed to get data response");
+
+    // Time coordinates are decoded from "days since 1982-01-01"
+    assert_eq!(
+        body["coords"]["time"],
+        serde_json::json!(["1982-01-02T00:00:00Z", "1982-01-03T00:00:00Z"])
+    );
+    assert_eq!(body["coords"]["lat"], serde_json::json!([10.0, 20.0, 30.0]));
+    assert_eq!(body["coords"]["lon"], serde_json::json!([30.0]));
+    assert_eq!(body["metadata"]["shapes"], serde_json::json!([[2, 3]]));
+
+    // The section is only included on request
+    let body: serde_json::Value =
+        http_client::get_json(&addr, "/data?vars=temperature&time_index=0&format=json")
+            .await
+            .expect("Failed to get data response");
+    assert!(body.get("coords").is_none());
+
+    let response = http_client::get(&addr, "/data?vars=temperature&format=json&coords=yes")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_bbox_crossing_seam() {
+    let addr = init_test_environment().await;
+
+    // Both sides of the seam of the 0..360 grid, in eastward order
+    let body: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature&time_index=0&bbox=340,10,10,30&format=json&coords=true",
+    )
+    .await
+    .expect("Failed to get data response");
+    assert_eq!(
+        body["coords"]["lon"],
+        serde_json::json!([340.0, 350.0, 0.0, 10.0])
+    );
+    assert_eq!(body["coords"]["lat"], serde_json::json!([10.0, 20.0, 30.0]));
+    assert_eq!(body["metadata"]["shapes"], serde_json::json!([[3, 4]]));
+
+    // The columns match those selected one by one
+    let column: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature&time_index=0&lat=10&lon=0&format=json",
+    )
+    .await
+    .expect("Failed to get data response");
+    assert_eq!(
+        body["data"]["temperature"][2],
+        column["data"]["temperature"][0]
+    );
+
+    // /stats covers the same cells
+    let stats: serde_json::Value = http_client::get_json(
+        &addr,
+        "/stats?var=temperature&time_index=0&bbox=340,10,10,30",
+    )
+    .await
+    .expect("Failed to get stats response");
+    assert_eq!(stats["shape"], serde_json::json!([3, 4]));
+
+    // A bbox cannot be combined with a latitude or longitude selector
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature&time_index=0&bbox=340,10,10,30&lat=10&format=json",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_debug_interpolate() {
+    let addr = init_test_environment().await;
+
+    for method in ["nearest", "bilinear", "bicubic"] {
+        let stencil: serde_json::Value = http_client::get_json(
+            &addr,
+            &format!(
+                "/debug/interpolate?var=temperature&lon=203.5&lat=21.0&time_index=0&method={}",
+                method
+            ),
+        )
+        .await
+        .expect("Failed to get debug response");
+        let point: serde_json::Value = http_client::get_json(
+            &addr,
+            &format!(
+                "/point?vars=temperature&lon=203.5&lat=21.0&time_index=0&interpolation={}",
+                method
+            ),
+        )
+        .await
+        .expect("Failed to get point response");
+
+        // The value is the one /point returns, and the stencil adds up to it
+        assert_eq!(stencil["value"], point["temperature"], "{}", method);
+        assert_eq!(stencil["method"], method);
+        let points = stencil["points"].as_array().unwrap();
+        let expected = match method {
+            "nearest" => 1,
+            "bilinear" => 4,
+            _ => 16,
+        };
+        assert_eq!(points.len(), expected);
+        let total: f64 = points
+            .iter()
+            .map(|p| p["value"].as_f64().unwrap() * p["weight"].as_f64().unwrap())
+            .sum();
+        let value = stencil["value"].as_f64().unwrap();
+        assert!((total - value).abs() < 1e-3 * value.abs().max(1.0));
+        assert_eq!(stencil["selection"]["time"]["index"], 0);
+    }
+
+    let stencil: serde_json::Value = http_client::get_json(
+        &addr,
+        "/debug/interpolate?var=temperature&lon=203.5&lat=21.0",
+    )
+    .await
+    .expect("Failed to get debug response");
+    assert_eq!(stencil["method"], "bilinear");
+    assert_eq!(stencil["axes"][0]["dimension"], "lat");
+    assert_eq!(
+        stencil["axes"][0]["coordinates"],
+        serde_json::json!([20.0, 30.0])
+    );
+    assert_eq!(
+        stencil["axes"][1]["coordinates"],
+        serde_json::json!([200.0, 210.0])
+    );
+    assert_eq!(stencil["intermediate"].as_array().unwrap().len(), 2);
+
+    // Unknown methods are rejected
+    let response = http_client::get(
+        &addr,
+        "/debug/interpolate?var=temperature&lon=203.5&lat=21.0&method=spline",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_data_polars_ipc() {
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::Int32Type;
+
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature,humidity&time_range=1,2&lat_range=10,30&lon=30&format=polars-ipc",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/vnd.apache.arrow.file"
+    );
+    let bytes = response.bytes().await.unwrap();
+
+    // An IPC file rather than a stream, in long format
+    let reader = arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(bytes), None)
+        .expect("Failed to read Arrow IPC file");
+    let schema = reader.schema();
+    let mut names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(names, vec!["lat", "lon", "time", "value", "variable"]);
+
+    let batch = reader.map(|batch| batch.unwrap()).next().unwrap();
+    assert_eq!(batch.num_rows(), 2 * 2 * 3);
+
+    // Time and variable names are categorical
+    let time = batch
+        .column_by_name("time")
+        .unwrap()
+        .as_dictionary::<Int32Type>();
+    let labels = time.values().as_string::<i32>();
+    assert_eq!(
+        labels.value(time.keys().value(0) as usize),
+        "1982-01-02T00:00:00Z"
+    );
+    let variable = batch
+        .column_by_name("variable")
+        .unwrap()
+        .as_dictionary::<Int32Type>();
+    let names = variable.values().as_string::<i32>();
+    assert_eq!(
+        names.value(variable.keys().value(0) as usize),
+        "temperature"
+    );
+    assert_eq!(names.value(variable.keys().value(11) as usize), "humidity");
+    assert_eq!(batch.column_by_name("lon").unwrap().null_count(), 0);
+}
+
+#[tokio::test]
+async fn test_data_keepbits() {
+    use arrow::array::AsArray;
+    use arrow::datatypes::Float32Type;
+
+    let addr = init_test_environment().await;
+
+    let full: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature,humidity&time_index=0&lat=10&format=json",
+    )
+    .await
+    .expect("Failed to get data response");
+    let rounded: serde_json::Value = http_client::get_json(
+        &addr,
+        "/data?vars=temperature,humidity&time_index=0&lat=10&format=json&keepbits=3,humidity:10",
+    )
+    .await
+    .expect("Failed to get data response");
+
+    // Values keep 3 mantissa bits, within the error bound of the rounding
+    let values = |body: &serde_json::Value| -> Vec<f64> {
+        body["data"]["temperature"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_f64().unwrap())
+            .collect()
+    };
+    for (&exact, &value) in values(&full).iter().zip(&values(&rounded)) {
+        assert_eq!((value as f32).to_bits() & ((1 << 20) - 1), 0);
+        assert!((value - exact).abs() <= exact.abs() / 16.0);
+    }
+    let variables = &rounded["metadata"]["variables"];
+    assert_eq!(variables["temperature"]["keepbits"], 3);
+    assert_eq!(variables["humidity"]["keepbits"], 10);
+
+    // Arrow output records the bits kept in the schema
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature&time_index=0&lat=10&keepbits=3",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let bytes = response.bytes().await.unwrap();
+    let reader = arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes), None)
+        .expect("Failed to read Arrow IPC stream");
+    assert_eq!(
+        reader
+            .schema()
+            .metadata()
+            .get("keepbits")
+            .map(String::as_str),
+        Some(r#"{"temperature":3}"#)
+    );
+    for batch in reader {
+        let column = batch.unwrap();
+        let column = column
+            .column_by_name("temperature")
+            .unwrap()
+            .as_primitive::<Float32Type>();
+        assert!(column
+            .values()
+            .iter()
+            .all(|value| value.to_bits() & ((1 << 20) - 1) == 0));
+    }
+
+    for keepbits in ["24", "sst:3", "abc"] {
+        let response = http_client::get(
+            &addr,
+            &format!(
+                "/data?vars=temperature&time_index=0&lat=10&keepbits={}",
+                keepbits
+            ),
+        )
+        .await
+        .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", keepbits);
+    }
+}
+
+#[tokio::test]
+async fn test_data_compression() {
+    let addr = init_test_environment().await;
+
+    let get_bytes = |query: String| async move {
+        let response =
+            http_client::get(&addr, &format!("/data?vars=temperature,humidity{}", query))
+                .await
+                .expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", query);
+        response.bytes().await.unwrap().to_vec()
+    };
+    let read_stream = |bytes: Vec<u8>| -> Vec<arrow::record_batch::RecordBatch> {
+        arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes), None)
+            .expect("Failed to read Arrow IPC stream")
+            .collect::<Result<_, _>>()
+            .expect("Failed to read record batch")
+    };
+
+    // Compressed buffers are smaller and read back to the same table
+    let plain = get_bytes("&time_index=0".to_string()).await;
+    for compression in ["lz4", "zstd"] {
+        let compressed = get_bytes(format!("&time_index=0&compression={}", compression)).await;
+        assert!(compressed.len() < plain.len(), "{}", compression);
+        assert_eq!(
+            read_stream(compressed),
+            read_stream(plain.clone()),
+            "{}",
+            compression
+        );
+    }
+
+    // Long-format files are compressed the same way
+    let compressed =
+        get_bytes("&time_index=0&format=polars-ipc&compression=zstd".to_string()).await;
+    let reader = arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(compressed), None)
+        .expect("Failed to read Arrow IPC file");
+    assert!(reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>() > 0);
+
+    for query in ["compression=gzip", "compression=lz4&format=json"] {
+        let response = http_client::get(
+            &addr,
+            &format!("/data?vars=temperature&time_index=0&{}", query),
+        )
+        .await
+        .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_data_max_points() {
+    // A key with the max_points scope may raise the limit up to the ceiling
+    let mut config = rossby::Config::default();
+    config.server.max_data_points = 10;
+    config.server.max_points_ceiling = Some(1_000_000);
+    config.server.api_key_scopes =
+        HashMap::from([("team-a".to_string(), vec!["max_points".to_string()])]);
+    let addr = init_test_environment_with_config(config).await;
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/data?vars=temperature&max_points=1e5", addr);
+
+    let response = http_client::get(&addr, "/data?vars=temperature")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 413);
+
+    let response = client
+        .get(&url)
+        .header("X-API-Key", "team-a")
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert!(!response
+        .bytes()
+        .await
+        .expect("Failed to read body")
+        .is_empty());
+
+    // The raised limit is not shared with concurrent requests of other keys
+    let (scoped, unscoped) = tokio::join!(
+        client.get(&url).header("X-API-Key", "team-a").send(),
+        client.get(&url).header("X-API-Key", "team-b").send(),
+    );
+    assert_eq!(scoped.expect("Failed to make request").status(), 200);
+    assert_eq!(unscoped.expect("Failed to make request").status(), 400);
+
+    // Beyond the ceiling even a scoped key is refused
+    let response = client
+        .get(format!(
+            "http://{}/data?vars=temperature&max_points=5e7",
+            addr
+        ))
+        .header("X-API-Key", "team-a")
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_thumbnail_endpoint() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(&addr, "/thumbnail?var=temperature")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+    let bytes = response.bytes().await.unwrap();
+    let img = image::load_from_memory(&bytes).expect("Failed to load thumbnail");
+    assert!(image_utils::assert_image_dimensions(&img, 320, 180).is_ok());
+
+    // Repeated previews are served from the cache, unchanged
+    let again = http_client::get(&addr, "/thumbnail?var=temperature")
+        .await
+        .expect("Failed to make request")
+        .bytes()
+        .await
+        .unwrap();
+    assert_eq!(bytes, again);
+
+    // The title band differs from an untitled render of the same slice
+    let untitled = http_client::get(&addr, "/thumbnail?var=temperature&title=")
+        .await
+        .expect("Failed to make request")
+        .bytes()
+        .await
+        .unwrap();
+    assert_ne!(bytes, untitled);
+
+    let response = http_client::get(
+        &addr,
+        "/thumbnail?var=temperature&time_index=0&width=120&height=90&colormap=plasma&title=Test",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let img = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+    assert!(image_utils::assert_image_dimensions(&img, 120, 90).is_ok());
+
+    for query in [
+        "/thumbnail?var=nonexistent",
+        "/thumbnail?var=temperature&width=5000",
+    ] {
+        let response = http_client::get(&addr, query)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_panel_endpoint() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(
+        &addr,
+        "/panel?vars=temperature,humidity,pressure&time=latest&cols=2&width=120&height=90",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+    let img = image::load_from_memory(&response.bytes().await.unwrap())
+        .expect("Failed to load panel image");
+
+    // Two rows of two cells, each a panel above its legend, with 8 pixel gaps
+    let legend_height = rossby::panel::LEGEND_HEIGHT;
+    assert!(image_utils::assert_image_dimensions(
+        &img,
+        2 * (120 + 8) + 8,
+        2 * (90 + legend_height + 8) + 8
+    )
+    .is_ok());
+
+    for query in [
+        "/panel?vars=",
+        "/panel?vars=temperature,nonexistent",
+        "/panel?vars=temperature,humidity&cols=3",
+        "/panel?vars=temperature&width=5000",
+    ] {
+        let response = http_client::get(&addr, query)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_localized_image_titles() {
+    let mut config = rossby::Config::default();
+    config.data.translations.insert(
+        "de".to_string(),
+        HashMap::from([(
+            "temperature".to_string(),
+            rossby::config::VariableTranslation {
+                long_name: Some("Lufttemperatur".to_string()),
+                units: Some("Kelvin".to_string()),
+                description: None,
+            },
+        )]),
+    );
+    let addr = init_test_environment_with_config(config).await;
+    let png = |path: String| async move {
+        let response = http_client::get(&addr, &path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", path);
+        response.bytes().await.unwrap()
+    };
+
+    // Titles are drawn from the translated long name, regional tags
+    // falling back to the primary language
+    let thumbnail = |lang: &str| png(format!("/thumbnail?var=temperature{}", lang));
+    let original = thumbnail("").await;
+    let german = thumbnail("&lang=de").await;
+    assert_ne!(german, original);
+    assert_eq!(thumbnail("&lang=de-AT").await, german);
+    assert_eq!(thumbnail("&lang=fr").await, original);
+    // An explicit title is drawn as given
+    assert_eq!(
+        thumbnail("&lang=de&title=Test").await,
+        thumbnail("&title=Test").await
+    );
+
+    // Panel titles and legend units are translated
+    let panel = |lang: &str| {
+        png(format!(
+            "/panel?vars=temperature,humidity&width=120&height=90{}",
+            lang
+        ))
+    };
+    let original = panel("").await;
+    assert_ne!(panel("&lang=de").await, original);
+    assert_eq!(panel("&lang=fr").await, original);
+}
+
+#[tokio::test]
+async fn test_wms_endpoint() {
+    let addr = init_test_environment().await;
+
+    let response = http_client::get(&addr, "/wms?SERVICE=WMS&REQUEST=GetCapabilities")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/xml");
+    let capabilities = response.text().await.unwrap();
+    assert!(capabilities.contains("<WMS_Capabilities version=\"1.3.0\""));
+    assert!(capabilities.contains("<Name>temperature</Name>"));
+    assert!(capabilities.contains("<CRS>EPSG:3857</CRS>"));
+    assert!(capabilities.contains("1982-01-03T00:00:00Z"));
+
+    // The grid covers latitudes -90..80, so the top of the map stays empty
+    // unless the map is opaque
+    let get_map = "/wms?SERVICE=WMS&VERSION=1.3.0&REQUEST=GetMap&LAYERS=temperature&STYLES=\
+                   &CRS=EPSG:4326&BBOX=-90,-180,90,180&WIDTH=360&HEIGHT=180&FORMAT=image/png\
+                   &TIME=1982-01-03T00:00:00Z";
+    for (transparent, top_alpha) in [("TRUE", 0), ("FALSE", 255)] {
+        let response = http_client::get(&addr, &format!("{}&TRANSPARENT={}", get_map, transparent))
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+        let img = image::load_from_memory(&response.bytes().await.unwrap())
+            .expect("Failed to load map")
+            .to_rgba8();
+        assert_eq!(img.dimensions(), (360, 180));
+        assert_eq!(img.get_pixel(180, 2)[3], top_alpha);
+        assert_eq!(img.get_pixel(180, 90)[3], 255);
+        // West of the prime meridian is drawn from the grid at 180..350
+        assert_eq!(img.get_pixel(90, 90)[3], 255);
+    }
+
+    let response = http_client::get(
+        &addr,
+        "/wms?REQUEST=GetMap&LAYERS=humidity&STYLES=plasma&CRS=EPSG:3857\
+         &BBOX=-2000000,-2000000,2000000,2000000&WIDTH=64&HEIGHT=64&FORMAT=image/jpeg",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "image/jpeg"
+    );
+
+    for (query, code) in [
+        ("REQUEST=GetMap&LAYERS=nonexistent&CRS=CRS:84&BBOX=0,0,10,10&WIDTH=8&HEIGHT=8", "LayerNotDefined"),
+        ("REQUEST=GetMap&LAYERS=temperature&CRS=EPSG:32633&BBOX=0,0,10,10&WIDTH=8&HEIGHT=8", "InvalidCRS"),
+        ("REQUEST=GetMap&LAYERS=temperature&STYLES=rainbow&CRS=CRS:84&BBOX=0,0,10,10&WIDTH=8&HEIGHT=8", "StyleNotDefined"),
+        ("REQUEST=GetFeatureInfo", "OperationNotSupported"),
+    ] {
+        let response = http_client::get(&addr, &format!("/wms?{}", query))
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+        let report = response.text().await.unwrap();
+        assert!(report.contains(&format!("code=\"{}\"", code)), "{}", report);
+    }
+}
+
+#[tokio::test]
+async fn test_climatology_modes() {
+    let addr = init_test_environment().await;
+
+    // Normals computed from the data: z-scores of each cell sum to zero
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature&lat_range=10,30&lon=30&mode=zscore&format=json",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let json: serde_json::Value = response.json().await.unwrap();
+    let values: Vec<f64> = json["data"]["temperature"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_f64().unwrap())
+        .collect();
+    assert_eq!(values.len(), 5 * 3);
+    assert!(values.iter().sum::<f64>().abs() < 1e-3);
+    assert!(values.iter().any(|v| v.abs() > 0.5));
+    let attributes = &json["metadata"]["variables"]["temperature"];
+    assert_eq!(attributes["units"], "1");
+    assert!(attributes.get("_FillValue").is_none());
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=2&mode=percent_normal&width=64&height=32",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    // Unknown modes and modes of expressions are rejected
+    for query in [
+        "/image?var=temperature&mode=anomaly",
+        "/data?vars=temperature-humidity&mode=zscore&format=json",
+    ] {
+        let response = http_client::get(&addr, query)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_admin_cache_flush() {
+    let mut config = rossby::Config::default();
+    config.server.admin_token = Some("s3cret".to_string());
+    let addr = init_test_environment_with_config(config).await;
+    let flush = |cache: &str, token: Option<&str>| {
+        let mut request =
+            reqwest::Client::new().post(format!("http://{}/admin/caches/flush{}", addr, cache));
+        if let Some(token) = token {
+            request = request.header("X-Admin-Token", token);
+        }
+        request.send()
+    };
+
+    // Whole-slice statistics are cached by /stats
+    let response = http_client::get(&addr, "/stats?var=temperature")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+
+    let response = flush("", None).await.expect("Failed to make request");
+    assert_eq!(response.status(), 401);
+    let response = flush("", Some("wrong"))
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 401);
+    let response = flush("?cache=tiles", Some("s3cret"))
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+
+    let body: serde_json::Value = flush("?cache=stats", Some("s3cret"))
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(body["flushed"], serde_json::json!({"stats": 1}));
+
+    // Normals computed for a mode are kept until flushed
+    let response = http_client::get(
+        &addr,
+        "/data?vars=temperature&lon=30&mode=zscore&format=json",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = flush("?cache=climatology", Some("s3cret"))
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(body["flushed"], serde_json::json!({"climatology": 1}));
+
+    let body: serde_json::Value = flush("", Some("s3cret"))
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(
+        body["flushed"],
+        serde_json::json!({
+            "stats": 0,
+            "pressure": 0,
+            "thumbnails": 0,
+            "climatology": 0,
+            "slabs": 0
+        })
+    );
+
+    // Without a configured token the admin endpoints are disabled
+    let addr = init_test_environment().await;
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/admin/caches/flush", addr))
+        .header("X-Admin-Token", "")
+        .send()
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 403);
+}
+
+#[tokio::test]
+async fn test_state_snapshot() {
+    let mut config = rossby::Config::default();
+    config.server.admin_token = Some("s3cret".to_string());
+    let addr = init_test_environment_with_config(config).await;
+
+    let response = http_client::get(&addr, "/admin/state")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 401);
+
+    let snapshot: serde_json::Value = reqwest::Client::new()
+        .get(format!("http://{}/admin/state", addr))
+        .header("X-Admin-Token", "s3cret")
+        .send()
+        .await
+        .expect("Failed to make request")
+        .json()
+        .await
+        .expect("Failed to parse JSON");
+    assert_eq!(snapshot["config"]["server"]["admin_token"], "<redacted>");
+    assert!(snapshot["metadata"]["coordinates"]["lat"].is_array());
+    let checksum = &snapshot["checksums"]["temperature"];
+    assert_eq!(checksum["sha256"].as_str().unwrap().len(), 64);
+    assert_eq!(checksum["shape"].as_array().unwrap().len(), 3);
+
+    // The command-line dump describes the same state
+    let file_path = TEST_FILE_PATH.get().expect("Test file path not set");
+    let state = rossby::data_loader::load_netcdf(
+        std::path::Path::new(file_path),
+        rossby::Config::default(),
+    )
+    .expect("Failed to load test NetCDF file");
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("state.json.gz");
+    rossby::snapshot::write_snapshot(&rossby::snapshot::snapshot(&state).unwrap(), &out)
+        .expect("Failed to write snapshot");
+    let dumped: serde_json::Value = serde_json::from_reader(flate2::read::GzDecoder::new(
+        std::fs::File::open(&out).unwrap(),
+    ))
+    .expect("Snapshot is not gzipped JSON");
+    assert_eq!(dumped["checksums"], snapshot["checksums"]);
+    assert_eq!(dumped["fingerprint"], snapshot["fingerprint"]);
+    assert!(dumped["config"]["server"]["admin_token"].is_null());
+}
+
+#[tokio::test]
+async fn test_access_log_replay() {
+    let dir = tempfile::tempdir().unwrap();
+    let log_path = dir.path().join("access.jsonl");
+    let mut config = rossby::Config::default();
+    config.server.access_log = Some(log_path.clone());
+    let addr = init_test_environment_with_config(config).await;
+
+    for path in [
+        "/point?lon=10&lat=20&vars=temperature",
+        "/image?var=temperature&width=40&height=20",
+        "/point?lon=10&lat=20&vars=missing",
+    ] {
+        http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+    }
+    let response = http_client::get(&addr, "/point?lon=10&lat=20&vars=temperature")
+        .await
+        .expect("Failed to make request");
+    let body = response.bytes().await.expect("Failed to read body");
+
+    let (mut records, skipped) = rossby::replay::read_log(&log_path, None).unwrap();
+    // The first record is the readiness probe of the test environment
+    assert_eq!((records.len(), skipped), (5, 0));
+    assert_eq!(records[0].path, "/metadata");
+    assert_eq!(records[1].path, "/point");
+    assert_eq!(records[1].query, "lon=10&lat=20&vars=temperature");
+    assert_eq!(records[3].status, 400);
+    // Logged responses reach the client unchanged
+    assert_eq!(records[4].sha256, rossby::artifact::sha256_hex(&body));
+
+    // Another instance serving the same data answers identically
+    let target = init_test_environment().await;
+    let target = format!("http://{}", target);
+    let summary = rossby::replay::replay(records.clone(), &target, 2, 1000.0).await;
+    assert_eq!(summary.replayed, 5);
+    assert_eq!(summary.differing(), 0);
+
+    records[2].sha256 = rossby::artifact::sha256_hex(b"corrupted");
+    let summary = rossby::replay::replay(records, &target, 2, 1000.0).await;
+    assert_eq!(summary.body_mismatches, 1);
+    assert_eq!(summary.differing(), 1);
+}
+
+#[tokio::test]
+async fn test_signed_responses() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_path = dir.path().join("signing.key");
+    std::fs::write(&key_path, "2a".repeat(32)).unwrap();
+    let mut config = rossby::Config::default();
+    config.server.signing_key_file = Some(key_path);
+    let addr = init_test_environment_with_config(config).await;
+
+    let key: serde_json::Value = http_client::get_json(&addr, "/signing_key")
+        .await
+        .expect("Failed to get signing key");
+    assert_eq!(key["algorithm"], "ed25519");
+    let public_key = key["public_key"].as_str().unwrap();
+    let fingerprint = key["dataset_fingerprint"].as_str().unwrap();
+
+    let path = "/point?lon=10&lat=20&vars=temperature";
+    let response = http_client::get(&addr, path)
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 200);
+    let header = |name: &str| response.headers()[name].to_str().unwrap().to_string();
+    let signature = header("x-rossby-signature");
+    assert_eq!(header("x-rossby-dataset-fingerprint"), fingerprint);
+    assert_eq!(header("x-rossby-signed-request"), path);
+    let body = response.bytes().await.expect("Failed to read body");
+    rossby::signing::verify(public_key, &signature, fingerprint, path, &body).unwrap();
+    assert!(rossby::signing::verify(
+        public_key,
+        &signature,
+        fingerprint,
+        "/point?lon=10&lat=30&vars=temperature",
+        &body
+    )
+    .is_err());
+
+    // Errors and operational endpoints are not signed
+    for path in ["/point?lon=10&lat=20&vars=missing", "/heartbeat"] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert!(response.headers().get("x-rossby-signature").is_none());
+    }
+
+    // Without a key, nothing is signed
+    let addr = init_test_environment().await;
+    let response = http_client::get(&addr, "/signing_key")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 404);
+    let response = http_client::get(&addr, path)
+        .await
+        .expect("Failed to make request");
+    assert!(response.headers().get("x-rossby-signature").is_none());
+}
+
+#[tokio::test]
+async fn test_dataset_namespaces() {
+    // Make sure the test file exists to serve it again as a dataset
+    init_test_environment().await;
+    let file_path = TEST_FILE_PATH.get().expect("Test file path not set");
+
+    let mut config = rossby::Config::default();
+    config.datasets.insert(
+        "demo".to_string(),
+        rossby::config::DatasetConfig {
+            file_path: file_path.into(),
+            overrides: serde_json::json!({"server": {"quotas": {"daily_bytes": 1_000_000}}}),
+            endpoints: Some(vec!["/metadata".to_string(), "/usage".to_string()]),
+            api_keys: Some(vec!["demo-key".to_string()]),
+        },
+    );
+    let addr = init_test_environment_with_config(config).await;
+    let client = reqwest::Client::new();
+    let get = |path: &str, key: Option<&str>| {
+        let mut request = client.get(format!("http://{}{}", addr, path));
+        if let Some(key) = key {
+            request = request.header("x-api-key", key);
+        }
+        request.send()
+    };
+
+    // The dataset is served with its own overrides
+    let response = get("/datasets/demo/metadata", Some("demo-key"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let metadata: serde_json::Value = response.json().await.unwrap();
+    assert!(metadata["variables"]["temperature"].is_object());
+
+    let usage: serde_json::Value = get("/datasets/demo/usage", Some("demo-key"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(usage["limit_bytes"], 1_000_000);
+    let usage: serde_json::Value = get("/usage", Some("demo-key"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(usage["limit_bytes"].is_null());
+
+    // Endpoints outside the allowlist and requests without a key are rejected
+    let response = get(
+        "/datasets/demo/point?lon=10&lat=20&vars=temperature",
+        Some("demo-key"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 404);
+    let response = get("/datasets/demo/metadata", None).await.unwrap();
+    assert_eq!(response.status(), 401);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("x-api-key"));
+
+    // The main dataset is unaffected
+    let response = get("/point?lon=10&lat=20&vars=temperature", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_rust_client() {
+    use rossby_client::{Client, ClientError, DataRequest, ImageRequest, PointRequest};
+
+    let addr = init_test_environment().await;
+    let client = Client::new(&format!("http://{}/", addr)).unwrap();
+
+    let metadata = client.metadata().await.unwrap();
+    assert!(metadata.variables.contains_key("temperature"));
+    let lat = metadata.coordinates["lat"].len();
+    let lon = metadata.coordinates["lon"].len();
+
+    let point = client
+        .point(
+            &PointRequest::new(190.0, 10.0, ["temperature", "humidity"])
+                .interpolation("nearest")
+                .param("time_index", 0),
+        )
+        .await
+        .unwrap();
+    assert!(point.values["temperature"].is_finite());
+    assert!(point.values.contains_key("humidity"));
+
+    // The Arrow response is decoded into arrays with their coordinates
+    let data = client
+        .data(&DataRequest::new(["temperature"]).param("time_index", 0))
+        .await
+        .unwrap();
+    let temperature = &data.variables["temperature"];
+    assert_eq!(temperature.len(), lat * lon);
+    assert_eq!(data.coordinates["lat"].len(), lat);
+    assert_eq!(data.coordinates["lon"], metadata.coordinates["lon"]);
+
+    let image = client
+        .image(
+            &ImageRequest::new("temperature")
+                .size(40, 30)
+                .param("time_index", 0),
+        )
+        .await
+        .unwrap();
+    assert!(image_utils::detect_image_format(&image).unwrap() == image::ImageFormat::Png);
+
+    // Errors of the server keep their status and request id
+    let error = client
+        .point(&PointRequest::new(190.0, 10.0, ["nonexistent"]))
+        .await
+        .unwrap_err();
+    match error {
+        ClientError::Api {
+            status, request_id, ..
+        } => {
+            assert_eq!(status, 400);
+            assert!(request_id.is_some());
+        }
+        other => panic!("Expected an API error, got {:?}", other),
+    }
+
+    // The blocking client answers the same
+    let base_url = client.base_url().to_string();
+    let blocking = tokio::task::spawn_blocking(move || {
+        let client = rossby_client::blocking::Client::new(&base_url).unwrap();
+        client.metadata().unwrap()
+    })
+    .await
+    .unwrap();
+    assert_eq!(blocking.variables.len(), metadata.variables.len());
+}
+
+#[tokio::test]
+async fn test_warmup_primes_caches() {
+    init_test_environment().await;
+    let file_path = TEST_FILE_PATH.get().expect("Test file path not set");
+    let state = std::sync::Arc::new(
+        rossby::data_loader::load_netcdf(
+            std::path::Path::new(file_path),
+            rossby::Config::default(),
+        )
+        .expect("Failed to load test NetCDF file"),
+    );
+    assert!(state.slice_stats.is_empty());
+    assert!(state.thumbnails.is_empty());
+
+    let config = rossby::config::WarmupConfig {
+        requests: vec![
+            "/image?var=temperature&time_index=0&width=40&height=20".to_string(),
+            "/thumbnail?var=temperature&time_index=0".to_string(),
+            "/image?var=nonexistent".to_string(),
+        ],
+        concurrency: 2,
+    };
+    let summary = rossby::warmup::warm_up(build_test_router(state.clone()), config).await;
+    assert_eq!(summary.succeeded, 2);
+    assert_eq!(summary.failed, 1);
+    assert!(!state.slice_stats.is_empty());
+    assert_eq!(state.thumbnails.len(), 1);
+}
+
+#[tokio::test]
+async fn test_identical_requests_are_coalesced() {
+    use tower::ServiceExt;
+
+    init_test_environment().await;
+    let file_path = TEST_FILE_PATH.get().expect("Test file path not set");
+    let load = |coalesce: bool| {
+        let mut config = rossby::Config::default();
+        config.server.coalesce_requests = coalesce;
+        std::sync::Arc::new(
+            rossby::data_loader::load_netcdf(std::path::Path::new(file_path), config)
+                .expect("Failed to load test NetCDF file"),
+        )
+    };
+    let send_identical = |state: std::sync::Arc<rossby::AppState>| async move {
+        let router = build_test_router(state);
+        let requests = (0..5).map(|i| {
+            // The same parameters in a different order
+            let uri = if i % 2 == 0 {
+                "/image?var=temperature&time_index=1&width=400&height=200"
+            } else {
+                "/image?width=400&height=200&var=temperature&time_index=1"
+            };
+            let request = axum::http::Request::get(uri)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            router.clone().oneshot(request)
+        });
+        let mut responses = Vec::new();
+        for response in futures::future::join_all(requests).await {
+            let response = response.unwrap();
+            assert_eq!(response.status(), 200);
+            let coalesced = response.headers().contains_key("x-rossby-coalesced");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            responses.push((coalesced, body));
+        }
+        responses
+    };
+
+    let state = load(true);
+    let responses = send_identical(state.clone()).await;
+    assert!(responses.iter().any(|(coalesced, _)| *coalesced));
+    assert!(responses.iter().all(|(_, body)| *body == responses[0].1));
+    assert!(state.in_flight.is_empty());
+
+    let responses = send_identical(load(false)).await;
+    assert!(responses.iter().all(|(coalesced, _)| !coalesced));
+}
+
+#[tokio::test]
+async fn test_streamed_data_is_not_coalesced() {
+    use axum::body::HttpBody;
+    use tower::ServiceExt;
+
+    // Three time steps of 720 x 730 points, more rows than one record batch
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("large_gradient.nc");
+    test_data::create_linear_gradient_nc(&file_path, (720, 730)).unwrap();
+    let state = std::sync::Arc::new(
+        rossby::data_loader::load_netcdf(&file_path, rossby::Config::default())
+            .expect("Failed to load test NetCDF file"),
+    );
+    assert!(state.config.server.coalesce_requests);
+    let router = build_test_router(state.clone());
+    let request = |range: Option<&str>| {
+        let mut request = axum::http::Request::get("/data?vars=gradient&format=arrow");
+        if let Some(range) = range {
+            request = request.header("range", range);
+        }
+        router
+            .clone()
+            .oneshot(request.body(axum::body::Body::empty()).unwrap())
+    };
+
+    // Identical requests are each streamed, none buffered to be shared
+    let (first, second) = tokio::join!(request(None), request(None));
+    let mut bodies = Vec::new();
+    for response in [first.unwrap(), second.unwrap()] {
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["x-rossby-arrow-batches"], "2");
+        assert!(!response.headers().contains_key("x-rossby-coalesced"));
+        assert!(!response.headers().contains_key("content-length"));
+        assert!(response.body().size_hint().exact().is_none());
+        bodies.push(
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap(),
+        );
+    }
+    assert_eq!(bodies[0], bodies[1]);
+    assert!(state.in_flight.is_empty());
+
+    let reader =
+        arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(bodies[0].clone()), None)
+            .expect("Failed to read Arrow IPC stream");
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.len(), 2);
+    assert_eq!(
+        batches.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+        3 * 720 * 730
+    );
+
+    // Interrupted downloads resume from the same serialization
+    let response = request(Some("bytes=1000-")).await.unwrap();
+    assert_eq!(response.status(), 206);
+    assert!(response.headers().contains_key("x-content-sha256"));
+    let rest = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(rest, bodies[0].slice(1000..));
+}
+
+#[tokio::test]
+async fn test_disabled_endpoints() {
+    let mut config = rossby::Config::default();
+    config.server.admin_token = Some("secret".to_string());
+    config.server.disabled_endpoints = vec!["/admin".to_string(), "/data?format=json".to_string()];
+    config.data.products.insert(
+        "temperature_json".to_string(),
+        HashMap::from([
+            ("vars".to_string(), serde_json::json!("temperature")),
+            ("format".to_string(), serde_json::json!("json")),
+        ]),
+    );
+    let addr = init_test_environment_with_config(config).await;
+
+    let response = http_client::get(&addr, "/admin/state")
+        .await
+        .expect("Failed to make request");
+    assert_eq!(response.status(), 404);
+
+    for path in [
+        "/data?vars=temperature&format=json",
+        "/data?product=temperature_json",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 403, "{}", path);
+        let json: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+        assert!(json["error"].as_str().unwrap().contains("format=json"));
+        assert!(json["request_id"].is_string());
+    }
+
+    for path in [
+        "/data?vars=temperature&time_index=0",
+        "/image?var=temperature&width=40&height=20",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_image_snap_to_grid() {
+    let addr = init_test_environment().await;
+    let fetch = |query: &'static str| async move {
+        let response = http_client::get(
+            &addr,
+            &format!("/image?var=temperature&time_index=0&{}", query),
+        )
+        .await
+        .expect("Failed to make request");
+        assert_eq!(response.status(), 200, "{}", query);
+        let bbox = response.headers()["x-rossby-snapped-bbox"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let bytes = response.bytes().await.expect("Failed to read body");
+        (bbox, image::load_from_memory(&bytes).unwrap().to_rgba8())
+    };
+
+    // 10 degree grid: the bbox shrinks to whole 20 degree pixels
+    let (bbox, wide) = fetch("bbox=5,-45,125,45&width=7&height=5&snap_to_grid=true").await;
+    assert_eq!(bbox, "20,-30,120,30");
+    assert_eq!(wide.dimensions(), (6, 4));
+
+    // Another bbox at the same zoom level samples the same grid points; rows
+    // start at the first latitude of the grid, in the south
+    let (bbox, narrow) = fetch("bbox=35,-5,125,45&width=6&height=3&snap_to_grid=true").await;
+    assert_eq!(bbox, "40,10,120,30");
+    assert_eq!(narrow.dimensions(), (5, 2));
+    for y in 0..2 {
+        for x in 0..5 {
+            assert_eq!(
+                narrow.get_pixel(x, y),
+                wide.get_pixel(x + 1, y + 2),
+                "({}, {})",
+                x,
+                y
+            );
+        }
+    }
+
+    let response = http_client::get(
+        &addr,
+        "/image?var=temperature&time_index=0&snap_to_grid=true&projection=equalarea",
+    )
+    .await
+    .expect("Failed to make request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_shared_time_resolution() {
+    let addr = init_test_environment().await;
+
+    // /point and /image reject an out-of-range time step alike, naming the
+    // parameter given
+    for path in [
+        "/point?lon=10&lat=0&vars=temperature&__time_index=9",
+        "/image?var=temperature&__time_index=9",
+        "/point?lon=10&lat=0&vars=temperature&time_index=9",
+        "/image?var=temperature&time_index=9",
+    ] {
+        let response = http_client::get(&addr, path)
+            .await
+            .expect("Failed to make request");
+        assert_eq!(response.status(), 400, "{}", path);
+        let body: serde_json::Value = response.json().await.expect("Failed to parse JSON");
+        let param = path.rsplit('&').next().unwrap().split('=').next().unwrap();
+        assert!(
+            body["error"].as_str().unwrap().contains(param),
+            "{}: {}",
+            path,
+            body
+        );
+        assert!(body["request_id"].is_string());
+    }
+
+    // __time_index takes precedence over the deprecated time_index
+    let point = |query: &'static str| async move {
+        let json: serde_json::Value = http_client::get_json(
+            &addr,
+            &format!("/point?lon=10&lat=0&vars=temperature&{}", query),
+        )
+        .await
+        .expect("Failed to make request");
+        json["temperature"].clone()
+    };
+    assert_eq!(
+        point("__time_index=3&time_index=1").await,
+        point("__time_index=3").await
+    );
+    assert_ne!(point("__time_index=3").await, point("time_index=1").await);
+}